@@ -0,0 +1,33 @@
+//! cpupark – take a core offline or bring it back
+#![no_std]
+
+extern crate alloc;
+
+use argparse::Parser;
+#[allow(unused_imports)]
+use runtime::*;
+use syscall::{syscall, SystemCall};
+use terminal::println;
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let parser = Parser::new("cpupark", "Take a core offline (it waits in 'hlt', until it is unparked)")
+        .flag(Some('u'), "unpark", "Bring the core back")
+        .positional("core", "Local APIC id of the core");
+    // the first argument is the program name
+    let args = parser.parse(env::args().skip(1))
+        .and_then(|matches| Ok((matches.required::<u32>("core")?, matches.flag("unpark"))));
+    let (core, unpark) = match args {
+        Ok(args) => args,
+        Err(err) => {
+            println!("{}", parser.report(&err));
+            return;
+        }
+    };
+
+    match syscall(SystemCall::CpuPark, &[core as usize, !unpark as usize]) {
+        Ok(_) if unpark => println!("Unparked core [{}]", core),
+        Ok(_) => println!("Parked core [{}]", core),
+        Err(err) => println!("Failed to {} core [{}]: {:?}", if unpark { "unpark" } else { "park" }, core, err),
+    }
+}
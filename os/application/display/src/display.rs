@@ -0,0 +1,65 @@
+//! display – show and change the display mode
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::ToString;
+use alloc::vec;
+use argparse::{ParseError, Parser};
+#[allow(unused_imports)]
+use runtime::*;
+use syscall::display::DisplayMode;
+use syscall::{syscall, SystemCall};
+use terminal::println;
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let parser = Parser::new("display", "Show the current and available display modes or switch to another one")
+        .optional_positional("mode", "Mode to switch to (WIDTHxHEIGHT)");
+    // the first argument is the program name
+    let mode = parser.parse(env::args().skip(1)).and_then(|matches| {
+        matches.value("mode")
+            .map(|mode| parse_mode(mode).ok_or_else(|| ParseError::InvalidValue { name: "mode", value: mode.to_string() }))
+            .transpose()
+    });
+    let mode = match mode {
+        Ok(mode) => mode,
+        Err(err) => {
+            println!("{}", parser.report(&err));
+            return;
+        }
+    };
+
+    let count = syscall(SystemCall::DisplayModes, &[0, 0]).unwrap_or(0);
+    let mut modes = vec![DisplayMode::default(); count];
+    if count > 0 {
+        let _ = syscall(SystemCall::DisplayModes, &[modes.as_mut_ptr() as usize, modes.len()]);
+    }
+
+    let Some((width, height)) = mode else {
+        let mut current = DisplayMode::default();
+        if syscall(SystemCall::DisplayModeGet, &[core::ptr::from_mut(&mut current) as usize]).is_ok() {
+            println!("Current mode: {}x{} ({} bpp)", current.width, current.height, current.bpp);
+        }
+        if modes.is_empty() {
+            println!("The display mode can't be changed");
+        }
+        for mode in &modes {
+            println!("  {}x{} ({} bpp)", mode.width, mode.height, mode.bpp);
+        }
+        return;
+    };
+
+    let Some(mode) = modes.iter().find(|mode| mode.width == width && mode.height == height) else {
+        println!("Mode {}x{} is not supported", width, height);
+        return;
+    };
+    if let Err(err) = syscall(SystemCall::DisplayModeSet, &[core::ptr::from_ref(mode) as usize]) {
+        println!("Failed to switch to {}x{}: {:?}", width, height, err);
+    }
+}
+
+fn parse_mode(mode: &str) -> Option<(u32, u32)> {
+    let (width, height) = mode.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
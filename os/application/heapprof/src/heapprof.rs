@@ -0,0 +1,127 @@
+//! heapprof – show the live allocations of a process built with the runtime's feature 'heapprof', grouped by call stack
+#![no_std]
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use argparse::Parser;
+use core::sync::atomic::Ordering;
+use concurrent::shm;
+use runtime::heapprof::{buffer_name, Event, Header, BUFFER_SIZE, EVENT_ALLOC, EVENT_FREE, MAGIC, STACK_DEPTH};
+#[allow(unused_imports)]
+use runtime::*;
+use terminal::println;
+
+/// Number of call stacks shown by default
+const DEFAULT_TOP: usize = 10;
+
+/// Allocations of one call stack
+#[derive(Default)]
+struct Site {
+    allocs: usize,
+    bytes: usize,
+    live: usize,
+    live_bytes: usize,
+}
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let parser = Parser::new("heapprof", "Show the live allocations of a profiled process, grouped by call stack")
+        .positional("pid", "Id of the process (built with the runtime's feature 'heapprof')")
+        .optional_positional("top", "Number of call stacks to show (default: 10)");
+    // the first argument is the program name
+    let args = parser.parse(env::args().skip(1))
+        .and_then(|matches| Ok((matches.required::<usize>("pid")?, matches.parse_value("top")?.unwrap_or(DEFAULT_TOP))));
+    let (pid, top) = match args {
+        Ok(args) => args,
+        Err(err) => {
+            println!("{}", parser.report(&err));
+            return;
+        }
+    };
+
+    let Ok(id) = shm::shm_open(&buffer_name(pid), BUFFER_SIZE, false) else {
+        println!("Process [{}] isn't profiled (it must be built with the runtime's feature 'heapprof')", pid);
+        return;
+    };
+    let buffer = shm::shm_attach(id, true).expect("Failed to attach heap profile");
+    let header = unsafe { &*(buffer as *const Header) };
+    if header.magic != MAGIC {
+        println!("Heap profile of process [{}] is not initialized", pid);
+        shm::shm_detach(buffer).expect("Failed to detach heap profile");
+        return;
+    }
+
+    let events = snapshot(header);
+    shm::shm_detach(buffer).expect("Failed to detach heap profile");
+
+    let recorded = events.last().map_or(0, |(number, _)| number + 1);
+    println!("{} events recorded, the last {} are analyzed", recorded, events.len());
+    if recorded > events.len() as u64 {
+        println!("Allocations, that have been freed before this window, may be shown as live");
+    }
+
+    // Allocations, that haven't been freed, by address
+    let mut live = BTreeMap::<u64, (usize, [u64; STACK_DEPTH])>::new();
+    let mut sites = BTreeMap::<[u64; STACK_DEPTH], Site>::new();
+    for (_, event) in events.iter() {
+        match event.kind {
+            EVENT_ALLOC => {
+                let site = sites.entry(event.stack).or_default();
+                site.allocs += 1;
+                site.bytes += event.size as usize;
+                live.insert(event.address, (event.size as usize, event.stack));
+            }
+            EVENT_FREE => {
+                live.remove(&event.address);
+            }
+            _ => {}
+        }
+    }
+    for (size, stack) in live.values() {
+        let site = sites.entry(*stack).or_default();
+        site.live += 1;
+        site.live_bytes += size;
+    }
+
+    let mut sites: Vec<_> = sites.into_iter().collect();
+    sites.sort_by(|a, b| b.1.live_bytes.cmp(&a.1.live_bytes).then(b.1.bytes.cmp(&a.1.bytes)));
+    println!("{} bytes in {} live allocations", live.values().map(|(size, _)| size).sum::<usize>(), live.len());
+    println!("{:>12} {:>8} {:>12} {:>8}  call stack (resolve with addr2line)", "live bytes", "live", "bytes", "allocs");
+    for (stack, site) in sites.iter().take(top) {
+        let mut addresses = stack.iter().take_while(|address| **address != 0);
+        let first = addresses.next().map_or(0, |address| *address);
+        println!("{:>12} {:>8} {:>12} {:>8}  {:#x}", site.live_bytes, site.live, site.bytes, site.allocs, first);
+        for address in addresses {
+            println!("{:>46}  {:#x}", "", address);
+        }
+    }
+}
+
+/// Copy all complete events in the buffer, ordered by their number.
+fn snapshot(header: &Header) -> Vec<(u64, Event)> {
+    let base = unsafe { (header as *const Header).add(1) as *const Event };
+    let mut events = Vec::with_capacity(header.capacity as usize);
+    for i in 0..header.capacity as usize {
+        let slot = unsafe { &*base.add(i) };
+        let sequence = slot.sequence.load(Ordering::Acquire);
+        if sequence == 0 {
+            continue;
+        }
+        let event = Event {
+            sequence: sequence.into(),
+            kind: slot.kind,
+            address: slot.address,
+            size: slot.size,
+            stack: slot.stack,
+        };
+        // the slot has been overwritten while copying
+        if slot.sequence.load(Ordering::Acquire) != sequence {
+            continue;
+        }
+        events.push((sequence - 1, event));
+    }
+    events.sort_by_key(|(number, _)| *number);
+    events
+}
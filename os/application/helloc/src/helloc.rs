@@ -1,6 +1,3 @@
+// The application is written in C and links the runtime dynamically (see Makefile.toml),
+// so this crate is empty.
 #![no_std]
-
-#[allow(unused_imports)]
-use runtime::*;
-#[allow(unused_imports)]
-use libc::*;
\ No newline at end of file
@@ -4,14 +4,21 @@ extern crate alloc;
 
 use core::{fmt::Display, net::{IpAddr, Ipv6Addr, SocketAddr}};
 
-use alloc::{collections::btree_map::BTreeMap, format, string::String, vec::Vec};
+use alloc::{collections::btree_map::BTreeMap, format, string::String, sync::Arc, vec::Vec};
 use httparse::{EMPTY_HEADER, Request};
+use logger::service::LogStream;
 use naming::{open, read, shared_types::OpenOptions};
 use network::{NetworkError, TcpListener, TcpStream};
 #[allow(unused_imports)]
 use runtime::*;
+use runtime::thread;
 use terminal::println;
 
+/// Name of the access log (managed by `logd`)
+const ACCESS_LOG: &str = "httpd-access";
+/// Stack size of the threads serving clients (only mapped as needed)
+const CLIENT_STACK_SIZE: usize = 256 * 1024;
+
 #[unsafe(no_mangle)]
 fn main() {
     // ignore all args for now
@@ -22,25 +29,78 @@ fn main() {
     
     let mut listener = TcpListener::bind(SocketAddr::new(ip, port))
         .expect("failed to bind socket");
-    let mut buffer: [u8; 4096] = [0; 4096];
+    let access_log = Arc::new(open_access_log());
     loop {
         if let Ok(client) = listener.accept() {
             println!("got a connection from {}", client.peer_addr());
-            buffer.fill(0);
-            if let Ok(len) = client.read(&mut buffer) {
-                let mut headers = [EMPTY_HEADER; 64];
-                let mut request = Request::new(&mut headers);
-                match request.parse(&buffer[0..len]) {
-                    Ok(_body_start) => if let Err(e) = handle(request, webroot).send_to(client) {
-                        println!("couldn't send reponse to client: {:?}", e);
-                    },
-                    Err(e) => println!("couldn't parse client request: {:?}", e),
-                }
+            // every client gets its own thread, so a slow one doesn't keep the others waiting
+            let access_log = Arc::clone(&access_log);
+            let serve_client = move || serve(client, webroot, access_log.as_ref().as_ref());
+            if let Err(e) = thread::Builder::new().stack_size(CLIENT_STACK_SIZE).spawn(serve_client) {
+                println!("couldn't start a thread for the client: {:?}", e);
             }
         }
     }
 }
 
+/// Read a request from `client`, answer it and log it.
+fn serve(client: TcpStream, webroot: &str, access_log: Option<&LogStream>) {
+    let mut buffer: [u8; 4096] = [0; 4096];
+    if let Ok(len) = client.read(&mut buffer) {
+        let mut headers = [EMPTY_HEADER; 64];
+        let mut request = Request::new(&mut headers);
+        match request.parse(&buffer[0..len]) {
+            Ok(_body_start) => {
+                let (client_ip, method, path) = (client.peer_addr().ip(), request.method, request.path);
+                let response = handle(request, webroot);
+                let (status, bytes) = (response.status.code(), response.body.len());
+                if let Err(e) = response.send_to(client) {
+                    println!("couldn't send reponse to client: {:?}", e);
+                }
+                if let Some(log) = access_log {
+                    log_access(log, client_ip, method, path, status, bytes);
+                }
+            },
+            Err(e) => println!("couldn't parse client request: {:?}", e),
+        }
+    }
+}
+
+/// Connect to the access log and write the header of the W3C extended log file format.
+fn open_access_log() -> Option<LogStream> {
+    let log = match LogStream::connect(ACCESS_LOG) {
+        Ok(log) => log,
+        Err(e) => {
+            println!("not writing an access log ({:?}), start 'logd {}' first to get one", e, ACCESS_LOG);
+            return None;
+        }
+    };
+    let header = [
+        "#Version: 1.0",
+        "#Software: D3OS httpd",
+        &format!("#Date: {}", time::date().format("%Y-%m-%d %H:%M:%S")),
+        "#Fields: date time c-ip cs-method cs-uri-stem sc-status sc-bytes",
+    ];
+    for line in header {
+        if let Err(e) = log.write_line(line) {
+            println!("couldn't write to the access log: {:?}", e);
+            return None;
+        }
+    }
+    Some(log)
+}
+
+/// Write an entry in the W3C extended log file format (see `open_access_log()` for the fields).
+fn log_access(log: &LogStream, client_ip: IpAddr, method: Option<&str>, path: Option<&str>, status: u16, bytes: usize) {
+    let entry = format!(
+        "{} {} {} {} {} {}",
+        time::date().format("%Y-%m-%d %H:%M:%S"), client_ip, method.unwrap_or("-"), path.unwrap_or("-"), status, bytes,
+    );
+    if let Err(e) = log.write_line(&entry) {
+        println!("couldn't write to the access log: {:?}", e);
+    }
+}
+
 struct Response {
     status: StatusCode,
     headers: BTreeMap<String, String>,
@@ -86,6 +146,16 @@ enum StatusCode {
     MethodNotAllowed,
 }
 
+impl StatusCode {
+    fn code(&self) -> u16 {
+        match self {
+            Self::Ok => 200,
+            Self::NotFound => 404,
+            Self::MethodNotAllowed => 405,
+        }
+    }
+}
+
 impl Display for StatusCode {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}", match self {
@@ -1,18 +1,74 @@
-//! ip – show the current IP address
+//! ip – show or change the current IP addresses
 
 #![no_std]
 extern crate alloc;
 
+use core::net::IpAddr;
+
+use alloc::vec::Vec;
 #[allow(unused_imports)]
 use runtime::*;
-use network::get_ip_addresses;
+use network::{add_address, get_ip_addresses, path_mtu_cache, remove_address};
 use terminal::println;
 
+fn usage() {
+    println!("Usage: ip");
+    println!("       ip addr add|del <address>/<prefix length> [dev <interface>]");
+    println!("       ip pmtu");
+}
+
+/// Parse `<address>/<prefix length>`.
+fn parse_cidr(cidr: &str) -> Option<(IpAddr, u8)> {
+    let (addr, prefix_len) = cidr.split_once('/')?;
+    Some((addr.parse().ok()?, prefix_len.parse().ok()?))
+}
+
+/// Print the path MTU cache of the kernel.
+fn show_path_mtus() {
+    match path_mtu_cache() {
+        Ok(entries) => {
+            for entry in entries {
+                println!("{} mtu {} age {}s", entry.destination(), entry.mtu, entry.age_ms / 1000);
+            }
+        },
+        Err(err) => println!("Failed to get the path MTU cache: {:?}", err),
+    }
+}
+
 #[unsafe(no_mangle)]
-fn main() {
-    // ignore all args for now
+pub fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+
+    let (add, cidr, interface) = match args.as_slice() {
+        [] | ["addr"] | ["addr", "show"] => {
+            for ip in get_ip_addresses() {
+                println!("{}", ip)
+            }
+            return;
+        },
+        ["pmtu"] => {
+            show_path_mtus();
+            return;
+        },
+        ["addr", action @ ("add" | "del"), cidr] => (*action == "add", *cidr, "0"),
+        ["addr", action @ ("add" | "del"), cidr, "dev", interface] => (*action == "add", *cidr, *interface),
+        _ => {
+            usage();
+            return;
+        },
+    };
 
-    for ip in get_ip_addresses() {
-        println!("{}", ip)
+    let (Some((addr, prefix_len)), Ok(interface)) = (parse_cidr(cidr), interface.parse()) else {
+        usage();
+        return;
+    };
+    let result = if add {
+        add_address(interface, addr, prefix_len)
+    } else {
+        remove_address(interface, addr, prefix_len)
+    };
+    if let Err(err) = result {
+        println!("Failed to change address {}/{}: {:?}", addr, prefix_len, err);
     }
 }
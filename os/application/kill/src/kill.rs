@@ -0,0 +1,45 @@
+//! kill – send a signal to a process (by default, terminate it right away)
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::ToString;
+use argparse::{ParseError, Parser};
+use concurrent::process;
+#[allow(unused_imports)]
+use runtime::*;
+use syscall::return_vals::Errno;
+use syscall::signal::Signal;
+use terminal::println;
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let parser = Parser::new("kill", "Send a signal to a process (see 'ps' for the ids)")
+        .option(Some('s'), "signal", "SIGNAL", "kill (default), term or int")
+        .positional("pid", "Id of the process");
+    // the first argument is the program name
+    let args = parser.parse(env::args().skip(1)).and_then(|matches| {
+        let pid = matches.required::<usize>("pid")?;
+        let signal = match matches.value("signal").unwrap_or("kill") {
+            "kill" | "9" => Signal::Kill,
+            "term" | "15" => Signal::Terminate,
+            "int" | "2" => Signal::Interrupt,
+            value => return Err(ParseError::InvalidValue { name: "signal", value: value.to_string() }),
+        };
+        Ok((pid, signal))
+    });
+    let (pid, signal) = match args {
+        Ok(args) => args,
+        Err(err) => {
+            println!("{}", parser.report(&err));
+            return;
+        }
+    };
+
+    match process::kill(pid, signal) {
+        Ok(()) => {}
+        Err(Errno::ESRCH) => println!("kill: No process with id {}", pid),
+        Err(Errno::EACCES) => println!("kill: Process {} belongs to another user", pid),
+        Err(err) => println!("kill: Failed to signal process {} ({:?})", pid, err),
+    }
+}
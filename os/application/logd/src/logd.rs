@@ -0,0 +1,262 @@
+//! logd – write log lines received from other applications into rotating files
+#![no_std]
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use concurrent::thread;
+use logger::service::{self, LOG_DIRECTORY, SPOOL_DIRECTORY};
+use naming::shared_types::{OpenOptions, SeekOrigin};
+use naming::{close, mkdir, mkfifo, open, read, seek, write};
+#[allow(unused_imports)]
+use runtime::*;
+use spin::Mutex;
+use syscall::return_vals::Errno;
+use terminal::println;
+
+const DEFAULT_MAX_SIZE_KIB: usize = 64;
+const DEFAULT_MAX_FILES: usize = 4;
+
+/// Logs, whose reader threads haven't been started yet
+static WORK_QUEUE: Mutex<VecDeque<LogConfig>> = Mutex::new(VecDeque::new());
+
+struct LogConfig {
+    name: String,
+    /// Maximum size of a single file (in bytes)
+    max_size: usize,
+    /// Number of files, including the current one
+    max_files: usize,
+}
+
+fn usage() {
+    println!("Usage: logd [-s <max size in KiB>] [-n <number of files>] <log>...");
+    println!("       logd -f <log> [<number of lines>]");
+}
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+
+    match args.as_slice() {
+        ["-f", name] => return follow(name, 10),
+        ["-f", name, lines] => match lines.parse() {
+            Ok(lines) => return follow(name, lines),
+            Err(_) => return usage(),
+        },
+        _ => {}
+    }
+
+    let mut max_size_kib = DEFAULT_MAX_SIZE_KIB;
+    let mut max_files = DEFAULT_MAX_FILES;
+    let mut names = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let value = match arg {
+            "-s" => &mut max_size_kib,
+            "-n" => &mut max_files,
+            name => {
+                names.push(name);
+                continue;
+            }
+        };
+        match args.next().and_then(|number| number.parse().ok()) {
+            Some(number) if number > 0 => *value = number,
+            _ => return usage(),
+        }
+    }
+    if names.is_empty() {
+        return usage();
+    }
+
+    for dir in ["/run", SPOOL_DIRECTORY, "/var", LOG_DIRECTORY] {
+        if let Err(e) = mkdir(dir)
+            && e != Errno::EEXIST
+        {
+            println!("Failed to create directory {}: {:?}", dir, e);
+            return;
+        }
+    }
+
+    let mut threads = Vec::new();
+    for name in names {
+        if let Err(e) = mkfifo(&service::spool_path(name))
+            && e != Errno::EEXIST
+        {
+            println!("Failed to create pipe for log {}: {:?}", name, e);
+            continue;
+        }
+
+        WORK_QUEUE.lock().push_back(LogConfig { name: name.to_string(), max_size: max_size_kib * 1024, max_files });
+        match thread::create(serve_log) {
+            Some(thread) => threads.push(thread),
+            None => println!("Failed to start thread for log {}", name),
+        }
+    }
+
+    for thread in threads {
+        let _ = thread.join();
+    }
+}
+
+/// Print the last `lines` lines of a log and wait for new ones.
+fn follow(name: &str, lines: usize) {
+    let mut follower = match service::follow(name, lines) {
+        Ok(follower) => follower,
+        Err(e) => {
+            println!("Failed to open log {}: {:?}", name, e);
+            return;
+        }
+    };
+    loop {
+        match follower.next_line() {
+            Ok(line) => println!("{}", line),
+            Err(e) => {
+                println!("Failed to read log {}: {:?}", name, e);
+                return;
+            }
+        }
+    }
+}
+
+/// Thread entry: receive lines for one log and write them to its file.
+fn serve_log() {
+    let config = WORK_QUEUE.lock().pop_front().expect("No log to serve");
+    let spool_path = service::spool_path(&config.name);
+    let mut file = match LogFile::open(config) {
+        Ok(file) => file,
+        Err(e) => {
+            println!("Failed to open log file: {:?}", e);
+            return;
+        }
+    };
+
+    loop {
+        // blocks until an application connects
+        let pipe = match open(&spool_path, OpenOptions::READONLY) {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                println!("Failed to open {}: {:?}", spool_path, e);
+                return;
+            }
+        };
+        while let Some(line) = service::receive_line(pipe) {
+            if let Err(e) = file.write_line(&line) {
+                println!("Failed to write to {}: {:?}", file.path, e);
+            }
+        }
+        let _ = close(pipe);
+    }
+}
+
+/// A log file, which is rotated when it would exceed its maximum size.
+/// Rotated files get the suffix `.1` (newest) to `.<max files - 1>` (oldest).
+struct LogFile {
+    config: LogConfig,
+    path: String,
+    handle: usize,
+    size: usize,
+    /// Directives (lines starting with `#`, like in the W3C extended log file format),
+    /// which are repeated at the beginning of each file
+    directives: Vec<String>,
+}
+
+impl LogFile {
+    fn open(config: LogConfig) -> Result<Self, Errno> {
+        let path = service::log_path(&config.name);
+        let handle = open_or_create(&path, OpenOptions::READWRITE)?;
+        let size = seek(handle, 0, SeekOrigin::End)?;
+        Ok(Self { config, path, handle, size, directives: Vec::new() })
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), Errno> {
+        if self.size > 0 && self.size + line.len() + 1 > self.config.max_size {
+            self.rotate()?;
+        }
+        if line.starts_with('#') {
+            self.remember_directive(line);
+        }
+        self.append(line)
+    }
+
+    fn append(&mut self, line: &str) -> Result<(), Errno> {
+        write_all(self.handle, line.as_bytes())?;
+        write_all(self.handle, b"\n")?;
+        self.size += line.len() + 1;
+        Ok(())
+    }
+
+    /// A directive replaces an earlier one of the same kind (e.g. `#Fields:`).
+    fn remember_directive(&mut self, line: &str) {
+        match self.directives.iter_mut().find(|directive| directive_kind(directive) == directive_kind(line)) {
+            Some(directive) => *directive = line.to_string(),
+            None => self.directives.push(line.to_string()),
+        }
+    }
+
+    /// Shift all files by one generation (dropping the oldest one) and start with an empty file.
+    fn rotate(&mut self) -> Result<(), Errno> {
+        close(self.handle)?;
+        for generation in (1..self.config.max_files).rev() {
+            let source = if generation == 1 { self.path.clone() } else { format!("{}.{}", self.path, generation - 1) };
+            copy_file(&source, &format!("{}.{}", self.path, generation))?;
+        }
+
+        self.handle = open(&self.path, OpenOptions::READWRITE | OpenOptions::TRUNCATE)?;
+        self.size = 0;
+        for directive in self.directives.clone() {
+            self.append(&directive)?;
+        }
+        Ok(())
+    }
+}
+
+/// `#Fields: date time` -> `#Fields`
+fn directive_kind(directive: &str) -> &str {
+    directive.split_once(':').map_or(directive, |(kind, _)| kind)
+}
+
+/// Open an existing file or create it.
+fn open_or_create(path: &str, flags: OpenOptions) -> Result<usize, Errno> {
+    match open(path, flags) {
+        Err(Errno::ENOENT) => open(path, flags | OpenOptions::CREATE),
+        result => result,
+    }
+}
+
+/// Replace the content of `destination` with the content of `source` (if it exists).
+fn copy_file(source: &str, destination: &str) -> Result<(), Errno> {
+    let source = match open(source, OpenOptions::READONLY) {
+        Ok(handle) => handle,
+        Err(Errno::ENOENT) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let result = open_or_create(destination, OpenOptions::READWRITE | OpenOptions::TRUNCATE).and_then(|destination| {
+        let mut buffer = [0u8; 4096];
+        let result = loop {
+            match read(source, &mut buffer) {
+                Ok(0) => break Ok(()),
+                Ok(received) => {
+                    if let Err(e) = write_all(destination, &buffer[..received]) {
+                        break Err(e);
+                    }
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        let _ = close(destination);
+        result
+    });
+    let _ = close(source);
+    result
+}
+
+fn write_all(handle: usize, mut data: &[u8]) -> Result<(), Errno> {
+    while !data.is_empty() {
+        let written = write(handle, data)?;
+        data = &data[written..];
+    }
+    Ok(())
+}
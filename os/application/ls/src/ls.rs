@@ -20,6 +20,10 @@ fn print_dir_entry(dentry: DirEntry) {
         println!("d {}", dentry.name);
     } else if dentry.file_type == FileType::NamedPipe {
         println!("p {}", dentry.name);
+    } else if dentry.file_type == FileType::CharDevice {
+        println!("c {}", dentry.name);
+    } else if dentry.file_type == FileType::BlockDevice {
+        println!("b {}", dentry.name);
     } else {
         println!("- {}", dentry.name);
     }
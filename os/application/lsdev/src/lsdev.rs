@@ -0,0 +1,69 @@
+//! lsdev – list the devices known to the kernel
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+use core::fmt::Write;
+#[allow(unused_imports)]
+use runtime::*;
+use syscall::{SystemCall, syscall};
+use system_info::devices::{DeviceInfo, devices};
+use terminal::println;
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let mut args = env::args();
+    // the first argument is the program name, ignore it
+    args.next();
+    let verbose = match args.next().as_deref() {
+        None => false,
+        Some("-v") | Some("--verbose") => true,
+        Some("-r") | Some("--rescan") => {
+            match syscall(SystemCall::DeviceRescan, &[]) {
+                Ok(changes) => println!("{} device(s) added or removed", changes),
+                Err(err) => println!("Failed to rescan: {:?}", err),
+            }
+            return;
+        }
+        Some(_) => {
+            println!("Usage: lsdev [-v | --rescan]");
+            return;
+        }
+    };
+
+    let devices = devices();
+    if devices.is_empty() {
+        println!("No devices found");
+        return;
+    }
+
+    // Geräte ohne (bekanntes) Elterngerät sind die Wurzeln des Baums
+    for device in devices.iter().filter(|device| device.parent.as_ref().is_none_or(|parent| !devices.iter().any(|known| known.name == *parent))) {
+        print_tree(&devices, device, 0, verbose);
+    }
+}
+
+fn print_tree(devices: &[DeviceInfo], device: &DeviceInfo, depth: usize, verbose: bool) {
+    println!(
+        "{:indent$}{:<20} {:<8} {:<6} {}",
+        "",
+        device.name,
+        device.class.as_str(),
+        device.bus,
+        device.driver.as_deref().unwrap_or("-"),
+        indent = depth * 2
+    );
+
+    if verbose && !device.resources.is_empty() {
+        let mut resources = String::new();
+        for resource in device.resources.iter() {
+            let _ = write!(resources, " {resource}");
+        }
+        println!("{:indent$}  resources:{}", "", resources, indent = depth * 2);
+    }
+
+    for child in devices.iter().filter(|child| child.parent.as_ref() == Some(&device.name)) {
+        print_tree(devices, child, depth + 1, verbose);
+    }
+}
@@ -0,0 +1,48 @@
+//! mount – mount a file system or list the mounted ones
+#![no_std]
+
+extern crate alloc;
+
+use argparse::{ParseError, Parser};
+use naming::shared_types::MountFlags;
+#[allow(unused_imports)]
+use runtime::*;
+use terminal::{print, println};
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let parser = Parser::new("mount", "Mount a file system (fat, 9p, 9p-tcp, tmpfs, devfs or procfs), list the mounted ones without arguments")
+        .flag(Some('r'), "read-only", "Mount the file system read-only")
+        .option(Some('t'), "type", "fstype", "Type of the file system (default: fat)")
+        .optional_positional("device", "Block device (e.g. /dev/ata0p0), tag of the 9P share or address of the 9P server")
+        .optional_positional("path", "Directory to mount the file system on");
+    // the first argument is the program name
+    let matches = match parser.parse(env::args().skip(1)) {
+        Ok(matches) => matches,
+        Err(err) => {
+            println!("{}", parser.report(&err));
+            return;
+        }
+    };
+
+    let (Some(device), Some(path)) = (matches.value("device"), matches.value("path")) else {
+        if matches.value("device").is_some() {
+            println!("{}", parser.report(&ParseError::MissingArgument("path")));
+            return;
+        }
+        match fs::read_to_string("/proc/mounts") {
+            Ok(mounts) => print!("{}", mounts),
+            Err(err) => println!("Failed to read /proc/mounts: {:?}", err),
+        }
+        return;
+    };
+
+    let fs_type = matches.value("type").unwrap_or("fat");
+    let flags = match matches.flag("read-only") {
+        true => MountFlags::READONLY,
+        false => MountFlags::empty(),
+    };
+    if let Err(err) = naming::mount(device, path, fs_type, flags) {
+        println!("Failed to mount {} on {}: {:?}", device, path, err);
+    }
+}
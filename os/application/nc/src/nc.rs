@@ -89,7 +89,9 @@ Examples:
         Mode::Connect => match protocol {
             Protocol::Udp => {
                 let local_addr = SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0);
-                Socket::Udp(UdpSocket::bind(local_addr).expect("failed to open socket"))
+                let mut socket = UdpSocket::bind(local_addr).expect("failed to open socket");
+                socket.connect(addr).expect("invalid address");
+                Socket::Udp(socket)
             },
             Protocol::Tcp => Socket::Tcp(TcpStream::connect(addr).expect("failed to open socket")),
         },
@@ -0,0 +1,139 @@
+//! netctl – show the network configuration managed by netmand and change routes
+#![no_std]
+extern crate alloc;
+
+use core::net::IpAddr;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use naming::shared_types::OpenOptions;
+use naming::{close, open, read};
+use network::{add_route, dhcp_lease, get_ip_addresses, remove_route, renew_dhcp, routes, NetworkError, RESOLVER_CONFIG};
+#[allow(unused_imports)]
+use runtime::*;
+use syscall::return_vals::Errno;
+use terminal::println;
+
+fn usage() {
+    println!("Usage: netctl [status]");
+    println!("       netctl renew");
+    println!("       netctl route add <destination>/<prefix length> via <gateway> [dev <interface>]");
+    println!("       netctl route del <destination>/<prefix length> [dev <interface>]");
+}
+
+/// Parse `<address>/<prefix length>`.
+fn parse_cidr(cidr: &str) -> Option<(IpAddr, u8)> {
+    let (addr, prefix_len) = cidr.split_once('/')?;
+    Some((addr.parse().ok()?, prefix_len.parse().ok()?))
+}
+
+/// Print the DHCP lease, addresses, routes and DNS servers.
+fn show_status() {
+    match dhcp_lease() {
+        Ok(Some(lease)) => {
+            println!("DHCP lease: {}/{} on interface [{}]", lease.address(), lease.prefix_len, lease.interface);
+            if let Some(router) = lease.router() {
+                println!("  gateway {}", router);
+            }
+            for server in lease.dns_servers() {
+                println!("  dns {}", server);
+            }
+        },
+        Ok(None) => println!("DHCP lease: none"),
+        Err(err) => println!("Failed to get the DHCP lease: {:?}", err),
+    }
+
+    println!("Addresses:");
+    for ip in get_ip_addresses() {
+        println!("  {}", ip);
+    }
+
+    println!("Routes:");
+    // interfaces are numbered without gaps, so stop at the first one, that doesn't exist
+    for interface in 0.. {
+        match routes(interface) {
+            Ok(routes) => {
+                for route in routes {
+                    println!("  {}/{} via {} dev {}", route.destination(), route.prefix_len, route.via(), interface);
+                }
+            },
+            Err(NetworkError::Unknown(Errno::ENOENT)) => break,
+            Err(err) => {
+                println!("Failed to get the routes of interface [{}]: {:?}", interface, err);
+                break;
+            },
+        }
+    }
+
+    println!("Resolver ({}):", RESOLVER_CONFIG);
+    match read_file(RESOLVER_CONFIG) {
+        Ok(content) => {
+            for line in content.lines().filter(|line| !line.starts_with('#')) {
+                println!("  {}", line);
+            }
+        },
+        Err(Errno::ENOENT) => println!("  not written yet (is netmand running?)"),
+        Err(err) => println!("  failed to read: {:?}", err),
+    }
+}
+
+fn read_file(path: &str) -> Result<String, Errno> {
+    let handle = open(path, OpenOptions::READONLY)?;
+    let mut content = Vec::new();
+    let mut buf = [0u8; 256];
+    let result = loop {
+        match read(handle, &mut buf) {
+            Ok(0) => break Ok(()),
+            Ok(len) => content.extend_from_slice(&buf[..len]),
+            Err(Errno::EOF) => break Ok(()),
+            Err(err) => break Err(err),
+        }
+    };
+    let _ = close(handle);
+    result.map(|()| String::from_utf8_lossy(&content).into_owned())
+}
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+
+    let (cidr, via, interface) = match args.as_slice() {
+        [] | ["status"] => {
+            show_status();
+            return;
+        },
+        ["renew"] => {
+            // netmand applies the new lease, once the kernel has got it
+            match renew_dhcp() {
+                Ok(()) => println!("Restarted DHCP"),
+                Err(err) => println!("Failed to restart DHCP: {:?}", err),
+            }
+            return;
+        },
+        ["route", "add", cidr, "via", via] => (*cidr, Some(*via), "0"),
+        ["route", "add", cidr, "via", via, "dev", interface] => (*cidr, Some(*via), *interface),
+        ["route", "del", cidr] => (*cidr, None, "0"),
+        ["route", "del", cidr, "dev", interface] => (*cidr, None, *interface),
+        _ => {
+            usage();
+            return;
+        },
+    };
+
+    let (Some((destination, prefix_len)), Ok(interface)) = (parse_cidr(cidr), interface.parse()) else {
+        usage();
+        return;
+    };
+    let result = match via.map(str::parse::<IpAddr>) {
+        Some(Ok(via)) => add_route(interface, destination, prefix_len, via),
+        Some(Err(_)) => {
+            usage();
+            return;
+        },
+        None => remove_route(interface, destination, prefix_len),
+    };
+    if let Err(err) = result {
+        println!("Failed to change route to {}/{}: {:?}", destination, prefix_len, err);
+    }
+}
@@ -0,0 +1,149 @@
+//! netmand – apply the leases of the kernel's DHCP client and react to link changes
+#![no_std]
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::net::{IpAddr, Ipv4Addr};
+use concurrent::event::{self, EventCounter, EventSource};
+use log::{error, info, warn, LevelFilter};
+use logger::Logger;
+use naming::shared_types::OpenOptions;
+use naming::{close, mkdir, open, write};
+use network::{
+    add_address, add_route, dhcp_lease, remove_address, remove_route, renew_dhcp, set_dns_servers, DhcpLease, NetworkError,
+    RESOLVER_CONFIG,
+};
+#[allow(unused_imports)]
+use runtime::*;
+use syscall::return_vals::Errno;
+
+static LOGGER: Logger = Logger::new();
+
+/// Destination of the default route
+const DEFAULT_ROUTE: (IpAddr, u8) = (IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let _ = log::set_logger(&LOGGER).map(|()| log::set_max_level(LevelFilter::Info));
+
+    let (link, dhcp) = match (EventCounter::new(EventSource::Link), EventCounter::new(EventSource::Dhcp)) {
+        (Ok(link), Ok(dhcp)) => (link, dhcp),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Failed to subscribe to network events: {:?}", e);
+            return;
+        }
+    };
+    if let Err(e) = mkdir("/etc")
+        && e != Errno::EEXIST
+    {
+        warn!("Failed to create /etc, not writing {}: {:?}", RESOLVER_CONFIG, e);
+    }
+
+    // the kernel may have acquired a lease before we have subscribed, so check first
+    let mut applied = None;
+    loop {
+        let lease = match dhcp_lease() {
+            Ok(lease) => lease,
+            Err(e) => {
+                error!("Failed to get the DHCP lease: {:?}", e);
+                return;
+            }
+        };
+        if lease != applied {
+            apply(applied.as_ref(), lease.as_ref());
+            applied = lease;
+        }
+
+        let ready = match event::poll(&[&link, &dhcp], None) {
+            Ok(ready) => ready,
+            Err(e) => {
+                error!("Failed to wait for network events: {:?}", e);
+                return;
+            }
+        };
+        if ready[0] && link.read().is_ok_and(|events| events > 0) {
+            // after the link has been down, we may be in a different network
+            info!("Link has changed, restarting DHCP");
+            if let Err(e) = renew_dhcp() {
+                warn!("Failed to restart DHCP: {:?}", e);
+            }
+        }
+        if ready[1] {
+            // the lease is fetched at the beginning of the loop
+            let _ = dhcp.read();
+        }
+    }
+}
+
+/// Replace the configuration from the lease `old` with the one from `new`.
+/// Addresses are only touched, if they have changed, so existing connections survive a renewal.
+fn apply(old: Option<&DhcpLease>, new: Option<&DhcpLease>) {
+    if let Some(old) = old {
+        let address_changed = new.is_none_or(|new| {
+            (new.interface, new.address(), new.prefix_len) != (old.interface, old.address(), old.prefix_len)
+        });
+        if address_changed {
+            info!("Releasing {}/{}", old.address(), old.prefix_len);
+            match remove_address(old.interface as usize, IpAddr::V4(old.address()), old.prefix_len) {
+                // the address may have been removed manually
+                Ok(()) | Err(NetworkError::AddressNotAvailable) => {},
+                Err(e) => warn!("Failed to remove {}: {:?}", old.address(), e),
+            }
+        }
+        // this fails, if the route has been removed together with the address
+        let _ = remove_route(old.interface as usize, DEFAULT_ROUTE.0, DEFAULT_ROUTE.1);
+    }
+
+    let dns_servers: Vec<IpAddr> = match new {
+        Some(lease) => {
+            configure(lease);
+            lease.dns_servers().map(IpAddr::V4).collect()
+        },
+        None => Vec::new(),
+    };
+    if let Err(e) = set_dns_servers(&dns_servers) {
+        warn!("Failed to set DNS servers: {:?}", e);
+    }
+    if let Err(e) = write_resolver_config(&dns_servers) {
+        warn!("Failed to write {}: {:?}", RESOLVER_CONFIG, e);
+    }
+}
+
+/// Assign the address of `lease` and route via its gateway.
+fn configure(lease: &DhcpLease) {
+    let interface = lease.interface as usize;
+    info!("Configuring {}/{} on interface [{}]", lease.address(), lease.prefix_len, interface);
+    match add_address(interface, IpAddr::V4(lease.address()), lease.prefix_len) {
+        Ok(()) | Err(NetworkError::Unknown(Errno::EEXIST)) => {},
+        Err(e) => warn!("Failed to add {}: {:?}", lease.address(), e),
+    }
+
+    match lease.router() {
+        Some(router) => {
+            info!("Default gateway is {}", router);
+            if let Err(e) = add_route(interface, DEFAULT_ROUTE.0, DEFAULT_ROUTE.1, IpAddr::V4(router)) {
+                warn!("Failed to add default route via {}: {:?}", router, e);
+            }
+        },
+        None => info!("No default gateway"),
+    }
+}
+
+/// Record `servers` in the resolver configuration for applications.
+fn write_resolver_config(servers: &[IpAddr]) -> Result<(), Errno> {
+    let mut content = String::from("# generated by netmand\n");
+    for server in servers {
+        content.push_str(&format!("nameserver {}\n", server));
+    }
+
+    let flags = OpenOptions::READWRITE | OpenOptions::TRUNCATE;
+    let handle = match open(RESOLVER_CONFIG, flags) {
+        Err(Errno::ENOENT) => open(RESOLVER_CONFIG, flags | OpenOptions::CREATE)?,
+        result => result?,
+    };
+    let result = write(handle, content.as_bytes());
+    let _ = close(handle);
+    result.map(|_| ())
+}
@@ -0,0 +1,40 @@
+//! netns – run an application in a new (experimental) network namespace
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use concurrent::thread;
+#[allow(unused_imports)]
+use runtime::*;
+use terminal::println;
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let mut args = env::args().peekable();
+    // the first argument is the program name, ignore it
+    args.next();
+    let with_interface = args.next_if(|arg| arg == "-i").is_some();
+    let Some(app) = args.next() else {
+        println!("Usage: netns [-i] <application> [args...]");
+        println!("  -i  connect the namespace to the root namespace");
+        return;
+    };
+    let app_args: Vec<_> = args.collect();
+
+    let id = match network::unshare_network(with_interface) {
+        Ok(id) => id,
+        Err(err) => {
+            println!("Failed to create network namespace: {:?}", err);
+            return;
+        }
+    };
+    if with_interface {
+        println!("Network namespace [{}]: 10.77.{}.2/24, root namespace is 10.77.{}.1", id, id, id);
+    }
+
+    match thread::start_application(&app, app_args.iter().map(|arg| arg.as_str()).collect()) {
+        Some(app) => { let _ = app.join(); },
+        None => println!("Failed to start application [{}]", app),
+    }
+}
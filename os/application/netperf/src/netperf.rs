@@ -630,6 +630,26 @@ fn handle_network_error(err: NetworkError, operation: &str) -> bool {
             println!("Failed to {}: Invalid address.", operation);
             false
         }
+        NetworkError::AddressNotAvailable => {
+            println!("Failed to {}: Address not available.", operation);
+            false
+        }
+        NetworkError::NotConnected => {
+            println!("Failed to {}: Not connected.", operation);
+            false
+        }
+        NetworkError::InvalidArgument => {
+            println!("Failed to {}: Invalid argument.", operation);
+            false
+        }
+        NetworkError::NoBufferSpace => {
+            println!("Failed to {}: No buffer space available.", operation);
+            false
+        }
+        NetworkError::NotSupported => {
+            println!("Failed to {}: Not supported by this kernel.", operation);
+            false
+        }
         NetworkError::Unknown(_) => {
             println!("Failed to {}.", operation);
             false
@@ -4,12 +4,11 @@
 #![no_std]
 extern crate alloc;
 
-use alloc::{string::String, vec};
+use alloc::string::String;
 use concurrent::thread::sleep;
 use network::{resolve_hostname, IcmpSocket};
 #[allow(unused_imports)]
 use runtime::*;
-use smoltcp::{phy::ChecksumCapabilities, wire::{Icmpv4Packet, Icmpv4Repr}};
 use terminal::println;
 
 #[unsafe(no_mangle)]
@@ -56,32 +55,18 @@ Examples:
     let ident = 0x1234;
     let socket = IcmpSocket::bind(ident).expect("failed to open socket");
     for seq_no in 0..count {
-        let send_time: [u8; 8] = time::date().timestamp_millis().to_ne_bytes();
-        // TODO: IPv6
-        let request = Icmpv4Repr::EchoRequest { ident, seq_no, data: &send_time };
-        let mut packet_buffer = vec![0u8; request.buffer_len()];
-        let mut packet = Icmpv4Packet::new_checked(&mut packet_buffer).unwrap();
-        request.emit(&mut packet, &ChecksumCapabilities::ignored());
-        socket.send_to(&packet_buffer, ip).expect("failed to send ping");
+        let send_time = socket.send_echo_request(ip, seq_no, b"D3OS ping").expect("failed to send ping");
 
-        let mut recv_buffer = [0u8; 4096];
-        let addr = loop {
-            let (len, addr) = socket
-                .recv(&mut recv_buffer)
+        let reply = loop {
+            let reply = socket
+                .recv_echo_reply()
                 .expect("failed to receive ping reply");
-            if len != 0 {
-                break addr;
+            match reply {
+                Some(reply) if reply.seq_no == seq_no => break reply,
+                Some(reply) => println!("ignoring reply with unexpected seq={}", reply.seq_no),
+                None => sleep(50),
             }
-            sleep(50);
         };
-        let response_packet = Icmpv4Packet::new_checked(&recv_buffer).expect("received packet is invalid");
-        let response = Icmpv4Repr::parse(&response_packet, &ChecksumCapabilities::ignored()).expect("received packet is invalid");
-        if let Icmpv4Repr::EchoReply { seq_no, data, .. } = response {
-            let timestamp_ms = i64::from_ne_bytes(data[0..8].try_into().unwrap());
-            let timedelta = time::date().timestamp_millis() - timestamp_ms;
-            println!("{} bytes from {}: seq={}, time={}ms", data.len(), addr, seq_no, timedelta);
-        } else {
-            println!("ignoring unexpected ICMP packet")
-        }
+        println!("{} bytes from {}: seq={}, time={}ms", reply.data.len(), reply.source, reply.seq_no, reply.rtt(send_time));
     }
 }
@@ -0,0 +1,18 @@
+//! poweroff – power down the system
+#![no_std]
+
+extern crate alloc;
+
+#[allow(unused_imports)]
+use runtime::*;
+use syscall::{syscall, SystemCall};
+use terminal::println;
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    println!("Powering down...");
+    // this only returns if something went wrong
+    if let Err(err) = syscall(SystemCall::PowerOff, &[]) {
+        println!("Failed to power down: {:?}", err);
+    }
+}
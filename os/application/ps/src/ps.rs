@@ -1,25 +1,107 @@
+//! ps – list the running processes and their threads
 #![no_std]
 
 extern crate alloc;
 
+use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
+use argparse::Parser;
 use concurrent::process;
-
 #[allow(unused_imports)]
 use runtime::*;
+use syscall::priority::SchedulingClass;
+use syscall::process_info::{ThreadInfo, ThreadStatus, NO_CORE};
 use terminal::println;
 
-
 #[unsafe(no_mangle)]
 pub fn main() {
+    let parser = Parser::new("ps", "List the running processes")
+        .flag(Some('t'), "threads", "Show each thread on its own line");
+    // the first argument is the program name
+    let threads = match parser.parse(env::args().skip(1)) {
+        Ok(matches) => matches.flag("threads"),
+        Err(err) => {
+            println!("{}", parser.report(&err));
+            return;
+        }
+    };
+
+    let infos = match process::list() {
+        Ok(infos) => infos,
+        Err(err) => {
+            println!("ps: Failed to list processes ({:?})", err);
+            return;
+        }
+    };
+
+    if threads {
+        println!("{:>5} {:>5} {:<16} {:<8} {:<10} {:<6} {:>4} {:>9} {:>10}", "PID", "TID", "NAME", "STATE", "PRIORITY", "CLASS", "CORE", "MEM", "CPU");
+        for info in &infos {
+            let core = match info.core {
+                NO_CORE => String::from("-"),
+                core => format!("{}", core),
+            };
+            println!("{:>5} {:>5} {:<16} {:<8} {:<10} {:<6} {:>4} {:>9} {:>10}",
+                info.pid, info.tid, info.name(), status(info.status), format!("{:?}", info.priority), class(info.class), core, memory(info), cpu_time(info.user_time_us + info.system_time_us));
+        }
+        return;
+    }
+
+    // The list is sorted by thread id, so the first thread of a process is (usually) its main thread
+    let mut pids: Vec<usize> = infos.iter().map(|info| info.pid).collect();
+    pids.sort_unstable();
+    pids.dedup();
+
+    println!("{:>5} {:>7} {:<16} {:<8} {:<10} {:>9} {:>10}", "PID", "THREADS", "NAME", "STATE", "PRIORITY", "MEM", "CPU");
+    for pid in pids {
+        let threads: Vec<&ThreadInfo> = infos.iter().filter(|info| info.pid == pid).collect();
+        let first = threads[0];
+        // a process is as busy as its busiest thread
+        let state = threads.iter().map(|info| info.status).min_by_key(|&status| busyness(status)).unwrap_or(first.status);
+        // CPU time is counted per thread (without the threads, that have already exited)
+        let time_us = threads.iter().map(|info| info.user_time_us + info.system_time_us).sum();
+        println!("{:>5} {:>7} {:<16} {:<8} {:<10} {:>9} {:>10}",
+            pid, threads.len(), first.name(), status(state), format!("{:?}", first.priority), memory(first), cpu_time(time_us));
+    }
+}
 
-    let mut buff: [u8; 2048] = [0; 2048];
-    let res = process::ps(&mut buff);
+fn status(status: ThreadStatus) -> &'static str {
+    match status {
+        ThreadStatus::Created => "created",
+        ThreadStatus::Ready => "ready",
+        ThreadStatus::Running => "running",
+        ThreadStatus::Blocked => "blocked",
+        ThreadStatus::Sleeping => "sleeping",
+        ThreadStatus::Exited => "exited",
+    }
+}
+
+fn class(class: SchedulingClass) -> &'static str {
+    match class {
+        SchedulingClass::Fair => "fair",
+        SchedulingClass::Strict => "strict",
+    }
+}
 
-    if let Ok(len) = res {
-        // Treat buffer as UTF-8 text (like /proc)
-        let s = core::str::from_utf8(&buff[..len]).unwrap_or("<invalid utf-8>");
-        println!("{}", s);
+/// Lower is busier
+fn busyness(status: ThreadStatus) -> u8 {
+    match status {
+        ThreadStatus::Running => 0,
+        ThreadStatus::Ready => 1,
+        ThreadStatus::Created => 2,
+        ThreadStatus::Blocked => 3,
+        ThreadStatus::Sleeping => 4,
+        ThreadStatus::Exited => 5,
     }
 }
+
+fn memory(info: &ThreadInfo) -> String {
+    format!("{} KiB", info.memory_kib)
+}
+
+/// CPU time (user and system) as minutes:seconds.hundredths
+fn cpu_time(time_us: u64) -> String {
+    let total_ms = time_us / 1000;
+    format!("{}:{:0>2}.{:0>2}", total_ms / 60_000, total_ms / 1000 % 60, total_ms % 1000 / 10)
+}
@@ -0,0 +1,51 @@
+//! ramdisk – create an empty RAM-backed block device
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::ToString;
+use argparse::{ParseError, Parser};
+#[allow(unused_imports)]
+use runtime::*;
+use syscall::{syscall, SystemCall};
+use terminal::println;
+
+/// Long enough for names like "ram12345"
+const NAME_CAPACITY: usize = 16;
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let parser = Parser::new("ramdisk", "Create an empty ramdisk (the size may end with K, M or G)")
+        .positional("size", "Size in bytes");
+    // the first argument is the program name
+    let size = parser.parse(env::args().skip(1)).and_then(|matches| {
+        let size = matches.value("size").ok_or(ParseError::MissingArgument("size"))?;
+        parse_size(size)
+            .filter(|size| *size > 0)
+            .ok_or_else(|| ParseError::InvalidValue { name: "size", value: size.to_string() })
+    });
+    let size = match size {
+        Ok(size) => size,
+        Err(err) => {
+            println!("{}", parser.report(&err));
+            return;
+        }
+    };
+
+    let mut name = [0u8; NAME_CAPACITY];
+    match syscall(SystemCall::RamdiskCreate, &[size, name.as_mut_ptr() as usize, name.len()]) {
+        Ok(length) => println!("Created {}", core::str::from_utf8(&name[..length.min(NAME_CAPACITY)]).unwrap_or("ramdisk")),
+        Err(err) => println!("Failed to create ramdisk: {:?}", err),
+    }
+}
+
+fn parse_size(size: &str) -> Option<usize> {
+    let (number, shift) = match size.as_bytes().last()? {
+        b'K' | b'k' => (&size[..size.len() - 1], 10),
+        b'M' | b'm' => (&size[..size.len() - 1], 20),
+        b'G' | b'g' => (&size[..size.len() - 1], 30),
+        _ => (size, 0),
+    };
+
+    number.parse::<usize>().ok()?.checked_mul(1 << shift)
+}
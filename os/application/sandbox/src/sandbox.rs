@@ -0,0 +1,92 @@
+//! sandbox – run an application with a restricted view of the system
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use concurrent::thread::{self, SandboxOptions};
+#[allow(unused_imports)]
+use runtime::*;
+use syscall::sandbox::Capabilities;
+use terminal::println;
+
+fn print_usage() {
+    println!("Usage: sandbox run [-r <root>] [-c <capabilities>] [-t <threads>] [-m <memory>] [-u <uid>] [-g <gid>] <application> [args...]");
+    println!("  -r  directory used as root of the naming service");
+    println!("  -c  comma separated list of capabilities: network, spawn, fs_write, devices, power, time (default: none)");
+    println!("  -t  maximum number of threads");
+    println!("  -m  maximum heap memory (suffixes K and M are supported)");
+    println!("  -u  user id (only root may change it)");
+    println!("  -g  group id (only root may change it)");
+}
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let mut args = env::args();
+    // the first argument is the program name, ignore it
+    args.next();
+    if args.next().as_deref() != Some("run") {
+        print_usage();
+        return;
+    }
+
+    let mut root: Option<String> = None;
+    let mut capabilities = Capabilities::empty();
+    let mut max_threads = 0;
+    let mut max_memory = 0;
+    let mut uid = None;
+    let mut gid = None;
+    let app = loop {
+        let Some(arg) = args.next() else {
+            print_usage();
+            return;
+        };
+        let parsed = match arg.as_str() {
+            "-r" => args.next().map(|value| root = Some(value)),
+            "-c" => args.next().as_deref().and_then(parse_capabilities).map(|value| capabilities = value),
+            "-t" => args.next().and_then(|value| value.parse().ok()).map(|value| max_threads = value),
+            "-m" => args.next().as_deref().and_then(parse_memory).map(|value| max_memory = value),
+            "-u" => args.next().and_then(|value| value.parse().ok()).map(|value| uid = Some(value)),
+            "-g" => args.next().and_then(|value| value.parse().ok()).map(|value| gid = Some(value)),
+            _ => break arg,
+        };
+        if parsed.is_none() {
+            println!("Invalid value for option [{}]", arg);
+            print_usage();
+            return;
+        }
+    };
+    let app_args: Vec<String> = args.collect();
+
+    let options = SandboxOptions { root: root.as_deref(), capabilities, max_threads, max_memory, uid, gid };
+    match thread::sandbox_spawn(&app, app_args.iter().map(String::as_str).collect(), &options) {
+        Ok(thread) => { let _ = thread.join(); },
+        Err(err) => println!("Failed to start [{}] in sandbox: {:?}", app, err),
+    }
+}
+
+fn parse_capabilities(list: &str) -> Option<Capabilities> {
+    list.split(',').filter(|name| !name.is_empty()).try_fold(Capabilities::empty(), |capabilities, name| {
+        let capability = match name {
+            "network" => Capabilities::NETWORK,
+            "spawn" => Capabilities::SPAWN,
+            "fs_write" => Capabilities::FS_WRITE,
+            "devices" => Capabilities::DEVICES,
+            "power" => Capabilities::POWER,
+            "time" => Capabilities::TIME,
+            _ => return None,
+        };
+        Some(capabilities | capability)
+    })
+}
+
+fn parse_memory(value: &str) -> Option<usize> {
+    let (number, factor) = match value.as_bytes().last()? {
+        b'K' | b'k' => (&value[..value.len() - 1], 1024),
+        b'M' | b'm' => (&value[..value.len() - 1], 1024 * 1024),
+        _ => (value, 1),
+    };
+
+    number.parse::<usize>().ok()?.checked_mul(factor)
+}
@@ -0,0 +1,83 @@
+//! sensors – show the temperature and frequency of all cores
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use argparse::Parser;
+use concurrent::thread::sleep;
+#[allow(unused_imports)]
+use runtime::*;
+use syscall::cpu::{CpuSensors, TEMPERATURE_UNKNOWN};
+use syscall::{syscall, SystemCall};
+use terminal::println;
+
+/// Time between two readouts (the frequency is averaged over it)
+const INTERVAL_MS: usize = 1000;
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let parser = Parser::new("sensors", "Show the temperature and frequency of all cores")
+        .option(Some('n'), "count", "N", "Number of readouts, one per second (default: 1)");
+    // the first argument is the program name
+    let count = parser.parse(env::args().skip(1)).and_then(|matches| matches.parse_value::<usize>("count"));
+    let count = match count {
+        Ok(count) => count.unwrap_or(1),
+        Err(err) => {
+            println!("{}", parser.report(&err));
+            return;
+        }
+    };
+
+    let cores = syscall(SystemCall::CpuSensors, &[0, 0]).unwrap_or(0);
+    let mut sensors = vec![CpuSensors::default(); cores];
+    // Die erste Messung startet nur den Zeitraum, über den die Frequenz gemittelt wird
+    let _ = syscall(SystemCall::CpuSensors, &[sensors.as_mut_ptr() as usize, sensors.len()]);
+
+    for i in 0..count {
+        if i > 0 {
+            println!("");
+        }
+        sleep(INTERVAL_MS);
+        if let Err(err) = syscall(SystemCall::CpuSensors, &[sensors.as_mut_ptr() as usize, sensors.len()]) {
+            println!("Failed to read sensors: {:?}", err);
+            return;
+        }
+
+        println!("{:>5} {:>12} {:>12} {:>12}", "Core", "Temperature", "Frequency", "Base");
+        for sensor in sensors.iter() {
+            println!(
+                "{:>5} {:>12} {:>12} {:>12}",
+                sensor.apic_id,
+                temperature(sensor),
+                frequency(sensor),
+                mhz(sensor.base_frequency_mhz)
+            );
+        }
+    }
+}
+
+fn temperature(sensor: &CpuSensors) -> String {
+    match sensor.temperature {
+        TEMPERATURE_UNKNOWN => String::from("-"),
+        temperature => format!("{} °C", temperature),
+    }
+}
+
+/// The frequency in MHz or, if the base frequency is unknown, relative to it
+fn frequency(sensor: &CpuSensors) -> String {
+    match (sensor.performance_permille, sensor.frequency_mhz) {
+        (0, _) => String::from("-"),
+        (permille, 0) => format!("{}.{} %", permille / 10, permille % 10),
+        (_, frequency) => mhz(frequency),
+    }
+}
+
+fn mhz(frequency: u32) -> String {
+    match frequency {
+        0 => String::from("-"),
+        frequency => format!("{} MHz", frequency),
+    }
+}
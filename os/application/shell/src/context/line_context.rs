@@ -72,6 +72,21 @@ impl LineContext {
         self.mark_dirty_at(index);
     }
 
+    /// Length (in bytes) of the character before the cursor (the cursor is a byte index into the line)
+    pub fn char_len_before_cursor(&self) -> usize {
+        self.line[..self.cursor_position].chars().next_back().map_or(0, char::len_utf8)
+    }
+
+    /// Length (in bytes) of the character at the cursor
+    pub fn char_len_at_cursor(&self) -> usize {
+        self.line[self.cursor_position..].chars().next().map_or(0, char::len_utf8)
+    }
+
+    /// Number of characters (and terminal columns) before the byte index `index`
+    pub fn columns_before(&self, index: usize) -> usize {
+        self.line[..index].chars().count()
+    }
+
     pub fn get_cursor_pos(&self) -> usize {
         self.cursor_position
     }
@@ -68,8 +68,9 @@ impl CommandLineService {
             return Ok(Response::Skip);
         }
 
-        line_clx.remove(line_clx.get_cursor_pos() - 1);
-        line_clx.move_cursor_left(1);
+        let len = line_clx.char_len_before_cursor();
+        line_clx.remove(line_clx.get_cursor_pos() - len);
+        line_clx.move_cursor_left(len);
         event_bus.trigger(Event::LineWritten);
         Ok(Response::Ok)
     }
@@ -80,7 +81,7 @@ impl CommandLineService {
         }
 
         line_clx.insert(line_clx.get_cursor_pos(), ch);
-        line_clx.move_cursor_right(1);
+        line_clx.move_cursor_right(ch.len_utf8());
         event_bus.trigger(Event::LineWritten);
         Ok(Response::Ok)
     }
@@ -101,7 +102,8 @@ impl CommandLineService {
             return Ok(Response::Skip);
         }
 
-        line_clx.move_cursor_right(1);
+        let len = line_clx.char_len_at_cursor();
+        line_clx.move_cursor_right(len);
         event_bus.trigger(Event::CursorMoved(1));
         Ok(Response::Ok)
     }
@@ -111,7 +113,8 @@ impl CommandLineService {
             return Ok(Response::Skip);
         }
 
-        line_clx.move_cursor_left(1);
+        let len = line_clx.char_len_before_cursor();
+        line_clx.move_cursor_left(len);
         event_bus.trigger(Event::CursorMoved(-1));
         Ok(Response::Ok)
     }
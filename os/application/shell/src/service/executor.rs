@@ -4,8 +4,10 @@ use alloc::{
     string::{String, ToString},
     vec::Vec,
 };
-use concurrent::thread;
+use concurrent::{process, thread::{self, Thread}};
+use syscall::usage::ResourceUsage;
 use terminal::println;
+use time::systime;
 
 use crate::{
     built_in::{
@@ -102,6 +104,11 @@ impl ExecutorService {
     fn execute_executable(&mut self, executable: &Executable) -> usize {
         let args: Vec<&str> = executable.arguments.iter().map(String::as_str).collect();
 
+        // `time` needs to run other commands, so it can't be a regular built-in
+        if executable.command == "time" {
+            return self.execute_timed(&args);
+        }
+
         if let Ok(built_in_exit_code) = self.execute_built_in(&executable.command, &args) {
             return built_in_exit_code;
         }
@@ -114,11 +121,48 @@ impl ExecutorService {
         // WORKAROUND ///////////////////////////////////////////////////////////
         // Extern applications don't yet provide a exit code => We assume success
         // Replace this with real exit code once supported
-        thread.join();
+        wait_in_foreground(&thread);
         0
         /////////////////////////////////////////////////////////////////////////
     }
 
+    /// Run `args[0]` with the remaining arguments and print its wall clock time and resource usage
+    fn execute_timed(&mut self, args: &[&str]) -> usize {
+        let Some((cmd, args)) = args.split_first() else {
+            println!("Usage: time COMMAND [ARGS...]");
+            return 1;
+        };
+
+        let start = systime();
+        let shell_usage = process::usage().ok();
+        let (exit_code, usage) = match self.execute_built_in(cmd, args) {
+            // built-ins run inside the shell process
+            Ok(exit_code) => (exit_code, shell_usage.zip(process::usage().ok()).map(|(before, after)| usage_delta(&before, &after))),
+            Err(()) => {
+                let Some(thread) = thread::start_application(cmd, args.to_vec()) else {
+                    println!("Command not found: {}", cmd);
+                    return 1;
+                };
+                wait_in_foreground(&thread);
+                // WORKAROUND: Extern applications don't yet provide a exit code => We assume success
+                (0, process::child_usage(&thread).ok())
+            }
+        };
+        let real = systime() - start;
+
+        println!("real    {}.{:03}s", real.num_seconds(), real.num_milliseconds() % 1000);
+        match usage {
+            Some(usage) => {
+                println!("user    {}.{:03}s", usage.user_time_us / 1000000, usage.user_time_us / 1000 % 1000);
+                println!("sys     {}.{:03}s", usage.system_time_us / 1000000, usage.system_time_us / 1000 % 1000);
+                println!("max rss {} KiB, {} page faults", usage.max_rss_kib, usage.page_faults);
+                println!("context switches: {} voluntary, {} involuntary", usage.voluntary_switches, usage.involuntary_switches);
+            }
+            None => println!("(resource usage not available)"),
+        }
+        exit_code
+    }
+
     fn execute_built_in(&mut self, cmd: &str, args: &[&str]) -> Result<usize, ()> {
         self.built_ins
             .iter_mut()
@@ -165,3 +209,24 @@ impl ExecutorService {
         false
     }
 }
+
+/// Wait for the application `thread` to exit, while Ctrl+C in the terminal interrupts it
+fn wait_in_foreground(thread: &Thread) {
+    // the application might already be gone
+    let _ = terminal::set_foreground(Some(thread.id()));
+    let _ = thread.join();
+    let _ = terminal::set_foreground(None);
+}
+
+/// Resource usage between the snapshots `before` and `after` of the same process
+fn usage_delta(before: &ResourceUsage, after: &ResourceUsage) -> ResourceUsage {
+    ResourceUsage {
+        user_time_us: after.user_time_us - before.user_time_us,
+        system_time_us: after.system_time_us - before.system_time_us,
+        // the peak can't be attributed to a single command
+        max_rss_kib: after.max_rss_kib,
+        voluntary_switches: after.voluntary_switches - before.voluntary_switches,
+        involuntary_switches: after.involuntary_switches - before.involuntary_switches,
+        page_faults: after.page_faults - before.page_faults,
+    }
+}
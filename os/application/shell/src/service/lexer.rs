@@ -83,8 +83,8 @@ impl LexerService {
             return Ok(Response::Skip);
         }
 
-        let n = total_len - index;
-        for _ in 0..n {
+        // Each removal takes away one character, which may be longer than one byte
+        while tokens_clx.total_len() > index {
             Self::remove(line_clx, tokens_clx);
         }
 
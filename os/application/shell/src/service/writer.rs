@@ -69,7 +69,7 @@ impl WriterService {
     fn write_prompt(&mut self) -> Result<Response, Error> {
         let prompt = self.prompt();
         print!("{}{}\x1b[0m", self.prompt_color(&TokenStatus::Valid), prompt);
-        self.terminal_cursor_pos += prompt.len();
+        self.terminal_cursor_pos += prompt.chars().count();
         Ok(Response::Ok)
     }
 
@@ -133,7 +133,10 @@ impl WriterService {
     }
 
     fn cursor_to_dirty_line(&mut self) -> String {
-        let offset = self.prompt().len() + self.line_provider.borrow().get_dirty_index();
+        let offset = {
+            let line_clx = self.line_provider.borrow();
+            self.prompt().chars().count() + line_clx.columns_before(line_clx.get_dirty_index())
+        };
         let step = self.terminal_cursor_pos as isize - offset as isize;
         self.move_cursor_by(step)
     }
@@ -147,8 +150,8 @@ impl WriterService {
                 true => self.terminal_cursor_pos as isize - self.total_line_len() as isize,
                 false => {
                     self.terminal_cursor_pos as isize
-                        - line_clx.get_cursor_pos() as isize
-                        - self.prompt().len() as isize
+                        - line_clx.columns_before(line_clx.get_cursor_pos()) as isize
+                        - self.prompt().chars().count() as isize
                 }
             }
         };
@@ -175,7 +178,7 @@ impl WriterService {
             formatted_tokens.push_str(color);
             formatted_tokens.push_str(dirty_content);
             formatted_tokens.push_str("\x1b[0m");
-            self.terminal_cursor_pos += dirty_content.len();
+            self.terminal_cursor_pos += dirty_content.chars().count();
         }
         formatted_tokens
     }
@@ -187,7 +190,7 @@ impl WriterService {
         }
         let theme = self.theme_provider.borrow().get_current();
         let line = suggestion_clx.get();
-        self.terminal_cursor_pos += line.len();
+        self.terminal_cursor_pos += line.chars().count();
         format!("{}{}\x1b[0m", theme.suggestion, line)
     }
 
@@ -228,8 +231,11 @@ impl WriterService {
         }
     }
 
+    /// Length of prompt, line and suggestion in terminal columns
     fn total_line_len(&self) -> usize {
-        self.prompt().len() + self.line_provider.borrow().len() + self.suggestion_provider.borrow().len()
+        self.prompt().chars().count()
+            + self.line_provider.borrow().get().chars().count()
+            + self.suggestion_provider.borrow().get().chars().count()
     }
 
     fn clear_right_of_cursor() -> &'static str {
@@ -116,7 +116,7 @@ impl Token {
     }
 
     pub fn pop(&mut self) -> Result<char, ()> {
-        if !self.is_content_dynamic() || self.content.len() <= 1 {
+        if !self.is_content_dynamic() || self.content.chars().count() <= 1 {
             return Err(());
         }
         let ch = self.content.pop().unwrap();
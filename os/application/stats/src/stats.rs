@@ -0,0 +1,40 @@
+//! stats – show the statistics counters of device drivers
+#![no_std]
+
+extern crate alloc;
+
+#[allow(unused_imports)]
+use runtime::*;
+use system_info::device_stats::device_stats;
+use terminal::println;
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let mut args = env::args();
+    // the first argument is the program name, ignore it
+    args.next();
+    let filter = args.next();
+    if matches!(filter.as_deref(), Some("-h") | Some("--help")) {
+        println!("Usage: stats [device]");
+        return;
+    }
+
+    let stats = device_stats();
+    let mut current_device = None;
+    let mut found = false;
+    for stat in stats.iter().filter(|stat| filter.as_ref().is_none_or(|device| *device == stat.device)) {
+        if current_device != Some(&stat.device) {
+            println!("{}:", stat.device);
+            current_device = Some(&stat.device);
+        }
+        println!("  {:<20} {:>16}", stat.counter, stat.value);
+        found = true;
+    }
+
+    if !found {
+        match filter {
+            Some(device) => println!("No statistics for device [{}]", device),
+            None => println!("No device statistics available"),
+        }
+    }
+}
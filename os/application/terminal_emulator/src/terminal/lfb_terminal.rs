@@ -98,6 +98,17 @@ impl LFBTerminal {
         }
     }
 
+    /// Draw to another framebuffer (e.g. after the display mode has been changed). The screen is cleared.
+    pub fn resize(&self, buffer: *mut u8, pitch: u32, width: u32, height: u32, bpp: u8) {
+        let mut display = self.display.lock();
+        let mut cursor = self.cursor.lock();
+        let mut color = self.color.lock();
+
+        *display = DisplayState::new(buffer, pitch, width, height, bpp);
+        LFBTerminal::position(&mut display, &mut cursor, &mut color, (0, 0));
+        cursor.saved_pos = cursor.pos;
+    }
+
     fn print_char(&self, c: char) {
         let mut display = self.display.lock();
         let mut cursor = self.cursor.lock();
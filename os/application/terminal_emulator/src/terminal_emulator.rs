@@ -18,10 +18,12 @@ use event_handler::{Event, EventHandler};
 use graphic::lfb::map_framebuffer;
 use operator::Operator;
 use stream::OutputStream;
+use syscall::display::DisplayMode;
 use terminal::lfb_terminal::LFBTerminal;
 use terminal_lib::init_logger;
 use util::banner::create_banner_string;
 use worker::cursor::Cursor;
+use worker::display_observer::DisplayObserver;
 use worker::input_observer::InputObserver;
 
 #[allow(unused_imports)]
@@ -47,6 +49,7 @@ pub struct TerminalEmulator {
     cursor: Cursor,
     operator: Operator,
     status_bar: StatusBar,
+    display_observer: DisplayObserver,
 }
 
 impl TerminalEmulator {
@@ -58,6 +61,7 @@ impl TerminalEmulator {
             input_observer: InputObserver::new(terminal.clone(), event_handler.clone()),
             output_observer: OutputObserver::new(terminal.clone()),
             cursor: Cursor::new(terminal.clone()),
+            display_observer: DisplayObserver::new(terminal.clone(), DisplayMode { width, height, bpp }),
             operator: Operator::new(),
             event_handler: event_handler,
             status_bar: StatusBar::new(terminal),
@@ -85,6 +89,7 @@ impl TerminalEmulator {
             self.input_observer.run();
             self.cursor.run();
             self.status_bar.run();
+            self.display_observer.run();
         }
     }
 
@@ -0,0 +1,48 @@
+use alloc::rc::Rc;
+use graphic::lfb::map_framebuffer;
+use syscall::display::DisplayMode;
+use syscall::{syscall, SystemCall};
+use time::systime;
+
+use super::worker::Worker;
+use crate::terminal::lfb_terminal::LFBTerminal;
+
+const UPDATE_INTERVAL: i64 = 1000;
+
+/// Watches the display mode and moves the terminal to the new framebuffer, if it has been changed.
+pub struct DisplayObserver {
+    terminal: Rc<LFBTerminal>,
+    mode: DisplayMode,
+    last_tick: i64,
+}
+
+impl DisplayObserver {
+    pub const fn new(terminal: Rc<LFBTerminal>, mode: DisplayMode) -> Self {
+        Self {
+            terminal,
+            mode,
+            last_tick: -UPDATE_INTERVAL,
+        }
+    }
+}
+
+impl Worker for DisplayObserver {
+    fn run(&mut self) {
+        let systime = systime().num_milliseconds();
+        if systime < self.last_tick + UPDATE_INTERVAL {
+            return;
+        }
+        self.last_tick = systime;
+
+        let mut mode = DisplayMode::default();
+        if syscall(SystemCall::DisplayModeGet, &[core::ptr::from_mut(&mut mode) as usize]).is_err() || mode == self.mode {
+            return;
+        }
+
+        // The old framebuffer may not be valid anymore, so it has to be mapped again
+        if let Ok(lfb_info) = map_framebuffer() {
+            self.terminal.resize(lfb_info.addr as *mut u8, lfb_info.pitch, lfb_info.width, lfb_info.height, lfb_info.bpp);
+            self.mode = DisplayMode { width: lfb_info.width, height: lfb_info.height, bpp: lfb_info.bpp };
+        }
+    }
+}
@@ -2,11 +2,12 @@ use core::cell::RefCell;
 
 use alloc::{format, rc::Rc, string::String, vec::Vec};
 use globals::hotkeys::HKEY_TOGGLE_TERMINAL_WINDOW;
-use pc_keyboard::{DecodedKey, EventDecoder, HandleControl, KeyCode, KeyEvent};
+use pc_keyboard::{DecodedKey, EventDecoder, HandleControl, KeyCode, KeyEvent, KeyState};
 use pc_keyboard::layouts::{AnyLayout, De105Key};
+use input::compose::{Composer, DE105_DEAD_KEYS};
 use stream::{event_to_u16, OutputStream, RawInputStream};
 use syscall::{SystemCall, syscall};
-use terminal_lib::{DecodedKeyType, TerminalInputState, TerminalMode};
+use terminal_lib::{encode_fluid_key, TerminalInputState, TerminalMode};
 
 use crate::{
     event_handler::{Event, EventHandler},
@@ -15,9 +16,11 @@ use crate::{
 
 use super::worker::Worker;
 
+/// Maximum length of a line in canonical mode (in characters)
 const BUFFER_SIZE: usize = 256;
 
 struct Canonical {
+    /// Position of the cursor in characters (not bytes, the line may contain any Unicode character)
     cursor_pos: usize,
     buffer: String,
 }
@@ -37,11 +40,21 @@ impl Canonical {
         buffer.into()
     }
 
+    /// Number of characters in the line
+    fn len(&self) -> usize {
+        self.buffer.chars().count()
+    }
+
+    /// Byte offset of the character at `pos`
+    fn byte_index(&self, pos: usize) -> usize {
+        self.buffer.char_indices().nth(pos).map_or(self.buffer.len(), |(index, _)| index)
+    }
+
     fn remove_at_cursor(&mut self) -> Result<(), ()> {
-        if self.cursor_pos >= self.buffer.len() || self.buffer.is_empty() {
+        if self.cursor_pos >= self.len() || self.buffer.is_empty() {
             return Err(());
         }
-        self.buffer.remove(self.cursor_pos);
+        self.buffer.remove(self.byte_index(self.cursor_pos));
         Ok(())
     }
 
@@ -49,16 +62,16 @@ impl Canonical {
         if self.cursor_pos <= 0 {
             return Err(());
         }
-        self.buffer.remove(self.cursor_pos - 1);
+        self.buffer.remove(self.byte_index(self.cursor_pos - 1));
         self.cursor_pos -= 1;
         Ok(())
     }
 
     fn add_at_cursor(&mut self, ch: char) -> Result<(), ()> {
-        if self.buffer.len() >= BUFFER_SIZE {
+        if self.len() >= BUFFER_SIZE {
             return Err(());
         }
-        self.buffer.insert(self.cursor_pos, ch);
+        self.buffer.insert(self.byte_index(self.cursor_pos), ch);
         self.cursor_pos += 1;
         Ok(())
     }
@@ -69,8 +82,9 @@ impl Canonical {
     }
 
     fn move_cursor_to_end(&mut self) -> Result<usize, ()> {
-        self.cursor_pos = self.buffer.len();
-        Ok(self.buffer.len() - self.cursor_pos)
+        let steps = self.len() - self.cursor_pos;
+        self.cursor_pos = self.len();
+        Ok(steps)
     }
 
     fn move_cursor_left(&mut self) -> Result<(), ()> {
@@ -82,7 +96,7 @@ impl Canonical {
     }
 
     fn move_cursor_right(&mut self) -> Result<(), ()> {
-        if self.cursor_pos >= self.buffer.len() {
+        if self.cursor_pos >= self.len() {
             return Err(());
         }
         self.cursor_pos += 1;
@@ -94,8 +108,11 @@ pub struct InputObserver {
     terminal: Rc<LFBTerminal>,
     event_handler: Rc<RefCell<EventHandler>>,
     decoder: EventDecoder<AnyLayout>,
+    composer: Composer,
     mode: TerminalMode,
     canonical: Canonical,
+    /// Is a control key held down? (the decoder ignores it)
+    ctrl: bool,
 }
 
 impl InputObserver {
@@ -107,8 +124,10 @@ impl InputObserver {
                 AnyLayout::De105Key(De105Key),
                 HandleControl::Ignore,
             ),
+            composer: Composer::new(DE105_DEAD_KEYS),
             mode: TerminalMode::Raw,
             canonical: Canonical::new(),
+            ctrl: false,
         }
     }
 }
@@ -116,6 +135,9 @@ impl InputObserver {
 impl Worker for InputObserver {
     fn run(&mut self) {
         let Some(key_event) = self.terminal.read_event_nb() else { return };
+        if matches!(key_event.code, KeyCode::LControl | KeyCode::RControl | KeyCode::RControl2) {
+            self.ctrl = key_event.state != KeyState::Up;
+        }
 
         // Get terminal input state (canonical, fluid, idle)
         let raw_state = syscall(SystemCall::TerminalCheckInputState, &[]).expect("Unable to check input state");
@@ -144,8 +166,8 @@ impl Worker for InputObserver {
 
         // Buffer the decoded key based on the terminal input state
         let (buffer, mode) = match state {
-            TerminalInputState::Canonical => (self.buffer_canonical(decoded_key), TerminalMode::Canonical),
-            TerminalInputState::Fluid => (self.buffer_fluid(decoded_key), TerminalMode::Fluid),
+            TerminalInputState::Canonical => (self.buffer_composed(decoded_key, TerminalMode::Canonical), TerminalMode::Canonical),
+            TerminalInputState::Fluid => (self.buffer_composed(decoded_key, TerminalMode::Fluid), TerminalMode::Fluid),
             TerminalInputState::Raw => (self.buffer_raw(key_event), TerminalMode::Raw),
             TerminalInputState::Idle => return
         };
@@ -161,12 +183,20 @@ impl Worker for InputObserver {
 }
 
 impl InputObserver {
-    fn try_intercept_reserved_key(&self, key: DecodedKey) -> Option<DecodedKey> {
+    fn try_intercept_reserved_key(&mut self, key: DecodedKey) -> Option<DecodedKey> {
         match key {
             DecodedKey::RawKey(HKEY_TOGGLE_TERMINAL_WINDOW) => {
                 self.event_handler.borrow_mut().trigger(Event::EnterGuiMode);
                 return None;
             }
+            // Ctrl+C interrupts the application running in the foreground (if any)
+            DecodedKey::Unicode('c' | 'C') if self.ctrl => {
+                if syscall(SystemCall::TerminalInterrupt, &[]).is_ok() {
+                    self.canonical = Canonical::new();
+                    self.terminal.write_str("^C\n");
+                }
+                return None;
+            }
             key => return Some(key),
         }
     }
@@ -176,11 +206,17 @@ impl InputObserver {
         Some(raw.to_ne_bytes().to_vec())
     }
 
-    fn buffer_fluid(&self, key: DecodedKey) -> Option<Vec<u8>> {
-        match key {
-            DecodedKey::Unicode(key) => Some([DecodedKeyType::Unicode as u8, key as u8].to_vec()),
-            DecodedKey::RawKey(key) => Some([DecodedKeyType::RawKey as u8, key as u8].to_vec()),
+    /// Apply dead keys and compose sequences to `key` and buffer the resulting keys (there may be none or two).
+    fn buffer_composed(&mut self, key: DecodedKey, mode: TerminalMode) -> Option<Vec<u8>> {
+        let mut buffer = Vec::new();
+        for key in self.composer.process(key) {
+            match mode {
+                TerminalMode::Canonical => buffer.extend(self.buffer_canonical(key).unwrap_or_default()),
+                _ => encode_fluid_key(key, &mut buffer),
+            }
         }
+
+        (!buffer.is_empty()).then_some(buffer)
     }
 
     fn buffer_canonical(&mut self, key: DecodedKey) -> Option<Vec<u8>> {
@@ -209,7 +245,7 @@ impl InputObserver {
 
             DecodedKey::Unicode('\x1B') => return None,
             DecodedKey::Unicode('\n') => {
-                let offset = self.canonical.buffer.len() - self.canonical.cursor_pos;
+                let offset = self.canonical.len() - self.canonical.cursor_pos;
                 if offset > 0 {
                     self.terminal.write_str(&format!("\x1B[{}C\n", offset));
                 } else {
@@ -240,11 +276,11 @@ impl InputObserver {
     }
 
     fn redraw_canonical_content(&self) -> String {
-        let content = &self.canonical.buffer[self.canonical.cursor_pos..];
+        let content = &self.canonical.buffer[self.canonical.byte_index(self.canonical.cursor_pos)..];
         if content.is_empty() {
             String::new()
         } else {
-            format!("\x1b[0K{}\x1B[{}D", content, content.len())
+            format!("\x1b[0K{}\x1B[{}D", content, content.chars().count())
         }
     }
 }
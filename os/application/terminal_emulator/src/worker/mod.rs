@@ -1,4 +1,5 @@
 pub mod cursor;
+pub mod display_observer;
 pub mod input_observer;
 pub mod output_observer;
 pub mod status_bar;
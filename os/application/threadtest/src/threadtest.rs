@@ -1,4 +1,5 @@
 #![no_std]
+#![feature(thread_local)]
 
 extern crate alloc;
 
@@ -9,6 +10,9 @@ use runtime::*;
 use runtime::env::args;
 use terminal::println;
 
+/// Each thread has its own copy, starting with the initial value
+#[thread_local]
+static mut COUNTER: usize = 42;
 
 fn thread_fn() {
     let process = process::current().unwrap();
@@ -21,6 +25,10 @@ fn thread_fn() {
     arr.fill(1);
 
     println!("Thread [{}] accessing arr[1797]: [{}]", thread.id(), arr[1797]);
+
+    // the main thread has already changed its copy
+    let counter = unsafe { COUNTER += thread.id(); COUNTER };
+    println!("Thread [{}] has thread-local counter [{}] (expected [{}])", thread.id(), counter, 42 + thread.id());
 }
 
 #[unsafe(no_mangle)]
@@ -35,6 +43,7 @@ pub fn main() {
     let start_time = thread.start_time();
 
     println!("Hello from main thread with ID [{}] in process [{}] started at [{}]!", thread.id(), process.id(), start_time);
+    unsafe { COUNTER = 0 };
 
     for _ in 0..num_threads {
         match thread::create(thread_fn) {
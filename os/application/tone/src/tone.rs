@@ -0,0 +1,51 @@
+//! tone – play a square wave through the sound card
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use argparse::Parser;
+#[allow(unused_imports)]
+use runtime::*;
+use syscall::audio::{CHANNELS, SAMPLE_RATE};
+use syscall::{syscall, SystemCall};
+use terminal::println;
+
+const DEFAULT_FREQUENCY: u32 = 440;
+const DEFAULT_DURATION_MS: u32 = 1000;
+/// A quarter of the full range, loud enough without clipping when mixed with other streams
+const AMPLITUDE: i16 = i16::MAX / 4;
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let parser = Parser::new("tone", "Play a square wave (needs an HD Audio device)")
+        .option(Some('f'), "frequency", "HZ", "Frequency of the tone (default: 440)")
+        .option(Some('d'), "duration", "MS", "Duration in milliseconds (default: 1000)");
+    // the first argument is the program name
+    let args = parser.parse(env::args().skip(1)).and_then(|matches| {
+        let frequency = matches.parse_value::<u32>("frequency")?.unwrap_or(DEFAULT_FREQUENCY);
+        let duration = matches.parse_value::<u32>("duration")?.unwrap_or(DEFAULT_DURATION_MS);
+        Ok((frequency, duration))
+    });
+    let (frequency, duration) = match args {
+        Ok((frequency, _)) if frequency == 0 || frequency > SAMPLE_RATE / 2 => {
+            println!("Frequency must be between 1 and {} Hz", SAMPLE_RATE / 2);
+            return;
+        }
+        Ok(args) => args,
+        Err(err) => {
+            println!("{}", parser.report(&err));
+            return;
+        }
+    };
+
+    let frames = SAMPLE_RATE as u64 * duration as u64 / 1000;
+    let samples: Vec<i16> = (0..frames)
+        .map(|frame| if frame * frequency as u64 * 2 / SAMPLE_RATE as u64 % 2 == 0 { AMPLITUDE } else { -AMPLITUDE })
+        .flat_map(|sample| [sample; CHANNELS])
+        .collect();
+
+    if let Err(err) = syscall(SystemCall::AudioWrite, &[samples.as_ptr() as usize, samples.len()]) {
+        println!("Failed to play tone: {:?}", err);
+    }
+}
@@ -0,0 +1,143 @@
+//! top – show the CPU usage of all threads and cores, measured over an interval
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use argparse::Parser;
+use concurrent::{process, thread};
+#[allow(unused_imports)]
+use runtime::*;
+use syscall::process_info::{ThreadInfo, NO_CORE};
+use terminal::println;
+use ::time::systime;
+
+/// Counters of a core from /proc/schedstat (times in microseconds)
+struct CoreCounters {
+    id: u32,
+    switches: u64,
+    user: u64,
+    system: u64,
+    idle: u64,
+}
+
+/// Counters of all threads and cores at one point in time
+struct Sample {
+    time_ms: i64,
+    threads: Vec<ThreadInfo>,
+    cores: Vec<CoreCounters>,
+}
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let parser = Parser::new("top", "Show the CPU usage of all threads and cores, measured over an interval")
+        .option(Some('d'), "delay", "SECONDS", "Length of the interval (default: 2)")
+        .option(Some('n'), "iterations", "COUNT", "Number of intervals to show (default: 1)");
+    // the first argument is the program name
+    let args = parser.parse(env::args().skip(1)).and_then(|matches| {
+        let delay = matches.parse_value::<usize>("delay")?.unwrap_or(2).max(1);
+        let iterations = matches.parse_value::<usize>("iterations")?.unwrap_or(1);
+        Ok((delay, iterations))
+    });
+    let (delay, iterations) = match args {
+        Ok(args) => args,
+        Err(err) => {
+            println!("{}", parser.report(&err));
+            return;
+        }
+    };
+
+    let Some(mut before) = sample() else {
+        return;
+    };
+    for _ in 0..iterations {
+        thread::sleep(delay * 1000);
+        let Some(after) = sample() else {
+            return;
+        };
+        show(&before, &after);
+        before = after;
+    }
+}
+
+fn sample() -> Option<Sample> {
+    let threads = match process::list() {
+        Ok(threads) => threads,
+        Err(err) => {
+            println!("top: Failed to list processes ({:?})", err);
+            return None;
+        }
+    };
+    let cores = match fs::read_to_string("/proc/schedstat") {
+        Ok(content) => parse_schedstat(&content),
+        Err(err) => {
+            println!("top: Failed to read /proc/schedstat ({:?})", err);
+            return None;
+        }
+    };
+
+    Some(Sample { time_ms: systime().num_milliseconds(), threads, cores })
+}
+
+/// Parse the lines "core switches ready user system idle" (the first one is the header)
+fn parse_schedstat(content: &str) -> Vec<CoreCounters> {
+    content.lines().skip(1).filter_map(|line| {
+        let values: Vec<u64> = line.split_whitespace().filter_map(|value| value.parse().ok()).collect();
+        match values[..] {
+            [id, switches, _ready, user, system, idle] => Some(CoreCounters { id: id as u32, switches, user, system, idle }),
+            _ => None,
+        }
+    }).collect()
+}
+
+fn show(before: &Sample, after: &Sample) {
+    let elapsed_us = ((after.time_ms - before.time_ms).max(1) * 1000) as u64;
+
+    println!("{:>4} {:>6} {:>6} {:>6} {:>10}", "CORE", "USER", "SYSTEM", "IDLE", "SWITCHES/s");
+    for core in &after.cores {
+        let Some(old) = before.cores.iter().find(|old| old.id == core.id) else {
+            continue;
+        };
+        let (user, system, idle) = (core.user - old.user, core.system - old.system, core.idle - old.idle);
+        // the times are sampled, so their sum is the interval as seen by the core
+        let total = (user + system + idle).max(1);
+        let switches = (core.switches - old.switches) * 1_000_000 / elapsed_us;
+        println!("{:>4} {:>6} {:>6} {:>6} {:>10}", core.id, percent(user, total), percent(system, total), percent(idle, total), switches);
+    }
+    println!();
+
+    // CPU time, context switches, dispatches and run delay of each thread during the interval
+    let mut deltas: Vec<(&ThreadInfo, u64, u64, u64, u64)> = after.threads.iter().map(|info| {
+        let zero = ThreadInfo::default();
+        let old = before.threads.iter().find(|old| old.tid == info.tid).unwrap_or(&zero);
+        let cpu = (info.user_time_us + info.system_time_us).saturating_sub(old.user_time_us + old.system_time_us);
+        let switches = (info.voluntary_switches + info.involuntary_switches).saturating_sub(old.voluntary_switches + old.involuntary_switches);
+        let dispatches = info.dispatches.saturating_sub(old.dispatches);
+        let delay = info.run_delay_us.saturating_sub(old.run_delay_us);
+        (info, cpu, switches, dispatches, delay)
+    }).collect();
+    deltas.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.tid.cmp(&b.0.tid)));
+
+    println!("{:>5} {:>5} {:<16} {:>6} {:>4} {:>8} {:>9} {:>11}", "PID", "TID", "NAME", "CPU%", "CORE", "SWITCHES", "DELAY(us)", "MAXDELAY(us)");
+    for (info, cpu, switches, dispatches, delay) in deltas {
+        let core = match info.core {
+            NO_CORE => String::from("-"),
+            core => format!("{}", core),
+        };
+        // average time between becoming ready and running during the interval
+        let delay = match dispatches {
+            0 => String::from("-"),
+            dispatches => format!("{}", delay / dispatches),
+        };
+        println!("{:>5} {:>5} {:<16} {:>6} {:>4} {:>8} {:>9} {:>11}",
+            info.pid, info.tid, info.name(), percent(cpu, elapsed_us), core, switches, delay, info.max_run_delay_us);
+    }
+}
+
+/// `part` of `total` in percent with one decimal place
+fn percent(part: u64, total: u64) -> String {
+    let per_mille = part * 1000 / total;
+    format!("{}.{}", per_mille / 10, per_mille % 10)
+}
@@ -0,0 +1,30 @@
+//! umount – unmount a file system
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::ToString;
+use argparse::{ParseError, Parser};
+#[allow(unused_imports)]
+use runtime::*;
+use terminal::println;
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let parser = Parser::new("umount", "Unmount the file system mounted on a directory")
+        .positional("path", "Directory the file system is mounted on");
+    // the first argument is the program name
+    let path = parser.parse(env::args().skip(1))
+        .and_then(|matches| matches.value("path").map(str::to_string).ok_or(ParseError::MissingArgument("path")));
+    let path = match path {
+        Ok(path) => path,
+        Err(err) => {
+            println!("{}", parser.report(&err));
+            return;
+        }
+    };
+
+    if let Err(err) = naming::umount(&path) {
+        println!("Failed to unmount {}: {:?}", path, err);
+    }
+}
@@ -0,0 +1,117 @@
+//! vsocktest – exchange messages with the host via virtio-vsock
+//!
+//! On the host, the other end can be provided by `socat`, e.g. `socat - VSOCK-LISTEN:1234`
+//! (for `vsocktest`) or `socat - VSOCK-CONNECT:3:1234` (for `vsocktest -l 1234`, if the guest has CID 3).
+#![no_std]
+
+extern crate alloc;
+
+#[allow(unused_imports)]
+use runtime::*;
+use terminal::println;
+use vsock::{VsockAddr, VsockListener, VsockStream, VMADDR_CID_HOST};
+use alloc::vec::Vec;
+
+const DEFAULT_PORT: u32 = 1234;
+
+fn usage() {
+    println!("Usage: vsocktest [<host port>]");
+    println!("       vsocktest -l <port>");
+}
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+
+    match args.as_slice() {
+        [] => client(DEFAULT_PORT),
+        ["-l", port] => match port.parse() {
+            Ok(port) => server(port),
+            Err(_) => usage(),
+        },
+        [port] => match port.parse() {
+            Ok(port) => client(port),
+            Err(_) => usage(),
+        },
+        _ => usage(),
+    }
+}
+
+/// Connect to the host, send a message and wait for the reply.
+fn client(port: u32) {
+    let host_addr = VsockAddr { cid: VMADDR_CID_HOST, port };
+    println!("Connecting to host on port {}...", port);
+
+    // Verbindungsaufbau
+    let stream = match VsockStream::connect(host_addr) {
+        Ok(stream) => stream,
+        Err(e) => {
+            println!("Connection failed: {:?}", e);
+            return;
+        }
+    };
+    println!("Successfully connected to host!");
+
+    // Nachricht senden
+    let message = "Hello from Guest!\n";
+    println!("Sending message: '{}'", message.trim_end());
+    if let Err(e) = stream.write(message.as_bytes()) {
+        println!("Send failed: {:?}", e);
+        return;
+    }
+
+    // Auf Antwort warten (blockiert)
+    println!("Waiting for reply...");
+    let mut buffer = [0u8; 1024];
+    match stream.read(&mut buffer) {
+        Ok(len) => {
+            let reply = core::str::from_utf8(&buffer[..len]).unwrap_or("Invalid UTF-8");
+            println!("Received reply: '{}'", reply.trim_end());
+        }
+        Err(e) => println!("Receive failed: {:?}", e),
+    }
+
+    // Verbindung wird beim Drop geschlossen
+    println!("VSock Test Application finished.");
+}
+
+/// Accept connections on `port` and echo everything back.
+fn server(port: u32) {
+    let listener = match VsockListener::bind(port) {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("Failed to listen on port {}: {:?}", port, e);
+            return;
+        }
+    };
+    println!("Listening on port {}...", listener.port());
+
+    loop {
+        let stream = match listener.accept() {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("Accept failed: {:?}", e);
+                return;
+            }
+        };
+        let peer = stream.peer_addr();
+        println!("Connection from {}:{}", peer.cid, peer.port);
+
+        let mut buffer = [0u8; 1024];
+        // bis der Host die Verbindung schließt
+        while let Ok(len) = stream.read(&mut buffer) {
+            let mut sent = 0;
+            while sent < len {
+                match stream.write(&buffer[sent..len]) {
+                    Ok(now_sent) => sent += now_sent,
+                    Err(e) => {
+                        println!("Send failed: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        }
+        println!("Connection from {}:{} closed", peer.cid, peer.port);
+    }
+}
@@ -0,0 +1,104 @@
+//! wget – download a file via HTTP
+//!
+//! The file is saved in the current directory under the last part of its path
+//! (or `index.html`), unless another name is given with `-O`.
+#![no_std]
+extern crate alloc;
+
+use alloc::string::{String, ToString};
+use http::Url;
+use naming::shared_types::OpenOptions;
+#[allow(unused_imports)]
+use runtime::*;
+use syscall::return_vals::Errno;
+use terminal::{print, println};
+
+#[unsafe(no_mangle)]
+fn main() {
+    let mut args = env::args().peekable();
+    // the first argument is the program name, ignore it
+    args.next();
+
+    let mut output = None;
+
+    // check the next arguments for flags
+    loop {
+        match args.peek().map(String::as_str) {
+            Some("-h") | Some("--help") => {
+                println!("Usage:
+    wget [-O file] url
+
+Examples:
+    wget http://10.0.2.2:8000/test.txt
+        save test.txt in the current directory
+    wget -O - http://example.org
+        print the page to the terminal");
+                return;
+            }
+            Some("-O") => {
+                args.next();
+                output = args.next();
+            },
+            // now, we're finally past the options
+            Some(_) => break,
+            None => {
+                println!("Usage: wget [-O file] url");
+                return;
+            },
+        }
+    }
+
+    // the next argument should be the URL
+    let Some(url) = args.next() else {
+        println!("Usage: wget [-O file] url");
+        return;
+    };
+
+    let response = match http::get(&url) {
+        Ok(response) => response,
+        Err(e) => {
+            println!("wget: {url}: {e:?}");
+            return;
+        }
+    };
+    if !response.is_success() {
+        println!("wget: {}: {} {}", response.url(), response.status(), response.reason());
+        return;
+    }
+
+    match output.as_deref() {
+        Some("-") => print!("{}", String::from_utf8_lossy(response.body())),
+        path => {
+            let path = path.map_or_else(|| file_name(response.url()), ToString::to_string);
+            match save(&path, response.body()) {
+                Ok(()) => println!("Saved {} bytes to '{path}'", response.body().len()),
+                Err(e) => println!("wget: {path}: {e:?}"),
+            }
+        }
+    }
+}
+
+/// The last part of the path of `url` (without the query) or `index.html` for directories.
+fn file_name(url: &Url) -> String {
+    let path = url.path().split('?').next().unwrap();
+    match path.rsplit('/').next() {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => "index.html".to_string(),
+    }
+}
+
+fn save(path: &str, mut data: &[u8]) -> Result<(), Errno> {
+    let fd = naming::open(path, OpenOptions::READWRITE | OpenOptions::CREATE | OpenOptions::TRUNCATE)?;
+    while !data.is_empty() {
+        match naming::write(fd, data) {
+            Ok(0) => break,
+            Ok(written) => data = &data[written..],
+            Err(e) => {
+                let _ = naming::close(fd);
+                return Err(e);
+            }
+        }
+    }
+    naming::close(fd)?;
+    Ok(())
+}
@@ -0,0 +1,42 @@
+//! wol – power on another machine with a Wake-on-LAN magic packet
+#![no_std]
+
+extern crate alloc;
+
+use argparse::Parser;
+#[allow(unused_imports)]
+use runtime::*;
+use terminal::println;
+
+#[unsafe(no_mangle)]
+pub fn main() {
+    let parser = Parser::new("wol", "Power on another machine with a Wake-on-LAN magic packet")
+        .positional("mac", "MAC address of the machine, e.g. 52:54:00:12:34:56");
+    // the first argument is the program name
+    let matches = match parser.parse(env::args().skip(1)) {
+        Ok(matches) => matches,
+        Err(err) => {
+            println!("{}", parser.report(&err));
+            return;
+        }
+    };
+    let Some(mac) = matches.value("mac").and_then(parse_mac) else {
+        println!("Invalid MAC address '{}'", matches.value("mac").unwrap_or_default());
+        return;
+    };
+
+    match network::wake_on_lan(mac) {
+        Ok(()) => println!("Sent magic packet"),
+        Err(err) => println!("Failed to send magic packet: {:?}", err),
+    }
+}
+
+fn parse_mac(text: &str) -> Option<[u8; 6]> {
+    let mut mac = [0u8; 6];
+    let mut parts = text.split([':', '-']);
+    for byte in mac.iter_mut() {
+        *byte = u8::from_str_radix(parts.next()?, 16).ok()?;
+    }
+
+    parts.next().is_none().then_some(mac)
+}
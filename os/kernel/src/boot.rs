@@ -9,25 +9,27 @@
 */
 
 use crate::consts;
-use crate::device::pit::Timer;
+use crate::device::pit::{ClockSource, Timer};
 use crate::device::ps2::{Keyboard, Mouse};
-use crate::device::{qemu_cfg, virtio};
+use crate::device::{bochs_vga, display, hda, hpet, pvclock, qemu_cfg, serial_console, virtio};
 use crate::device::serial::SerialPort;
-use crate::interrupt::interrupt_dispatcher;
+use crate::interrupt::{halt, interrupt_dispatcher, park, smp_call, watchdog};
 use crate::memory::nvmem::Nfit;
 use crate::memory::pages::page_table_index;
 use crate::memory::vma::VmaType;
-use crate::memory::{dram, nvmem, PAGE_SIZE};
+use crate::memory::{dram, nvmem, tlb, PAGE_SIZE};
 use crate::process::thread::Thread;
+use crate::storage::ramdisk;
+use crate::splash::{self, SubsystemEvent};
 use crate::syscall::{sys_vmem, syscall_dispatcher};
 use crate::{
-    acpi_tables, allocator, apic, gdt, get_initrd_frames,
+    acpi_tables, allocator, apic, entropy_pool, gdt, get_initrd_frames,
     efi_services_available, init_acpi_tables, init_apic, init_boot_info,
     init_cpu_info, init_initrd, init_lfb, init_lfb_info, init_pci,
     init_serial_port, init_tty, keyboard, logger, mouse,
     process_manager, scheduler, serial_port, timer, tss,
 };
-use crate::{built_info, memory, naming, network, storage};
+use crate::{built_info, kshell, memory, naming, network, storage, timesync, wallclock};
 
 use alloc::format;
 use alloc::string::ToString;
@@ -39,6 +41,7 @@ use core::ops::Deref;
 use core::ptr;
 use log::{trace, debug, info, warn, LevelFilter};
 use multiboot2::{BootInformation, BootInformationHeader, EFIMemoryMapTag, MemoryAreaType, MemoryMapTag, TagHeader};
+use syscall::display::DisplayMode;
 use uefi::data_types::Handle;
 use uefi::mem::memory_map::MemoryMap;
 use uefi::runtime::Time;
@@ -54,6 +57,7 @@ use x86_64::structures::gdt::Descriptor;
 use x86_64::structures::paging::frame::PhysFrameRange;
 use x86_64::structures::paging::{PageTable, PageTableFlags, PhysFrame};
 use x86_64::{PhysAddr, VirtAddr};
+use syscall::priority::Priority;
 
 // import labels from linker script 'link.ld'
 unsafe extern "C" {
@@ -62,6 +66,28 @@ unsafe extern "C" {
 }
 
 const BOOT_TO_GUI: bool = false; // Immediately start the GUI instead of terminal (Debug)
+const BOOT_LOG_PATH: Option<&str> = Some("/boot.log.lz4"); // Persist the compressed boot log in the naming service
+const BOOT_SPLASH: bool = true; // Show the boot progress on the framebuffer (disable with 'nosplash' on the kernel command line)
+
+/// Subsystems initialized by `start()` in this order (the boot splash derives the length of its progress bar from this table)
+const SUBSYSTEMS: &[&str] = &[
+    "ACPI tables",
+    "Interrupts",
+    "Timer",
+    "Entropy",
+    "EFI runtime services",
+    "Input devices",
+    "PCI",
+    "VirtIO devices",
+    "Display",
+    "Storage",
+    "Network",
+    "Audio",
+    "Non-volatile memory",
+    "Initial ramdisk",
+    "Naming service",
+    "Applications",
+];
 
 /// First Rust function called from assembly code `boot.asm` \
 ///   `multiboot2_magic` is the magic number read from 'eax' \
@@ -131,6 +157,21 @@ pub extern "C" fn start(multiboot2_magic: u32, multiboot2_addr: *const BootInfor
         initrd_region.len()
     );
 
+    // and for an optional boot module, that is loaded into a ramdisk
+    let ramdisk_module = multiboot
+        .module_tags()
+        .find(|module| module.cmdline().is_ok_and(|name| name == "ramdisk"))
+        .map(|module| (get_initrd_frames(module), (module.end_address() - module.start_address()) as usize));
+    if let Some((ramdisk_region, _)) = ramdisk_module {
+        dram::insert_reserved(ramdisk_region);
+        info!(
+            "Ramdisk region:      [{:#x} - {:#x}], #frames: [{}]",
+            ramdisk_region.start.start_address().as_u64(),
+            ramdisk_region.end.start_address().as_u64(),
+            ramdisk_region.len()
+        );
+    }
+
     // and finally the same for the multiboot region
     let multiboot_region = get_multiboot_frames(&multiboot);
     dram::insert_reserved(multiboot_region);
@@ -197,6 +238,7 @@ pub extern "C" fn start(multiboot2_magic: u32, multiboot2_addr: *const BootInfor
     init_lfb_info(fb_info.address(), fb_info.pitch(), fb_info.width(), fb_info.height(), fb_info.bpp());
     // Initialize framebuffer (For window_manager)
     init_lfb(fb_info.address() as *mut u8, fb_info.pitch(), fb_info.width(), fb_info.height(), fb_info.bpp());
+    display::init(DisplayMode { width: fb_info.width(), height: fb_info.height(), bpp: fb_info.bpp() });
 
     // Dumping basic infos
     info!("Welcome to D3OS!");
@@ -235,141 +277,222 @@ pub extern "C" fn start(multiboot2_magic: u32, multiboot2_addr: *const BootInfor
     info!("Compiler: [{}]", built_info::RUSTC_VERSION);
     info!("Bootloader: [{bootloader_name}]");
 
-    // Initialize ACPI tables
-    info!("Initializing ACPI tables");
-    let rsdp_addr: usize = if let Some(rsdp_tag) = multiboot.rsdp_v2_tag() {
-        ptr::from_ref(rsdp_tag) as usize + size_of::<TagHeader>()
-    } else if let Some(rsdp_tag) = multiboot.rsdp_v1_tag() {
-        ptr::from_ref(rsdp_tag) as usize + size_of::<TagHeader>()
-    } else {
-        panic!("ACPI not available!");
-    };
-    init_acpi_tables(rsdp_addr);
-
-    interrupt_dispatcher::setup_idt();
-
-    syscall_dispatcher::init();
-
-    init_apic();
-
-    // Initialize timer
-    info!("Initializing timer");
-    let timer = timer();
-    Timer::plugin(Arc::clone(&timer));
-
-    // Enable interrupts
-    info!("Enabling interrupts");
-    interrupts::enable();
+    // From now on, the progress of the subsystems can be shown on the framebuffer
+    let cmdline = multiboot.command_line_tag().and_then(|tag| tag.cmdline().ok());
+    if BOOT_SPLASH && !cmdline.is_some_and(|cmdline| cmdline.split_whitespace().any(|arg| arg == "nosplash")) {
+        splash::show(SUBSYSTEMS.len());
+    }
+    let serial_console = serial_console::requested(cmdline);
 
-    // Initialize EFI runtime service (if available and not done already during memory initialization)
-    if uefi::table::system_table_raw().is_none() {
-        match multiboot.efi_sdt64_tag() {
-            Some(tag) => {
-                info!("Initializing EFI runtime services");
-                unsafe { uefi::table::set_system_table(tag.sdt_address() as *const SystemTable) };
+    // Initialize ACPI tables
+    init_subsystem("ACPI tables", || {
+        info!("Initializing ACPI tables");
+        let rsdp_addr: usize = if let Some(rsdp_tag) = multiboot.rsdp_v2_tag() {
+            ptr::from_ref(rsdp_tag) as usize + size_of::<TagHeader>()
+        } else if let Some(rsdp_tag) = multiboot.rsdp_v1_tag() {
+            ptr::from_ref(rsdp_tag) as usize + size_of::<TagHeader>()
+        } else {
+            panic!("ACPI not available!");
+        };
+        init_acpi_tables(rsdp_addr);
+    });
+
+    init_subsystem("Interrupts", || {
+        interrupt_dispatcher::setup_idt();
+
+        syscall_dispatcher::init();
+
+        init_apic();
+        tlb::init();
+        halt::init();
+        smp_call::init();
+        park::init();
+    });
+
+    init_subsystem("Timer", || {
+        // Initialize timer
+        info!("Initializing timer");
+        pvclock::init();
+        hpet::init();
+        let timer = timer();
+        let clock_source = ClockSource::choose(cmdline);
+        info!("Using clock source [{:?}]", clock_source);
+        timer.select_clock_source(clock_source);
+        Timer::plugin(Arc::clone(&timer));
+        watchdog::init();
+        timesync::init();
+        wallclock::init();
+
+        // Enable interrupts
+        info!("Enabling interrupts");
+        interrupts::enable();
+    });
+
+    // Seed entropy pool (interrupts keep feeding it afterwards)
+    init_subsystem("Entropy", || {
+        info!("Gathering entropy");
+        entropy_pool().add_cpu_entropy();
+        entropy_pool().gather_jitter_entropy();
+    });
+
+    init_subsystem("EFI runtime services", || {
+        // Initialize EFI runtime service (if available and not done already during memory initialization)
+        if uefi::table::system_table_raw().is_none() {
+            match multiboot.efi_sdt64_tag() {
+                Some(tag) => {
+                    info!("Initializing EFI runtime services");
+                    unsafe { uefi::table::set_system_table(tag.sdt_address() as *const SystemTable) };
+                }
+                None => warn!("Bootloader did not provide EFI system table pointer"),
             }
-            None => warn!("Bootloader did not provide EFI system table pointer"),
         }
-    }
 
-    // Dump information about EFI runtime service
-    info!(
-        "EFI runtime services available (Vendor: [{}], UEFI version: [{}])",
-        uefi::system::firmware_vendor(),
-        uefi::system::uefi_revision()
-    );
+        // Dump information about EFI runtime service
+        info!(
+            "EFI runtime services available (Vendor: [{}], UEFI version: [{}])",
+            uefi::system::firmware_vendor(),
+            uefi::system::uefi_revision()
+        );
+    });
 
-    // Initialize keyboard
-    if let Some(keyboard) = keyboard() {
-        Keyboard::plugin(keyboard);
-    }
+    init_subsystem("Input devices", || {
+        // Initialize keyboard
+        if let Some(keyboard) = keyboard() {
+            Keyboard::plugin(keyboard);
+        }
 
-    if let Some(mouse) = mouse() {
-        Mouse::plugin(mouse);
-    }
+        if let Some(mouse) = mouse() {
+            Mouse::plugin(mouse);
+        }
 
-    // Enable serial port interrupts
-    if let Some(serial) = serial_port() {
-        SerialPort::plugin(serial);
-    }
+        // Enable serial port interrupts and offer the kernel shell on the serial port
+        // (unless the serial port is used as the console, which needs its input)
+        if let Some(serial) = serial_port() {
+            SerialPort::plugin(serial);
+        }
+        if !serial_console {
+            kshell::init();
+        }
+    });
 
     // Scan PCI bus
-    info!("Scanning PCI bus");
-    init_pci();
+    init_subsystem("PCI", || {
+        info!("Scanning PCI bus");
+        init_pci();
+    });
 
-    virtio::init_devices(fb_start_phys_addr, fb_end_phys_addr); // Framebuffer Start und Endadresse von Multiboot-LFB
+    // The kernel command line may describe virtio-mmio devices, which can't be found by scanning a bus
+    init_subsystem("VirtIO devices", || {
+        virtio::init_devices(fb_start_phys_addr, fb_end_phys_addr, cmdline); // Framebuffer Start und Endadresse von Multiboot-LFB
+    });
+
+    // Look for a graphics card, that can switch the display mode
+    init_subsystem("Display", bochs_vga::init);
 
     // Initialize storage devices
-    storage::init();
+    init_subsystem("Storage", || {
+        storage::init(cmdline);
+        ramdisk::init(cmdline, ramdisk_module);
+    });
 
     // Initialize network stack
-    network::init();
-
-    // Initialize non-volatile memory (creates identity mappings for any non-volatile memory regions)
-    nvmem::init();
-
-    // As a demo for NVRAM support, we read the last boot time from NVRAM and write the current boot time to it
-    if let Ok(nfit) = acpi_tables().lock().find_table::<Nfit>() {
-        if let Some(range) = nfit.get_phys_addr_ranges().first() {
-            let date_ptr = range.as_phys_frame_range().start.start_address().as_u64() as *mut Time;
-
-            // Read last boot time from NVRAM
-            let date = unsafe { date_ptr.read() };
-            if date.is_valid().is_ok() {
-                info!(
-                    "Last boot time: [{:0>4}-{:0>2}-{:0>2} {:0>2}:{:0>2}:{:0>2}]",
-                    date.year(),
-                    date.month(),
-                    date.day(),
-                    date.hour(),
-                    date.minute(),
-                    date.second()
-                );
-            }
+    init_subsystem("Network", network::init);
+
+    // Initialize sound output (HD Audio)
+    init_subsystem("Audio", hda::init);
+
+    init_subsystem("Non-volatile memory", || {
+        // Initialize non-volatile memory (creates identity mappings for any non-volatile memory regions)
+        nvmem::init();
+
+        // As a demo for NVRAM support, we read the last boot time from NVRAM and write the current boot time to it
+        if let Ok(nfit) = acpi_tables().lock().find_table::<Nfit>() {
+            if let Some(range) = nfit.get_phys_addr_ranges().first() {
+                let date_ptr = range.as_phys_frame_range().start.start_address().as_u64() as *mut Time;
+
+                // Read last boot time from NVRAM
+                let date = unsafe { date_ptr.read() };
+                if date.is_valid().is_ok() {
+                    info!(
+                        "Last boot time: [{:0>4}-{:0>2}-{:0>2} {:0>2}:{:0>2}:{:0>2}]",
+                        date.year(),
+                        date.month(),
+                        date.day(),
+                        date.hour(),
+                        date.minute(),
+                        date.second()
+                    );
+                }
 
-            // Write current boot time to NVRAM
-            if efi_services_available() {
-                if let Ok(time) = uefi::runtime::get_time() {
-                    unsafe { date_ptr.write(time) }
+                // Write current boot time to NVRAM
+                if efi_services_available() {
+                    if let Ok(time) = uefi::runtime::get_time() {
+                        unsafe { date_ptr.write(time) }
+                    }
                 }
             }
         }
-    }
+    });
 
     // Load initial ramdisk
-    init_initrd(initrd_tag);
+    init_subsystem("Initial ramdisk", || init_initrd(initrd_tag));
 
     // Init naming service
-    naming::api::init();
-
-    // Create and register the cleanup thread in the scheduler
-    // (If the last thread of a process terminates, it cannot delete its own address space)
-    extern "sysv64" fn cleanup() {
-        loop {
-            scheduler().sleep(100);
-            process_manager().write().drop_exited_process();
+    init_subsystem("Naming service", naming::api::init);
+
+    init_subsystem("Applications", || {
+        // Create and register the cleanup thread in the scheduler
+        // (If the last thread of a process terminates, it cannot delete its own address space)
+        extern "sysv64" fn cleanup() {
+            loop {
+                scheduler().sleep(100);
+                process_manager().write().drop_exited_process();
+            }
+        }
+        let thread = Thread::new_kernel_thread(cleanup, "cleanup");
+        thread.set_priority(Priority::Background);
+        scheduler().ready(thread);
+
+        //Initialize tty buffer (Workaround for missing pipes)
+        init_tty();
+
+        // The network manager applies DHCP leases (the kernel only runs the DHCP client)
+        if network::dhcp_enabled() {
+            match Thread::load_application("/bin/netmand", "netmand", &[].to_vec()) {
+                Ok(thread) => scheduler().ready(thread),
+                Err(e) => warn!("Failed to start netmand, network interfaces stay unconfigured: {e:?}"),
+            }
         }
-    }
-    scheduler().ready(Thread::new_kernel_thread(cleanup, "cleanup"));
-
-    //Initialize tty buffer (Workaround for missing pipes)
-    init_tty();
 
-    if BOOT_TO_GUI {
-        // Create and register the 'window_manager' thread in the scheduler
-        scheduler().ready(Thread::load_application(
-            "bin/window_manager", "window_manager", &[].to_vec(),
-        ).expect("failed to load window_manager"));
-    } else {
-        // Create and register the 'terminal_emulator' thread (from app image in ramdisk) in the scheduler
-        scheduler().ready(Thread::load_application(
-            "bin/terminal_emulator", "terminal_emulator", &[].to_vec(),
-        ).expect("failed to load terminal_emulator"));
-    }
+        if serial_console {
+            // The shell runs on the serial port ('console=serial' on the kernel command line)
+            serial_console::start();
+        } else if BOOT_TO_GUI {
+            // Create and register the 'window_manager' thread in the scheduler
+            scheduler().ready(Thread::load_application(
+                "/bin/window_manager", "window_manager", &[].to_vec(),
+            ).expect("failed to load window_manager"));
+        } else {
+            // Create and register the 'terminal_emulator' thread (from app image in ramdisk) in the scheduler
+            scheduler().ready(Thread::load_application(
+                "/bin/terminal_emulator", "terminal_emulator", &[].to_vec(),
+            ).expect("failed to load terminal_emulator"));
+        }
+    });
 
     // Dump information about all processes (including VMAs)
     process_manager().read().dump();
 
+    // Save the compressed boot log
+    if let Some(path) = BOOT_LOG_PATH {
+        if let Err(e) = logger().persist_history(path) {
+            warn!("Failed to persist boot log to [{path}]: {e:?}");
+        }
+    }
+
+    // The applications take over the framebuffer
+    splash::finish();
+
     // Start APIC timer & scheduler
     info!("Starting scheduler");
     apic().start_timer(10);
@@ -377,6 +500,22 @@ pub extern "C" fn start(multiboot2_magic: u32, multiboot2_addr: *const BootInfor
     scheduler().start();
 }
 
+/// Initialize a subsystem and report its progress to the boot splash. \
+/// A subsystem is considered failed, if it has logged an error during its initialization. \
+/// Each subsystem must be listed in `SUBSYSTEMS`, so that the progress bar of the boot splash is complete.
+fn init_subsystem<T>(name: &'static str, init: impl FnOnce() -> T) -> T {
+    debug_assert!(SUBSYSTEMS.contains(&name), "Subsystem [{name}] is missing in 'SUBSYSTEMS'");
+    let errors = logger().error_count();
+    splash::report(SubsystemEvent::Started(name));
+    let result = init();
+    if logger().error_count() > errors {
+        splash::report(SubsystemEvent::Failed(name));
+    } else {
+        splash::report(SubsystemEvent::Finished(name));
+    }
+    result
+}
+
 /// Set up the GDT
 fn init_gdt() {
     let mut gdt = gdt().lock();
@@ -479,6 +618,13 @@ fn multiboot2_search_memory_map(multiboot2_addr: *const BootInformationHeader) -
 /// Available only if efi boot services have been exited and bootloader provides these memory maps.
 fn scan_multiboot2_memory_map(memory_map: &MemoryMapTag) {
     info!("Searching memory map for available regions");
+    for area in memory_map.memory_areas() {
+        dram::insert_firmware(PhysFrameRange {
+            start: PhysFrame::containing_address(PhysAddr::new(area.start_address())),
+            end: PhysFrame::from_start_address(PhysAddr::new(area.end_address()).align_up(PAGE_SIZE as u64)).unwrap(),
+        });
+    }
+
     memory_map
         .memory_areas()
         .iter()
@@ -496,6 +642,11 @@ fn scan_multiboot2_memory_map(memory_map: &MemoryMapTag) {
 /// efi information has been requested.
 fn scan_efi_multiboot2_memory_map(memory_map: &EFIMemoryMapTag) {
     info!("Searching memory map for available regions");
+    for area in memory_map.memory_areas() {
+        let start = PhysFrame::containing_address(PhysAddr::new(area.phys_start));
+        dram::insert_firmware(PhysFrame::range(start, start + area.page_count));
+    }
+
     memory_map
         .memory_areas()
         .filter(|area| {
@@ -525,6 +676,11 @@ fn scan_efi_multiboot2_memory_map(memory_map: &EFIMemoryMapTag) {
 /// Memory map from efi. Only available if boot services have NOT been exited.
 fn scan_efi_memory_map(memory_map: &dyn MemoryMap) {
     info!("Searching memory map for available regions");
+    for area in memory_map.entries() {
+        let start = PhysFrame::containing_address(PhysAddr::new(area.phys_start));
+        dram::insert_firmware(PhysFrame::range(start, start + area.page_count));
+    }
+
     memory_map
         .entries()
         .filter(|area| {
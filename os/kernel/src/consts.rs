@@ -11,6 +11,11 @@ pub const USER_SPACE_ARG_START: usize = USER_SPACE_ENV_START;
 // User space stacks (Max size per stack: 1 GiB)
 pub const MAX_USER_STACK_SIZE: usize = 0x40000000;  // 1 GiB
 pub const MAIN_USER_STACK_START: usize = USER_SPACE_ENV_START + 0x40000000;  // 1 GiB
+
+// Shared libraries are loaded once and mapped at the same address into all processes using them (Max size: 1 TiB)
+pub const USER_SPACE_LIB_START: usize = 0x200000000000;  // 32 TiB
+pub const MAX_USER_SPACE_LIB_SIZE: usize = 0x10000000000;  // 1 TiB
+
 pub const KERNEL_STACK_PAGES: usize = 64;
 pub const STACK_ENTRY_SIZE: usize = 8;  
 
@@ -1,7 +1,9 @@
 use crate::interrupt::interrupt_dispatcher::InterruptVector;
 use crate::interrupt::interrupt_handler::InterruptHandler;
 use crate::memory::vma::VmaType;
-use crate::{acpi_tables, allocator, interrupt_dispatcher, process_manager, scheduler, timer};
+use crate::{acpi_tables, allocator, apic, interrupt_dispatcher, process_manager, scheduler, timer};
+use core::arch::x86_64::{_mm_mfence, _rdtsc};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use acpi::InterruptModel;
 use acpi::madt::Madt;
 use acpi::platform::interrupt::{InterruptSourceOverride, NmiSource, Polarity, TriggerMode};
@@ -10,18 +12,62 @@ use alloc::format;
 use alloc::vec::Vec;
 use log::{info, warn};
 use raw_cpuid::CpuId;
-use spin::Mutex;
+use spin::{Mutex, Once};
 use uefi::boot::PAGE_SIZE;
 use x2apic::ioapic::{IoApic, IrqFlags, IrqMode, RedirectionTableEntry};
 use x2apic::lapic::{LocalApic, LocalApicBuilder, TimerDivide, TimerMode};
+use x86_64::registers::model_specific::Msr;
 use x86_64::structures::paging::PageTableFlags;
 
+/// The APIC base MSR contains the physical address of the local APIC's registers (bits 12 to 51).
+/// Bit 10 is set, if the local APIC is in x2APIC mode.
+const IA32_APIC_BASE: u32 = 0x1b;
+const APIC_BASE_X2APIC_ENABLE: u64 = 1 << 10;
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+/// Destination of IO APIC redirection entries and of IPIs in xAPIC mode (physical destination mode)
+const MAX_XAPIC_ID: u32 = 0xff;
+/// In TSC-deadline mode, the local APIC timer fires, once the TSC reaches the value of this MSR (writing 0 disarms it)
+const IA32_TSC_DEADLINE: u32 = 0x6e0;
+/// Time, during which the timers are compared to the PIT for calibration
+const CALIBRATION_MS: usize = 50;
+/// Maximum number of cores with their own timer
+const MAX_CORES: usize = 64;
+
+/// Timer configuration of each core. It is read by the timer interrupt handler, so it is kept in atomics.
+static CORE_TIMERS: [CoreTimer; MAX_CORES] = [const { CoreTimer::new() }; MAX_CORES];
+/// Next free entry in `CORE_TIMERS`
+static NEXT_TIMER_SLOT: AtomicUsize = AtomicUsize::new(0);
+/// The timer interrupt handler is shared by all cores
+static TIMER_HANDLER: Once = Once::new();
+
 pub struct Apic {
     local_apic: Mutex<LocalApic>,
+    /// The local APIC is accessed via MSRs and has 32 bit ids (instead of MMIO and 8 bit ids)
+    x2apic: bool,
     io_apics: Vec<(Mutex<IoApic>, u32)>, // (0: IO APIC instance, 1: Base Global System Interrupt)
     irq_overrides: Vec<InterruptSourceOverride>,
     nmi_sources: Vec<NmiSource>,
-    timer_ticks_per_ms: usize,
+}
+
+struct CoreTimer {
+    apic_id: AtomicU32,
+    /// Set, once `apic_id` is valid
+    registered: AtomicBool,
+    /// TSC cycles between two ticks in TSC-deadline mode (0 in periodic mode, where the timer re-arms itself)
+    deadline_cycles: AtomicU64,
+    /// Time between two ticks (in nanoseconds)
+    interval_ns: AtomicU64,
+}
+
+impl CoreTimer {
+    const fn new() -> Self {
+        Self {
+            apic_id: AtomicU32::new(0),
+            registered: AtomicBool::new(false),
+            deadline_cycles: AtomicU64::new(0),
+            interval_ns: AtomicU64::new(0),
+        }
+    }
 }
 
 unsafe impl Send for Apic {}
@@ -32,7 +78,23 @@ struct ApicTimerInterruptHandler {}
 
 impl InterruptHandler for ApicTimerInterruptHandler {
     fn trigger(&self) {
-        scheduler().switch_thread_from_interrupt();
+        // Im TSC-Deadline-Modus feuert der Timer nur einmal, der nächste Tick muss also vor dem Threadwechsel gestellt werden
+        if let Some(core) = core_timer(apic().local_apic_id()) {
+            let cycles = core.deadline_cycles.load(Ordering::Relaxed);
+            if cycles > 0 {
+                arm_tsc_deadline(cycles);
+            }
+        }
+        scheduler().tick();
+    }
+}
+
+#[derive(Default)]
+struct ApicErrorInterruptHandler {}
+
+impl InterruptHandler for ApicErrorInterruptHandler {
+    fn trigger(&self) {
+        apic().log_error();
     }
 }
 
@@ -82,6 +144,12 @@ impl Apic {
             cpu_info.boot_processor.processor_uid
         );
 
+        // Ohne Interrupt-Remapping können IO APICs nur 8 Bit breite Ziele adressieren
+        let bsp_apic_id = cpu_info.boot_processor.local_apic_id;
+        if bsp_apic_id > MAX_XAPIC_ID {
+            warn!("   Local APIC id [{bsp_apic_id}] of the bootstrap processor can't be addressed by the IO APICs, device interrupts won't work");
+        }
+
         // Vectors to store IRQ overrides and Non-maskable interrupts
         let mut irq_overrides = Vec::<InterruptSourceOverride>::new();
         let mut nmi_sources = Vec::<NmiSource>::new();
@@ -90,7 +158,7 @@ impl Apic {
         let mut io_apics = Vec::<(Mutex<IoApic>, u32)>::new();
 
         // Create Local APIC instance
-        let local_apic = Mutex::new(Self::create_local_apic(&madt));
+        let local_apic = Mutex::new(Self::create_local_apic(madt.local_apic_address as u64));
 
         match int_model.0 {
             InterruptModel::Apic(apic_desc) => {
@@ -177,7 +245,7 @@ impl Apic {
                         let mut flags = IrqFlags::MASKED;
 
                         entry.set_mode(IrqMode::Fixed);
-                        entry.set_dest(cpu_info.boot_processor.local_apic_id as u8);
+                        entry.set_dest(bsp_apic_id as u8);
 
                         match override_for_target(&irq_overrides, i) {
                             None => entry.set_vector(i as u8 + InterruptVector::Pit as u8),
@@ -228,8 +296,8 @@ impl Apic {
             entry.set_vector(0);
             entry.set_flags(flags);
 
-            // Needs to be executed in unsafe block; At this point, the APIC has been initialized successfully, so we can assume that reading the MSR works.
-            entry.set_dest(unsafe { local_apic.lock().id() } as u8);
+            // The firmware may already have switched to x2APIC mode
+            entry.set_dest(read_local_apic_id(x2apic_enabled()) as u8);
 
             // Find the correct IO APIC for the given NMI and set the corresponding entry in its redirection table
             match io_apic_for_target(&io_apics, nmi.global_system_interrupt) {
@@ -254,29 +322,34 @@ impl Apic {
             );
             local_apic.lock().enable();
         }
-
-        // Calibrate APIC timer
-        let timer_ticks_per_ms = Apic::calibrate_timer(&mut local_apic.lock());
-        info!("   APIC Timer ticks per millisecond: [{timer_ticks_per_ms}]");
+        // The x2apic crate switches to x2APIC mode, if the CPU supports it
+        let x2apic = x2apic_enabled();
+        info!("   Local APIC is in {} mode", if x2apic { "x2APIC" } else { "xAPIC" });
+        interrupt_dispatcher().assign(InterruptVector::ApicError, Box::new(ApicErrorInterruptHandler::default()));
 
         Self {
             local_apic,
+            x2apic,
             io_apics,
             irq_overrides,
             nmi_sources,
-            timer_ticks_per_ms,
         }
     }
 
-    fn create_local_apic(madt: &Madt) -> LocalApic {
+    fn create_local_apic(madt_address: u64) -> LocalApic {
         let process = process_manager().read().kernel_process().unwrap();
 
-        let lapic_registers_phys_addr = madt.local_apic_address as u64;
-        
+        // The MSR is authoritative, the firmware may have relocated the registers (the MADT only contains the default)
+        let lapic_registers_phys_addr = unsafe { Msr::new(IA32_APIC_BASE).read() } & APIC_BASE_ADDRESS_MASK;
+        if lapic_registers_phys_addr != madt_address {
+            warn!("   Local APIC registers are at [{lapic_registers_phys_addr:#x}], but MADT reports [{madt_address:#x}]");
+        }
+
+        // The registers must be uncacheable (PCD and PWT), all accesses go through the x2apic crate
         let lapic_registers_page = process.virtual_address_space.kernel_map_devm_identity(
             lapic_registers_phys_addr,
             lapic_registers_phys_addr + PAGE_SIZE as u64,
-            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE,
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE | PageTableFlags::WRITE_THROUGH,
             VmaType::DeviceMemory,
             "lapic",
         );
@@ -327,6 +400,61 @@ impl Apic {
         }
     }
 
+    /// Get the id of the local APIC of the calling core (32 bit in x2APIC mode, 8 bit otherwise).
+    /// It is read via CPUID, so this works in interrupt handlers without locking the local APIC.
+    pub fn local_apic_id(&self) -> u32 {
+        read_local_apic_id(self.x2apic)
+    }
+
+    /// Send an inter-processor interrupt with `vector` to the core with the local APIC `apic_id`
+    /// (use the typed senders in `interrupt::ipi` instead).
+    /// The x2apic crate writes the ICR via MSR (as a single 64 bit write) in x2APIC mode
+    /// and via MMIO otherwise, where only 8 bit destinations are possible.
+    pub fn send_ipi(&self, vector: u8, apic_id: u32) {
+        if !self.x2apic && apic_id > MAX_XAPIC_ID {
+            panic!("APIC: Can't send IPI to local APIC [{apic_id}] in xAPIC mode!");
+        }
+        unsafe { self.local_apic.lock().send_ipi(vector, apic_id) };
+    }
+
+    /// Like `send_ipi()`, but doesn't wait for the local APIC lock (used on panic,
+    /// where the lock may be held by the interrupted code on this core).
+    pub fn send_ipi_forced(&self, vector: u8, apic_id: u32) {
+        if !self.x2apic && apic_id > MAX_XAPIC_ID {
+            return;
+        }
+        let mut local_apic = self.local_apic.try_lock();
+        while local_apic.is_none() {
+            unsafe { self.local_apic.force_unlock() };
+            local_apic = self.local_apic.try_lock();
+        }
+        unsafe { local_apic.unwrap().send_ipi(vector, apic_id) };
+    }
+
+    /// Send a non-maskable interrupt to the core with the local APIC `apic_id` (used by the watchdog).
+    /// Returns `false` without sending, if the local APIC is locked by the interrupted code on this core.
+    pub fn try_send_nmi(&self, apic_id: u32) -> bool {
+        if !self.x2apic && apic_id > MAX_XAPIC_ID {
+            return false;
+        }
+        match self.local_apic.try_lock() {
+            Some(mut local_apic) => {
+                unsafe { local_apic.send_nmi(apic_id) };
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Log the content of the error status register (called by the interrupt handler for `ApicError`).
+    fn log_error(&self) {
+        // Der Local APIC könnte gerade von der unterbrochenen Stelle gesperrt sein
+        match self.local_apic.try_lock() {
+            Some(local_apic) => warn!("APIC error on local APIC [{}]: [{:?}]", self.local_apic_id(), unsafe { local_apic.error_flags() }),
+            None => warn!("APIC error on local APIC [{}] (error status not readable)", self.local_apic_id()),
+        }
+    }
+
     pub fn end_of_interrupt(&self) {
         let mut local_apic = self.local_apic.try_lock();
         while local_apic.is_none() {
@@ -343,20 +471,48 @@ impl Apic {
         }
     }
 
+    /// Calibrate and start the local APIC timer of the calling core, which then drives the scheduler tick every `interval_ms`.
+    /// Must be called by each core (application processors during their startup), since the timers are per core
+    /// and may run at different rates. TSC-deadline mode is used, if the core supports it.
     pub fn start_timer(&self, interval_ms: usize) {
-        let mut local_apic = self.local_apic.lock();
+        TIMER_HANDLER.call_once(|| {
+            interrupt_dispatcher().assign(InterruptVector::ApicTimer, Box::new(ApicTimerInterruptHandler::default()));
+        });
+
+        let apic_id = self.local_apic_id();
+        let tsc_deadline = CpuId::new().get_feature_info().is_some_and(|features| features.has_tsc_deadline());
+        let deadline_cycles = if tsc_deadline {
+            let tsc_per_ms = timer().calibrate_tsc(CALIBRATION_MS);
+            info!("APIC timer of core [{apic_id}] uses TSC-deadline mode (TSC ticks per millisecond: [{tsc_per_ms}])");
+            let mut local_apic = self.local_apic.lock();
+            unsafe {
+                local_apic.disable_timer();
+                local_apic.set_timer_mode(TimerMode::TscDeadline);
+                local_apic.enable_timer();
+            }
+            tsc_per_ms * interval_ms as u64
+        } else {
+            let mut local_apic = self.local_apic.lock();
+            let ticks_per_ms = Apic::calibrate_timer(&mut local_apic);
+            info!("APIC timer of core [{apic_id}] uses periodic mode (ticks per millisecond: [{ticks_per_ms}])");
+            unsafe {
+                local_apic.set_timer_divide(TimerDivide::Div1);
+                local_apic.set_timer_mode(TimerMode::Periodic);
+                local_apic.set_timer_initial((ticks_per_ms * interval_ms) as u32);
+                local_apic.enable_timer();
+            }
+            0
+        };
 
-        unsafe {
-            local_apic.set_timer_divide(TimerDivide::Div1);
-            local_apic.set_timer_mode(TimerMode::Periodic);
-            local_apic.set_timer_initial((self.timer_ticks_per_ms * interval_ms) as u32);
-            local_apic.enable_timer();
+        register_core_timer(apic_id, deadline_cycles, interval_ms as u64 * 1_000_000);
+        if deadline_cycles > 0 {
+            arm_tsc_deadline(deadline_cycles);
         }
+    }
 
-        interrupt_dispatcher().assign(
-            InterruptVector::ApicTimer,
-            Box::new(ApicTimerInterruptHandler::default()),
-        );
+    /// Time between two ticks of the calling core's local APIC timer (in nanoseconds, 0 if it has not been started)
+    pub fn timer_interval_ns(&self) -> u64 {
+        core_timer(self.local_apic_id()).map_or(0, |core| core.interval_ns.load(Ordering::Relaxed))
     }
 
     fn calibrate_timer(local_apic: &mut LocalApic) -> usize {
@@ -368,11 +524,11 @@ impl Apic {
             local_apic.set_timer_initial(0xffffffff);
             local_apic.enable_timer();
 
-            // Wait 50 ms using the PIT
-            timer().wait(50);
+            // Wait using the PIT
+            timer().wait(CALIBRATION_MS);
 
             // Calculate APIC timer ticks per millisecond
-            let ticks_per_ms = ((0xffffffff - local_apic.timer_current()) / 50) as usize;
+            let ticks_per_ms = ((0xffffffff - local_apic.timer_current()) / CALIBRATION_MS as u32) as usize;
             local_apic.disable_timer();
 
             ticks_per_ms
@@ -380,6 +536,62 @@ impl Apic {
     }
 }
 
+/// Remember the timer configuration of the calling core for its interrupt handler.
+fn register_core_timer(apic_id: u32, deadline_cycles: u64, interval_ns: u64) {
+    // a core, that restarts its timer, keeps its entry
+    if let Some(core) = core_timer(apic_id) {
+        core.deadline_cycles.store(deadline_cycles, Ordering::Relaxed);
+        core.interval_ns.store(interval_ns, Ordering::Relaxed);
+        return;
+    }
+
+    let slot = NEXT_TIMER_SLOT.fetch_add(1, Ordering::Relaxed);
+    if slot >= MAX_CORES {
+        warn!("APIC: Too many cores, the timer of core [{apic_id}] is not re-armed");
+        return;
+    }
+    CORE_TIMERS[slot].apic_id.store(apic_id, Ordering::Relaxed);
+    CORE_TIMERS[slot].deadline_cycles.store(deadline_cycles, Ordering::Relaxed);
+    CORE_TIMERS[slot].interval_ns.store(interval_ns, Ordering::Relaxed);
+    CORE_TIMERS[slot].registered.store(true, Ordering::Release);
+}
+
+fn core_timer(apic_id: u32) -> Option<&'static CoreTimer> {
+    CORE_TIMERS.iter()
+        .take(NEXT_TIMER_SLOT.load(Ordering::Relaxed).min(MAX_CORES))
+        .filter(|core| core.registered.load(Ordering::Acquire))
+        .find(|core| core.apic_id.load(Ordering::Relaxed) == apic_id)
+}
+
+/// Make the calling core's timer fire in `cycles` TSC cycles (in TSC-deadline mode).
+fn arm_tsc_deadline(cycles: u64) {
+    unsafe {
+        // Im xAPIC-Modus kann das Schreiben der MSR sonst vor dem Umschalten des LVT-Eintrags (MMIO) wirksam werden
+        _mm_mfence();
+        Msr::new(IA32_TSC_DEADLINE).write(_rdtsc() + cycles);
+    }
+}
+
+/// Check the mode of the calling core's local APIC.
+fn x2apic_enabled() -> bool {
+    // Reading the APIC base MSR is safe on every CPU with a local APIC
+    unsafe { Msr::new(IA32_APIC_BASE).read() & APIC_BASE_X2APIC_ENABLE != 0 }
+}
+
+/// Get the id of the calling core's local APIC without accessing its registers.
+/// In x2APIC mode, the 32 bit id is only reported by the extended topology leaf (CPUID 0x0b),
+/// the initial APIC id in leaf 1 is truncated to 8 bits.
+fn read_local_apic_id(x2apic: bool) -> u32 {
+    let cpuid = CpuId::new();
+    if x2apic && let Some(level) = cpuid.get_extended_topology_info().and_then(|mut levels| levels.next()) {
+        return level.x2apic_id();
+    }
+
+    cpuid.get_feature_info()
+        .map(|features| features.initial_local_apic_id() as u32)
+        .expect("APIC: Failed to read CPU ID features!")
+}
+
 fn target_gsi(irq_overrides: &[InterruptSourceOverride], source_irq: u8) -> u32 {
     match override_for_source(irq_overrides, source_irq) {
         None => source_irq as u32,
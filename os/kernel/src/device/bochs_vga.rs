@@ -0,0 +1,203 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: bochs_vga                                                       ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Mode setting for the Bochs/QEMU standard VGA ('-vga std' and    ║
+   ║         '-device bochs-display'). The display interface (DISPI) is      ║
+   ║         programmed via its memory mapped registers in BAR 2 or, on      ║
+   ║         older devices, via the I/O ports 0x1ce/0x1cf. The framebuffer   ║
+   ║         is at the start of the video memory in BAR 0 for all modes.     ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - init                 find the device and register it for modes      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use graphic::lfb::FramebufferInfo;
+use log::{info, warn};
+use spin::Mutex;
+use syscall::display::DisplayMode;
+use syscall::return_vals::Errno;
+use x86_64::PhysAddr;
+use x86_64::instructions::port::Port;
+use x86_64::structures::paging::PageTableFlags;
+use crate::device::display::{self, DisplayDriver};
+use crate::device::{pci_resources, registry};
+use crate::memory::PAGE_SIZE;
+use crate::memory::vma::VmaType;
+use crate::{lfb_info, pci_bus, process_manager};
+
+const VENDOR_ID: u16 = 0x1234;
+const DEVICE_ID: u16 = 0x1111;
+
+const INDEX_PORT: u16 = 0x01ce;
+const DATA_PORT: u16 = 0x01cf;
+/// Offset of the DISPI registers in BAR 2 (each register is 16 bits wide)
+const MMIO_DISPI_OFFSET: u64 = 0x500;
+
+/// Versions of the interface (all of them support linear framebuffers with 32 bits per pixel)
+const ID_MIN: u16 = 0xb0c0;
+const ID_MAX: u16 = 0xb0cf;
+const ENABLED: u16 = 0x01;
+const LFB_ENABLED: u16 = 0x40;
+
+const BPP: u8 = 32;
+/// Modes offered to applications (the ones, that fit into the video memory)
+const RESOLUTIONS: &[(u32, u32)] = &[
+    (640, 480), (800, 600), (1024, 768), (1280, 720), (1280, 800),
+    (1280, 1024), (1440, 900), (1600, 900), (1680, 1050), (1920, 1080),
+];
+
+#[repr(u16)]
+#[derive(Clone, Copy)]
+enum Register {
+    Id = 0x0,
+    XResolution = 0x1,
+    YResolution = 0x2,
+    Bpp = 0x3,
+    Enable = 0x4,
+    VirtualWidth = 0x6,
+    VirtualHeight = 0x7,
+    XOffset = 0x8,
+    YOffset = 0x9,
+}
+
+enum Registers {
+    Ports { index: Port<u16>, data: Port<u16> },
+    Mmio(u64),
+}
+
+impl Registers {
+    fn read(&mut self, register: Register) -> u16 {
+        match self {
+            Registers::Ports { index, data } => unsafe {
+                index.write(register as u16);
+                data.read()
+            },
+            Registers::Mmio(base) => unsafe { ((*base + register as u64 * 2) as *const u16).read_volatile() },
+        }
+    }
+
+    fn write(&mut self, register: Register, value: u16) {
+        match self {
+            Registers::Ports { index, data } => unsafe {
+                index.write(register as u16);
+                data.write(value);
+            },
+            Registers::Mmio(base) => unsafe { ((*base + register as u64 * 2) as *mut u16).write_volatile(value) },
+        }
+    }
+}
+
+pub struct BochsVga {
+    registers: Mutex<Registers>,
+    /// Physical address of the video memory (BAR 0)
+    framebuffer: u64,
+    memory_size: u64,
+}
+
+impl DisplayDriver for BochsVga {
+    fn name(&self) -> &'static str {
+        "bochs-vga"
+    }
+
+    fn modes(&self) -> Vec<DisplayMode> {
+        RESOLUTIONS.iter()
+            .filter(|(width, height)| (*width as u64 * *height as u64 * (BPP as u64 / 8)) <= self.memory_size)
+            .map(|(width, height)| DisplayMode { width: *width, height: *height, bpp: BPP })
+            .collect()
+    }
+
+    fn set_mode(&self, mode: DisplayMode) -> Result<FramebufferInfo, Errno> {
+        let (Ok(width), Ok(height)) = (u16::try_from(mode.width), u16::try_from(mode.height)) else {
+            return Err(Errno::EINVAL);
+        };
+
+        let mut registers = self.registers.lock();
+        // Der Modus kann nur bei ausgeschaltetem Display geändert werden
+        registers.write(Register::Enable, 0);
+        registers.write(Register::XResolution, width);
+        registers.write(Register::YResolution, height);
+        registers.write(Register::Bpp, mode.bpp as u16);
+        registers.write(Register::VirtualWidth, width);
+        registers.write(Register::VirtualHeight, height);
+        registers.write(Register::XOffset, 0);
+        registers.write(Register::YOffset, 0);
+        registers.write(Register::Enable, ENABLED | LFB_ENABLED);
+
+        if registers.read(Register::XResolution) != width || registers.read(Register::YResolution) != height {
+            warn!("Bochs VGA did not accept mode [{}x{}x{}]", mode.width, mode.height, mode.bpp);
+            return Err(Errno::EINVAL);
+        }
+
+        Ok(FramebufferInfo {
+            addr: self.framebuffer,
+            width: mode.width,
+            height: mode.height,
+            pitch: mode.width * (mode.bpp as u32 / 8),
+            bpp: mode.bpp,
+        })
+    }
+}
+
+/// Register the first Bochs/QEMU VGA for mode setting.
+pub fn init() {
+    let devices = pci_bus().search_by_ids(VENDOR_ID, DEVICE_ID);
+    let Some(device) = devices.first() else {
+        return;
+    };
+    let device = device.read();
+    let bars = pci_resources::bars(pci_bus().config_space(), &device);
+    let Some(memory) = bars.iter().find(|bar| bar.slot == 0 && bar.start != 0) else {
+        warn!("Bochs VGA has no video memory");
+        return;
+    };
+
+    let mut registers = match pci_resources::map_memory_bar(&device, 2, "bochs-vga-mmio") {
+        Some((start, _)) => Registers::Mmio(start + MMIO_DISPI_OFFSET),
+        None => Registers::Ports { index: Port::new(INDEX_PORT), data: Port::new(DATA_PORT) },
+    };
+    let id = registers.read(Register::Id);
+    if !(ID_MIN..=ID_MAX).contains(&id) {
+        warn!("Unsupported Bochs VGA interface [0x{:04x}]", id);
+        return;
+    }
+    info!("Found Bochs VGA (interface [0x{:04x}], {} KiB video memory)", id, memory.size / 1024);
+    registry::bind(&registry::pci_name(device.header().address()), "bochs-vga");
+
+    map_video_memory(memory.start, memory.start + memory.size);
+    display::register(Box::new(BochsVga { registers: Mutex::new(registers), framebuffer: memory.start, memory_size: memory.size }));
+}
+
+/// Identity map the video memory, except for the part, that is already mapped as the framebuffer of the bootloader.
+fn map_video_memory(start: u64, end: u64) {
+    let boot_framebuffer = lfb_info();
+    let boot_start = PhysAddr::new(boot_framebuffer.address).align_down(PAGE_SIZE as u64).as_u64();
+    let boot_end = PhysAddr::new(boot_framebuffer.address + boot_framebuffer.height as u64 * boot_framebuffer.pitch as u64)
+        .align_up(PAGE_SIZE as u64)
+        .as_u64();
+
+    if boot_start < end && start < boot_end {
+        map_range(start, boot_start);
+        map_range(boot_end, end);
+    } else {
+        map_range(start, end);
+    }
+}
+
+fn map_range(start: u64, end: u64) {
+    if start >= end {
+        return;
+    }
+
+    let kernel_process = process_manager().read().kernel_process().unwrap();
+    kernel_process.virtual_address_space.kernel_map_devm_identity(
+        start,
+        end,
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE,
+        VmaType::DeviceMemory,
+        "bochs-vram",
+    );
+}
@@ -0,0 +1,117 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: cpu_sensors                                                     ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Temperature and frequency of each core, read from MSRs on the   ║
+   ║         core itself (with a cross-core call). The temperature comes     ║
+   ║         from the digital thermal sensor (IA32_THERM_STATUS), which      ║
+   ║         reports the distance to TjMax. The frequency is derived from    ║
+   ║         APERF (counting at the actual frequency) and MPERF (counting at ║
+   ║         the base frequency) since the previous readout. MSRs are only   ║
+   ║         accessed, if CPUID announces them (hypervisors usually don't).  ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - read_all             read the sensors of all registered cores       ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use raw_cpuid::CpuId;
+use spin::{Mutex, Once};
+use syscall::cpu::{CpuSensors, TEMPERATURE_UNKNOWN};
+use x86_64::registers::model_specific::Msr;
+use crate::apic;
+use crate::interrupt::smp_call;
+
+const IA32_MPERF: u32 = 0xe7;
+const IA32_APERF: u32 = 0xe8;
+const IA32_THERM_STATUS: u32 = 0x19c;
+const MSR_TEMPERATURE_TARGET: u32 = 0x1a2;
+
+/// IA32_THERM_STATUS: the digital readout (bits 16..23) is valid
+const THERM_STATUS_VALID: u64 = 1 << 31;
+/// TjMax of CPUs, that don't report it
+const DEFAULT_TJ_MAX: i32 = 100;
+
+/// Sensors announced by CPUID (all cores are assumed to be the same)
+struct Features {
+    thermal_sensor: bool,
+    /// TjMax in degrees Celsius
+    tj_max: i32,
+    aperf_mperf: bool,
+    base_frequency_mhz: u32,
+}
+
+static FEATURES: Once<Features> = Once::new();
+/// APERF and MPERF of the previous readout per core (always accessed with interrupts disabled)
+static PREVIOUS: Mutex<BTreeMap<u32, (u64, u64)>> = Mutex::new(BTreeMap::new());
+
+/// Read the sensors of all cores, that take part in cross-core calls (sorted by local APIC id).
+pub fn read_all() -> Vec<CpuSensors> {
+    let readouts = Arc::new(Mutex::new(Vec::new()));
+
+    // Auf dem eigenen Kern läuft die Funktion direkt (mit gesperrten Interrupts)
+    for apic_id in smp_call::cpus() {
+        let readouts = Arc::clone(&readouts);
+        let _ = smp_call::call_on(apic_id, move || readouts.lock().push(read_local()));
+    }
+
+    let mut readouts = core::mem::take(&mut *readouts.lock());
+    readouts.sort_by_key(|readout| readout.apic_id);
+    readouts
+}
+
+fn features() -> &'static Features {
+    FEATURES.call_once(|| {
+        let cpuid = CpuId::new();
+        let thermal = cpuid.get_thermal_power_info();
+        let thermal_sensor = thermal.as_ref().is_some_and(|info| info.has_dts());
+        // MSR_TEMPERATURE_TARGET gibt es erst seit Nehalem, das Package-Thermal-Management-Bit erst seit Sandy Bridge
+        let tj_max = match thermal.as_ref().is_some_and(|info| info.has_ptm()) {
+            true => ((unsafe { Msr::new(MSR_TEMPERATURE_TARGET).read() } >> 16) & 0xff) as i32,
+            false => DEFAULT_TJ_MAX,
+        };
+
+        Features {
+            thermal_sensor,
+            tj_max: if tj_max == 0 { DEFAULT_TJ_MAX } else { tj_max },
+            aperf_mperf: thermal.as_ref().is_some_and(|info| info.has_hw_coord_feedback()),
+            base_frequency_mhz: cpuid.get_processor_frequency_info().map_or(0, |info| info.processor_base_frequency() as u32),
+        }
+    })
+}
+
+/// Read the sensors of the calling core (must be called with interrupts disabled).
+fn read_local() -> CpuSensors {
+    let features = features();
+    let apic_id = apic().local_apic_id();
+
+    let mut temperature = TEMPERATURE_UNKNOWN;
+    if features.thermal_sensor {
+        let status = unsafe { Msr::new(IA32_THERM_STATUS).read() };
+        if status & THERM_STATUS_VALID != 0 {
+            temperature = features.tj_max - ((status >> 16) & 0x7f) as i32;
+        }
+    }
+
+    let mut performance_permille = 0;
+    if features.aperf_mperf {
+        let (aperf, mperf) = unsafe { (Msr::new(IA32_APERF).read(), Msr::new(IA32_MPERF).read()) };
+        let (last_aperf, last_mperf) = PREVIOUS.lock().insert(apic_id, (aperf, mperf)).unwrap_or_default();
+
+        let delta_mperf = mperf.wrapping_sub(last_mperf);
+        if delta_mperf != 0 {
+            performance_permille = (aperf.wrapping_sub(last_aperf) as u128 * 1000 / delta_mperf as u128) as u32;
+        }
+    }
+
+    CpuSensors {
+        apic_id,
+        temperature,
+        performance_permille,
+        base_frequency_mhz: features.base_frequency_mhz,
+        frequency_mhz: (features.base_frequency_mhz as u64 * performance_permille as u64 / 1000) as u32,
+    }
+}
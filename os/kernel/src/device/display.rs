@@ -0,0 +1,89 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: display                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Mode setting for the framebuffer. The bootloader's framebuffer  ║
+   ║         is used, until a display driver (e.g. for the Bochs/QEMU VGA)   ║
+   ║         switches to another mode. Afterwards, the kernel draws to the   ║
+   ║         new framebuffer and 'MapFrameBuffer' maps it, so applications   ║
+   ║         have to map it again when the mode has changed. Drivers, that   ║
+   ║         replace the framebuffer on their own (e.g. virtio-gpu after the ║
+   ║         host has resized its window), announce the new one as well.     ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - init                 remember the mode set up by the bootloader     ║
+   ║   - register             register the display driver                    ║
+   ║   - modes                list the modes supported by the driver         ║
+   ║   - current_mode         get the mode of the current framebuffer        ║
+   ║   - set_mode             switch to another mode                         ║
+   ║   - framebuffer_changed  use another framebuffer                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use graphic::lfb::FramebufferInfo;
+use log::info;
+use spin::{Mutex, RwLock};
+use syscall::display::DisplayMode;
+use syscall::return_vals::Errno;
+use crate::replace_lfb;
+use crate::syscall::sys_vmem;
+
+/// A driver, that can switch the resolution of the display.
+pub trait DisplayDriver: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// All modes, that `set_mode()` accepts.
+    fn modes(&self) -> Vec<DisplayMode>;
+
+    /// Switch to `mode` and return the new framebuffer (which must be identity mapped in the kernel).
+    fn set_mode(&self, mode: DisplayMode) -> Result<FramebufferInfo, Errno>;
+}
+
+static DRIVER: RwLock<Option<Box<dyn DisplayDriver>>> = RwLock::new(None);
+static MODE: Mutex<DisplayMode> = Mutex::new(DisplayMode { width: 0, height: 0, bpp: 0 });
+
+/// Remember the mode of the framebuffer, that has been set up by the bootloader.
+pub fn init(mode: DisplayMode) {
+    *MODE.lock() = mode;
+}
+
+/// Use `driver` for mode setting (replacing a driver registered before).
+pub fn register(driver: Box<dyn DisplayDriver>) {
+    info!("Using [{}] for mode setting ({} modes)", driver.name(), driver.modes().len());
+    *DRIVER.write() = Some(driver);
+}
+
+/// All modes of the display driver (empty, if there is none).
+pub fn modes() -> Vec<DisplayMode> {
+    DRIVER.read().as_ref().map(|driver| driver.modes()).unwrap_or_default()
+}
+
+pub fn current_mode() -> DisplayMode {
+    *MODE.lock()
+}
+
+/// Switch to `mode`. Fails with `ENOTSUP` without a display driver and with `EINVAL` for modes it doesn't support.
+pub fn set_mode(mode: DisplayMode) -> Result<(), Errno> {
+    let driver = DRIVER.read();
+    let driver = driver.as_ref().ok_or(Errno::ENOTSUP)?;
+    if !driver.modes().contains(&mode) {
+        return Err(Errno::EINVAL);
+    }
+
+    let framebuffer = driver.set_mode(mode)?;
+    framebuffer_changed(framebuffer);
+    Ok(())
+}
+
+/// Draw to `framebuffer` from now on and let `MapFrameBuffer` map it (`framebuffer.addr` is its physical address).
+pub fn framebuffer_changed(framebuffer: FramebufferInfo) {
+    let mode = DisplayMode { width: framebuffer.width, height: framebuffer.height, bpp: framebuffer.bpp };
+    info!("Display mode is now [{}x{}x{}]", mode.width, mode.height, mode.bpp);
+
+    // Kernel-Mappings sind identitätsgemappt, virtuelle = physische Adresse
+    replace_lfb(framebuffer.addr as *mut u8, framebuffer.pitch, framebuffer.width, framebuffer.height, framebuffer.bpp);
+    sys_vmem::set_fb_info(framebuffer);
+    *MODE.lock() = mode;
+}
@@ -0,0 +1,283 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: codec                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Parsing of the widgets of a codec and setup of an output path.  ║
+   ║         The audio function group is searched for pins, that can output  ║
+   ║         sound and are connected to a jack or a speaker. From such a     ║
+   ║         pin, the connection lists are followed (through mixers and      ║
+   ║         selectors) to an audio output converter (DAC). All widgets on   ║
+   ║         the path are powered on, selected and unmuted.                  ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - find_output          find and enable an output path of a codec      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use log::info;
+use super::controller::Controller;
+
+/// Verbs with an 8 bit payload
+const VERB_GET_PARAMETER: u16 = 0xf00;
+const VERB_GET_CONNECTION_LIST: u16 = 0xf02;
+const VERB_GET_CONFIG_DEFAULT: u16 = 0xf1c;
+const VERB_SET_CONNECTION_SELECT: u16 = 0x701;
+const VERB_SET_POWER_STATE: u16 = 0x705;
+const VERB_SET_CHANNEL_STREAM: u16 = 0x706;
+const VERB_SET_PIN_CONTROL: u16 = 0x707;
+const VERB_SET_EAPD: u16 = 0x70c;
+/// Verbs with a 16 bit payload
+const VERB_SET_FORMAT: u8 = 0x2;
+const VERB_SET_AMPLIFIER: u8 = 0x3;
+
+/// Parameters
+const PARAMETER_NODE_COUNT: u8 = 0x04;
+const PARAMETER_FUNCTION_TYPE: u8 = 0x05;
+const PARAMETER_WIDGET_CAPABILITIES: u8 = 0x09;
+const PARAMETER_PIN_CAPABILITIES: u8 = 0x0c;
+const PARAMETER_CONNECTION_LIST_LENGTH: u8 = 0x0e;
+const PARAMETER_OUTPUT_AMPLIFIER: u8 = 0x12;
+
+const FUNCTION_TYPE_AUDIO: u32 = 0x01;
+const WIDGET_STEREO: u32 = 1 << 0;
+const WIDGET_INPUT_AMPLIFIER: u32 = 1 << 1;
+const WIDGET_OUTPUT_AMPLIFIER: u32 = 1 << 2;
+const WIDGET_CONNECTION_LIST: u32 = 1 << 8;
+const PIN_OUTPUT: u32 = 1 << 4;
+const PIN_EAPD: u32 = 1 << 16;
+const CONNECTION_LIST_LONG: u32 = 1 << 7;
+const POWER_STATE_D0: u8 = 0;
+const PIN_CONTROL_OUTPUT: u8 = 1 << 6;
+const PIN_CONTROL_HEADPHONE: u8 = 1 << 7;
+const EAPD_ENABLE: u8 = 1 << 1;
+
+const AMPLIFIER_OUTPUT: u16 = 1 << 15;
+const AMPLIFIER_INPUT: u16 = 1 << 14;
+const AMPLIFIER_LEFT: u16 = 1 << 13;
+const AMPLIFIER_RIGHT: u16 = 1 << 12;
+
+/// Port connectivity "no physical connection" in the configuration default
+const CONFIG_NOT_CONNECTED: u32 = 1;
+const CONFIG_DEVICE_LINE_OUT: u32 = 0x0;
+const CONFIG_DEVICE_SPEAKER: u32 = 0x1;
+const CONFIG_DEVICE_HEADPHONE: u32 = 0x2;
+
+/// Longest path from a pin to a converter (pin, mixers/selectors, converter)
+const MAX_PATH_LENGTH: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum WidgetType {
+    Output,
+    Input,
+    Mixer,
+    Selector,
+    Pin,
+    Other,
+}
+
+struct Widget {
+    kind: WidgetType,
+    capabilities: u32,
+    connections: Vec<u8>,
+}
+
+/// An enabled path from a DAC to an output pin
+pub struct OutputPath {
+    pub codec: u8,
+    pub converter: u8,
+    pub pin: u8,
+}
+
+impl WidgetType {
+    fn from_capabilities(capabilities: u32) -> Self {
+        match (capabilities >> 20) & 0xf {
+            0x0 => WidgetType::Output,
+            0x1 => WidgetType::Input,
+            0x2 => WidgetType::Mixer,
+            0x3 => WidgetType::Selector,
+            0x4 => WidgetType::Pin,
+            _ => WidgetType::Other,
+        }
+    }
+}
+
+impl OutputPath {
+    /// Connect the converter to the stream `tag` and set its format (must match the format of the stream).
+    pub fn attach_stream(&self, controller: &Controller, tag: u8, format: u16) {
+        controller.command(self.codec, self.converter, verb16(VERB_SET_FORMAT, format));
+        controller.command(self.codec, self.converter, verb(VERB_SET_CHANNEL_STREAM, tag << 4));
+    }
+}
+
+/// Find an output pin of codec `codec` with a path to a converter and enable this path.
+/// Line outs are preferred over speakers and headphones (as QEMU's `hda-output` only has a line out).
+pub fn find_output(controller: &Controller, codec: u8) -> Option<OutputPath> {
+    let function_group = audio_function_group(controller, codec)?;
+    controller.command(codec, function_group, verb(VERB_SET_POWER_STATE, POWER_STATE_D0));
+    let widgets = parse_widgets(controller, codec, function_group);
+
+    let mut pins: Vec<(u32, u8)> = widgets.iter()
+        .filter(|(_, widget)| widget.kind == WidgetType::Pin)
+        .filter(|(node, _)| parameter(controller, codec, **node, PARAMETER_PIN_CAPABILITIES) & PIN_OUTPUT != 0)
+        .filter_map(|(node, _)| {
+            let config = controller.command(codec, *node, verb(VERB_GET_CONFIG_DEFAULT, 0))?;
+            let device = (config >> 20) & 0xf;
+            let connected = config >> 30 != CONFIG_NOT_CONNECTED;
+            let priority = match device {
+                CONFIG_DEVICE_LINE_OUT => 0,
+                CONFIG_DEVICE_SPEAKER => 1,
+                CONFIG_DEVICE_HEADPHONE => 2,
+                _ => return None,
+            };
+            connected.then_some((priority, *node))
+        })
+        .collect();
+    pins.sort();
+
+    for (priority, pin) in pins {
+        let mut path = Vec::new();
+        if !find_converter(&widgets, pin, &mut path) {
+            continue;
+        }
+
+        enable_path(controller, codec, function_group, &widgets, &path);
+        let pin_control = if priority == 2 { PIN_CONTROL_OUTPUT | PIN_CONTROL_HEADPHONE } else { PIN_CONTROL_OUTPUT };
+        controller.command(codec, pin, verb(VERB_SET_PIN_CONTROL, pin_control));
+        if parameter(controller, codec, pin, PARAMETER_PIN_CAPABILITIES) & PIN_EAPD != 0 {
+            controller.command(codec, pin, verb(VERB_SET_EAPD, EAPD_ENABLE));
+        }
+
+        let converter = *path.last().unwrap();
+        info!("HDA: Codec [{}] plays through converter [{}] and pin [{}] (path: {:?})", codec, converter, pin, path);
+        return Some(OutputPath { codec, converter, pin });
+    }
+
+    None
+}
+
+fn audio_function_group(controller: &Controller, codec: u8) -> Option<u8> {
+    let (start, count) = node_range(controller, codec, 0);
+    (start..start.saturating_add(count))
+        .find(|node| parameter(controller, codec, *node, PARAMETER_FUNCTION_TYPE) & 0xff == FUNCTION_TYPE_AUDIO)
+}
+
+fn parse_widgets(controller: &Controller, codec: u8, function_group: u8) -> BTreeMap<u8, Widget> {
+    let (start, count) = node_range(controller, codec, function_group);
+    (start..start.saturating_add(count)).map(|node| {
+        let capabilities = parameter(controller, codec, node, PARAMETER_WIDGET_CAPABILITIES);
+        let connections = if capabilities & WIDGET_CONNECTION_LIST != 0 {
+            connection_list(controller, codec, node)
+        } else {
+            Vec::new()
+        };
+        (node, Widget { kind: WidgetType::from_capabilities(capabilities), capabilities, connections })
+    }).collect()
+}
+
+/// Read the connection list of `node` (ranges are expanded)
+fn connection_list(controller: &Controller, codec: u8, node: u8) -> Vec<u8> {
+    let info = parameter(controller, codec, node, PARAMETER_CONNECTION_LIST_LENGTH);
+    let length = (info & 0x7f) as usize;
+    let (entry_bits, per_response) = if info & CONNECTION_LIST_LONG != 0 { (16, 2) } else { (8, 4) };
+    let range_flag = 1u32 << (entry_bits - 1);
+    let mask = range_flag - 1;
+
+    let mut connections: Vec<u8> = Vec::new();
+    let mut index = 0;
+    while index < length {
+        let Some(response) = controller.command(codec, node, verb(VERB_GET_CONNECTION_LIST, index as u8)) else {
+            break;
+        };
+        for entry in 0..per_response.min(length - index) {
+            let value = (response >> (entry * entry_bits)) & ((1 << entry_bits) - 1);
+            let target = (value & mask) as u8;
+            match connections.last() {
+                // Bereich: alle Knoten vom vorherigen Eintrag bis zu diesem
+                Some(&previous) if value & range_flag != 0 && previous < target => connections.extend(previous + 1..=target),
+                _ => connections.push(target),
+            }
+        }
+        index += per_response;
+    }
+
+    connections
+}
+
+/// Depth first search from `node` to an audio output converter. On success, `path` holds all nodes from `node` to the converter.
+fn find_converter(widgets: &BTreeMap<u8, Widget>, node: u8, path: &mut Vec<u8>) -> bool {
+    let Some(widget) = widgets.get(&node) else {
+        return false;
+    };
+    if path.contains(&node) || path.len() >= MAX_PATH_LENGTH {
+        return false;
+    }
+
+    path.push(node);
+    let passes_through = match widget.kind {
+        WidgetType::Output => return true,
+        WidgetType::Mixer | WidgetType::Selector => true,
+        // Pins dürfen nur am Anfang des Pfads stehen
+        WidgetType::Pin => path.len() == 1,
+        WidgetType::Input | WidgetType::Other => false,
+    };
+    if passes_through && widget.connections.iter().any(|next| find_converter(widgets, *next, path)) {
+        return true;
+    }
+    path.pop();
+    false
+}
+
+/// Power on all widgets of the path, select the next widget in each connection list and unmute all amplifiers.
+fn enable_path(controller: &Controller, codec: u8, function_group: u8, widgets: &BTreeMap<u8, Widget>, path: &[u8]) {
+    for (position, node) in path.iter().enumerate() {
+        let widget = &widgets[node];
+        controller.command(codec, *node, verb(VERB_SET_POWER_STATE, POWER_STATE_D0));
+
+        if let Some(next) = path.get(position + 1) {
+            let index = widget.connections.iter().position(|connection| connection == next).unwrap_or(0) as u8;
+            if widget.kind != WidgetType::Mixer && widget.connections.len() > 1 {
+                controller.command(codec, *node, verb(VERB_SET_CONNECTION_SELECT, index));
+            }
+            if widget.capabilities & WIDGET_INPUT_AMPLIFIER != 0 {
+                let gain = default_gain(controller, codec, function_group, *node);
+                let amplifier = AMPLIFIER_INPUT | AMPLIFIER_LEFT | AMPLIFIER_RIGHT | (index as u16) << 8 | gain;
+                controller.command(codec, *node, verb16(VERB_SET_AMPLIFIER, amplifier));
+            }
+        }
+
+        if widget.capabilities & WIDGET_OUTPUT_AMPLIFIER != 0 {
+            let gain = default_gain(controller, codec, function_group, *node);
+            let channels = if widget.capabilities & WIDGET_STEREO != 0 { AMPLIFIER_LEFT | AMPLIFIER_RIGHT } else { AMPLIFIER_LEFT };
+            controller.command(codec, *node, verb16(VERB_SET_AMPLIFIER, AMPLIFIER_OUTPUT | channels | gain));
+        }
+    }
+}
+
+/// Gain for 0 dB (the offset of the amplifier), taken from the function group, if the widget has no own amplifier parameters
+fn default_gain(controller: &Controller, codec: u8, function_group: u8, node: u8) -> u16 {
+    let capabilities = match parameter(controller, codec, node, PARAMETER_OUTPUT_AMPLIFIER) {
+        0 => parameter(controller, codec, function_group, PARAMETER_OUTPUT_AMPLIFIER),
+        capabilities => capabilities,
+    };
+    (capabilities & 0x7f) as u16
+}
+
+/// First node and number of nodes below `node`
+fn node_range(controller: &Controller, codec: u8, node: u8) -> (u8, u8) {
+    let count = parameter(controller, codec, node, PARAMETER_NODE_COUNT);
+    (((count >> 16) & 0xff) as u8, (count & 0xff) as u8)
+}
+
+fn parameter(controller: &Controller, codec: u8, node: u8, parameter: u8) -> u32 {
+    controller.command(codec, node, verb(VERB_GET_PARAMETER, parameter)).unwrap_or(0)
+}
+
+fn verb(verb: u16, payload: u8) -> u32 {
+    (verb as u32) << 8 | payload as u32
+}
+
+fn verb16(verb: u8, payload: u16) -> u32 {
+    (verb as u32) << 16 | payload as u32
+}
@@ -0,0 +1,347 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: controller                                                      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Intel High Definition Audio controller. Verbs are sent to the   ║
+   ║         codecs through the CORB and their responses are polled from the ║
+   ║         RIRB (one verb at a time, unsolicited responses are ignored).   ║
+   ║         Output streams play a cyclic buffer, which is described by a    ║
+   ║         buffer descriptor list with one entry per page. Interrupts are  ║
+   ║         not used, the position of a stream is polled instead.           ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - new                  reset and initialize a controller              ║
+   ║   - codecs               addresses of the codecs found at reset         ║
+   ║   - command              send a verb to a codec and get its response    ║
+   ║   - output_stream        set up the first output stream                 ║
+   ║                                                                         ║
+   ║ Spec: High Definition Audio Specification, Revision 1.0a                ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::hint::spin_loop;
+use core::ptr;
+use log::{info, warn};
+use pci_types::{CommandRegister, EndpointHeader};
+use spin::{Mutex, RwLock};
+use crate::device::pci_resources;
+use crate::device::virtio::dma::Dma;
+use crate::memory::PAGE_SIZE;
+use crate::{pci_bus, scheduler, timer};
+
+/// Global registers
+const GCAP: u64 = 0x00;
+const GCTL: u64 = 0x08;
+const STATESTS: u64 = 0x0e;
+const INTCTL: u64 = 0x20;
+const CORBLBASE: u64 = 0x40;
+const CORBUBASE: u64 = 0x44;
+const CORBWP: u64 = 0x48;
+const CORBRP: u64 = 0x4a;
+const CORBCTL: u64 = 0x4c;
+const CORBSIZE: u64 = 0x4e;
+const RIRBLBASE: u64 = 0x50;
+const RIRBUBASE: u64 = 0x54;
+const RIRBWP: u64 = 0x58;
+const RINTCNT: u64 = 0x5a;
+const RIRBCTL: u64 = 0x5c;
+const RIRBSIZE: u64 = 0x5e;
+const STREAM_DESCRIPTORS: u64 = 0x80;
+const STREAM_DESCRIPTOR_SIZE: u64 = 0x20;
+
+/// Stream descriptor registers (offsets from the descriptor)
+const SD_CTL: u64 = 0x00;
+const SD_CTL_STREAM: u64 = 0x02;
+const SD_STS: u64 = 0x03;
+const SD_LPIB: u64 = 0x04;
+const SD_CBL: u64 = 0x08;
+const SD_LVI: u64 = 0x0c;
+const SD_FMT: u64 = 0x12;
+const SD_BDPL: u64 = 0x18;
+const SD_BDPU: u64 = 0x1c;
+
+const GCAP_64_BIT: u16 = 1 << 0;
+const GCTL_RESET: u32 = 1 << 0;
+const CORBRP_RESET: u16 = 1 << 15;
+const RIRBWP_RESET: u16 = 1 << 15;
+const DMA_RUN: u8 = 1 << 1;
+const SD_CTL_RESET: u8 = 1 << 0;
+const SD_CTL_RUN: u8 = 1 << 1;
+/// Buffer completion, FIFO error and descriptor error (write 1 to clear)
+const SD_STS_ALL: u8 = 0x1c;
+/// Set in the extended response for unsolicited responses
+const RESPONSE_UNSOLICITED: u32 = 1 << 4;
+
+/// CORB and RIRB use their largest size (256 entries), if supported
+const RING_ENTRIES: u16 = 256;
+const RING_SIZE_256: u8 = 0x02;
+const RING_SIZE_256_SUPPORTED: u8 = 0x40;
+const CORB_ENTRY_SIZE: usize = 4;
+const RIRB_ENTRY_SIZE: usize = 8;
+/// Offset of the RIRB in the page holding both rings (it must be aligned to 128 bytes)
+const RIRB_OFFSET: usize = RING_ENTRIES as usize * CORB_ENTRY_SIZE;
+
+const RESET_TIMEOUT_MS: usize = 100;
+const RESPONSE_TIMEOUT_MS: usize = 100;
+/// Codecs need 521 µs after the reset to request their addresses
+const CODEC_WAKEUP_MS: usize = 1;
+const BDL_ENTRY_SIZE: usize = 16;
+/// Stream tags 1 to 15 are valid, 0 means "no stream"
+const OUTPUT_STREAM_TAG: u8 = 1;
+
+/// Memory mapped registers
+#[derive(Clone, Copy)]
+struct Mmio(u64);
+
+impl Mmio {
+    fn read8(&self, offset: u64) -> u8 {
+        unsafe { ptr::read_volatile((self.0 + offset) as *const u8) }
+    }
+
+    fn read16(&self, offset: u64) -> u16 {
+        unsafe { ptr::read_volatile((self.0 + offset) as *const u16) }
+    }
+
+    fn read32(&self, offset: u64) -> u32 {
+        unsafe { ptr::read_volatile((self.0 + offset) as *const u32) }
+    }
+
+    fn write8(&self, offset: u64, value: u8) {
+        unsafe { ptr::write_volatile((self.0 + offset) as *mut u8, value) }
+    }
+
+    fn write16(&self, offset: u64, value: u16) {
+        unsafe { ptr::write_volatile((self.0 + offset) as *mut u16, value) }
+    }
+
+    fn write32(&self, offset: u64, value: u32) {
+        unsafe { ptr::write_volatile((self.0 + offset) as *mut u32, value) }
+    }
+
+    /// 64 bit addresses are written as two halves (low half first)
+    fn write_address(&self, lower: u64, upper: u64, address: u64) {
+        self.write32(lower, address as u32);
+        self.write32(upper, (address >> 32) as u32);
+    }
+}
+
+/// State of the command rings (CORB and RIRB share one page)
+struct Rings {
+    memory: Dma,
+    /// Last entry written to the CORB
+    corb_write: u16,
+    /// Last entry read from the RIRB
+    rirb_read: u16,
+}
+
+pub struct Controller {
+    registers: Mmio,
+    input_streams: u8,
+    output_streams: u8,
+    /// Bit `n` is set, if there is a codec with address `n`
+    codecs: u16,
+    rings: Mutex<Rings>,
+}
+
+/// An output stream, playing a cyclic buffer
+pub struct OutputStream {
+    registers: Mmio,
+    buffer: Dma,
+    /// Buffer descriptor list (must be kept, while the stream is running)
+    _descriptors: Dma,
+    size: usize,
+}
+
+impl Controller {
+    /// Reset the controller, set up the command rings and detect the codecs. Returns `None`, if this fails.
+    pub fn new(pci_device: &RwLock<EndpointHeader>) -> Option<Self> {
+        let config_space = pci_bus().config_space();
+        let (mmio_start, _) = pci_resources::map_memory_bar(&pci_device.read(), 0, "hda")?;
+        pci_device.write().update_command(config_space, |command| {
+            command | CommandRegister::BUS_MASTER_ENABLE | CommandRegister::MEMORY_ENABLE
+        });
+
+        let registers = Mmio(mmio_start);
+        let capabilities = registers.read16(GCAP);
+        let output_streams = ((capabilities >> 12) & 0xf) as u8;
+        let input_streams = ((capabilities >> 8) & 0xf) as u8;
+        info!("HDA: [{}] input and [{}] output streams", input_streams, output_streams);
+
+        // Reset beenden lassen (CRST muss erst 0 und dann wieder 1 lesen)
+        registers.write32(GCTL, registers.read32(GCTL) & !GCTL_RESET);
+        if !wait_until(RESET_TIMEOUT_MS, || registers.read32(GCTL) & GCTL_RESET == 0) {
+            warn!("HDA: Controller does not enter reset");
+            return None;
+        }
+        registers.write32(GCTL, registers.read32(GCTL) | GCTL_RESET);
+        if !wait_until(RESET_TIMEOUT_MS, || registers.read32(GCTL) & GCTL_RESET != 0) {
+            warn!("HDA: Controller does not leave reset");
+            return None;
+        }
+        scheduler().sleep(CODEC_WAKEUP_MS);
+        let codecs = registers.read16(STATESTS);
+        registers.write16(STATESTS, codecs);
+        // no interrupts, everything is polled
+        registers.write32(INTCTL, 0);
+
+        let memory = Dma::new(1);
+        unsafe { ptr::write_bytes(memory.vaddr(0).as_ptr(), 0, PAGE_SIZE) };
+        let address = memory.paddr().as_u64();
+        if capabilities & GCAP_64_BIT == 0 && address + PAGE_SIZE as u64 > u32::MAX as u64 {
+            warn!("HDA: Controller can't access memory above 4 GiB");
+            return None;
+        }
+        if registers.read8(CORBSIZE) & RING_SIZE_256_SUPPORTED == 0 || registers.read8(RIRBSIZE) & RING_SIZE_256_SUPPORTED == 0 {
+            warn!("HDA: Controller does not support command rings with 256 entries");
+            return None;
+        }
+
+        // CORB: stop, configure and reset the read pointer
+        registers.write8(CORBCTL, 0);
+        wait_until(RESET_TIMEOUT_MS, || registers.read8(CORBCTL) & DMA_RUN == 0);
+        registers.write8(CORBSIZE, RING_SIZE_256);
+        registers.write_address(CORBLBASE, CORBUBASE, address);
+        registers.write16(CORBRP, CORBRP_RESET);
+        // some controllers (e.g. QEMU's) clear the read pointer without ever reporting the reset bit
+        wait_until(RESET_TIMEOUT_MS, || registers.read16(CORBRP) & CORBRP_RESET != 0);
+        registers.write16(CORBRP, 0);
+        if !wait_until(RESET_TIMEOUT_MS, || registers.read16(CORBRP) & CORBRP_RESET == 0) {
+            warn!("HDA: CORB read pointer does not leave reset");
+            return None;
+        }
+        registers.write16(CORBWP, 0);
+        registers.write8(CORBCTL, DMA_RUN);
+
+        // RIRB: stop, configure and reset the write pointer
+        registers.write8(RIRBCTL, 0);
+        wait_until(RESET_TIMEOUT_MS, || registers.read8(RIRBCTL) & DMA_RUN == 0);
+        registers.write8(RIRBSIZE, RING_SIZE_256);
+        registers.write_address(RIRBLBASE, RIRBUBASE, address + RIRB_OFFSET as u64);
+        registers.write16(RIRBWP, RIRBWP_RESET);
+        registers.write16(RINTCNT, 1);
+        registers.write8(RIRBCTL, DMA_RUN);
+
+        let rings = Rings { memory, corb_write: 0, rirb_read: 0 };
+        Some(Self { registers, input_streams, output_streams, codecs, rings: Mutex::new(rings) })
+    }
+
+    /// Addresses of all codecs, that have been detected at reset
+    pub fn codecs(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..15).filter(|address| self.codecs & (1 << address) != 0)
+    }
+
+    /// Send `verb` (with its payload, 20 bits) to node `node` of codec `codec` and wait for the response.
+    pub fn command(&self, codec: u8, node: u8, verb: u32) -> Option<u32> {
+        let mut rings = self.rings.lock();
+        let command = (codec as u32) << 28 | (node as u32) << 20 | (verb & 0xfffff);
+
+        rings.corb_write = (rings.corb_write + 1) % RING_ENTRIES;
+        let entry = rings.memory.vaddr(rings.corb_write as usize * CORB_ENTRY_SIZE).as_ptr() as *mut u32;
+        unsafe { ptr::write_volatile(entry, command) };
+        self.registers.write16(CORBWP, rings.corb_write);
+
+        let deadline = timer().systime_ms() + RESPONSE_TIMEOUT_MS;
+        loop {
+            while self.registers.read16(RIRBWP) & 0xff != rings.rirb_read {
+                rings.rirb_read = (rings.rirb_read + 1) % RING_ENTRIES;
+                let entry = rings.memory.vaddr(RIRB_OFFSET + rings.rirb_read as usize * RIRB_ENTRY_SIZE).as_ptr() as *const u32;
+                let (response, extended) = unsafe { (ptr::read_volatile(entry), ptr::read_volatile(entry.add(1))) };
+                if extended & RESPONSE_UNSOLICITED == 0 {
+                    return Some(response);
+                }
+            }
+
+            if timer().systime_ms() >= deadline {
+                warn!("HDA: No response from codec [{}] to verb [{:#x}] for node [{}]", codec, verb, node);
+                return None;
+            }
+            spin_loop();
+        }
+    }
+
+    /// Set up the first output stream with a cyclic buffer of `pages` pages and the stream format `format`.
+    /// The stream is stopped, until `OutputStream::start()` is called.
+    pub fn output_stream(&self, pages: usize, format: u16) -> Option<OutputStream> {
+        if self.output_streams == 0 {
+            warn!("HDA: Controller has no output streams");
+            return None;
+        }
+
+        // Ausgabe-Streams folgen auf die Eingabe-Streams
+        let registers = Mmio(self.registers.0 + STREAM_DESCRIPTORS + self.input_streams as u64 * STREAM_DESCRIPTOR_SIZE);
+        registers.write8(SD_CTL, registers.read8(SD_CTL) & !SD_CTL_RUN);
+        registers.write8(SD_CTL, SD_CTL_RESET);
+        if !wait_until(RESET_TIMEOUT_MS, || registers.read8(SD_CTL) & SD_CTL_RESET != 0) {
+            warn!("HDA: Output stream does not enter reset");
+            return None;
+        }
+        registers.write8(SD_CTL, 0);
+        if !wait_until(RESET_TIMEOUT_MS, || registers.read8(SD_CTL) & SD_CTL_RESET == 0) {
+            warn!("HDA: Output stream does not leave reset");
+            return None;
+        }
+
+        let buffer = Dma::new(pages);
+        let descriptors = Dma::new(1);
+        unsafe {
+            ptr::write_bytes(buffer.vaddr(0).as_ptr(), 0, pages * PAGE_SIZE);
+            ptr::write_bytes(descriptors.vaddr(0).as_ptr(), 0, PAGE_SIZE);
+        }
+        for page in 0..pages {
+            let entry = descriptors.vaddr(page * BDL_ENTRY_SIZE).as_ptr();
+            unsafe {
+                ptr::write_volatile(entry as *mut u64, buffer.paddr().as_u64() + (page * PAGE_SIZE) as u64);
+                ptr::write_volatile(entry.add(8) as *mut u32, PAGE_SIZE as u32);
+                // no interrupt on completion
+                ptr::write_volatile(entry.add(12) as *mut u32, 0);
+            }
+        }
+
+        let size = pages * PAGE_SIZE;
+        registers.write_address(SD_BDPL, SD_BDPU, descriptors.paddr().as_u64());
+        registers.write32(SD_CBL, size as u32);
+        registers.write16(SD_LVI, (pages - 1) as u16);
+        registers.write16(SD_FMT, format);
+        registers.write8(SD_CTL_STREAM, OUTPUT_STREAM_TAG << 4);
+        registers.write8(SD_STS, SD_STS_ALL);
+
+        Some(OutputStream { registers, buffer, _descriptors: descriptors, size })
+    }
+}
+
+impl OutputStream {
+    /// Tag, that the converter of the codec must be set to
+    pub fn tag(&self) -> u8 {
+        OUTPUT_STREAM_TAG
+    }
+
+    /// Size of the cyclic buffer in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The buffer, that the stream plays from
+    pub fn buffer(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.buffer.vaddr(0).as_ptr(), self.size) }
+    }
+
+    /// Position of the controller in the buffer (in bytes)
+    pub fn position(&self) -> usize {
+        self.registers.read32(SD_LPIB) as usize % self.size
+    }
+
+    pub fn start(&self) {
+        self.registers.write8(SD_CTL, self.registers.read8(SD_CTL) | SD_CTL_RUN);
+    }
+}
+
+fn wait_until(timeout_ms: usize, condition: impl Fn() -> bool) -> bool {
+    let deadline = timer().systime_ms() + timeout_ms;
+    while !condition() {
+        if timer().systime_ms() >= deadline {
+            return false;
+        }
+        scheduler().sleep(1);
+    }
+    true
+}
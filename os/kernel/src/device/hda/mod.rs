@@ -0,0 +1,109 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: hda                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Intel High Definition Audio: The first controller with a codec, that    ║
+   ║ has an output path, plays the output of the mixer (48 kHz, 16 bit,      ║
+   ║ stereo). A kernel thread refills the part of the cyclic buffer, that    ║
+   ║ the controller has already played, every few milliseconds, so the       ║
+   ║ latency is about the length of the buffer. Tested with QEMU's           ║
+   ║ `intel-hda` and `hda-output` devices.                                   ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - init                 find a controller and start playing            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::vec;
+use log::{info, warn};
+use spin::{Mutex, Once};
+use crate::device::{mixer, registry};
+use crate::process::thread::Thread;
+use crate::{pci_bus, scheduler};
+use self::codec::OutputPath;
+use self::controller::{Controller, OutputStream};
+use syscall::priority::Priority;
+
+pub mod codec;
+pub mod controller;
+
+/// PCI class of HDA controllers (multimedia controller, HD audio)
+const PCI_CLASS_MULTIMEDIA: u8 = 0x04;
+const PCI_SUBCLASS_HDA: u8 = 0x03;
+
+/// 48 kHz (base rate, no multiplier or divisor), 16 bits per sample, 2 channels
+const STREAM_FORMAT: u16 = 0x0011;
+const BYTES_PER_FRAME: usize = 4;
+/// 16 KiB are about 85 ms at 48 kHz
+const BUFFER_PAGES: usize = 4;
+const POLL_INTERVAL_MS: usize = 5;
+
+static OUTPUT: Once<Output> = Once::new();
+
+struct Output {
+    /// The command rings must be kept
+    _controller: Controller,
+    _path: OutputPath,
+    stream: Mutex<OutputStream>,
+}
+
+/// Set up the first controller with an output path and start the thread feeding it.
+pub fn init() {
+    for pci_device in pci_bus().search_by_class(PCI_CLASS_MULTIMEDIA, PCI_SUBCLASS_HDA) {
+        let device_id = pci_device.read().header().id(pci_bus().config_space());
+        info!("Found HDA controller [{}:{}]", device_id.0, device_id.1);
+        let Some(controller) = Controller::new(pci_device) else {
+            warn!("Failed to initialize HDA controller [{}:{}]", device_id.0, device_id.1);
+            continue;
+        };
+
+        let Some(path) = controller.codecs().find_map(|address| codec::find_output(&controller, address)) else {
+            warn!("HDA controller [{}:{}] has no codec with an output", device_id.0, device_id.1);
+            continue;
+        };
+        let Some(stream) = controller.output_stream(BUFFER_PAGES, STREAM_FORMAT) else {
+            continue;
+        };
+        path.attach_stream(&controller, stream.tag(), STREAM_FORMAT);
+        registry::bind(&registry::pci_name(pci_device.read().header().address()), "hda");
+
+        OUTPUT.call_once(|| Output { _controller: controller, _path: path, stream: Mutex::new(stream) });
+        let thread = Thread::new_kernel_thread(play, "hda");
+        thread.set_priority(Priority::Realtime);
+        scheduler().ready(thread);
+        mixer::enable();
+        return;
+    }
+}
+
+extern "sysv64" fn play() {
+    let mut stream = OUTPUT.get().expect("HDA output thread started without an output").stream.lock();
+    let size = stream.size();
+    let mut samples = vec![0i16; size / size_of::<i16>()];
+
+    // der ganze Puffer liegt vor der Position des Controllers
+    fill(&mut stream, 0, size, &mut samples);
+    stream.start();
+
+    let mut last_position = 0;
+    loop {
+        scheduler().sleep(POLL_INTERVAL_MS);
+        let position = stream.position() / BYTES_PER_FRAME * BYTES_PER_FRAME;
+        let played = (position + size - last_position) % size;
+        fill(&mut stream, last_position, played, &mut samples);
+        last_position = position;
+    }
+}
+
+/// Write `length` bytes of mixed samples to the buffer of `stream`, starting at `offset` (wrapping around at the end).
+fn fill(stream: &mut OutputStream, offset: usize, length: usize, samples: &mut [i16]) {
+    let samples = &mut samples[..length / size_of::<i16>()];
+    mixer::mix(samples);
+
+    let buffer = stream.buffer();
+    let size = buffer.len();
+    for (index, sample) in samples.iter().enumerate() {
+        let position = (offset + index * size_of::<i16>()) % size;
+        buffer[position..position + size_of::<i16>()].copy_from_slice(&sample.to_le_bytes());
+    }
+}
@@ -0,0 +1,53 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: hotplug                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Devices, that are added or removed after boot (e.g. with QEMU's ║
+   ║         'device_add'). Hotplug notifications from ACPI are not handled, ║
+   ║         so the PCI bus is rescanned on request (the 'DeviceRescan'      ║
+   ║         syscall or the 'rescan' command of the kernel shell). New       ║
+   ║         virtio devices get their driver, other new functions are only   ║
+   ║         registered. Removed functions are unregistered and the network  ║
+   ║         stack is told about a removed RTL8139. Other drivers notice     ║
+   ║         their removal on their own (e.g. when reads return all ones).   ║
+   ║         Each change is announced with an 'EventSource::Device' event.   ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - rescan               rescan the PCI bus and set up new devices      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use log::info;
+use syscall::event::EventSource;
+use crate::device::{registry, virtio};
+use crate::network;
+use crate::sync::event;
+use crate::{lfb_info, pci_bus};
+
+/// Rescan the PCI bus, initialize drivers for new functions and return the number of added and removed functions.
+pub fn rescan() -> usize {
+    let result = pci_bus().rescan();
+
+    // Die Framebuffer-Adresse wird nur gebraucht, um doppelte Mappings zu vermeiden
+    let framebuffer = lfb_info();
+    let fb_start = framebuffer.address;
+    let fb_end = fb_start + framebuffer.height as u64 * framebuffer.pitch as u64;
+    for device in result.added.iter() {
+        if virtio::is_virtio_device(device) {
+            virtio::init_pci_device(device, fb_start, fb_end);
+        } else {
+            info!("No driver for hot-added PCI device [{}]", registry::pci_name(device.read().header().address()));
+        }
+    }
+
+    // The RTL8139 only notices its removal by an interrupt, which might never come
+    for address in result.removed.iter() {
+        network::remove_pci_device(*address);
+    }
+
+    let changes = result.added.len() + result.removed.len();
+    if changes > 0 {
+        event::notify(EventSource::Device);
+    }
+    changes
+}
@@ -0,0 +1,112 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: hpet                                                            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ High Precision Event Timer. Its location is taken from the ACPI HPET    ║
+   ║ table. Only the main counter is used (as a clock source for the timer   ║
+   ║ and to calibrate the TSC), the comparators stay disabled, so the PIT    ║
+   ║ still generates the timer interrupts. A 32 bit counter would wrap after ║
+   ║ a few minutes, so only HPETs with a 64 bit counter are used.            ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - init                 find, map and enable the HPET                  ║
+   ║   - hpet                 get the HPET (if there is one)                 ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use acpi::HpetInfo;
+use core::ptr;
+use log::{info, warn};
+use spin::Once;
+use x86_64::structures::paging::PageTableFlags;
+use crate::memory::vma::VmaType;
+use crate::memory::PAGE_SIZE;
+use crate::{acpi_tables, process_manager};
+
+/// Registers (offsets from the base address)
+const REGISTER_CAPABILITIES: usize = 0x000;
+const REGISTER_CONFIGURATION: usize = 0x010;
+const REGISTER_MAIN_COUNTER: usize = 0x0f0;
+
+const CAPABILITY_64_BIT_COUNTER: u64 = 1 << 13;
+const CONFIGURATION_ENABLE: u64 = 1 << 0;
+/// Routes the interrupts of the first comparators to IRQ 0 and 8 (instead of PIT and RTC)
+const CONFIGURATION_LEGACY_ROUTING: u64 = 1 << 1;
+
+/// The specification allows periods of up to 100 ns
+const MAX_PERIOD_FS: u64 = 100_000_000;
+const FEMTOSECONDS_PER_NANOSECOND: u128 = 1_000_000;
+
+static HPET: Once<Hpet> = Once::new();
+
+pub struct Hpet {
+    base: *mut u64,
+    /// Length of a counter tick in femtoseconds
+    period_fs: u64,
+}
+
+// The registers are only read after initialization (and reads have no side effects)
+unsafe impl Send for Hpet {}
+unsafe impl Sync for Hpet {}
+
+/// Find the HPET in the ACPI tables, map its registers and start its main counter.
+pub fn init() {
+    let info = match HpetInfo::new(&*acpi_tables().lock()) {
+        Ok(info) => info,
+        Err(_) => {
+            info!("No HPET found");
+            return;
+        }
+    };
+
+    let base_address = info.base_address as u64;
+    let kernel_process = process_manager().read().kernel_process().unwrap();
+    let page = kernel_process.virtual_address_space.kernel_map_devm_identity(
+        base_address,
+        base_address + PAGE_SIZE as u64,
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE,
+        VmaType::DeviceMemory,
+        "hpet",
+    );
+
+    let base = page.start_address().as_mut_ptr::<u64>();
+    let capabilities = unsafe { read(base, REGISTER_CAPABILITIES) };
+    let period_fs = capabilities >> 32;
+    if period_fs == 0 || period_fs > MAX_PERIOD_FS {
+        warn!("HPET reports an invalid counter period of [{} fs]", period_fs);
+        return;
+    }
+    if capabilities & CAPABILITY_64_BIT_COUNTER == 0 {
+        warn!("HPET only has a 32 bit counter, which is not supported");
+        return;
+    }
+
+    unsafe {
+        let configuration = read(base, REGISTER_CONFIGURATION);
+        write(base, REGISTER_CONFIGURATION, (configuration & !CONFIGURATION_LEGACY_ROUTING) | CONFIGURATION_ENABLE);
+    }
+
+    let hpet = HPET.call_once(|| Hpet { base, period_fs });
+    info!("HPET enabled at [{:#x}] (frequency: [{} Hz], counter: [{} ns])", base_address, 1_000_000_000_000_000 / period_fs, hpet.now_ns());
+}
+
+/// Get the HPET, if it has been enabled.
+pub fn hpet() -> Option<&'static Hpet> {
+    HPET.get()
+}
+
+impl Hpet {
+    /// Time since the main counter has been started (in nanoseconds).
+    pub fn now_ns(&self) -> u64 {
+        let counter = unsafe { read(self.base, REGISTER_MAIN_COUNTER) };
+        (counter as u128 * self.period_fs as u128 / FEMTOSECONDS_PER_NANOSECOND) as u64
+    }
+}
+
+unsafe fn read(base: *mut u64, register: usize) -> u64 {
+    unsafe { ptr::read_volatile(base.byte_add(register)) }
+}
+
+unsafe fn write(base: *mut u64, register: usize, value: u64) {
+    unsafe { ptr::write_volatile(base.byte_add(register), value) }
+}
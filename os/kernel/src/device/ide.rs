@@ -23,6 +23,8 @@ use x86_64::VirtAddr;
 use x86_64::structures::paging::page::{PageRange, Page};
 
 
+use crate::device::registry;
+use crate::device::stats::{self, DeviceStats};
 use crate::interrupt::interrupt_dispatcher::InterruptVector;
 use crate::interrupt::interrupt_handler::InterruptHandler;
 use crate::memory::PAGE_SIZE;
@@ -34,21 +36,34 @@ use crate::{apic, interrupt_dispatcher, memory, pci_bus, scheduler, timer, proce
 
 
 /// Initialize all IDE controllers found on the PCI bus.
+/// If there is none, the legacy ports of the primary and secondary channel are probed (PIO only).
 /// Each connected drive gets registered as a block device in the storage module.
 pub fn init() {
     let devices = pci_bus().search_by_class(0x01, 0x01);
+    if devices.is_empty() {
+        info!("No IDE controller found on the PCI bus, probing legacy ports");
+        register_drives(Arc::new(IdeController::legacy()));
+        return;
+    }
+
     for device in devices {
         let device_id = device.read().header().id(pci_bus().config_space());
         info!("Found IDE controller [{}:{}]", device_id.0, device_id.1);
+        registry::bind(&registry::pci_name(device.read().header().address()), "ide");
 
-        let ide_controller = Arc::new(IdeController::new(device));
-        IdeController::plugin(Arc::clone(&ide_controller));
+        register_drives(Arc::new(IdeController::new(device)));
+    }
+}
 
-        let found_drives = ide_controller.init_drives();
-        for drive in found_drives.iter() {
-            let block_device = Arc::new(IdeDrive::new(Arc::clone(&ide_controller), *drive));
-            add_block_device("ata", block_device);
-        }
+fn register_drives(ide_controller: Arc<IdeController>) {
+    IdeController::plugin(Arc::clone(&ide_controller));
+
+    let found_drives = ide_controller.init_drives();
+    for drive in found_drives.iter() {
+        let block_device = Arc::new(IdeDrive::new(Arc::clone(&ide_controller), *drive));
+        let stats = Arc::clone(&block_device.stats);
+        let name = add_block_device("ata", block_device);
+        stats::register(&name, stats);
     }
 }
 
@@ -67,6 +82,7 @@ const ATAPI_CYLINDER_LOW_V1: u8 = 0x14;
 const ATAPI_CYLINDER_HIGH_V1: u8 = 0xeb;
 const ATAPI_CYLINDER_LOW_V2: u8 = 0x69;
 const ATAPI_CYLINDER_HIGH_V2: u8 = 0x96;
+const STATS_COUNTERS: &[&str] = &["read_requests", "write_requests", "sectors_read", "sectors_written", "errors"];
 
 /* ╔═════════════════════════════════════════════════════════════════════════╗
    ║ Enums and structs needed to communicate with the ide controller.        ║
@@ -123,6 +139,8 @@ enum Command {
     WritePioLba48 = 0x34,
     WriteDmaLba28 = 0xca,
     WriteDmaLba48 = 0x35,
+    FlushCache = 0xe7,
+    FlushCacheExt = 0xea,
     IdentifyAtaDrive = 0xec,
     IdentifyAtapiDrive = 0xa1,
 }
@@ -351,11 +369,33 @@ impl IdeController {
         Self { channels }
     }
 
+    /// Controller at the fixed ISA ports (compatibility mode without PCI), which only supports PIO.
+    fn legacy() -> Self {
+        let interrupts = [InterruptVector::PrimaryAta, InterruptVector::SecondaryAta];
+        let channels = [0, 1].map(|i| {
+            Mutex::new(IdeChannel::new(
+                i,
+                interrupts[i as usize],
+                false,
+                DEFAULT_BASE_ADDRESSES[i as usize],
+                DEFAULT_CONTROL_BASE_ADDRESSES[i as usize],
+                0,
+            ))
+        });
+
+        Self { channels }
+    }
+
     fn init_drives(&self) -> Vec<DriveInfo> {
         let mut drives: Vec<DriveInfo> = Vec::new();
 
         for channel in self.channels.iter() {
             let mut channel = channel.lock();
+            if channel.is_floating() {
+                info!("No drives connected to channel [{}]", channel.index);
+                continue;
+            }
+
             for i in 0..DEVICES_PER_CHANNEL {
                 if !channel.reset_drive(i) {
                     continue;
@@ -410,18 +450,39 @@ impl IdeController {
 pub struct IdeDrive {
     controller: Arc<IdeController>,
     info: DriveInfo,
+    stats: Arc<DeviceStats>,
 }
 
 impl IdeDrive {
     fn new(controller: Arc<IdeController>, info: DriveInfo) -> Self {
-        Self { controller, info }
+        Self { controller, info, stats: DeviceStats::new(STATS_COUNTERS) }
+    }
+
+    /// Update the statistics after a request for `count` sectors, of which `processed` have been transferred.
+    fn update_stats(&self, mode: TransferMode, count: usize, processed: usize) {
+        match mode {
+            TransferMode::Read => {
+                self.stats.inc("read_requests");
+                self.stats.add("sectors_read", processed as u64);
+            }
+            TransferMode::Write => {
+                self.stats.inc("write_requests");
+                self.stats.add("sectors_written", processed as u64);
+            }
+        }
+
+        if processed < count {
+            self.stats.inc("errors");
+        }
     }
 }
 
 impl BlockDevice for IdeDrive {
     fn read(&self, sector: u64, count: usize, buffer: &mut [u8]) -> usize {
         let channel = &mut self.controller.channels[self.info.channel as usize].lock();
-        channel.perform_ata_io(&self.info, TransferMode::Read, sector, count, buffer)
+        let processed = channel.perform_ata_io(&self.info, TransferMode::Read, sector, count, buffer);
+        self.update_stats(TransferMode::Read, count, processed);
+        processed
     }
 
     fn write(&self, sector: u64, count: usize, buffer: &[u8]) -> usize {
@@ -430,7 +491,23 @@ impl BlockDevice for IdeDrive {
         let buffer = unsafe { slice::from_raw_parts_mut(buffer.as_ptr().cast_mut(), buffer.len()) };
 
         let channel = &mut self.controller.channels[self.info.channel as usize].lock();
-        channel.perform_ata_io(&self.info, TransferMode::Write, sector, count, buffer)
+        let processed = channel.perform_ata_io(&self.info, TransferMode::Write, sector, count, buffer);
+        self.update_stats(TransferMode::Write, count, processed);
+        processed
+    }
+
+    fn flush(&self) -> bool {
+        if self.info.typ != DriveType::Ata {
+            return true;
+        }
+
+        let channel = &mut self.controller.channels[self.info.channel as usize].lock();
+        let success = channel.flush_cache(&self.info);
+        if !success {
+            self.stats.inc("errors");
+        }
+
+        success
     }
 
     fn sector_count(&self) -> u64 {
@@ -587,6 +664,12 @@ impl IdeChannel {
         false
     }
 
+    /// Check if nothing is connected to the channel.
+    /// Without a drive, the pull-up resistors make the status register read as 0xff ("floating bus").
+    fn is_floating(&mut self) -> bool {
+        unsafe { self.control.alternate_status.read() == 0xff }
+    }
+
     /// Wait for the BUSY bit to be cleared
     fn wait_busy(&mut self, timeout: usize) -> bool {
         Self::wait_status(&mut self.command.status, Status::None, timeout)
@@ -1106,6 +1189,25 @@ impl IdeChannel {
 
         processed_sectors
     }
+
+    /// Write the drive's cache to the medium (FLUSH CACHE, or FLUSH CACHE EXT for LBA48 drives).
+    fn flush_cache(&mut self, info: &DriveInfo) -> bool {
+        if !self.select_drive(info.drive, false, 0) {
+            return false;
+        }
+
+        let command = if info.addressing == AddressType::Lba48 { Command::FlushCacheExt } else { Command::FlushCache };
+        unsafe { self.command.command.write(command as u8) };
+        scheduler().sleep(1);
+
+        // Flushing may take a while on real hardware, so use the longer DMA timeout
+        if !self.wait_busy(DMA_TIMEOUT) {
+            error!("Failed to flush cache of drive [{}] on channel [{}]", info.drive, self.index);
+            return false;
+        }
+
+        true
+    }
 }
 
 /// Each channel has its own interrupt handler with a reference to the channel's `received_interrupt` flag.
@@ -0,0 +1,44 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: keyboard_wait                                                   ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Blocking reads from the keyboards (PS/2, virtio-input and the input of  ║
+   ║ virtio-console). Readers block on a wait queue, which the interrupt     ║
+   ║ handlers of the devices wake up, after they have received input. The    ║
+   ║ scheduler's locks can only be tried in an interrupt handler, so a       ║
+   ║ wakeup may be missed: Readers wake up after 'WAKEUP_TIMEOUT_MS' anyway. ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - epoch                get the number of input notifications          ║
+   ║   - notify               wake up readers (called by interrupt handlers) ║
+   ║   - wait                 block until input arrives after an epoch       ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::sync::wait_queue::WaitQueue;
+use crate::timer;
+
+/// Longest time a reader blocks without checking the keyboards, in case its wakeup has been missed
+const WAKEUP_TIMEOUT_MS: usize = 100;
+
+static READERS: WaitQueue = WaitQueue::new();
+/// Incremented every time a keyboard has received input
+static EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+/// Get the current epoch. Readers take it before checking the keyboards and pass it to `wait()`,
+/// so input arriving in between is not missed.
+pub fn epoch() -> usize {
+    EPOCH.load(Ordering::Acquire)
+}
+
+/// Wake up the readers, after a keyboard has received input (called from interrupt handlers).
+pub fn notify() {
+    EPOCH.fetch_add(1, Ordering::Release);
+    READERS.wake_all_from_interrupt();
+}
+
+/// Block the calling thread, until input has arrived after `seen` (see `epoch()`) or `WAKEUP_TIMEOUT_MS` have passed.
+pub fn wait(seen: usize) {
+    READERS.wait_until_deadline(timer().systime_ms() + WAKEUP_TIMEOUT_MS, || EPOCH.load(Ordering::Acquire) != seen);
+}
@@ -0,0 +1,225 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: line_discipline                                                 ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Turns the bytes received from a serial terminal into input for  ║
+   ║         applications, like the terminal emulator does for the keyboard: ║
+   ║         UTF-8 sequences are assembled and ANSI escape sequences (cursor ║
+   ║         keys, Home, End, Delete) are decoded into keys. In canonical    ║
+   ║         mode, a line is edited (with echo) until Enter is pressed and   ║
+   ║         then passed on as a whole. In fluid mode, each key is passed on ║
+   ║         immediately. Raw mode needs key events, which a serial terminal ║
+   ║         can't provide, so no input is passed on in that mode.           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use pc_keyboard::{DecodedKey, KeyCode};
+use terminal::{DecodedKeyType, TerminalMode};
+
+/// Maximum length of a line in canonical mode (in characters)
+const MAX_LINE_LENGTH: usize = 256;
+
+const ESCAPE: u8 = 0x1b;
+const BACKSPACE: char = '\x08';
+const DELETE: char = '\x7f';
+/// Ctrl+U discards the line (as in most Unix terminals)
+const KILL_LINE: u8 = 0x15;
+
+/// Progress of an escape sequence
+#[derive(Clone, Copy, PartialEq)]
+enum Escape {
+    None,
+    /// ESC has been received
+    Started,
+    /// ESC [ (and maybe a numeric parameter) has been received
+    Csi(u8),
+}
+
+pub struct LineDiscipline {
+    line: String,
+    /// Position of the cursor in the line in characters (not bytes)
+    cursor: usize,
+    escape: Escape,
+    /// Bytes of an incomplete UTF-8 sequence
+    utf8: Vec<u8>,
+}
+
+impl LineDiscipline {
+    pub const fn new() -> Self {
+        Self { line: String::new(), cursor: 0, escape: Escape::None, utf8: Vec::new() }
+    }
+
+    /// Forget the current line and any incomplete sequence (e.g. after Ctrl+C).
+    pub fn reset(&mut self) {
+        self.line.clear();
+        self.cursor = 0;
+        self.escape = Escape::None;
+        self.utf8.clear();
+    }
+
+    /// Process the byte `byte`, which has been received while an application is reading in `mode`.
+    /// Output for the terminal (echo and cursor movements) is appended to `echo`.
+    /// Returns the input for the application, once there is any.
+    pub fn input(&mut self, byte: u8, mode: TerminalMode, echo: &mut String) -> Option<Vec<u8>> {
+        if mode == TerminalMode::Canonical && byte == KILL_LINE {
+            self.kill_line(echo);
+            return None;
+        }
+
+        let key = self.decode(byte)?;
+        match mode {
+            TerminalMode::Canonical => self.edit(key, echo),
+            TerminalMode::Fluid => {
+                let mut buffer = Vec::new();
+                match key {
+                    DecodedKey::Unicode(ch) => {
+                        buffer.push(DecodedKeyType::Unicode as u8);
+                        buffer.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+                    }
+                    DecodedKey::RawKey(code) => buffer.extend_from_slice(&[DecodedKeyType::RawKey as u8, code as u8]),
+                }
+                Some(buffer)
+            }
+            TerminalMode::Raw => None,
+        }
+    }
+
+    /// Decode the next key from the received bytes.
+    fn decode(&mut self, byte: u8) -> Option<DecodedKey> {
+        match (self.escape, byte) {
+            (Escape::None, ESCAPE) => {
+                self.escape = Escape::Started;
+                None
+            }
+            (Escape::Started, b'[') => {
+                self.escape = Escape::Csi(0);
+                None
+            }
+            // a lone ESC (followed by something else) is passed on as a key
+            (Escape::Started, _) => {
+                self.escape = Escape::None;
+                Some(DecodedKey::Unicode(ESCAPE as char))
+            }
+            (Escape::Csi(parameter), b'0'..=b'9') => {
+                self.escape = Escape::Csi(parameter.saturating_mul(10).saturating_add(byte - b'0'));
+                None
+            }
+            (Escape::Csi(parameter), _) => {
+                self.escape = Escape::None;
+                let code = match (byte, parameter) {
+                    (b'A', _) => KeyCode::ArrowUp,
+                    (b'B', _) => KeyCode::ArrowDown,
+                    (b'C', _) => KeyCode::ArrowRight,
+                    (b'D', _) => KeyCode::ArrowLeft,
+                    (b'H', _) | (b'~', 1) | (b'~', 7) => KeyCode::Home,
+                    (b'F', _) | (b'~', 4) | (b'~', 8) => KeyCode::End,
+                    (b'~', 3) => return Some(DecodedKey::Unicode(DELETE)),
+                    (b'~', 5) => KeyCode::PageUp,
+                    (b'~', 6) => KeyCode::PageDown,
+                    _ => return None,
+                };
+                Some(DecodedKey::RawKey(code))
+            }
+            // Enter sends CR, Backspace usually sends DEL
+            (Escape::None, b'\r') => Some(DecodedKey::Unicode('\n')),
+            (Escape::None, 0x7f) => Some(DecodedKey::Unicode(BACKSPACE)),
+            (Escape::None, _) => {
+                self.utf8.push(byte);
+                match core::str::from_utf8(&self.utf8) {
+                    Ok(string) => {
+                        let ch = string.chars().next();
+                        self.utf8.clear();
+                        ch.map(DecodedKey::Unicode)
+                    }
+                    // wait for the rest of the sequence, unless it can't become valid anymore
+                    Err(e) if e.error_len().is_none() => None,
+                    Err(_) => {
+                        self.utf8.clear();
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    /// Edit the line in canonical mode and return it, when Enter has been pressed (without the line break).
+    fn edit(&mut self, key: DecodedKey, echo: &mut String) -> Option<Vec<u8>> {
+        match key {
+            DecodedKey::RawKey(KeyCode::ArrowLeft) if self.cursor > 0 => {
+                self.cursor -= 1;
+                echo.push_str("\x1b[1D");
+            }
+            DecodedKey::RawKey(KeyCode::ArrowRight) if self.cursor < self.len() => {
+                self.cursor += 1;
+                echo.push_str("\x1b[1C");
+            }
+            DecodedKey::RawKey(KeyCode::Home) if self.cursor > 0 => {
+                echo.push_str(&format!("\x1b[{}D", self.cursor));
+                self.cursor = 0;
+            }
+            DecodedKey::RawKey(KeyCode::End) if self.cursor < self.len() => {
+                echo.push_str(&format!("\x1b[{}C", self.len() - self.cursor));
+                self.cursor = self.len();
+            }
+            DecodedKey::Unicode('\n') => {
+                let tail = self.len() - self.cursor;
+                if tail > 0 {
+                    echo.push_str(&format!("\x1b[{}C", tail));
+                }
+                echo.push('\n');
+                self.cursor = 0;
+                return Some(core::mem::take(&mut self.line).into_bytes());
+            }
+            DecodedKey::Unicode(BACKSPACE) if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.line.remove(self.byte_index(self.cursor));
+                echo.push_str("\x1b[1D");
+                self.redraw_tail(echo);
+            }
+            DecodedKey::Unicode(DELETE) if self.cursor < self.len() => {
+                self.line.remove(self.byte_index(self.cursor));
+                self.redraw_tail(echo);
+            }
+            DecodedKey::Unicode(ch) if !ch.is_control() && self.len() < MAX_LINE_LENGTH => {
+                self.line.insert(self.byte_index(self.cursor), ch);
+                self.cursor += 1;
+                echo.push(ch);
+                self.redraw_tail(echo);
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    fn kill_line(&mut self, echo: &mut String) {
+        if self.cursor > 0 {
+            echo.push_str(&format!("\x1b[{}D", self.cursor));
+        }
+        echo.push_str("\x1b[0K");
+        self.line.clear();
+        self.cursor = 0;
+    }
+
+    /// Rewrite the line from the cursor to its end (after a change) and move the cursor back.
+    fn redraw_tail(&self, echo: &mut String) {
+        let tail = &self.line[self.byte_index(self.cursor)..];
+        echo.push_str("\x1b[0K");
+        if !tail.is_empty() {
+            echo.push_str(&format!("{}\x1b[{}D", tail, tail.chars().count()));
+        }
+    }
+
+    /// Number of characters in the line
+    fn len(&self) -> usize {
+        self.line.chars().count()
+    }
+
+    /// Byte offset of the character at `pos`
+    fn byte_index(&self, pos: usize) -> usize {
+        self.line.char_indices().nth(pos).map_or(self.line.len(), |(index, _)| index)
+    }
+}
@@ -0,0 +1,64 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: mixer                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Mixes the PCM streams of all processes for the audio device.    ║
+   ║         Each process has its own queue of samples (16 bit, stereo and   ║
+   ║         interleaved, at the rate given in `syscall::audio`). The driver ║
+   ║         pulls the sum of all queues (saturated) whenever it needs more  ║
+   ║         data and gets silence if nothing is queued. Queues, that have   ║
+   ║         been played completely, are removed.                            ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - enable               announce an audio device (called by drivers)   ║
+   ║   - available            check, whether there is an audio device        ║
+   ║   - submit               queue samples for a process                    ║
+   ║   - mix                  get the next mixed samples (called by drivers) ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::collections::{BTreeMap, VecDeque};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+use syscall::audio::{CHANNELS, SAMPLE_RATE};
+
+/// Maximum number of queued samples per process (half a second)
+const QUEUE_CAPACITY: usize = SAMPLE_RATE as usize * CHANNELS / 2;
+
+static AVAILABLE: AtomicBool = AtomicBool::new(false);
+/// Queues of samples by process id
+static QUEUES: Mutex<BTreeMap<usize, VecDeque<i16>>> = Mutex::new(BTreeMap::new());
+
+/// Announce, that an audio device plays the output of the mixer.
+pub fn enable() {
+    AVAILABLE.store(true, Ordering::Release);
+}
+
+pub fn available() -> bool {
+    AVAILABLE.load(Ordering::Acquire)
+}
+
+/// Queue as many of `samples` for the process `process` as fit into its queue and return their number.
+/// Only whole frames (one sample per channel) are queued.
+pub fn submit(process: usize, samples: &[i16]) -> usize {
+    let mut queues = QUEUES.lock();
+    let queue = queues.entry(process).or_default();
+    let free = QUEUE_CAPACITY - queue.len();
+    let count = samples.len().min(free) / CHANNELS * CHANNELS;
+    queue.extend(&samples[..count]);
+
+    count
+}
+
+/// Fill `samples` with the sum of the queued samples of all processes.
+pub fn mix(samples: &mut [i16]) {
+    samples.fill(0);
+    let mut queues = QUEUES.lock();
+    for queue in queues.values_mut() {
+        let count = samples.len().min(queue.len());
+        for (sample, queued) in samples.iter_mut().zip(queue.drain(..count)) {
+            *sample = sample.saturating_add(queued);
+        }
+    }
+    queues.retain(|_, queue| !queue.is_empty());
+}
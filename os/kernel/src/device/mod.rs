@@ -1,13 +1,31 @@
 pub mod apic;
 pub mod pit;
+pub mod hpet;
+pub mod pvclock;
+pub mod rtc;
 pub mod ps2;
+pub mod keyboard_wait;
+pub mod pointer;
 pub mod qemu_cfg;
 pub mod speaker;
 pub mod tty;
 #[macro_use]
 pub mod serial;
+pub mod serial_console;
+pub mod line_discipline;
 pub mod ide;
 pub mod pci;
+pub mod pci_resources;
+pub mod pci_irq;
 pub mod rtl8139;
 pub mod cpu;
+pub mod cpu_sensors;
+pub mod stats;
+pub mod registry;
+pub mod hotplug;
 pub mod virtio;
+pub mod usb;
+pub mod hda;
+pub mod mixer;
+pub mod display;
+pub mod bochs_vga;
@@ -1,28 +1,84 @@
+use alloc::boxed::Box;
 use alloc::vec::Vec;
-use log::info;
+use acpi::mcfg::Mcfg;
+use log::{info, warn};
 use pci_types::{BaseClass, ConfigRegionAccess, EndpointHeader, HeaderType, PciAddress, PciHeader, PciPciBridgeHeader, SubClass};
 use spin::{Mutex, RwLock};
 use x86_64::instructions::port::{Port, PortWriteOnly};
+use x86_64::structures::paging::PageTableFlags;
+use crate::memory::vma::VmaType;
+use crate::{acpi_tables, process_manager};
+use super::{pci_resources, registry};
 
 use virtio::transport::pci::bus::ConfigurationAccess as VirtioConfigAccess;
 
 const MAX_DEVICES_PER_BUS: u8 = 32;
 const MAX_FUNCTIONS_PER_DEVICE: u8 = 8;
+const MAX_BUS: u8 = 0xff;
 const INVALID: u16 = 0xffff;
+/// Without an MCFG table, only the first segment can be accessed (via I/O ports)
+const LEGACY_SEGMENT: u16 = 0;
+/// The I/O ports only reach the first 256 bytes of the configuration space (the extended space needs ECAM)
+const LEGACY_CONFIG_SPACE_SIZE: u16 = 0x100;
+
+/// Offset of the bus numbers in the configuration space of a PCI-to-PCI bridge
+const BRIDGE_BUS_NUMBERS: u16 = 0x18;
 
 pub struct PciBus {
     config_space: ConfigurationSpace,
-    devices: Vec<RwLock<EndpointHeader>>,
+    /// Functions are never freed, since drivers keep references to them (even after they have been removed)
+    devices: RwLock<Vec<&'static RwLock<EndpointHeader>>>,
+    bridges: RwLock<Vec<PciBridge>>,
+    /// Only one rescan may run at a time
+    rescan_lock: Mutex<()>,
+}
+
+/// State of a single walk over all buses
+struct Scanner<'a> {
+    config_space: &'a ConfigurationSpace,
+    devices: Vec<EndpointHeader>,
+    bridges: Vec<PciBridge>,
+    /// Highest bus number in use per segment (new bus numbers for unconfigured bridges are assigned after it)
+    last_bus: Vec<(u16, u8)>,
+    /// Buses, that have already been scanned
+    scanned: Vec<(u16, u8)>,
+}
+
+/// Result of `PciBus::rescan()`
+pub struct RescanResult {
+    pub added: Vec<&'static RwLock<EndpointHeader>>,
+    pub removed: Vec<PciAddress>,
+}
+
+/// A PCI-to-PCI bridge and the range of buses behind it
+#[derive(Debug, Clone, Copy)]
+pub struct PciBridge {
+    pub address: PciAddress,
+    pub secondary_bus: u8,
+    pub subordinate_bus: u8,
 }
 
+/// Access to the configuration space, either memory mapped (ECAM, described by the MCFG table)
+/// or via the legacy I/O ports, which only reach the first segment.
 pub struct ConfigurationSpace {
     ports: Mutex<ConfigurationPorts>,
+    ecam: Vec<EcamRegion>,
+}
+
+/// Memory mapped configuration space of the buses `start_bus..=end_bus` of a segment
+#[derive(Debug, Clone, Copy)]
+struct EcamRegion {
+    segment: u16,
+    start_bus: u8,
+    end_bus: u8,
+    base: u64,
 }
 
 impl Clone for ConfigurationSpace {
     fn clone(&self) -> Self {
         Self {
             ports: Mutex::new(ConfigurationPorts::new()),
+            ecam: self.ecam.clone(),
         }
     }
 }
@@ -42,9 +98,58 @@ impl ConfigurationPorts {
 }
 
 impl ConfigurationSpace {
-    const fn new() -> Self {
+    /// Use the memory mapped configuration space of all segments described by the MCFG table (if there is one).
+    fn new() -> Self {
+        let mut ecam = Vec::new();
+        if let Ok(mcfg) = acpi_tables().lock().find_table::<Mcfg>() {
+            let kernel_process = process_manager().read().kernel_process().unwrap();
+            for entry in mcfg.entries() {
+                let region = EcamRegion {
+                    segment: entry.pci_segment_group,
+                    start_bus: entry.bus_number_start,
+                    end_bus: entry.bus_number_end,
+                    base: entry.base_address,
+                };
+                info!("PCI segment [{}] (buses [{:02x}-{:02x}]) is memory mapped at [0x{:x}]", region.segment, region.start_bus, region.end_bus, region.base);
+                kernel_process.virtual_address_space.kernel_map_devm_identity(
+                    region.address(region.start_bus, 0, 0),
+                    region.address(region.end_bus, 0, 0) + (1 << 20),
+                    PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE,
+                    VmaType::DeviceMemory,
+                    "pci-ecam",
+                );
+                ecam.push(region);
+            }
+        }
+
+        if ecam.is_empty() {
+            info!("No MCFG table found, accessing the PCI configuration space via I/O ports");
+        }
+
         Self {
             ports: Mutex::new(ConfigurationPorts::new()),
+            ecam,
+        }
+    }
+
+    /// Get the memory mapped configuration space of a function, if its segment has one.
+    fn ecam_address(&self, address: PciAddress, offset: u16) -> Option<*mut u32> {
+        self.ecam.iter()
+            .find(|region| region.segment == address.segment() && (region.start_bus..=region.end_bus).contains(&address.bus()))
+            .map(|region| (region.address(address.bus(), address.device(), address.function()) + (offset & 0xffc) as u64) as *mut u32)
+    }
+
+    /// Physical address ranges (`start..end`) of the memory mapped configuration space
+    pub(super) fn ecam_ranges(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.ecam.iter().map(|region| (region.address(region.start_bus, 0, 0), region.address(region.end_bus, 0, 0) + (1 << 20)))
+    }
+
+    /// Segments and their bus range (all segments of the MCFG table or just the legacy one)
+    fn segments(&self) -> Vec<(u16, u8, u8)> {
+        if self.ecam.is_empty() {
+            Vec::from([(LEGACY_SEGMENT, 0, MAX_BUS)])
+        } else {
+            self.ecam.iter().map(|region| (region.segment, region.start_bus, region.end_bus)).collect()
         }
     }
 
@@ -58,8 +163,21 @@ impl ConfigurationSpace {
     }
 }
 
+impl EcamRegion {
+    /// Each function has 4 KiB of configuration space
+    fn address(&self, bus: u8, device: u8, function: u8) -> u64 {
+        self.base + (((bus - self.start_bus) as u64) << 20 | (device as u64) << 15 | (function as u64) << 12)
+    }
+}
+
 impl ConfigRegionAccess for ConfigurationSpace {
     unsafe fn read(&self, address: PciAddress, offset: u16) -> u32 {
+        if let Some(register) = self.ecam_address(address, offset) {
+            return unsafe { register.read_volatile() };
+        }
+        if address.segment() != LEGACY_SEGMENT || offset >= LEGACY_CONFIG_SPACE_SIZE {
+            return u32::MAX;
+        }
         let mut ports = self.ports.lock();
 
         unsafe {
@@ -69,6 +187,13 @@ impl ConfigRegionAccess for ConfigurationSpace {
     }
 
     unsafe fn write(&self, address: PciAddress, offset: u16, value: u32) {
+        if let Some(register) = self.ecam_address(address, offset) {
+            unsafe { register.write_volatile(value) };
+            return;
+        }
+        if address.segment() != LEGACY_SEGMENT || offset >= LEGACY_CONFIG_SPACE_SIZE {
+            return;
+        }
         let mut ports = self.ports.lock();
 
         unsafe {
@@ -97,29 +222,75 @@ impl VirtioConfigAccess for ConfigurationSpace {
 
 impl PciBus {
     pub fn scan() -> Self {
-        let mut pci = Self {
-            config_space: ConfigurationSpace::new(),
-            devices: Vec::new(),
-        };
+        let config_space = ConfigurationSpace::new();
+        let (devices, bridges) = Scanner::new(&config_space).scan();
+        let devices: Vec<&'static RwLock<EndpointHeader>> = devices.into_iter()
+            .map(|device| &*Box::leak(Box::new(RwLock::new(device))))
+            .collect();
+
+        // Die Fenster der Brücken werden erst um die (neu vergebenen) Adressen der BARs herum geöffnet
+        pci_resources::assign_bars(&config_space, &devices, &bridges);
+        for bridge in bridges.iter() {
+            pci_resources::open_windows(&config_space, &devices, bridge);
+        }
+        registry::register_pci(&config_space, &devices, &bridges);
 
-        let root = PciHeader::new(PciAddress::new(0x8000, 0, 0, 0));
-        if root.has_multiple_functions(&pci.config_space) {
-            info!("Multiple PCI host controllers detected");
-            for i in 0..MAX_FUNCTIONS_PER_DEVICE {
-                let address = PciAddress::new(0x8000, 0, 0, i);
-                let header = PciHeader::new(address);
-                if header.id(&pci.config_space).0 == INVALID {
-                    break;
-                }
+        Self {
+            config_space,
+            devices: RwLock::new(devices),
+            bridges: RwLock::new(bridges),
+            rescan_lock: Mutex::new(()),
+        }
+    }
 
-                pci.scan_bus(address);
+    /// Walk the buses again to find functions, that have been added or removed since the last scan (e.g. by QEMU's `device_add`).
+    /// New functions get addresses for their BARs, are registered in the device registry and returned, so drivers can be set up for them.
+    /// Removed functions are unregistered, but stay allocated for drivers still holding a reference.
+    pub fn rescan(&self) -> RescanResult {
+        let _rescan = self.rescan_lock.lock();
+        let (found, found_bridges) = Scanner::new(&self.config_space).scan();
+
+        let mut devices = self.devices.write();
+        let known: Vec<PciAddress> = devices.iter().map(|device| device.read().header().address()).collect();
+        let removed: Vec<PciAddress> = known.iter()
+            .filter(|address| !found.iter().any(|device| device.header().address() == **address))
+            .copied()
+            .collect();
+        devices.retain(|device| !removed.contains(&device.read().header().address()));
+
+        let added: Vec<&'static RwLock<EndpointHeader>> = found.into_iter()
+            .filter(|device| !known.contains(&device.header().address()))
+            .map(|device| &*Box::leak(Box::new(RwLock::new(device))))
+            .collect();
+        for device in added.iter() {
+            let address = device.read().header().address();
+            let id = device.read().header().id(&self.config_space);
+            info!("New PCI device [0x{:0>4x}:0x{:0>4x}] at [{:02x}:{:02x}.{:x}]", id.0, id.1, address.bus(), address.device(), address.function());
+        }
+        devices.extend(added.iter().copied());
+
+        let mut bridges = self.bridges.write();
+        let new_bridges: Vec<PciBridge> = found_bridges.into_iter()
+            .filter(|bridge| !bridges.iter().any(|known| known.address == bridge.address))
+            .collect();
+        bridges.extend(new_bridges.iter().copied());
+
+        if !added.is_empty() {
+            // Bekannte Geräte stehen vorne, ihre gültigen BARs werden also nicht verschoben
+            pci_resources::assign_bars(&self.config_space, &devices, &bridges);
+            for bridge in new_bridges.iter() {
+                pci_resources::open_windows(&self.config_space, &devices, bridge);
             }
-        } else {
-            info!("Single PCI host controller detected");
-            pci.scan_bus(PciAddress::new(0x8000, 0, 0, 0));
+            registry::register_pci(&self.config_space, &devices, &bridges);
+        }
+
+        for address in removed.iter() {
+            info!("PCI device at [{:02x}:{:02x}.{:x}] has been removed", address.bus(), address.device(), address.function());
+            registry::unregister(&registry::pci_name(*address));
+            pci_resources::forget_bars(*address);
         }
 
-        pci
+        RescanResult { added, removed }
     }
 
     #[inline(always)]
@@ -127,35 +298,104 @@ impl PciBus {
         &self.config_space
     }
 
+    /// All PCI-to-PCI bridges with the buses behind them
+    pub fn bridges(&self) -> Vec<PciBridge> {
+        self.bridges.read().clone()
+    }
+
     /// neu um VirtIO Geräte zu finden
     pub fn search_by_vendor(&self, vendor_id: u16) -> Vec<&RwLock<EndpointHeader>> {
         self.devices
+            .read()
             .iter()
+            .copied()
             .filter(|device| device.read().header().id(self.config_space()).0 == vendor_id)
             .collect()
     }
 
     pub fn search_by_ids(&self, vendor_id: u16, device_id: u16) -> Vec<&RwLock<EndpointHeader>> {
         self.devices
+            .read()
             .iter()
+            .copied()
             .filter(|device| device.read().header().id(self.config_space()) == (vendor_id, device_id))
             .collect()
     }
 
     pub fn search_by_class(&self, base_class: BaseClass, sub_class: SubClass) -> Vec<&RwLock<EndpointHeader>> {
         self.devices
+            .read()
             .iter()
+            .copied()
             .filter(|device| {
                 let info = device.read().header().revision_and_class(self.config_space());
                 info.1 == base_class && info.2 == sub_class
             })
             .collect()
     }
+}
+
+impl<'a> Scanner<'a> {
+    fn new(config_space: &'a ConfigurationSpace) -> Self {
+        Self {
+            config_space,
+            devices: Vec::new(),
+            bridges: Vec::new(),
+            last_bus: Vec::new(),
+            scanned: Vec::new(),
+        }
+    }
+
+    /// Find all functions and bridges of all segments.
+    fn scan(mut self) -> (Vec<EndpointHeader>, Vec<PciBridge>) {
+        for (segment, start_bus, end_bus) in self.config_space.segments() {
+            self.scan_segment(segment, start_bus, end_bus);
+        }
+
+        (self.devices, self.bridges)
+    }
+
+    fn config_space(&self) -> &ConfigurationSpace {
+        self.config_space
+    }
+
+    /// Scan the root buses of a segment: If the host bridge has multiple functions, each of them is a separate root bus.
+    /// Host bridges, which are only described by ACPI (`_CRS`), are found by probing the remaining buses of the segment.
+    fn scan_segment(&mut self, segment: u16, start_bus: u8, end_bus: u8) {
+        let root = PciHeader::new(PciAddress::new(segment, start_bus, 0, 0));
+        if root.has_multiple_functions(&self.config_space) {
+            info!("Multiple PCI host controllers detected on segment [{}]", segment);
+            for i in 0..MAX_FUNCTIONS_PER_DEVICE {
+                let address = PciAddress::new(segment, start_bus, 0, i);
+                let header = PciHeader::new(address);
+                if header.id(&self.config_space).0 == INVALID {
+                    break;
+                }
+
+                self.scan_bus(PciAddress::new(segment, start_bus + i, 0, 0));
+            }
+        } else {
+            info!("Single PCI host controller detected on segment [{}]", segment);
+            self.scan_bus(PciAddress::new(segment, start_bus, 0, 0));
+        }
+
+        // Nur über ECAM ist das Abtasten aller Busse schnell genug
+        if self.config_space.ecam.is_empty() {
+            return;
+        }
+        for bus in start_bus..=end_bus {
+            if !self.is_scanned(segment, bus) && self.bus_has_devices(segment, bus) {
+                info!("Found additional PCI root bus [{:02x}] on segment [{}]", bus, segment);
+                self.scan_bus(PciAddress::new(segment, bus, 0, 0));
+            }
+        }
+    }
 
     fn scan_bus(&mut self, address: PciAddress) {
         assert_eq!(address.device(), 0);
         assert_eq!(address.function(), 0);
 
+        self.mark_scanned(address.segment(), address.bus());
         for i in 0..MAX_DEVICES_PER_BUS {
             self.check_device(PciAddress::new(address.segment(), address.bus(), i, 0));
         }
@@ -187,16 +427,71 @@ impl PciBus {
         if id.0 == INVALID {
             return;
         }
-        info!("[PCI SCAN DEBUG] Checking... Vendor:Device = {:04x}:{:04x}", id.0, id.1);
-
         if device.header_type(self.config_space()) == HeaderType::PciPciBridge {
             info!("Found PCI-to-PCI bridge on bus [{}]", address.bus());
-            let bridge = PciPciBridgeHeader::from_header(device, self.config_space()).unwrap();
-            self.scan_bus(PciAddress::new(0x8000, bridge.secondary_bus_number(self.config_space()), 0, 0));
+            self.scan_bridge(address);
         } else {
             info!("Found PCI device [0x{:0>4x}:0x{:0>4x}] at [{:02x}:{:02x}.{:x}]", id.0, id.1, address.bus(), address.device(), address.function());
-            self.devices
-                .push(RwLock::new(EndpointHeader::from_header(device, self.config_space()).unwrap()));
+            self.devices.push(EndpointHeader::from_header(device, self.config_space()).unwrap());
+        }
+    }
+
+    /// Scan the buses behind a bridge. If the firmware hasn't assigned bus numbers to the bridge, this is done here.
+    fn scan_bridge(&mut self, address: PciAddress) {
+        let bridge = PciPciBridgeHeader::from_header(PciHeader::new(address), self.config_space()).unwrap();
+        let mut secondary_bus = bridge.secondary_bus_number(self.config_space());
+        let configured = secondary_bus > address.bus() && !self.is_scanned(address.segment(), secondary_bus);
+
+        if !configured {
+            // Neue Busnummer vergeben, die Obergrenze wird nach dem Scannen angepasst
+            secondary_bus = match self.last_bus(address.segment()).checked_add(1) {
+                Some(bus) => bus,
+                None => {
+                    warn!("No bus number left for PCI-to-PCI bridge at [{:02x}:{:02x}.{:x}]", address.bus(), address.device(), address.function());
+                    return;
+                }
+            };
+            info!("Assigning bus [{:02x}] to PCI-to-PCI bridge at [{:02x}:{:02x}.{:x}]", secondary_bus, address.bus(), address.device(), address.function());
+            self.set_bus_numbers(address, secondary_bus, MAX_BUS);
+        }
+
+        self.scan_bus(PciAddress::new(address.segment(), secondary_bus, 0, 0));
+
+        let subordinate_bus = if configured {
+            bridge.subordinate_bus_number(self.config_space())
+        } else {
+            let last_bus = self.last_bus(address.segment());
+            self.set_bus_numbers(address, secondary_bus, last_bus);
+            last_bus
+        };
+        self.bridges.push(PciBridge { address, secondary_bus, subordinate_bus });
+    }
+
+    fn set_bus_numbers(&self, address: PciAddress, secondary_bus: u8, subordinate_bus: u8) {
+        unsafe {
+            let value = self.config_space.read(address, BRIDGE_BUS_NUMBERS);
+            let value = (value & 0xff000000) | (subordinate_bus as u32) << 16 | (secondary_bus as u32) << 8 | address.bus() as u32;
+            self.config_space.write(address, BRIDGE_BUS_NUMBERS, value);
+        }
+    }
+
+    fn mark_scanned(&mut self, segment: u16, bus: u8) {
+        match self.last_bus.iter_mut().find(|(s, _)| *s == segment) {
+            Some((_, last_bus)) => *last_bus = (*last_bus).max(bus),
+            None => self.last_bus.push((segment, bus)),
         }
+        self.scanned.push((segment, bus));
+    }
+
+    fn is_scanned(&self, segment: u16, bus: u8) -> bool {
+        self.scanned.contains(&(segment, bus))
+    }
+
+    fn last_bus(&self, segment: u16) -> u8 {
+        self.last_bus.iter().find(|(s, _)| *s == segment).map_or(0, |(_, bus)| *bus)
+    }
+
+    fn bus_has_devices(&self, segment: u16, bus: u8) -> bool {
+        (0..MAX_DEVICES_PER_BUS).any(|device| PciHeader::new(PciAddress::new(segment, bus, device, 0)).id(self.config_space()).0 != INVALID)
     }
 }
@@ -0,0 +1,228 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: pci_irq                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Interrupts of PCI devices. Drivers request an interrupt with a  ║
+   ║         set of acceptable mechanisms and get the best one, the device   ║
+   ║         supports: MSI-X, MSI or the legacy INTx pin (routed by the IO   ║
+   ║         APIC). Message signaled interrupts get a free vector of their   ║
+   ║         own and are delivered to the local APIC of the calling core     ║
+   ║         (edge triggered, fixed delivery). Only a single vector per      ║
+   ║         device is used.                                                 ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - request_irq          set up the interrupt of a device               ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use bitflags::bitflags;
+use core::ops::RangeInclusive;
+use log::{info, warn};
+use pci_types::{CommandRegister, ConfigRegionAccess, EndpointHeader, PciAddress};
+use spin::RwLock;
+use syscall::return_vals::Errno;
+use system_info::devices::Resource;
+use crate::interrupt::interrupt_dispatcher::InterruptVector;
+use crate::interrupt::interrupt_handler::InterruptHandler;
+use crate::memory::vma::VmaType;
+use crate::{apic, interrupt_dispatcher, pci_bus, process_manager};
+use super::pci::ConfigurationSpace;
+use super::{pci_resources, registry};
+
+/// Vectors for message signaled interrupts (above the IO APIC inputs, below the vectors for IPIs)
+const MSI_VECTORS: RangeInclusive<u8> = 0x50..=0xdf;
+
+/// Status register: the device has a list of capabilities
+const STATUS_CAPABILITIES_LIST: u32 = 1 << 20;
+const CAPABILITIES_POINTER: u16 = 0x34;
+const CAPABILITY_MSI: u8 = 0x05;
+const CAPABILITY_MSIX: u8 = 0x11;
+
+/// Bits in the message control register of the MSI capability
+const MSI_ENABLE: u32 = 1 << 16;
+const MSI_MULTIPLE_MESSAGE_ENABLE: u32 = 0x7 << 20;
+const MSI_64_BIT: u32 = 1 << 23;
+/// Bits in the message control register of the MSI-X capability
+const MSIX_FUNCTION_MASK: u32 = 1 << 30;
+const MSIX_ENABLE: u32 = 1 << 31;
+const MSIX_ENTRY_SIZE: u64 = 16;
+const MSIX_VECTOR_MASKED: u32 = 1;
+
+/// Messages to this address range are delivered to a local APIC (the destination id is in bits 12..20)
+const MSI_ADDRESS_BASE: u32 = 0xfee0_0000;
+
+bitflags! {
+    /// Interrupt mechanisms, a driver can handle.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct IrqTypes: u8 {
+        const LEGACY = 1;
+        const MSI = 2;
+        const MSIX = 4;
+    }
+}
+
+/// An interrupt, that has been set up for a device.
+#[derive(Debug, Clone, Copy)]
+pub struct Irq {
+    pub vector: u8,
+    /// The mechanism, that has been chosen (exactly one bit)
+    pub typ: IrqTypes,
+}
+
+/// Where the message of a message signaled interrupt is configured
+enum Message {
+    Msi { offset: u16 },
+    Msix { offset: u16, table: u64 },
+}
+
+/// Install `handler` for the interrupt of `device`, preferring MSI-X over MSI over the legacy pin (out of the mechanisms in `types`).
+/// Fails with `ENOTSUP`, if the device supports none of them and with `EBUSY`, if no vector is free.
+pub fn request_irq(device: &RwLock<EndpointHeader>, types: IrqTypes, handler: Box<dyn InterruptHandler>) -> Result<Irq, Errno> {
+    let config_space = pci_bus().config_space();
+    let mut device = device.write();
+    let address = device.header().address();
+
+    let destination = apic().local_apic_id();
+    if destination <= 0xff && let Some(message) = find_message(config_space, &device, types) {
+        let vector = interrupt_dispatcher().assign_free(MSI_VECTORS, handler).ok_or(Errno::EBUSY)?;
+        let message_address = MSI_ADDRESS_BASE | destination << 12;
+        let typ = match message {
+            Message::Msix { offset, table } => {
+                enable_msix(config_space, address, offset, table, message_address, vector);
+                IrqTypes::MSIX
+            }
+            Message::Msi { offset } => {
+                enable_msi(config_space, address, offset, message_address, vector);
+                IrqTypes::MSI
+            }
+        };
+
+        // Der INTx-Pin darf jetzt nicht mehr ausgelöst werden
+        device.update_command(config_space, |command| command | CommandRegister::INTERRUPT_DISABLE);
+        info!("Device [{:?}] uses {:?} with vector [0x{:x}]", address, typ, vector);
+        registry::add_resource(&registry::pci_name(address), Resource::Irq(vector));
+        return Ok(Irq { vector, typ });
+    }
+
+    if !types.contains(IrqTypes::LEGACY) {
+        return Err(Errno::ENOTSUP);
+    }
+
+    let (_, line) = device.interrupt(config_space);
+    let vector = match line {
+        0 | 0xff => None,
+        line => InterruptVector::try_from(line + InterruptVector::Pit as u8).ok(),
+    };
+    let Some(vector) = vector else {
+        warn!("Device [{:?}] has no usable interrupt line (line: {})", address, line);
+        return Err(Errno::ENOTSUP);
+    };
+
+    interrupt_dispatcher().assign(vector, handler);
+    apic().allow(vector);
+    info!("Device [{:?}] uses legacy interrupt line [{}]", address, line);
+    registry::add_resource(&registry::pci_name(address), Resource::Irq(vector as u8));
+    Ok(Irq { vector: vector as u8, typ: IrqTypes::LEGACY })
+}
+
+/// Choose MSI-X or MSI (out of `types`), if the device supports it. The MSI-X table is mapped here,
+/// so a device, whose table is not accessible, can still use MSI or the legacy pin.
+fn find_message(config_space: &ConfigurationSpace, device: &EndpointHeader, types: IrqTypes) -> Option<Message> {
+    let address = device.header().address();
+
+    if types.contains(IrqTypes::MSIX) && let Some(offset) = find_capability(config_space, address, CAPABILITY_MSIX) {
+        match map_msix_table(config_space, device, offset) {
+            Some(table) => return Some(Message::Msix { offset, table }),
+            None => warn!("Failed to map the MSI-X table of device [{:?}]", address),
+        }
+    }
+
+    if types.contains(IrqTypes::MSI) && let Some(offset) = find_capability(config_space, address, CAPABILITY_MSI) {
+        return Some(Message::Msi { offset });
+    }
+
+    None
+}
+
+/// Return the address of the MSI-X table of the capability at `offset` and map its BAR, unless the driver has done so already.
+fn map_msix_table(config_space: &ConfigurationSpace, device: &EndpointHeader, offset: u16) -> Option<u64> {
+    let table = unsafe { config_space.read(device.header().address(), offset + 0x04) };
+    let slot = (table & 0x7) as u8;
+    let bar = pci_resources::bars(config_space, device).into_iter().find(|bar| bar.slot == slot && bar.start != 0)?;
+    let table = bar.start + (table & !0x7) as u64;
+
+    let kernel_process = process_manager().read().kernel_process().unwrap();
+    if kernel_process.virtual_address_space.is_address_within_vma(table, VmaType::DeviceMemory).is_none() {
+        pci_resources::map_memory_bar(device, slot, "msix-table")?;
+    }
+
+    Some(table)
+}
+
+/// Search the capability list of a device for the capability `id` and return its offset.
+fn find_capability(config_space: &ConfigurationSpace, address: PciAddress, id: u8) -> Option<u16> {
+    let status = unsafe { config_space.read(address, 0x04) };
+    if status & STATUS_CAPABILITIES_LIST == 0 {
+        return None;
+    }
+
+    let mut offset = (unsafe { config_space.read(address, CAPABILITIES_POINTER) } & 0xfc) as u16;
+    // Bound the walk, in case a broken device has a loop in its list
+    for _ in 0..48 {
+        if offset == 0 {
+            return None;
+        }
+
+        let header = unsafe { config_space.read(address, offset) };
+        if header as u8 == id {
+            return Some(offset);
+        }
+        offset = ((header >> 8) & 0xfc) as u16;
+    }
+
+    None
+}
+
+/// Program the MSI capability at `offset` for a single message.
+fn enable_msi(config_space: &ConfigurationSpace, address: PciAddress, offset: u16, message_address: u32, vector: u8) {
+    let control = unsafe { config_space.read(address, offset) };
+    let data_offset = if control & MSI_64_BIT != 0 {
+        unsafe { config_space.write(address, offset + 0x08, 0) };
+        offset + 0x0c
+    } else {
+        offset + 0x08
+    };
+
+    unsafe {
+        config_space.write(address, offset + 0x04, message_address);
+        // Nur die unteren 16 Bit gehören zu den Nachrichtendaten
+        let data = config_space.read(address, data_offset) & 0xffff_0000;
+        config_space.write(address, data_offset, data | vector as u32);
+        config_space.write(address, offset, (control & !MSI_MULTIPLE_MESSAGE_ENABLE) | MSI_ENABLE);
+    }
+}
+
+/// Program the first entry of the MSI-X table at `table`, mask all others and enable MSI-X (capability at `offset`).
+fn enable_msix(config_space: &ConfigurationSpace, address: PciAddress, offset: u16, table: u64, message_address: u32, vector: u8) {
+    let control = unsafe { config_space.read(address, offset) };
+    let table_size = ((control >> 16) & 0x7ff) as u64 + 1;
+
+    unsafe {
+        // Während die Tabelle beschrieben wird, sind alle Vektoren maskiert
+        config_space.write(address, offset, control | MSIX_FUNCTION_MASK | MSIX_ENABLE);
+
+        for entry in 0..table_size {
+            let vector_control = (table + entry * MSIX_ENTRY_SIZE + 0x0c) as *mut u32;
+            vector_control.write_volatile(vector_control.read_volatile() | MSIX_VECTOR_MASKED);
+        }
+
+        let entry = table as *mut u32;
+        entry.write_volatile(message_address);
+        entry.add(1).write_volatile(0);
+        entry.add(2).write_volatile(vector as u32);
+        entry.add(3).write_volatile(entry.add(3).read_volatile() & !MSIX_VECTOR_MASKED);
+
+        config_space.write(address, offset, (control | MSIX_ENABLE) & !MSIX_FUNCTION_MASK);
+    }
+}
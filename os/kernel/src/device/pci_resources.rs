@@ -0,0 +1,485 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: pci_resources                                                   ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Address resources of PCI devices (BARs) and bridges (windows).  ║
+   ║         BARs, that the firmware has left unassigned (e.g. for devices   ║
+   ║         hot-added to QEMU), placed outside the window of the bridge in  ║
+   ║         front of them or on top of another BAR, get a new address: in   ║
+   ║         the innermost open window of a bridge above the device or else  ║
+   ║         above all resources in use (ACPI `_CRS` is not evaluated, so    ║
+   ║         the apertures of the host bridges are unknown), skipping all    ║
+   ║         regions listed in the memory map of the firmware. Closed        ║
+   ║         windows of bridges are opened around the BARs behind them       ║
+   ║         afterwards. BARs are sized only once, when a device is found,   ║
+   ║         since sizing briefly overwrites their addresses.                ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - bars                 get the resources of all BARs of a device      ║
+   ║   - map_memory_bar       identity map a memory BAR for a driver         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::vec::Vec;
+use log::{info, warn};
+use pci_types::{Bar, CommandRegister, ConfigRegionAccess, EndpointHeader, MAX_BARS, PciAddress, PciHeader};
+use spin::RwLock;
+use x86_64::structures::paging::PageTableFlags;
+use crate::memory::{PAGE_SIZE, dram};
+use crate::memory::vma::VmaType;
+use crate::process_manager;
+use super::pci::{ConfigurationSpace, PciBridge};
+
+/// Offset of the first BAR in the configuration space
+const BAR_OFFSET: u16 = 0x10;
+/// Offsets in the configuration space of a PCI-to-PCI bridge
+const BRIDGE_IO_WINDOW: u16 = 0x1c;
+const BRIDGE_MEMORY_WINDOW: u16 = 0x20;
+const BRIDGE_PREFETCHABLE_WINDOW: u16 = 0x24;
+const BRIDGE_PREFETCHABLE_BASE_UPPER: u16 = 0x28;
+const BRIDGE_PREFETCHABLE_LIMIT_UPPER: u16 = 0x2c;
+const BRIDGE_IO_WINDOW_UPPER: u16 = 0x30;
+/// Granularity of the windows of a bridge
+const BRIDGE_MEMORY_GRANULARITY: u64 = 0x100000;
+const BRIDGE_IO_GRANULARITY: u64 = 0x1000;
+
+/// Unassigned memory BARs are placed above all resources in use, but below the I/O APIC
+const MEMORY_POOL_START: u64 = 0xc0000000;
+const MEMORY_POOL_END: u64 = 0xfec00000;
+/// Unassigned I/O BARs are placed above the ports of legacy devices
+const IO_POOL_START: u64 = 0xc000;
+const IO_POOL_END: u64 = 0x10000;
+
+/// The BARs of all devices found so far, sized when the device has been found
+static BAR_RESOURCES: RwLock<Vec<(PciAddress, Vec<BarResource>)>> = RwLock::new(Vec::new());
+
+/// Address spaces of BARs. Each bridge has one window per kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    Io,
+    Memory,
+    Prefetchable,
+}
+
+/// A BAR with its address range (the address is 0, if the BAR has not been assigned)
+#[derive(Debug, Clone, Copy)]
+pub struct BarResource {
+    pub slot: u8,
+    pub kind: ResourceKind,
+    pub start: u64,
+    pub size: u64,
+    pub is_64bit: bool,
+}
+
+/// Addresses are handed out in ascending order (per window or pool), so the devices behind
+/// different bridges don't interleave and their windows can't overlap.
+struct Cursor {
+    /// Start of the window (or `0` for the pool)
+    window: u64,
+    next: u64,
+    end: u64,
+    /// The bridge, whose window is opened around the last BAR placed here
+    last_group: Option<PciAddress>,
+}
+
+impl ResourceKind {
+    fn is_io(self) -> bool {
+        self == ResourceKind::Io
+    }
+
+    fn granularity(self) -> u64 {
+        if self.is_io() { BRIDGE_IO_GRANULARITY } else { BRIDGE_MEMORY_GRANULARITY }
+    }
+}
+
+impl BarResource {
+    fn end(&self) -> u64 {
+        self.start + self.size
+    }
+
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end()
+    }
+}
+
+/// Get the resources of all implemented BARs of a device.
+/// The BARs are only sized on the first call for a device, later calls return the known resources.
+pub fn bars(config_space: &ConfigurationSpace, device: &EndpointHeader) -> Vec<BarResource> {
+    let address = device.header().address();
+    let mut resources = BAR_RESOURCES.write();
+    if let Some((_, bars)) = resources.iter().find(|(known, _)| *known == address) {
+        return bars.clone();
+    }
+
+    let bars = probe_bars(config_space, device);
+    resources.push((address, bars.clone()));
+    bars
+}
+
+/// Forget the BARs of a removed device, so a device added at its address later is sized again.
+pub(super) fn forget_bars(address: PciAddress) {
+    BAR_RESOURCES.write().retain(|(known, _)| *known != address);
+}
+
+/// Size all implemented BARs of a device (writes all ones into each BAR).
+fn probe_bars(config_space: &ConfigurationSpace, device: &EndpointHeader) -> Vec<BarResource> {
+    let mut bars = Vec::new();
+    let mut slot = 0;
+    while slot < MAX_BARS as u8 {
+        let bar = match device.bar(slot, config_space) {
+            Some(Bar::Memory32 { address, size, prefetchable }) => BarResource {
+                slot,
+                kind: if prefetchable { ResourceKind::Prefetchable } else { ResourceKind::Memory },
+                start: address as u64,
+                size: size as u64,
+                is_64bit: false,
+            },
+            Some(Bar::Memory64 { address, size, prefetchable }) => BarResource {
+                slot,
+                kind: if prefetchable { ResourceKind::Prefetchable } else { ResourceKind::Memory },
+                start: address,
+                size,
+                is_64bit: true,
+            },
+            Some(Bar::Io { port }) => BarResource {
+                slot,
+                kind: ResourceKind::Io,
+                start: port as u64,
+                size: io_bar_size(config_space, device.header().address(), slot),
+                is_64bit: false,
+            },
+            None => {
+                slot += 1;
+                continue;
+            }
+        };
+
+        slot += if bar.is_64bit { 2 } else { 1 };
+        if bar.size > 0 {
+            bars.push(bar);
+        }
+    }
+
+    bars
+}
+
+/// Identity map the memory BAR `slot` of a device (uncached) and return its address range.
+/// Returns `None` for I/O BARs and for BARs, that could not be assigned.
+pub fn map_memory_bar(device: &EndpointHeader, slot: u8, tag: &str) -> Option<(u64, u64)> {
+    let config_space = crate::pci_bus().config_space();
+    let bar = bars(config_space, device).into_iter().find(|bar| bar.slot == slot)?;
+    if bar.kind.is_io() || bar.start == 0 {
+        return None;
+    }
+
+    // BARs kleiner als eine Seite sind nicht auf Seiten ausgerichtet
+    let start = bar.start & !(PAGE_SIZE as u64 - 1);
+    let end = bar.end().div_ceil(PAGE_SIZE as u64) * PAGE_SIZE as u64;
+    let kernel_process = process_manager().read().kernel_process().unwrap();
+    kernel_process.virtual_address_space.kernel_map_devm_identity(
+        start,
+        end,
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE,
+        VmaType::DeviceMemory,
+        tag,
+    );
+
+    Some((bar.start, bar.end()))
+}
+
+/// Assign new addresses to all BARs without a valid one. This must happen before the windows of the bridges are opened.
+pub(super) fn assign_bars(config_space: &ConfigurationSpace, devices: &[&RwLock<EndpointHeader>], bridges: &[PciBridge]) {
+    let devices: Vec<(PciAddress, Vec<BarResource>)> = devices.iter()
+        .map(|device| device.read())
+        .filter(|device| !is_legacy_ide(config_space, device))
+        .map(|device| (device.header().address(), bars(config_space, &device)))
+        .collect();
+
+    // Gültige BARs behalten ihre Adressen, alle anderen werden neu vergeben
+    let mut valid: Vec<(PciAddress, BarResource)> = Vec::new();
+    let mut invalid: Vec<(PciAddress, BarResource)> = Vec::new();
+    for (address, bar) in devices.iter().flat_map(|(address, bars)| bars.iter().map(move |bar| (*address, *bar))) {
+        let window = ancestors(bridges, address).find_map(|bridge| container(config_space, bridge, bar.kind));
+        let outside_window = window.is_some_and(|(start, end)| bar.start < start || bar.end() > end + 1);
+        let conflict = valid.iter().any(|(_, other)| other.kind.is_io() == bar.kind.is_io() && other.overlaps(bar.start, bar.end()));
+
+        if bar.start == 0 || outside_window || conflict {
+            invalid.push((address, bar));
+        } else {
+            valid.push((address, bar));
+        }
+    }
+    if invalid.is_empty() {
+        return;
+    }
+
+    // Der freie Bereich beginnt oberhalb aller genutzten Adressen (BARs, Fenster und ECAM)
+    let mut memory_pool = MEMORY_POOL_START;
+    let mut io_pool = IO_POOL_START;
+    for (_, bar) in valid.iter() {
+        match bar.kind {
+            ResourceKind::Io => io_pool = io_pool.max(bar.end()),
+            _ if bar.end() <= MEMORY_POOL_END => memory_pool = memory_pool.max(bar.end()),
+            _ => {}
+        }
+    }
+    for bridge in bridges {
+        for kind in [ResourceKind::Memory, ResourceKind::Prefetchable] {
+            if let Some((_, end)) = open_window(config_space, bridge.address, kind).filter(|(_, end)| *end < MEMORY_POOL_END) {
+                memory_pool = memory_pool.max(end + 1);
+            }
+        }
+        if let Some((_, end)) = open_window(config_space, bridge.address, ResourceKind::Io) {
+            io_pool = io_pool.max(end + 1);
+        }
+    }
+    for (_, end) in config_space.ecam_ranges().filter(|(_, end)| *end <= MEMORY_POOL_END) {
+        memory_pool = memory_pool.max(end);
+    }
+
+    let mut cursors: Vec<Cursor> = Vec::from([
+        Cursor { window: 0, next: memory_pool, end: MEMORY_POOL_END, last_group: None },
+        Cursor { window: 0, next: io_pool, end: IO_POOL_END, last_group: None },
+    ]);
+
+    for (address, bar) in invalid {
+        // Das innerste offene Fenster oberhalb des Geräts, geschlossene Fenster werden später um die BARs herum geöffnet
+        let mut group = None;
+        let mut window = None;
+        for bridge in ancestors(bridges, address) {
+            window = container(config_space, bridge, bar.kind);
+            if window.is_some() {
+                break;
+            }
+            group.get_or_insert(bridge.address);
+        }
+
+        let cursor = match window {
+            Some((start, end)) => match cursors.iter().position(|cursor| cursor.window == start && cursor.end == end + 1) {
+                Some(index) => &mut cursors[index],
+                None => {
+                    let next = valid.iter()
+                        .filter(|(_, other)| other.kind.is_io() == bar.kind.is_io() && other.start >= start && other.end() <= end + 1)
+                        .map(|(_, other)| other.end())
+                        .max()
+                        .unwrap_or(start);
+                    cursors.push(Cursor { window: start, next, end: end + 1, last_group: None });
+                    cursors.last_mut().unwrap()
+                }
+            },
+            None => &mut cursors[if bar.kind.is_io() { 1 } else { 0 }],
+        };
+
+        let Some(start) = cursor.allocate(&bar, group) else {
+            warn!("No space for BAR{} (size: {:#x}) of PCI device at [{:02x}:{:02x}.{:x}], leaving it unassigned",
+                bar.slot, bar.size, address.bus(), address.device(), address.function());
+            continue;
+        };
+        info!("Assigning [{:#x}-{:#x}] to BAR{} of PCI device at [{:02x}:{:02x}.{:x}] (was [{:#x}])",
+            start, start + bar.size - 1, bar.slot, address.bus(), address.device(), address.function(), bar.start);
+        write_bar(config_space, address, &bar, start);
+        valid.push((address, BarResource { start, ..bar }));
+    }
+}
+
+/// Open the windows of a bridge, which the firmware has left closed, so they cover the BARs behind it.
+/// Windows opened by the firmware are never changed.
+pub(super) fn open_windows(config_space: &ConfigurationSpace, devices: &[&RwLock<EndpointHeader>], bridge: &PciBridge) {
+    let address = bridge.address;
+    let bars: Vec<BarResource> = devices.iter()
+        .map(|device| device.read())
+        .filter(|device| {
+            let device = device.header().address();
+            device.segment() == address.segment() && (bridge.secondary_bus..=bridge.subordinate_bus).contains(&device.bus())
+        })
+        .flat_map(|device| bars(config_space, &device))
+        .filter(|bar| bar.start != 0)
+        .collect();
+
+    for kind in [ResourceKind::Io, ResourceKind::Memory, ResourceKind::Prefetchable] {
+        if open_window(config_space, address, kind).is_some() {
+            continue;
+        }
+
+        let memory_window = open_window(config_space, address, ResourceKind::Memory);
+        let range = bars.iter()
+            .filter(|bar| bar.kind == kind)
+            // Prefetchable BARs dürfen auch im normalen Speicherfenster liegen
+            .filter(|bar| kind != ResourceKind::Prefetchable || !memory_window.is_some_and(|(start, end)| bar.start >= start && bar.end() <= end + 1))
+            .fold(None, |range: Option<(u64, u64)>, bar| {
+                Some(range.map_or((bar.start, bar.end() - 1), |(min, max)| (min.min(bar.start), max.max(bar.end() - 1))))
+            });
+        if let Some((start, end)) = range {
+            let (base, limit) = write_window(config_space, address, kind, start, end);
+            info!("Opened {:?} window [{:#x}-{:#x}] of bridge at [{:02x}:{:02x}.{:x}]", kind, base, limit, address.bus(), address.device(), address.function());
+        }
+    }
+
+    // Ohne Weiterleitung erreichen Zugriffe die Geräte hinter der Brücke nicht
+    PciHeader::new(address).update_command(config_space, |command| {
+        command | CommandRegister::IO_ENABLE | CommandRegister::MEMORY_ENABLE | CommandRegister::BUS_MASTER_ENABLE
+    });
+}
+
+impl Cursor {
+    /// Take the next address suitable for `bar`. If the BAR is behind a bridge with a closed window (`group`),
+    /// the devices behind different bridges are separated by the granularity of the windows.
+    fn allocate(&mut self, bar: &BarResource, group: Option<PciAddress>) -> Option<u64> {
+        let mut alignment = bar.size.max(if bar.kind.is_io() { 4 } else { PAGE_SIZE as u64 });
+        if group.is_some() && group != self.last_group {
+            alignment = alignment.max(bar.kind.granularity());
+        }
+
+        let mut next = self.next;
+        loop {
+            let start = next.checked_next_multiple_of(alignment)?;
+            if start + bar.size > self.end {
+                return None;
+            }
+
+            // Speicherbereiche aus der Memory Map der Firmware (RAM, ACPI, MMIO, ...) werden übersprungen
+            if !bar.kind.is_io() && let Some(region) = dram::firmware_overlap(start, start + bar.size) {
+                next = region.end.as_u64();
+                continue;
+            }
+
+            self.next = start + bar.size;
+            self.last_group = group;
+            return Some(start);
+        }
+    }
+}
+
+/// Get the bridges above a device, starting with the innermost one.
+fn ancestors(bridges: &[PciBridge], address: PciAddress) -> impl Iterator<Item = &PciBridge> {
+    let mut ancestors: Vec<&PciBridge> = bridges.iter()
+        .filter(|bridge| bridge.address.segment() == address.segment() && (bridge.secondary_bus..=bridge.subordinate_bus).contains(&address.bus()))
+        .collect();
+    ancestors.sort_by_key(|bridge| bridge.subordinate_bus - bridge.secondary_bus);
+    ancestors.into_iter()
+}
+
+/// Get the open window of a bridge, that can hold a BAR of `kind` (prefetchable BARs also fit into the memory window).
+fn container(config_space: &ConfigurationSpace, bridge: &PciBridge, kind: ResourceKind) -> Option<(u64, u64)> {
+    open_window(config_space, bridge.address, kind).or_else(|| match kind {
+        ResourceKind::Prefetchable => open_window(config_space, bridge.address, ResourceKind::Memory),
+        _ => None,
+    })
+}
+
+/// Read a window of a bridge (`base..=limit`). Returns `None` if it is closed (base above limit)
+/// or has never been programmed (all zero).
+fn open_window(config_space: &ConfigurationSpace, address: PciAddress, kind: ResourceKind) -> Option<(u64, u64)> {
+    let (base, limit) = unsafe {
+        match kind {
+            ResourceKind::Io => {
+                let window = config_space.read(address, BRIDGE_IO_WINDOW);
+                if window & 0xffff == 0 {
+                    return None;
+                }
+                let upper = if window & 0x1 != 0 { config_space.read(address, BRIDGE_IO_WINDOW_UPPER) as u64 } else { 0 };
+                let base = (upper & 0xffff) << 16 | (window as u64 & 0xf0) << 8;
+                let limit = (upper >> 16) << 16 | (window as u64 & 0xf000) | 0xfff;
+                (base, limit)
+            }
+            ResourceKind::Memory => {
+                let window = config_space.read(address, BRIDGE_MEMORY_WINDOW);
+                if window == 0 {
+                    return None;
+                }
+                ((window as u64 & 0xfff0) << 16, (window as u64 & 0xfff00000) | 0xfffff)
+            }
+            ResourceKind::Prefetchable => {
+                let window = config_space.read(address, BRIDGE_PREFETCHABLE_WINDOW);
+                if window & 0xfff0fff0 == 0 {
+                    return None;
+                }
+                let (base_upper, limit_upper) = if window & 0x1 != 0 {
+                    (config_space.read(address, BRIDGE_PREFETCHABLE_BASE_UPPER) as u64, config_space.read(address, BRIDGE_PREFETCHABLE_LIMIT_UPPER) as u64)
+                } else {
+                    (0, 0)
+                };
+                (base_upper << 32 | (window as u64 & 0xfff0) << 16, limit_upper << 32 | (window as u64 & 0xfff00000) | 0xfffff)
+            }
+        }
+    };
+
+    (base <= limit).then_some((base, limit))
+}
+
+/// Program a window of a bridge, so it covers `start..=end`. Returns the actual window (`base..=limit`).
+fn write_window(config_space: &ConfigurationSpace, address: PciAddress, kind: ResourceKind, start: u64, end: u64) -> (u64, u64) {
+    let granularity = kind.granularity();
+    let (base, limit) = (start & !(granularity - 1), end & !(granularity - 1));
+    unsafe {
+        match kind {
+            ResourceKind::Io => {
+                let window = config_space.read(address, BRIDGE_IO_WINDOW);
+                let value = (window & 0xffff0000) as u64 | ((limit >> 8) & 0xf0) << 8 | ((base >> 8) & 0xf0);
+                config_space.write(address, BRIDGE_IO_WINDOW_UPPER, ((limit >> 16) << 16 | (base >> 16)) as u32);
+                config_space.write(address, BRIDGE_IO_WINDOW, value as u32);
+            }
+            ResourceKind::Memory => {
+                config_space.write(address, BRIDGE_MEMORY_WINDOW, ((limit >> 16) << 16 | (base >> 16)) as u32);
+            }
+            ResourceKind::Prefetchable => {
+                // Die unteren 4 Bit geben an, ob das Fenster 64 Bit breit ist
+                let window = config_space.read(address, BRIDGE_PREFETCHABLE_WINDOW);
+                let value = (window & 0x000f000f) as u64 | ((limit >> 16) & 0xfff0) << 16 | ((base >> 16) & 0xfff0);
+                config_space.write(address, BRIDGE_PREFETCHABLE_BASE_UPPER, (base >> 32) as u32);
+                config_space.write(address, BRIDGE_PREFETCHABLE_LIMIT_UPPER, (limit >> 32) as u32);
+                config_space.write(address, BRIDGE_PREFETCHABLE_WINDOW, value as u32);
+            }
+        }
+    }
+
+    (base, limit + granularity - 1)
+}
+
+/// Move a BAR to `start`. Decoding is disabled meanwhile, so the device never answers at a half written address.
+fn write_bar(config_space: &ConfigurationSpace, address: PciAddress, bar: &BarResource, start: u64) {
+    let offset = BAR_OFFSET + bar.slot as u16 * 4;
+    without_decoding(config_space, address, || unsafe {
+        let flags = config_space.read(address, offset) & if bar.kind.is_io() { 0x3 } else { 0xf };
+        config_space.write(address, offset, start as u32 | flags);
+        if bar.is_64bit {
+            config_space.write(address, offset + 4, (start >> 32) as u32);
+        }
+    });
+
+    if let Some((_, bars)) = BAR_RESOURCES.write().iter_mut().find(|(known, _)| *known == address)
+        && let Some(known) = bars.iter_mut().find(|known| known.slot == bar.slot) {
+        known.start = start;
+    }
+}
+
+/// The size of an I/O BAR (`pci_types` only reports the port).
+fn io_bar_size(config_space: &ConfigurationSpace, address: PciAddress, slot: u8) -> u64 {
+    let offset = BAR_OFFSET + slot as u16 * 4;
+    let mask = without_decoding(config_space, address, || unsafe {
+        let original = config_space.read(address, offset);
+        config_space.write(address, offset, u32::MAX);
+        let mask = config_space.read(address, offset);
+        config_space.write(address, offset, original);
+        mask
+    });
+
+    // Nur die unteren 16 Bit sind auf x86 nutzbar
+    let mask = (mask & !0x3) as u16;
+    if mask == 0 { 0 } else { (!mask as u64) + 1 }
+}
+
+fn without_decoding<T>(config_space: &ConfigurationSpace, address: PciAddress, f: impl FnOnce() -> T) -> T {
+    let header = PciHeader::new(address);
+    let command = header.command(config_space);
+    header.update_command(config_space, |command| command - CommandRegister::IO_ENABLE - CommandRegister::MEMORY_ENABLE);
+    let result = f();
+    header.update_command(config_space, |_| command);
+    result
+}
+
+/// IDE controllers in compatibility mode decode the legacy ports and ignore their first four BARs.
+fn is_legacy_ide(config_space: &ConfigurationSpace, device: &EndpointHeader) -> bool {
+    let (_, base_class, sub_class, interface) = device.header().revision_and_class(config_space);
+    base_class == 0x01 && sub_class == 0x01 && interface & 0x05 != 0x05
+}
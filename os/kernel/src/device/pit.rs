@@ -2,13 +2,35 @@ use alloc::boxed::Box;
 use alloc::sync::Arc;
 use crate::interrupt::interrupt_dispatcher::InterruptVector;
 use crate::interrupt::interrupt_handler::InterruptHandler;
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::arch::x86_64::_rdtsc;
+use core::hint::spin_loop;
+use core::sync::atomic::{fence, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use log::warn;
 use spin::Mutex;
 use x86_64::instructions::port::{Port, PortWriteOnly};
+use crate::device::hpet::{hpet, Hpet};
+use crate::device::pvclock::{pvclock, PvClock};
 use crate::{apic, interrupt_dispatcher};
 
 pub const BASE_FREQUENCY: usize = 1193182;
 const NANOSECONDS_PER_TICK: usize = 1000000000 / BASE_FREQUENCY;
+/// If more time has passed between two timer interrupts (according to the pvclock),
+/// the guest has been paused and this time is not added to the system time.
+const PAUSE_THRESHOLD_NS: usize = 1000000000;
+const CMDLINE_CLOCK_SOURCE: &str = "clocksource=";
+
+/// Clock, from which the system time is taken. The timer interrupts always come from the PIT,
+/// but with a clock source other than the PIT, each interrupt advances the system time by the time,
+/// that has actually elapsed, and the system time is interpolated between two interrupts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ClockSource {
+    /// Only the timer interrupts are counted (millisecond resolution)
+    Pit = 0,
+    Hpet = 1,
+    /// Also detects, that the guest has been paused
+    Pvclock = 2,
+}
 
 #[derive(Copy, Clone)]
 #[allow(dead_code)]
@@ -54,6 +76,13 @@ pub struct Timer {
     registers: Mutex<Registers>,
     interval_ns: usize,
     systime_ns: AtomicUsize,
+    /// Time of the pvclock at the last timer interrupt (0 if there is no pvclock)
+    last_reference_ns: AtomicU64,
+    /// Time, during which the guest has been paused and which is not part of the system time
+    paused_ns: AtomicUsize,
+    clock_source: AtomicU8,
+    /// Odd, while the system time and the reference time are updated
+    sequence: AtomicUsize,
 }
 
 struct Registers {
@@ -72,6 +101,40 @@ struct TimerInterruptHandler {
     timer: Arc<Timer>,
 }
 
+impl ClockSource {
+    /// Choose the clock source given with `clocksource=pit|hpet|pvclock` on the kernel command line,
+    /// or otherwise the best one available (pvclock, then HPET).
+    pub fn choose(cmdline: Option<&str>) -> Self {
+        let requested = cmdline.and_then(|cmdline| {
+            cmdline.split_whitespace().find_map(|arg| arg.strip_prefix(CMDLINE_CLOCK_SOURCE))
+        });
+
+        match requested {
+            Some("pit") => return ClockSource::Pit,
+            Some("hpet") if hpet().is_some() => return ClockSource::Hpet,
+            Some("pvclock") if pvclock().is_some() => return ClockSource::Pvclock,
+            Some(name) => warn!("Clock source [{}] is not available", name),
+            None => {}
+        }
+
+        if pvclock().is_some() {
+            ClockSource::Pvclock
+        } else if hpet().is_some() {
+            ClockSource::Hpet
+        } else {
+            ClockSource::Pit
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => ClockSource::Hpet,
+            2 => ClockSource::Pvclock,
+            _ => ClockSource::Pit,
+        }
+    }
+}
+
 impl Command {
     pub const fn new(operating_mode: OperatingMode, access_mode: AccessMode) -> Self {
         Self {
@@ -116,7 +179,11 @@ impl Timer {
         let mut timer = Self {
             registers: Mutex::new(Registers::new()),
             interval_ns: 0,
-            systime_ns: AtomicUsize::new(0)
+            systime_ns: AtomicUsize::new(0),
+            last_reference_ns: AtomicU64::new(0),
+            paused_ns: AtomicUsize::new(0),
+            clock_source: AtomicU8::new(ClockSource::Pit as u8),
+            sequence: AtomicUsize::new(0),
         };
 
         timer.interrupt_rate(1);
@@ -160,12 +227,81 @@ impl Timer {
         apic().allow(InterruptVector::Pit);
     }
 
+    /// Time between two timer interrupts
+    pub fn interval_ns(&self) -> usize {
+        self.interval_ns
+    }
+
     pub fn systime_ms(&self) -> usize {
         self.systime_ns.load(Ordering::Relaxed) / 1000000
     }
 
+    /// System time in nanoseconds. With a clock source other than the PIT,
+    /// the time since the last timer interrupt is added, so the resolution is better than the timer interval.
+    pub fn systime_ns(&self) -> usize {
+        loop {
+            let sequence = self.sequence.load(Ordering::Acquire);
+            if sequence & 1 != 0 {
+                spin_loop();
+                continue;
+            }
+
+            let systime_ns = self.systime_ns.load(Ordering::Relaxed);
+            let last_reference_ns = self.last_reference_ns.load(Ordering::Relaxed);
+            let now = self.reference_ns();
+
+            fence(Ordering::Acquire);
+            if self.sequence.load(Ordering::Relaxed) != sequence {
+                continue;
+            }
+
+            return match now {
+                // a long gap is a pause, which is left out at the next interrupt
+                Some(now) if last_reference_ns != 0 => systime_ns + (now.saturating_sub(last_reference_ns) as usize).min(self.interval_ns),
+                _ => systime_ns,
+            };
+        }
+    }
+
+    pub fn clock_source(&self) -> ClockSource {
+        ClockSource::from_u8(self.clock_source.load(Ordering::Relaxed))
+    }
+
+    /// Take the system time from `source` (which must be available) from the next timer interrupt on.
+    pub fn select_clock_source(&self, source: ClockSource) {
+        self.clock_source.store(source as u8, Ordering::Relaxed);
+        self.last_reference_ns.store(0, Ordering::Relaxed);
+    }
+
+    /// Measure the frequency of the TSC (in ticks per millisecond) against the clock source.
+    pub fn calibrate_tsc(&self, duration_ms: usize) -> u64 {
+        let start_ns = self.reference_ns();
+        let start_tsc = unsafe { _rdtsc() };
+        self.wait(duration_ms);
+        let end_tsc = unsafe { _rdtsc() };
+
+        // waiting may take a bit longer, which only the clock sources other than the PIT can measure
+        let elapsed_ns = match (start_ns, self.reference_ns()) {
+            (Some(start), Some(end)) if end > start => end - start,
+            _ => (duration_ms * 1000000) as u64,
+        };
+        ((end_tsc - start_tsc) as u128 * 1000000 / elapsed_ns as u128) as u64
+    }
+
+    /// Total time, during which the guest has been paused (only detected with a pvclock)
+    pub fn paused_ms(&self) -> usize {
+        self.paused_ns.load(Ordering::Relaxed) / 1000000
+    }
+
     pub fn wait(&self, wait_time_ms: usize) {
         let wait_time_ns = wait_time_ms * 1000000;
+        if let Some(start_ns) = self.reference_ns() {
+            while self.reference_ns().is_some_and(|now| now.saturating_sub(start_ns) < wait_time_ns as u64) {
+                spin_loop();
+            }
+            return;
+        }
+
         let mut elapsed_time_ns = 0;
         let mut last_timer_value = self.read_timer();
 
@@ -184,7 +320,41 @@ impl Timer {
         }
     }
 
+    /// With a clock source other than the PIT, the system time advances by the time actually elapsed since the last
+    /// interrupt. This way, interrupts lost or re-injected after the guest has been paused (suspend/resume or
+    /// live migration) don't make the system time jump, which would make all timeouts expire at once.
+    /// Only the pvclock keeps running during a pause; this time is left out of the system time.
     fn inc_systime(&self) {
-        self.systime_ns.fetch_add(self.interval_ns, Ordering::Relaxed);
+        let now = self.reference_ns();
+        self.sequence.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        let elapsed_ns = match now {
+            Some(now) => {
+                let last = self.last_reference_ns.swap(now, Ordering::Relaxed);
+                let elapsed_ns = now.saturating_sub(last) as usize;
+                if last == 0 {
+                    self.interval_ns
+                } else if elapsed_ns > PAUSE_THRESHOLD_NS && self.clock_source() == ClockSource::Pvclock {
+                    self.paused_ns.fetch_add(elapsed_ns - self.interval_ns, Ordering::Relaxed);
+                    self.interval_ns
+                } else {
+                    elapsed_ns
+                }
+            }
+            None => self.interval_ns,
+        };
+
+        self.systime_ns.fetch_add(elapsed_ns, Ordering::Relaxed);
+        self.sequence.fetch_add(1, Ordering::Release);
     }
-}
\ No newline at end of file
+
+    /// Current time of the clock source (`None` for the PIT)
+    fn reference_ns(&self) -> Option<u64> {
+        match self.clock_source() {
+            ClockSource::Pit => None,
+            ClockSource::Hpet => hpet().map(Hpet::now_ns),
+            ClockSource::Pvclock => pvclock().map(PvClock::now_ns),
+        }
+    }
+}
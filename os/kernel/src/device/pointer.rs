@@ -0,0 +1,46 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: pointer                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Queue for the pointer events of all mice and tablets (PS/2 and virtio). ║
+   ║ Drivers decode their packets into `MouseEvent`s and push them from      ║
+   ║ their interrupt handlers; user space reads them with                    ║
+   ║ `SystemCall::MouseReadEvent`. If nobody reads the queue, the oldest     ║
+   ║ events are dropped.                                                     ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - push                 add an event (called by drivers)               ║
+   ║   - read                 get the next event (non-blocking)              ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use input::MouseEvent;
+use nolock::queues::{mpmc, DequeueError};
+use spin::Once;
+
+const BUFFER_CAPACITY: usize = 256;
+
+static EVENTS: Once<(mpmc::bounded::scq::Receiver<MouseEvent>, mpmc::bounded::scq::Sender<MouseEvent>)> = Once::new();
+
+fn events() -> &'static (mpmc::bounded::scq::Receiver<MouseEvent>, mpmc::bounded::scq::Sender<MouseEvent>) {
+    EVENTS.call_once(|| mpmc::bounded::scq::queue(BUFFER_CAPACITY))
+}
+
+/// Add `event` to the queue (may be called from interrupt handlers).
+pub fn push(event: MouseEvent) {
+    let (receiver, sender) = events();
+    while sender.try_enqueue(event).is_err() {
+        if receiver.try_dequeue().is_err() {
+            panic!("Pointer: Failed to store event in buffer!");
+        }
+    }
+}
+
+/// Get the next pointer event, if there is any.
+pub fn read() -> Option<MouseEvent> {
+    match events().0.try_dequeue() {
+        Ok(event) => Some(event),
+        Err(DequeueError::Closed) => panic!("Pointer event stream closed!"),
+        Err(DequeueError::Empty) => None,
+    }
+}
@@ -1,7 +1,10 @@
 use alloc::boxed::Box;
+use alloc::string::ToString;
 use alloc::sync::Arc;
+use alloc::vec;
 use crate::interrupt::interrupt_dispatcher::InterruptVector;
 use crate::interrupt::interrupt_handler::InterruptHandler;
+use input::MouseEvent;
 use stream::{DecodedInputStream, RawInputStream};
 use log::{debug, error, info};
 use nolock::queues::{DequeueError, mpmc};
@@ -12,10 +15,20 @@ use pc_keyboard::layouts::{AnyLayout, De105Key};
 use pc_keyboard::{DecodedKey, Error as PcError, HandleControl, KeyEvent, Keyboard as PcKeyboard, ScancodeSet1, ScancodeSet2};
 use spin::{Mutex, MutexGuard};
 use spin::once::Once;
+use system_info::devices::{DeviceClass, DeviceInfo, Resource};
+use crate::device::{keyboard_wait, pointer, registry};
 use crate::{apic, interrupt_dispatcher};
 
 const KEYBOARD_BUFFER_CAPACITY: usize = 128;
 const MOUSE_BUFFER_CAPACITY: usize = 128;
+/// Ports of the PS/2 controller (only used for the device registry, the `ps2` crate accesses them itself)
+const DATA_PORT: u64 = 0x60;
+const COMMAND_PORT: u64 = 0x64;
+
+/// Bits in the first byte of a mouse packet
+const PACKET_BUTTONS: u8 = 0x07;
+const PACKET_X_OVERFLOW: u8 = 0x40;
+const PACKET_Y_OVERFLOW: u8 = 0x80;
 
 pub struct PS2 {
     controller: Arc<Mutex<Controller>>,
@@ -83,6 +96,7 @@ impl Keyboard {
     pub fn plugin(keyboard: Arc<Keyboard>) {
         interrupt_dispatcher().assign(InterruptVector::Keyboard, Box::new(KeyboardInterruptHandler::new(Arc::clone(&keyboard))));
         apic().allow(InterruptVector::Keyboard);
+        register_device("ps2-keyboard", InterruptVector::Keyboard);
     }
     
     /// Parse a byte and get the next key event.
@@ -156,6 +170,7 @@ impl InterruptHandler for KeyboardInterruptHandler {
                         panic!("Keyboard: Failed to store received byte in buffer!");
                     }
                 }
+                keyboard_wait::notify();
             }
         } else {
             panic!("Keyboard: Controller is locked during interrupt!");
@@ -192,6 +207,41 @@ impl Mouse {
     pub fn plugin(mouse: Arc<Mouse>) {
         interrupt_dispatcher().assign(InterruptVector::Mouse, Box::new(MouseInterruptHandler::new(Arc::clone(&mouse))));
         apic().allow(InterruptVector::Mouse);
+        register_device("ps2-mouse", InterruptVector::Mouse);
+    }
+
+    /// Store a complete packet for `read()` and pass it (decoded) to the pointer event queue.
+    fn enqueue(&self, packet: u32) {
+        while self.buffer.1.try_enqueue(packet).is_err() {
+            if self.buffer.0.try_dequeue().is_err() {
+                panic!("Mouse: Failed to store received packet in buffer!");
+            }
+        }
+
+        pointer::push(self.decode(packet));
+    }
+
+    /// Convert a packet (flags, delta x, delta y and the optional fourth byte) into a pointer event.
+    fn decode(&self, packet: u32) -> MouseEvent {
+        let flags = packet as u8;
+        // Die Deltas sind 9-Bit-Zweierkomplement, die Vorzeichenbits stehen im ersten Byte
+        let mut dx = ((packet >> 8) & 0xff) as i32 - (((flags as i32) << 4) & 0x100);
+        let mut dy = ((packet >> 16) & 0xff) as i32 - (((flags as i32) << 3) & 0x100);
+        if flags & (PACKET_X_OVERFLOW | PACKET_Y_OVERFLOW) != 0 {
+            dx = 0;
+            dy = 0;
+        }
+
+        // the wheel movement is a 4 bit signed value, IntelliMouse Explorers report buttons 4 and 5 above it
+        let extra = (packet >> 24) as u8;
+        let wheel = ((extra << 4) as i8 >> 4) as i32;
+        let mut buttons = flags & PACKET_BUTTONS;
+        if self.mouse_type == MouseType::IntelliMouseExplorer {
+            buttons |= ((extra >> 4) & 0x3) << 3;
+        }
+
+        // PS/2 mice count upwards movement and scrolling towards the user as positive
+        MouseEvent { dx, dy: -dy, wheel: -wheel, buttons, ..MouseEvent::default() }
     }
 
     pub fn read(&self) -> Option<u32> {
@@ -246,12 +296,7 @@ impl InterruptHandler for MouseInterruptHandler {
                             || self.mouse.mouse_type == MouseType::IntelliMouseExplorer {
                             mouse_state.cycle += 1;
                         } else {
-                            // Enqueue the packet
-                            while self.mouse.buffer.1.try_enqueue(mouse_state.packet).is_err() {
-                                if self.mouse.buffer.0.try_dequeue().is_err() {
-                                    panic!("Mouse: Failed to store received packet in buffer!");
-                                }
-                            }
+                            self.mouse.enqueue(mouse_state.packet);
 
                             mouse_state.cycle = 0;
                         }
@@ -266,12 +311,7 @@ impl InterruptHandler for MouseInterruptHandler {
                             mouse_state.packet &= 0x0F_FF_FF_FF;
                         }
 
-                        // Enqueue the packet
-                        while self.mouse.buffer.1.try_enqueue(mouse_state.packet).is_err() {
-                            if self.mouse.buffer.0.try_dequeue().is_err() {
-                                panic!("Mouse: Failed to store received packet in buffer!");
-                            }
-                        }
+                        self.mouse.enqueue(mouse_state.packet);
 
                         mouse_state.cycle = 0;
                     }
@@ -296,7 +336,8 @@ impl PS2 {
         }
     }
 
-    pub fn init_controller(&self) -> Result<(), ControllerError> {
+    /// Initialize the controller and enable its ports. Returns whether the second port (for a mouse) is available.
+    pub fn init_controller(&self) -> Result<bool, ControllerError> {
         info!("   Initializing controller");
         let mut controller = self.controller.lock();
 
@@ -333,9 +374,9 @@ impl PS2 {
             info!("   First port enabled");
         }
 
-        // Check if mouse is present
-        let test_result = controller.test_mouse();
-        if test_result.is_ok() {
+        // Check if mouse is present (a missing mouse port must not prevent using the keyboard)
+        let mouse_present = controller.test_mouse().is_ok();
+        if mouse_present {
             // Enable mouse
             info!("Second port detected");
             controller.enable_mouse()?;
@@ -345,7 +386,7 @@ impl PS2 {
             info!("Second port enabled");
         }
 
-        test_result
+        Ok(mouse_present)
     }
 
     pub fn init_keyboard(&mut self) -> Result<(), KeyboardError> {
@@ -466,3 +507,15 @@ impl PS2 {
         }
     }
 }
+
+/// Make a device of the PS/2 controller visible in the device registry (both share the controller's ports).
+fn register_device(name: &str, vector: InterruptVector) {
+    registry::register(DeviceInfo {
+        name: name.to_string(),
+        class: DeviceClass::Input,
+        bus: "isa".to_string(),
+        parent: None,
+        driver: Some("ps2".to_string()),
+        resources: vec![Resource::Io { start: DATA_PORT, size: 1 }, Resource::Io { start: COMMAND_PORT, size: 1 }, Resource::Irq(vector as u8)],
+    });
+}
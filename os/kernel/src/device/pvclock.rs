@@ -0,0 +1,144 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: pvclock                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Paravirtualized clock of KVM (kvmclock). The hypervisor shares the      ║
+   ║ parameters for converting the TSC into nanoseconds via a page in guest  ║
+   ║ memory, which it updates whenever the TSC changes (e.g. on migration).  ║
+   ║ The host also sets a flag in this page, after the guest has been        ║
+   ║ paused, so time jumps can be detected.                                  ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - init                 detect and enable the clock                    ║
+   ║   - pvclock              get the clock (if running on KVM)              ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::arch::x86_64::{__cpuid, _rdtsc};
+use core::hint::spin_loop;
+use core::ptr::{addr_of, addr_of_mut};
+use core::sync::atomic::{fence, Ordering};
+use log::info;
+use spin::Once;
+use x86_64::registers::model_specific::Msr;
+use crate::memory;
+
+/// CPUID leaf identifying the hypervisor
+const CPUID_HYPERVISOR_SIGNATURE: u32 = 0x40000000;
+/// CPUID leaf with the paravirtualization features of KVM
+const CPUID_KVM_FEATURES: u32 = 0x40000001;
+const KVM_SIGNATURE: &[u8; 12] = b"KVMKVMKVM\0\0\0";
+const KVM_FEATURE_CLOCKSOURCE2: u32 = 1 << 3;
+/// Writing the physical address of `VcpuTimeInfo` (with bit 0 set) enables the clock
+const MSR_KVM_SYSTEM_TIME_NEW: u32 = 0x4b564d01;
+
+/// Set by the host, after the guest has been stopped (and cleared by the guest)
+const PVCLOCK_GUEST_STOPPED: u8 = 1 << 1;
+
+static PVCLOCK: Once<PvClock> = Once::new();
+
+/// Layout of `pvclock_vcpu_time_info`, as written by the hypervisor
+#[repr(C)]
+struct VcpuTimeInfo {
+    /// Odd while the host updates the structure
+    version: u32,
+    _pad0: u32,
+    tsc_timestamp: u64,
+    system_time: u64,
+    tsc_to_system_mul: u32,
+    tsc_shift: i8,
+    flags: u8,
+    _pad: [u8; 2],
+}
+
+pub struct PvClock {
+    info: *mut VcpuTimeInfo,
+}
+
+// The page is only written by the hypervisor, reads retry until they are consistent
+unsafe impl Send for PvClock {}
+unsafe impl Sync for PvClock {}
+
+/// Enable the paravirtualized clock, if the hypervisor is KVM and supports it.
+pub fn init() {
+    if !kvm_clocksource_available() {
+        return;
+    }
+
+    // Kernel memory is identity mapped, so the physical address can be used directly
+    let frame = memory::alloc_frames(1).start.start_address();
+    let info = frame.as_u64() as *mut VcpuTimeInfo;
+    unsafe {
+        info.write_bytes(0, 1);
+        Msr::new(MSR_KVM_SYSTEM_TIME_NEW).write(frame.as_u64() | 1);
+    }
+
+    let clock = PVCLOCK.call_once(|| PvClock { info });
+    info!("KVM paravirtualized clock enabled (system time: [{} ms])", clock.now_ns() / 1000000);
+}
+
+/// Get the paravirtualized clock, if it has been enabled.
+pub fn pvclock() -> Option<&'static PvClock> {
+    PVCLOCK.get()
+}
+
+fn kvm_clocksource_available() -> bool {
+    let signature = unsafe { __cpuid(CPUID_HYPERVISOR_SIGNATURE) };
+    let mut vendor = [0u8; 12];
+    vendor[0..4].copy_from_slice(&signature.ebx.to_le_bytes());
+    vendor[4..8].copy_from_slice(&signature.ecx.to_le_bytes());
+    vendor[8..12].copy_from_slice(&signature.edx.to_le_bytes());
+    if &vendor != KVM_SIGNATURE || signature.eax < CPUID_KVM_FEATURES {
+        return false;
+    }
+
+    let features = unsafe { __cpuid(CPUID_KVM_FEATURES) };
+    features.eax & KVM_FEATURE_CLOCKSOURCE2 != 0
+}
+
+impl PvClock {
+    /// Time of the host (in nanoseconds), which keeps running while the guest is paused.
+    pub fn now_ns(&self) -> u64 {
+        loop {
+            let version = unsafe { addr_of!((*self.info).version).read_volatile() };
+            if version & 1 != 0 {
+                spin_loop();
+                continue;
+            }
+            fence(Ordering::Acquire);
+
+            let (tsc_timestamp, system_time, mul, shift) = unsafe {
+                (
+                    addr_of!((*self.info).tsc_timestamp).read_volatile(),
+                    addr_of!((*self.info).system_time).read_volatile(),
+                    addr_of!((*self.info).tsc_to_system_mul).read_volatile(),
+                    addr_of!((*self.info).tsc_shift).read_volatile(),
+                )
+            };
+            let tsc = unsafe { _rdtsc() };
+
+            fence(Ordering::Acquire);
+            if unsafe { addr_of!((*self.info).version).read_volatile() } != version {
+                continue;
+            }
+
+            let mut delta = tsc.wrapping_sub(tsc_timestamp);
+            if shift >= 0 {
+                delta <<= shift;
+            } else {
+                delta >>= -shift;
+            }
+            return system_time + ((delta as u128 * mul as u128) >> 32) as u64;
+        }
+    }
+
+    /// Check (and clear) the flag, that the host sets after the guest has been paused.
+    pub fn take_guest_stopped(&self) -> bool {
+        let flags = unsafe { addr_of_mut!((*self.info).flags) };
+        let stopped = unsafe { flags.read_volatile() } & PVCLOCK_GUEST_STOPPED != 0;
+        if stopped {
+            unsafe { flags.write_volatile(flags.read_volatile() & !PVCLOCK_GUEST_STOPPED) };
+        }
+        stopped
+    }
+}
@@ -1,8 +1,12 @@
-use crate::device::qemu_cfg::Selector::Signature;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::device::qemu_cfg::Selector::{RootDirectory, Signature};
 use x86_64::instructions::port::{PortReadOnly, PortWriteOnly};
 
 const SELECTOR_PORT: u16 = 0x510;
 const DATA_PORT: u16 = 0x511;
+/// Length of a file name in the file directory (including the terminating null byte)
+const FILE_NAME_LENGTH: usize = 56;
 
 #[allow(dead_code)]
 #[repr(u16)]
@@ -29,3 +33,49 @@ pub fn is_available() -> bool {
 
     id[0] == b'Q' && id[1] == b'E' && id[2] == b'M' && id[3] == b'U'
 }
+
+/// Read the file called `name` (e.g. passed to QEMU with `-fw_cfg name=opt/...,string=...`).
+/// Returns `None`, if QEMU is not available or there is no such file.
+pub fn read_file(name: &str) -> Option<Vec<u8>> {
+    if !is_available() {
+        return None;
+    }
+
+    // all numbers in the file directory are big endian
+    select(RootDirectory as u16);
+    let count = u32::from_be_bytes(read_array());
+    for _ in 0..count {
+        let size = u32::from_be_bytes(read_array());
+        let selector = u16::from_be_bytes(read_array());
+        let _reserved: [u8; 2] = read_array();
+        let file_name: [u8; FILE_NAME_LENGTH] = read_array();
+
+        let length = file_name.iter().position(|b| *b == 0).unwrap_or(FILE_NAME_LENGTH);
+        if &file_name[..length] == name.as_bytes() {
+            select(selector);
+            let mut data = vec![0; size as usize];
+            read(&mut data);
+            return Some(data);
+        }
+    }
+
+    None
+}
+
+fn select(selector: u16) {
+    let mut selector_port = PortWriteOnly::<u16>::new(SELECTOR_PORT);
+    unsafe { selector_port.write(selector) };
+}
+
+fn read(buf: &mut [u8]) {
+    let mut data_port = PortReadOnly::<u8>::new(DATA_PORT);
+    for b in buf.iter_mut() {
+        *b = unsafe { data_port.read() };
+    }
+}
+
+fn read_array<const N: usize>() -> [u8; N] {
+    let mut buf = [0; N];
+    read(&mut buf);
+    buf
+}
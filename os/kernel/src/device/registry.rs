@@ -0,0 +1,184 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: registry                                                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Central list of all devices. Buses register the devices they    ║
+   ║         have found (e.g. every PCI function with its BARs), drivers     ║
+   ║         claim them with 'bind' and register the devices they provide    ║
+   ║         (e.g. drives or input devices) as children. Devices are kept in ║
+   ║         the order of registration, so parents come before children and  ║
+   ║         can be shut down after them. User space reads the list with the ║
+   ║         'Devices' syscall.                                              ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - register             add a device                                   ║
+   ║   - unregister           remove a device and all of its children        ║
+   ║   - bind                 record the driver, that has claimed a device   ║
+   ║   - add_resource         record a resource assigned to a device         ║
+   ║   - device               get a single device                            ║
+   ║   - devices              get all devices                                ║
+   ║   - serialize            serialize all devices for user space           ║
+   ║   - pci_name             name of a PCI function in the registry         ║
+   ║   - register_pci         register new functions and bridges of the bus  ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use log::warn;
+use pci_types::{EndpointHeader, PciAddress};
+use spin::RwLock;
+use system_info::devices::{self, DeviceClass, DeviceInfo, Resource};
+use super::pci::{ConfigurationSpace, PciBridge};
+use super::pci_resources::{self, ResourceKind};
+
+/// PCI base classes, that are mapped to device classes
+const PCI_CLASS_STORAGE: u8 = 0x01;
+const PCI_CLASS_NETWORK: u8 = 0x02;
+const PCI_CLASS_DISPLAY: u8 = 0x03;
+const PCI_CLASS_MULTIMEDIA: u8 = 0x04;
+const PCI_CLASS_BRIDGE: u8 = 0x06;
+const PCI_CLASS_COMMUNICATION: u8 = 0x07;
+const PCI_CLASS_INPUT: u8 = 0x09;
+const PCI_CLASS_SERIAL_BUS: u8 = 0x0c;
+/// Multimedia subclasses for audio devices (audio controller and HD audio)
+const PCI_SUBCLASS_AUDIO: u8 = 0x01;
+const PCI_SUBCLASS_HDA: u8 = 0x03;
+
+static DEVICES: RwLock<Vec<DeviceInfo>> = RwLock::new(Vec::new());
+
+/// Add `device`. Its parent (if any) should have been registered before.
+pub fn register(device: DeviceInfo) {
+    let mut devices = DEVICES.write();
+    if devices.iter().any(|known| known.name == device.name) {
+        warn!("Device [{}] is already registered", device.name);
+        return;
+    }
+    if let Some(parent) = &device.parent && !devices.iter().any(|known| known.name == *parent) {
+        warn!("Parent [{}] of device [{}] is not registered", parent, device.name);
+    }
+
+    devices.push(device);
+}
+
+/// Remove the device called `name` and all devices attached to it (e.g. when a controller has been removed).
+pub fn unregister(name: &str) {
+    let mut devices = DEVICES.write();
+    let mut removed = Vec::from([name.to_string()]);
+    while let Some(name) = removed.pop() {
+        devices.retain(|device| {
+            if device.parent.as_ref() == Some(&name) {
+                removed.push(device.name.clone());
+            }
+            device.name != name
+        });
+    }
+}
+
+/// Record, that `driver` has claimed the device called `name`.
+pub fn bind(name: &str, driver: &str) {
+    match DEVICES.write().iter_mut().find(|device| device.name == name) {
+        Some(device) => {
+            if let Some(bound) = &device.driver {
+                warn!("Device [{}] is already claimed by [{}], rebinding to [{}]", name, bound, driver);
+            }
+            device.driver = Some(driver.to_string());
+        }
+        None => warn!("Trying to bind unknown device [{}] to [{}]", name, driver),
+    }
+}
+
+/// Record, that `resource` has been assigned to the device called `name` (e.g. an interrupt vector).
+pub fn add_resource(name: &str, resource: Resource) {
+    if let Some(device) = DEVICES.write().iter_mut().find(|device| device.name == name) {
+        device.resources.push(resource);
+    }
+}
+
+pub fn device(name: &str) -> Option<DeviceInfo> {
+    DEVICES.read().iter().find(|device| device.name == name).cloned()
+}
+
+/// All devices in the order of registration (parents before their children).
+pub fn devices() -> Vec<DeviceInfo> {
+    DEVICES.read().clone()
+}
+
+/// Serialize all devices (see `system_info::devices` for the format).
+pub fn serialize() -> String {
+    let mut out = String::new();
+    for device in DEVICES.read().iter() {
+        devices::write_entry(&mut out, device);
+    }
+
+    out
+}
+
+/// Name of the PCI function at `address` (e.g. "0000:00:03.0").
+pub fn pci_name(address: PciAddress) -> String {
+    format!("{:04x}:{:02x}:{:02x}.{:x}", address.segment(), address.bus(), address.device(), address.function())
+}
+
+/// Register the bridges and functions found on the PCI bus, that are not registered yet
+/// (called by the bus after the BARs have been assigned, during the first scan and after rescans).
+pub fn register_pci(config_space: &ConfigurationSpace, functions: &[&RwLock<EndpointHeader>], bridges: &[PciBridge]) {
+    // Brücken werden erst nach den Geräten dahinter gefunden, die Busnummern steigen aber nach außen hin an
+    let mut sorted_bridges = bridges.to_vec();
+    sorted_bridges.sort_by_key(|bridge| (bridge.address.segment(), bridge.secondary_bus));
+    for bridge in sorted_bridges.iter().filter(|bridge| device(&pci_name(bridge.address)).is_none()) {
+        register(DeviceInfo {
+            name: pci_name(bridge.address),
+            class: DeviceClass::Bus,
+            bus: "pci".to_string(),
+            parent: pci_parent(bridges, bridge.address),
+            driver: None,
+            resources: Vec::new(),
+        });
+    }
+
+    for function in functions.iter() {
+        let function = function.read();
+        if device(&pci_name(function.header().address())).is_some() {
+            continue;
+        }
+        let (_, base_class, sub_class, _) = function.header().revision_and_class(config_space);
+        let resources = pci_resources::bars(config_space, &function)
+            .into_iter()
+            .filter(|bar| bar.start != 0)
+            .map(|bar| match bar.kind {
+                ResourceKind::Io => Resource::Io { start: bar.start, size: bar.size },
+                ResourceKind::Memory | ResourceKind::Prefetchable => Resource::Memory { start: bar.start, size: bar.size },
+            })
+            .collect();
+
+        register(DeviceInfo {
+            name: pci_name(function.header().address()),
+            class: pci_class(base_class, sub_class),
+            bus: "pci".to_string(),
+            parent: pci_parent(bridges, function.header().address()),
+            driver: None,
+            resources,
+        });
+    }
+}
+
+/// The bridge in front of the bus of `address` (`None` for root buses)
+fn pci_parent(bridges: &[PciBridge], address: PciAddress) -> Option<String> {
+    bridges.iter()
+        .find(|bridge| bridge.address.segment() == address.segment() && bridge.secondary_bus == address.bus())
+        .map(|bridge| pci_name(bridge.address))
+}
+
+fn pci_class(base_class: u8, sub_class: u8) -> DeviceClass {
+    match base_class {
+        PCI_CLASS_STORAGE => DeviceClass::Storage,
+        PCI_CLASS_NETWORK => DeviceClass::Network,
+        PCI_CLASS_DISPLAY => DeviceClass::Display,
+        PCI_CLASS_MULTIMEDIA if sub_class == PCI_SUBCLASS_AUDIO || sub_class == PCI_SUBCLASS_HDA => DeviceClass::Audio,
+        PCI_CLASS_BRIDGE | PCI_CLASS_SERIAL_BUS => DeviceClass::Bus,
+        PCI_CLASS_COMMUNICATION => DeviceClass::Serial,
+        PCI_CLASS_INPUT => DeviceClass::Input,
+        _ => DeviceClass::Other,
+    }
+}
@@ -0,0 +1,213 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: rtc                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Driver for the real time clock in the CMOS (MC146818). The      ║
+   ║         registers are read until two consecutive reads match, outside   ║
+   ║         of an update cycle. BCD and 12 hour formats are converted. The  ║
+   ║         century is taken from the century register, if the FADT names   ║
+   ║         one. The RTC is expected to hold UTC (QEMU uses local time with ║
+   ║         `-rtc base=localtime`, which then shows up as UTC).             ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - read                 read the date and time                         ║
+   ║   - write                set the date and time                          ║
+   ║   - write_count          number of times the RTC has been set           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use acpi::fadt::Fadt;
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::{Mutex, Once};
+use x86_64::instructions::port::{Port, PortWriteOnly};
+use crate::acpi_tables;
+
+const ADDRESS_PORT: u16 = 0x70;
+const DATA_PORT: u16 = 0x71;
+
+const REGISTER_SECONDS: u8 = 0x00;
+const REGISTER_MINUTES: u8 = 0x02;
+const REGISTER_HOURS: u8 = 0x04;
+const REGISTER_DAY: u8 = 0x07;
+const REGISTER_MONTH: u8 = 0x08;
+const REGISTER_YEAR: u8 = 0x09;
+const REGISTER_STATUS_A: u8 = 0x0a;
+const REGISTER_STATUS_B: u8 = 0x0b;
+
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 0x80;
+/// Stops the update cycles while the clock is set
+const STATUS_B_SET: u8 = 0x80;
+const STATUS_B_24_HOUR: u8 = 0x02;
+const STATUS_B_BINARY: u8 = 0x04;
+/// Set in the hours register for PM in 12 hour mode
+const HOUR_PM: u8 = 0x80;
+
+/// An update cycle takes less than 2 ms, so this is only reached without a working RTC
+const MAX_UPDATE_POLLS: usize = 100_000;
+const MAX_READ_ATTEMPTS: usize = 10;
+
+static CMOS: Mutex<Cmos> = Mutex::new(Cmos::new());
+/// CMOS register holding the century (0 = none)
+static CENTURY_REGISTER: Once<u8> = Once::new();
+static WRITE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+struct Cmos {
+    address: PortWriteOnly<u8>,
+    data: Port<u8>,
+}
+
+/// Raw values of the date and time registers
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Registers {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+impl Cmos {
+    const fn new() -> Self {
+        Self { address: PortWriteOnly::new(ADDRESS_PORT), data: Port::new(DATA_PORT) }
+    }
+
+    // Bit 7 der Adresse bleibt frei, sonst wären NMIs (und damit der Watchdog) abgeschaltet
+    fn read(&mut self, register: u8) -> u8 {
+        unsafe {
+            self.address.write(register);
+            self.data.read()
+        }
+    }
+
+    fn write(&mut self, register: u8, value: u8) {
+        unsafe {
+            self.address.write(register);
+            self.data.write(value);
+        }
+    }
+
+    fn wait_for_update(&mut self) -> bool {
+        (0..MAX_UPDATE_POLLS).any(|_| self.read(REGISTER_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS == 0)
+    }
+
+    fn read_registers(&mut self, century_register: u8) -> Registers {
+        Registers {
+            seconds: self.read(REGISTER_SECONDS),
+            minutes: self.read(REGISTER_MINUTES),
+            hours: self.read(REGISTER_HOURS),
+            day: self.read(REGISTER_DAY),
+            month: self.read(REGISTER_MONTH),
+            year: self.read(REGISTER_YEAR),
+            century: if century_register != 0 { self.read(century_register) } else { 0 },
+        }
+    }
+}
+
+/// Read the date and time from the RTC.
+/// Returns `None`, if the RTC does not finish its update cycle or holds an invalid date.
+pub fn read() -> Option<NaiveDateTime> {
+    let century_register = century_register();
+    let mut cmos = CMOS.lock();
+
+    // a read may overlap an update cycle, so the registers are read until they are stable
+    let mut registers = None;
+    for _ in 0..MAX_READ_ATTEMPTS {
+        if !cmos.wait_for_update() {
+            return None;
+        }
+        let current = cmos.read_registers(century_register);
+        if registers == Some(current) {
+            break;
+        }
+        registers = Some(current);
+    }
+    let registers = registers?;
+    let status_b = cmos.read(REGISTER_STATUS_B);
+    drop(cmos);
+
+    let binary = status_b & STATUS_B_BINARY != 0;
+    let decode = |value: u8| if binary { value } else { from_bcd(value) };
+
+    let pm = registers.hours & HOUR_PM != 0;
+    let mut hours = decode(registers.hours & !HOUR_PM);
+    if status_b & STATUS_B_24_HOUR == 0 {
+        // 12 hour mode: 12 AM is midnight, 12 PM is noon
+        hours %= 12;
+        if pm {
+            hours += 12;
+        }
+    }
+
+    let year = decode(registers.year) as i32;
+    let year = if century_register != 0 {
+        decode(registers.century) as i32 * 100 + year
+    } else if year < 70 {
+        2000 + year
+    } else {
+        1900 + year
+    };
+
+    NaiveDate::from_ymd_opt(year, decode(registers.month) as u32, decode(registers.day) as u32)?
+        .and_hms_opt(hours as u32, decode(registers.minutes) as u32, decode(registers.seconds) as u32)
+}
+
+/// Set the RTC to `time` (fractions of a second are dropped).
+/// Returns `false`, if the year can't be stored in the RTC.
+pub fn write(time: &NaiveDateTime) -> bool {
+    let century_register = century_register();
+    let year = time.year();
+    let representable = if century_register != 0 { (0..10000).contains(&year) } else { (1970..2070).contains(&year) };
+    if !representable {
+        return false;
+    }
+
+    let mut cmos = CMOS.lock();
+    let status_b = cmos.read(REGISTER_STATUS_B);
+    let binary = status_b & STATUS_B_BINARY != 0;
+    let encode = |value: u32| if binary { value as u8 } else { to_bcd(value as u8) };
+
+    let hours = if status_b & STATUS_B_24_HOUR != 0 {
+        encode(time.hour())
+    } else {
+        let (pm, hour) = time.hour12();
+        encode(hour) | if pm { HOUR_PM } else { 0 }
+    };
+
+    cmos.write(REGISTER_STATUS_B, status_b | STATUS_B_SET);
+    cmos.write(REGISTER_SECONDS, encode(time.second()));
+    cmos.write(REGISTER_MINUTES, encode(time.minute()));
+    cmos.write(REGISTER_HOURS, hours);
+    cmos.write(REGISTER_DAY, encode(time.day()));
+    cmos.write(REGISTER_MONTH, encode(time.month()));
+    cmos.write(REGISTER_YEAR, encode(year as u32 % 100));
+    if century_register != 0 {
+        cmos.write(century_register, encode(year as u32 / 100));
+    }
+    cmos.write(REGISTER_STATUS_B, status_b & !STATUS_B_SET);
+    WRITE_COUNT.fetch_add(1, Ordering::Relaxed);
+
+    true
+}
+
+/// Number of times the RTC has been set, so readers can tell a changed time from a time jump.
+pub fn write_count() -> usize {
+    WRITE_COUNT.load(Ordering::Relaxed)
+}
+
+fn century_register() -> u8 {
+    *CENTURY_REGISTER.call_once(|| match acpi_tables().lock().find_table::<Fadt>() {
+        Ok(fadt) => fadt.get().century,
+        Err(_) => 0,
+    })
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0f)
+}
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
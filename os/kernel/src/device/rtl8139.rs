@@ -5,15 +5,16 @@ use core::ops::BitOr;
 use core::{ptr, slice};
 use core::alloc::{AllocError, Allocator, Layout};
 use core::ptr::NonNull;
-use core::sync::atomic::{AtomicU8, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use bitflags::bitflags;
-use log::info;
+use log::{error, info, warn};
 use nolock::queues::{mpmc, mpsc};
-use pci_types::{CommandRegister, EndpointHeader};
+use pci_types::{CommandRegister, EndpointHeader, PciAddress};
 use smoltcp::phy;
 use smoltcp::phy::{DeviceCapabilities, Medium};
 use smoltcp::time::Instant;
 use smoltcp::wire::EthernetAddress;
+use syscall::event::EventSource;
 use spin::{Mutex, RwLock};
 use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
 use x86_64::structures::paging::{Page, PageTableFlags, PhysFrame};
@@ -21,11 +22,14 @@ use x86_64::structures::paging::frame::PhysFrameRange;
 use x86_64::structures::paging::page::PageRange;
 use x86_64::{PhysAddr, VirtAddr};
 
-use crate::{apic, interrupt_dispatcher, pci_bus, process_manager, scheduler};
-use crate::interrupt::interrupt_dispatcher::InterruptVector;
+use crate::{network, pci_bus, process_manager, scheduler};
+use crate::device::registry;
+use crate::device::stats::{self, DeviceStats};
+use crate::device::pci_irq::{self, IrqTypes};
 use crate::interrupt::interrupt_handler::InterruptHandler;
 use crate::memory::PAGE_SIZE;
 use crate::memory;
+use crate::sync::event;
 
 // Maximum Ethernet frame size without FCS
 const MAX_ETHERNET_FRAME_SIZE: usize = 1514;
@@ -35,6 +39,10 @@ const MIN_ETHERNET_FRAME_SIZE: usize = 60;
 const BUFFER_SIZE: usize = 8 * 1024 + 16 + MAX_ETHERNET_FRAME_SIZE + 4;
 const BUFFER_PAGES: usize = if BUFFER_SIZE % PAGE_SIZE == 0 { BUFFER_SIZE / PAGE_SIZE } else { BUFFER_SIZE / PAGE_SIZE + 1 };
 const RECV_QUEUE_CAP: usize = 16;
+// Counters registered with the device statistics framework
+const STATS_COUNTERS: &[&str] = &["rx_packets", "rx_bytes", "rx_dropped", "rx_errors", "rx_overruns", "tx_packets", "tx_bytes", "tx_errors"];
+// The RTL8139 can match up to 8 wake-up frames, each with a 64-bit byte mask and the LSB of the CRC
+const WAKEUP_FRAMES: usize = 8;
 
 bitflags! {
     pub struct Command: u8 {
@@ -45,6 +53,25 @@ bitflags! {
     }
 }
 
+bitflags! {
+    pub struct Eeprom9346Command: u8 {
+        const CONFIG_WRITE_ENABLE = 0xc0;
+    }
+}
+
+bitflags! {
+    pub struct Config1: u8 {
+        const POWER_MANAGEMENT_ENABLE = 0x01;
+    }
+}
+
+bitflags! {
+    pub struct Config3: u8 {
+        const LINK_UP = 0x10;
+        const MAGIC = 0x20;
+    }
+}
+
 bitflags! {
     pub struct Interrupt: u16 {
         const RECEIVE_OK = 0x0001;
@@ -127,17 +154,29 @@ struct Registers {
     interrupt_mask: PortWriteOnly<u16>,
     interrupt_status: Mutex<Port<u16>>,
     receive_configuration: PortWriteOnly<u32>,
-    config1: PortWriteOnly<u8>,
+    eeprom_command: Mutex<Port<u8>>,
+    config1: Mutex<Port<u8>>,
+    config3: Mutex<Port<u8>>,
+    wakeup_frames: Mutex<[WakeupFrame; WAKEUP_FRAMES]>,
+}
+
+struct WakeupFrame {
+    mask: (PortWriteOnly<u32>, PortWriteOnly<u32>),
+    crc: PortWriteOnly<u8>,
 }
 
 pub struct Rtl8139 {
     registers: Registers,
     transmit_index: AtomicU8,
-    interrupt: InterruptVector,
     recv_buffer: Mutex<ReceiveBuffer>,
     send_queue: (Mutex<mpsc::jiffy::Receiver<PhysFrameRange>>, mpsc::jiffy::Sender<PhysFrameRange>),
     recv_buffers_empty: (mpmc::bounded::scq::Receiver<Vec<u8, PacketAllocator>>, mpmc::bounded::scq::Sender<Vec<u8, PacketAllocator>>),
-    recv_messages: (mpmc::bounded::scq::Receiver<Vec<u8, PacketAllocator>>, mpmc::bounded::scq::Sender<Vec<u8, PacketAllocator>>)
+    recv_messages: (mpmc::bounded::scq::Receiver<Vec<u8, PacketAllocator>>, mpmc::bounded::scq::Sender<Vec<u8, PacketAllocator>>),
+    stats: Arc<DeviceStats>,
+    /// Set when the device has disappeared from the bus (e.g. hot-unplugged).
+    removed: AtomicBool,
+    /// Address of the PCI function (used to recognize the device's removal when rescanning the bus)
+    pci_address: PciAddress,
 }
 
 pub struct Rtl8139InterruptHandler {
@@ -166,7 +205,21 @@ impl Registers {
             interrupt_mask: PortWriteOnly::new(base_address + 0x3c),
             interrupt_status: Mutex::new(Port::new(base_address + 0x3e)),
             receive_configuration: PortWriteOnly::new(base_address + 0x44),
-            config1: PortWriteOnly::new(base_address + 0x52),
+            eeprom_command: Mutex::new(Port::new(base_address + 0x50)),
+            config1: Mutex::new(Port::new(base_address + 0x52)),
+            config3: Mutex::new(Port::new(base_address + 0x59)),
+            wakeup_frames: Mutex::new(core::array::from_fn(|index| WakeupFrame::new(base_address, index as u8))),
+        }
+    }
+}
+
+impl WakeupFrame {
+    fn new(base_address: u16, index: u8) -> Self {
+        assert!((index as usize) < WAKEUP_FRAMES, "Wake-up frame index out of bounds!");
+
+        Self {
+            mask: (PortWriteOnly::new(base_address + 0x84 + index as u16 * 8), PortWriteOnly::new(base_address + 0x88 + index as u16 * 8)),
+            crc: PortWriteOnly::new(base_address + 0xc4 + index as u16),
         }
     }
 }
@@ -261,6 +314,7 @@ impl<'a> phy::TxToken for Rtl8139TxToken<'a> {
         // Let smoltcp write the packet data to the buffer
         let buffer = unsafe { slice::from_raw_parts_mut(phys_buffer.start.start_address().as_u64() as *mut u8, tx_len) };
         let result = f(&mut buffer[0..len]);
+        network::pmtu::process_transmitted_frame(&mut buffer[0..len]);
 
         // Zero-pad the rest if necessary
         if len < MIN_ETHERNET_FRAME_SIZE {
@@ -281,6 +335,8 @@ impl<'a> phy::TxToken for Rtl8139TxToken<'a> {
             descriptor.address.write(phys_buffer.start.start_address().as_u64() as u32);
             descriptor.status.write(tx_len as u32);
         }
+        self.device.stats.inc("tx_packets");
+        self.device.stats.add("tx_bytes", tx_len as u64);
 
         result
     }
@@ -289,6 +345,7 @@ impl<'a> phy::TxToken for Rtl8139TxToken<'a> {
 impl<'a> phy::RxToken for Rtl8139RxToken<'a> {
     fn consume<R, F>(mut self, f: F) -> R
     where F: FnOnce(&[u8]) -> R {
+        network::pmtu::process_received_frame(&mut self.buffer);
         let result = f(&mut self.buffer);
         self.device.recv_buffers_empty.1.try_enqueue(self.buffer).expect("Failed to enqueue used receive buffer!");
 
@@ -309,6 +366,9 @@ impl phy::Device for Rtl8139 {
     }
 
     fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        if self.is_removed() {
+            return None;
+        }
         let device = unsafe { ptr::from_ref(self).as_ref()? };
         Some(Rtl8139TxToken::new(device))
     }
@@ -337,16 +397,35 @@ impl InterruptHandler for Rtl8139InterruptHandler {
 
         // Read interrupt status register (Each bit corresponds to an interrupt type or error)
         let mut status_reg = self.device.registers.interrupt_status.lock();
-        let status = Interrupt::from_bits_retain(unsafe { status_reg.read() });
+        let raw_status = unsafe { status_reg.read() };
+
+        // Reads from a device that is not present anymore return all ones.
+        // The network stack notices this and removes the interface
+        // (if a rescan of the PCI bus hasn't told it about the removal already).
+        if raw_status == 0xffff || self.device.is_removed() {
+            self.device.mark_removed();
+            return;
+        }
+
+        let status = Interrupt::from_bits_retain(raw_status);
 
-        // Check error flags
+        // Check error flags (the affected packet is lost, but the device keeps working)
         if status.contains(Interrupt::TRANSMIT_ERROR) {
-            panic!("Transmit failed!");
-        } else if status.contains(Interrupt::RECEIVE_ERROR) {
-            panic!("Receive failed!");
-        } else if status.contains(Interrupt::RX_BUFFER_OVERFLOW) {
+            self.device.stats.inc("tx_errors");
+            warn!("Transmit failed");
+        }
+        if status.contains(Interrupt::RECEIVE_ERROR) {
+            self.device.stats.inc("rx_errors");
+            warn!("Received a damaged packet");
+        }
+        if status.contains(Interrupt::RX_BUFFER_OVERFLOW) {
+            self.device.stats.inc("rx_overruns");
             info!("RX buffer overflow - Draining buffer");
         }
+        // also set on packet underruns, but a spurious link event is harmless
+        if status.contains(Interrupt::PACKET_UNDERRUN_LINK_CHANGE) {
+            event::notify(EventSource::Link);
+        }
 
         // Writing the status register clears all bits.
         // According to the RTL8139 documentation, this is not necessary,
@@ -354,8 +433,8 @@ impl InterruptHandler for Rtl8139InterruptHandler {
         // Furthermore, this needs to be done before processing the received packet (https://wiki.osdev.org/RTL8139).
         unsafe { status_reg.write(status.bits()); }
 
-        // Handle transmit by freeing allocated buffers
-        if status.contains(Interrupt::TRANSMIT_OK) && !memory::frame_allocator_locked() {
+        // Handle transmit by freeing allocated buffers (the buffer of a failed transmission is not needed anymore either)
+        if status.intersects(Interrupt::TRANSMIT_OK | Interrupt::TRANSMIT_ERROR) && !memory::frame_allocator_locked() {
             let mut queue = self.device.send_queue.0.lock();
             let mut buffer = queue.try_dequeue();
             while buffer.is_ok() {
@@ -388,7 +467,6 @@ impl Rtl8139 {
         let base_address = bar0.unwrap_io() as u16;
         info!("RTL8139 base address: [0x{base_address:x}]");
 
-        let interrupt = InterruptVector::try_from(pci_device.interrupt(pci_config_space).1 + 32).unwrap();
         let send_queue = mpsc::jiffy::queue();
 
         let kernel_process = process_manager().read().kernel_process().unwrap();
@@ -409,16 +487,19 @@ impl Rtl8139 {
         let mut rtl8139 = Self {
             registers: Registers::new(base_address),
             transmit_index: AtomicU8::new(0),
-            interrupt,
             recv_buffer: Mutex::new(ReceiveBuffer::new()),
             send_queue: (Mutex::new(send_queue.0), send_queue.1),
             recv_buffers_empty: recv_buffers,
-            recv_messages: mpmc::bounded::scq::queue(RECV_QUEUE_CAP)
+            recv_messages: mpmc::bounded::scq::queue(RECV_QUEUE_CAP),
+            stats: DeviceStats::new(STATS_COUNTERS),
+            removed: AtomicBool::new(false),
+            pci_address: pci_device.header().address(),
         };
+        stats::register("rtl8139", Arc::clone(&rtl8139.stats));
 
         unsafe {
             info!("Powering on device");
-            rtl8139.registers.config1.write(0x00);
+            rtl8139.registers.config1.lock().write(0x00);
 
             info!("Performing software reset");
             rtl8139.registers.command.lock().write(Command::RESET.bits());
@@ -429,7 +510,7 @@ impl Rtl8139 {
             }
 
             info!("Masking interrupts");
-            rtl8139.registers.interrupt_mask.write((Interrupt::RECEIVE_OK | Interrupt::RECEIVE_ERROR | Interrupt::TRANSMIT_OK | Interrupt::TRANSMIT_ERROR | Interrupt::RX_BUFFER_OVERFLOW).bits());
+            rtl8139.registers.interrupt_mask.write((Interrupt::RECEIVE_OK | Interrupt::RECEIVE_ERROR | Interrupt::TRANSMIT_OK | Interrupt::TRANSMIT_ERROR | Interrupt::RX_BUFFER_OVERFLOW | Interrupt::PACKET_UNDERRUN_LINK_CHANGE).bits());
 
             info!("Configuring receive buffer");
             rtl8139.registers.current_read_address.lock().write(0);
@@ -444,10 +525,52 @@ impl Rtl8139 {
         rtl8139
     }
 
-    pub fn plugin(device: Arc<Rtl8139>) {
-        let interrupt = device.interrupt;
-        interrupt_dispatcher().assign(device.interrupt, Box::new(Rtl8139InterruptHandler::new(device)));
-        apic().allow(interrupt);
+    /// Arm Wake-on-LAN before the system is powered down.
+    /// The device will wake the system, when it receives a magic packet (see `network::wol`).
+    /// All wake-up frame patterns are cleared, so no other frames wake the system.
+    pub fn enable_wake_on_lan(&self) {
+        info!("Enabling Wake-on-LAN (magic packet)");
+        let mut eeprom_command = self.registers.eeprom_command.lock();
+        let mut config1 = self.registers.config1.lock();
+        let mut config3 = self.registers.config3.lock();
+
+        unsafe {
+            // The configuration registers are write protected, unless the 93C46 command register is in config write mode
+            eeprom_command.write(Eeprom9346Command::CONFIG_WRITE_ENABLE.bits());
+
+            for frame in self.registers.wakeup_frames.lock().iter_mut() {
+                frame.mask.0.write(0);
+                frame.mask.1.write(0);
+                frame.crc.write(0);
+            }
+
+            let config3_value = Config3::from_bits_retain(config3.read()).difference(Config3::LINK_UP).union(Config3::MAGIC);
+            config3.write(config3_value.bits());
+            let config1_value = Config1::from_bits_retain(config1.read()).union(Config1::POWER_MANAGEMENT_ENABLE);
+            config1.write(config1_value.bits());
+
+            eeprom_command.write(0x00);
+        }
+    }
+
+    pub fn plugin(device: Arc<Rtl8139>, pci_device: &RwLock<EndpointHeader>) {
+        registry::bind(&registry::pci_name(pci_device.read().header().address()), "rtl8139");
+        if let Err(errno) = pci_irq::request_irq(pci_device, IrqTypes::all(), Box::new(Rtl8139InterruptHandler::new(device))) {
+            error!("Failed to set up the interrupt of the RTL8139 ({:?})", errno);
+        }
+    }
+
+    /// Mark the device as removed. It won't be used by the network stack anymore.
+    pub fn mark_removed(&self) {
+        self.removed.store(true, Ordering::Release);
+    }
+
+    pub fn is_removed(&self) -> bool {
+        self.removed.load(Ordering::Acquire)
+    }
+
+    pub fn pci_address(&self) -> PciAddress {
+        self.pci_address
     }
 
     pub fn read_mac_address(&self) -> EthernetAddress {
@@ -509,7 +632,15 @@ impl Rtl8139 {
                     let src = &recv_buffer.data[msg_start..msg_end];
                     target[0..src.len()].copy_from_slice(src);
 
-                    let _ = self.recv_messages.1.try_enqueue(target);
+                    match self.recv_messages.1.try_enqueue(target) {
+                        Ok(()) => {
+                            self.stats.inc("rx_packets");
+                            self.stats.add("rx_bytes", src.len() as u64);
+                        }
+                        Err(_) => self.stats.inc("rx_dropped"),
+                    }
+                } else {
+                    self.stats.inc("rx_dropped");
                 }
             }
         } else {
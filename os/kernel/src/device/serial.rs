@@ -3,15 +3,18 @@ use crate::device::serial::ComPort::{Com1, Com2, Com3, Com4};
 use crate::interrupt::interrupt_dispatcher::InterruptVector;
 use crate::interrupt::interrupt_handler::InterruptHandler;
 use stream::{DecodedInputStream, OutputStream};
-use alloc::string::String;
+use alloc::string::ToString;
 use alloc::sync::Arc;
+use alloc::vec;
 use core::ptr;
 use bitflags::bitflags;
 use log::info;
 use nolock::queues::mpmc::bounded::scq::{Receiver, Sender};
 use nolock::queues::{mpmc, DequeueError};
 use spin::Mutex;
+use system_info::devices::{DeviceClass, DeviceInfo, Resource};
 use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
+use crate::device::registry;
 use crate::{allocator, apic, interrupt_dispatcher, scheduler};
 
 #[allow(dead_code)]
@@ -146,6 +149,25 @@ bitflags! {
     }
 }
 
+bitflags! {
+    struct FifoControl: u8 {
+        const ENABLE = 0x01;
+        const CLEAR_RECEIVE = 0x02;
+        const CLEAR_TRANSMIT = 0x04;
+        /// raise the receive interrupt, once 14 bytes are in the FIFO (or after a timeout)
+        const TRIGGER_14_BYTES = 0xc0;
+    }
+}
+
+bitflags! {
+    struct ModemControl: u8 {
+        const DATA_TERMINAL_READY = 0x01;
+        const REQUEST_TO_SEND = 0x02;
+        /// connects the interrupt line of the UART to the interrupt controller
+        const OUT2 = 0x08;
+    }
+}
+
 bitflags! {
     struct InterruptStatus: u8 {
         const InterruptPending = 0x01;
@@ -165,6 +187,8 @@ struct Transceiver {
     transmit_buffer: Mutex<PortWriteOnly<u8>>,
     interrupt_control: Mutex<Port<u8>>,
     line_control: Mutex<Port<u8>>,
+    fifo_control: Mutex<PortWriteOnly<u8>>,
+    modem_control: Mutex<Port<u8>>,
     line_status: PortReadOnly<u8>,
 }
 
@@ -177,6 +201,8 @@ impl Transceiver {
             transmit_buffer: Mutex::new(PortWriteOnly::new(base)),
             interrupt_control: Mutex::new(Port::new(base + 1)),
             line_control: Mutex::new(Port::new(base + 3)),
+            fifo_control: Mutex::new(PortWriteOnly::new(base + 2)),
+            modem_control: Mutex::new(Port::new(base + 4)),
             line_status: PortReadOnly::new(base + 5),
         }
     }
@@ -214,6 +240,16 @@ impl Transceiver {
         unsafe { line_control.write(value.bits()) };
     }
 
+    fn fifo_control(&self, value: FifoControl) {
+        let mut fifo_control = self.fifo_control.lock();
+        unsafe { fifo_control.write(value.bits()) };
+    }
+
+    fn modem_control(&self, value: ModemControl) {
+        let mut modem_control = self.modem_control.lock();
+        unsafe { modem_control.write(value.bits()) };
+    }
+
     fn line_status(&self) -> LineStatus {
         unsafe {
             // Reading line status is always safe. However, PortReadOnly::read() needs a mutable reference.
@@ -271,17 +307,18 @@ pub fn check_port(port: ComPort) -> bool {
 }
 
 impl OutputStream for SerialPort {
+    /// Bytes are written as they are (not as characters), so UTF-8 output can be written byte by byte.
     fn write_byte(&self, b: u8) {
-        self.write_str(&String::from(char::from(b)));
+        if b == b'\n' {
+            self.transceiver.write(b'\r');
+        }
+
+        self.transceiver.write(b);
     }
 
     fn write_str(&self, string: &str) {
         for b in string.bytes() {
-            if b == b'\n' {
-                self.write_str("\r");
-            }
-
-            self.transceiver.write(b);
+            self.write_byte(b);
         }
     }
 }
@@ -340,7 +377,9 @@ impl SerialPort {
         transceiver.speed(speed);
         // the default: 8 bits, no parity, one stop bit
         transceiver.line_control(LineControl::DATA);
-        // TODO: set FIFO and modem control
+        transceiver.fifo_control(FifoControl::ENABLE | FifoControl::CLEAR_RECEIVE | FifoControl::CLEAR_TRANSMIT | FifoControl::TRIGGER_14_BYTES);
+        // without OUT2, the receive interrupts don't reach the interrupt controller
+        transceiver.modem_control(ModemControl::DATA_TERMINAL_READY | ModemControl::REQUEST_TO_SEND | ModemControl::OUT2);
 
         Self {
             port,
@@ -356,7 +395,8 @@ impl SerialPort {
         transceiver.speed(BaudRate::Baud115200);
         // the default: 8 bits, no parity, one stop bit
         transceiver.line_control(LineControl::DATA);
-        // TODO: set FIFO and modem control
+        transceiver.fifo_control(FifoControl::ENABLE | FifoControl::CLEAR_RECEIVE | FifoControl::CLEAR_TRANSMIT);
+        transceiver.modem_control(ModemControl::DATA_TERMINAL_READY | ModemControl::REQUEST_TO_SEND);
 
         Self {
             port,
@@ -372,6 +412,21 @@ impl SerialPort {
             Com2 | Com4 => InterruptVector::Com2,
         };
 
+        let name = match serial_port.port {
+            Com1 => "com1",
+            Com2 => "com2",
+            Com3 => "com3",
+            Com4 => "com4",
+        };
+        registry::register(DeviceInfo {
+            name: name.to_string(),
+            class: DeviceClass::Serial,
+            bus: "isa".to_string(),
+            parent: None,
+            driver: Some("serial".to_string()),
+            resources: vec![Resource::Io { start: serial_port.port as u64, size: 8 }, Resource::Irq(vector as u8)],
+        });
+
         serial_port.transceiver.interrupts(true);
         interrupt_dispatcher().assign(vector, Box::new(SerialInterruptHandler::new(serial_port)));
         apic().allow(vector);
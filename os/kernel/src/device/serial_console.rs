@@ -0,0 +1,135 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: serial_console                                                  ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Attaches the terminal (the tty buffers) to the serial port      ║
+   ║         instead of the framebuffer terminal emulator, so the shell can  ║
+   ║         be used over a serial line (e.g. QEMU's `-serial stdio`).       ║
+   ║         Enabled with `console=serial` on the kernel command line. A     ║
+   ║         kernel thread copies the output of applications to the serial   ║
+   ║         port and feeds received bytes through the line discipline into  ║
+   ║         the tty input. Input is only taken from the receive buffer,     ║
+   ║         while an application is reading, so typing ahead works. While   ║
+   ║         an application runs in the foreground, input is read ahead to   ║
+   ║         catch Ctrl+C, which interrupts the application. Kernel log      ║
+   ║         messages are still written to the same serial port.             ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - requested            check the kernel command line for the option   ║
+   ║   - start                start the console and the shell                ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::Arc;
+use log::{info, warn};
+use stream::{DecodedInputStream, OutputStream};
+use crate::device::line_discipline::LineDiscipline;
+use crate::device::tty::TtyInputState;
+use crate::process::thread::Thread;
+use crate::{scheduler, serial_port, tty_input, tty_output};
+use syscall::priority::Priority;
+
+const CMDLINE_OPTION: &str = "console=serial";
+/// How often the tty and the serial port are checked, if there has been nothing to do
+const POLL_INTERVAL_MS: usize = 10;
+const OUTPUT_CHUNK_SIZE: usize = 128;
+/// Ctrl+C interrupts the foreground application
+const INTERRUPT: u8 = 0x03;
+/// Wait before restarting the shell, so a shell crashing at startup doesn't flood the console
+const RESTART_DELAY_MS: usize = 1000;
+
+/// Check, whether `console=serial` is given on the kernel command line and a serial port is available.
+pub fn requested(cmdline: Option<&str>) -> bool {
+    let requested = cmdline.is_some_and(|cmdline| cmdline.split_whitespace().any(|arg| arg == CMDLINE_OPTION));
+    if requested && serial_port().is_none() {
+        warn!("Serial console requested, but there is no serial port");
+        return false;
+    }
+
+    requested
+}
+
+/// Start the console thread and the shell (must be called after the tty buffers have been initialized).
+pub fn start() {
+    let thread = Thread::new_kernel_thread(run, "serial_console");
+    thread.set_priority(Priority::High);
+    scheduler().ready(thread);
+    scheduler().ready(Thread::new_kernel_thread(operate, "serial_shell"));
+    info!("Shell available on the serial port");
+}
+
+extern "sysv64" fn run() {
+    let serial = serial_port().expect("Serial console started without a serial port");
+    let mut discipline = LineDiscipline::new();
+    let mut output = [0u8; OUTPUT_CHUNK_SIZE];
+    let mut typeahead = VecDeque::new();
+
+    loop {
+        let mut idle = true;
+
+        let count = tty_output().read(&mut output);
+        if count > 0 {
+            idle = false;
+            for byte in &output[..count] {
+                serial.write_byte(*byte);
+            }
+        }
+
+        // Eingaben bleiben im Empfangspuffer, solange keine Anwendung liest (oder im Vordergrund läuft)
+        let input = tty_input();
+        while input.state() == TtyInputState::Waiting || input.has_foreground() {
+            let Some(byte) = serial.decoded_try_read_byte() else {
+                break;
+            };
+            // the receive buffer has been closed
+            if byte < 0 {
+                return;
+            }
+            idle = false;
+
+            if byte as u8 == INTERRUPT {
+                typeahead.clear();
+                discipline.reset();
+                serial.write_str("^C\n");
+                input.interrupt();
+            } else {
+                typeahead.push_back(byte as u8);
+            }
+        }
+
+        while input.state() == TtyInputState::Waiting {
+            let Some(byte) = typeahead.pop_front() else {
+                break;
+            };
+
+            let mode = input.mode();
+            let mut echo = String::new();
+            let line = discipline.input(byte, mode, &mut echo);
+            serial.write_str(&echo);
+            if let Some(line) = line {
+                input.write(&line, mode);
+            }
+        }
+
+        if idle {
+            scheduler().sleep(POLL_INTERVAL_MS);
+        }
+    }
+}
+
+/// Run the shell and restart it, whenever it exits (like the terminal emulator does).
+extern "sysv64" fn operate() {
+    loop {
+        match Thread::load_application("/bin/shell", "shell", &[].to_vec()) {
+            Ok(thread) => {
+                scheduler().ready(Arc::clone(&thread));
+                thread.join();
+                info!("Restarting shell...");
+            }
+            Err(e) => warn!("Failed to start the shell on the serial console: {e:?}"),
+        }
+        scheduler().sleep(RESTART_DELAY_MS);
+    }
+}
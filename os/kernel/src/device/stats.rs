@@ -0,0 +1,109 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: stats                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Generic statistics for device drivers. Each driver creates a    ║
+   ║         set of named counters and registers it under its device name.   ║
+   ║         All registered counters can be read by user space with the      ║
+   ║         'DeviceStats' syscall, so drivers don't need to invent their    ║
+   ║         own reporting.                                                  ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use log::warn;
+use spin::RwLock;
+use system_info::device_stats;
+
+static DEVICES: RwLock<Vec<(String, Arc<DeviceStats>)>> = RwLock::new(Vec::new());
+
+/// A set of named counters belonging to a single device.
+/// The counters are atomic, so they can be updated from interrupt handlers without locking.
+pub struct DeviceStats {
+    counters: Vec<(&'static str, AtomicU64)>,
+}
+
+impl DeviceStats {
+    /// Create a new set of counters (all starting at 0). It is not visible to user space until it is registered.
+    pub fn new(names: &[&'static str]) -> Arc<Self> {
+        Arc::new(Self {
+            counters: names.iter().map(|name| (*name, AtomicU64::new(0))).collect(),
+        })
+    }
+
+    /// Add `value` to the counter called `name`.
+    pub fn add(&self, name: &str, value: u64) {
+        match self.counters.iter().find(|(counter, _)| *counter == name) {
+            Some((_, counter)) => { counter.fetch_add(value, Ordering::Relaxed); },
+            None => warn!("Trying to update unknown device counter [{name}]"),
+        }
+    }
+
+    /// Increment the counter called `name` by one.
+    pub fn inc(&self, name: &str) {
+        self.add(name, 1);
+    }
+
+    /// Get the current value of the counter called `name`.
+    pub fn get(&self, name: &str) -> Option<u64> {
+        self.counters
+            .iter()
+            .find(|(counter, _)| *counter == name)
+            .map(|(_, counter)| counter.load(Ordering::Relaxed))
+    }
+
+    /// Get the current values of all counters.
+    pub fn snapshot(&self) -> Vec<(&'static str, u64)> {
+        self.counters
+            .iter()
+            .map(|(name, counter)| (*name, counter.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+/// Make the counters of `device` visible to user space.
+pub fn register(device: &str, stats: Arc<DeviceStats>) {
+    let mut devices = DEVICES.write();
+    if devices.iter().any(|(name, _)| name == device) {
+        warn!("Statistics for device [{device}] are already registered");
+        return;
+    }
+
+    devices.push((device.to_string(), stats));
+}
+
+/// Remove the counters of `device` (e.g. when the device has been removed).
+pub fn unregister(device: &str) {
+    DEVICES.write().retain(|(name, _)| name != device);
+}
+
+/// Get the counters of a registered device.
+pub fn device(device: &str) -> Option<Arc<DeviceStats>> {
+    DEVICES.read()
+        .iter()
+        .find(|(name, _)| name == device)
+        .map(|(_, stats)| Arc::clone(stats))
+}
+
+/// Get the current values of all counters of all registered devices.
+pub fn snapshot() -> Vec<(String, Vec<(&'static str, u64)>)> {
+    DEVICES.read()
+        .iter()
+        .map(|(device, stats)| (device.clone(), stats.snapshot()))
+        .collect()
+}
+
+/// Serialize all counters of all registered devices (see `system_info::device_stats` for the format).
+pub fn serialize() -> String {
+    let mut out = String::new();
+    for (device, stats) in DEVICES.read().iter() {
+        for (counter, value) in stats.snapshot() {
+            device_stats::write_entry(&mut out, device, counter, value);
+        }
+    }
+
+    out
+}
@@ -18,8 +18,10 @@ use core::{
 use alloc::collections::vec_deque::VecDeque;
 use num_enum::{FromPrimitive, IntoPrimitive};
 use spin::Mutex;
+use syscall::signal::Signal;
 use terminal::TerminalMode;
-use crate::scheduler;
+use crate::process::signal;
+use crate::{process_manager, scheduler};
 
 /// TTY-Input device (Workaround for missing pipes).
 /// Buffers input from the terminal when an application is reading.
@@ -33,6 +35,8 @@ pub struct TtyInput {
     buffer: Mutex<VecDeque<u8>>,
     state: AtomicUsize,
     mode: AtomicUsize,
+    /// Process running in the foreground (0 = none), which gets `Signal::Interrupt` on Ctrl+C
+    foreground: AtomicUsize,
 }
 
 /// TTY-Output device (Workaround for missing pipes).
@@ -65,6 +69,7 @@ impl TtyInput {
             buffer: Mutex::new(VecDeque::new()),
             state: AtomicUsize::new(TtyInputState::Idle as usize),
             mode: AtomicUsize::new(TerminalMode::Canonical as usize),
+            foreground: AtomicUsize::new(0),
         }
     }
 
@@ -73,6 +78,12 @@ impl TtyInput {
         self.mode.store(mode.into(), Ordering::SeqCst);
 
         while self.state.load(Ordering::SeqCst) != (TtyInputState::Ready as usize) {
+            // a signal is delivered on the way back to user mode (and a killed process exits there)
+            let process = scheduler().current_thread().process();
+            if process.has_pending_signals() || process.is_killed() {
+                self.state.store(TtyInputState::Idle as usize, Ordering::SeqCst);
+                return 0;
+            }
             scheduler().switch_thread_no_interrupt();
         }
 
@@ -118,6 +129,31 @@ impl TtyInput {
     pub fn mode(&self) -> TerminalMode {
         TerminalMode::from(self.mode.load(Ordering::SeqCst))
     }
+
+    /// Set the process running in the foreground (0 = none).
+    pub fn set_foreground(&self, process_id: usize) {
+        self.foreground.store(process_id, Ordering::SeqCst);
+    }
+
+    /// Return the process running in the foreground (0 = none).
+    pub fn foreground(&self) -> usize {
+        self.foreground.load(Ordering::SeqCst)
+    }
+
+    pub fn has_foreground(&self) -> bool {
+        self.foreground.load(Ordering::SeqCst) != 0
+    }
+
+    /// Send `Signal::Interrupt` to the foreground process (when Ctrl+C has been pressed).
+    /// Returns false, if there is none.
+    pub fn interrupt(&self) -> bool {
+        let process_id = self.foreground.load(Ordering::SeqCst);
+        let Some(process) = (process_id != 0).then(|| process_manager().read().process(process_id)).flatten() else {
+            return false;
+        };
+
+        signal::send(&process, Signal::Interrupt).is_ok()
+    }
 }
 
 impl TtyOutput {
@@ -0,0 +1,349 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: mass_storage                                                    ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Class driver for USB mass storage devices (USB sticks, card readers)    ║
+   ║ using the bulk-only transport with the SCSI command set. Each command   ║
+   ║ is wrapped in a command block wrapper, followed by an optional data     ║
+   ║ phase and a command status wrapper. Only the first logical unit is      ║
+   ║ used. Devices are registered as block devices ("usb0", "usb1", etc.).   ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - probe                claim a device, if it is a mass storage device ║
+   ║                                                                         ║
+   ║ Spec: USB Mass Storage Class, Bulk-Only Transport, Revision 1.0         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::string::String;
+use alloc::sync::Arc;
+use log::{error, info, warn};
+use spin::Mutex;
+use crate::device::stats::{self, DeviceStats};
+use crate::scheduler;
+use crate::storage::add_block_device;
+use crate::storage::block::BlockDevice;
+use super::xhci::{TransferError, Xhci, MAX_TRANSFER_SIZE};
+use super::{EndpointDescriptor, SetupPacket, UsbDevice};
+
+const STATS_COUNTERS: &[&str] = &["read_requests", "write_requests", "sectors_read", "sectors_written", "flushes", "errors"];
+
+/// Interface class, subclass (SCSI transparent command set) and protocol (bulk-only transport)
+const CLASS_MASS_STORAGE: u8 = 0x08;
+const SUBCLASS_SCSI: u8 = 0x06;
+const PROTOCOL_BULK_ONLY: u8 = 0x50;
+
+/// Class specific request to reset the bulk-only transport
+const REQUEST_MASS_STORAGE_RESET: u8 = 0xff;
+
+const CBW_SIGNATURE: u32 = 0x4342_5355;
+const CBW_LENGTH: usize = 31;
+const CBW_DIRECTION_IN: u8 = 0x80;
+const CSW_SIGNATURE: u32 = 0x5342_5355;
+const CSW_LENGTH: usize = 13;
+const CSW_PASSED: u8 = 0;
+const CSW_PHASE_ERROR: u8 = 2;
+
+/// SCSI commands
+const SCSI_TEST_UNIT_READY: u8 = 0x00;
+const SCSI_REQUEST_SENSE: u8 = 0x03;
+const SCSI_INQUIRY: u8 = 0x12;
+const SCSI_READ_CAPACITY_10: u8 = 0x25;
+const SCSI_READ_10: u8 = 0x28;
+const SCSI_WRITE_10: u8 = 0x2a;
+const SCSI_SYNCHRONIZE_CACHE_10: u8 = 0x35;
+const INQUIRY_LENGTH: usize = 36;
+const SENSE_LENGTH: usize = 18;
+
+/// Devices may need some time to spin up or read their medium after being configured
+const READY_RETRIES: usize = 20;
+const READY_RETRY_DELAY_MS: usize = 100;
+
+#[derive(Debug)]
+enum CommandError {
+    Transfer(TransferError),
+    /// The device has answered with an invalid status wrapper
+    InvalidStatus,
+    /// The device has reported, that the command failed (see REQUEST SENSE)
+    Failed,
+}
+
+impl From<TransferError> for CommandError {
+    fn from(error: TransferError) -> Self {
+        CommandError::Transfer(error)
+    }
+}
+
+/// Data phase of a command
+enum Data<'a> {
+    None,
+    In(&'a mut [u8]),
+    Out(&'a [u8]),
+}
+
+/// A mass storage device, registered in the storage module (as "usb0", "usb1", etc.).
+pub struct MassStorageDevice {
+    controller: Arc<Xhci>,
+    slot: u8,
+    interface: u8,
+    bulk_in: EndpointDescriptor,
+    bulk_out: EndpointDescriptor,
+    /// Tag of the next command; the lock also keeps the phases of different commands apart
+    tag: Mutex<u32>,
+    block_count: u64,
+    block_size: u16,
+    stats: Arc<DeviceStats>,
+}
+
+/// Claim `device` and register it as a block device, if it implements the bulk-only transport. Returns `false` otherwise.
+pub fn probe(device: &UsbDevice) -> bool {
+    let Some(interface) = device.interfaces.iter().find(|interface| {
+        interface.class == CLASS_MASS_STORAGE && interface.sub_class == SUBCLASS_SCSI && interface.protocol == PROTOCOL_BULK_ONLY
+    }) else {
+        return false;
+    };
+    let bulk_in = interface.endpoints.iter().find(|endpoint| endpoint.is_bulk() && endpoint.is_in());
+    let bulk_out = interface.endpoints.iter().find(|endpoint| endpoint.is_bulk() && !endpoint.is_in());
+    let (Some(bulk_in), Some(bulk_out)) = (bulk_in, bulk_out) else {
+        warn!("USB mass storage: Interface [{}] has no bulk endpoints", interface.number);
+        return false;
+    };
+
+    let mut storage = MassStorageDevice {
+        controller: Arc::clone(&device.controller),
+        slot: device.slot,
+        interface: interface.number,
+        bulk_in: *bulk_in,
+        bulk_out: *bulk_out,
+        tag: Mutex::new(1),
+        block_count: 0,
+        block_size: 0,
+        stats: DeviceStats::new(STATS_COUNTERS),
+    };
+
+    let mut inquiry = [0u8; INQUIRY_LENGTH];
+    if storage.command(&[SCSI_INQUIRY, 0, 0, 0, INQUIRY_LENGTH as u8, 0], Data::In(&mut inquiry)).is_ok() {
+        let vendor = String::from_utf8_lossy(&inquiry[8..16]);
+        let product = String::from_utf8_lossy(&inquiry[16..32]);
+        info!("USB mass storage: [{}] [{}]", vendor.trim(), product.trim());
+    }
+    if !storage.wait_ready() {
+        warn!("USB mass storage: Device does not become ready (no medium?)");
+        return true;
+    }
+
+    let mut capacity = [0u8; 8];
+    if let Err(e) = storage.command(&[SCSI_READ_CAPACITY_10, 0, 0, 0, 0, 0, 0, 0, 0, 0], Data::In(&mut capacity)) {
+        error!("USB mass storage: Failed to read capacity: {:?}", e);
+        return true;
+    }
+    let last_block = u32::from_be_bytes([capacity[0], capacity[1], capacity[2], capacity[3]]);
+    let block_size = u32::from_be_bytes([capacity[4], capacity[5], capacity[6], capacity[7]]);
+    if block_size == 0 || block_size as usize > MAX_TRANSFER_SIZE || block_size > u16::MAX as u32 {
+        error!("USB mass storage: Unsupported block size [{}]", block_size);
+        return true;
+    }
+    if last_block == u32::MAX {
+        // READ(10) can't address more blocks anyway
+        warn!("USB mass storage: Device is larger than 2^32 blocks, only the first blocks are accessible");
+    }
+    storage.block_count = last_block as u64 + 1;
+    storage.block_size = block_size as u16;
+    info!("USB mass storage: [{}] blocks of [{}] bytes", storage.block_count, storage.block_size);
+
+    let storage = Arc::new(storage);
+    let stats = Arc::clone(&storage.stats);
+    let name = add_block_device("usb", storage);
+    stats::register(&name, stats);
+    true
+}
+
+impl MassStorageDevice {
+    /// Poll the device with TEST UNIT READY until it reports a medium. Each failure leaves sense data
+    /// (e.g. "medium may have changed"), which must be fetched before the next command.
+    fn wait_ready(&self) -> bool {
+        for _ in 0..READY_RETRIES {
+            match self.command(&[SCSI_TEST_UNIT_READY, 0, 0, 0, 0, 0], Data::None) {
+                Ok(()) => return true,
+                Err(CommandError::Failed) => {
+                    let mut sense = [0u8; SENSE_LENGTH];
+                    let _ = self.command(&[SCSI_REQUEST_SENSE, 0, 0, 0, SENSE_LENGTH as u8, 0], Data::In(&mut sense));
+                }
+                Err(e) => {
+                    warn!("USB mass storage: TEST UNIT READY failed: {:?}", e);
+                    return false;
+                }
+            }
+            scheduler().sleep(READY_RETRY_DELAY_MS);
+        }
+
+        false
+    }
+
+    /// Run the SCSI command `command_block` with the bulk-only transport.
+    fn command(&self, command_block: &[u8], data: Data) -> Result<(), CommandError> {
+        let mut tag = self.tag.lock();
+        let current_tag = *tag;
+        *tag = tag.wrapping_add(1);
+
+        let (direction, length) = match &data {
+            Data::None => (0, 0),
+            Data::In(buffer) => (CBW_DIRECTION_IN, buffer.len()),
+            Data::Out(buffer) => (0, buffer.len()),
+        };
+        let mut cbw = [0u8; CBW_LENGTH];
+        cbw[0..4].copy_from_slice(&CBW_SIGNATURE.to_le_bytes());
+        cbw[4..8].copy_from_slice(&current_tag.to_le_bytes());
+        cbw[8..12].copy_from_slice(&(length as u32).to_le_bytes());
+        cbw[12] = direction;
+        cbw[13] = 0; // LUN
+        cbw[14] = command_block.len() as u8;
+        cbw[15..15 + command_block.len()].copy_from_slice(command_block);
+
+        if let Err(e) = self.controller.bulk_out(self.slot, &self.bulk_out, &cbw) {
+            self.reset_recovery();
+            return Err(e.into());
+        }
+
+        // a stalled data phase is not an error by itself, the status wrapper tells, what went wrong
+        let data_result = match data {
+            Data::None => Ok(0),
+            Data::In(buffer) => self.controller.bulk_in(self.slot, &self.bulk_in, buffer),
+            Data::Out(buffer) => self.controller.bulk_out(self.slot, &self.bulk_out, buffer),
+        };
+        match data_result {
+            Err(TransferError::Stall) => {
+                let endpoint = if direction == CBW_DIRECTION_IN { &self.bulk_in } else { &self.bulk_out };
+                self.controller.clear_halt(self.slot, endpoint)?;
+            }
+            Err(e) => {
+                self.reset_recovery();
+                return Err(e.into());
+            }
+            Ok(_) => {}
+        }
+
+        let mut csw = [0u8; CSW_LENGTH];
+        let status = match self.controller.bulk_in(self.slot, &self.bulk_in, &mut csw) {
+            Err(TransferError::Stall) => {
+                // the device may stall the first attempt to read the status wrapper
+                self.controller.clear_halt(self.slot, &self.bulk_in)?;
+                self.controller.bulk_in(self.slot, &self.bulk_in, &mut csw)
+            }
+            result => result,
+        };
+        if status != Ok(CSW_LENGTH)
+            || u32::from_le_bytes([csw[0], csw[1], csw[2], csw[3]]) != CSW_SIGNATURE
+            || u32::from_le_bytes([csw[4], csw[5], csw[6], csw[7]]) != current_tag
+        {
+            self.reset_recovery();
+            return Err(status.err().map_or(CommandError::InvalidStatus, CommandError::Transfer));
+        }
+
+        match csw[12] {
+            CSW_PASSED => Ok(()),
+            CSW_PHASE_ERROR => {
+                self.reset_recovery();
+                Err(CommandError::InvalidStatus)
+            }
+            _ => Err(CommandError::Failed),
+        }
+    }
+
+    /// Bring the device back into a known state after a transport error (bulk-only transport, section 5.3.4).
+    fn reset_recovery(&self) {
+        let reset = SetupPacket::class_interface(false, REQUEST_MASS_STORAGE_RESET, 0, self.interface, 0);
+        let result = self.controller.control_transfer(self.slot, reset, &mut [])
+            .and_then(|()| self.controller.clear_halt(self.slot, &self.bulk_in))
+            .and_then(|()| self.controller.clear_halt(self.slot, &self.bulk_out));
+        if let Err(e) = result {
+            error!("USB mass storage: Reset recovery failed: {:?}", e);
+        }
+    }
+
+    /// Clamp a request to the size of the device and return the number of blocks that can be processed.
+    fn blocks_available(&self, block: u64, count: usize, buffer_len: usize) -> usize {
+        if block >= self.block_count {
+            return 0;
+        }
+        count
+            .min((self.block_count - block) as usize)
+            .min(buffer_len / self.block_size as usize)
+    }
+
+    /// Split a request into commands, that fit into the bounce buffer of the controller, and run `transfer`
+    /// for each (with the first block, the number of blocks and the byte range in the buffer). Returns the blocks processed.
+    fn transfer_blocks(&self, block: u64, count: usize, mut transfer: impl FnMut(u32, u16, core::ops::Range<usize>) -> Result<(), CommandError>) -> usize {
+        let block_size = self.block_size as usize;
+        let max_blocks = (MAX_TRANSFER_SIZE / block_size).min(u16::MAX as usize);
+        let mut done = 0;
+        while done < count {
+            let blocks = (count - done).min(max_blocks);
+            let range = done * block_size..(done + blocks) * block_size;
+            if let Err(e) = transfer((block + done as u64) as u32, blocks as u16, range) {
+                error!("USB mass storage: Failed to transfer {} blocks at {}: {:?}", blocks, block + done as u64, e);
+                break;
+            }
+            done += blocks;
+        }
+
+        done
+    }
+
+    fn update_stats(&self, requests: &str, sectors: &str, count: usize, processed: usize) {
+        self.stats.inc(requests);
+        self.stats.add(sectors, processed as u64);
+        if processed < count {
+            self.stats.inc("errors");
+        }
+    }
+}
+
+impl BlockDevice for MassStorageDevice {
+    fn read(&self, sector: u64, count: usize, buffer: &mut [u8]) -> usize {
+        let blocks = self.blocks_available(sector, count, buffer.len());
+        let processed = self.transfer_blocks(sector, blocks, |block, blocks, range| {
+            self.command(&rw_command(SCSI_READ_10, block, blocks), Data::In(&mut buffer[range]))
+        });
+
+        self.update_stats("read_requests", "sectors_read", count, processed);
+        processed
+    }
+
+    fn write(&self, sector: u64, count: usize, buffer: &[u8]) -> usize {
+        let blocks = self.blocks_available(sector, count, buffer.len());
+        let processed = self.transfer_blocks(sector, blocks, |block, blocks, range| {
+            self.command(&rw_command(SCSI_WRITE_10, block, blocks), Data::Out(&buffer[range]))
+        });
+
+        self.update_stats("write_requests", "sectors_written", count, processed);
+        processed
+    }
+
+    fn flush(&self) -> bool {
+        self.stats.inc("flushes");
+        match self.command(&[SCSI_SYNCHRONIZE_CACHE_10, 0, 0, 0, 0, 0, 0, 0, 0, 0], Data::None) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("USB mass storage: Failed to flush: {:?}", e);
+                self.stats.inc("errors");
+                false
+            }
+        }
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.block_count
+    }
+
+    fn sector_size(&self) -> u16 {
+        self.block_size
+    }
+}
+
+/// READ(10) or WRITE(10)
+fn rw_command(opcode: u8, block: u32, blocks: u16) -> [u8; 10] {
+    let block = block.to_be_bytes();
+    let blocks = blocks.to_be_bytes();
+    [opcode, 0, block[0], block[1], block[2], block[3], 0, blocks[0], blocks[1], 0]
+}
@@ -0,0 +1,245 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: usb                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ USB support: xHCI host controllers are found on the PCI bus and the     ║
+   ║ devices on their root ports are enumerated (addressed, described and    ║
+   ║ configured with their first configuration). Class drivers then claim    ║
+   ║ the devices; currently only mass storage devices are supported. Hubs    ║
+   ║ and hotplug are not supported, so devices must be attached at boot.     ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - init                 enumerate all devices on all xHCI controllers  ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use log::{info, warn};
+use crate::device::registry;
+use crate::pci_bus;
+use self::xhci::{TransferError, Xhci, SPEED_FULL};
+
+pub mod mass_storage;
+pub mod xhci;
+
+/// PCI class of USB controllers (serial bus controller, USB)
+const PCI_CLASS_SERIAL_BUS: u8 = 0x0c;
+const PCI_SUBCLASS_USB: u8 = 0x03;
+const PCI_INTERFACE_XHCI: u8 = 0x30;
+
+/// Standard requests
+const REQUEST_CLEAR_FEATURE: u8 = 1;
+const REQUEST_GET_DESCRIPTOR: u8 = 6;
+const REQUEST_SET_CONFIGURATION: u8 = 9;
+const FEATURE_ENDPOINT_HALT: u16 = 0;
+
+/// Descriptor types
+const DESCRIPTOR_DEVICE: u8 = 1;
+const DESCRIPTOR_CONFIGURATION: u8 = 2;
+const DESCRIPTOR_INTERFACE: u8 = 4;
+const DESCRIPTOR_ENDPOINT: u8 = 5;
+const DEVICE_DESCRIPTOR_LENGTH: usize = 18;
+const CONFIGURATION_DESCRIPTOR_LENGTH: usize = 9;
+
+/// bmRequestType bits
+const REQUEST_DEVICE_TO_HOST: u8 = 0x80;
+const REQUEST_TYPE_CLASS: u8 = 0x20;
+const RECIPIENT_INTERFACE: u8 = 0x01;
+const RECIPIENT_ENDPOINT: u8 = 0x02;
+
+/// Find all xHCI controllers, enumerate their devices and hand them to the class drivers.
+pub fn init() {
+    for pci_device in pci_bus().search_by_class(PCI_CLASS_SERIAL_BUS, PCI_SUBCLASS_USB) {
+        let (_, _, _, interface) = pci_device.read().header().revision_and_class(pci_bus().config_space());
+        if interface != PCI_INTERFACE_XHCI {
+            continue;
+        }
+
+        let device_id = pci_device.read().header().id(pci_bus().config_space());
+        info!("Found xHCI controller [{}:{}]", device_id.0, device_id.1);
+        let Some(controller) = Xhci::new(pci_device) else {
+            warn!("Failed to initialize xHCI controller [{}:{}]", device_id.0, device_id.1);
+            continue;
+        };
+
+        registry::bind(&registry::pci_name(pci_device.read().header().address()), "xhci");
+        let controller = Arc::new(controller);
+        for port in 1..=controller.ports() {
+            let Some(speed) = controller.reset_port(port) else {
+                continue;
+            };
+            match UsbDevice::enumerate(&controller, port, speed) {
+                Ok(device) => {
+                    info!("USB device [{:04x}:{:04x}] on port [{}] (class [{}])", device.vendor_id, device.product_id, port, device.class);
+                    if !mass_storage::probe(&device) {
+                        info!("No driver for USB device [{:04x}:{:04x}]", device.vendor_id, device.product_id);
+                    }
+                }
+                Err(e) => warn!("Failed to enumerate USB device on port [{}]: {:?}", port, e),
+            }
+        }
+    }
+}
+
+/// The 8 byte setup packet of a control transfer
+#[derive(Debug, Clone, Copy)]
+pub struct SetupPacket {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+impl SetupPacket {
+    pub fn get_descriptor(descriptor_type: u8, index: u8, length: u16) -> Self {
+        Self { request_type: REQUEST_DEVICE_TO_HOST, request: REQUEST_GET_DESCRIPTOR, value: ((descriptor_type as u16) << 8) | index as u16, index: 0, length }
+    }
+
+    pub fn set_configuration(value: u8) -> Self {
+        Self { request_type: 0, request: REQUEST_SET_CONFIGURATION, value: value as u16, index: 0, length: 0 }
+    }
+
+    pub fn clear_endpoint_halt(endpoint_address: u8) -> Self {
+        Self { request_type: RECIPIENT_ENDPOINT, request: REQUEST_CLEAR_FEATURE, value: FEATURE_ENDPOINT_HALT, index: endpoint_address as u16, length: 0 }
+    }
+
+    /// A class specific request to an interface
+    pub fn class_interface(device_to_host: bool, request: u8, value: u16, interface: u8, length: u16) -> Self {
+        let direction = if device_to_host { REQUEST_DEVICE_TO_HOST } else { 0 };
+        Self { request_type: direction | REQUEST_TYPE_CLASS | RECIPIENT_INTERFACE, request, value, index: interface as u16, length }
+    }
+
+    pub fn is_device_to_host(&self) -> bool {
+        self.request_type & REQUEST_DEVICE_TO_HOST != 0
+    }
+
+    /// The packet as it is put into a setup stage TRB (little endian)
+    pub fn as_u64(&self) -> u64 {
+        self.request_type as u64
+            | (self.request as u64) << 8
+            | (self.value as u64) << 16
+            | (self.index as u64) << 32
+            | (self.length as u64) << 48
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EndpointDescriptor {
+    /// Endpoint number, with bit 7 set for IN endpoints
+    pub address: u8,
+    pub attributes: u8,
+    pub max_packet_size: u16,
+}
+
+impl EndpointDescriptor {
+    pub fn is_in(&self) -> bool {
+        self.address & 0x80 != 0
+    }
+
+    pub fn is_bulk(&self) -> bool {
+        self.attributes & 0x3 == 0x2
+    }
+
+    /// Index of the endpoint in the device context of the xHCI
+    pub fn context_index(&self) -> u8 {
+        (self.address & 0xf) * 2 + self.is_in() as u8
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InterfaceDescriptor {
+    pub number: u8,
+    pub class: u8,
+    pub sub_class: u8,
+    pub protocol: u8,
+    pub endpoints: Vec<EndpointDescriptor>,
+}
+
+/// An addressed and configured device on a root port
+pub struct UsbDevice {
+    pub controller: Arc<Xhci>,
+    pub slot: u8,
+    pub port: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// Device class (0 means, that the interfaces define their class)
+    pub class: u8,
+    pub interfaces: Vec<InterfaceDescriptor>,
+}
+
+impl UsbDevice {
+    /// Address the device on `port`, read its descriptors and select its first configuration.
+    /// Transfer rings are created for all bulk endpoints (other endpoint types are not supported yet).
+    fn enumerate(controller: &Arc<Xhci>, port: u8, speed: u8) -> Result<Self, TransferError> {
+        let slot = controller.address_device(port, speed)?;
+
+        // full speed devices may have larger control packets than the 8 bytes assumed by the controller
+        let mut descriptor = [0u8; DEVICE_DESCRIPTOR_LENGTH];
+        controller.control_transfer(slot, SetupPacket::get_descriptor(DESCRIPTOR_DEVICE, 0, 8), &mut descriptor[..8])?;
+        if speed == SPEED_FULL {
+            controller.set_max_packet_size(slot, descriptor[7] as u16)?;
+        }
+        controller.control_transfer(slot, SetupPacket::get_descriptor(DESCRIPTOR_DEVICE, 0, DEVICE_DESCRIPTOR_LENGTH as u16), &mut descriptor)?;
+
+        let mut header = [0u8; CONFIGURATION_DESCRIPTOR_LENGTH];
+        controller.control_transfer(slot, SetupPacket::get_descriptor(DESCRIPTOR_CONFIGURATION, 0, header.len() as u16), &mut header)?;
+        let total_length = u16::from_le_bytes([header[2], header[3]]).clamp(CONFIGURATION_DESCRIPTOR_LENGTH as u16, 4096);
+        let mut configuration = vec![0u8; total_length as usize];
+        controller.control_transfer(slot, SetupPacket::get_descriptor(DESCRIPTOR_CONFIGURATION, 0, total_length), &mut configuration)?;
+
+        let interfaces = parse_interfaces(&configuration);
+        let endpoints: Vec<EndpointDescriptor> = interfaces.iter().flat_map(|interface| interface.endpoints.iter().copied()).collect();
+        // Laut Spezifikation müssen die Endpunkte im Controller vor SET_CONFIGURATION eingerichtet werden
+        controller.configure_endpoints(slot, &endpoints)?;
+        controller.control_transfer(slot, SetupPacket::set_configuration(header[5]), &mut [])?;
+
+        Ok(Self {
+            controller: Arc::clone(controller),
+            slot,
+            port,
+            vendor_id: u16::from_le_bytes([descriptor[8], descriptor[9]]),
+            product_id: u16::from_le_bytes([descriptor[10], descriptor[11]]),
+            class: descriptor[4],
+            interfaces,
+        })
+    }
+}
+
+/// Collect the interfaces (with their endpoints) of a configuration descriptor. Alternate settings are skipped.
+fn parse_interfaces(configuration: &[u8]) -> Vec<InterfaceDescriptor> {
+    let mut interfaces: Vec<InterfaceDescriptor> = Vec::new();
+    let mut alternate = false;
+    let mut offset = 0;
+    while offset + 2 <= configuration.len() {
+        let length = configuration[offset] as usize;
+        if length < 2 || offset + length > configuration.len() {
+            break;
+        }
+        let descriptor = &configuration[offset..offset + length];
+
+        match descriptor[1] {
+            DESCRIPTOR_INTERFACE if length >= 9 => {
+                alternate = descriptor[3] != 0;
+                if !alternate {
+                    interfaces.push(InterfaceDescriptor { number: descriptor[2], class: descriptor[5], sub_class: descriptor[6], protocol: descriptor[7], endpoints: Vec::new() });
+                }
+            }
+            DESCRIPTOR_ENDPOINT if length >= 7 && !alternate => {
+                if let Some(interface) = interfaces.last_mut() {
+                    interface.endpoints.push(EndpointDescriptor {
+                        address: descriptor[2],
+                        attributes: descriptor[3],
+                        max_packet_size: u16::from_le_bytes([descriptor[4], descriptor[5]]) & 0x7ff,
+                    });
+                }
+            }
+            _ => {}
+        }
+        offset += length;
+    }
+
+    interfaces
+}
@@ -0,0 +1,745 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: xhci                                                            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Driver for xHCI USB host controllers (xHCI 1.2). It sets up the ║
+   ║         device context table, the command ring and a single event ring  ║
+   ║         and offers synchronous control and bulk transfers: Each request ║
+   ║         is put on a transfer ring and the event ring is polled for its  ║
+   ║         completion, so interrupts are not used. Only one request is in  ║
+   ║         flight per controller, which keeps the event handling trivial.  ║
+   ║         Transfer data goes through a bounce buffer, so callers can use  ║
+   ║         any memory.                                                     ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - new                  take over and initialize a controller          ║
+   ║   - ports                number of root hub ports                       ║
+   ║   - reset_port           enable the device on a port, get its speed     ║
+   ║   - address_device       assign a slot and an address to a device       ║
+   ║   - set_max_packet_size  change the packet size of the control endpoint ║
+   ║   - configure_endpoints  set up transfer rings for bulk endpoints       ║
+   ║   - control_transfer     send a request to the control endpoint         ║
+   ║   - bulk_in, bulk_out    transfer data on a bulk endpoint               ║
+   ║   - clear_halt           recover a bulk endpoint after a stall          ║
+   ║                                                                         ║
+   ║ Spec: eXtensible Host Controller Interface for USB, Revision 1.2        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::collections::BTreeMap;
+use core::hint::spin_loop;
+use core::ptr;
+use log::{info, warn};
+use pci_types::{CommandRegister, EndpointHeader};
+use spin::{Mutex, RwLock};
+use crate::device::pci_resources;
+use crate::device::virtio::dma::Dma;
+use crate::memory::PAGE_SIZE;
+use crate::{pci_bus, scheduler, timer};
+use super::{EndpointDescriptor, SetupPacket};
+
+/// Capability registers
+const CAPLENGTH: u64 = 0x00;
+const HCSPARAMS1: u64 = 0x04;
+const HCSPARAMS2: u64 = 0x08;
+const HCCPARAMS1: u64 = 0x10;
+const DBOFF: u64 = 0x14;
+const RTSOFF: u64 = 0x18;
+/// Operational registers
+const USBCMD: u64 = 0x00;
+const USBSTS: u64 = 0x04;
+const PAGESIZE: u64 = 0x08;
+const CRCR: u64 = 0x18;
+const DCBAAP: u64 = 0x30;
+const CONFIG: u64 = 0x38;
+const PORTSC_BASE: u64 = 0x400;
+/// Registers of interrupter 0 (in the runtime registers)
+const ERSTSZ: u64 = 0x28;
+const ERSTBA: u64 = 0x30;
+const ERDP: u64 = 0x38;
+
+const USBCMD_RUN: u32 = 1 << 0;
+const USBCMD_RESET: u32 = 1 << 1;
+const USBSTS_HALTED: u32 = 1 << 0;
+const USBSTS_NOT_READY: u32 = 1 << 11;
+const HCCPARAMS1_CONTEXT_64: u32 = 1 << 2;
+const ERDP_BUSY: u64 = 1 << 3;
+
+const PORTSC_CONNECTED: u32 = 1 << 0;
+const PORTSC_ENABLED: u32 = 1 << 1;
+const PORTSC_RESET: u32 = 1 << 4;
+const PORTSC_RESET_CHANGE: u32 = 1 << 21;
+/// Bits, that keep their value, when written back (all others are either read-only or cleared by writing 1)
+const PORTSC_PRESERVE: u32 = 0x0e00_c3e0;
+
+/// USB legacy support capability, used by the firmware to emulate PS/2 devices
+const CAPABILITY_LEGACY: u32 = 1;
+const LEGACY_BIOS_OWNED: u32 = 1 << 16;
+const LEGACY_OS_OWNED: u32 = 1 << 24;
+/// SMI enable bits are cleared and SMI events acknowledged (like Linux does)
+const LEGACY_DISABLE_SMI: u32 = (0x7 << 1) | (0xff << 5) | (0x7 << 17);
+const LEGACY_SMI_EVENTS: u32 = 0x7 << 29;
+
+/// TRB types
+const TRB_NORMAL: u32 = 1;
+const TRB_SETUP: u32 = 2;
+const TRB_DATA: u32 = 3;
+const TRB_STATUS: u32 = 4;
+const TRB_LINK: u32 = 6;
+const TRB_NOOP: u32 = 8;
+const TRB_ENABLE_SLOT: u32 = 9;
+const TRB_ADDRESS_DEVICE: u32 = 11;
+const TRB_CONFIGURE_ENDPOINT: u32 = 12;
+const TRB_EVALUATE_CONTEXT: u32 = 13;
+const TRB_RESET_ENDPOINT: u32 = 14;
+const TRB_SET_DEQUEUE: u32 = 16;
+const TRB_TRANSFER_EVENT: u32 = 32;
+const TRB_COMMAND_COMPLETION: u32 = 33;
+
+/// TRB control bits
+const TRB_CYCLE: u32 = 1 << 0;
+const TRB_TOGGLE_CYCLE: u32 = 1 << 1;
+const TRB_CHAIN: u32 = 1 << 4;
+const TRB_IOC: u32 = 1 << 5;
+const TRB_IMMEDIATE: u32 = 1 << 6;
+const TRB_DIR_IN: u32 = 1 << 16;
+/// Transfer type of setup stages
+const TRT_NO_DATA: u32 = 0;
+const TRT_OUT: u32 = 2;
+const TRT_IN: u32 = 3;
+
+/// Completion codes
+const COMPLETION_SUCCESS: u8 = 1;
+const COMPLETION_STALL: u8 = 6;
+const COMPLETION_SHORT_PACKET: u8 = 13;
+
+/// Endpoint types in endpoint contexts
+const EP_BULK_OUT: u32 = 2;
+const EP_CONTROL: u32 = 4;
+const EP_BULK_IN: u32 = 6;
+/// Number of retries of the controller for failed transactions
+const EP_ERROR_COUNT: u32 = 3;
+/// Average TRB length of bulk endpoints recommended by the specification
+const BULK_AVERAGE_TRB_LENGTH: u32 = 3072;
+
+/// Port speeds in PORTSC and slot contexts
+pub const SPEED_FULL: u8 = 1;
+pub const SPEED_LOW: u8 = 2;
+pub const SPEED_HIGH: u8 = 3;
+
+const TRB_SIZE: usize = 16;
+/// Each ring is a single page, the last TRB links back to the first one
+const RING_TRBS: usize = PAGE_SIZE / TRB_SIZE;
+/// Size of the bounce buffer (and therefore of a single bulk transfer)
+const TRANSFER_BUFFER_PAGES: usize = 16;
+pub const MAX_TRANSFER_SIZE: usize = TRANSFER_BUFFER_PAGES * PAGE_SIZE;
+/// Timeouts (in milliseconds)
+const RESET_TIMEOUT_MS: usize = 1000;
+const COMMAND_TIMEOUT_MS: usize = 1000;
+const TRANSFER_TIMEOUT_MS: usize = 5000;
+const PORT_RESET_TIMEOUT_MS: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferError {
+    Timeout,
+    /// The endpoint has been halted by the device (see `clear_halt()`)
+    Stall,
+    /// The controller has reported another completion code
+    Failed(u8),
+    /// The slot or endpoint has not been set up
+    NotConfigured,
+    /// The data doesn't fit into the bounce buffer
+    TooLarge,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct Trb {
+    parameter: u64,
+    status: u32,
+    control: u32,
+}
+
+impl Trb {
+    const fn new(trb_type: u32, parameter: u64, status: u32, flags: u32) -> Self {
+        Self { parameter, status, control: (trb_type << 10) | flags }
+    }
+
+    fn trb_type(&self) -> u32 {
+        (self.control >> 10) & 0x3f
+    }
+
+    fn completion_code(&self) -> u8 {
+        (self.status >> 24) as u8
+    }
+
+    /// Bytes not transferred (transfer events)
+    fn residual(&self) -> usize {
+        (self.status & 0x00ff_ffff) as usize
+    }
+
+    fn slot(&self) -> u8 {
+        (self.control >> 24) as u8
+    }
+
+    /// Device context index (transfer events)
+    fn endpoint(&self) -> u8 {
+        ((self.control >> 16) & 0x1f) as u8
+    }
+}
+
+/// Memory mapped registers
+#[derive(Clone, Copy)]
+struct Mmio(u64);
+
+impl Mmio {
+    fn read(&self, offset: u64) -> u32 {
+        unsafe { ptr::read_volatile((self.0 + offset) as *const u32) }
+    }
+
+    fn write(&self, offset: u64, value: u32) {
+        unsafe { ptr::write_volatile((self.0 + offset) as *mut u32, value) }
+    }
+
+    /// 64 bit registers may be written as two halves (low half first)
+    fn write64(&self, offset: u64, value: u64) {
+        self.write(offset, value as u32);
+        self.write(offset + 4, (value >> 32) as u32);
+    }
+}
+
+/// A command or transfer ring, consisting of one page
+struct Ring {
+    memory: Dma,
+    enqueue: usize,
+    /// Producer cycle state
+    cycle: bool,
+}
+
+impl Ring {
+    fn new() -> Self {
+        let memory = zeroed_dma(1);
+        let ring = Self { memory, enqueue: 0, cycle: true };
+        let link = Trb::new(TRB_LINK, ring.memory.paddr().as_u64(), 0, TRB_TOGGLE_CYCLE);
+        ring.write(RING_TRBS - 1, link);
+        ring
+    }
+
+    fn write(&self, index: usize, trb: Trb) {
+        let trb_ptr = self.memory.vaddr(index * TRB_SIZE).as_ptr() as *mut Trb;
+        // Das Cycle-Bit wird zuletzt geschrieben, damit der Controller keinen halb geschriebenen TRB sieht
+        unsafe {
+            ptr::addr_of_mut!((*trb_ptr).parameter).write_volatile(trb.parameter);
+            ptr::addr_of_mut!((*trb_ptr).status).write_volatile(trb.status);
+            ptr::addr_of_mut!((*trb_ptr).control).write_volatile(trb.control);
+        }
+    }
+
+    /// Put `trb` on the ring (with the producer cycle bit) and return its physical address.
+    fn push(&mut self, mut trb: Trb) -> u64 {
+        let address = self.memory.paddr().as_u64() + (self.enqueue * TRB_SIZE) as u64;
+        trb.control = (trb.control & !TRB_CYCLE) | self.cycle as u32;
+        self.write(self.enqueue, trb);
+
+        self.enqueue += 1;
+        if self.enqueue == RING_TRBS - 1 {
+            // hand the link TRB to the controller and continue at the start with the inverted cycle bit
+            let link = Trb::new(TRB_LINK, self.memory.paddr().as_u64(), 0, TRB_TOGGLE_CYCLE | self.cycle as u32);
+            self.write(RING_TRBS - 1, link);
+            self.enqueue = 0;
+            self.cycle = !self.cycle;
+        }
+        address
+    }
+
+    /// Make sure, that the next `count` TRBs are not split by the link TRB, so a transfer descriptor never wraps.
+    fn reserve(&mut self, count: usize) {
+        while self.enqueue + count > RING_TRBS - 1 {
+            self.push(Trb::new(TRB_NOOP, 0, 0, 0));
+        }
+    }
+
+    /// Physical address of the next TRB together with the cycle state (as needed by contexts and commands)
+    fn dequeue_pointer(&self) -> u64 {
+        (self.memory.paddr().as_u64() + (self.enqueue * TRB_SIZE) as u64) | self.cycle as u64
+    }
+}
+
+/// The event ring with a single segment
+struct EventRing {
+    segment: Dma,
+    /// Event ring segment table (one entry)
+    table: Dma,
+    dequeue: usize,
+    /// Consumer cycle state
+    cycle: bool,
+}
+
+impl EventRing {
+    fn new() -> Self {
+        let segment = zeroed_dma(1);
+        let table = zeroed_dma(1);
+        unsafe {
+            let entry = table.vaddr(0).as_ptr();
+            ptr::write_volatile(entry as *mut u64, segment.paddr().as_u64());
+            ptr::write_volatile(entry.add(8) as *mut u32, RING_TRBS as u32);
+        }
+        Self { segment, table, dequeue: 0, cycle: true }
+    }
+
+    /// Get the next event, if the controller has written one.
+    fn next(&mut self) -> Option<Trb> {
+        let trb_ptr = self.segment.vaddr(self.dequeue * TRB_SIZE).as_ptr() as *const Trb;
+        let control = unsafe { ptr::addr_of!((*trb_ptr).control).read_volatile() };
+        if (control & TRB_CYCLE != 0) != self.cycle {
+            return None;
+        }
+
+        let trb = unsafe {
+            Trb {
+                parameter: ptr::addr_of!((*trb_ptr).parameter).read_volatile(),
+                status: ptr::addr_of!((*trb_ptr).status).read_volatile(),
+                control,
+            }
+        };
+        self.dequeue += 1;
+        if self.dequeue == RING_TRBS {
+            self.dequeue = 0;
+            self.cycle = !self.cycle;
+        }
+        Some(trb)
+    }
+
+    fn dequeue_pointer(&self) -> u64 {
+        self.segment.paddr().as_u64() + (self.dequeue * TRB_SIZE) as u64
+    }
+}
+
+/// A device slot with its contexts and transfer rings
+struct Slot {
+    /// Device context (written by the controller)
+    _output: Dma,
+    input: Dma,
+    /// Transfer rings by device context index
+    rings: BTreeMap<u8, Ring>,
+    port: u8,
+    speed: u8,
+    /// Packet size of the control endpoint
+    max_packet_size: u16,
+}
+
+struct State {
+    commands: Ring,
+    events: EventRing,
+    slots: BTreeMap<u8, Slot>,
+    /// Bounce buffer for all transfers
+    buffer: Dma,
+    _scratchpad: Option<(Dma, Dma)>,
+}
+
+pub struct Xhci {
+    operational: Mmio,
+    runtime: Mmio,
+    doorbells: Mmio,
+    ports: u8,
+    /// Size of a context in bytes (32 or 64)
+    context_size: usize,
+    device_contexts: Dma,
+    state: Mutex<State>,
+}
+
+impl Xhci {
+    /// Take the controller over from the firmware, reset and start it. Returns `None`, if this fails.
+    pub fn new(pci_device: &RwLock<EndpointHeader>) -> Option<Self> {
+        let config_space = pci_bus().config_space();
+        let (mmio_start, _) = pci_resources::map_memory_bar(&pci_device.read(), 0, "xhci")?;
+        pci_device.write().update_command(config_space, |command| {
+            command | CommandRegister::BUS_MASTER_ENABLE | CommandRegister::MEMORY_ENABLE
+        });
+
+        let capabilities = Mmio(mmio_start);
+        let operational = Mmio(mmio_start + (capabilities.read(CAPLENGTH) & 0xff) as u64);
+        let runtime = Mmio(mmio_start + (capabilities.read(RTSOFF) & !0x1f) as u64);
+        let doorbells = Mmio(mmio_start + (capabilities.read(DBOFF) & !0x3) as u64);
+
+        let hcsparams1 = capabilities.read(HCSPARAMS1);
+        let max_slots = (hcsparams1 & 0xff) as u8;
+        let ports = (hcsparams1 >> 24) as u8;
+        let hccparams1 = capabilities.read(HCCPARAMS1);
+        let context_size = if hccparams1 & HCCPARAMS1_CONTEXT_64 != 0 { 64 } else { 32 };
+        info!("xHCI: [{}] slots, [{}] ports, [{}] byte contexts", max_slots, ports, context_size);
+
+        take_ownership(capabilities, hccparams1);
+
+        // stop and reset the controller
+        operational.write(USBCMD, operational.read(USBCMD) & !USBCMD_RUN);
+        if !wait_until(RESET_TIMEOUT_MS, || operational.read(USBSTS) & USBSTS_HALTED != 0) {
+            warn!("xHCI: Controller does not stop");
+            return None;
+        }
+        operational.write(USBCMD, USBCMD_RESET);
+        if !wait_until(RESET_TIMEOUT_MS, || operational.read(USBCMD) & USBCMD_RESET == 0 && operational.read(USBSTS) & USBSTS_NOT_READY == 0) {
+            warn!("xHCI: Controller does not finish its reset");
+            return None;
+        }
+        if operational.read(PAGESIZE) & 1 == 0 {
+            warn!("xHCI: Controller does not support 4 KiB pages");
+            return None;
+        }
+
+        // the first entry of the device context table points to the scratchpad buffers, if the controller needs them
+        let device_contexts = zeroed_dma(1);
+        let hcsparams2 = capabilities.read(HCSPARAMS2);
+        let scratchpad_count = ((((hcsparams2 >> 21) & 0x1f) << 5) | (hcsparams2 >> 27)) as usize;
+        let scratchpad = (scratchpad_count > 0).then(|| {
+            let buffers = zeroed_dma(scratchpad_count);
+            let array = zeroed_dma(1);
+            for index in 0..scratchpad_count {
+                let address = buffers.paddr().as_u64() + (index * PAGE_SIZE) as u64;
+                unsafe { ptr::write_volatile(array.vaddr(index * 8).as_ptr() as *mut u64, address) };
+            }
+            unsafe { ptr::write_volatile(device_contexts.vaddr(0).as_ptr() as *mut u64, array.paddr().as_u64()) };
+            (buffers, array)
+        });
+
+        let state = State {
+            commands: Ring::new(),
+            events: EventRing::new(),
+            slots: BTreeMap::new(),
+            buffer: zeroed_dma(TRANSFER_BUFFER_PAGES),
+            _scratchpad: scratchpad,
+        };
+
+        operational.write(CONFIG, max_slots as u32);
+        operational.write64(DCBAAP, device_contexts.paddr().as_u64());
+        operational.write64(CRCR, state.commands.dequeue_pointer());
+        runtime.write(ERSTSZ, 1);
+        runtime.write64(ERDP, state.events.dequeue_pointer());
+        runtime.write64(ERSTBA, state.events.table.paddr().as_u64());
+
+        operational.write(USBCMD, USBCMD_RUN);
+        if !wait_until(RESET_TIMEOUT_MS, || operational.read(USBSTS) & USBSTS_HALTED == 0) {
+            warn!("xHCI: Controller does not start");
+            return None;
+        }
+
+        Some(Self { operational, runtime, doorbells, ports, context_size, device_contexts, state: Mutex::new(state) })
+    }
+
+    /// Number of root hub ports (numbered from 1)
+    pub fn ports(&self) -> u8 {
+        self.ports
+    }
+
+    /// Reset the port `port`, if a device is connected, and return the speed of the device once the port is enabled.
+    pub fn reset_port(&self, port: u8) -> Option<u8> {
+        let register = PORTSC_BASE + 0x10 * (port as u64 - 1);
+        let portsc = self.operational.read(register);
+        if portsc & PORTSC_CONNECTED == 0 {
+            return None;
+        }
+
+        // USB 3 ports are enabled by the link training, USB 2 ports need a reset
+        if portsc & PORTSC_ENABLED == 0 {
+            self.operational.write(register, (portsc & PORTSC_PRESERVE) | PORTSC_RESET);
+            let reset = wait_until(PORT_RESET_TIMEOUT_MS, || self.operational.read(register) & PORTSC_RESET_CHANGE != 0);
+            let portsc = self.operational.read(register);
+            self.operational.write(register, (portsc & PORTSC_PRESERVE) | PORTSC_RESET_CHANGE);
+            if !reset || portsc & PORTSC_ENABLED == 0 {
+                warn!("xHCI: Failed to reset port [{}]", port);
+                return None;
+            }
+        }
+
+        Some(((self.operational.read(register) >> 10) & 0xf) as u8)
+    }
+
+    /// Enable a slot for the device on `port` and assign an address to it. Returns the slot id.
+    pub fn address_device(&self, port: u8, speed: u8) -> Result<u8, TransferError> {
+        let mut state = self.state.lock();
+        let slot = self.command(&mut state, Trb::new(TRB_ENABLE_SLOT, 0, 0, 0))?.slot();
+
+        let output = zeroed_dma(1);
+        unsafe { ptr::write_volatile(self.device_contexts.vaddr(slot as usize * 8).as_ptr() as *mut u64, output.paddr().as_u64()) };
+
+        // the packet size of the control endpoint is only known after reading the device descriptor
+        let max_packet_size = match speed {
+            SPEED_LOW | SPEED_FULL => 8,
+            SPEED_HIGH => 64,
+            _ => 512,
+        };
+        let mut slot_state = Slot { _output: output, input: zeroed_dma(1), rings: BTreeMap::new(), port, speed, max_packet_size };
+        slot_state.rings.insert(1, Ring::new());
+        self.prepare_input_context(&slot_state, 1 << 1, 1);
+        let input = slot_state.input.paddr().as_u64();
+        state.slots.insert(slot, slot_state);
+
+        if let Err(e) = self.command(&mut state, Trb::new(TRB_ADDRESS_DEVICE, input, 0, (slot as u32) << 24)) {
+            state.slots.remove(&slot);
+            return Err(e);
+        }
+        Ok(slot)
+    }
+
+    /// Tell the controller the packet size of the control endpoint of `slot` (from the device descriptor).
+    pub fn set_max_packet_size(&self, slot: u8, max_packet_size: u16) -> Result<(), TransferError> {
+        let mut state = self.state.lock();
+        let slot_state = state.slots.get_mut(&slot).ok_or(TransferError::NotConfigured)?;
+        if slot_state.max_packet_size == max_packet_size {
+            return Ok(());
+        }
+        slot_state.max_packet_size = max_packet_size;
+        self.prepare_input_context(slot_state, 1 << 1, 1);
+        let input = slot_state.input.paddr().as_u64();
+        self.command(&mut state, Trb::new(TRB_EVALUATE_CONTEXT, input, 0, (slot as u32) << 24)).map(|_| ())
+    }
+
+    /// Create transfer rings for the bulk endpoints `endpoints` of the device in `slot`
+    /// (before the configuration is selected on the device).
+    pub fn configure_endpoints(&self, slot: u8, endpoints: &[EndpointDescriptor]) -> Result<(), TransferError> {
+        let mut state = self.state.lock();
+        let slot_state = state.slots.get_mut(&slot).ok_or(TransferError::NotConfigured)?;
+
+        let mut add_flags = 0;
+        let mut last_index = 1;
+        for endpoint in endpoints.iter().filter(|endpoint| endpoint.is_bulk()) {
+            let index = endpoint.context_index();
+            slot_state.rings.insert(index, Ring::new());
+            add_flags |= 1 << index;
+            last_index = last_index.max(index);
+        }
+        self.prepare_input_context(slot_state, add_flags, last_index);
+        for endpoint in endpoints.iter().filter(|endpoint| endpoint.is_bulk()) {
+            let index = endpoint.context_index();
+            let ep_type = if endpoint.is_in() { EP_BULK_IN } else { EP_BULK_OUT };
+            let dequeue = slot_state.rings[&index].dequeue_pointer();
+            self.write_endpoint_context(slot_state, index, ep_type, endpoint.max_packet_size, dequeue, BULK_AVERAGE_TRB_LENGTH);
+        }
+
+        let input = slot_state.input.paddr().as_u64();
+        self.command(&mut state, Trb::new(TRB_CONFIGURE_ENDPOINT, input, 0, (slot as u32) << 24)).map(|_| ())
+    }
+
+    /// Send `setup` to the control endpoint of the device in `slot`. The data stage transfers `data`
+    /// (its length must match the request). Descriptors carry their own length, so the number of bytes
+    /// actually received is not reported.
+    pub fn control_transfer(&self, slot: u8, setup: SetupPacket, data: &mut [u8]) -> Result<(), TransferError> {
+        if data.len() > PAGE_SIZE {
+            return Err(TransferError::TooLarge);
+        }
+        let mut state = self.state.lock();
+        let buffer = state.buffer.paddr().as_u64();
+        let device_to_host = setup.is_device_to_host();
+
+        let mut trbs = [Trb::default(); 3];
+        let mut count = 0;
+        let transfer_type = match (data.is_empty(), device_to_host) {
+            (true, _) => TRT_NO_DATA,
+            (false, true) => TRT_IN,
+            (false, false) => TRT_OUT,
+        };
+        trbs[count] = Trb::new(TRB_SETUP, setup.as_u64(), 8, TRB_IMMEDIATE | (transfer_type << 16));
+        count += 1;
+        if !data.is_empty() {
+            if !device_to_host {
+                copy_to_dma(&state.buffer, data);
+            }
+            trbs[count] = Trb::new(TRB_DATA, buffer, data.len() as u32, if device_to_host { TRB_DIR_IN } else { 0 });
+            count += 1;
+        }
+        // the status stage goes in the opposite direction of the data stage
+        let status_in = data.is_empty() || !device_to_host;
+        trbs[count] = Trb::new(TRB_STATUS, 0, 0, TRB_IOC | if status_in { TRB_DIR_IN } else { 0 });
+        count += 1;
+
+        self.transfer(&mut state, slot, 1, &trbs[..count])?;
+        if device_to_host {
+            copy_from_dma(&state.buffer, data);
+        }
+        Ok(())
+    }
+
+    /// Receive up to `buffer.len()` bytes (at most `MAX_TRANSFER_SIZE`) from the bulk endpoint `endpoint`.
+    /// Returns the number of bytes received.
+    pub fn bulk_in(&self, slot: u8, endpoint: &EndpointDescriptor, buffer: &mut [u8]) -> Result<usize, TransferError> {
+        let mut state = self.state.lock();
+        let received = self.bulk_transfer(&mut state, slot, endpoint, buffer.len())?;
+        copy_from_dma(&state.buffer, &mut buffer[..received]);
+        Ok(received)
+    }
+
+    /// Send `data` (at most `MAX_TRANSFER_SIZE` bytes) to the bulk endpoint `endpoint`. Returns the number of bytes sent.
+    pub fn bulk_out(&self, slot: u8, endpoint: &EndpointDescriptor, data: &[u8]) -> Result<usize, TransferError> {
+        let mut state = self.state.lock();
+        if data.len() > MAX_TRANSFER_SIZE {
+            return Err(TransferError::TooLarge);
+        }
+        copy_to_dma(&state.buffer, data);
+        self.bulk_transfer(&mut state, slot, endpoint, data.len())
+    }
+
+    /// Make a halted bulk endpoint usable again (after `TransferError::Stall`): The endpoint is reset in the controller,
+    /// its ring continues after the failed transfer and the halt is cleared on the device.
+    pub fn clear_halt(&self, slot: u8, endpoint: &EndpointDescriptor) -> Result<(), TransferError> {
+        let index = endpoint.context_index() as u32;
+        {
+            let mut state = self.state.lock();
+            self.command(&mut state, Trb::new(TRB_RESET_ENDPOINT, 0, 0, (index << 16) | ((slot as u32) << 24)))?;
+            let dequeue = state.slots.get(&slot)
+                .and_then(|slot_state| slot_state.rings.get(&endpoint.context_index()))
+                .ok_or(TransferError::NotConfigured)?
+                .dequeue_pointer();
+            self.command(&mut state, Trb::new(TRB_SET_DEQUEUE, dequeue, 0, (index << 16) | ((slot as u32) << 24)))?;
+        }
+        self.control_transfer(slot, SetupPacket::clear_endpoint_halt(endpoint.address), &mut [])
+    }
+
+    fn bulk_transfer(&self, state: &mut State, slot: u8, endpoint: &EndpointDescriptor, len: usize) -> Result<usize, TransferError> {
+        if len > MAX_TRANSFER_SIZE {
+            return Err(TransferError::TooLarge);
+        }
+
+        // Ein TRB darf keine 64-KiB-Grenze überschreiten, daher bekommt jede Seite des Puffers einen eigenen TRB
+        let buffer = state.buffer.paddr().as_u64();
+        let pages = len.div_ceil(PAGE_SIZE).max(1);
+        let mut trbs = [Trb::default(); TRANSFER_BUFFER_PAGES];
+        for (page, trb) in trbs.iter_mut().enumerate().take(pages) {
+            let chunk = (len - page * PAGE_SIZE).min(PAGE_SIZE);
+            let flags = if page == pages - 1 { TRB_IOC } else { TRB_CHAIN };
+            *trb = Trb::new(TRB_NORMAL, buffer + (page * PAGE_SIZE) as u64, chunk as u32, flags);
+        }
+
+        let residual = self.transfer(state, slot, endpoint.context_index(), &trbs[..pages])?;
+        Ok(len.saturating_sub(residual))
+    }
+
+    /// Put a transfer descriptor on the ring of endpoint `index` and wait for its completion. Returns the residual length.
+    fn transfer(&self, state: &mut State, slot: u8, index: u8, trbs: &[Trb]) -> Result<usize, TransferError> {
+        let ring = state.slots.get_mut(&slot)
+            .and_then(|slot_state| slot_state.rings.get_mut(&index))
+            .ok_or(TransferError::NotConfigured)?;
+        ring.reserve(trbs.len());
+        for trb in trbs {
+            ring.push(*trb);
+        }
+        self.doorbells.write(4 * slot as u64, index as u32);
+
+        // there is only one transfer in flight, so the first event of the endpoint ends it (even on errors in earlier TRBs)
+        let event = self.wait_event(state, TRANSFER_TIMEOUT_MS, |event| {
+            event.trb_type() == TRB_TRANSFER_EVENT && event.slot() == slot && event.endpoint() == index
+        }).ok_or(TransferError::Timeout)?;
+        match event.completion_code() {
+            COMPLETION_SUCCESS | COMPLETION_SHORT_PACKET => Ok(event.residual()),
+            COMPLETION_STALL => Err(TransferError::Stall),
+            code => Err(TransferError::Failed(code)),
+        }
+    }
+
+    /// Put `trb` on the command ring and wait for its completion.
+    fn command(&self, state: &mut State, trb: Trb) -> Result<Trb, TransferError> {
+        let address = state.commands.push(trb);
+        self.doorbells.write(0, 0);
+
+        let event = self.wait_event(state, COMMAND_TIMEOUT_MS, |event| {
+            event.trb_type() == TRB_COMMAND_COMPLETION && event.parameter == address
+        }).ok_or(TransferError::Timeout)?;
+        match event.completion_code() {
+            COMPLETION_SUCCESS => Ok(event),
+            code => Err(TransferError::Failed(code)),
+        }
+    }
+
+    /// Poll the event ring until an event matching `matches` arrives. Other events (e.g. port status changes) are dropped.
+    fn wait_event(&self, state: &mut State, timeout_ms: usize, matches: impl Fn(&Trb) -> bool) -> Option<Trb> {
+        let deadline = timer().systime_ms() + timeout_ms;
+        loop {
+            while let Some(event) = state.events.next() {
+                self.runtime.write64(ERDP, state.events.dequeue_pointer() | ERDP_BUSY);
+                if matches(&event) {
+                    return Some(event);
+                }
+            }
+            if timer().systime_ms() >= deadline {
+                return None;
+            }
+            spin_loop();
+        }
+    }
+
+    /// Fill the input context of a slot: `add_flags` selects the contexts to evaluate,
+    /// the slot context covers the endpoints up to `last_index`. The control endpoint is always described.
+    fn prepare_input_context(&self, slot: &Slot, add_flags: u32, last_index: u8) {
+        unsafe { ptr::write_bytes(slot.input.vaddr(0).as_ptr(), 0, PAGE_SIZE) };
+        self.write_context(slot, 0, 1, add_flags | 1);
+        self.write_context(slot, 1, 0, ((slot.speed as u32) << 20) | ((last_index as u32) << 27));
+        self.write_context(slot, 1, 1, (slot.port as u32) << 16);
+
+        let dequeue = slot.rings[&1].dequeue_pointer();
+        self.write_endpoint_context(slot, 1, EP_CONTROL, slot.max_packet_size, dequeue, 8);
+    }
+
+    fn write_endpoint_context(&self, slot: &Slot, index: u8, ep_type: u32, max_packet_size: u16, dequeue: u64, average_length: u32) {
+        // the input context starts with the input control context, so endpoint contexts are shifted by one
+        let context = index as usize + 1;
+        self.write_context(slot, context, 1, (EP_ERROR_COUNT << 1) | (ep_type << 3) | ((max_packet_size as u32) << 16));
+        self.write_context(slot, context, 2, dequeue as u32);
+        self.write_context(slot, context, 3, (dequeue >> 32) as u32);
+        self.write_context(slot, context, 4, average_length);
+    }
+
+    fn write_context(&self, slot: &Slot, context: usize, dword: usize, value: u32) {
+        let offset = context * self.context_size + dword * 4;
+        unsafe { ptr::write_volatile(slot.input.vaddr(offset).as_ptr() as *mut u32, value) };
+    }
+}
+
+unsafe impl Send for Xhci {}
+unsafe impl Sync for Xhci {}
+
+/// Request the controller from the firmware, which may be using it to emulate a PS/2 keyboard.
+fn take_ownership(capabilities: Mmio, hccparams1: u32) {
+    let mut offset = ((hccparams1 >> 16) << 2) as u64;
+    while offset != 0 {
+        let capability = capabilities.read(offset);
+        if capability & 0xff == CAPABILITY_LEGACY {
+            capabilities.write(offset, capability | LEGACY_OS_OWNED);
+            if !wait_until(RESET_TIMEOUT_MS, || capabilities.read(offset) & LEGACY_BIOS_OWNED == 0) {
+                warn!("xHCI: Firmware does not release the controller, taking it anyway");
+                capabilities.write(offset, (capabilities.read(offset) & !LEGACY_BIOS_OWNED) | LEGACY_OS_OWNED);
+            }
+            let control = capabilities.read(offset + 4);
+            capabilities.write(offset + 4, (control & LEGACY_DISABLE_SMI) | LEGACY_SMI_EVENTS);
+            return;
+        }
+
+        let next = ((capability >> 8) & 0xff) as u64;
+        offset = if next == 0 { 0 } else { offset + (next << 2) };
+    }
+}
+
+/// Wait until `condition` is true, but at most `timeout_ms` milliseconds. Returns false on timeout.
+fn wait_until(timeout_ms: usize, condition: impl Fn() -> bool) -> bool {
+    let deadline = timer().systime_ms() + timeout_ms;
+    while !condition() {
+        if timer().systime_ms() >= deadline {
+            return false;
+        }
+        scheduler().sleep(1);
+    }
+    true
+}
+
+/// The controller expects all structures to be zeroed
+fn zeroed_dma(pages: usize) -> Dma {
+    let dma = Dma::new(pages);
+    unsafe { ptr::write_bytes(dma.vaddr(0).as_ptr(), 0, pages * PAGE_SIZE) };
+    dma
+}
+
+fn copy_to_dma(dma: &Dma, data: &[u8]) {
+    unsafe { ptr::copy_nonoverlapping(data.as_ptr(), dma.vaddr(0).as_ptr(), data.len()) };
+}
+
+fn copy_from_dma(dma: &Dma, data: &mut [u8]) {
+    unsafe { ptr::copy_nonoverlapping(dma.vaddr(0).as_ptr(), data.as_mut_ptr(), data.len()) };
+}
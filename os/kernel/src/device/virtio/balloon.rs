@@ -0,0 +1,257 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use log::{info, warn};
+use spin::{Mutex, Once};
+use virtio::queue::VirtQueue;
+use virtio::transport::Transport;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::PhysFrame;
+
+use crate::memory::{self, PAGE_SIZE};
+use crate::process::thread::Thread;
+use crate::scheduler;
+
+use super::hal::HalImpl;
+use super::VirtioTransport;
+use syscall::priority::Priority;
+
+const QUEUE_INFLATE: u16 = 0;
+const QUEUE_DEFLATE: u16 = 1;
+const QUEUE_STATS: u16 = 2;
+const QUEUE_SIZE: usize = 16;
+/// Offsets in the configuration space: the number of pages requested by the host
+/// and the number of pages actually in the balloon (written by the driver)
+const CONFIG_NUM_PAGES: usize = 0;
+const CONFIG_ACTUAL: usize = 4;
+/// The host is asked for its target this often
+const ADJUST_INTERVAL_MS: usize = 1000;
+/// Maximum number of pages given to (or taken from) the host with a single request
+const PAGES_PER_REQUEST: usize = 256;
+/// The balloon never takes the last free frames (16 MiB), the frame allocator can't handle running out of memory
+const MIN_FREE_FRAMES: usize = 4096;
+
+/// Tags of the memory statistics (virtio 1.2, 5.5.6.3)
+const STAT_MEMFREE: u16 = 4;
+const STAT_MEMTOT: u16 = 5;
+const STAT_AVAIL: u16 = 6;
+/// Each statistic consists of a 16 bit tag and a 64 bit value
+const STAT_SIZE: usize = 10;
+const STAT_COUNT: usize = 3;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Feature: u64 {
+        /// Pages must not be used, before the host has been told about deflating
+        const MUST_TELL_HOST = 1 << 0;
+        /// The driver reports memory statistics via a third queue
+        const STATS_VQ = 1 << 1;
+        const VERSION_1 = 1 << 32;
+    }
+}
+
+/// There can only be one balloon
+static BALLOON: Once<Mutex<VirtioBalloon>> = Once::new();
+
+/// A virtio-balloon device. The host sets a target for the number of pages, that the guest gives back.
+/// These pages are taken from the frame allocator (inflating) and returned to it, once the host lowers its target (deflating).
+struct VirtioBalloon {
+    transport: VirtioTransport,
+    inflate_queue: VirtQueue<HalImpl, QUEUE_SIZE>,
+    deflate_queue: VirtQueue<HalImpl, QUEUE_SIZE>,
+    stats_queue: Option<VirtQueue<HalImpl, QUEUE_SIZE>>,
+    /// Frames currently given to the host
+    frames: Vec<PhysFrame>,
+    /// Target of the host, as seen the last time (only for logging)
+    target: usize,
+    /// Statistics buffer, which the device keeps until it wants new statistics.
+    /// It is boxed, because its address must not change while it is in the queue.
+    stats: Box<[u8; STAT_SIZE * STAT_COUNT]>,
+    stats_token: Option<u16>,
+}
+
+/// Initialize the driver for `transport` and start the thread, that follows the host's requests.
+pub fn plugin(mut transport: VirtioTransport) {
+    if BALLOON.is_completed() {
+        warn!("Only one VirtIO balloon is supported, skipping");
+        return;
+    }
+
+    let features = transport.begin_init(Feature::MUST_TELL_HOST | Feature::STATS_VQ | Feature::VERSION_1);
+    let (Some(inflate_queue), Some(deflate_queue)) = (
+        create_queue(&mut transport, QUEUE_INFLATE),
+        create_queue(&mut transport, QUEUE_DEFLATE),
+    ) else {
+        return;
+    };
+    let stats_queue = if features.contains(Feature::STATS_VQ) { create_queue(&mut transport, QUEUE_STATS) } else { None };
+    transport.finish_init();
+
+    let mut balloon = VirtioBalloon {
+        transport,
+        inflate_queue,
+        deflate_queue,
+        stats_queue,
+        frames: Vec::new(),
+        target: 0,
+        stats: Box::new([0; STAT_SIZE * STAT_COUNT]),
+        stats_token: None,
+    };
+    // Der Host fordert Statistiken an, indem er den ersten Puffer zurückgibt
+    balloon.send_stats();
+
+    BALLOON.call_once(|| Mutex::new(balloon));
+    let thread = Thread::new_kernel_thread(adjust_thread, "virtio-balloon");
+    thread.set_priority(Priority::Background);
+    scheduler().ready(thread);
+}
+
+fn create_queue(transport: &mut VirtioTransport, index: u16) -> Option<VirtQueue<HalImpl, QUEUE_SIZE>> {
+    VirtQueue::new(transport, index, false, false)
+        .inspect_err(|e| warn!("Failed to create VirtIO balloon queue {}: {:?}", index, e))
+        .ok()
+}
+
+/// Called from the virtio interrupt handler: the target and statistics requests are polled, so only acknowledge the interrupt.
+pub fn handle_interrupt() {
+    if let Some(mut balloon) = BALLOON.get().and_then(|balloon| balloon.try_lock()) {
+        balloon.transport.ack_interrupt();
+    }
+}
+
+extern "sysv64" fn adjust_thread() {
+    let balloon = BALLOON.get().expect("VirtIO balloon not initialized");
+    loop {
+        scheduler().sleep(ADJUST_INTERVAL_MS);
+
+        // In Schritten, damit Interrupts nicht zu lange gesperrt sind
+        while interrupts::without_interrupts(|| balloon.lock().adjust()) {}
+        interrupts::without_interrupts(|| balloon.lock().poll_stats());
+    }
+}
+
+impl VirtioBalloon {
+    /// Move the balloon one step towards the target of the host.
+    /// Returns `true`, if there are more pages to inflate or deflate.
+    fn adjust(&mut self) -> bool {
+        let target = self.transport.read_config_space::<u32>(CONFIG_NUM_PAGES).unwrap_or(0) as usize;
+        if target != self.target {
+            info!("VirtIO balloon: Host requests [{}] pages (currently [{}])", target, self.frames.len());
+            self.target = target;
+        }
+
+        let current = self.frames.len();
+        if target > current {
+            self.inflate((target - current).min(PAGES_PER_REQUEST))
+        } else if target < current {
+            self.deflate((current - target).min(PAGES_PER_REQUEST))
+        } else {
+            false
+        }
+    }
+
+    /// Give up to `count` free frames to the host.
+    fn inflate(&mut self, count: usize) -> bool {
+        let count = count.min(memory::get_total_free_frames().saturating_sub(MIN_FREE_FRAMES));
+        if count == 0 {
+            return false;
+        }
+
+        let frames: Vec<PhysFrame> = (0..count).map(|_| memory::alloc_frames(1).start).collect();
+        let pfns = page_frame_numbers(&frames);
+        match self.inflate_queue.add_notify_wait_pop(&[&pfns], &mut [], &mut self.transport) {
+            Ok(_) => {
+                self.frames.extend(frames);
+                self.update_actual();
+                true
+            }
+            Err(e) => {
+                warn!("VirtIO balloon: Failed to inflate: {:?}", e);
+                for frame in frames {
+                    memory::free_frames(PhysFrame::range(frame, frame + 1));
+                }
+                false
+            }
+        }
+    }
+
+    /// Take `count` frames back from the host and return them to the frame allocator.
+    fn deflate(&mut self, count: usize) -> bool {
+        let frames = self.frames.split_off(self.frames.len() - count);
+        let pfns = page_frame_numbers(&frames);
+        // Die Frames werden erst nach der Antwort des Hosts wieder freigegeben (MUST_TELL_HOST)
+        match self.deflate_queue.add_notify_wait_pop(&[&pfns], &mut [], &mut self.transport) {
+            Ok(_) => {
+                for frame in frames {
+                    memory::free_frames(PhysFrame::range(frame, frame + 1));
+                }
+                self.update_actual();
+                true
+            }
+            Err(e) => {
+                warn!("VirtIO balloon: Failed to deflate: {:?}", e);
+                self.frames.extend(frames);
+                false
+            }
+        }
+    }
+
+    fn update_actual(&mut self) {
+        if let Err(e) = self.transport.write_config_space::<u32>(CONFIG_ACTUAL, self.frames.len() as u32) {
+            warn!("VirtIO balloon: Failed to update number of pages: {:?}", e);
+        }
+    }
+
+    /// The host has returned the statistics buffer, when it wants new statistics.
+    fn poll_stats(&mut self) {
+        let (Some(queue), Some(token)) = (self.stats_queue.as_mut(), self.stats_token) else {
+            return;
+        };
+        if !queue.can_pop() {
+            return;
+        }
+
+        let stats: &[u8] = self.stats.as_slice();
+        // Safety: The buffer is the one added with this token
+        if let Err(e) = unsafe { queue.pop_used(token, &[stats], &mut []) } {
+            warn!("VirtIO balloon: Failed to receive statistics request: {:?}", e);
+        }
+        self.stats_token = None;
+        self.send_stats();
+    }
+
+    /// Fill the statistics buffer with the current memory usage and hand it to the host.
+    fn send_stats(&mut self) {
+        let Some(queue) = self.stats_queue.as_mut() else {
+            return;
+        };
+
+        let free = (memory::get_total_free_frames() * PAGE_SIZE) as u64;
+        let total = (memory::get_total_frames() * PAGE_SIZE) as u64;
+        let stats = [(STAT_MEMFREE, free), (STAT_MEMTOT, total), (STAT_AVAIL, free)];
+        for (i, (tag, value)) in stats.into_iter().enumerate() {
+            let entry = &mut self.stats[i * STAT_SIZE..(i + 1) * STAT_SIZE];
+            entry[..2].copy_from_slice(&tag.to_le_bytes());
+            entry[2..].copy_from_slice(&value.to_le_bytes());
+        }
+
+        let stats: &[u8] = self.stats.as_slice();
+        // Safety: The buffer is boxed and not touched, until the device has returned it (see `poll_stats()`)
+        match unsafe { queue.add(&[stats], &mut []) } {
+            Ok(token) => {
+                self.stats_token = Some(token);
+                if queue.should_notify() {
+                    self.transport.notify(QUEUE_STATS);
+                }
+            }
+            Err(e) => warn!("VirtIO balloon: Failed to send statistics: {:?}", e),
+        }
+    }
+}
+
+/// The device expects 32 bit page frame numbers (of 4 KiB pages, independent of the guest's page size).
+fn page_frame_numbers(frames: &[PhysFrame]) -> Vec<u8> {
+    frames.iter()
+        .flat_map(|frame| ((frame.start_address().as_u64() / 4096) as u32).to_le_bytes())
+        .collect()
+}
@@ -0,0 +1,118 @@
+use alloc::sync::Arc;
+use log::error;
+use spin::Mutex;
+use virtio::device::blk::{VirtIOBlk, SECTOR_SIZE};
+
+use crate::device::stats::{self, DeviceStats};
+use crate::storage::add_block_device;
+use crate::storage::block::BlockDevice;
+
+use super::hal::HalImpl;
+use super::VirtioTransport;
+
+const STATS_COUNTERS: &[&str] = &["read_requests", "write_requests", "sectors_read", "sectors_written", "flushes", "errors"];
+
+/// A virtio block device, registered in the storage module (as "vd0", "vd1", etc.).
+pub struct VirtioBlockDevice {
+    blk: Mutex<VirtIOBlk<HalImpl, VirtioTransport>>,
+    capacity: u64,
+    stats: Arc<DeviceStats>,
+}
+
+impl VirtioBlockDevice {
+    /// Initialize the driver for `transport` and register the device as a block device.
+    pub fn plugin(transport: VirtioTransport) {
+        let blk = match VirtIOBlk::<HalImpl, VirtioTransport>::new(transport) {
+            Ok(blk) => blk,
+            Err(e) => {
+                error!("Failed to create VirtIO Block driver: {:?}", e);
+                return;
+            }
+        };
+
+        let device = Arc::new(Self {
+            capacity: blk.capacity(),
+            blk: Mutex::new(blk),
+            stats: DeviceStats::new(STATS_COUNTERS),
+        });
+        let stats = Arc::clone(&device.stats);
+        let name = add_block_device("vd", device);
+        stats::register(&name, stats);
+    }
+
+    /// Clamp a request to the size of the device and return the number of sectors that can be processed.
+    fn sectors_available(&self, sector: u64, count: usize, buffer_len: usize) -> usize {
+        if sector >= self.capacity {
+            return 0;
+        }
+        count
+            .min((self.capacity - sector) as usize)
+            .min(buffer_len / SECTOR_SIZE)
+    }
+
+    fn update_stats(&self, requests: &str, sectors: &str, count: usize, processed: usize) {
+        self.stats.inc(requests);
+        self.stats.add(sectors, processed as u64);
+        if processed < count {
+            self.stats.inc("errors");
+        }
+    }
+}
+
+impl BlockDevice for VirtioBlockDevice {
+    fn read(&self, sector: u64, count: usize, buffer: &mut [u8]) -> usize {
+        let sectors = self.sectors_available(sector, count, buffer.len());
+        let processed = if sectors == 0 {
+            0
+        } else {
+            match self.blk.lock().read_blocks(sector as usize, &mut buffer[..sectors * SECTOR_SIZE]) {
+                Ok(()) => sectors,
+                Err(e) => {
+                    error!("VirtIO Block: Failed to read {} sectors at {}: {:?}", sectors, sector, e);
+                    0
+                }
+            }
+        };
+
+        self.update_stats("read_requests", "sectors_read", count, processed);
+        processed
+    }
+
+    fn write(&self, sector: u64, count: usize, buffer: &[u8]) -> usize {
+        let sectors = self.sectors_available(sector, count, buffer.len());
+        let processed = if sectors == 0 {
+            0
+        } else {
+            match self.blk.lock().write_blocks(sector as usize, &buffer[..sectors * SECTOR_SIZE]) {
+                Ok(()) => sectors,
+                Err(e) => {
+                    error!("VirtIO Block: Failed to write {} sectors at {}: {:?}", sectors, sector, e);
+                    0
+                }
+            }
+        };
+
+        self.update_stats("write_requests", "sectors_written", count, processed);
+        processed
+    }
+
+    fn flush(&self) -> bool {
+        self.stats.inc("flushes");
+        match self.blk.lock().flush() {
+            Ok(()) => true,
+            Err(e) => {
+                error!("VirtIO Block: Failed to flush: {:?}", e);
+                self.stats.inc("errors");
+                false
+            }
+        }
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.capacity
+    }
+
+    fn sector_size(&self) -> u16 {
+        SECTOR_SIZE as u16
+    }
+}
@@ -0,0 +1,66 @@
+use alloc::sync::Arc;
+use log::error;
+use spin::Mutex;
+use stream::OutputStream;
+use virtio::device::console::VirtIOConsole;
+
+use crate::device::qemu_cfg;
+use crate::logger;
+
+use super::hal::HalImpl;
+use super::VirtioTransport;
+
+/// virtio-console, used as an additional log sink and terminal input when running under QEMU
+/// (e.g. with `-device virtio-serial-pci -device virtconsole,chardev=...`).
+pub struct VirtioConsole {
+    console: Mutex<VirtIOConsole<HalImpl, VirtioTransport>>,
+}
+
+impl VirtioConsole {
+    /// Initialize the driver for `transport`. Under QEMU, the console is registered as log output.
+    pub fn new(transport: VirtioTransport) -> Option<Arc<Self>> {
+        let console = match VirtIOConsole::<HalImpl, VirtioTransport>::new(transport) {
+            Ok(console) => console,
+            Err(e) => {
+                error!("Failed to create VirtIO Console driver: {:?}", e);
+                return None;
+            }
+        };
+
+        let console = Arc::new(Self { console: Mutex::new(console) });
+        if qemu_cfg::is_available() {
+            logger().register(Arc::clone(&console) as Arc<dyn OutputStream>);
+        }
+        Some(console)
+    }
+
+    /// Read a received byte, if there is one.
+    pub fn try_read_byte(&self) -> Option<u8> {
+        // Nicht blockieren, falls gerade jemand schreibt
+        let mut console = self.console.try_lock()?;
+        console.recv(true).ok().flatten()
+    }
+
+    /// Acknowledge an interrupt of the device (called from the interrupt handler).
+    pub fn ack_interrupt(&self) {
+        if let Some(mut console) = self.console.try_lock() {
+            let _ = console.ack_interrupt();
+        }
+    }
+}
+
+impl OutputStream for VirtioConsole {
+    fn write_byte(&self, b: u8) {
+        let _ = self.console.lock().send(b);
+    }
+
+    fn write_str(&self, string: &str) {
+        let mut console = self.console.lock();
+        for b in string.bytes() {
+            if b == b'\n' {
+                let _ = console.send(b'\r');
+            }
+            let _ = console.send(b);
+        }
+    }
+}
@@ -21,7 +21,7 @@ use crate::keyboard;
 use crate::syscall::sys_time::sys_get_system_time;
 
 use super::super::hal::HalImpl;
-use virtio::transport::pci::PciTransport;
+use super::super::VirtioTransport;
 use virtio::device::gpu::VirtIOGpu;
 
 use spin::Mutex;
@@ -221,7 +221,7 @@ fn draw_digit(gfx: &mut Graphics, digit: usize, x: usize, y: usize) {
 }
 
 /// Entry point for the Pong demo. Blocks indefinitely running the game loop.
-pub fn pong_demo(gpu_mutex: &Mutex<VirtIOGpu<HalImpl, PciTransport>>) {
+pub fn pong_demo(gpu_mutex: &Mutex<VirtIOGpu<HalImpl, VirtioTransport>>) {
     // Initialize framebuffer and resolution
     let (fb_ptr, fb_len, width, height) = {
         let mut gpu = gpu_mutex.lock();
@@ -19,7 +19,7 @@ use crate::syscall::sys_concurrent::sys_thread_sleep;
 use crate::syscall::sys_time::sys_get_system_time;
 
 use super::super::hal::HalImpl;
-use virtio::transport::pci::PciTransport;
+use super::super::VirtioTransport;
 use virtio::device::gpu::VirtIOGpu;
 
 use spin::Mutex;
@@ -46,7 +46,7 @@ struct MovingRect {
 }
 
 /// Starts the rectangle animation demo on the Virtio GPU framebuffer.
-pub fn rectangle_demo(gpu_mutex: &Mutex<VirtIOGpu<HalImpl, PciTransport>>) {
+pub fn rectangle_demo(gpu_mutex: &Mutex<VirtIOGpu<HalImpl, VirtioTransport>>) {
     
     // 1. Initial Setup: Retrieve resolution and framebuffer pointer safely.
     // We make these variables mutable so we can update them on resize events.
@@ -0,0 +1,89 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use graphic::lfb::FramebufferInfo;
+use log::{error, info};
+use x86_64::instructions::interrupts;
+
+use crate::device::display;
+use crate::process::thread::Thread;
+use crate::scheduler;
+
+use super::{hal, virtio_gpu, GPU_CONFIG_PENDING};
+
+/// virtio-gpu uses 32 bits per pixel (B8G8R8A8)
+const BPP: u8 = 32;
+/// Interval in which the framebuffer is transferred to the host (~30 FPS)
+const FLUSH_INTERVAL_MS: usize = 33;
+
+/// Address of the framebuffer, the graphics code draws to (0, if there is none yet)
+static FRAMEBUFFER: AtomicU64 = AtomicU64::new(0);
+
+/// Use the virtio-gpu as framebuffer for the terminal and graphics code.
+///
+/// A 2D resource with the preferred resolution of the host is created and attached to the first scanout.
+/// As virtio-gpu does not scan out guest memory by itself, a kernel thread regularly flushes
+/// the framebuffer to the host. If the host changes the resolution (e.g. by resizing the window),
+/// the framebuffer is set up again.
+pub fn init() {
+    if !setup_framebuffer() {
+        return;
+    }
+    scheduler().ready(Thread::new_kernel_thread(flush_thread, "virtio-gpu"));
+}
+
+/// Query the resolution, set up a framebuffer and hand it to the graphics code.
+///
+/// When the framebuffer is replaced, virtio-drivers frees the old one while setting up the new one.
+/// Its memory is retained until the graphics code and the mappings of the applications
+/// have been moved to the new framebuffer (see `hal::retain()`).
+fn setup_framebuffer() -> bool {
+    let Some(gpu) = virtio_gpu() else {
+        return false;
+    };
+
+    let old_framebuffer = FRAMEBUFFER.load(Ordering::Acquire);
+    if old_framebuffer != 0 {
+        hal::retain(old_framebuffer as usize);
+    }
+
+    // Interrupts aus, damit der Interrupt-Handler nicht auf das gesperrte Gerät wartet
+    let result = interrupts::without_interrupts(|| {
+        let mut gpu = gpu.lock();
+        let (width, height) = gpu.resolution()?;
+        let buffer = gpu.setup_framebuffer()?;
+        Ok::<_, virtio::Error>((buffer.as_mut_ptr(), width, height))
+    });
+
+    match result {
+        Ok((buffer, width, height)) => {
+            let pitch = width * (BPP as u32 / 8);
+            // DMA-Speicher ist identitätsgemappt, virtuelle = physische Adresse
+            display::framebuffer_changed(FramebufferInfo { addr: buffer as u64, width, height, pitch, bpp: BPP });
+            FRAMEBUFFER.store(buffer as u64, Ordering::Release);
+            // nothing refers to the old framebuffer anymore
+            hal::release();
+            info!("Using VirtIO GPU framebuffer ({}x{})", width, height);
+            true
+        }
+        Err(e) => {
+            // the old framebuffer stays retained, because the graphics code still uses it
+            error!("Failed to set up VirtIO GPU framebuffer: {:?}", e);
+            false
+        }
+    }
+}
+
+extern "sysv64" fn flush_thread() {
+    let gpu = virtio_gpu().expect("VirtIO GPU vanished");
+    loop {
+        if GPU_CONFIG_PENDING.swap(false, Ordering::Acquire) {
+            info!("VirtIO GPU configuration changed, setting up framebuffer again");
+            setup_framebuffer();
+        }
+
+        if let Err(e) = interrupts::without_interrupts(|| gpu.lock().flush()) {
+            error!("Failed to flush VirtIO GPU framebuffer: {:?}", e);
+        }
+        scheduler().sleep(FLUSH_INTERVAL_MS);
+    }
+}
@@ -1,19 +1,41 @@
-use crate::memory;
+use crate::memory::{self, PAGE_SIZE};
+use crate::process_manager;
 
 use super::dma::Dma;
-use core::ptr::NonNull;
+use core::ptr::{self, NonNull};
+use spin::Mutex;
 use virtio::{BufferDirection, Hal, PhysAddr};
 use x86_64::structures::paging::PhysFrame;
+use x86_64::structures::paging::frame::PhysFrameRange;
 
 pub struct HalImpl;
 
+/// DMA-Speicher, der erst mit `release()` freigegeben werden darf, und die Seiten, falls virtio-drivers ihn schon freigegeben hat
+static RETAINED: Mutex<Option<(PhysAddr, Option<PhysFrameRange>)>> = Mutex::new(None);
+
+/// Verzögert die Freigabe des DMA-Speichers ab `paddr` bis zum Aufruf von `release()`
+/// (z.B. den alten Framebuffer, auf den noch gezeichnet wird, während virtio-drivers schon einen neuen einrichtet).
+pub fn retain(paddr: PhysAddr) {
+    *RETAINED.lock() = Some((paddr, None));
+}
+
+/// Gibt den mit `retain()` zurückgehaltenen Speicher frei, falls virtio-drivers ihn inzwischen freigegeben hat.
+pub fn release() {
+    if let Some((_, Some(frame_range))) = RETAINED.lock().take() {
+        memory::free_frames(frame_range);
+    }
+}
+
 unsafe impl Hal for HalImpl {
-    /// Alloziert physisch zusammenhängende Speicherseiten für DMA.
+    /// Alloziert physisch zusammenhängende, genullte Speicherseiten für DMA.
     fn dma_alloc(pages: usize, _direction: BufferDirection) -> (PhysAddr, NonNull<u8>) {
         let dma_buffer = Dma::new(pages);
 
         let paddr = dma_buffer.paddr().as_u64() as PhysAddr;
         let vaddr = dma_buffer.vaddr(0);
+
+        // virtio-drivers erwartet genullten Speicher (z.B. für die Virtqueues)
+        unsafe { vaddr.as_ptr().write_bytes(0, dma_buffer.size()); }
         
         // Speicher nur per dealloc vergebbar
         core::mem::forget(dma_buffer);
@@ -29,6 +51,14 @@ unsafe impl Hal for HalImpl {
             start: start_frame,
             end: start_frame + pages as u64,
         };
+
+        let mut retained = RETAINED.lock();
+        if let Some((retained_paddr, freed)) = retained.as_mut() && *retained_paddr == paddr {
+            *freed = Some(frame_range);
+            return 0;
+        }
+        drop(retained);
+
         memory::free_frames(frame_range);
         0
     }
@@ -39,14 +69,55 @@ unsafe impl Hal for HalImpl {
     }
 
     /// Gibt einen Speicherbereich für das Gerät frei und gibt die physische Adresse zurück.
-    unsafe fn share(buffer: NonNull<[u8]>, _direction: BufferDirection) -> PhysAddr {
-        buffer.as_ptr() as *mut u8 as PhysAddr
+    /// Ist der Puffer physisch nicht zusammenhängend (z.B. auf einem Stack), wird ein Bounce-Puffer verwendet.
+    unsafe fn share(buffer: NonNull<[u8]>, direction: BufferDirection) -> PhysAddr {
+        if let Some(paddr) = contiguous_phys_addr(buffer) {
+            return paddr;
+        }
+
+        let (paddr, bounce) = Self::dma_alloc(buffer.len().div_ceil(PAGE_SIZE).max(1), direction);
+        if direction != BufferDirection::DeviceToDriver {
+            unsafe { ptr::copy_nonoverlapping(buffer.as_ptr() as *const u8, bounce.as_ptr(), buffer.len()); }
+        }
+        paddr
     }
 
     /// Beendet die Freigabe eines Speicherbereichs für das Gerät.
-    unsafe fn unshare(_paddr: PhysAddr, _buffer: NonNull<[u8]>, _direction: BufferDirection) {
-        // Nichts tun
+    /// Bounce-Puffer werden zurückkopiert (falls das Gerät hineingeschrieben hat) und freigegeben.
+    unsafe fn unshare(paddr: PhysAddr, buffer: NonNull<[u8]>, direction: BufferDirection) {
+        if contiguous_phys_addr(buffer) == Some(paddr) {
+            return;
+        }
+
+        // Bounce-Puffer sind identitätsgemappt
+        let bounce = paddr as *const u8;
+        if direction != BufferDirection::DriverToDevice {
+            unsafe { ptr::copy_nonoverlapping(bounce, buffer.as_ptr() as *mut u8, buffer.len()); }
+        }
+        let pages = buffer.len().div_ceil(PAGE_SIZE).max(1);
+        unsafe { Self::dma_dealloc(paddr, NonNull::new_unchecked(bounce as *mut u8), pages); }
+    }
+}
+
+/// Übersetzt einen Puffer über die Seitentabellen in seine physische Adresse.
+/// Gibt `None` zurück, wenn der Puffer nicht gemappt oder physisch nicht zusammenhängend ist.
+fn contiguous_phys_addr(buffer: NonNull<[u8]>) -> Option<PhysAddr> {
+    let vaddr = buffer.as_ptr() as *mut u8 as u64;
+    let len = buffer.len().max(1) as u64;
+    let process = process_manager().read().current_process();
+    let address_space = &process.virtual_address_space;
+
+    let paddr = address_space.get_phys(vaddr)?.as_u64();
+    // jede weitere Seite muss direkt auf die vorherige folgen
+    let mut page = (vaddr & !(PAGE_SIZE as u64 - 1)) + PAGE_SIZE as u64;
+    while page < vaddr + len {
+        if address_space.get_phys(page)?.as_u64() != paddr + (page - vaddr) {
+            return None;
+        }
+        page += PAGE_SIZE as u64;
     }
+
+    Some(paddr as PhysAddr)
 }
 
 // Drop-Implementierung
@@ -0,0 +1,276 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use input::{MouseEvent, MOUSE_BUTTON_EXTRA, MOUSE_BUTTON_LEFT, MOUSE_BUTTON_MIDDLE, MOUSE_BUTTON_RIGHT, MOUSE_BUTTON_SIDE};
+use log::{debug, warn};
+use nolock::queues::{mpmc, DequeueError};
+use pc_keyboard::layouts::{AnyLayout, De105Key};
+use pc_keyboard::{DecodedKey, HandleControl, KeyEvent, Keyboard as PcKeyboard, ScancodeSet1};
+use spin::{Mutex, Once};
+use stream::{DecodedInputStream, RawInputStream};
+use virtio::device::input::VirtIOInput;
+
+use crate::device::{keyboard_wait, pointer};
+
+use super::hal::HalImpl;
+use super::VirtioTransport;
+
+const KEYBOARD_BUFFER_CAPACITY: usize = 128;
+
+// Ereignistypen und -codes aus Linux' input-event-codes.h
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const EV_ABS: u16 = 0x03;
+const SYN_REPORT: u16 = 0;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_WHEEL: u16 = 0x08;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const BTN_LEFT: u16 = 0x110;
+const BTN_RIGHT: u16 = 0x111;
+const BTN_MIDDLE: u16 = 0x112;
+const BTN_SIDE: u16 = 0x113;
+const BTN_EXTRA: u16 = 0x114;
+/// Key codes below this are keyboard keys, buttons (mouse, joystick, ...) start here
+const BTN_MISC: u16 = 0x100;
+
+/// Prefix for extended scancodes (set 1)
+const SCANCODE_EXTENDED: u8 = 0xe0;
+/// Set in a scancode (set 1), when the key is released
+const SCANCODE_RELEASED: u8 = 0x80;
+
+/// All virtio-input devices (QEMU uses separate devices for keyboard and mouse/tablet)
+static DEVICES: Mutex<Vec<VirtIOInput<HalImpl, VirtioTransport>>> = Mutex::new(Vec::new());
+static KEYBOARD: Once<Arc<VirtioKeyboard>> = Once::new();
+static MOUSE: Once<VirtioMouse> = Once::new();
+
+/// Keyboard fed by all virtio-input devices.
+/// Linux' key codes are translated into scancodes (set 1), so they can be decoded just like the PS/2 keyboard.
+pub struct VirtioKeyboard {
+    buffer: (mpmc::bounded::scq::Receiver<u8>, mpmc::bounded::scq::Sender<u8>),
+    decoder: Mutex<PcKeyboard<AnyLayout, ScancodeSet1>>,
+}
+
+/// Assembles the pointer events of all virtio-input devices and passes them to the pointer event queue
+struct VirtioMouse {
+    /// Event, that is being assembled until the device sends `SYN_REPORT`
+    pending: Mutex<PendingMouseEvent>,
+}
+
+#[derive(Default)]
+struct PendingMouseEvent {
+    event: MouseEvent,
+    changed: bool,
+}
+
+/// Add a virtio-input device (keyboard, mouse or tablet).
+pub fn plugin(transport: VirtioTransport) {
+    let device = match VirtIOInput::<HalImpl, VirtioTransport>::new(transport) {
+        Ok(device) => device,
+        Err(e) => {
+            warn!("Failed to create VirtIO Input driver: {:?}", e);
+            return;
+        }
+    };
+
+    KEYBOARD.call_once(|| Arc::new(VirtioKeyboard::new()));
+    MOUSE.call_once(VirtioMouse::new);
+    DEVICES.lock().push(device);
+}
+
+/// Get the keyboard, if there is any virtio-input device.
+pub fn virtio_keyboard() -> Option<Arc<VirtioKeyboard>> {
+    KEYBOARD.get().cloned()
+}
+
+/// Called from the virtio interrupt handler: take all pending events from the devices.
+pub fn handle_interrupt() {
+    // Im Interrupt nicht warten, die Ereignisse bleiben in der Queue des Geräts
+    let Some(mut devices) = DEVICES.try_lock() else {
+        return;
+    };
+    for device in devices.iter_mut() {
+        if device.ack_interrupt().is_empty() {
+            continue;
+        }
+        while let Some(event) = device.pop_pending_event() {
+            handle_event(event.event_type, event.code, event.value);
+        }
+    }
+}
+
+fn handle_event(event_type: u16, code: u16, value: u32) {
+    match event_type {
+        EV_KEY if code < BTN_MISC => {
+            if let Some(keyboard) = KEYBOARD.get() {
+                keyboard.add_key(code, value);
+                keyboard_wait::notify();
+            }
+        }
+        EV_KEY | EV_REL | EV_ABS | EV_SYN => {
+            if let Some(mouse) = MOUSE.get() {
+                mouse.add_event(event_type, code, value);
+            }
+        }
+        _ => debug!("Ignoring VirtIO input event: type={}, code={}, value={}", event_type, code, value),
+    }
+}
+
+/// Translate a Linux key code into a scancode (set 1). Returns `None` for unknown keys.
+/// For most keys, both are the same, the others are prefixed with `SCANCODE_EXTENDED`.
+fn scancode(code: u16) -> Option<(bool, u8)> {
+    match code {
+        1..=88 => Some((false, code as u8)),
+        96 => Some((true, 0x1c)), // KEY_KPENTER
+        97 => Some((true, 0x1d)), // KEY_RIGHTCTRL
+        98 => Some((true, 0x35)), // KEY_KPSLASH
+        99 => Some((true, 0x37)), // KEY_SYSRQ
+        100 => Some((true, 0x38)), // KEY_RIGHTALT
+        102 => Some((true, 0x47)), // KEY_HOME
+        103 => Some((true, 0x48)), // KEY_UP
+        104 => Some((true, 0x49)), // KEY_PAGEUP
+        105 => Some((true, 0x4b)), // KEY_LEFT
+        106 => Some((true, 0x4d)), // KEY_RIGHT
+        107 => Some((true, 0x4f)), // KEY_END
+        108 => Some((true, 0x50)), // KEY_DOWN
+        109 => Some((true, 0x51)), // KEY_PAGEDOWN
+        110 => Some((true, 0x52)), // KEY_INSERT
+        111 => Some((true, 0x53)), // KEY_DELETE
+        125 => Some((true, 0x5b)), // KEY_LEFTMETA
+        126 => Some((true, 0x5c)), // KEY_RIGHTMETA
+        127 => Some((true, 0x5d)), // KEY_COMPOSE
+        _ => None,
+    }
+}
+
+impl VirtioKeyboard {
+    fn new() -> Self {
+        Self {
+            buffer: mpmc::bounded::scq::queue(KEYBOARD_BUFFER_CAPACITY),
+            decoder: Mutex::new(PcKeyboard::new(ScancodeSet1::new(), AnyLayout::De105Key(De105Key), HandleControl::Ignore)),
+        }
+    }
+
+    /// `value` is 0 for release, 1 for press and 2 for autorepeat
+    fn add_key(&self, code: u16, value: u32) {
+        let Some((extended, scancode)) = scancode(code) else {
+            debug!("Ignoring unknown VirtIO key code {}", code);
+            return;
+        };
+        let scancode = if value == 0 { scancode | SCANCODE_RELEASED } else { scancode };
+
+        if extended {
+            self.enqueue(SCANCODE_EXTENDED);
+        }
+        self.enqueue(scancode);
+    }
+
+    fn enqueue(&self, byte: u8) {
+        // the oldest bytes are dropped, if nobody reads the keyboard
+        while self.buffer.1.try_enqueue(byte).is_err() {
+            if self.buffer.0.try_dequeue().is_err() {
+                panic!("VirtIO Keyboard: Failed to store scancode in buffer!");
+            }
+        }
+    }
+
+    fn next_key_event(&self) -> Option<KeyEvent> {
+        let mut decoder = self.decoder.lock();
+        loop {
+            let scancode = match self.buffer.0.try_dequeue() {
+                Ok(scancode) => scancode,
+                Err(DequeueError::Closed) => panic!("VirtIO Keyboard stream closed!"),
+                Err(DequeueError::Empty) => return None,
+            };
+            // the prefix of extended keys doesn't produce an event on its own
+            if let Ok(Some(event)) = decoder.add_byte(scancode) {
+                return Some(event);
+            }
+        }
+    }
+}
+
+impl DecodedInputStream for VirtioKeyboard {
+    fn decoded_read_byte(&self) -> i16 {
+        loop {
+            if let Some(byte) = self.decoded_try_read_byte() {
+                return byte;
+            }
+        }
+    }
+
+    fn decoded_try_read_byte(&self) -> Option<i16> {
+        let event = self.next_key_event()?;
+        match self.decoder.lock().process_keyevent(event)? {
+            DecodedKey::Unicode(c) => Some(c as i16),
+            _ => None,
+        }
+    }
+}
+
+impl RawInputStream for VirtioKeyboard {
+    fn read_event(&self) -> KeyEvent {
+        loop {
+            if let Some(event) = self.read_event_nb() {
+                return event;
+            }
+        }
+    }
+
+    fn read_event_nb(&self) -> Option<KeyEvent> {
+        self.next_key_event()
+    }
+}
+
+impl VirtioMouse {
+    fn new() -> Self {
+        Self {
+            pending: Mutex::new(PendingMouseEvent::default()),
+        }
+    }
+
+    fn add_event(&self, event_type: u16, code: u16, value: u32) {
+        let mut pending = self.pending.lock();
+        let event = &mut pending.event;
+        match (event_type, code) {
+            (EV_REL, REL_X) => event.dx += value as i32,
+            (EV_REL, REL_Y) => event.dy += value as i32,
+            (EV_REL, REL_WHEEL) => event.wheel += value as i32,
+            (EV_ABS, ABS_X) => {
+                event.x = value;
+                event.absolute = true;
+            }
+            (EV_ABS, ABS_Y) => {
+                event.y = value;
+                event.absolute = true;
+            }
+            (EV_KEY, BTN_LEFT) => set_button(&mut event.buttons, MOUSE_BUTTON_LEFT, value != 0),
+            (EV_KEY, BTN_RIGHT) => set_button(&mut event.buttons, MOUSE_BUTTON_RIGHT, value != 0),
+            (EV_KEY, BTN_MIDDLE) => set_button(&mut event.buttons, MOUSE_BUTTON_MIDDLE, value != 0),
+            (EV_KEY, BTN_SIDE) => set_button(&mut event.buttons, MOUSE_BUTTON_SIDE, value != 0),
+            (EV_KEY, BTN_EXTRA) => set_button(&mut event.buttons, MOUSE_BUTTON_EXTRA, value != 0),
+            (EV_SYN, SYN_REPORT) => {
+                if pending.changed {
+                    let event = pending.event;
+                    pointer::push(event);
+                    // buttons and the absolute position stay the same until they are changed
+                    pending.event = MouseEvent { buttons: event.buttons, x: event.x, y: event.y, absolute: event.absolute, ..MouseEvent::default() };
+                    pending.changed = false;
+                }
+                return;
+            }
+            _ => return,
+        }
+        pending.changed = true;
+    }
+
+}
+
+fn set_button(buttons: &mut u8, button: u8, pressed: bool) {
+    if pressed {
+        *buttons |= button;
+    } else {
+        *buttons &= !button;
+    }
+}
@@ -1,5 +1,6 @@
+use crate::device::keyboard_wait;
 use crate::interrupt::interrupt_handler::InterruptHandler;
-use super::{virtio_gpu, GPU_QUEUE_PENDING, GPU_CONFIG_PENDING, virtio_input, VIRTIO_INPUT_PENDING, virtio_rng, virtio_sound};
+use super::{balloon, virtio_console, virtio_gpu, GPU_QUEUE_PENDING, GPU_CONFIG_PENDING, input, p9, virtio_rng, virtio_sound};
 use log::{debug};
 use virtio::transport::InterruptStatus;
 use core::sync::atomic::Ordering;
@@ -29,18 +30,8 @@ impl InterruptHandler for VirtioInterruptHandler {
                 }
             }
         }
-        // Input Handler
-        if let Some(input_dev) = virtio_input() {
-            if let Some(mut driver) = input_dev.try_lock() {
-                if !driver.ack_interrupt().is_empty() {
-                    //VIRTIO_INPUT_PENDING.store(true, Ordering::Release);
-                    debug!("virtio-input irq acknowledged.");
-                    // while let Some(event) = driver.pop_pending_event() {
-                    //     info!("VirtIO Input Event: type={}, code={}, value={}", event.event_type, event.code, event.value);
-                    // }
-                }
-            }
-        }
+        // Input Handler - Tastatur- und Mausereignisse sofort abholen
+        input::handle_interrupt();
         // RNG Handler
         if let Some(rng_dev) = virtio_rng() {
             if let Some(mut r) = rng_dev.try_lock() {
@@ -59,6 +50,15 @@ impl InterruptHandler for VirtioInterruptHandler {
                 // }
             }
         }
+        // Console Handler - empfangene Bytes werden per Polling abgeholt
+        if let Some(console) = virtio_console() {
+            console.ack_interrupt();
+            keyboard_wait::notify();
+        }
         // Socket Handler - nutzt polling
+        // 9P Handler - Antworten werden ebenfalls gepollt
+        p9::handle_interrupt();
+        // Balloon Handler - Vorgaben und Statistik-Anfragen werden gepollt
+        balloon::handle_interrupt();
     }
 }
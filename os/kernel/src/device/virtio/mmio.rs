@@ -0,0 +1,112 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ptr::NonNull;
+use log::{info, warn};
+use virtio::transport::mmio::{MmioError, MmioTransport, VirtIOHeader};
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::PhysAddr;
+
+use crate::interrupt::interrupt_dispatcher::InterruptVector;
+use crate::memory::vma::VmaType;
+use crate::memory::PAGE_SIZE;
+use crate::{apic, interrupt_dispatcher, process_manager};
+
+use super::interrupt::VirtioInterruptHandler;
+use super::plugin_device;
+
+/// Kernel parameter describing a virtio-mmio device (same format as in Linux):
+/// `virtio_mmio.device=<size>@<base address>:<irq>`, e.g. `virtio_mmio.device=512@0xfeb00000:12`
+const DEVICE_PARAMETER: &str = "virtio_mmio.device=";
+
+/// A virtio-mmio device, as described on the kernel command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MmioDevice {
+    pub base: u64,
+    pub size: u64,
+    pub irq: u8,
+}
+
+/// Initialize all virtio-mmio devices given on the kernel command line.
+/// There is no way to discover them (at least without a device tree), so they have to be passed by the bootloader.
+pub fn init_devices(cmdline: &str) {
+    let devices = parse_devices(cmdline);
+    if devices.is_empty() {
+        return;
+    }
+
+    info!("Searching for VirtIO MMIO devices...");
+    for device in devices {
+        info!("    VirtIO MMIO slot at {:#x} (size: {:#x}, IRQ: {})", device.base, device.size, device.irq);
+
+        // MMIO-Bereich identitätsmappen (auf Seiten ausgerichtet)
+        let start = PhysAddr::new(device.base).align_down(PAGE_SIZE as u64).as_u64();
+        let end = PhysAddr::new(device.base + device.size).align_up(PAGE_SIZE as u64).as_u64();
+        let kernel_process = process_manager().read().kernel_process().unwrap();
+        kernel_process.virtual_address_space.kernel_map_devm_identity(
+            start,
+            end,
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE,
+            VmaType::DeviceMemory,
+            "virtio-mmio",
+        );
+
+        let header = NonNull::new(device.base as *mut VirtIOHeader).unwrap();
+        match unsafe { MmioTransport::new(header, device.size as usize) } {
+            Ok(transport) => {
+                let interrupt_vector = InterruptVector::try_from(device.irq + 32).unwrap();
+                info!("    VirtIO device uses interrupt vector {:?}", interrupt_vector);
+                interrupt_dispatcher().assign(interrupt_vector, Box::new(VirtioInterruptHandler));
+                apic().allow(interrupt_vector);
+
+                plugin_device(transport.into());
+            }
+            // QEMU legt auch leere Slots an
+            Err(MmioError::ZeroDeviceId) => info!("    Empty VirtIO MMIO slot, skipping"),
+            Err(e) => warn!("    Invalid VirtIO MMIO device: {:?}", e),
+        }
+    }
+}
+
+/// Parse all `virtio_mmio.device=` parameters on the kernel command line. Invalid ones are skipped.
+pub fn parse_devices(cmdline: &str) -> Vec<MmioDevice> {
+    cmdline.split_whitespace()
+        .filter_map(|arg| arg.strip_prefix(DEVICE_PARAMETER))
+        .filter_map(|device| {
+            let parsed = parse_device(device);
+            if parsed.is_none() {
+                warn!("Invalid VirtIO MMIO device parameter [{}]", device);
+            }
+            parsed
+        })
+        .collect()
+}
+
+/// Parse `<size>@<base address>:<irq>` (an optional device id after the IRQ is ignored).
+fn parse_device(device: &str) -> Option<MmioDevice> {
+    let (size, rest) = device.split_once('@')?;
+    let mut rest = rest.split(':');
+    let base = parse_number(rest.next()?)?;
+    let irq = rest.next()?.parse().ok()?;
+    let size = parse_number(size)?;
+
+    // über IRQ 15 hinaus gibt es keine ISA-Interrupts
+    if size == 0 || irq > 15 {
+        return None;
+    }
+    Some(MmioDevice { base, size, irq })
+}
+
+/// Parse a number, which may be hexadecimal (`0x...`) and may have a size suffix (`K`, `M` or `G`).
+fn parse_number(number: &str) -> Option<u64> {
+    let (number, shift) = match number.as_bytes().last()? {
+        b'k' | b'K' => (&number[..number.len() - 1], 10),
+        b'm' | b'M' => (&number[..number.len() - 1], 20),
+        b'g' | b'G' => (&number[..number.len() - 1], 30),
+        _ => (number, 0),
+    };
+    let value = match number.strip_prefix("0x").or_else(|| number.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => number.parse().ok()?,
+    };
+    value.checked_mul(1 << shift)
+}
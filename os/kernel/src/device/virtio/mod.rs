@@ -1,12 +1,20 @@
 use core::sync::atomic::AtomicBool;
 
 use alloc::boxed::Box;
+use alloc::sync::Arc;
 use log::{error, info, warn};
-use spin::{Mutex, Once};
-use virtio::{device::{gpu::VirtIOGpu, input::VirtIOInput, rng::VirtIORng, socket::VirtIOSocket, sound::VirtIOSound}, transport::{Transport, pci::{PciTransport, bus::{BarInfo, ConfigurationAccess, DeviceFunction, PciRoot}}}};
+use pci_types::EndpointHeader;
+use spin::{Mutex, Once, RwLock};
+use virtio::{device::{gpu::VirtIOGpu, rng::VirtIORng, socket::{VirtIOSocket, VsockConnectionManager}, sound::VirtIOSound}, transport::{SomeTransport, Transport, pci::{PciTransport, bus::{BarInfo, ConfigurationAccess, DeviceFunction, PciRoot}}}};
 use x86_64::{PhysAddr, structures::paging::PageTableFlags};
 
-use crate::{apic, interrupt::interrupt_dispatcher::InterruptVector, interrupt_dispatcher, memory::{PAGE_SIZE, vma::VmaType}, pci_bus, process_manager};
+use crate::device::pci_irq::{self, IrqTypes};
+use crate::device::{pci_resources, registry};
+use crate::{memory::{PAGE_SIZE, vma::VmaType}, pci_bus, process_manager};
+use blk::VirtioBlockDevice;
+pub use console::VirtioConsole;
+pub use input::{virtio_keyboard, VirtioKeyboard};
+pub use p9::{virtio_9p_shares, Virtio9p};
 use interrupt::VirtioInterruptHandler;
 use hal::HalImpl;
 #[cfg(feature = "virtio_tests")]
@@ -14,208 +22,249 @@ use demo::{pong::pong_demo, rectangle::rectangle_demo, virtio_tests};
 
 #[cfg(feature = "virtio_tests")]
 mod demo;
-mod dma;
+mod balloon;
+mod blk;
+mod console;
+pub(crate) mod dma;
+#[cfg(not(feature = "virtio_tests"))]
+mod gpu_fb;
 mod hal;
+mod input;
 mod interrupt;
+mod mmio;
+mod p9;
+mod rng;
 
-static VIRTIO_RNG: Once<Mutex<VirtIORng<HalImpl, PciTransport>>> = Once::new();
+/// Transport of all virtio devices (PCI or MMIO)
+pub type VirtioTransport = SomeTransport<'static>;
 
-static VIRTIO_GPU: Once<Mutex<VirtIOGpu<HalImpl, PciTransport>>> = Once::new(); //Arc hinzufügen? Mutex pflicht
+const VIRTIO_VENDOR_ID: u16 = 0x1AF4;
+
+static VIRTIO_RNG: Once<Mutex<VirtIORng<HalImpl, VirtioTransport>>> = Once::new();
+
+static VIRTIO_GPU: Once<Mutex<VirtIOGpu<HalImpl, VirtioTransport>>> = Once::new(); //Arc hinzufügen? Mutex pflicht
 pub static GPU_QUEUE_PENDING:  AtomicBool = AtomicBool::new(false);
 pub static GPU_CONFIG_PENDING: AtomicBool = AtomicBool::new(false);
 
-static VIRTIO_INPUT: Once<Mutex<VirtIOInput<HalImpl, PciTransport>>> = Once::new();
-pub static VIRTIO_INPUT_PENDING: AtomicBool = AtomicBool::new(false);
+static VIRTIO_SOCKET: Once<Mutex<VsockConnectionManager<HalImpl, VirtioTransport>>> = Once::new();
 
-static VIRTIO_SOCKET: Once<Mutex<VirtIOSocket<HalImpl, PciTransport>>> = Once::new();
+static VIRTIO_SOUND: Once<Mutex<VirtIOSound<HalImpl, VirtioTransport>>> = Once::new();
 
-static VIRTIO_SOUND: Once<Mutex<VirtIOSound<HalImpl, PciTransport>>> = Once::new();
+static VIRTIO_CONSOLE: Once<Arc<VirtioConsole>> = Once::new();
 
-pub fn virtio_rng() -> Option<&'static Mutex<VirtIORng<HalImpl, PciTransport>>> {
+pub fn virtio_rng() -> Option<&'static Mutex<VirtIORng<HalImpl, VirtioTransport>>> {
     VIRTIO_RNG.get()
 }
 
-pub fn virtio_input() -> Option<&'static Mutex<VirtIOInput<HalImpl, PciTransport>>> {
-    VIRTIO_INPUT.get()
-}
-
-pub fn virtio_gpu() -> Option<&'static Mutex<VirtIOGpu<HalImpl, PciTransport>>> {
+pub fn virtio_gpu() -> Option<&'static Mutex<VirtIOGpu<HalImpl, VirtioTransport>>> {
     VIRTIO_GPU.get()
 }
 
-pub fn virtio_socket() -> Option<&'static Mutex<VirtIOSocket<HalImpl, PciTransport>>> {
+pub fn virtio_socket() -> Option<&'static Mutex<VsockConnectionManager<HalImpl, VirtioTransport>>> {
     VIRTIO_SOCKET.get()
 }
 
-pub fn virtio_sound() -> Option<&'static Mutex<VirtIOSound<HalImpl, PciTransport>>> {
+pub fn virtio_sound() -> Option<&'static Mutex<VirtIOSound<HalImpl, VirtioTransport>>> {
     VIRTIO_SOUND.get()
 }
 
-/// Find and initialize virtio devices
-pub fn init_devices(fb_start_phys_addr: u64, fb_end_phys_addr: u64) {
-    info!("Searching for VirtIO devices...");
-    let pci_bus = pci_bus();
-    let pci_config_space = pci_bus.config_space();
-    const VIRTIO_VENDOR_ID: u16 = 0x1AF4;
+pub fn virtio_console() -> Option<Arc<VirtioConsole>> {
+    VIRTIO_CONSOLE.get().cloned()
+}
 
-    let virtio_devices = pci_bus.search_by_vendor(VIRTIO_VENDOR_ID);
-    let mut pci_root = PciRoot::new(unsafe { pci_config_space.unsafe_clone() });
+/// Find and initialize virtio devices on the PCI bus and the MMIO devices given on the kernel command line
+pub fn init_devices(fb_start_phys_addr: u64, fb_end_phys_addr: u64, cmdline: Option<&str>) {
+    info!("Searching for VirtIO devices...");
+    let virtio_devices = pci_bus().search_by_vendor(VIRTIO_VENDOR_ID);
 
     if virtio_devices.is_empty() {
         info!("No VirtIO devices found on the PCI bus.");
     } else {
-        
-        for device_lock in virtio_devices.iter(){
-
-            let address = device_lock.read().header().address();
-
-            let device_function = DeviceFunction {
-                bus: address.bus(),
-                device: address.device(),
-                function: address.function(),
-            };
-
-            for bar_index in 0..6 {
-            if let Ok(Some(BarInfo::Memory { address, size, .. })) =
-                pci_root.bar_info(device_function, bar_index)
-            {
-                if size == 0 {
-                    continue;
-                }
+        for device_lock in virtio_devices.iter() {
+            init_pci_device(device_lock, fb_start_phys_addr, fb_end_phys_addr);
+        }
+    }
 
-                if address % PAGE_SIZE as u64 != 0 {
-                    warn!("      Skipping non-page-aligned 64-Bit Bar{} at {:#x}", bar_index, address);
-                    continue;
-                }
+    if let Some(cmdline) = cmdline {
+        mmio::init_devices(cmdline);
+    }
+}
 
-                let bar_start = address;
-                let bar_end   = address + size;
+/// Whether a PCI function is a virtio device (e.g. one, that has been hot-added)
+pub fn is_virtio_device(device_lock: &RwLock<EndpointHeader>) -> bool {
+    device_lock.read().header().id(pci_bus().config_space()).0 == VIRTIO_VENDOR_ID
+}
+
+/// Map the BARs of a virtio device on the PCI bus, set up its interrupt and initialize its driver.
+/// BARs overlapping the framebuffer of the bootloader (`fb_start_phys_addr..fb_end_phys_addr`) are mapped already.
+pub fn init_pci_device(device_lock: &RwLock<EndpointHeader>, fb_start_phys_addr: u64, fb_end_phys_addr: u64) {
+    let pci_bus = pci_bus();
+    let mut pci_root = PciRoot::new(unsafe { pci_bus.config_space().unsafe_clone() });
+    let address = device_lock.read().header().address();
+
+    let device_function = DeviceFunction {
+        bus: address.bus(),
+        device: address.device(),
+        function: address.function(),
+    };
+
+    for bar_index in 0..6 {
+        if let Ok(Some(BarInfo::Memory { address, size, .. })) =
+            pci_root.bar_info(device_function, bar_index)
+        {
+            if size == 0 {
+                continue;
+            }
+
+            if address % PAGE_SIZE as u64 != 0 {
+                warn!("      Skipping non-page-aligned 64-Bit Bar{} at {:#x}", bar_index, address);
+                continue;
+            }
+
+            let bar_start = address;
+            let bar_end   = address + size;
 
-                if overlaps(bar_start, bar_end, fb_start_phys_addr, fb_end_phys_addr) {
-                    // Bereits gemappte Multiboot-LFB (virtio-vga BAR0) - Reuse
-                    info!(
-                        "      BAR{} {:#x}..{:#x} overlaps framebuffer {:#x}..{:#x} -> reusing existing mapping 'framebuffer'",
-                        bar_index, bar_start, bar_end, fb_start_phys_addr, fb_end_phys_addr
+            if overlaps(bar_start, bar_end, fb_start_phys_addr, fb_end_phys_addr) {
+                // Bereits gemappte Multiboot-LFB (virtio-vga BAR0) - Reuse
+                info!(
+                    "      BAR{} {:#x}..{:#x} overlaps framebuffer {:#x}..{:#x} -> reusing existing mapping 'framebuffer'",
+                    bar_index, bar_start, bar_end, fb_start_phys_addr, fb_end_phys_addr
+                );
+                let fb_end_aligned = PhysAddr::new(fb_end_phys_addr).align_up(PAGE_SIZE as u64).as_u64();
+
+                if bar_end > fb_end_aligned {
+                    let tail_start = fb_end_aligned;
+                    let tail_end = bar_end;
+
+                    let tail_size = tail_end - tail_start; // Für Terminalausgabe
+
+                    info!("      Mapping BAR{} tail at {:#x} (size: {:#x})", bar_index, tail_start, tail_size);
+
+                    let kernel_process = process_manager().read().kernel_process().unwrap();
+                    kernel_process.virtual_address_space.kernel_map_devm_identity(
+                        tail_start,
+                        tail_end,
+                        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE,
+                        VmaType::DeviceMemory,
+                        "virtio-gpu-vram-tail",
                     );
-                    let fb_end_aligned = PhysAddr::new(fb_end_phys_addr).align_up(PAGE_SIZE as u64).as_u64();
-
-                    if bar_end > fb_end_aligned {
-                        let tail_start = fb_end_aligned;
-                        let tail_end = bar_end;
-                        
-                        let tail_size = tail_end - tail_start; // Für Terminalausgabe
-
-                        info!("      Mapping BAR{} tail at {:#x} (size: {:#x})", bar_index, tail_start, tail_size);
-                        
-                        let kernel_process = process_manager().read().kernel_process().unwrap();
-                        kernel_process.virtual_address_space.kernel_map_devm_identity(
-                            tail_start,
-                            tail_end,
-                            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE,
-                            VmaType::DeviceMemory,
-                            "virtio-gpu-vram-tail",
-                        );
-                    }
-                    continue;
                 }
+                continue;
+            }
 
-                // Kein Overlap - normal identitätsmappen
-                if address == 0 {
-                    warn!("      Not mapping BAR{} at {:#x} (size: {:#x})", bar_index, address, size);
-                    continue;
-                }
-                info!("      Mapping BAR{} at {:#x} (size: {:#x})", bar_index, address, size);
-                let kernel_process = process_manager().read().kernel_process().unwrap();
-                kernel_process.virtual_address_space.kernel_map_devm_identity(
-                    address,
-                    address + size,
-                    PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE,
-                    VmaType::DeviceMemory,
-                    "virtio-bar",
-                );
+            // Kein Overlap - normal identitätsmappen
+            if address == 0 {
+                warn!("      Not mapping BAR{} at {:#x} (size: {:#x})", bar_index, address, size);
+                continue;
             }
+            info!("      Mapping BAR{} at {:#x} (size: {:#x})", bar_index, address, size);
+            pci_resources::map_memory_bar(&device_lock.read(), bar_index, "virtio-bar");
         }
+    }
 
-        if let Some(mut device_writer) = device_lock.try_write() {
-
-            device_writer.update_command(pci_bus.config_space(), |cmd| {
-                cmd | pci_types::CommandRegister::BUS_MASTER_ENABLE
-                    | pci_types::CommandRegister::MEMORY_ENABLE
-                    | pci_types::CommandRegister::IO_ENABLE
-                    //| pci_types::CommandRegister::INTERRUPT_DISABLE
-            });
+    if let Some(mut device_writer) = device_lock.try_write() {
+        device_writer.update_command(pci_bus.config_space(), |cmd| {
+            cmd | pci_types::CommandRegister::BUS_MASTER_ENABLE
+                | pci_types::CommandRegister::MEMORY_ENABLE
+                | pci_types::CommandRegister::IO_ENABLE
+                //| pci_types::CommandRegister::INTERRUPT_DISABLE
+        });
+    } else {
+        warn!("Konnte keinen Schreibzugriff auf VirtIO-Gerät erhalten, wird übersprungen.");
+        return;
+    }
 
-            let (_, interrupt_line) = device_writer.interrupt(pci_bus.config_space());
-            if interrupt_line != 0 && interrupt_line != 0xFF {
-                let interrupt_vector = InterruptVector::try_from(interrupt_line + 32).unwrap();
-                info!("    VirtIO device uses interrupt vector {:?}", interrupt_vector);
-                interrupt_dispatcher().assign(interrupt_vector, Box::new(VirtioInterruptHandler));
-                apic().allow(interrupt_vector);
-            }
+    // MSI-X is not offered: the driver doesn't assign MSI-X vectors to the queues, so the device would never interrupt
+    match pci_irq::request_irq(device_lock, IrqTypes::LEGACY | IrqTypes::MSI, Box::new(VirtioInterruptHandler)) {
+        Ok(irq) => info!("    VirtIO device uses interrupt vector [0x{:x}]", irq.vector),
+        Err(errno) => warn!("    VirtIO device has no interrupt ({:?})", errno),
+    }
 
-        } else {
-            warn!("Konnte keinen Schreibzugriff auf VirtIO-Gerät erhalten, wird übersprungen.");
-            return;
+    match PciTransport::new::<HalImpl, _>(&mut pci_root, device_function) {
+        Ok(transport) => {
+            registry::bind(&registry::pci_name(address), "virtio");
+            plugin_device(transport.into())
         }
+        Err(e) => {
+            error!("Fehler: {:?}", e);
+        }
+    }
+}
 
-            match PciTransport::new::<HalImpl, _>(&mut pci_root, device_function) {
-                Ok(mut transport) => {
-                    match transport.device_type() {
-                        virtio::transport::DeviceType::GPU => {
-                            info!("     VirtIO GPU device found. Initializing driver...");
-                            VIRTIO_GPU.call_once(|| {
-                                Mutex::new(
-                                    VirtIOGpu::<HalImpl, PciTransport>::new(transport)
-                                        .expect("Failed to create VirtIO GPU driver")
-                                )
-                            });
-                        }
-                        virtio::transport::DeviceType::EntropySource => {
-                            info!("     VirtIO RNG device found. Initializing driver...");
-                            VIRTIO_RNG.call_once(|| {
-                                Mutex::new(
-                                    VirtIORng::<HalImpl, PciTransport>::new(transport)
-                                        .expect("Failed to create VirtIO Rng driver")
-                                )
-                            });
-                        }
-                        virtio::transport::DeviceType::Input => {
-                            info!("     VirtIO Input device found. Initializing driver...");
-                            VIRTIO_INPUT.call_once(|| {
-                                Mutex::new(
-                                    VirtIOInput::<HalImpl, PciTransport>::new(transport)
-                                        .expect("Failed to create VirtIO Input driver")
-                                )
-                            });
-                        }
-                        virtio::transport::DeviceType::Socket => {
-                            info!("     VirtIO Socket device found. Initializing driver...");
-                            VIRTIO_SOCKET.call_once(|| {
-                                Mutex::new(
-                                    VirtIOSocket::<HalImpl, PciTransport>::new(transport)
-                                        .expect("Failed to create VirtIO Socket driver")
-                                )
-                            });
-                        }
-                        virtio::transport::DeviceType::Sound => {
-                            info!("     VirtIO Sound device found. Initializing driver...");
-                            VIRTIO_SOUND.call_once(|| {
-                                Mutex::new(
-                                    VirtIOSound::<HalImpl, PciTransport>::new(transport)
-                                        .expect("Failed to create VirtIO Sound driver")
-                                )
-                            });
-                        }
-                        dt => {
-                            warn!("Unbehandelter Typ: {:?}", dt);
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("Fehler: {:?}", e);
-                }
+/// Initialize the driver for a virtio device (independent of its transport)
+fn plugin_device(transport: VirtioTransport) {
+    match transport.device_type() {
+        virtio::transport::DeviceType::GPU => {
+            info!("     VirtIO GPU device found. Initializing driver...");
+            VIRTIO_GPU.call_once(|| {
+                Mutex::new(
+                    VirtIOGpu::<HalImpl, VirtioTransport>::new(transport)
+                        .expect("Failed to create VirtIO GPU driver")
+                )
+            });
+            // Die Demos nutzen die GPU selbst
+            #[cfg(not(feature = "virtio_tests"))]
+            gpu_fb::init();
+        }
+        virtio::transport::DeviceType::EntropySource => {
+            info!("     VirtIO RNG device found. Initializing driver...");
+            VIRTIO_RNG.call_once(|| {
+                Mutex::new(
+                    VirtIORng::<HalImpl, VirtioTransport>::new(transport)
+                        .expect("Failed to create VirtIO Rng driver")
+                )
+            });
+            rng::init();
+        }
+        virtio::transport::DeviceType::Input => {
+            info!("     VirtIO Input device found. Initializing driver...");
+            // Tastatur und Maus/Tablet sind bei QEMU getrennte Geräte
+            input::plugin(transport);
+        }
+        virtio::transport::DeviceType::Socket => {
+            info!("     VirtIO Socket device found. Initializing driver...");
+            // Verbindungen und Empfangspuffer verwaltet der ConnectionManager (siehe network::vsock)
+            VIRTIO_SOCKET.call_once(|| {
+                let socket = VirtIOSocket::<HalImpl, VirtioTransport>::new(transport)
+                    .expect("Failed to create VirtIO Socket driver");
+                info!("     VirtIO Socket guest CID: {}", socket.guest_cid());
+                Mutex::new(VsockConnectionManager::new(socket))
+            });
+        }
+        virtio::transport::DeviceType::Sound => {
+            info!("     VirtIO Sound device found. Initializing driver...");
+            VIRTIO_SOUND.call_once(|| {
+                Mutex::new(
+                    VirtIOSound::<HalImpl, VirtioTransport>::new(transport)
+                        .expect("Failed to create VirtIO Sound driver")
+                )
+            });
+        }
+        virtio::transport::DeviceType::Block => {
+            info!("     VirtIO Block device found. Initializing driver...");
+            VirtioBlockDevice::plugin(transport);
+        }
+        virtio::transport::DeviceType::Console => {
+            info!("     VirtIO Console device found. Initializing driver...");
+            if VIRTIO_CONSOLE.is_completed() {
+                warn!("Nur eine VirtIO Console wird unterstützt, wird übersprungen.");
+            } else if let Some(console) = VirtioConsole::new(transport) {
+                VIRTIO_CONSOLE.call_once(|| console);
             }
         }
+        virtio::transport::DeviceType::MemoryBalloon => {
+            info!("     VirtIO Balloon device found. Initializing driver...");
+            // Der Ballon wird von einem Kernel-Thread an die Vorgabe des Hosts angepasst
+            balloon::plugin(transport);
+        }
+        virtio::transport::DeviceType::_9P => {
+            info!("     VirtIO 9P device found. Initializing driver...");
+            // Die Freigabe wird vom Naming Service unter /mnt/<tag> eingehängt
+            p9::plugin(transport);
+        }
+        dt => {
+            warn!("Unbehandelter Typ: {:?}", dt);
+        }
     }
 }
 
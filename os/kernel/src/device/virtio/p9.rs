@@ -0,0 +1,115 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use log::{info, warn};
+use spin::Mutex;
+use syscall::return_vals::Errno;
+use virtio::queue::VirtQueue;
+use virtio::transport::Transport;
+use x86_64::instructions::interrupts;
+
+use super::hal::HalImpl;
+use super::VirtioTransport;
+
+/// The device has only one queue, used for requests and their responses
+const QUEUE_REQUESTS: u16 = 0;
+const QUEUE_SIZE: usize = 16;
+/// Offsets in the configuration space: the length of the mount tag, followed by the tag itself
+const CONFIG_TAG_LEN: usize = 0;
+const CONFIG_TAG: usize = 2;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Feature: u64 {
+        /// The configuration space contains a mount tag
+        const MOUNT_TAG = 1 << 0;
+        const VERSION_1 = 1 << 32;
+    }
+}
+
+/// All virtio-9p devices (one per shared directory of the host)
+static DEVICES: Mutex<Vec<Arc<Virtio9p>>> = Mutex::new(Vec::new());
+
+/// A virtio-9p device, transporting 9P messages to the host.
+/// The protocol itself is implemented by the naming service, which mounts the share.
+pub struct Virtio9p {
+    tag: String,
+    device: Mutex<Device>,
+}
+
+struct Device {
+    transport: VirtioTransport,
+    queue: VirtQueue<HalImpl, QUEUE_SIZE>,
+}
+
+/// Initialize the driver for `transport` and remember the device, so its share can be mounted.
+pub fn plugin(mut transport: VirtioTransport) {
+    let features = transport.begin_init(Feature::MOUNT_TAG | Feature::VERSION_1);
+    let queue = match VirtQueue::<HalImpl, QUEUE_SIZE>::new(&mut transport, QUEUE_REQUESTS, false, false) {
+        Ok(queue) => queue,
+        Err(e) => {
+            warn!("Failed to create VirtIO 9P request queue: {:?}", e);
+            return;
+        }
+    };
+    transport.finish_init();
+
+    // Ohne Mount-Tag wird die Freigabe nach ihrer Nummer benannt
+    let mut devices = DEVICES.lock();
+    let tag = if features.contains(Feature::MOUNT_TAG) { read_tag(&transport) } else { None };
+    let tag = tag.unwrap_or_else(|| alloc::format!("share{}", devices.len()));
+    info!("     VirtIO 9P mount tag: {}", tag);
+
+    devices.push(Arc::new(Virtio9p { tag, device: Mutex::new(Device { transport, queue }) }));
+}
+
+/// Get all virtio-9p devices.
+pub fn virtio_9p_shares() -> Vec<Arc<Virtio9p>> {
+    DEVICES.lock().clone()
+}
+
+/// Called from the virtio interrupt handler: responses are polled, so only acknowledge the interrupt.
+pub fn handle_interrupt() {
+    let Some(devices) = DEVICES.try_lock() else {
+        return;
+    };
+    for p9 in devices.iter() {
+        if let Some(mut device) = p9.device.try_lock() {
+            device.transport.ack_interrupt();
+        }
+    }
+}
+
+fn read_tag(transport: &VirtioTransport) -> Option<String> {
+    let len = transport.read_config_space::<u16>(CONFIG_TAG_LEN).ok()? as usize;
+    let tag = (0..len)
+        .map(|i| transport.read_config_space::<u8>(CONFIG_TAG + i))
+        .collect::<Result<Vec<u8>, _>>()
+        .ok()?;
+    String::from_utf8(tag).ok().filter(|tag| !tag.is_empty())
+}
+
+impl Virtio9p {
+    /// The mount tag, which identifies the share (e.g. given with `mount_tag=` to QEMU)
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Send a 9P message and wait for the response.
+    /// Returns the number of bytes written into `response`.
+    pub fn request(&self, request: &[u8], response: &mut [u8]) -> Result<usize, Errno> {
+        // Interrupts aus, damit der Interrupt-Handler nicht auf das gesperrte Gerät wartet
+        interrupts::without_interrupts(|| {
+            let mut device = self.device.lock();
+            let Device { transport, queue } = &mut *device;
+            match queue.add_notify_wait_pop(&[request], &mut [response], transport) {
+                Ok(len) => Ok(len as usize),
+                Err(e) => {
+                    warn!("VirtIO 9P: Request failed: {:?}", e);
+                    Err(Errno::EIO)
+                }
+            }
+        })
+    }
+}
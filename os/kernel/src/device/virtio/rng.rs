@@ -0,0 +1,65 @@
+use log::warn;
+use x86_64::instructions::interrupts;
+
+use crate::process::thread::Thread;
+use crate::{entropy_pool, scheduler};
+
+use super::virtio_rng;
+
+/// Number of bytes requested from the host at once
+const REQUEST_SIZE: usize = 32;
+/// Interval between two requests, until the entropy pool is seeded
+const SEED_INTERVAL_MS: usize = 100;
+/// Interval between two requests, once the entropy pool is seeded
+const FEED_INTERVAL_MS: usize = 1000;
+/// The host is not fully trusted, so only half of the bits are credited
+const CREDIT_DIVISOR: usize = 2;
+
+/// Use the virtio-rng as entropy source for the kernel entropy pool.
+///
+/// A kernel thread regularly requests random bytes from the host and mixes them into the pool.
+/// Until the pool is seeded, this happens more often, so random numbers (e.g. for the smoltcp seed) are available early.
+pub fn init() {
+    if virtio_rng().is_none() {
+        return;
+    }
+    // Sofort einmal füttern, damit der Pool möglichst vor dem Netzwerk initialisiert ist
+    if !feed() {
+        return;
+    }
+    scheduler().ready(Thread::new_kernel_thread(feed_thread, "virtio-rng"));
+}
+
+extern "sysv64" fn feed_thread() {
+    loop {
+        let interval = if entropy_pool().is_seeded() { FEED_INTERVAL_MS } else { SEED_INTERVAL_MS };
+        scheduler().sleep(interval);
+
+        if !feed() {
+            warn!("virtio-rng: Stopping entropy thread");
+            return;
+        }
+    }
+}
+
+/// Request random bytes from the host and mix them into the entropy pool.
+/// Returns `false` if the device is not usable.
+fn feed() -> bool {
+    let Some(rng) = virtio_rng() else {
+        return false;
+    };
+    let mut buffer = [0u8; REQUEST_SIZE];
+
+    // Interrupts aus, damit der Interrupt-Handler nicht auf das gesperrte Gerät wartet
+    let result = interrupts::without_interrupts(|| rng.lock().request_entropy(&mut buffer));
+    match result {
+        Ok(bytes) => {
+            entropy_pool().add_entropy(&buffer[..bytes], bytes * 8 / CREDIT_DIVISOR);
+            true
+        }
+        Err(e) => {
+            warn!("virtio-rng: Failed to request entropy: {:?}", e);
+            false
+        }
+    }
+}
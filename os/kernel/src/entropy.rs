@@ -0,0 +1,453 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: entropy                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Kernel entropy pool and random number generator.                ║
+   ║         Entropy is gathered from interrupt arrival times and TSC jitter ║
+   ║         (so it works without RDRAND or virtio-rng) and mixed into an    ║
+   ║         input pool using the ChaCha permutation as a sponge. If the CPU ║
+   ║         supports RDSEED, it is credited with half of its output bits.   ║
+   ║         RDRAND output is mixed in at each reseed, but never credited,   ║
+   ║         so a broken or malicious CPU can't weaken the pool. Random      ║
+   ║         numbers are generated by ChaCha20, which is reseeded from the   ║
+   ║         input pool and rekeyed after each request (fast key erasure).   ║
+   ║         If a virtio-rng device is present, it feeds the pool as well.   ║
+   ║         Interrupts are sampled into a small pool of each core, which is ║
+   ║         only folded into the input pool every 'INTERRUPTS_PER_BIT'      ║
+   ║         interrupts, so the cores don't contend for the input pool.      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::arch::x86_64::{_rdrand64_step, _rdseed64_step, _rdtsc};
+use core::hint::{black_box, spin_loop};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use log::info;
+use raw_cpuid::CpuId;
+use spin::{Mutex, Once};
+use syscall::return_vals::Errno;
+use crate::{apic, scheduler};
+use crate::sync::irqsave_spinlock::IrqSaveSpinlock;
+
+/// Number of credited bits needed, before random numbers are handed out.
+const SEED_BITS: usize = 256;
+/// The input pool can't hold more entropy than its capacity.
+const POOL_BITS: usize = 256;
+/// Number of interrupts mixed into the fast pool, before it is folded into the input pool.
+/// Each fold is credited with a single bit, since the timing of most interrupts (e.g. the timer) is quite predictable.
+const INTERRUPTS_PER_BIT: u32 = 64;
+/// Number of jittery TSC samples per credited bit.
+const JITTER_SAMPLES_PER_BIT: usize = 64;
+/// Maximum number of bytes generated with a single key.
+const GENERATE_CHUNK_SIZE: usize = 256;
+/// Number of 32-bit words, that are xored into the input pool before it is permuted.
+const RATE_WORDS: usize = 8;
+/// RDSEED output is credited with one bit per this many bits (it may be conditioned less than advertised).
+const RDSEED_CREDIT_DIVISOR: usize = 2;
+/// RDRAND and RDSEED may fail transiently, if the hardware generator is exhausted.
+const CPU_RNG_RETRIES: usize = 16;
+/// Maximum number of cores with their own fast pool (interrupts of further cores are not sampled)
+const MAX_CORES: usize = 64;
+/// No core (for free entries in `EntropyPool::fast_pools`)
+const NO_CORE: u32 = u32::MAX;
+
+const CHACHA_CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+
+pub struct EntropyPool {
+    state: IrqSaveSpinlock<PoolState>,
+    fast_pools: [FastPool; MAX_CORES],
+    seeded: AtomicBool,
+}
+
+/// Random number instructions supported by the CPU
+#[derive(Clone, Copy)]
+struct CpuRng {
+    rdrand: bool,
+    rdseed: bool,
+}
+
+static CPU_RNG: Once<CpuRng> = Once::new();
+
+struct PoolState {
+    /// Input pool. The first `RATE_WORDS` words absorb new data, the rest is never exposed.
+    input: [u32; 16],
+    /// Next word of the input pool, that new data is xored into
+    input_pos: usize,
+    /// Estimated entropy in the input pool (in bits)
+    entropy_bits: usize,
+    /// Key of the output generator
+    key: [u32; 8],
+}
+
+/// Interrupt samples of one core. Entries are assigned on first use (see `EntropyPool::local_fast_pool()`).
+struct FastPool {
+    /// Local APIC id of the core (`NO_CORE` for a free entry)
+    apic_id: AtomicU32,
+    /// Only locked by the interrupts of the core, so it is never contended (a nested interrupt skips its sample)
+    state: Mutex<FastState>,
+}
+
+/// Small pool, which is cheap enough to be mixed on every interrupt
+struct FastState {
+    pool: [u32; 4],
+    count: u32,
+    last_tsc: u64,
+    last_delta: u64,
+}
+
+impl FastPool {
+    const fn new() -> Self {
+        Self {
+            apic_id: AtomicU32::new(NO_CORE),
+            state: Mutex::new(FastState { pool: [0; 4], count: 0, last_tsc: 0, last_delta: 0 }),
+        }
+    }
+}
+
+impl Default for EntropyPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EntropyPool {
+    pub const fn new() -> Self {
+        Self {
+            state: IrqSaveSpinlock::new(PoolState {
+                input: [0; 16],
+                input_pos: 0,
+                entropy_bits: 0,
+                key: [0; 8],
+            }),
+            fast_pools: [const { FastPool::new() }; MAX_CORES],
+            seeded: AtomicBool::new(false),
+        }
+    }
+
+    /// Called by the interrupt dispatcher for every interrupt.
+    /// Mixes the arrival time (TSC) and the jitter relative to the previous interrupt into the fast pool of the core.
+    /// Only every `INTERRUPTS_PER_BIT` interrupts, the fast pool is folded into the (shared) input pool.
+    pub fn add_interrupt_sample(&self, vector: u8) {
+        let tsc = unsafe { _rdtsc() };
+        let Some(mut fast) = self.local_fast_pool().and_then(|fast| fast.state.try_lock()) else {
+            return;
+        };
+
+        let delta = tsc.wrapping_sub(fast.last_tsc);
+        let jitter = delta.wrapping_sub(fast.last_delta);
+        fast.last_tsc = tsc;
+        fast.last_delta = delta;
+
+        fast.pool[0] ^= tsc as u32;
+        fast.pool[1] ^= (tsc >> 32) as u32;
+        fast.pool[2] ^= jitter as u32;
+        fast.pool[3] ^= (vector as u32) | (((jitter >> 32) as u32) << 8);
+        fast_mix(&mut fast.pool);
+
+        fast.count += 1;
+        if fast.count >= INTERRUPTS_PER_BIT {
+            fast.count = 0;
+            let pool = fast.pool;
+            drop(fast);
+
+            let mut state = self.state.lock();
+            state.absorb(&pool);
+            state.credit(1);
+            drop(state);
+            self.try_seed();
+        }
+    }
+
+    /// Fast pool of the calling core (an entry in `self.fast_pools` is assigned on first use)
+    fn local_fast_pool(&self) -> Option<&FastPool> {
+        let own_id = apic().local_apic_id();
+        // Entries are assigned in order and never released, so the first free entry is ours
+        self.fast_pools.iter().find(|fast| {
+            match fast.apic_id.compare_exchange(NO_CORE, own_id, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => true,
+                Err(apic_id) => apic_id == own_id,
+            }
+        })
+    }
+
+    /// Gather entropy from the jitter of the TSC while executing a short, memory bound workload.
+    /// This is used during boot to get a seeded pool quickly, even if there are only few interrupts.
+    pub fn gather_jitter_entropy(&self) {
+        let mut scratch = [0u8; 4096];
+        let mut last_delta = 0u64;
+        let mut samples = [0u32; RATE_WORDS];
+        let mut credited = 0;
+
+        // give up after a while, if the TSC is too coarse (e.g. in some emulators)
+        for i in 0..SEED_BITS * JITTER_SAMPLES_PER_BIT * 16 {
+            let start = unsafe { _rdtsc() };
+            let mut index = start as usize;
+            for _ in 0..16 {
+                index = index.wrapping_mul(31).wrapping_add(scratch[index % scratch.len()] as usize + 1);
+                scratch[index % scratch.len()] = scratch[index % scratch.len()].wrapping_add(1);
+            }
+            black_box(&mut scratch);
+            let delta = unsafe { _rdtsc() }.wrapping_sub(start);
+
+            samples[i % RATE_WORDS] ^= delta as u32 ^ (start as u32).rotate_left(16);
+            if delta != last_delta {
+                credited += 1;
+            }
+            last_delta = delta;
+
+            if i % RATE_WORDS == RATE_WORDS - 1 {
+                self.state.lock().absorb(&samples);
+            }
+            if credited == JITTER_SAMPLES_PER_BIT {
+                credited = 0;
+                let mut state = self.state.lock();
+                state.credit(1);
+                if state.entropy_bits >= SEED_BITS {
+                    break;
+                }
+            }
+        }
+
+        self.try_seed();
+        if !self.is_seeded() {
+            info!("Not enough TSC jitter to seed entropy pool, waiting for interrupts");
+        }
+    }
+
+    /// Fill the input pool from RDSEED, if the CPU supports it (called during boot, before gathering jitter).
+    pub fn add_cpu_entropy(&self) {
+        let cpu_rng = cpu_rng();
+        info!("CPU random number generator: RDRAND [{}], RDSEED [{}]", cpu_rng.rdrand, cpu_rng.rdseed);
+        if !cpu_rng.rdseed {
+            return;
+        }
+
+        let mut seeds = [0u32; RATE_WORDS];
+        for _ in 0..SEED_BITS * RDSEED_CREDIT_DIVISOR / (RATE_WORDS * 32) {
+            for pair in seeds.chunks_mut(2) {
+                let Some(seed) = (unsafe { rdseed() }) else {
+                    info!("RDSEED failed repeatedly, not using it");
+                    return;
+                };
+                pair[0] = seed as u32;
+                pair[1] = (seed >> 32) as u32;
+            }
+
+            let mut state = self.state.lock();
+            state.absorb(&seeds);
+            state.credit(RATE_WORDS * 32 / RDSEED_CREDIT_DIVISOR);
+        }
+
+        self.try_seed();
+    }
+
+    /// Mix `data` into the input pool and credit it with `entropy_bits` bits of entropy.
+    /// Sources without a reliable estimate (e.g. MAC addresses) should credit 0 bits.
+    pub fn add_entropy(&self, data: &[u8], entropy_bits: usize) {
+        let mut state = self.state.lock();
+        for chunk in data.chunks(4) {
+            let mut word = [0u8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            state.absorb(&[u32::from_le_bytes(word)]);
+        }
+        state.credit(entropy_bits);
+        drop(state);
+
+        self.try_seed();
+    }
+
+    /// Whether enough entropy has been gathered to generate random numbers.
+    pub fn is_seeded(&self) -> bool {
+        self.seeded.load(Ordering::Acquire)
+    }
+
+    /// Fill `buf` with random bytes. Blocks until the pool is seeded, unless `nonblock` is set,
+    /// in which case `EAGAIN` is returned.
+    pub fn get_random(&self, buf: &mut [u8], nonblock: bool) -> Result<usize, Errno> {
+        while !self.is_seeded() {
+            if nonblock {
+                return Err(Errno::EAGAIN);
+            }
+            scheduler().sleep(10);
+        }
+
+        // interrupts are disabled while holding the lock, so large requests are split up
+        for chunk in buf.chunks_mut(GENERATE_CHUNK_SIZE) {
+            let mut state = self.state.lock();
+            if state.entropy_bits >= SEED_BITS {
+                state.reseed();
+            }
+            state.generate(chunk);
+        }
+        Ok(buf.len())
+    }
+
+    fn try_seed(&self) {
+        if self.is_seeded() {
+            return;
+        }
+
+        let mut state = self.state.lock();
+        if state.entropy_bits >= SEED_BITS {
+            state.reseed();
+            self.seeded.store(true, Ordering::Release);
+            info!("Entropy pool seeded");
+        }
+    }
+}
+
+impl PoolState {
+    fn absorb(&mut self, words: &[u32]) {
+        for word in words {
+            self.input[self.input_pos] ^= word;
+            self.input_pos += 1;
+            if self.input_pos == RATE_WORDS {
+                chacha_permute(&mut self.input);
+                self.input_pos = 0;
+            }
+        }
+    }
+
+    fn credit(&mut self, bits: usize) {
+        self.entropy_bits = (self.entropy_bits + bits).min(POOL_BITS);
+    }
+
+    /// Extract a new key for the output generator from the input pool.
+    fn reseed(&mut self) {
+        // RDRAND is not credited, but can't hurt either
+        if cpu_rng().rdrand {
+            let mut words = [0u32; RATE_WORDS];
+            for pair in words.chunks_mut(2) {
+                if let Some(random) = unsafe { rdrand() } {
+                    pair[0] = random as u32;
+                    pair[1] = (random >> 32) as u32;
+                }
+            }
+            self.absorb(&words);
+        }
+
+        // finish absorbing and separate the extraction from normal absorbing
+        self.input[RATE_WORDS] ^= 1;
+        chacha_permute(&mut self.input);
+        for (key, input) in self.key.iter_mut().zip(self.input[..RATE_WORDS].iter()) {
+            *key ^= input;
+        }
+
+        // overwrite the extracted words, so the key can't be reconstructed from the pool later
+        self.input[..RATE_WORDS].fill(0);
+        chacha_permute(&mut self.input);
+        self.input_pos = 0;
+        self.entropy_bits = 0;
+    }
+
+    fn generate(&mut self, buf: &mut [u8]) {
+        for (counter, chunk) in (1u64..).zip(buf.chunks_mut(64)) {
+            let block = chacha20_block(&self.key, counter);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+
+        // fast key erasure: block 0 is never output, but used as the next key
+        let block = chacha20_block(&self.key, 0);
+        for (i, key) in self.key.iter_mut().enumerate() {
+            *key = u32::from_le_bytes([block[i * 4], block[i * 4 + 1], block[i * 4 + 2], block[i * 4 + 3]]);
+        }
+    }
+}
+
+fn cpu_rng() -> CpuRng {
+    *CPU_RNG.call_once(|| {
+        let cpuid = CpuId::new();
+        CpuRng {
+            rdrand: cpuid.get_feature_info().is_some_and(|features| features.has_rdrand()),
+            rdseed: cpuid.get_extended_feature_info().is_some_and(|features| features.has_rdseed()),
+        }
+    })
+}
+
+/// Only call this, if `cpu_rng().rdseed` is set.
+#[target_feature(enable = "rdseed")]
+unsafe fn rdseed() -> Option<u64> {
+    let mut value = 0;
+    for _ in 0..CPU_RNG_RETRIES {
+        if unsafe { _rdseed64_step(&mut value) } == 1 {
+            return Some(value);
+        }
+        spin_loop();
+    }
+    None
+}
+
+/// Only call this, if `cpu_rng().rdrand` is set.
+#[target_feature(enable = "rdrand")]
+unsafe fn rdrand() -> Option<u64> {
+    let mut value = 0;
+    for _ in 0..CPU_RNG_RETRIES {
+        if unsafe { _rdrand64_step(&mut value) } == 1 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Cheap mixing function for the fast pool (taken from Linux' former `fast_mix()`).
+fn fast_mix(pool: &mut [u32; 4]) {
+    let [mut a, mut b, mut c, mut d] = *pool;
+    for _ in 0..2 {
+        a = a.wrapping_add(b);
+        c = c.wrapping_add(d);
+        b = b.rotate_left(6);
+        d = d.rotate_left(27);
+        d ^= a;
+        b ^= c;
+
+        a = a.wrapping_add(b);
+        c = c.wrapping_add(d);
+        b = b.rotate_left(16);
+        d = d.rotate_left(14);
+        d ^= a;
+        b ^= c;
+    }
+    *pool = [a, b, c, d];
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// The 20 rounds of the ChaCha permutation (without the final addition).
+fn chacha_permute(state: &mut [u32; 16]) {
+    for _ in 0..10 {
+        quarter_round(state, 0, 4, 8, 12);
+        quarter_round(state, 1, 5, 9, 13);
+        quarter_round(state, 2, 6, 10, 14);
+        quarter_round(state, 3, 7, 11, 15);
+        quarter_round(state, 0, 5, 10, 15);
+        quarter_round(state, 1, 6, 11, 12);
+        quarter_round(state, 2, 7, 8, 13);
+        quarter_round(state, 3, 4, 9, 14);
+    }
+}
+
+/// Generate a single ChaCha20 block (the nonce is always 0, since each key is only used for one request).
+fn chacha20_block(key: &[u32; 8], counter: u64) -> [u8; 64] {
+    let mut input = [0u32; 16];
+    input[..4].copy_from_slice(&CHACHA_CONSTANTS);
+    input[4..12].copy_from_slice(key);
+    input[12] = counter as u32;
+    input[13] = (counter >> 32) as u32;
+
+    let mut state = input;
+    chacha_permute(&mut state);
+
+    let mut block = [0u8; 64];
+    for (i, (word, input)) in state.iter().zip(input.iter()).enumerate() {
+        block[i * 4..i * 4 + 4].copy_from_slice(&word.wrapping_add(*input).to_le_bytes());
+    }
+    block
+}
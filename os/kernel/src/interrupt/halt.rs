@@ -0,0 +1,140 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: halt                                                            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Stopping the other cores on a kernel panic. The panicking core sends    ║
+   ║ a halt IPI to all cores registered for TLB shootdowns. These disable    ║
+   ║ interrupts, record their local APIC id and the interrupted thread and   ║
+   ║ park in `hlt`, so they can't corrupt state or interleave their output   ║
+   ║ with the panic message. If several cores panic at the same time, only   ║
+   ║ the first one reports, the others park as well.                         ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - init                 register the handler for halt IPIs             ║
+   ║   - halt_other_cores     stop all other cores (called on panic)         ║
+   ║   - log_halted_cores     log the state recorded by the halted cores     ║
+   ║   - park                 stop the calling core forever                  ║
+   ║   - is_panicking         check, whether a core has panicked             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use log::error;
+use x86_64::instructions::{hlt, interrupts};
+use crate::interrupt::ipi::{self, Ipi};
+use crate::interrupt::interrupt_handler::InterruptHandler;
+use crate::memory::tlb;
+use crate::{apic, scheduler};
+
+/// Maximum number of halted cores, whose state is recorded
+const MAX_RECORDED_CORES: usize = 64;
+/// How long the panicking core waits for the others to acknowledge (in spin loop iterations)
+const ACK_TIMEOUT: usize = 10_000_000;
+/// Recorded instead of a thread id, if the current thread could not be determined
+const NO_THREAD: usize = usize::MAX;
+
+/// Set by the first core, that panics
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Number of halt IPIs sent by the panicking core
+static SENT: AtomicUsize = AtomicUsize::new(0);
+/// Next free entry in `HALTED_CORES`
+static NEXT_SLOT: AtomicUsize = AtomicUsize::new(0);
+/// Number of cores, that have recorded their state and parked (may be larger than `MAX_RECORDED_CORES`)
+static HALTED: AtomicUsize = AtomicUsize::new(0);
+/// State of the halted cores (only the first `min(HALTED, MAX_RECORDED_CORES)` entries are valid)
+static HALTED_CORES: [HaltedCore; MAX_RECORDED_CORES] = [const { HaltedCore::new() }; MAX_RECORDED_CORES];
+
+struct HaltedCore {
+    apic_id: AtomicU32,
+    thread_id: AtomicUsize,
+}
+
+impl HaltedCore {
+    const fn new() -> Self {
+        Self { apic_id: AtomicU32::new(0), thread_id: AtomicUsize::new(NO_THREAD) }
+    }
+}
+
+struct HaltInterruptHandler;
+
+impl InterruptHandler for HaltInterruptHandler {
+    fn trigger(&self) {
+        interrupts::disable();
+
+        // Der Scheduler könnte von diesem Kern gesperrt sein, dann wird kein Thread vermerkt
+        let thread_id = scheduler().try_get_current_thread().map(|thread| thread.id()).unwrap_or(NO_THREAD);
+        let slot = NEXT_SLOT.fetch_add(1, Ordering::Relaxed);
+        if slot < MAX_RECORDED_CORES {
+            HALTED_CORES[slot].apic_id.store(apic().local_apic_id(), Ordering::Relaxed);
+            HALTED_CORES[slot].thread_id.store(thread_id, Ordering::Relaxed);
+        }
+        // publishes the recorded state to the panicking core
+        HALTED.fetch_add(1, Ordering::Release);
+
+        // never returns, so no EOI is sent
+        park();
+    }
+}
+
+/// Register the handler for halt IPIs (must be called after the APIC has been initialized).
+pub fn init() {
+    ipi::register(Ipi::Halt, Box::new(HaltInterruptHandler));
+}
+
+/// Send a halt IPI to all other cores. Must be called with interrupts disabled.
+/// Returns `false`, if another core is already panicking (the caller should `park()` then).
+pub fn halt_other_cores() -> bool {
+    if PANICKING.swap(true, Ordering::AcqRel) {
+        return false;
+    }
+
+    // If the registry is locked (e.g. by a core in the middle of `register_cpu()`), no core can be stopped
+    tlb::try_for_each_cpu(|apic_id| {
+        if apic_id != apic().local_apic_id() {
+            ipi::send_halt(apic_id);
+            SENT.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+
+    true
+}
+
+/// Wait (for a limited time) until all cores have parked and log their recorded state.
+pub fn log_halted_cores() {
+    let sent = SENT.load(Ordering::Relaxed);
+    if sent == 0 {
+        return;
+    }
+
+    let mut timeout = ACK_TIMEOUT;
+    while HALTED.load(Ordering::Acquire) < sent && timeout > 0 {
+        spin_loop();
+        timeout -= 1;
+    }
+
+    let halted = HALTED.load(Ordering::Acquire);
+    error!("Halted [{}] of [{}] other cores", halted, sent);
+    for core in HALTED_CORES.iter().take(halted.min(MAX_RECORDED_CORES)) {
+        match core.thread_id.load(Ordering::Relaxed) {
+            NO_THREAD => error!("  Core [{}]: current thread unknown", core.apic_id.load(Ordering::Relaxed)),
+            thread_id => error!("  Core [{}]: interrupted thread [{}]", core.apic_id.load(Ordering::Relaxed), thread_id),
+        }
+    }
+}
+
+/// Check, whether a core has panicked (and halted the others).
+pub fn is_panicking() -> bool {
+    PANICKING.load(Ordering::Acquire)
+}
+
+/// Stop the calling core forever.
+pub fn park() -> ! {
+    interrupts::disable();
+    loop {
+        // only NMIs and SMIs can wake the core, so we go back to sleep
+        hlt();
+    }
+}
@@ -1,19 +1,18 @@
 use crate::interrupt::interrupt_handler::InterruptHandler;
-use crate::memory::MemorySpace;
+use crate::interrupt::watchdog;
 use crate::memory;
-use crate::memory::vma::VmaType;
-use crate::{apic, idt, interrupt_dispatcher, scheduler};
+use crate::process::signal;
+use crate::{apic, entropy_pool, idt, interrupt_dispatcher, scheduler};
 use alloc::boxed::Box;
 use alloc::vec::Vec;
-use core::ops::Deref;
+use core::ops::{Deref, RangeInclusive};
 use core::ptr;
 use log::{error, info, trace};
 use spin::Mutex;
 use x86_64::registers::control::Cr2;
 use x86_64::set_general_handler;
-use x86_64::structures::idt::InterruptStackFrame;
-use x86_64::structures::paging::page::PageRange;
-use x86_64::structures::paging::{Page, PageTableFlags};
+use x86_64::PrivilegeLevel;
+use x86_64::structures::idt::{InterruptStackFrame, PageFaultErrorCode};
 
 #[repr(u8)]
 #[derive(PartialEq, PartialOrd, Copy, Clone, Debug)]
@@ -64,6 +63,13 @@ pub enum InterruptVector {
     SecondaryAta = 0x2f,
     // Possibly some other interrupts supported by IO APICs
 
+    // Inter-processor interrupts
+    TlbShootdown = 0xf0,
+    Reschedule = 0xf1,
+    Halt = 0xf2,
+    CallFunction = 0xf3,
+    Park = 0xf4,
+
     // Local APIC interrupts (247 - 254)
     Cmci = 0xf8,
     ApicTimer = 0xf9,
@@ -123,6 +129,11 @@ impl TryFrom<u8> for InterruptVector {
             value if value == InterruptVector::PrimaryAta as u8 => Ok(InterruptVector::PrimaryAta),
             value if value == InterruptVector::SecondaryAta as u8 => Ok(InterruptVector::SecondaryAta),
 
+            value if value == InterruptVector::TlbShootdown as u8 => Ok(InterruptVector::TlbShootdown),
+            value if value == InterruptVector::Reschedule as u8 => Ok(InterruptVector::Reschedule),
+            value if value == InterruptVector::Halt as u8 => Ok(InterruptVector::Halt),
+            value if value == InterruptVector::CallFunction as u8 => Ok(InterruptVector::CallFunction),
+            value if value == InterruptVector::Park as u8 => Ok(InterruptVector::Park),
             value if value == InterruptVector::Cmci as u8 => Ok(InterruptVector::Cmci),
             value if value == InterruptVector::ApicTimer as u8 => Ok(InterruptVector::ApicTimer),
             value if value == InterruptVector::Thermal as u8 => Ok(InterruptVector::Thermal),
@@ -152,6 +163,7 @@ pub fn setup_idt() {
 
     set_general_handler!(&mut idt, handle_exception, 0..31);
     set_general_handler!(&mut idt, handle_interrupt, 32..255);
+    set_general_handler!(&mut idt, handle_nmi, 2);
     set_general_handler!(&mut idt, handle_page_fault, 14);
 
     unsafe {
@@ -173,6 +185,13 @@ fn handle_exception(frame: InterruptStackFrame, index: u8, error: Option<u64>) {
     );
 }
 
+fn handle_nmi(frame: InterruptStackFrame, index: u8, error: Option<u64>) {
+    // NMIs of the watchdog are expected, all others are handled like exceptions
+    if !watchdog::handle_nmi(&frame) {
+        handle_exception(frame, index, error);
+    }
+}
+
 fn handle_page_fault(frame: InterruptStackFrame, _index: u8, error: Option<u64>) {
     let fault_addr = Cr2::read().expect("Invalid address in CR2 during page fault");
     let thread = scheduler().try_get_current_thread();
@@ -181,47 +200,49 @@ fn handle_page_fault(frame: InterruptStackFrame, _index: u8, error: Option<u64>)
     }
 
     let thread = thread.unwrap();
+    let error_code = PageFaultErrorCode::from_bits_truncate(error.unwrap_or(0));
 
-    // Was the page fault caused by a user thread?
+    // Was the page fault caused by a user thread (also by the kernel, e.g. in a system call)?
     if !thread.is_kernel_thread() {
-        let fault_page = Page::containing_address(fault_addr);
-
-        // Check if page fault occurred inside a user stack
-        if let Some(stack) = thread
-            .process()
-            .virtual_address_space
-            .is_address_within_vma(fault_addr.as_u64(), VmaType::UserStack)
-        {
-            if memory::frame_allocator_locked() {
-                panic!("Page Fault, cannot get lock to frame allocator\nError code: [{:?}]\nAddress: [0x{:0>16x}]", error, fault_addr);
-            }
-
-            thread.process().virtual_address_space.map_partial_vma(
-                &stack,
-                PageRange {
-                    start: fault_page,
-                    end: fault_page + 1,
-                },
-                MemorySpace::User,
-                PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE,
-            );
-            return ;
+        if memory::frame_allocator_locked() {
+            panic!("Page Fault, cannot get lock to frame allocator\nError code: [{:?}]\nAddress: [0x{:0>16x}]", error, fault_addr);
         }
 
-
-        // Check if page fault occurred inside a user heap
-        if let Some(heap) = thread.process().virtual_address_space.is_address_within_vma(fault_addr.as_u64(), VmaType::Heap) {
-            if memory::frame_allocator_locked() {
-                panic!("Page Fault, cannot get lock to frame allocator\nError code: [{:?}]\nAddress: [0x{:0>16x}]", error, fault_addr);
+        let process = thread.process();
+        match process.virtual_address_space.resolve_page_fault(fault_addr, error_code) {
+            Ok(mapped) => {
+                if mapped {
+                    process.usage().add_page_fault();
+                }
+                return;
             }
-
-            thread.process().virtual_address_space.map_partial_vma(
-                &heap,
-                PageRange { start: fault_page, end: fault_page + 1 },
-                MemorySpace::User,
-                PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE,
-            );
-            return ;
+            // An invalid access of the application only terminates its process (the thread holds no locks in user mode)
+            Err(reason) if frame.code_segment.rpl() == PrivilegeLevel::Ring3 => {
+                let access = if error_code.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+                    "execute"
+                } else if error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+                    "write"
+                } else {
+                    "read"
+                };
+                error!(
+                    "Segmentation fault: Thread [{}] of process [{}] ({}) tried to {} [0x{:0>16x}] at [0x{:0>16x}]: {}",
+                    thread.id(),
+                    process.id(),
+                    process.name(),
+                    access,
+                    fault_addr,
+                    frame.instruction_pointer,
+                    reason
+                );
+                process.dump();
+                process.exit();
+                // exit() does not return, so nothing may keep the process alive
+                drop(process);
+                drop(thread);
+                scheduler().exit();
+            }
+            Err(reason) => error!("Page Fault in system call: {}", reason),
         }
     }
 
@@ -229,8 +250,18 @@ fn handle_page_fault(frame: InterruptStackFrame, _index: u8, error: Option<u64>)
     panic!("Page Fault!\nError code: [{:?}]\nAddress: [0x{:0>16x}]\n{:?}", error, fault_addr, frame);
 }
 
-fn handle_interrupt(_frame: InterruptStackFrame, index: u8, _error: Option<u64>) {
+fn handle_interrupt(frame: InterruptStackFrame, index: u8, _error: Option<u64>) {
+    if index == InterruptVector::ApicTimer as u8 {
+        // CPU time is sampled on each core: the whole tick is charged to the interrupted thread
+        let user = frame.code_segment.rpl() == PrivilegeLevel::Ring3;
+        scheduler().account_tick(user, apic().timer_interval_ns());
+    }
     interrupt_dispatcher().dispatch(index);
+
+    // signals are delivered, before the interrupted thread continues in user mode
+    if index == InterruptVector::Pit as u8 && frame.code_segment.rpl() == PrivilegeLevel::Ring3 {
+        signal::deliver();
+    }
 }
 
 impl InterruptDispatcher {
@@ -250,11 +281,31 @@ impl InterruptDispatcher {
         }
     }
 
+    /// Assign `handler` to the first vector in `range`, that has no handlers yet, and return it.
+    pub fn assign_free(&self, range: RangeInclusive<u8>, handler: Box<dyn InterruptHandler>) -> Option<u8> {
+        for vector in range {
+            let mut handlers = self.int_vectors[vector as usize].lock();
+            if handlers.is_empty() {
+                handlers.push(handler);
+                return Some(vector);
+            }
+        }
+
+        None
+    }
+
+    /// Remove all handlers of `vector` (e.g. of an IPI, that is not used anymore).
+    pub fn release(&self, vector: u8) {
+        self.int_vectors[vector as usize].lock().clear();
+    }
+
     pub fn dispatch(&self, interrupt: u8) {
         // if we log the timer interrupt, it just spams the log and nothing else happens
         if interrupt != 32 {
             trace!("handling interrupt {interrupt}");
         }
+        entropy_pool().add_interrupt_sample(interrupt);
+
         let handler_vec_mutex = self
             .int_vectors
             .get(interrupt as usize)
@@ -0,0 +1,115 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: ipi                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Inter-processor interrupts. The IPIs of the kernel (TLB shootdown,      ║
+   ║ reschedule, halt, cross-core calls and parking) have fixed vectors in   ║
+   ║ `InterruptVector` and typed senders, so no code needs to write the ICR  ║
+   ║ with a raw vector. Further IPIs (e.g. for experiments) get a vector     ║
+   ║ from a reserved range of the interrupt dispatcher. All IPIs are         ║
+   ║ acknowledged by the dispatcher, which sends the EOI after the handlers  ║
+   ║ have run.                                                               ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - register             install the handler for one of the kernel's    ║
+   ║                          IPIs                                           ║
+   ║   - allocate             get a free vector for a new IPI                ║
+   ║   - send_tlb_flush       make a core process its TLB shootdowns         ║
+   ║   - send_reschedule      make a core switch threads                     ║
+   ║   - send_halt            stop a core (on panic, ignores locks)          ║
+   ║   - send_call_function   make a core run its cross-core calls           ║
+   ║   - send_park            park a core or wake it up                      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use core::ops::RangeInclusive;
+use log::info;
+use syscall::return_vals::Errno;
+use crate::interrupt::interrupt_dispatcher::InterruptVector;
+use crate::interrupt::interrupt_handler::InterruptHandler;
+use crate::{apic, interrupt_dispatcher};
+
+/// Vectors for IPIs, that are allocated at runtime (below the fixed IPIs starting at `TlbShootdown`)
+const DYNAMIC_VECTORS: RangeInclusive<u8> = 0xe0..=0xef;
+
+/// The IPIs of the kernel, which have fixed vectors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ipi {
+    TlbShootdown,
+    Reschedule,
+    Halt,
+    CallFunction,
+    Park,
+}
+
+impl Ipi {
+    const fn vector(self) -> InterruptVector {
+        match self {
+            Ipi::TlbShootdown => InterruptVector::TlbShootdown,
+            Ipi::Reschedule => InterruptVector::Reschedule,
+            Ipi::Halt => InterruptVector::Halt,
+            Ipi::CallFunction => InterruptVector::CallFunction,
+            Ipi::Park => InterruptVector::Park,
+        }
+    }
+}
+
+/// An IPI with a vector allocated by `allocate()`. The vector is released, when this is dropped.
+pub struct IpiVector(u8);
+
+impl IpiVector {
+    pub fn vector(&self) -> u8 {
+        self.0
+    }
+
+    /// Send this IPI to the core with the local APIC id `apic_id`.
+    pub fn send(&self, apic_id: u32) {
+        apic().send_ipi(self.0, apic_id);
+    }
+}
+
+impl Drop for IpiVector {
+    fn drop(&mut self) {
+        interrupt_dispatcher().release(self.0);
+    }
+}
+
+/// Install `handler` for `ipi` (must be called after the APIC has been initialized).
+pub fn register(ipi: Ipi, handler: Box<dyn InterruptHandler>) {
+    interrupt_dispatcher().assign(ipi.vector(), handler);
+}
+
+/// Get a free vector for a new IPI and install `handler` for it.
+/// Fails with `EBUSY`, if all vectors reserved for IPIs are in use.
+pub fn allocate(handler: Box<dyn InterruptHandler>) -> Result<IpiVector, Errno> {
+    let vector = interrupt_dispatcher().assign_free(DYNAMIC_VECTORS, handler).ok_or(Errno::EBUSY)?;
+    info!("Allocated vector [0x{:x}] for an IPI", vector);
+    Ok(IpiVector(vector))
+}
+
+pub fn send_tlb_flush(apic_id: u32) {
+    send(Ipi::TlbShootdown, apic_id);
+}
+
+pub fn send_reschedule(apic_id: u32) {
+    send(Ipi::Reschedule, apic_id);
+}
+
+/// Stop the core with the local APIC id `apic_id`. Doesn't wait for the local APIC lock,
+/// since it may be held by the code, that has panicked.
+pub fn send_halt(apic_id: u32) {
+    apic().send_ipi_forced(Ipi::Halt.vector() as u8, apic_id);
+}
+
+pub fn send_call_function(apic_id: u32) {
+    send(Ipi::CallFunction, apic_id);
+}
+
+pub fn send_park(apic_id: u32) {
+    send(Ipi::Park, apic_id);
+}
+
+fn send(ipi: Ipi, apic_id: u32) {
+    apic().send_ipi(ipi.vector() as u8, apic_id);
+}
@@ -1,2 +1,7 @@
+pub mod halt;
 pub mod interrupt_dispatcher;
 pub mod interrupt_handler;
+pub mod ipi;
+pub mod park;
+pub mod smp_call;
+pub mod watchdog;
@@ -0,0 +1,120 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: park                                                            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Taking cores offline at runtime (e.g. for energy experiments). A core   ║
+   ║ is parked with a directed IPI and waits in `hlt` inside the interrupt   ║
+   ║ handler, until it is unparked (by clearing its entry and sending the    ║
+   ║ IPI again). Parked cores still handle interrupts, so they take part in  ║
+   ║ TLB shootdowns and cross-core calls. A core running threads is taken    ║
+   ║ offline in the scheduler first, so its threads move to other cores, and ║
+   ║ it is only parked, once it runs its idle thread (a thread would be      ║
+   ║ stuck in the handler otherwise).                                        ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - init                 register the handler for park IPIs             ║
+   ║   - park                 take a core offline                            ║
+   ║   - unpark               bring a parked core back                       ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use log::info;
+use spin::Mutex;
+use syscall::return_vals::Errno;
+use x86_64::instructions::interrupts;
+use crate::interrupt::ipi::{self, Ipi};
+use crate::interrupt::interrupt_handler::InterruptHandler;
+use crate::interrupt::smp_call;
+use crate::{apic, scheduler};
+
+/// How long `park()` waits for the core to acknowledge
+const PARK_TIMEOUT_MS: usize = 100;
+
+/// Cores, that should be parked, and whether they have acknowledged it
+static PARKED: Mutex<BTreeMap<u32, bool>> = Mutex::new(BTreeMap::new());
+
+struct ParkInterruptHandler;
+
+impl InterruptHandler for ParkInterruptHandler {
+    fn trigger(&self) {
+        let own_id = apic().local_apic_id();
+        match PARKED.lock().get_mut(&own_id) {
+            Some(acknowledged) if !*acknowledged && scheduler().is_idle(own_id) => *acknowledged = true,
+            // the IPI sent by `unpark()`, which only wakes the core up, a repeated one for a parked core
+            // or the core has not switched to its idle thread yet (`park()` sends the IPI again)
+            _ => return,
+        }
+
+        // Während des Wartens müssen Interrupts (z.B. TLB-Shootdowns) bearbeitet werden, daher wird das EOI schon hier gesendet.
+        // Das zweite EOI des Dispatchers wird ignoriert, da dann kein Interrupt mehr in Bearbeitung ist.
+        apic().end_of_interrupt();
+        while PARKED.lock().contains_key(&own_id) {
+            interrupts::enable_and_hlt();
+            interrupts::disable();
+        }
+    }
+}
+
+/// Register the handler for park IPIs (must be called after the APIC has been initialized).
+pub fn init() {
+    ipi::register(Ipi::Park, Box::new(ParkInterruptHandler));
+}
+
+/// Park the core with the local APIC id `apic_id` and wait, until it has stopped. A core running threads is taken
+/// offline in the scheduler, before it is parked. \
+/// Fails with `EINVAL` for unknown cores, with `EBUSY` for the calling core and the last core running threads
+/// and with `EIO`, if the core doesn't respond.
+pub fn park(apic_id: u32) -> Result<(), Errno> {
+    if !smp_call::is_registered(apic_id) {
+        return Err(Errno::EINVAL);
+    }
+    if apic().local_apic_id() == apic_id {
+        return Err(Errno::EBUSY);
+    }
+
+    if interrupts::without_interrupts(|| PARKED.lock().try_insert(apic_id, false).is_err()) {
+        // already parked
+        return Ok(());
+    }
+    let was_online = match scheduler().take_offline(apic_id) {
+        Ok(was_online) => was_online,
+        Err(error) => {
+            interrupts::without_interrupts(|| PARKED.lock().remove(&apic_id));
+            return Err(error);
+        }
+    };
+
+    for _ in 0..PARK_TIMEOUT_MS {
+        match interrupts::without_interrupts(|| PARKED.lock().get(&apic_id).copied()) {
+            Some(true) => {
+                info!("Parked core [{}]", apic_id);
+                return Ok(());
+            }
+            // the core ignores the IPI, until it runs its idle thread
+            _ if scheduler().is_idle(apic_id) => ipi::send_park(apic_id),
+            _ => {}
+        }
+        scheduler().sleep(1);
+    }
+
+    interrupts::without_interrupts(|| PARKED.lock().remove(&apic_id));
+    if was_online {
+        scheduler().bring_online(apic_id);
+    }
+    Err(Errno::EIO)
+}
+
+/// Bring the parked core with the local APIC id `apic_id` back. Fails with `EINVAL`, if the core is not parked.
+pub fn unpark(apic_id: u32) -> Result<(), Errno> {
+    if interrupts::without_interrupts(|| PARKED.lock().remove(&apic_id)).is_none() {
+        return Err(Errno::EINVAL);
+    }
+
+    // wake the core up from `hlt`, so it notices, that its entry is gone
+    ipi::send_park(apic_id);
+    scheduler().bring_online(apic_id);
+    info!("Unparked core [{}]", apic_id);
+    Ok(())
+}
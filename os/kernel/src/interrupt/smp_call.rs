@@ -0,0 +1,163 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: smp_call                                                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Running functions on other cores. Each registered core has a mailbox,   ║
+   ║ into which calls are put, before an IPI is sent to the core. The        ║
+   ║ interrupt handler runs all calls in the mailbox. Synchronous calls      ║
+   ║ wait, until all targets have run the function, asynchronous calls       ║
+   ║ return immediately. Calls targeting the calling core are run directly.  ║
+   ║ The functions run in interrupt context (with interrupts disabled), so   ║
+   ║ they must not block or sleep.                                           ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - init                 register the bootstrap processor and the IPI   ║
+   ║                          handler                                        ║
+   ║   - register_cpu         add the calling core to the possible targets   ║
+   ║   - is_registered        check, whether a core has been registered      ║
+   ║   - cpus                 get the local APIC ids of all registered cores ║
+   ║   - call_on              run a function on one core and wait            ║
+   ║   - call_on_async        run a function on one core without waiting     ║
+   ║   - call_on_others       run a function on all other cores and wait     ║
+   ║   - call_on_others_async run a function on all other cores without      ║
+   ║                          waiting                                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::{Mutex, RwLock};
+use syscall::return_vals::Errno;
+use x86_64::instructions::interrupts;
+use crate::interrupt::ipi::{self, Ipi};
+use crate::interrupt::interrupt_handler::InterruptHandler;
+use crate::apic;
+
+/// All cores, that can be targeted by calls
+static CPUS: RwLock<Vec<Arc<Mailbox>>> = RwLock::new(Vec::new());
+
+type Function = Arc<dyn Fn() + Send + Sync>;
+
+/// Pending calls of a core (identified by its local APIC id)
+struct Mailbox {
+    apic_id: u32,
+    calls: Mutex<Vec<Call>>,
+}
+
+struct Call {
+    function: Function,
+    /// Number of cores, that have not run the function yet (only for synchronous calls)
+    pending: Option<Arc<AtomicUsize>>,
+}
+
+struct CallFunctionHandler;
+
+impl InterruptHandler for CallFunctionHandler {
+    fn trigger(&self) {
+        process_mailbox();
+    }
+}
+
+/// Register the bootstrap processor and the handler for call IPIs (must be called after the APIC has been initialized).
+pub fn init() {
+    ipi::register(Ipi::CallFunction, Box::new(CallFunctionHandler));
+    register_cpu();
+}
+
+/// Add the calling core to the possible targets. Must be called by each core, before it runs threads.
+pub fn register_cpu() {
+    let apic_id = apic().local_apic_id();
+    interrupts::without_interrupts(|| {
+        CPUS.write().push(Arc::new(Mailbox { apic_id, calls: Mutex::new(Vec::new()) }));
+    });
+}
+
+pub fn is_registered(apic_id: u32) -> bool {
+    find_cpu(apic_id).is_some()
+}
+
+/// Local APIC ids of all registered cores (in the order of registration).
+pub fn cpus() -> Vec<u32> {
+    interrupts::without_interrupts(|| CPUS.read().iter().map(|cpu| cpu.apic_id).collect())
+}
+
+/// Run `function` on the core with the local APIC id `apic_id` and wait, until it has returned.
+/// Fails with `EINVAL`, if no such core has been registered.
+pub fn call_on(apic_id: u32, function: impl Fn() + Send + Sync + 'static) -> Result<(), Errno> {
+    let target = find_cpu(apic_id).ok_or(Errno::EINVAL)?;
+    submit(&[target], Arc::new(function), true);
+    Ok(())
+}
+
+/// Run `function` on the core with the local APIC id `apic_id`, without waiting for it.
+/// Fails with `EINVAL`, if no such core has been registered.
+pub fn call_on_async(apic_id: u32, function: impl Fn() + Send + Sync + 'static) -> Result<(), Errno> {
+    let target = find_cpu(apic_id).ok_or(Errno::EINVAL)?;
+    submit(&[target], Arc::new(function), false);
+    Ok(())
+}
+
+/// Run `function` on all registered cores except the calling one and wait, until it has returned everywhere.
+pub fn call_on_others(function: impl Fn() + Send + Sync + 'static) {
+    submit(&other_cpus(), Arc::new(function), true);
+}
+
+/// Run `function` on all registered cores except the calling one, without waiting for it.
+pub fn call_on_others_async(function: impl Fn() + Send + Sync + 'static) {
+    submit(&other_cpus(), Arc::new(function), false);
+}
+
+fn find_cpu(apic_id: u32) -> Option<Arc<Mailbox>> {
+    interrupts::without_interrupts(|| CPUS.read().iter().find(|cpu| cpu.apic_id == apic_id).cloned())
+}
+
+fn other_cpus() -> Vec<Arc<Mailbox>> {
+    interrupts::without_interrupts(|| {
+        let own_id = apic().local_apic_id();
+        CPUS.read().iter().filter(|cpu| cpu.apic_id != own_id).cloned().collect()
+    })
+}
+
+fn submit(targets: &[Arc<Mailbox>], function: Function, wait: bool) {
+    interrupts::without_interrupts(|| {
+        let own_id = apic().local_apic_id();
+        let remote: Vec<&Arc<Mailbox>> = targets.iter().filter(|cpu| cpu.apic_id != own_id).collect();
+        let pending = wait.then(|| Arc::new(AtomicUsize::new(remote.len())));
+
+        for cpu in remote.iter() {
+            cpu.calls.lock().push(Call { function: Arc::clone(&function), pending: pending.clone() });
+            ipi::send_call_function(cpu.apic_id);
+        }
+
+        if targets.iter().any(|cpu| cpu.apic_id == own_id) {
+            function();
+        }
+
+        if let Some(pending) = pending {
+            // Eigene Aufrufe bearbeiten, falls ein anderer Kern gleichzeitig (mit gesperrten Interrupts) auf uns wartet
+            while pending.load(Ordering::Acquire) > 0 {
+                process_mailbox();
+                spin_loop();
+            }
+        }
+    });
+}
+
+/// Run all calls in the mailbox of the calling core and signal their completion.
+fn process_mailbox() {
+    let own_id = apic().local_apic_id();
+    let Some(cpu) = CPUS.read().iter().find(|cpu| cpu.apic_id == own_id).cloned() else {
+        return;
+    };
+
+    let calls = core::mem::take(&mut *cpu.calls.lock());
+    for call in calls {
+        (call.function)();
+        if let Some(pending) = call.pending {
+            pending.fetch_sub(1, Ordering::Release);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: watchdog                                                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ NMI watchdog for detecting hard lockups. Once per period, the core      ║
+   ║ handling the timer interrupt sends an NMI to all other registered       ║
+   ║ cores. The NMI handler checks, whether interrupts were enabled in the   ║
+   ║ interrupted code. A core, that is sampled with interrupts disabled too  ║
+   ║ many times in a row, is considered stuck (e.g. spinning on a lock) and  ║
+   ║ panics with the address it is stuck at. Cores, that don't answer the    ║
+   ║ NMIs at all, are reported by the sending core. The core receiving the   ║
+   ║ timer interrupt can't be checked itself (if it locks up, no NMIs are    ║
+   ║ sent), so on a single core system, the watchdog does nothing.           ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - init                 register the bootstrap processor and start     ║
+   ║                          sending NMIs on timer interrupts               ║
+   ║   - register_cpu         add the calling core to the watched cores      ║
+   ║   - handle_nmi           check the calling core (called on NMI)         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use log::warn;
+use x86_64::registers::rflags::RFlags;
+use x86_64::structures::idt::InterruptStackFrame;
+use crate::interrupt::halt;
+use crate::interrupt::interrupt_dispatcher::InterruptVector;
+use crate::interrupt::interrupt_handler::InterruptHandler;
+use crate::{apic, interrupt_dispatcher, timer};
+
+/// Time between two NMIs to each core
+const PERIOD_MS: usize = 1000;
+/// A core is considered stuck, if it has been sampled with interrupts disabled this many times in a row
+const LOCKUP_THRESHOLD: usize = 5;
+/// Maximum number of watched cores
+const MAX_CORES: usize = 64;
+
+/// The NMI handler is not allowed to take locks (it may interrupt their owner), so all state is kept in atomics.
+static CORES: [WatchedCore; MAX_CORES] = [const { WatchedCore::new() }; MAX_CORES];
+/// Next free entry in `CORES`
+static NEXT_SLOT: AtomicUsize = AtomicUsize::new(0);
+/// System time (in milliseconds), at which the next NMIs are sent
+static NEXT_CHECK: AtomicUsize = AtomicUsize::new(0);
+
+struct WatchedCore {
+    apic_id: AtomicU32,
+    /// Set, once `apic_id` is valid
+    registered: AtomicBool,
+    /// Set by the sender and cleared by the NMI handler, so other NMIs (e.g. from hardware) can be told apart
+    pending: AtomicBool,
+    /// Number of consecutive NMIs, that have interrupted code with interrupts disabled
+    disabled_samples: AtomicUsize,
+    /// Number of consecutive NMIs, that have not been answered
+    missed: AtomicUsize,
+}
+
+impl WatchedCore {
+    const fn new() -> Self {
+        Self {
+            apic_id: AtomicU32::new(0),
+            registered: AtomicBool::new(false),
+            pending: AtomicBool::new(false),
+            disabled_samples: AtomicUsize::new(0),
+            missed: AtomicUsize::new(0),
+        }
+    }
+}
+
+struct WatchdogTimerHandler;
+
+impl InterruptHandler for WatchdogTimerHandler {
+    fn trigger(&self) {
+        let now = timer().systime_ms();
+        let next = NEXT_CHECK.load(Ordering::Relaxed);
+        // Bei mehreren Kernen sendet nur derjenige, der den Zeitpunkt zuerst beansprucht
+        if now < next || NEXT_CHECK.compare_exchange(next, now + PERIOD_MS, Ordering::Relaxed, Ordering::Relaxed).is_err() {
+            return;
+        }
+        if halt::is_panicking() {
+            return;
+        }
+
+        let own_id = apic().local_apic_id();
+        for core in registered_cores().filter(|core| core.apic_id.load(Ordering::Relaxed) != own_id) {
+            let apic_id = core.apic_id.load(Ordering::Relaxed);
+            // the previous NMI has not been handled yet
+            if core.pending.load(Ordering::Acquire) {
+                let missed = core.missed.fetch_add(1, Ordering::Relaxed) + 1;
+                if missed == LOCKUP_THRESHOLD {
+                    warn!("Watchdog: Core [{}] has not answered NMIs for [{}] ms", apic_id, missed * PERIOD_MS);
+                }
+                continue;
+            }
+
+            core.missed.store(0, Ordering::Relaxed);
+            core.pending.store(true, Ordering::Release);
+            if !apic().try_send_nmi(apic_id) {
+                // the local APIC is in use by the interrupted code, try again next period
+                core.pending.store(false, Ordering::Release);
+            }
+        }
+    }
+}
+
+/// Register the bootstrap processor and start sending NMIs on timer interrupts (must be called after the timer has been initialized).
+pub fn init() {
+    NEXT_CHECK.store(timer().systime_ms() + PERIOD_MS, Ordering::Relaxed);
+    register_cpu();
+    interrupt_dispatcher().assign(InterruptVector::Pit, Box::new(WatchdogTimerHandler));
+}
+
+/// Add the calling core to the watched cores. Must be called by each core, before it runs threads.
+pub fn register_cpu() {
+    let slot = NEXT_SLOT.fetch_add(1, Ordering::Relaxed);
+    if slot >= MAX_CORES {
+        warn!("Watchdog: Too many cores, core [{}] is not watched", apic().local_apic_id());
+        return;
+    }
+
+    CORES[slot].apic_id.store(apic().local_apic_id(), Ordering::Relaxed);
+    CORES[slot].registered.store(true, Ordering::Release);
+}
+
+/// Check the calling core after an NMI, that has interrupted the code described by `frame`.
+/// Returns `false`, if the NMI has not been sent by the watchdog.
+pub fn handle_nmi(frame: &InterruptStackFrame) -> bool {
+    let own_id = apic().local_apic_id();
+    let Some(core) = registered_cores().find(|core| core.apic_id.load(Ordering::Relaxed) == own_id) else {
+        return false;
+    };
+    if !core.pending.swap(false, Ordering::AcqRel) {
+        return false;
+    }
+
+    if frame.cpu_flags.contains(RFlags::INTERRUPT_FLAG) {
+        core.disabled_samples.store(0, Ordering::Relaxed);
+        return true;
+    }
+
+    let samples = core.disabled_samples.fetch_add(1, Ordering::Relaxed) + 1;
+    if samples >= LOCKUP_THRESHOLD && !halt::is_panicking() {
+        panic!(
+            "Watchdog: Hard lockup on core [{}], interrupts disabled for [{}] ms at RIP [0x{:0>16x}]\n{:?}",
+            own_id, samples * PERIOD_MS, frame.instruction_pointer.as_u64(), frame
+        );
+    }
+
+    true
+}
+
+fn registered_cores() -> impl Iterator<Item = &'static WatchedCore> {
+    CORES.iter()
+        .take(NEXT_SLOT.load(Ordering::Relaxed).min(MAX_CORES))
+        .filter(|core| core.registered.load(Ordering::Acquire))
+}
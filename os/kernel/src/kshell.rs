@@ -0,0 +1,110 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: kshell                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Minimal command interpreter on the serial port, running as a    ║
+   ║         kernel thread. It is independent of the userspace shell and     ║
+   ║         the terminal, so it can be used to inspect the system, even if  ║
+   ║         userspace is wedged. Kernel log messages are written to the     ║
+   ║         same serial port. Commands are read line by line (the input is  ║
+   ║         echoed) and the output is written directly to the serial port.  ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::str::FromStr;
+use log::{info, LevelFilter};
+use stream::{DecodedInputStream, OutputStream};
+use crate::device::hotplug;
+use crate::device::serial::SerialPort;
+use crate::process::thread::Thread;
+use crate::{logger, network, scheduler, serial_port};
+
+/// How often the serial input buffer is checked for new characters
+const POLL_INTERVAL_MS: usize = 50;
+/// Longer lines are cut off
+const MAX_LINE_LENGTH: usize = 128;
+const PROMPT: &str = "kshell> ";
+
+const HELP: &str = "\
+Kernel shell commands:
+  help              show this help
+  threads           list all threads with their state
+  sockets           list all sockets of all network namespaces
+  log <level>       set the log level (off, error, warn, info, debug, trace)
+  rescan            rescan the PCI bus for added or removed devices
+  panic             trigger a kernel panic
+";
+
+/// Start the kernel shell on the serial port (if there is one).
+/// The serial port's interrupts must have been enabled, because the input is read from its receive buffer.
+pub fn init() {
+    if serial_port().is_some() {
+        scheduler().ready(Thread::new_kernel_thread(run, "kshell"));
+        info!("Kernel shell available on the serial port (type 'help' for a list of commands)");
+    }
+}
+
+extern "sysv64" fn run() {
+    let serial = serial_port().expect("Kernel shell started without a serial port");
+    let mut line = String::new();
+
+    loop {
+        let Some(byte) = serial.decoded_try_read_byte() else {
+            scheduler().sleep(POLL_INTERVAL_MS);
+            continue;
+        };
+        // the receive buffer has been closed
+        if byte < 0 {
+            return;
+        }
+
+        match byte as u8 {
+            b'\r' | b'\n' => {
+                serial.write_str("\n");
+                if !line.trim().is_empty() {
+                    execute(&serial, line.trim());
+                }
+                line.clear();
+                serial.write_str(PROMPT);
+            }
+            // backspace (0x08) or delete (0x7f), depending on the terminal
+            0x08 | 0x7f => {
+                if line.pop().is_some() {
+                    serial.write_str("\x08 \x08");
+                }
+            }
+            byte if byte.is_ascii_graphic() || byte == b' ' => {
+                if line.len() < MAX_LINE_LENGTH {
+                    line.push(byte as char);
+                    serial.write_byte(byte);
+                }
+            }
+            // ignore control characters and escape sequences
+            _ => {}
+        }
+    }
+}
+
+fn execute(serial: &Arc<SerialPort>, line: &str) {
+    let mut args = line.split_whitespace();
+    let command = args.next().unwrap_or_default();
+
+    match command {
+        "help" => serial.write_str(HELP),
+        "threads" => serial.write_str(&scheduler().status()),
+        "sockets" => serial.write_str(&network::socket_status()),
+        "log" => match args.next().map(LevelFilter::from_str) {
+            Some(Ok(level)) => {
+                logger().set_level(level);
+                serial.write_str(&format!("Log level set to {}\n", level));
+            }
+            _ => serial.write_str("Usage: log <off|error|warn|info|debug|trace>\n"),
+        },
+        "rescan" => serial.write_str(&format!("{} device(s) added or removed\n", hotplug::rescan())),
+        "panic" => panic!("Panic requested via kernel shell"),
+        command => serial.write_str(&format!("Unknown command '{}' (type 'help' for a list of commands)\n", command)),
+    }
+}
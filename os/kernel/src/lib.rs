@@ -24,8 +24,10 @@ use crate::device::ps2::{Keyboard, Mouse, PS2};
 use crate::device::serial;
 use crate::device::serial::{BaudRate, ComPort, SerialPort};
 use crate::device::speaker::Speaker;
+use crate::entropy::EntropyPool;
+use crate::interrupt::halt;
 use crate::interrupt::interrupt_dispatcher::InterruptDispatcher;
-use crate::log::Logger;
+use crate::log::{FixedString, Logger, CRASH_MESSAGE_SIZE};
 use crate::memory::PAGE_SIZE;
 use crate::memory::acpi_handler::AcpiHandler;
 use crate::memory::heap::KernelAllocator;
@@ -33,14 +35,13 @@ use crate::process::process_manager::ProcessManager;
 use crate::process::scheduler::Scheduler;
 use crate::syscall::sys_graphic::LfbInfo;
 use crate::syscall::syscall_dispatcher::CoreLocalStorage;
-use alloc::format;
 use graphic::color::{BLUE, WHITE};
 use ::log::{Level, Log, Record, error};
 use acpi::AcpiTables;
 use alloc::string::String;
 use alloc::sync::Arc;
 use x86_64::instructions::interrupts;
-use core::fmt::Arguments;
+use core::fmt::{Arguments, Write};
 use core::hint::spin_loop;
 use core::panic::PanicInfo;
 use device::tty::{TtyInput, TtyOutput};
@@ -48,7 +49,6 @@ use graphic::buffered_lfb::BufferedLFB;
 use graphic::lfb::LFB;
 use multiboot2::ModuleTag;
 use spin::{Mutex, Once, RwLock};
-use tar_no_std::TarArchiveRef;
 use x86_64::PhysAddr;
 use x86_64::structures::gdt::GlobalDescriptorTable;
 use x86_64::structures::idt::InterruptDescriptorTable;
@@ -64,15 +64,21 @@ extern crate llfree;
 pub mod device;
 pub mod boot;
 pub mod consts;
+pub mod entropy;
 pub mod interrupt;
+pub mod kshell;
 pub mod log;
 pub mod memory;
 pub mod naming;
 pub mod network;
+pub mod power;
 pub mod process;
+pub mod splash;
 pub mod storage;
 pub mod syscall;
 pub mod sync;
+pub mod timesync;
+pub mod wallclock;
 
 pub mod built_info {
     // The file has been placed there by the build script
@@ -83,10 +89,18 @@ pub mod built_info {
 fn panic(info: &PanicInfo) -> ! {
     // make sure we never exit
     interrupts::disable();
-    
+
+    // stop the other cores, so they can't interfere with the panic output
+    if !halt::halt_other_cores() {
+        // another core is already panicking and reports
+        halt::park();
+    }
+
     // write the panic directly out to the serial port
     // this needs no allocations and should always work
     unsafe { logger().force_unlock() };
+    // don't hide the panic behind the boot screen
+    unsafe { splash::force_text_mode() };
     error!("Panic:");
     let args = info.message().as_str().unwrap_or("(no message provided)");
     let record = Record::builder()
@@ -108,15 +122,20 @@ fn panic(info: &PanicInfo) -> ! {
         lfb.direct_lfb().draw_string(lfb_width/7, lfb_height/3, WHITE, BLUE, "D3OS has encountered an unknown error:");
         (lfb, lfb_height, lfb_width)
     });
-    // if we do have an allocator already, try to use it to print more information
-    // this might fail, but we got the basic information out already
+    // format the complete panic message into a statically allocated buffer,
+    // because the heap might be inconsistent or not initialized yet
+    let mut message = PANIC_MESSAGE.lock();
+    message.clear();
+    let _ = write!(message, "{info}");
+    // the logger needs the heap for formatted messages early in the boot process
     if allocator().is_initialized() {
-        let message = format!("{info}");
-        error!("{message}");
-        if let Some((mut lfb, lfb_height, lfb_width)) = lfb_info {
-            lfb.direct_lfb().draw_string(lfb_width/7, lfb_height/2, WHITE, BLUE, &message);
-        }
+        error!("{}", message.as_str());
     }
+    logger().write_crash_dump(message.as_str());
+    if let Some((mut lfb, lfb_height, lfb_width)) = lfb_info {
+        lfb.direct_lfb().draw_string(lfb_width/7, lfb_height/2, WHITE, BLUE, message.as_str());
+    }
+    halt::log_halted_cores();
 
     loop {
         spin_loop();
@@ -130,6 +149,9 @@ fn panic(info: &PanicInfo) -> ! {
 ║ once, they are shared as static lifetime references.                    ║
 ╚═════════════════════════════════════════════════════════════════════════╝ */
 
+/// Buffer for the message of a panic (only used by the panic handler, which runs on one core at a time).
+static PANIC_MESSAGE: Mutex<FixedString<CRASH_MESSAGE_SIZE>> = Mutex::new(FixedString::new());
+
 /// CPU caps.
 static CPU: Once<Cpu> = Once::new();
 
@@ -213,10 +235,10 @@ pub fn acpi_tables() -> &'static Mutex<AcpiTables<AcpiHandler>> {
 }
 
 /// Initial Ramdisk.
-/// The initial ramdisk is TAR archive, loaded into memory by the bootloader.
-/// It contains all programs that D3OS can execute.
+/// The initial ramdisk is an archive (ustar or cpio), loaded into memory by the bootloader.
+/// It contains all programs that D3OS can execute and is mounted by the naming service (see 'naming::archive').
 /// 'boot.rs' initializes this struct by calling 'init_initrd()' after obtaining the corresponding multiboot2 tag.
-static INIT_RAMDISK: Once<TarArchiveRef> = Once::new();
+static INIT_RAMDISK: Once<&'static [u8]> = Once::new();
 
 pub fn get_initrd_frames(module: &ModuleTag) -> PhysFrameRange {
     PhysFrameRange {
@@ -230,21 +252,16 @@ pub fn get_initrd_frames(module: &ModuleTag) -> PhysFrameRange {
 }
 
 pub fn init_initrd(module: &ModuleTag) {
-    INIT_RAMDISK.call_once(|| {
-        let initrd_bytes = unsafe {
-            core::slice::from_raw_parts(
-                module.start_address() as *const u8,
-                (module.end_address() - module.start_address()) as usize,
-            )
-        };
-
-        TarArchiveRef::new(initrd_bytes)
-            .expect("Failed to create TarArchiveRef from Multiboot2 module")
+    INIT_RAMDISK.call_once(|| unsafe {
+        core::slice::from_raw_parts(
+            module.start_address() as *const u8,
+            (module.end_address() - module.start_address()) as usize,
+        )
     });
 }
 
-pub fn initrd() -> &'static TarArchiveRef<'static> {
-    INIT_RAMDISK
+pub fn initrd() -> &'static [u8] {
+    *INIT_RAMDISK
         .get()
         .expect("Trying to access initial ramdisk before initialization!")
 }
@@ -295,6 +312,14 @@ pub fn interrupt_dispatcher() -> &'static InterruptDispatcher {
     INTERRUPT_DISPATCHER.get().unwrap()
 }
 
+/// Entropy Pool.
+/// Collects entropy from interrupt timing and other sources and generates random numbers (e.g. for the 'getrandom' syscall).
+static ENTROPY_POOL: EntropyPool = EntropyPool::new();
+
+pub fn entropy_pool() -> &'static EntropyPool {
+    &ENTROPY_POOL
+}
+
 /*
 ╔═════════════════════════════════════════════════════════════════════════╗
 ║ Device driver instances.                                                ║
@@ -321,8 +346,8 @@ pub fn apic() -> &'static Apic {
 }
 
 /// Programmable Interval Timer.
-/// The timer generates an interrupt each millisecond to keep track of the system time.
-/// In the future, we will probably replace it with the HPET or TSC.
+/// The timer generates an interrupt each millisecond to keep track of the system time,
+/// which is taken from a more precise clock source (HPET or pvclock), if available.
 static TIMER: Once<Arc<Timer>> = Once::new();
 
 pub fn timer() -> Arc<Timer> {
@@ -341,7 +366,8 @@ pub fn speaker() -> Arc<Speaker> {
 
 /// Serial Port.
 /// Currently only one serial port is initialized. Once we have a driver framework, multiple serial ports can be supported.
-/// At the moment, the serial port is only used to print kernel log messages.
+/// At the moment, the serial port is used to print kernel log messages and for the kernel shell (see `kshell`)
+/// or as the console of the user space shell (see `serial_console`).
 static SERIAL_PORT: Once<Arc<SerialPort>> = Once::new();
 
 pub fn init_serial_port() {
@@ -395,21 +421,23 @@ pub fn tty_output() -> Arc<TtyOutput> {
 }
 
 /// PS/2 Controller.
-/// Used to access PS/2 devices like the keyboard or mouse. Currently only the keyboard is supported.
+/// Used to access PS/2 devices like the keyboard or mouse.
 static PS2: Once<Arc<PS2>> = Once::new();
 
 fn init_ps2() -> Arc<PS2> {
     let mut ps2 = PS2::new();
     match ps2.init_controller() {
-        Ok(_) => {
+        Ok(mouse_present) => {
             match ps2.init_keyboard() {
                 Ok(_) => {}
                 Err(error) => error!("Keyboard initialization failed: {:?}", error),
             }
 
-            match ps2.init_mouse() {
-                Ok(_) => {}
-                Err(error) => error!("Mouse initialization failed: {:?}", error),
+            if mouse_present {
+                match ps2.init_mouse() {
+                    Ok(_) => {}
+                    Err(error) => error!("Mouse initialization failed: {:?}", error),
+                }
             }
         }
         Err(error) => error!("PS/2 controller initialization failed: {:?}", error),
@@ -458,6 +486,14 @@ pub fn init_lfb(buffer: *mut u8, pitch: u32, width: u32, height: u32, bpp: u8) {
     });
 }
 
+/// Switch to another framebuffer (e.g. set up by a graphics driver after boot).
+pub fn replace_lfb(buffer: *mut u8, pitch: u32, width: u32, height: u32, bpp: u8) {
+    match BUFFERED_LFB.get() {
+        Some(lfb) => *lfb.lock() = BufferedLFB::new(LFB::new(buffer, pitch, width, height, bpp)),
+        None => init_lfb(buffer, pitch, width, height, bpp),
+    }
+}
+
 pub fn buffered_lfb() -> &'static Mutex<BufferedLFB> {
     BUFFERED_LFB
         .get()
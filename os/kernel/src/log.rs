@@ -3,6 +3,8 @@
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Descr.: Logger implementation. Support one or several output streams.   ║
    ║         Messages are dumped on each output stream.                      ║
+   ║         The most recent messages are kept in memory and can be written  ║
+   ║         as an LZ4 compressed crash dump or persisted as boot log.       ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Fabian Ruhland & Niklas Sombert, HHU                            ║
    ╚═════════════════════════════════════════════════════════════════════════╝
@@ -19,15 +21,75 @@ use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::ptr;
-use log::{Level, Metadata, Record};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use log::{Level, LevelFilter, Metadata, Record};
 use thingbuf::recycling::WithCapacity;
 use thingbuf::ThingBuf;
 use spin::{Mutex, Once};
 use crate::built_info;
+use crate::naming::api;
+use naming::shared_types::OpenOptions;
+use syscall::return_vals::Errno;
+
+/// Number of bytes of recent log output kept in memory
+const HISTORY_SIZE: usize = 0x10000;
+/// Number of hex characters per line in a crash dump
+const CRASH_DUMP_LINE_LENGTH: usize = 64;
+/// Maximum length of the panic message appended to a crash dump
+pub const CRASH_MESSAGE_SIZE: usize = 0x1000;
+/// Size of the uncompressed crash dump (log history and panic message)
+const CRASH_DUMP_SIZE: usize = HISTORY_SIZE + CRASH_MESSAGE_SIZE;
+
+/// Statically allocated buffers for the crash dump, because the heap must not be used while panicking
+struct CrashDumpBuffer {
+    dump: [u8; CRASH_DUMP_SIZE],
+    /// The compressed dump, prefixed with the uncompressed size (u32, little endian)
+    compressed: [u8; 4 + lz4::compress_bound(CRASH_DUMP_SIZE)],
+}
+
+static CRASH_DUMP_BUFFER: Mutex<CrashDumpBuffer> = Mutex::new(CrashDumpBuffer {
+    dump: [0; CRASH_DUMP_SIZE],
+    compressed: [0; 4 + lz4::compress_bound(CRASH_DUMP_SIZE)],
+});
+
+/// String with a fixed capacity, which can be formatted into without heap allocations.
+/// Text exceeding the capacity is cut off.
+pub struct FixedString<const N: usize> {
+    data: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> FixedString<N> {
+    pub const fn new() -> Self {
+        Self { data: [0; N], len: 0 }
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only complete characters are copied in `write_str()`
+        unsafe { core::str::from_utf8_unchecked(&self.data[..self.len]) }
+    }
+}
+
+impl<const N: usize> Write for FixedString<N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let mut len = s.len().min(N - self.len);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        self.data[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
 
 pub struct Logger {
-    /// the verbosity
-    level: Level,
+    /// the verbosity (a `LevelFilter` as `usize`)
+    level: AtomicUsize,
     /// The queue messages are placed into. This is lock-free and needs no
     /// additional heap allocations after its creation.
     queue: Once<ThingBuf<String, WithCapacity>>,
@@ -36,11 +98,75 @@ pub struct Logger {
     /// If there are no streams and no queue (in the very early boot process),
     /// text is instead written directly to the serial port.
     serial: Option<SerialPort>,
+    /// The most recent output, used for crash dumps and the boot log.
+    /// This is registered as one of the streams.
+    history: Once<Arc<LogHistory>>,
+    /// Number of error messages logged so far
+    errors: AtomicUsize,
+}
+
+/// Ring buffer holding the last `HISTORY_SIZE` bytes written to the log.
+struct LogHistory {
+    buffer: Mutex<HistoryBuffer>,
+}
+
+struct HistoryBuffer {
+    data: Vec<u8>,
+    /// Position of the oldest byte (only relevant once the buffer is full)
+    start: usize,
+}
+
+impl LogHistory {
+    fn new() -> Self {
+        Self { buffer: Mutex::new(HistoryBuffer { data: Vec::with_capacity(HISTORY_SIZE), start: 0 }) }
+    }
+
+    /// Return the content of the ring buffer in chronological order.
+    fn snapshot(&self) -> Vec<u8> {
+        let buffer = self.buffer.lock();
+        let mut snapshot = Vec::with_capacity(buffer.data.len());
+        snapshot.extend_from_slice(&buffer.data[buffer.start..]);
+        snapshot.extend_from_slice(&buffer.data[..buffer.start]);
+        snapshot
+    }
+
+    /// Copy the content of the ring buffer in chronological order into `target` (without heap allocations)
+    /// and return the number of copied bytes. `target` must be able to hold `HISTORY_SIZE` bytes.
+    fn copy_to(&self, target: &mut [u8]) -> usize {
+        let buffer = self.buffer.lock();
+        let (newer, older) = buffer.data.split_at(buffer.start);
+        target[..older.len()].copy_from_slice(older);
+        target[older.len()..older.len() + newer.len()].copy_from_slice(newer);
+        buffer.data.len()
+    }
+}
+
+impl HistoryBuffer {
+    fn push(&mut self, b: u8) {
+        if self.data.len() < HISTORY_SIZE {
+            // this doesn't allocate, because the buffer is pre-allocated
+            self.data.push(b);
+        } else {
+            self.data[self.start] = b;
+            self.start = (self.start + 1) % HISTORY_SIZE;
+        }
+    }
+}
+
+impl OutputStream for LogHistory {
+    fn write_byte(&self, b: u8) {
+        self.buffer.lock().push(b);
+    }
+
+    fn write_str(&self, string: &str) {
+        let mut buffer = self.buffer.lock();
+        string.bytes().for_each(|b| buffer.push(b));
+    }
 }
 
 impl log::Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= self.level
+        metadata.level() as usize <= self.level.load(Ordering::Relaxed)
     }
 
     fn log(&self, record: &Record) {
@@ -49,6 +175,9 @@ impl log::Log for Logger {
         }
 
         let level = record.metadata().level();
+        if level == Level::Error {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
         let file = record.file().unwrap_or("unknown").split('/').next_back().unwrap_or("unknown");
         let line = record.line().unwrap_or(0);
 
@@ -137,13 +266,28 @@ impl Logger {
         }
 
         Self {
-            level: if built_info::PROFILE == "debug" { Level::Debug } else { Level::Info },
+            level: AtomicUsize::new(if built_info::PROFILE == "debug" { LevelFilter::Debug } else { LevelFilter::Info } as usize),
             queue: Once::new(),
             streams: Mutex::new(Vec::new()),
-            serial
+            serial,
+            history: Once::new(),
+            errors: AtomicUsize::new(0),
         }
     }
 
+    /// Change the verbosity at runtime (this also raises the global maximum level of the `log` crate, if needed).
+    pub fn set_level(&self, level: LevelFilter) {
+        self.level.store(level as usize, Ordering::Relaxed);
+        if level > log::max_level() {
+            log::set_max_level(level);
+        }
+    }
+
+    /// Number of error messages logged so far (used to detect failures during boot).
+    pub fn error_count(&self) -> usize {
+        self.errors.load(Ordering::Relaxed)
+    }
+
     pub fn register(&self, stream: Arc<dyn OutputStream>) {
         // make sure we have a queue
         self.queue.call_once(|| {
@@ -158,7 +302,13 @@ impl Logger {
             while buf.pop_ref().is_some() {}
             buf
         });
-        self.streams.lock().push(stream);
+        let history = self.history.call_once(|| Arc::new(LogHistory::new()));
+
+        let mut streams = self.streams.lock();
+        if streams.is_empty() {
+            streams.push(Arc::clone(history) as Arc<dyn OutputStream>);
+        }
+        streams.push(stream);
     }
 
     pub fn remove(&self, stream: &dyn OutputStream) {
@@ -170,6 +320,69 @@ impl Logger {
     /// Unlock all streams. This may cause gibberish.
     pub unsafe fn force_unlock(&self) {
         unsafe { self.streams.force_unlock() };
+        if let Some(history) = self.history.get() {
+            unsafe { history.buffer.force_unlock() };
+        }
+    }
+
+    /// Return the recent log output (or an empty buffer, if no history has been recorded yet).
+    pub fn history(&self) -> Vec<u8> {
+        self.history.get().map_or(Vec::new(), |history| history.snapshot())
+    }
+
+    /// Return the recent log output, compressed with LZ4 and prefixed with the uncompressed size.
+    /// Returns `None`, if no history has been recorded yet (no stream registered).
+    pub fn compressed_history(&self) -> Option<Vec<u8>> {
+        self.history.get().map(|history| lz4::compress_prepend_size(&history.snapshot()))
+    }
+
+    /// Write the recent log output together with the panic `message` as a compressed crash dump
+    /// to all streams. The dump is hex encoded and can be decoded with `xxd -r -p` and any LZ4
+    /// block decoder (the first 4 bytes contain the uncompressed size in little endian).
+    /// Only statically allocated buffers are used, so this works without a (consistent) heap.
+    /// The message is cut off after `CRASH_MESSAGE_SIZE` bytes.
+    pub fn write_crash_dump(&self, message: &str) {
+        let Some(history) = self.history.get() else {
+            return;
+        };
+        // Only one core panics, so this is only locked, if writing the crash dump has panicked itself
+        let Some(mut buffer) = CRASH_DUMP_BUFFER.try_lock() else {
+            return;
+        };
+        let CrashDumpBuffer { dump, compressed } = &mut *buffer;
+
+        let mut len = history.copy_to(dump);
+        let message = &message.as_bytes()[..message.len().min(CRASH_MESSAGE_SIZE)];
+        dump[len..len + message.len()].copy_from_slice(message);
+        len += message.len();
+
+        compressed[..4].copy_from_slice(&(len as u32).to_le_bytes());
+        let compressed_len = 4 + lz4::compress_into_slice(&dump[..len], &mut compressed[4..]);
+
+        let streams = self.streams.lock();
+        let mut header = FixedString::<128>::new();
+        write!(header, "*** BEGIN CRASH DUMP (lz4, {len} bytes compressed to {compressed_len} bytes) ***\n").unwrap();
+        write_message_to_all_streams(header.as_str(), &streams);
+        let mut line = FixedString::<{ CRASH_DUMP_LINE_LENGTH + 1 }>::new();
+        for chunk in compressed[..compressed_len].chunks(CRASH_DUMP_LINE_LENGTH / 2) {
+            line.clear();
+            for byte in chunk {
+                write!(line, "{byte:02x}").unwrap();
+            }
+            line.write_char('\n').unwrap();
+            write_message_to_all_streams(line.as_str(), &streams);
+        }
+        write_message_to_all_streams("*** END CRASH DUMP ***\n", &streams);
+    }
+
+    /// Write the compressed log history (see [`Logger::compressed_history`]) into the file at `path`.
+    pub fn persist_history(&self, path: &str) -> Result<usize, Errno> {
+        let compressed = self.compressed_history().ok_or(Errno::ENOENT)?;
+
+        let handle = api::open(path, OpenOptions::CREATE | OpenOptions::READWRITE | OpenOptions::TRUNCATE)?;
+        let written = api::write(handle, &compressed);
+        api::close(handle)?;
+        written
     }
 }
 
@@ -4,7 +4,8 @@
    ║ This module provides functions for collecting information regarding     ║
    ║ available and reserved physical memory regions from EFI during boot     ║
    ║ time. After the kernel heap and page frame allocator are setup this     ║ 
-   ║ module is no longer used.                                               ║
+   ║ module is only used to look up the regions of the firmware's memory     ║
+   ║ map, so PCI devices are not placed on top of them.                      ║
    ║                                                                         ║
    ║ Public functions:                                                       ║
    ║   - limit             highest dram address on this system               ║
@@ -15,6 +16,8 @@
    ║   - dump              dump the collected dram information               ║
    ║   - get_all_reserved  get a ro view into the finalized reserved regions ║
    ║   - get_all_available get a ro view into the finalized avail. regions   ║
+   ║   - insert_firmware   insert a region described by the firmware's map   ║
+   ║   - firmware_overlap  find a firmware region overlapping an addr. range ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Michael Schoettner, Univ. Duesseldorf, 2.4.2026                 ║
    ╚═════════════════════════════════════════════════════════════════════════╝
@@ -64,6 +67,7 @@ const EMPTY_REGION_SET: RegionSet = RegionSet {
 
 static AVAILABLE_REGIONS: Mutex<RegionSet> = Mutex::new(EMPTY_REGION_SET);
 static RESERVED_REGIONS: Mutex<RegionSet> = Mutex::new(EMPTY_REGION_SET);
+static FIRMWARE_REGIONS: Mutex<RegionSet> = Mutex::new(EMPTY_REGION_SET); // All regions of the memory map (RAM, ACPI, MMIO, ...)
 
 
 /// Insert a available physical memory region (retrieved from EFI) into the available region set
@@ -92,6 +96,23 @@ pub fn insert_reserved(region: PhysFrameRange) {
 }
 
 
+/// Insert a region of the physical address space, which the memory map of the firmware describes (whatever its type). \
+/// These regions are kept after finalization, so devices are never placed on top of them.
+pub fn insert_firmware(region: PhysFrameRange) {
+    let mut firmware = FIRMWARE_REGIONS.lock();
+    merge_region(&mut *firmware, region.start.start_address(), region.end.start_address());
+}
+
+/// Get the first region of the firmware's memory map, that overlaps `start..end`
+pub fn firmware_overlap(start: u64, end: u64) -> Option<Region> {
+    let firmware = FIRMWARE_REGIONS.lock();
+    firmware.regions[..firmware.count]
+        .iter()
+        .find(|region| region.start.as_u64() < end && start < region.end.as_u64())
+        .copied()
+}
+
+
 fn insert_region(set: &mut RegionSet, new_region: PhysFrameRange) {
     if DRAM_FINALIZED.load(Ordering::Acquire) {
         panic!("available: DRAM regions have already been finalized");
@@ -103,6 +124,11 @@ fn insert_region(set: &mut RegionSet, new_region: PhysFrameRange) {
     // Update the physical limit if this region extends beyond the current limit.
     DRAM_LIMIT.fetch_max(end.as_u64(), Ordering::SeqCst);
 
+    merge_region(set, start, end);
+}
+
+/// Insert `start..end` into a sorted region set and merge it with overlapping or adjacent regions
+fn merge_region(set: &mut RegionSet, start: PhysAddr, end: PhysAddr) {
     let regions = &mut set.regions;
     let mut count = set.count;
 
@@ -11,6 +11,7 @@
    ║   - boot_reserve       reserve a range of frames during boot            ║
    ║   - frame_from_u64     convert a u64 address to a PhysFrame             ║
    ║   - get_total_free_frames  return currently number of free frames       ║
+   ║   - get_total_frames   return the number of frames inserted during boot ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Fabian Ruhland and Michael Schoettner                           ║
    ║         Univ. Duesseldorf, 2.4.2026                                     ║
@@ -20,6 +21,7 @@ use alloc::format;
 use alloc::string::String;
 use core::fmt::{Debug, Formatter};
 use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use log::{info, trace};
 use spin::Mutex;
 use x86_64::PhysAddr;
@@ -64,6 +66,14 @@ pub(super) fn get_total_free_frames() -> usize {
 
 static PAGE_FRAME_ALLOCATOR: Mutex<PageFrameListAllocator> = Mutex::new(PageFrameListAllocator::new());
 
+/// Number of frames inserted during boot (free or allocated)
+static TOTAL_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+/// Return the number of frames managed by the allocator.
+pub(super) fn get_total_frames() -> usize {
+    TOTAL_FRAMES.load(Ordering::Relaxed)
+}
+
 /// Check if the page frame allocator is currently locked.
 pub(super) fn allocator_locked() -> bool {
     PAGE_FRAME_ALLOCATOR.is_locked()
@@ -89,6 +99,7 @@ pub fn mark_avail(mut region: PhysFrameRange) {
         region.start = first_page; // Cut first page out of region and continue
     }
 
+    TOTAL_FRAMES.fetch_add((region.end - region.start) as usize, Ordering::Relaxed);
     unsafe {
         free(region);
     }
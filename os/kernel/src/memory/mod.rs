@@ -7,6 +7,7 @@ pub mod frames_lf;
 pub mod nvmem;
 pub mod dram;
 pub mod shm;
+pub mod tlb;
 
 pub mod heap;
 pub mod stack;
@@ -73,4 +74,9 @@ pub fn frame_allocator_locked() -> bool {
 /// Wrapper function
 pub fn get_total_free_frames() -> usize {
     frames::get_total_free_frames()
-}
\ No newline at end of file
+}
+
+/// Wrapper function
+pub fn get_total_frames() -> usize {
+    frames::get_total_frames()
+}
@@ -8,6 +8,10 @@
    ║   - set_flags     set flags of page table entries for a range of pages  ║
    ║   - translate     translate a virtual address to a physical address     ║
    ║   - unmap         unmap a range of pages                                ║
+   ║   - unmap_entries first step of unmap: remove the page table entries    ║
+   ║   - flush_and_free second step of unmap: flush TLBs and free the frames ║
+   ║   - copy_on_write give a copy-on-write page its own, writable frame     ║
+   ║   - map_on_demand map a zeroed frame to a page on its first access      ║
    ║   - page_from_u64 convert a u64 address to a Page                       ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Fabian Ruhland, Univ. Duesseldorf, 24.5.2025                    ║
@@ -17,8 +21,9 @@
 
 use core::cmp::min;
 use core::{ptr, fmt};
+use alloc::vec::Vec;
 use spin::RwLock;
-use x86_64::structures::paging::{PageTable, PageTableFlags, PageTableIndex, PhysFrame};
+use x86_64::structures::paging::{PageTable, PageTableEntry, PageTableFlags, PageTableIndex, PhysFrame};
 use x86_64::{PhysAddr, VirtAddr};
 use x86_64::registers::control::{Cr3, Cr3Flags};
 use x86_64::structures::paging::frame::PhysFrameRange;
@@ -26,13 +31,20 @@ use x86_64::structures::paging::page::{PageRange,Page};
 use x86_64::structures::paging::Size4KiB;
 use log::{info, debug};
 
-use crate::memory::{MemorySpace, PAGE_SIZE, frames};
+use crate::memory::{MemorySpace, PAGE_SIZE, frames, tlb};
 
 /// Helper function to convert a u64 address to a PhysFrame.
 pub fn page_from_u64(addr: u64) -> Result<Page<Size4KiB>, x86_64::structures::paging::page::AddressNotAligned> {
     Page::from_start_address(VirtAddr::new(addr))
 }
 
+/// Page table entry flag (available to the OS): The frame is shared with other address spaces (e.g. a shared library)
+/// and is not freed, when the page is unmapped.
+pub const SHARED: PageTableFlags = PageTableFlags::BIT_9;
+
+/// Page table entry flag (available to the OS): The page is mapped read-only, but gets its own copy of the frame
+/// on the first write access (see `Paging::copy_on_write()`).
+pub const COPY_ON_WRITE: PageTableFlags = PageTableFlags::BIT_10;
 
 /// Address space for a process
 pub struct Paging {
@@ -147,12 +159,35 @@ impl Paging {
 
     /// Unmap a range of `pages` from the address space. 
     /// `free_physical` indicates if the physical frames should be freed.
-    pub(super) fn unmap(&self, pages: PageRange, free_physical: bool) {
+    /// Frames (and emptied page tables) are only freed after the pages have been flushed from the TLBs of all cores. \
+    /// Returns the number of pages, that have actually been mapped.
+    pub(super) fn unmap(&self, pages: PageRange, free_physical: bool) -> u64 {
+        let (unused_frames, unmapped) = self.unmap_entries(pages, free_physical);
+        Paging::flush_and_free(pages, unused_frames);
+        unmapped
+    }
+
+    /// First half of `unmap()`: Remove the entries for `pages`, without flushing the TLBs. \
+    /// Returns the frames to be freed by `flush_and_free()` (the pages may still be accessed through stale TLB entries until then)
+    /// and the number of pages, that have actually been mapped.
+    pub(super) fn unmap_entries(&self, pages: PageRange, free_physical: bool) -> (Vec<PhysFrame>, u64) {
         let depth = self.depth;
         let root_table_guard = self.root_table.read();
         let root_table = unsafe { root_table_guard.as_mut().unwrap() };
 
-        Paging::unmap_in_table(root_table, pages, depth, free_physical);
+        let mut unused_frames = Vec::new();
+        let mut unmapped = 0;
+        Paging::unmap_in_table(root_table, pages, depth, free_physical, &mut unused_frames, &mut unmapped);
+        (unused_frames, unmapped)
+    }
+
+    /// Second half of `unmap()`: Flush `pages` from the TLBs of all cores and free `unused_frames` afterwards.
+    pub(super) fn flush_and_free(pages: PageRange, unused_frames: Vec<PhysFrame>) {
+        tlb::shootdown(pages);
+
+        for frame in unused_frames {
+            unsafe { frames::free(PhysFrameRange { start: frame, end: frame + 1 }); }
+        }
     }
 
     /// Set `flags` of page table entries for the give range of `pages`` 
@@ -162,6 +197,58 @@ impl Paging {
         let root_table = unsafe { root_table_guard.as_mut().unwrap() };
 
         Paging::set_flags_in_table(root_table, pages, flags, depth);
+        tlb::shootdown(pages);
+    }
+
+    /// Resolve a write access to the page containing `addr`, if it is mapped with `COPY_ON_WRITE`:
+    /// The page gets a private copy of its shared frame and becomes writable. \
+    /// Returns false, if the page may not be written at all.
+    pub(super) fn copy_on_write(&self, addr: VirtAddr) -> bool {
+        let depth = self.depth;
+        let root_table_guard = self.root_table.write();
+        let root_table = unsafe { root_table_guard.as_mut().unwrap() };
+
+        let Some(entry) = Paging::entry_in_table(root_table, addr, depth) else {
+            return false;
+        };
+        let flags = entry.flags();
+        if flags.contains(PageTableFlags::WRITABLE) {
+            // Another thread of the process has already copied the page
+            return true;
+        }
+        if !flags.contains(COPY_ON_WRITE) {
+            return false;
+        }
+
+        let frame = frames::alloc(1).start;
+        unsafe {
+            let source = entry.addr().as_u64() as *const u8;
+            (frame.start_address().as_u64() as *mut u8).copy_from_nonoverlapping(source, PAGE_SIZE);
+        }
+        entry.set_frame(frame, (flags - SHARED - COPY_ON_WRITE) | PageTableFlags::WRITABLE);
+
+        let page = Page::containing_address(addr);
+        tlb::shootdown(PageRange { start: page, end: page + 1 });
+        true
+    }
+
+    /// Map a new, zeroed frame with the given `flags` to `page` in user space, if it is not mapped yet
+    /// (several threads may fault on the same page at the same time). \
+    /// Returns false, if the page has already been mapped.
+    pub(super) fn map_on_demand(&self, page: Page, flags: PageTableFlags) -> bool {
+        let depth = self.depth;
+        let root_table_guard = self.root_table.write();
+        let root_table = unsafe { root_table_guard.as_mut().unwrap() };
+
+        if Paging::entry_in_table(root_table, page.start_address(), depth).is_some() {
+            return false;
+        }
+
+        // Frames are not cleared when they are freed, so the page must not show data of another process
+        let frames = frames::alloc(1);
+        unsafe { (frames.start.start_address().as_u64() as *mut u8).write_bytes(0, PAGE_SIZE); }
+        Paging::map_in_table(root_table, frames, PageRange { start: page, end: page + 1 }, MemorySpace::User, flags, depth);
+        true
     }
     
     pub fn dump(&self) {
@@ -239,8 +326,9 @@ impl Paging {
             for entry in table.iter_mut().skip(start_index) {
                 let next_level_table;
                 if entry.is_unused() { // Entry is empty -> Allocate new page frame
+                    // Access rights are only restricted in the level 1 entries, since other pages may share this table
                     let phys_frame = frames::alloc(1).start;
-                    entry.set_frame(phys_frame, flags);
+                    entry.set_frame(phys_frame, (flags | PageTableFlags::WRITABLE) - SHARED - COPY_ON_WRITE);
 
                     next_level_table = unsafe { (entry.addr().as_u64() as *mut PageTable).as_mut().unwrap() };
                     next_level_table.zero();
@@ -277,24 +365,36 @@ impl Paging {
     }
 
     /// Internal recursive function to unmap a range of `pages` where `free_phyisical` defines if frame should be freed.
-    fn unmap_in_table(table: &mut PageTable, mut pages: PageRange, level: usize, free_physical: bool) -> usize {
+    /// Frames to be freed are collected in `unused_frames` and `unmapped` is increased by the number of removed level 1 entries.
+    fn unmap_in_table(
+        table: &mut PageTable, mut pages: PageRange, level: usize, free_physical: bool, unused_frames: &mut Vec<PhysFrame>, unmapped: &mut u64,
+    ) -> usize {
         let mut total_freed_pages: usize = 0;
         let start_index = usize::from(page_table_index(pages.start.start_address(), level));
 
         if level > 1 { // Calculate next level page table until level == 1
             for entry in table.iter_mut().skip(start_index) {
                 if entry.is_unused() {
+                    // Nothing is mapped in the part of `pages` covered by this entry (e.g. in sparse demand paged memory)
+                    let pages_per_entry = 512u64.pow(level as u32 - 1);
+                    let offset = (pages.start.start_address().as_u64() / PAGE_SIZE as u64) % pages_per_entry;
+                    let skipped = min(pages_per_entry - offset, pages.end - pages.start);
+                    pages = PageRange { start: pages.start + skipped, end: pages.end };
+                    total_freed_pages += skipped as usize;
+
+                    if pages.start >= pages.end {
+                        break;
+                    }
                     continue;
                 }
 
                 let next_level_table = unsafe { (entry.addr().as_u64() as *mut PageTable).as_mut().unwrap() };
-                let freed_pages = Paging::unmap_in_table(next_level_table, pages, level - 1, free_physical);
+                let freed_pages = Paging::unmap_in_table(next_level_table, pages, level - 1, free_physical, unused_frames, unmapped);
                 pages = PageRange { start: pages.start + freed_pages as u64, end: pages.end };
                 total_freed_pages += freed_pages;
 
                 if Paging::is_table_empty(next_level_table) {
-                    let table_frame = PhysFrame::from_start_address(entry.addr()).unwrap();
-                    unsafe { frames::free(PhysFrameRange { start: table_frame, end: table_frame + 1 }); }
+                    unused_frames.push(PhysFrame::from_start_address(entry.addr()).unwrap());
                     entry.set_unused();
                 }
 
@@ -312,12 +412,13 @@ impl Paging {
                 }
 
                 if !entry.is_unused() {
-                    if free_physical {
-                        let frame = PhysFrame::from_start_address(entry.addr()).unwrap();
-                        unsafe { frames::free(PhysFrameRange { start: frame, end: frame + 1 }); }
+                    // Shared frames are owned by someone else (e.g. a shared library)
+                    if free_physical && !entry.flags().contains(SHARED) {
+                        unused_frames.push(PhysFrame::from_start_address(entry.addr()).unwrap());
                     }
 
                     entry.set_unused();
+                    *unmapped += 1;
                 }
             }
 
@@ -403,6 +504,22 @@ impl Paging {
         }
     }
 
+    /// Internal recursive function returning the level 1 entry for the given virtual address `addr` or None.
+    fn entry_in_table(table: &mut PageTable, addr: VirtAddr, level: usize) -> Option<&mut PageTableEntry> {
+        let index = usize::from(page_table_index(addr, level));
+        let entry = &mut table[index];
+        if entry.is_unused() {
+            return None;
+        }
+
+        if level > 1 { // Calculate next level page table until level == 1
+            let next_level_table = unsafe { (entry.addr().as_u64() as *mut PageTable).as_mut().unwrap() };
+            Paging::entry_in_table(next_level_table, addr, level - 1)
+        } else { // Reached level 1 page table
+            Some(entry)
+        }
+    }
+
     /// Create 1:1 mapping entries in the given page `table` for `pages` with the given `flags` for the kernel space.
     fn identity_map_kernel(table: &mut PageTable, pages: PageRange, flags: PageTableFlags) -> usize {
         let start_index = usize::from(page_table_index(pages.start.start_address(), 1));
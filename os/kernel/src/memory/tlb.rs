@@ -0,0 +1,146 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: tlb                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ TLB shootdown. After page table entries have been removed or their      ║
+   ║ flags have been changed, stale translations must be flushed on every    ║
+   ║ core, that may have cached them. The local TLB is flushed directly,     ║
+   ║ other cores get a request in their invalidation queue and an IPI and    ║
+   ║ flush the pages in the interrupt handler. The initiator waits, until    ║
+   ║ all cores have acknowledged, so frames can be freed afterwards.         ║
+   ║ Cores are not tracked per address space, so all of them are targeted.   ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - init                 register the bootstrap processor and the IPI   ║
+   ║                          handler                                        ║
+   ║   - register_cpu         add the calling core to the shootdown targets  ║
+   ║   - shootdown            invalidate a range of pages on all cores       ║
+   ║   - try_for_each_cpu     call a function for each registered core       ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::{Mutex, RwLock};
+use x86_64::instructions::{interrupts, tlb};
+use x86_64::structures::paging::page::PageRange;
+use crate::interrupt::ipi::{self, Ipi};
+use crate::interrupt::interrupt_handler::InterruptHandler;
+use crate::apic;
+
+/// Larger ranges are not flushed page by page, but by flushing the whole TLB
+const MAX_INVLPG_PAGES: u64 = 32;
+
+/// All cores, that take part in TLB shootdowns
+static CPUS: RwLock<Vec<Arc<CpuQueue>>> = RwLock::new(Vec::new());
+
+/// Invalidation queue of a core (identified by its local APIC id)
+struct CpuQueue {
+    apic_id: u32,
+    requests: Mutex<Vec<Request>>,
+}
+
+#[derive(Clone, Copy)]
+enum Invalidation {
+    Pages(PageRange),
+    All,
+}
+
+struct Request {
+    invalidation: Invalidation,
+    /// Number of cores, that have not flushed yet (shared by all targets of a shootdown)
+    pending: Arc<AtomicUsize>,
+}
+
+struct TlbShootdownHandler;
+
+impl InterruptHandler for TlbShootdownHandler {
+    fn trigger(&self) {
+        process_queue();
+    }
+}
+
+/// Register the bootstrap processor and the handler for shootdown IPIs (must be called after the APIC has been initialized).
+pub fn init() {
+    ipi::register(Ipi::TlbShootdown, Box::new(TlbShootdownHandler));
+    register_cpu();
+}
+
+/// Add the calling core to the targets of shootdowns. Must be called by each core, before it runs threads.
+pub fn register_cpu() {
+    let apic_id = apic().local_apic_id();
+    interrupts::without_interrupts(|| {
+        CPUS.write().push(Arc::new(CpuQueue { apic_id, requests: Mutex::new(Vec::new()) }));
+    });
+}
+
+/// Invalidate the translations of `pages` on all cores.
+/// Returns after all cores have flushed them, so the frames behind the pages may be reused afterwards.
+pub fn shootdown(pages: PageRange) {
+    let invalidation = if pages.end - pages.start > MAX_INVLPG_PAGES {
+        Invalidation::All
+    } else {
+        Invalidation::Pages(pages)
+    };
+    invalidation.flush();
+
+    // Vor dem Start weiterer Kerne (und vor der APIC-Initialisierung) reicht das lokale Invalidieren
+    if CPUS.read().len() <= 1 {
+        return;
+    }
+
+    interrupts::without_interrupts(|| {
+        let own_id = apic().local_apic_id();
+        let targets: Vec<Arc<CpuQueue>> = CPUS.read().iter().filter(|cpu| cpu.apic_id != own_id).cloned().collect();
+        let pending = Arc::new(AtomicUsize::new(targets.len()));
+
+        for cpu in targets.iter() {
+            cpu.requests.lock().push(Request { invalidation, pending: Arc::clone(&pending) });
+            ipi::send_tlb_flush(cpu.apic_id);
+        }
+
+        // Eigene Anfragen bearbeiten, falls ein anderer Kern gleichzeitig (mit gesperrten Interrupts) auf uns wartet
+        while pending.load(Ordering::Acquire) > 0 {
+            process_queue();
+            spin_loop();
+        }
+    });
+}
+
+/// Call `f` with the local APIC id of each registered core, without blocking (used on panic).
+/// Returns `false` (without calling `f`), if the registry is currently locked.
+pub fn try_for_each_cpu(mut f: impl FnMut(u32)) -> bool {
+    match CPUS.try_read() {
+        Some(cpus) => {
+            cpus.iter().for_each(|cpu| f(cpu.apic_id));
+            true
+        }
+        None => false,
+    }
+}
+
+/// Flush all pages requested for the calling core and acknowledge the requests.
+fn process_queue() {
+    let own_id = apic().local_apic_id();
+    let Some(cpu) = CPUS.read().iter().find(|cpu| cpu.apic_id == own_id).cloned() else {
+        return;
+    };
+
+    let requests = core::mem::take(&mut *cpu.requests.lock());
+    for request in requests {
+        request.invalidation.flush();
+        request.pending.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl Invalidation {
+    fn flush(&self) {
+        match *self {
+            Invalidation::Pages(pages) => pages.for_each(|page| tlb::flush(page.start_address())),
+            Invalidation::All => tlb::flush_all(),
+        }
+    }
+}
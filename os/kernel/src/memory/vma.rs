@@ -11,6 +11,7 @@
 
 use crate::memory::{MemorySpace, PAGE_SIZE};
 use core::fmt;
+use syscall::memory::Protection;
 use x86_64::VirtAddr;
 use x86_64::structures::paging::page::PageRange;
 use x86_64::structures::paging::PageTableFlags;
@@ -22,10 +23,22 @@ pub enum VmaType {
     Environment,
     DeviceMemory,
     UserStack,
+    Tls,
+    SharedLibrary,
     KernelStack,
     KernelBuffer,
     Anonymous,
     SharedMemory {id: usize},
+    /// Anonymous memory mapped by the application with `sys_mmap`
+    Mapped {prot: Protection},
+}
+
+impl VmaType {
+    /// Pages of these VMAs are only backed by a (zeroed) frame, when they are accessed for the first time.
+    /// All other VMAs are mapped completely, when they are created.
+    pub fn is_demand_paged(&self) -> bool {
+        matches!(self, VmaType::Heap | VmaType::UserStack | VmaType::Anonymous | VmaType::Mapped { .. })
+    }
 }
 
 pub const TAG_SIZE: usize = 16; // Define a constant for tag size in bytes
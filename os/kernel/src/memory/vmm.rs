@@ -24,6 +24,7 @@
    ║   - map_partial_vma           map a sub page range of a vma by          ║
    ║                               allocating frames as needed               ║
    ║   - unmap_vma                 unmap VMA in this address space           ║
+   ║   - free_vma                  unmap VMA, free its frames and remove it  ║
    ║                                                                         ║
    ║   - clone_address_space       used for process creation                 ║
    ║   - create_kernel_address_space   used for process creation             ║
@@ -31,8 +32,14 @@
    ║   - page_table_address        get root page table address               ║
    ║   - set_flags                 set page table flags                      ║
    ║   - is_address_within_vma     check if address is within any vma        ║
+   ║   - find_vma                  get the vma containing an address         ║
+   ║   - resolve_page_fault        map demand paged memory on first access   ║
+   ║   - unmap_mapped              remove pages mapped with sys_mmap         ║
+   ║   - remap_device_memory       point device memory mappings elsewhere    ║
+   ║   - max_resident_pages        peak of pages mapped in user space        ║
    ║   - copy_to_addr_space        copy data to a given address space        ║
    ║   - get_phys                  get physical address of a page            ║
+   ║   - copy_on_write             resolve a write to a copy-on-write page   ║
    ║   - pfr_from_pr_identity      get pfr range from page range identity    ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Fabian Ruhland and Michael Schoettner                           ║
@@ -42,12 +49,18 @@
 
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::{max, min};
+use core::fmt;
 use core::ops::Range;
+use core::sync::atomic::{AtomicU64, Ordering};
 use log::{warn, info};
 use spin::RwLock;
+use syscall::memory::Protection;
 
 use x86_64::PhysAddr;
 use x86_64::VirtAddr;
+use x86_64::structures::idt::PageFaultErrorCode;
 use x86_64::structures::paging::PhysFrame;
 use x86_64::structures::paging::frame::PhysFrameRange;
 use x86_64::structures::paging::page::PageRange;
@@ -99,6 +112,29 @@ pub fn pfr_from_pr_identity(pr: PageRange) -> PhysFrameRange {
     }
 }
 
+/// Reason, why a page fault in user space could not be resolved (the access is invalid)
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PageFaultError {
+    /// The address does not belong to any VMA
+    Unmapped,
+    /// The address belongs to a kernel VMA
+    KernelMemory,
+    /// The page is mapped, but the access is not allowed (e.g. writing to a read-only page)
+    AccessViolation(VmaType),
+    /// The VMA has been mapped completely, when it was created, but the page is missing
+    NotDemandPaged(VmaType),
+}
+
+impl fmt::Display for PageFaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PageFaultError::Unmapped => write!(f, "address is not mapped"),
+            PageFaultError::KernelMemory => write!(f, "address belongs to the kernel"),
+            PageFaultError::AccessViolation(typ) => write!(f, "access not allowed in {typ:?} memory"),
+            PageFaultError::NotDemandPaged(typ) => write!(f, "page of {typ:?} memory is missing"),
+        }
+    }
+}
 
 /// All data related to a virtual address space of a process.
 pub struct VirtualAddressSpace {
@@ -106,6 +142,8 @@ pub struct VirtualAddressSpace {
     page_tables: Arc<Paging>,                                                 // page tables of this address space
     first_usable_user_addr: VirtAddr,                                         // first usable user address (fixed constant)
     last_usable_user_addr: VirtAddr,                                          // last usable user address (fixed by cpu model)
+    resident_pages: AtomicU64,                                                // pages currently mapped in user VMAs (without device memory)
+    max_resident_pages: AtomicU64,                                            // peak of `resident_pages`
 }
 
 impl VirtualAddressSpace {
@@ -119,6 +157,8 @@ impl VirtualAddressSpace {
             virtual_memory_areas: RwLock::new(BTreeMap::new()),
             first_usable_user_addr,
             last_usable_user_addr,
+            resident_pages: AtomicU64::new(0),
+            max_resident_pages: AtomicU64::new(0),
         }
     }
 
@@ -130,6 +170,26 @@ impl VirtualAddressSpace {
         Arc::clone(&self.page_tables)
     }
 
+    /// Highest number of pages, that have been mapped in user space at the same time (without device memory)
+    pub fn max_resident_pages(&self) -> u64 {
+        self.max_resident_pages.load(Ordering::Relaxed)
+    }
+
+    /// Account `count` pages, that have been mapped in `vma`, for `max_resident_pages()`
+    fn add_resident(&self, vma: &VirtualMemoryArea, count: u64) {
+        if vma.space == MemorySpace::User && vma.typ != VmaType::DeviceMemory {
+            let resident = self.resident_pages.fetch_add(count, Ordering::Relaxed) + count;
+            self.max_resident_pages.fetch_max(resident, Ordering::Relaxed);
+        }
+    }
+
+    /// Account `count` pages, that have been unmapped in `vma`, for `max_resident_pages()`
+    fn remove_resident(&self, vma: &VirtualMemoryArea, count: u64) {
+        if vma.space == MemorySpace::User && vma.typ != VmaType::DeviceMemory {
+            self.resident_pages.fetch_sub(count, Ordering::Relaxed);
+        }
+    }
+
     /// Tries to allocate a virtual memory region for `num_pages` pages for the given `space`, `typ`, and `tag` in the address space `self`. \
     /// If `start_page` is `Some` the allocator tries to allocate the vma from the given page otherwise it will allocate from any free page. \
     /// No frames are allocated and no mappings are created in the page tables. \
@@ -189,6 +249,7 @@ impl VirtualAddressSpace {
 
         // Do the mapping
         self.page_tables.map_physical(frame_range, page_range, vma.space, flags);
+        self.add_resident(vma, num_pages);
 
         Ok(())
     }
@@ -296,6 +357,7 @@ impl VirtualAddressSpace {
         assert!(page_range.start.start_address() >= vma.start());
         assert!(page_range.end.start_address() <= vma.end());
         self.page_tables.map(page_range, space, flags);
+        self.add_resident(vma, page_range.len());
     }
 
     /// Set page table `flags` for the give page range `pages`  
@@ -394,6 +456,7 @@ impl VirtualAddressSpace {
             MemorySpace::User,
             PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE,
         );
+        self.add_resident(&vma, num_pages);
 
         Some(vma)
     }
@@ -434,6 +497,7 @@ impl VirtualAddressSpace {
             MemorySpace::User,
             PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE,
         );
+        self.add_resident(&vma, alloc_num_pages);
 
         Some(vma)
     }
@@ -443,6 +507,12 @@ impl VirtualAddressSpace {
         self.page_tables.translate(VirtAddr::new(virt_addr))
     }
 
+    /// Resolve a write access to the copy-on-write page containing `virt_addr` by copying its shared frame. \
+    /// Returns false, if the page may not be written.
+    pub fn copy_on_write(&self, virt_addr: u64) -> bool {
+        self.page_tables.copy_on_write(VirtAddr::new(virt_addr))
+    }
+
     /// Copy `total_bytes_to_copy` from `src_ptr` in the `self` address space to `dest_page_start` in the `dest_process` address space. \
     /// Destination addresses are manually retrieved from the page tables of the `dest_process`. \
     /// If `fill_up_with_zeroes` is true, the remaining bytes in the last page will be filled with zeroes.
@@ -512,10 +582,146 @@ impl VirtualAddressSpace {
         None
     }
 
+    /// Get the VMA containing the given `address` (of any type) in this address space.
+    pub fn find_vma(&self, address: u64) -> Option<Arc<VirtualMemoryArea>> {
+        let areas = self.virtual_memory_areas.read();
+        let vaddr = VirtAddr::new(address);
+
+        areas.range(..=vaddr).next_back()
+            .filter(|(_, vma)| vaddr < vma.end())
+            .map(|(_, vma)| Arc::clone(vma))
+    }
+
+    /// Resolve a page fault at `address` in user space, caused by an access described by `error_code`: \
+    /// Pages of demand paged VMAs (see `VmaType::is_demand_paged()`) get a zeroed frame on their first access
+    /// and copy-on-write pages of shared libraries get their own frame on their first write. \
+    /// Returns whether a new frame has been mapped or why the access is invalid.
+    pub fn resolve_page_fault(&self, address: VirtAddr, error_code: PageFaultErrorCode) -> Result<bool, PageFaultError> {
+        // The VMAs stay locked, so the VMA can't be removed while its page is mapped
+        let areas = self.virtual_memory_areas.read();
+        let vma = match areas.range(..=address).next_back() {
+            Some((_, vma)) if address < vma.end() => vma,
+            _ => return Err(PageFaultError::Unmapped),
+        };
+        if vma.space != MemorySpace::User {
+            return Err(PageFaultError::KernelMemory);
+        }
+
+        if error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+            if vma.typ == VmaType::SharedLibrary && error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) && self.copy_on_write(address.as_u64()) {
+                return Ok(true);
+            }
+            return Err(PageFaultError::AccessViolation(vma.typ));
+        }
+
+        if !vma.typ.is_demand_paged() {
+            return Err(PageFaultError::NotDemandPaged(vma.typ));
+        }
+
+        let mut flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        if let VmaType::Mapped { prot } = vma.typ {
+            if !prot.contains(Protection::READ) || (error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) && !prot.contains(Protection::WRITE)) {
+                return Err(PageFaultError::AccessViolation(vma.typ));
+            }
+            flags.set(PageTableFlags::WRITABLE, prot.contains(Protection::WRITE));
+        }
+        let flags = vma.check_and_enforce_consistency(flags);
+        let mapped = self.page_tables.map_on_demand(Page::containing_address(address), flags);
+        if mapped {
+            self.add_resident(vma, 1);
+        }
+        Ok(mapped)
+    }
+
+    /// Remove the pages in `pages` from the VMAs created by `sys_mmap` and free their frames.
+    /// VMAs, that are only partly covered, are split. \
+    /// Returns the number of removed pages, or `None` (removing nothing), if `pages` overlaps another kind of VMA.
+    pub fn unmap_mapped(&self, pages: PageRange) -> Option<u64> {
+        let (start, end) = (pages.start.start_address(), pages.end.start_address());
+        let mut removed = 0;
+        let mut unused_frames = Vec::new();
+        {
+            let mut areas = self.virtual_memory_areas.write();
+
+            // VMAs don't overlap, so their ends are sorted like their starts
+            let overlapping: Vec<Arc<VirtualMemoryArea>> = areas.range(..end).rev()
+                .take_while(|(_, vma)| vma.end() > start)
+                .map(|(_, vma)| Arc::clone(vma))
+                .collect();
+            if overlapping.iter().any(|vma| !matches!(vma.typ, VmaType::Mapped { .. })) {
+                return None;
+            }
+
+            for vma in overlapping {
+                areas.remove(&vma.start());
+                let cut = PageRange { start: max(vma.range.start, pages.start), end: min(vma.range.end, pages.end) };
+                if vma.range.start < cut.start {
+                    let before = VirtualMemoryArea { range: PageRange { start: vma.range.start, end: cut.start }, ..*vma };
+                    areas.insert(before.start(), Arc::new(before));
+                }
+                if cut.end < vma.range.end {
+                    let after = VirtualMemoryArea { range: PageRange { start: cut.end, end: vma.range.end }, ..*vma };
+                    areas.insert(after.start(), Arc::new(after));
+                }
+
+                // The entries are removed while holding the VMAs, so a page fault can't map the pages again in the meantime
+                let (mut frames, unmapped) = self.page_tables.unmap_entries(cut, true);
+                unused_frames.append(&mut frames);
+                self.remove_resident(&vma, unmapped);
+                removed += cut.len();
+            }
+        }
+
+        // Page faults on other cores wait for the VMAs with interrupts disabled, so the TLBs are flushed afterwards
+        Paging::flush_and_free(pages, unused_frames);
+        Some(removed)
+    }
+
+    /// Map the user device memory VMAs, which start with the frames of `old`, to the frames of `new` instead
+    /// (e.g. the framebuffer, after a driver has replaced it). Pages beyond the end of `new` are unmapped. \
+    /// The TLBs are flushed before returning, so the frames of `old` may be freed afterwards.
+    pub fn remap_device_memory(&self, old: PhysFrameRange, new: PhysFrameRange, flags: PageTableFlags) {
+        let mut remapped = Vec::new();
+        {
+            let areas = self.virtual_memory_areas.read();
+            for vma in areas.values() {
+                if vma.typ != VmaType::DeviceMemory || vma.space != MemorySpace::User
+                    || self.page_tables.translate(vma.start()) != Some(old.start.start_address()) {
+                    continue;
+                }
+
+                let count = min(vma.range.len(), new.end - new.start);
+                let pages = PageRange { start: vma.range.start, end: vma.range.start + count };
+                let flags = vma.check_and_enforce_consistency(flags) | PageTableFlags::PRESENT;
+                self.page_tables.map_physical(PhysFrameRange { start: new.start, end: new.start + count }, pages, vma.space, flags);
+
+                let mut unused_frames = Vec::new();
+                if pages.end < vma.range.end {
+                    (unused_frames, _) = self.page_tables.unmap_entries(PageRange { start: pages.end, end: vma.range.end }, false);
+                }
+                remapped.push((vma.range, unused_frames));
+            }
+        }
+
+        // Page faults on other cores wait for the VMAs with interrupts disabled, so the TLBs are flushed afterwards
+        for (pages, unused_frames) in remapped {
+            Paging::flush_and_free(pages, unused_frames);
+        }
+    }
+
     /// unmap VMA in this adress space 
     /// set free_physical to free the frames
     pub fn unmap_vma(&self, vma:Arc<VirtualMemoryArea>, free_physical:bool) {
-        self.page_tables.unmap(vma.range, free_physical);
+        let unmapped = self.page_tables.unmap(vma.range, free_physical);
+        self.remove_resident(&vma, unmapped);
+    }
+
+    /// Unmap `vma`, free its frames and remove it from this address space, so its pages can be reused
+    /// (e.g. the user stack of an exited thread)
+    pub fn free_vma(&self, vma: Arc<VirtualMemoryArea>) {
+        self.virtual_memory_areas.write().remove(&vma.start());
+        let unmapped = self.page_tables.unmap(vma.range, true);
+        self.remove_resident(&vma, unmapped);
     }
 }
 
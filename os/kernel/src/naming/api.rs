@@ -10,60 +10,152 @@
    ║   - mkdir  create a directory                                           ║
    ║   - touch  create a file                                                ║
    ║   - mkfifo create a named pipe                                          ║
+   ║   - unlink remove a file, pipe or empty directory                       ║
+   ║   - rename rename or move a named object                                ║
+   ║   - stat   get type, size and time stamps of a named object             ║
+   ║   - chmod  change the permission bits of a named object                 ║
+   ║   - chown  change the owner and group of a named object                 ║
+   ║   - is_dir check whether a path refers to a directory                   ║
+   ║   - mount  mount a file system on a directory                           ║
+   ║   - mount_device  mount a file system by type (for the syscall)         ║
+   ║   - umount unmount a file system, if none of its objects are in use     ║
+   ║   - read_file  read a whole file (e.g. a program to be loaded)          ║
+   ║   - check_handle  check whether the current process may use a handle    ║
+   ║   - share  let a child process use an open object                       ║
+   ║   - close_for_process  close all objects opened by an exiting process   ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Michael Schoettner, Univ. Duesseldorf, 23.12.2025               ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
 
+use alloc::collections::BTreeMap;
+use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
+use core::net::{IpAddr, SocketAddr};
 use log::{info, warn};
-use spin::{Mutex, Once};
+use spin::{Once, RwLock};
 
+use super::archive::ArchiveFs;
+use super::devfs::DevFs;
+use super::fat::FatFs;
 use super::lookup;
+use super::ninep::NinePFs;
 use super::open_objects;
-use super::stat::Mode;
+use super::procfs::ProcFs;
+use super::readonly::ReadOnlyFs;
+use super::stat::{check_access, Mode, ACCESS_EXECUTE, ACCESS_WRITE, PERMISSIONS_ALL, PERMISSIONS_DIR, PERMISSIONS_FILE, PERMISSIONS_PIPE};
 use super::tmpfs;
-use super::traits::FileSystem;
+use super::traits::{DirectoryObject, FileSystem, NamedObject};
 
-use crate::initrd;
-use naming::shared_types::{OpenOptions, RawDirent, SeekOrigin};
+use crate::device::virtio::virtio_9p_shares;
+use crate::process::sandbox;
+use crate::{initrd, process_manager, storage};
+use naming::shared_types::{FileStat, FileType, MountFlags, OpenOptions, RawDirent, SeekOrigin};
 use syscall::return_vals::Errno;
+use syscall::sandbox::{KEEP_ID, ROOT_ID};
+
+/// Default port of 9P servers
+const NINEP_TCP_PORT: u16 = 564;
 
 // root of naming service
 pub(super) static ROOT: Once<Arc<dyn FileSystem>> = Once::new();
 
-// current working directory
-static CWD: Mutex<String> = Mutex::new(String::new());
+// file systems mounted on directories of the root file system (by absolute path)
+static MOUNTS: RwLock<BTreeMap<String, Mount>> = RwLock::new(BTreeMap::new());
+
+/// A file system mounted on a directory
+#[derive(Clone)]
+pub(super) struct Mount {
+    pub(super) fs: Arc<dyn FileSystem>,
+    /// Device or share, the file system has been created from (or its type, for pseudo file systems)
+    pub(super) source: String,
+    pub(super) fs_type: String,
+    pub(super) flags: MountFlags,
+}
 
 /// Initialize the naming service (must be called once before using it).
 pub fn init() {
-    // Initialize ROOT with TmpFs
+    // the root is a tmpfs, so programs can create files and directories (e.g. /etc) anywhere
+    let initrd = match ArchiveFs::new(initrd()) {
+        Ok(archive) => Some(archive),
+        Err(e) => {
+            warn!("Failed to parse initial ramdisk: {e:?}");
+            None
+        }
+    };
     ROOT.call_once(|| {
         let tmpfs = tmpfs::TmpFs::new();
 
-        for entry in initrd().entries() {
-            let res = tmpfs.create_static_file(entry.filename().as_str().unwrap(), entry.data());
-            if res.is_err() {
-                warn!("Failed to create static file in tmpfs: {}", entry.filename().as_str().unwrap());
+        for (name, data) in initrd.iter().flat_map(|archive| archive.files()) {
+            if tmpfs.create_static_file(name, data).is_err() {
+                warn!("Failed to create static file in tmpfs: {name}");
             }
         }
 
         Arc::new(tmpfs)
     });
     open_objects::open_object_table_init();
-    let mut cwd = CWD.lock();
-    *cwd = "/".to_string();
+
+    // the directories of the initial ramdisk (e.g. /bin and /usr) are mounted read-only
+    for (name, fs) in initrd.iter().flat_map(|archive| archive.dirs()) {
+        let path = format!("/{name}");
+        if let Err(e) = mount_creating_dirs(&path, Arc::new(fs), "initrd", "archive", MountFlags::READONLY) {
+            warn!("Failed to mount initial ramdisk at [{path}]: {e:?}");
+        }
+    }
+
+    open_objects::open_object_table_init();
+
+    // devices can be accessed as files in /dev
+    if let Err(e) = mount_creating_dirs("/dev", Arc::new(DevFs::new()), "devfs", "devfs", MountFlags::empty()) {
+        warn!("Failed to mount devfs at [/dev]: {e:?}");
+    }
+
+    // information about processes and the kernel in /proc
+    if let Err(e) = mount_creating_dirs("/proc", Arc::new(ProcFs::new()), "procfs", "procfs", MountFlags::empty()) {
+        warn!("Failed to mount procfs at [/proc]: {e:?}");
+    }
+
+    // temporary files get their own tmpfs, so they are kept apart from the files of the initrd
+    if let Err(e) = mount_creating_dirs("/tmp", Arc::new(tmpfs::TmpFs::new()), "tmpfs", "tmpfs", MountFlags::empty()) {
+        warn!("Failed to mount tmpfs at [/tmp]: {e:?}");
+    }
+
+    // mount the directories shared by the host (virtio-9p) at /mnt/<tag>
+    for share in virtio_9p_shares() {
+        let tag = share.tag().to_string();
+        let path = format!("/mnt/{tag}");
+        let result = NinePFs::attach(share).and_then(|fs| mount_creating_dirs(&path, Arc::new(fs), &tag, "9p", MountFlags::empty()));
+        match result {
+            Ok(()) => info!("Mounted 9P share at [{path}]"),
+            Err(e) => warn!("Failed to mount 9P share at [{path}]: {e:?}"),
+        }
+    }
+
+    // mount FAT file systems on block devices and partitions at /mnt/<device>
+    for name in storage::block_device_names() {
+        let Some(fs) = storage::block_device(&name).and_then(FatFs::probe) else {
+            continue;
+        };
+        let path = format!("/mnt/{name}");
+        match mount_creating_dirs(&path, Arc::new(fs), &name, "fat", MountFlags::empty()) {
+            Ok(()) => info!("Mounted FAT file system on [{name}] at [{path}]"),
+            Err(e) => warn!("Failed to mount FAT file system on [{name}] at [{path}]: {e:?}"),
+        }
+    }
     info!("naming service initialized");
     //    test::running_tests();
 }
 
 /// Open/create a named object referenced by `path` using the given `flags`. \
+/// With `CREATE`, a missing file is created, with `CREATE | EXCLUSIVE`, the file must not exist yet. \
 /// Returns `Ok(object_handle)` or `Err`.
 pub fn open(path: &str, flags: OpenOptions) -> Result<usize, Errno> {
     // avoid creating a file twice
-    if flags.contains(OpenOptions::CREATE) {
+    if flags.contains(OpenOptions::CREATE | OpenOptions::EXCLUSIVE) {
         let result = lookup::lookup_named_object(path);
         if result.is_ok() {
             return Err(Errno::EEXIST);
@@ -107,68 +199,29 @@ pub fn close(object_handle: usize) -> Result<usize, Errno> {
 /// Create a directory for the given `path`. \
 /// Returns `Ok(0)` or `Err(errno)`
 pub fn mkdir(path: &str) -> Result<usize, Errno> {
-    // Split the path into components
-    let mut components: Vec<&str> = path.split("/").collect();
-
-    // Remove the last component (the name of the new directory)
-    let new_dir_name = components.pop();
-
-    // We need parent directory to create the new directory
-    let parent_dir = if components.len() == 1 {
-        "/".to_string()
-    } else {
-        components.join("/") // Joins the remaining components
-    };
-
-    // Safely lookup the parent directory and create the new file
-    let result = lookup::lookup_dir(&parent_dir)
-        .and_then(|dir| {
-            new_dir_name
-                .ok_or(Errno::EINVAL) // Handle missing file name
-                .and_then(|name| dir.create_dir(name, Mode::new(0))) // Create the file
-        })
-        .map(|_| 0); // Convert the success result to 0
-
-    match result {
-        Ok(_) => Ok(0), // Successfully created the file
-        Err(_) => {
-            // Handle the error here (e.g., logging or returning the error code)
-            Err(Errno::ENOTDIR)
-        }
-    }
+    create(path, PERMISSIONS_DIR, |dir, name| dir.create_dir(name, Mode::new(0)))
 }
 
 /// Create an empty file defined by `path`. \
 /// Returns `Ok(0)` or `Err(errno)`
 pub fn touch(path: &str) -> Result<usize, Errno> {
-    // Split the path into components
-    let mut components: Vec<&str> = path.split("/").collect();
-
-    // Remove the last component (the name of the new file)
-    let new_file_name = components.pop();
-
-    // We need parent directory to create the new file
-    let parent_dir = if components.len() == 1 {
-        "/".to_string()
-    } else {
-        components.join("/") // Joins the remaining components
-    };
-
-    // Safely lookup the parent directory and create the new file
-    let result = lookup::lookup_dir(&parent_dir)
-        .and_then(|dir| {
-            new_file_name
-                .ok_or(Errno::EINVAL) // Handle missing file name
-                .and_then(|name| dir.create_file(name, Mode::new(0))) // Create the file
-        })
-        .map(|_| 0); // Convert the success result to 0
+    create(path, PERMISSIONS_FILE, |dir, name| dir.create_file(name, Mode::new(0)))
+}
 
-    match result {
-        Ok(_) => Ok(0), // Successfully created the file
-        Err(_) => {
-            // Handle the error here (e.g., logging or returning the error code)
-            Err(Errno::ENOTDIR)
-        }
+/// Create the object `path` with `create_object`, owned by the current process and with the given `permissions`.
+/// The current process needs the right to write to the parent directory.
+fn create<F>(path: &str, permissions: u32, create_object: F) -> Result<usize, Errno>
+where
+    F: FnOnce(&Arc<dyn DirectoryObject>, &str) -> Result<NamedObject, Errno>,
+{
+    let (dir, name) = split_path(path)?;
+    check_access(&dir.stat()?, ACCESS_WRITE | ACCESS_EXECUTE)?;
+
+    let object = create_object(&dir, name)?;
+    let (uid, gid) = sandbox::credentials();
+    match object.set_attributes(permissions, uid, gid) {
+        Ok(()) | Err(Errno::ENOTSUP) => Ok(0),
+        Err(e) => Err(e),
     }
 }
 
@@ -205,11 +258,10 @@ pub fn readdir(dir_handle: usize, dentry: Option<&mut RawDirent>) -> Result<usiz
     }
 }
 
-/// Get the current working directory and return path in `buffer`. \
+/// Get the current working directory of the calling process and return path in `buffer`. \
 /// Return: `Ok(len of string)` or `Err(errno)`
 pub fn cwd(buffer: &mut [u8]) -> Result<usize, Errno> {
-    // Lock the CWD mutex to access its value
-    let cwd = CWD.lock();
+    let cwd = process_manager().read().current_process().cwd();
 
     // Get the string as bytes
     let cwd_bytes = cwd.as_bytes();
@@ -230,7 +282,7 @@ pub fn cwd(buffer: &mut [u8]) -> Result<usize, Errno> {
 }
 
 ///
-/// Description: Change working directory \
+/// Description: Change working directory of the calling process (inherited by processes started afterwards) \
 /// Parameters: `path` absolute path \
 /// Return: `Ok(0)` or `Err(errno)`
 ///
@@ -238,8 +290,7 @@ pub fn cd(path: &String) -> Result<usize, Errno> {
     let result = lookup::lookup_dir(path);
     match result {
         Ok(_) => {
-            let mut cwd = CWD.lock();
-            *cwd = path.clone();
+            process_manager().read().current_process().set_cwd(path.clone());
             Ok(0)
         }
         Err(_) => {
@@ -249,35 +300,263 @@ pub fn cd(path: &String) -> Result<usize, Errno> {
     }
 }
 
+/// Check whether `path` refers to a directory.
+pub fn is_dir(path: &str) -> bool {
+    lookup::lookup_dir(&path.to_string()).is_ok()
+}
+
 /// Create a named pipe using `path`. \
 /// Returns `Ok(0)` or `Err(errno)`
 pub fn mkfifo(path: &str) -> Result<usize, Errno> {
-    // Split the path into components
-    let mut components: Vec<&str> = path.split("/").collect();
+    create(path, PERMISSIONS_PIPE, |dir, name| dir.create_pipe(name, Mode::new(0)))
+}
+
+/// Remove the file, pipe or directory `path` (directories must be empty).
+/// Mount points (and directories containing them) can't be removed. \
+/// Returns `Ok(0)` or `Err(errno)`
+pub fn unlink(path: &str) -> Result<usize, Errno> {
+    if is_mount_point(path)? {
+        return Err(Errno::EBUSY);
+    }
+
+    let (dir, name) = split_path(path)?;
+    check_access(&dir.stat()?, ACCESS_WRITE | ACCESS_EXECUTE)?;
+    dir.unlink(name).map(|_| 0)
+}
+
+/// Rename `old_path` to `new_path`, which may be in another directory of the same file system.
+/// An existing object at `new_path` is replaced, if it is of the same kind (and empty for directories). \
+/// Returns `Ok(0)` or `Err(errno)`
+pub fn rename(old_path: &str, new_path: &str) -> Result<usize, Errno> {
+    if is_mount_point(old_path)? || is_mount_point(new_path)? {
+        return Err(Errno::EBUSY);
+    }
+
+    let (old_dir, old_name) = split_path(old_path)?;
+    let (new_dir, new_name) = split_path(new_path)?;
+    check_access(&old_dir.stat()?, ACCESS_WRITE | ACCESS_EXECUTE)?;
+    check_access(&new_dir.stat()?, ACCESS_WRITE | ACCESS_EXECUTE)?;
+    old_dir.rename(old_name, &new_dir, new_name).map(|_| 0)
+}
+
+/// Get the type, size and time stamps of the named object `path`. \
+/// Returns `Ok(stat)` or `Err(errno)`
+pub fn stat(path: &str) -> Result<FileStat, Errno> {
+    let object = lookup::lookup_named_object(path)?;
+    let file_type = match object {
+        NamedObject::FileObject(_) => FileType::Regular,
+        NamedObject::DirectoryObject(_) => FileType::Directory,
+        NamedObject::PipeObject(_) => FileType::NamedPipe,
+    };
+    let stat = object.stat()?;
+
+    Ok(FileStat {
+        file_type: file_type as usize,
+        size: stat.size,
+        created: stat.created_time,
+        modified: stat.modified_time,
+        accessed: stat.accessed_time,
+        permissions: stat.permissions,
+        uid: stat.uid,
+        gid: stat.gid,
+    })
+}
+
+/// Set the permission bits of `path` (only the owner and root may do this). \
+/// Returns `Ok(0)` or `Err(errno)`
+pub fn chmod(path: &str, permissions: u32) -> Result<usize, Errno> {
+    if permissions & !PERMISSIONS_ALL != 0 {
+        return Err(Errno::EINVAL);
+    }
+
+    let object = lookup::lookup_named_object(path)?;
+    let stat = object.stat()?;
+    let (uid, _) = sandbox::credentials();
+    if uid != ROOT_ID && uid != stat.uid {
+        return Err(Errno::EACCES);
+    }
+    object.set_attributes(permissions, stat.uid, stat.gid).map(|_| 0)
+}
+
+/// Change the owner and group of `path` (only root may do this), `KEEP_ID` keeps the current one. \
+/// Returns `Ok(0)` or `Err(errno)`
+pub fn chown(path: &str, uid: u32, gid: u32) -> Result<usize, Errno> {
+    if sandbox::credentials().0 != ROOT_ID {
+        return Err(Errno::EACCES);
+    }
+
+    let object = lookup::lookup_named_object(path)?;
+    let stat = object.stat()?;
+    let uid = if uid == KEEP_ID { stat.uid } else { uid };
+    let gid = if gid == KEEP_ID { stat.gid } else { gid };
+    object.set_attributes(stat.permissions, uid, gid).map(|_| 0)
+}
+
+/// Split `path` into the directory containing the named object and its name.
+fn split_path(path: &str) -> Result<(Arc<dyn DirectoryObject>, &str), Errno> {
+    let (parent, name) = path.trim_end_matches('/').rsplit_once('/').ok_or(Errno::EINVAL)?;
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(Errno::EINVAL);
+    }
 
-    // Remove the last component (the name of the new file)
-    let new_pipe_name = components.pop();
+    let parent = if parent.is_empty() { "/" } else { parent };
+    Ok((lookup::lookup_dir(&parent.to_string())?, name))
+}
+
+/// Check whether a file system is mounted on `path` or on a directory below it.
+fn is_mount_point(path: &str) -> Result<bool, Errno> {
+    let path = lookup::translate_path(path)?;
+    let path = path.trim_end_matches('/');
+    let below = format!("{path}/");
+    Ok(MOUNTS.read().keys().any(|mount| mount == path || mount.starts_with(&below)))
+}
+
+/// Read the whole file `path` (an absolute path, not subject to the sandbox of the current process),
+/// e.g. to load a program. \
+/// Returns `Ok(content)` or `Err(errno)`
+pub fn read_file(path: &str) -> Result<Vec<u8>, Errno> {
+    let file = lookup::resolve(path)?;
+    let file = file.as_file()?;
+
+    let mut content = vec![0; file.stat()?.size];
+    let mut pos = 0;
+    while pos < content.len() {
+        match file.read(&mut content[pos..], pos, OpenOptions::READONLY)? {
+            0 => break,
+            count => pos += count,
+        }
+    }
+    content.truncate(pos);
+
+    Ok(content)
+}
 
-    // We need parent directory to create the new file
-    let parent_dir = if components.len() == 1 {
-        "/".to_string()
-    } else {
-        components.join("/") // Joins the remaining components
+/// Mount the file system `fs` on the directory `path` (an absolute path, not subject to the sandbox
+/// of the current process), hiding its content. `source` and `fs_type` are only used for listing mounts. \
+/// Returns `Ok(())` or `Err(errno)`
+pub fn mount(path: &str, fs: Arc<dyn FileSystem>, source: &str, fs_type: &str, flags: MountFlags) -> Result<(), Errno> {
+    // the root file system can't be replaced
+    let path = path.trim_end_matches('/');
+    if path.is_empty() {
+        return Err(Errno::EBUSY);
+    }
+    if !lookup::resolve(path)?.is_dir() {
+        return Err(Errno::ENOTDIR);
+    }
+
+    let fs: Arc<dyn FileSystem> = match flags.contains(MountFlags::READONLY) {
+        true => Arc::new(ReadOnlyFs::new(fs)),
+        false => fs,
     };
+    let mut mounts = MOUNTS.write();
+    if mounts.contains_key(path) {
+        return Err(Errno::EBUSY);
+    }
+    mounts.insert(path.to_string(), Mount { fs, source: source.to_string(), fs_type: fs_type.to_string(), flags });
+    Ok(())
+}
 
-    // Safely lookup the parent directory and create the new pipe
-    let result = lookup::lookup_dir(&parent_dir)
-        .and_then(|dir| {
-            new_pipe_name
-                .ok_or(Errno::EINVAL) // Handle missing file name
-                .and_then(|name| dir.create_pipe(name, Mode::new(0))) // Create the pipe
-        })
-        .map(|_| 0); // Convert the success result to 0
+/// Create a file system of type `fs_type` ("fat", "9p", "9p-tcp", "tmpfs", "devfs" or "procfs") and mount it on `path`.
+/// `device` is the block device (e.g. "/dev/ata0p0") for "fat", the tag of the share for "9p"
+/// and the address of the server (e.g. "10.0.2.2" or "10.0.2.2:5640") for "9p-tcp". \
+/// Returns `Ok(0)` or `Err(errno)`
+pub fn mount_device(device: &str, path: &str, fs_type: &str, flags: MountFlags) -> Result<usize, Errno> {
+    let fs: Arc<dyn FileSystem> = match fs_type {
+        "fat" => {
+            let device = storage::block_device(device.trim_start_matches("/dev/")).ok_or(Errno::ENOENT)?;
+            Arc::new(FatFs::probe(device).ok_or(Errno::EINVAL)?)
+        }
+        "9p" => {
+            let share = virtio_9p_shares().into_iter().find(|share| share.tag() == device).ok_or(Errno::ENOENT)?;
+            Arc::new(NinePFs::attach(share)?)
+        }
+        "9p-tcp" => {
+            let address = device.parse::<SocketAddr>()
+                .or_else(|_| device.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, NINEP_TCP_PORT)))
+                .map_err(|_| Errno::EINVAL)?;
+            Arc::new(NinePFs::connect(address.ip().into(), address.port())?)
+        }
+        "tmpfs" => Arc::new(tmpfs::TmpFs::new()),
+        "devfs" => Arc::new(DevFs::new()),
+        "procfs" => Arc::new(ProcFs::new()),
+        _ => return Err(Errno::ENOTSUP),
+    };
 
-    match result {
-        Ok(_) => Ok(0), // Successfully created the pipe
-        Err(e) => {
-            Err(e)
+    let path = lookup::translate_path(path)?;
+    mount(&path, fs, device, fs_type, flags).map(|_| 0)
+}
+
+/// Unmount the file system mounted on `path`. Fails with `EBUSY`, if an object in it is open,
+/// it contains the working directory of a process or another file system is mounted in it. \
+/// Returns `Ok(0)` or `Err(errno)`
+pub fn umount(path: &str) -> Result<usize, Errno> {
+    let path = lookup::translate_path(path)?;
+    let path = path.trim_end_matches('/');
+    let below = format!("{path}/");
+    let is_below = |other: &str| other == path || other.starts_with(&below);
+
+    let cwd_below = {
+        let processes = process_manager().read();
+        processes.active_process_ids().into_iter()
+            .filter_map(|pid| processes.process(pid))
+            .filter_map(|process| process.sandbox().translate_path(&process.cwd()).ok())
+            .any(|cwd| is_below(cwd.trim_end_matches('/')))
+    };
+
+    let mut mounts = MOUNTS.write();
+    if !mounts.contains_key(path) {
+        return Err(Errno::EINVAL);
+    }
+    if cwd_below || mounts.keys().any(|mount| mount.starts_with(&below)) || open_objects::is_open_below(path) {
+        return Err(Errno::EBUSY);
+    }
+    mounts.remove(path);
+    drop(mounts);
+
+    // Daten auf dem Gerät sollen nach dem Aushängen vollständig sein (z.B. vor dem Abziehen eines USB-Sticks)
+    if !storage::cache::sync() {
+        warn!("Failed to write back block cache after unmounting [{path}]");
+    }
+    Ok(0)
+}
+
+/// All mounted file systems (by absolute path)
+pub(super) fn mounts() -> Vec<(String, Mount)> {
+    MOUNTS.read().iter().map(|(path, mount)| (path.clone(), mount.clone())).collect()
+}
+
+/// Mount `fs` on `path` (an absolute path), creating the directory and its parents as needed.
+fn mount_creating_dirs(path: &str, fs: Arc<dyn FileSystem>, source: &str, fs_type: &str, flags: MountFlags) -> Result<(), Errno> {
+    let mut dir = String::new();
+    for component in path.split('/').filter(|component| !component.is_empty()) {
+        dir.push('/');
+        dir.push_str(component);
+        if !is_dir(&dir) {
+            mkdir(&dir)?;
         }
     }
+    mount(path, fs, source, fs_type, flags)
+}
+
+/// Get the root directory of the file system mounted on `path` (if any).
+pub(super) fn mounted_root(path: &str) -> Option<Arc<dyn DirectoryObject>> {
+    MOUNTS.read().get(path).map(|mount| mount.fs.root_dir())
+}
+
+/// Check whether `object_handle` refers to an object, that the current process may use. \
+/// Returns `Ok(0)` or `Err(errno)`
+pub fn check_handle(object_handle: usize) -> Result<usize, Errno> {
+    open_objects::check_handle(object_handle)
+}
+
+/// Let the process `process_id` use the object `object_handle` of the current process (with the same handle),
+/// e.g. as its standard input or output. The object is closed, when both processes have closed it. \
+/// Returns `Ok(0)` or `Err(errno)`
+pub fn share(object_handle: usize, process_id: usize) -> Result<usize, Errno> {
+    open_objects::share(object_handle, process_id)
+}
+
+/// Close all objects opened by the process `process_id`, so their handles can be reused.
+pub(crate) fn close_for_process(process_id: usize) {
+    open_objects::close_for_process(process_id);
 }
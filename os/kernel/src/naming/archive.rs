@@ -0,0 +1,254 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: archive                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Read-only file system for an archive in memory (the initial ramdisk).   ║
+   ║ Supported formats are ustar (tar) and the "new ASCII" format of cpio    ║
+   ║ (as used by Linux' initramfs). The directory tree is built once, file   ║
+   ║ objects refer directly to the data in the archive. Links and device     ║
+   ║ nodes in cpio archives are skipped.                                     ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use super::stat::{Mode, Stat, MODE_DIR, MODE_FILE};
+use super::traits::{DirectoryObject, FileObject, FileSystem, NamedObject};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str;
+use log::warn;
+use naming::shared_types::{DirEntry, FileType, OpenOptions};
+use syscall::return_vals::Errno;
+use tar_no_std::TarArchiveRef;
+
+/// Magic numbers of the cpio "new ASCII" format (without and with checksums)
+const CPIO_MAGIC: &[u8] = b"070701";
+const CPIO_MAGIC_CRC: &[u8] = b"070702";
+/// Magic number at offset 257 of a ustar header
+const USTAR_MAGIC: &[u8] = b"ustar";
+const USTAR_MAGIC_OFFSET: usize = 257;
+/// A cpio header consists of the magic number and 13 fields with 8 hexadecimal digits
+const CPIO_HEADER_SIZE: usize = 110;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+const CPIO_MODE_TYPE: u32 = 0o170000;
+const CPIO_MODE_DIR: u32 = 0o040000;
+const CPIO_MODE_FILE: u32 = 0o100000;
+
+/// The content of an archive (or one of its directories) as a file system.
+pub struct ArchiveFs {
+    root_dir: Arc<ArchiveDir>,
+}
+
+impl ArchiveFs {
+    /// Parse the ustar or cpio archive `data`.
+    pub fn new(data: &'static [u8]) -> Result<ArchiveFs, Errno> {
+        let mut root = DirBuilder::default();
+        if data.starts_with(CPIO_MAGIC) || data.starts_with(CPIO_MAGIC_CRC) {
+            parse_cpio(data, &mut root)?;
+        } else if data.get(USTAR_MAGIC_OFFSET..USTAR_MAGIC_OFFSET + USTAR_MAGIC.len()) == Some(USTAR_MAGIC) {
+            let archive = TarArchiveRef::new(data).map_err(|_| Errno::EINVAL)?;
+            for entry in archive.entries() {
+                match entry.filename().as_str() {
+                    Ok(path) => root.add_file(path, entry.data()),
+                    Err(_) => warn!("Skipping file with invalid name in archive"),
+                }
+            }
+        } else {
+            return Err(Errno::EINVAL);
+        }
+
+        Ok(ArchiveFs { root_dir: root.build() })
+    }
+
+    /// Files in the top level directory of the archive
+    pub fn files(&self) -> Vec<(&str, &'static [u8])> {
+        self.root_dir.entries.iter()
+            .filter_map(|(name, node)| match node {
+                Node::File(file) => Some((name.as_str(), file.data)),
+                Node::Dir(_) => None,
+            })
+            .collect()
+    }
+
+    /// Directories in the top level directory of the archive, each as a file system of its own
+    pub fn dirs(&self) -> Vec<(&str, ArchiveFs)> {
+        self.root_dir.entries.iter()
+            .filter_map(|(name, node)| match node {
+                Node::Dir(dir) => Some((name.as_str(), ArchiveFs { root_dir: Arc::clone(dir) })),
+                Node::File(_) => None,
+            })
+            .collect()
+    }
+}
+
+impl FileSystem for ArchiveFs {
+    fn root_dir(&self) -> Arc<dyn DirectoryObject> {
+        self.root_dir.clone()
+    }
+}
+
+/// Parse the entries of a cpio archive in the "new ASCII" format into `root`.
+fn parse_cpio(data: &'static [u8], root: &mut DirBuilder) -> Result<(), Errno> {
+    let mut offset = 0;
+    loop {
+        let header = data.get(offset..offset + CPIO_HEADER_SIZE).ok_or(Errno::EINVAL)?;
+        if !header.starts_with(CPIO_MAGIC) && !header.starts_with(CPIO_MAGIC_CRC) {
+            return Err(Errno::EINVAL);
+        }
+        // Felder: ino, mode, uid, gid, nlink, mtime, filesize, devmajor, devminor, rdevmajor, rdevminor, namesize, check
+        let field = |index: usize| -> Result<u32, Errno> {
+            let start = CPIO_MAGIC.len() + index * 8;
+            str::from_utf8(&header[start..start + 8]).ok()
+                .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                .ok_or(Errno::EINVAL)
+        };
+        let mode = field(1)?;
+        let file_size = field(6)? as usize;
+        let name_size = field(11)? as usize;
+
+        // name and data are padded to a multiple of 4 bytes (the name includes the terminating 0)
+        let name_start = offset + CPIO_HEADER_SIZE;
+        let name = data.get(name_start..name_start + name_size.saturating_sub(1)).ok_or(Errno::EINVAL)?;
+        let name = str::from_utf8(name).map_err(|_| Errno::EINVAL)?;
+        let data_start = (name_start + name_size).next_multiple_of(4);
+        let file_data = data.get(data_start..data_start + file_size).ok_or(Errno::EINVAL)?;
+        offset = (data_start + file_size).next_multiple_of(4);
+
+        match mode & CPIO_MODE_TYPE {
+            _ if name == CPIO_TRAILER => return Ok(()),
+            CPIO_MODE_DIR => root.add_dir(name),
+            CPIO_MODE_FILE => root.add_file(name, file_data),
+            _ => warn!("Skipping special file [{name}] in archive"),
+        }
+    }
+}
+
+/// Directory tree being assembled from the entries of an archive
+#[derive(Default)]
+struct DirBuilder {
+    dirs: BTreeMap<String, DirBuilder>,
+    files: BTreeMap<String, &'static [u8]>,
+}
+
+impl DirBuilder {
+    /// Get the directory `path` (relative to this one), creating it and its parents as needed.
+    fn dir(&mut self, path: &str) -> &mut DirBuilder {
+        // Archive enthalten Pfade wie "./bin/shell" oder "/bin/shell"
+        path.split('/')
+            .filter(|component| !component.is_empty() && *component != ".")
+            .fold(self, |dir, component| dir.dirs.entry(component.to_string()).or_default())
+    }
+
+    fn add_dir(&mut self, path: &str) {
+        self.dir(path);
+    }
+
+    fn add_file(&mut self, path: &str, data: &'static [u8]) {
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if !name.is_empty() && name != "." {
+            self.dir(parent).files.insert(name.to_string(), data);
+        }
+    }
+
+    fn build(self) -> Arc<ArchiveDir> {
+        let dirs = self.dirs.into_iter().map(|(name, dir)| (name, Node::Dir(dir.build())));
+        let files = self.files.into_iter().map(|(name, data)| (name, Node::File(Arc::new(ArchiveFile { data }))));
+        Arc::new(ArchiveDir { entries: dirs.chain(files).collect() })
+    }
+}
+
+#[derive(Clone)]
+enum Node {
+    File(Arc<ArchiveFile>),
+    Dir(Arc<ArchiveDir>),
+}
+
+struct ArchiveDir {
+    entries: Vec<(String, Node)>,
+}
+
+impl DirectoryObject for ArchiveDir {
+    fn lookup(&self, name: &str) -> Result<NamedObject, Errno> {
+        match self.entries.iter().find(|(entry_name, _)| entry_name == name) {
+            Some((_, Node::File(file))) => Ok((file.clone() as Arc<dyn FileObject>).into()),
+            Some((_, Node::Dir(dir))) => Ok((dir.clone() as Arc<dyn DirectoryObject>).into()),
+            None => Err(Errno::ENOENT),
+        }
+    }
+
+    fn create_file(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn create_dir(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn create_pipe(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(Stat::new(Mode::new(MODE_DIR), 0))
+    }
+
+    fn readdir(&self, index: usize) -> Result<Option<DirEntry>, Errno> {
+        Ok(self.entries.get(index).map(|(name, node)| DirEntry {
+            file_type: match node {
+                Node::File(_) => FileType::Regular,
+                Node::Dir(_) => FileType::Directory,
+            },
+            name: name.clone(),
+        }))
+    }
+
+    fn rename(&self, _old_name: &str, _new_dir: &Arc<dyn DirectoryObject>, _new_name: &str) -> Result<(), Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn unlink(&self, _name: &str) -> Result<(), Errno> {
+        Err(Errno::ERDONLY)
+    }
+}
+
+impl fmt::Debug for ArchiveDir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArchiveDir").field("entries", &self.entries.len()).finish()
+    }
+}
+
+struct ArchiveFile {
+    data: &'static [u8],
+}
+
+impl FileObject for ArchiveFile {
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(Stat::new(Mode::new(MODE_FILE), self.data.len()))
+    }
+
+    fn read(&self, buf: &mut [u8], offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        let Some(data) = self.data.get(offset..) else {
+            return Ok(0);
+        };
+
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Ok(len)
+    }
+
+    fn write(&self, _buf: &[u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn truncate(&self, _size: usize) -> Result<(), Errno> {
+        Err(Errno::ERDONLY)
+    }
+}
+
+impl fmt::Debug for ArchiveFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArchiveFile").field("size", &self.data.len()).finish()
+    }
+}
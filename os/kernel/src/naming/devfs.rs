@@ -0,0 +1,339 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: devfs                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Pseudo file system, that makes devices accessible as files (mounted on  ║
+   ║ /dev), so applications can use them with read and write:                ║
+   ║   - null, zero           discard writes, read nothing or zeros          ║
+   ║   - random, urandom      random numbers from the entropy pool           ║
+   ║   - ttyS0                the serial port (if there is one)              ║
+   ║   - fb0                  the pixels of the framebuffer                  ║
+   ║   - <block device>       drives and partitions (e.g. ata0, ata0p0)      ║
+   ║ The content of the directory is determined on every access, so block    ║
+   ║ devices added at runtime appear without remounting.                     ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use super::stat::{Mode, Stat, MODE_DIR, MODE_FILE};
+use super::traits::{DirectoryObject, FileObject, FileSystem, NamedObject};
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use naming::shared_types::{DirEntry, FileType, OpenOptions};
+use stream::{DecodedInputStream, OutputStream};
+use syscall::return_vals::Errno;
+use crate::device::serial::SerialPort;
+use crate::storage::block::BlockDevice;
+use crate::{buffered_lfb, entropy_pool, serial_port, storage};
+
+pub struct DevFs {
+    root_dir: Arc<DevDir>,
+}
+
+impl DevFs {
+    pub fn new() -> DevFs {
+        DevFs { root_dir: Arc::new(DevDir) }
+    }
+}
+
+impl FileSystem for DevFs {
+    fn root_dir(&self) -> Arc<dyn DirectoryObject> {
+        self.root_dir.clone()
+    }
+}
+
+#[derive(Debug)]
+struct DevDir;
+
+impl DevDir {
+    /// All devices, that are currently present (sorted by name)
+    fn devices(&self) -> Vec<(String, FileType)> {
+        let mut devices = Vec::from([
+            ("null".to_string(), FileType::CharDevice),
+            ("zero".to_string(), FileType::CharDevice),
+            ("random".to_string(), FileType::CharDevice),
+            ("urandom".to_string(), FileType::CharDevice),
+            ("fb0".to_string(), FileType::CharDevice),
+        ]);
+        if serial_port().is_some() {
+            devices.push(("ttyS0".to_string(), FileType::CharDevice));
+        }
+        devices.extend(storage::block_device_names().into_iter().map(|name| (name, FileType::BlockDevice)));
+
+        devices.sort_by(|(a, _), (b, _)| a.cmp(b));
+        devices
+    }
+}
+
+impl DirectoryObject for DevDir {
+    fn lookup(&self, name: &str) -> Result<NamedObject, Errno> {
+        let file: Arc<dyn FileObject> = match name {
+            "null" => Arc::new(Null),
+            "zero" => Arc::new(Zero),
+            "random" => Arc::new(Random { nonblock: false }),
+            "urandom" => Arc::new(Random { nonblock: true }),
+            "fb0" => Arc::new(Framebuffer),
+            "ttyS0" => Arc::new(Serial { port: serial_port().ok_or(Errno::ENOENT)? }),
+            _ => Arc::new(Block { device: storage::block_device(name).ok_or(Errno::ENOENT)? }),
+        };
+
+        Ok(file.into())
+    }
+
+    fn create_file(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::EACCES)
+    }
+
+    fn create_dir(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::EACCES)
+    }
+
+    fn create_pipe(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::EACCES)
+    }
+
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(Stat::new(Mode::new(MODE_DIR), 0))
+    }
+
+    fn readdir(&self, index: usize) -> Result<Option<DirEntry>, Errno> {
+        Ok(self.devices().into_iter().nth(index).map(|(name, file_type)| DirEntry { file_type, name }))
+    }
+
+    fn rename(&self, _old_name: &str, _new_dir: &Arc<dyn DirectoryObject>, _new_name: &str) -> Result<(), Errno> {
+        Err(Errno::EACCES)
+    }
+
+    fn unlink(&self, _name: &str) -> Result<(), Errno> {
+        Err(Errno::EACCES)
+    }
+}
+
+/// Reads nothing, discards everything written to it.
+#[derive(Debug)]
+struct Null;
+
+impl FileObject for Null {
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(Stat::new(Mode::new(MODE_FILE), 0))
+    }
+
+    fn read(&self, _buf: &mut [u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        Ok(0)
+    }
+
+    fn write(&self, buf: &[u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        Ok(buf.len())
+    }
+
+    /// Devices have no size, so truncating them (e.g. when opened with `TRUNCATE`) does nothing.
+    fn truncate(&self, _size: usize) -> Result<(), Errno> {
+        Ok(())
+    }
+}
+
+/// Reads an endless stream of zeros, discards everything written to it.
+#[derive(Debug)]
+struct Zero;
+
+impl FileObject for Zero {
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(Stat::new(Mode::new(MODE_FILE), 0))
+    }
+
+    fn read(&self, buf: &mut [u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        buf.fill(0);
+        Ok(buf.len())
+    }
+
+    fn write(&self, buf: &[u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: usize) -> Result<(), Errno> {
+        Ok(())
+    }
+}
+
+/// Random numbers from the entropy pool. Data written to it is mixed into the pool (without counting as entropy).
+#[derive(Debug)]
+struct Random {
+    /// `urandom` fails with `EAGAIN` instead of waiting for the pool to be seeded
+    nonblock: bool,
+}
+
+impl FileObject for Random {
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(Stat::new(Mode::new(MODE_FILE), 0))
+    }
+
+    fn read(&self, buf: &mut [u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        entropy_pool().get_random(buf, self.nonblock)
+    }
+
+    fn write(&self, buf: &[u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        entropy_pool().add_entropy(buf, 0);
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: usize) -> Result<(), Errno> {
+        Ok(())
+    }
+}
+
+/// The serial port. Reading blocks until at least one byte has been received.
+struct Serial {
+    port: Arc<SerialPort>,
+}
+
+impl FileObject for Serial {
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(Stat::new(Mode::new(MODE_FILE), 0))
+    }
+
+    fn read(&self, buf: &mut [u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // -1 bedeutet, dass der Empfangspuffer geschlossen wurde
+        let mut count = 0;
+        let mut next = Some(self.port.decoded_read_byte());
+        while let Some(byte) = next && byte >= 0 {
+            buf[count] = byte as u8;
+            count += 1;
+            next = if count < buf.len() { self.port.decoded_try_read_byte() } else { None };
+        }
+
+        Ok(count)
+    }
+
+    fn write(&self, buf: &[u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        buf.iter().for_each(|byte| self.port.write_byte(*byte));
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: usize) -> Result<(), Errno> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Serial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Serial")
+    }
+}
+
+/// The memory of the framebuffer (line by line, including the padding at the end of each line)
+#[derive(Debug)]
+struct Framebuffer;
+
+impl FileObject for Framebuffer {
+    fn stat(&self) -> Result<Stat, Errno> {
+        let mut lfb = buffered_lfb().lock();
+        let lfb = lfb.direct_lfb();
+        Ok(Stat::new(Mode::new(MODE_FILE), lfb.pitch() as usize * lfb.height() as usize))
+    }
+
+    fn read(&self, buf: &mut [u8], offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        let mut lfb = buffered_lfb().lock();
+        let lfb = lfb.direct_lfb();
+        let size = lfb.pitch() as usize * lfb.height() as usize;
+        let len = buf.len().min(size.saturating_sub(offset));
+
+        let memory = unsafe { core::slice::from_raw_parts(lfb.buffer(), size) };
+        buf[..len].copy_from_slice(&memory[offset..offset + len]);
+        Ok(len)
+    }
+
+    fn write(&self, buf: &[u8], offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        let mut lfb = buffered_lfb().lock();
+        let lfb = lfb.direct_lfb();
+        let size = lfb.pitch() as usize * lfb.height() as usize;
+        let len = buf.len().min(size.saturating_sub(offset));
+
+        let memory = unsafe { core::slice::from_raw_parts_mut(lfb.buffer(), size) };
+        memory[offset..offset + len].copy_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn truncate(&self, _size: usize) -> Result<(), Errno> {
+        Ok(())
+    }
+}
+
+/// A drive or partition, accessible at any byte offset (partial sectors are read, modified and written back)
+struct Block {
+    device: Arc<dyn BlockDevice + Send + Sync>,
+}
+
+impl Block {
+    fn size(&self) -> usize {
+        self.device.sector_count() as usize * self.device.sector_size() as usize
+    }
+
+    /// Read the sectors covering `len` bytes at `offset`.
+    /// Returns the buffer and the position of `offset` in it.
+    fn read_sectors(&self, offset: usize, len: usize) -> Result<(Vec<u8>, usize), Errno> {
+        let sector_size = self.device.sector_size() as usize;
+        let first = offset / sector_size;
+        let count = (offset + len).div_ceil(sector_size) - first;
+
+        let mut sectors = vec![0; count * sector_size];
+        if self.device.read(first as u64, count, &mut sectors) != count {
+            return Err(Errno::EIO);
+        }
+
+        Ok((sectors, offset % sector_size))
+    }
+}
+
+impl FileObject for Block {
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(Stat::new(Mode::new(MODE_FILE), self.size()))
+    }
+
+    fn read(&self, buf: &mut [u8], offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        let len = buf.len().min(self.size().saturating_sub(offset));
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let (sectors, start) = self.read_sectors(offset, len)?;
+        buf[..len].copy_from_slice(&sectors[start..start + len]);
+        Ok(len)
+    }
+
+    fn write(&self, buf: &[u8], offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        let len = buf.len().min(self.size().saturating_sub(offset));
+        if len == 0 {
+            return match buf.is_empty() {
+                true => Ok(0),
+                false => Err(Errno::EINVAL),
+            };
+        }
+
+        let sector_size = self.device.sector_size() as usize;
+        let (mut sectors, start) = self.read_sectors(offset, len)?;
+        sectors[start..start + len].copy_from_slice(&buf[..len]);
+
+        let count = sectors.len() / sector_size;
+        if self.device.write((offset / sector_size) as u64, count, &sectors) != count {
+            return Err(Errno::EIO);
+        }
+
+        Ok(len)
+    }
+
+    fn truncate(&self, _size: usize) -> Result<(), Errno> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Block").field("size", &self.size()).finish()
+    }
+}
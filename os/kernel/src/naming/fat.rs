@@ -0,0 +1,1089 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: fat                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ FAT16 and FAT32 file system on a block device. Long file names (VFAT)   ║
+   ║ are read and written, short names are generated for new entries. Named  ║
+   ║ objects don't cache anything: a directory is identified by its first    ║
+   ║ cluster (or the fixed root directory of FAT16) and a file by the slot   ║
+   ║ of its entry in the parent directory, which is read for every access.   ║
+   ║ All operations on a volume are serialized by a single lock. Renaming is ║
+   ║ not supported yet.                                                      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use super::stat::{Mode, Stat, MODE_DIR, MODE_FILE};
+use super::traits::{DirectoryObject, FileObject, FileSystem, NamedObject};
+use crate::storage::block::BlockDevice;
+use crate::wallclock;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use chrono::{DateTime, Datelike, NaiveDate, Timelike};
+use core::fmt;
+use core::sync::atomic::{AtomicU32, Ordering};
+use log::warn;
+use naming::shared_types::{DirEntry, FileType, OpenOptions};
+use spin::Mutex;
+use syscall::return_vals::Errno;
+
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+/// Volumes with fewer clusters are FAT12, volumes with more are FAT32 (as specified by Microsoft)
+const MIN_FAT16_CLUSTERS: u32 = 4085;
+const MIN_FAT32_CLUSTERS: u32 = 65525;
+/// Clusters 0 and 1 are reserved, their FAT entries hold the media type and flags
+const FIRST_CLUSTER: u32 = 2;
+/// The upper 4 bits of a FAT32 entry are reserved
+const FAT32_MASK: u32 = 0x0fff_ffff;
+
+const DIR_ENTRY_SIZE: usize = 32;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0f;
+const ATTR_LONG_NAME_MASK: u8 = 0x3f;
+/// First byte of a free entry, after which all entries are free
+const END_OF_DIR: u8 = 0x00;
+/// First byte of a deleted entry
+const DELETED: u8 = 0xe5;
+/// First byte of a short name, which really starts with 0xe5
+const ESCAPED_E5: u8 = 0x05;
+/// Flags of the NT reserved byte: base name or extension of a short name are lower case
+const LOWER_CASE_BASE: u8 = 0x08;
+const LOWER_CASE_EXTENSION: u8 = 0x10;
+
+/// Ordinal flag of the first long name entry (which holds the end of the name)
+const LAST_LONG_ENTRY: u8 = 0x40;
+/// UTF-16 characters per long name entry and their offsets within the entry
+const LONG_NAME_CHARS: usize = 13;
+const LONG_NAME_OFFSETS: [usize; LONG_NAME_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+const MAX_NAME_LENGTH: usize = 255;
+/// Characters, that are not allowed in long names (besides control characters)
+const INVALID_NAME_CHARS: &str = "\"*/:<>?\\|";
+/// Characters, that are allowed in short names besides upper case letters and digits
+const SHORT_NAME_SPECIAL_CHARS: &str = "!#$%&'()-@^_`{}~";
+
+/// 1980-01-01, the earliest date FAT can store
+const DOS_EPOCH_YEAR: i32 = 1980;
+const DOS_EPOCH_DATE: u16 = (1 << 5) | 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FatType {
+    Fat16,
+    Fat32,
+}
+
+/// A FAT16 or FAT32 volume.
+pub struct FatFs {
+    volume: Arc<Volume>,
+}
+
+impl FatFs {
+    /// Check, whether `device` contains a FAT16 or FAT32 file system and open it.
+    /// Returns `None` for other content (e.g. a partition table) and unsupported variants.
+    pub fn probe(device: Arc<dyn BlockDevice + Send + Sync>) -> Option<FatFs> {
+        Volume::probe(device).map(|volume| FatFs { volume: Arc::new(volume) })
+    }
+}
+
+impl FileSystem for FatFs {
+    fn root_dir(&self) -> Arc<dyn DirectoryObject> {
+        let location = match self.volume.fat_type {
+            FatType::Fat16 => DirLocation::FixedRoot,
+            FatType::Fat32 => DirLocation::Clusters(self.volume.root_cluster),
+        };
+        Arc::new(FatDir::new(Arc::clone(&self.volume), location))
+    }
+}
+
+/// Where the entries of a directory are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DirLocation {
+    /// The root directory of FAT16, which is stored between the FATs and the data area
+    FixedRoot,
+    /// A chain of clusters, starting with the given one
+    Clusters(u32),
+}
+
+/// Layout of a volume (from the BIOS parameter block)
+struct Volume {
+    device: Arc<dyn BlockDevice + Send + Sync>,
+    fat_type: FatType,
+    bytes_per_sector: usize,
+    sectors_per_cluster: usize,
+    fat_start: u64,
+    fat_count: usize,
+    sectors_per_fat: u64,
+    /// Fixed root directory (FAT16 only)
+    root_dir_start: u64,
+    root_dir_sectors: u64,
+    /// First cluster of the root directory (FAT32 only)
+    root_cluster: u32,
+    data_start: u64,
+    cluster_count: u32,
+    /// The search for a free cluster starts here
+    next_free: AtomicU32,
+    lock: Mutex<()>,
+}
+
+impl Volume {
+    fn probe(device: Arc<dyn BlockDevice + Send + Sync>) -> Option<Volume> {
+        let sector_size = device.sector_size() as usize;
+        let mut boot_sector = vec![0u8; sector_size];
+        if sector_size < 512 || device.read(0, 1, &mut boot_sector) != 1 || boot_sector[510..512] != BOOT_SIGNATURE {
+            return None;
+        }
+
+        let bytes_per_sector = u16_at(&boot_sector, 11) as usize;
+        let sectors_per_cluster = boot_sector[13] as usize;
+        let reserved_sectors = u16_at(&boot_sector, 14) as u64;
+        let fat_count = boot_sector[16] as usize;
+        let root_dir_entries = u16_at(&boot_sector, 17) as usize;
+        let total_sectors = match u16_at(&boot_sector, 19) {
+            0 => u32_at(&boot_sector, 32) as u64,
+            sectors => sectors as u64,
+        };
+        let sectors_per_fat = match u16_at(&boot_sector, 22) {
+            0 => u32_at(&boot_sector, 36) as u64,
+            sectors => sectors as u64,
+        };
+
+        // Ein MBR oder andere Dateisysteme haben hier keine gültigen Werte
+        if !matches!(boot_sector[0], 0xeb | 0xe9)
+            || !bytes_per_sector.is_power_of_two()
+            || !(512..=4096).contains(&bytes_per_sector)
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved_sectors == 0
+            || fat_count == 0
+            || sectors_per_fat == 0
+        {
+            return None;
+        }
+        if bytes_per_sector != sector_size {
+            warn!("FAT volumes with {bytes_per_sector} bytes per sector are not supported on devices with {sector_size}-byte sectors");
+            return None;
+        }
+
+        let root_dir_start = reserved_sectors + fat_count as u64 * sectors_per_fat;
+        let root_dir_sectors = (root_dir_entries * DIR_ENTRY_SIZE).div_ceil(bytes_per_sector) as u64;
+        let data_start = root_dir_start + root_dir_sectors;
+        if total_sectors <= data_start || total_sectors > device.sector_count() {
+            return None;
+        }
+
+        let cluster_count = ((total_sectors - data_start) / sectors_per_cluster as u64) as u32;
+        let fat_type = if cluster_count < MIN_FAT16_CLUSTERS {
+            warn!("FAT12 is not supported");
+            return None;
+        } else if cluster_count < MIN_FAT32_CLUSTERS {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+
+        let entry_size = match fat_type {
+            FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        };
+        let fixed_root = root_dir_entries != 0;
+        if fixed_root != (fat_type == FatType::Fat16) || sectors_per_fat * (bytes_per_sector as u64) < (cluster_count as u64 + 2) * entry_size {
+            return None;
+        }
+
+        let volume = Volume {
+            device,
+            fat_type,
+            bytes_per_sector,
+            sectors_per_cluster,
+            fat_start: reserved_sectors,
+            fat_count,
+            sectors_per_fat,
+            root_dir_start,
+            root_dir_sectors,
+            root_cluster: if fat_type == FatType::Fat32 { u32_at(&boot_sector, 44) } else { 0 },
+            data_start,
+            cluster_count,
+            next_free: AtomicU32::new(FIRST_CLUSTER),
+            lock: Mutex::new(()),
+        };
+        if fat_type == FatType::Fat32 && !volume.is_valid_cluster(volume.root_cluster) {
+            return None;
+        }
+
+        Some(volume)
+    }
+
+    fn read_sectors(&self, sector: u64, buffer: &mut [u8]) -> Result<(), Errno> {
+        let count = buffer.len() / self.bytes_per_sector;
+        match self.device.read(sector, count, buffer) == count {
+            true => Ok(()),
+            false => Err(Errno::EIO),
+        }
+    }
+
+    fn write_sectors(&self, sector: u64, buffer: &[u8]) -> Result<(), Errno> {
+        let count = buffer.len() / self.bytes_per_sector;
+        match self.device.write(sector, count, buffer) == count {
+            true => Ok(()),
+            false => Err(Errno::EIO),
+        }
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.bytes_per_sector * self.sectors_per_cluster
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= FIRST_CLUSTER && cluster < self.cluster_count + FIRST_CLUSTER
+    }
+
+    fn cluster_sector(&self, cluster: u32) -> Result<u64, Errno> {
+        if !self.is_valid_cluster(cluster) {
+            return Err(Errno::EIO);
+        }
+        Ok(self.data_start + (cluster - FIRST_CLUSTER) as u64 * self.sectors_per_cluster as u64)
+    }
+
+    fn read_cluster(&self, cluster: u32, buffer: &mut [u8]) -> Result<(), Errno> {
+        self.read_sectors(self.cluster_sector(cluster)?, buffer)
+    }
+
+    fn write_cluster(&self, cluster: u32, buffer: &[u8]) -> Result<(), Errno> {
+        self.write_sectors(self.cluster_sector(cluster)?, buffer)
+    }
+
+    /// Sector (relative to the start of a FAT) and offset of the FAT entry of `cluster`
+    fn fat_position(&self, cluster: u32) -> (u64, usize) {
+        let offset = cluster as usize * match self.fat_type {
+            FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        };
+        ((offset / self.bytes_per_sector) as u64, offset % self.bytes_per_sector)
+    }
+
+    fn fat_value(&self, sector: &[u8], offset: usize) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => u16_at(sector, offset) as u32,
+            FatType::Fat32 => u32_at(sector, offset) & FAT32_MASK,
+        }
+    }
+
+    fn fat_entry(&self, cluster: u32) -> Result<u32, Errno> {
+        let (sector, offset) = self.fat_position(cluster);
+        let mut buffer = vec![0u8; self.bytes_per_sector];
+        self.read_sectors(self.fat_start + sector, &mut buffer)?;
+        Ok(self.fat_value(&buffer, offset))
+    }
+
+    /// Set the FAT entry of `cluster` in all copies of the FAT.
+    fn set_fat_entry(&self, cluster: u32, value: u32) -> Result<(), Errno> {
+        let (sector, offset) = self.fat_position(cluster);
+        let mut buffer = vec![0u8; self.bytes_per_sector];
+        for fat in 0..self.fat_count as u64 {
+            let sector = self.fat_start + fat * self.sectors_per_fat + sector;
+            self.read_sectors(sector, &mut buffer)?;
+            match self.fat_type {
+                FatType::Fat16 => buffer[offset..offset + 2].copy_from_slice(&(value as u16).to_le_bytes()),
+                FatType::Fat32 => {
+                    let value = (u32_at(&buffer, offset) & !FAT32_MASK) | (value & FAT32_MASK);
+                    buffer[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+                }
+            }
+            self.write_sectors(sector, &buffer)?;
+        }
+
+        Ok(())
+    }
+
+    fn end_of_chain(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => 0xffff,
+            FatType::Fat32 => 0x0fff_ffff,
+        }
+    }
+
+    fn is_end_of_chain(&self, value: u32) -> bool {
+        value >= match self.fat_type {
+            FatType::Fat16 => 0xfff8,
+            FatType::Fat32 => 0x0fff_fff8,
+        }
+    }
+
+    /// All clusters of the chain starting with `first` (empty for 0, which is used by empty files).
+    fn cluster_chain(&self, first: u32) -> Result<Vec<u32>, Errno> {
+        let mut chain = Vec::new();
+        let mut cluster = first;
+        while cluster != 0 {
+            // eine beschädigte FAT kann Schleifen enthalten
+            if !self.is_valid_cluster(cluster) || chain.len() >= self.cluster_count as usize {
+                return Err(Errno::EIO);
+            }
+            chain.push(cluster);
+
+            let next = self.fat_entry(cluster)?;
+            if self.is_end_of_chain(next) {
+                break;
+            }
+            cluster = next;
+        }
+
+        Ok(chain)
+    }
+
+    /// Allocate a zeroed cluster and append it to the chain ending with `last` (if any).
+    /// Fails with `ENOMEM`, if the volume is full.
+    fn allocate_cluster(&self, last: Option<u32>) -> Result<u32, Errno> {
+        let cluster = self.find_free_cluster()?.ok_or(Errno::ENOMEM)?;
+        self.write_cluster(cluster, &vec![0u8; self.cluster_size()])?;
+        self.set_fat_entry(cluster, self.end_of_chain())?;
+        if let Some(last) = last {
+            self.set_fat_entry(last, cluster)?;
+        }
+
+        self.next_free.store(cluster + 1, Ordering::Relaxed);
+        Ok(cluster)
+    }
+
+    fn find_free_cluster(&self) -> Result<Option<u32>, Errno> {
+        let start = self.next_free.load(Ordering::Relaxed).clamp(FIRST_CLUSTER, self.cluster_count + FIRST_CLUSTER - 1);
+        let mut buffer = vec![0u8; self.bytes_per_sector];
+        let mut buffered_sector = None;
+
+        for index in 0..self.cluster_count {
+            let cluster = FIRST_CLUSTER + (start - FIRST_CLUSTER + index) % self.cluster_count;
+            let (sector, offset) = self.fat_position(cluster);
+            if buffered_sector != Some(sector) {
+                self.read_sectors(self.fat_start + sector, &mut buffer)?;
+                buffered_sector = Some(sector);
+            }
+            if self.fat_value(&buffer, offset) == 0 {
+                return Ok(Some(cluster));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Mark all clusters of the chain starting with `first` as free.
+    fn free_chain(&self, first: u32) -> Result<(), Errno> {
+        for cluster in self.cluster_chain(first)? {
+            self.set_fat_entry(cluster, 0)?;
+        }
+        Ok(())
+    }
+
+    /// All sectors holding the entries of the directory at `location`
+    fn dir_sectors(&self, location: DirLocation) -> Result<Vec<u64>, Errno> {
+        match location {
+            DirLocation::FixedRoot => Ok((self.root_dir_start..self.root_dir_start + self.root_dir_sectors).collect()),
+            DirLocation::Clusters(first) => {
+                let mut sectors = Vec::new();
+                for cluster in self.cluster_chain(first)? {
+                    let start = self.cluster_sector(cluster)?;
+                    sectors.extend(start..start + self.sectors_per_cluster as u64);
+                }
+                Ok(sectors)
+            }
+        }
+    }
+
+    /// Read all entries (slots) of the directory at `location`.
+    fn read_dir(&self, location: DirLocation) -> Result<Vec<[u8; DIR_ENTRY_SIZE]>, Errno> {
+        let mut buffer = vec![0u8; self.bytes_per_sector];
+        let mut slots = Vec::new();
+        for sector in self.dir_sectors(location)? {
+            self.read_sectors(sector, &mut buffer)?;
+            slots.extend(buffer.chunks_exact(DIR_ENTRY_SIZE).map(|slot| <[u8; DIR_ENTRY_SIZE]>::try_from(slot).unwrap()));
+        }
+
+        Ok(slots)
+    }
+
+    fn read_slot(&self, location: DirLocation, index: usize) -> Result<[u8; DIR_ENTRY_SIZE], Errno> {
+        let offset = index * DIR_ENTRY_SIZE;
+        let sector = *self.dir_sectors(location)?.get(offset / self.bytes_per_sector).ok_or(Errno::EIO)?;
+        let mut buffer = vec![0u8; self.bytes_per_sector];
+        self.read_sectors(sector, &mut buffer)?;
+
+        let offset = offset % self.bytes_per_sector;
+        Ok(buffer[offset..offset + DIR_ENTRY_SIZE].try_into().unwrap())
+    }
+
+    /// Overwrite the entries of the directory at `location` starting with slot `start`.
+    fn write_slots(&self, location: DirLocation, start: usize, slots: &[[u8; DIR_ENTRY_SIZE]]) -> Result<(), Errno> {
+        let sectors = self.dir_sectors(location)?;
+        let mut buffer = vec![0u8; self.bytes_per_sector];
+        let mut buffered_sector = None;
+
+        for (index, slot) in slots.iter().enumerate() {
+            let offset = (start + index) * DIR_ENTRY_SIZE;
+            let sector = *sectors.get(offset / self.bytes_per_sector).ok_or(Errno::EIO)?;
+            if buffered_sector != Some(sector) {
+                if let Some(buffered) = buffered_sector {
+                    self.write_sectors(buffered, &buffer)?;
+                }
+                self.read_sectors(sector, &mut buffer)?;
+                buffered_sector = Some(sector);
+            }
+
+            let offset = offset % self.bytes_per_sector;
+            buffer[offset..offset + DIR_ENTRY_SIZE].copy_from_slice(slot);
+        }
+
+        match buffered_sector {
+            Some(sector) => self.write_sectors(sector, &buffer),
+            None => Ok(()),
+        }
+    }
+
+    /// Store `new` as consecutive entries in the directory at `location`, whose current entries are `slots`.
+    /// Deleted entries are reused, otherwise the entries are appended (which may grow the directory).
+    /// Returns the index of the last new slot.
+    fn insert_slots(&self, location: DirLocation, slots: &[[u8; DIR_ENTRY_SIZE]], new: &[[u8; DIR_ENTRY_SIZE]]) -> Result<usize, Errno> {
+        let count = new.len();
+        let end = slots.iter().position(|slot| slot[0] == END_OF_DIR).unwrap_or(slots.len());
+
+        let mut run = 0;
+        let mut start = None;
+        for (index, slot) in slots[..end].iter().enumerate() {
+            run = if slot[0] == DELETED { run + 1 } else { 0 };
+            if run == count {
+                start = Some(index + 1 - count);
+                break;
+            }
+        }
+
+        let start = match start {
+            Some(start) => start,
+            None => {
+                let mut capacity = slots.len();
+                while end + count > capacity {
+                    let DirLocation::Clusters(first) = location else {
+                        // das Wurzelverzeichnis von FAT16 hat eine feste Größe
+                        return Err(Errno::ENOMEM);
+                    };
+                    let last = self.cluster_chain(first)?.last().copied();
+                    self.allocate_cluster(last)?;
+                    capacity += self.cluster_size() / DIR_ENTRY_SIZE;
+                }
+
+                // entries behind the end marker are free, but not necessarily zeroed
+                if end + count < slots.len() && slots[end + count][0] != END_OF_DIR {
+                    self.write_slots(location, end + count, &[[0u8; DIR_ENTRY_SIZE]])?;
+                }
+                end
+            }
+        };
+
+        self.write_slots(location, start, new)?;
+        Ok(start + count - 1)
+    }
+
+    /// Write `data` at `offset` into the file described by `entry`, allocating clusters as needed.
+    /// The first cluster is updated in `entry`, but the size is not.
+    fn write_data(&self, entry: &mut ShortEntry, offset: usize, data: &[u8]) -> Result<(), Errno> {
+        let cluster_size = self.cluster_size();
+        let mut chain = self.cluster_chain(entry.first_cluster())?;
+        while chain.len() < (offset + data.len()).div_ceil(cluster_size) {
+            let cluster = self.allocate_cluster(chain.last().copied())?;
+            if chain.is_empty() {
+                entry.set_first_cluster(cluster);
+            }
+            chain.push(cluster);
+        }
+
+        let mut buffer = vec![0u8; cluster_size];
+        let mut done = 0;
+        while done < data.len() {
+            let position = offset + done;
+            let cluster = chain[position / cluster_size];
+            let start = position % cluster_size;
+            let length = (cluster_size - start).min(data.len() - done);
+
+            if length < cluster_size {
+                self.read_cluster(cluster, &mut buffer)?;
+            }
+            buffer[start..start + length].copy_from_slice(&data[done..done + length]);
+            self.write_cluster(cluster, &buffer)?;
+            done += length;
+        }
+
+        Ok(())
+    }
+}
+
+/// A short (8.3) directory entry with attributes, first cluster, size and times
+#[derive(Clone, Copy)]
+struct ShortEntry([u8; DIR_ENTRY_SIZE]);
+
+impl ShortEntry {
+    fn new(short_name: [u8; 11], attributes: u8, first_cluster: u32) -> ShortEntry {
+        let mut entry = ShortEntry([0u8; DIR_ENTRY_SIZE]);
+        entry.0[..11].copy_from_slice(&short_name);
+        entry.0[11] = attributes;
+        entry.set_first_cluster(first_cluster);
+
+        let (date, time) = fat_timestamp();
+        entry.0[14..16].copy_from_slice(&time.to_le_bytes());
+        entry.0[16..18].copy_from_slice(&date.to_le_bytes());
+        entry.touch();
+        entry
+    }
+
+    fn short_name(&self) -> [u8; 11] {
+        self.0[..11].try_into().unwrap()
+    }
+
+    fn is_dir(&self) -> bool {
+        self.0[11] & ATTR_DIRECTORY != 0
+    }
+
+    fn first_cluster(&self) -> u32 {
+        (u16_at(&self.0, 20) as u32) << 16 | u16_at(&self.0, 26) as u32
+    }
+
+    fn set_first_cluster(&mut self, cluster: u32) {
+        self.0[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+        self.0[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+    }
+
+    fn size(&self) -> u32 {
+        u32_at(&self.0, 28)
+    }
+
+    fn set_size(&mut self, size: u32) {
+        self.0[28..32].copy_from_slice(&size.to_le_bytes());
+    }
+
+    /// Set the modification and access time to now.
+    fn touch(&mut self) {
+        let (date, time) = fat_timestamp();
+        self.0[18..20].copy_from_slice(&date.to_le_bytes());
+        self.0[22..24].copy_from_slice(&time.to_le_bytes());
+        self.0[24..26].copy_from_slice(&date.to_le_bytes());
+    }
+
+    fn stat(&self) -> Stat {
+        let mode = if self.is_dir() { MODE_DIR } else { MODE_FILE };
+        Stat {
+            created_time: unix_time(u16_at(&self.0, 16), u16_at(&self.0, 14)),
+            modified_time: unix_time(u16_at(&self.0, 24), u16_at(&self.0, 22)),
+            accessed_time: unix_time(u16_at(&self.0, 18), 0),
+            ..Stat::new(Mode::new(mode), self.size() as usize)
+        }
+    }
+
+    /// The short name as it is displayed (e.g. "README.TXT")
+    fn display_name(&self) -> String {
+        let case = self.0[12];
+        let convert = |part: &[u8], lower_case: bool| -> String {
+            part.iter()
+                .map(|&byte| char::from(byte))
+                .map(|char| if lower_case { char.to_ascii_lowercase() } else { char })
+                .collect::<String>()
+                .trim_end()
+                .into()
+        };
+
+        let mut short_name = self.short_name();
+        if short_name[0] == ESCAPED_E5 {
+            short_name[0] = DELETED;
+        }
+        let base = convert(&short_name[..8], case & LOWER_CASE_BASE != 0);
+        let extension = convert(&short_name[8..], case & LOWER_CASE_EXTENSION != 0);
+        match extension.is_empty() {
+            true => base,
+            false => format!("{base}.{extension}"),
+        }
+    }
+}
+
+/// A parsed directory entry
+struct Entry {
+    name: String,
+    short: ShortEntry,
+    /// Index of the short entry within the directory
+    slot: usize,
+    /// Index of the first long name entry (or of the short entry, if there is no long name)
+    first_slot: usize,
+}
+
+/// Long name entries being collected (they precede the short entry, starting with the end of the name)
+struct LongName {
+    /// Index of the first long name entry
+    start: usize,
+    units: Vec<u16>,
+    checksum: u8,
+    /// Ordinal of the next expected entry (0 after the entry with the start of the name)
+    next_ordinal: u8,
+}
+
+/// Parse the entries of a directory (deleted entries, volume labels, `.` and `..` are skipped).
+/// Long names are only used, if they are complete and belong to the short entry following them.
+fn parse_entries(slots: &[[u8; DIR_ENTRY_SIZE]]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut long_name: Option<LongName> = None;
+
+    for (index, slot) in slots.iter().enumerate() {
+        match slot[0] {
+            END_OF_DIR => break,
+            DELETED => {
+                long_name = None;
+                continue;
+            }
+            _ => {}
+        }
+
+        if slot[11] & ATTR_LONG_NAME_MASK == ATTR_LONG_NAME {
+            let ordinal = slot[0] & !LAST_LONG_ENTRY;
+            let units = LONG_NAME_OFFSETS.map(|offset| u16_at(slot, offset));
+            long_name = match long_name.take() {
+                _ if slot[0] & LAST_LONG_ENTRY != 0 && ordinal != 0 => {
+                    Some(LongName { start: index, units: units.to_vec(), checksum: slot[13], next_ordinal: ordinal - 1 })
+                }
+                Some(mut long_name) if ordinal != 0 && ordinal == long_name.next_ordinal && slot[13] == long_name.checksum => {
+                    long_name.units.splice(0..0, units);
+                    long_name.next_ordinal -= 1;
+                    Some(long_name)
+                }
+                _ => None,
+            };
+            continue;
+        }
+
+        let long_name = long_name.take();
+        if slot[11] & ATTR_VOLUME_ID != 0 {
+            continue;
+        }
+
+        let short = ShortEntry(*slot);
+        let (name, first_slot) = match long_name {
+            Some(long_name) if long_name.next_ordinal == 0 && long_name.checksum == checksum(&short.short_name()) => {
+                let end = long_name.units.iter().position(|&unit| unit == 0).unwrap_or(long_name.units.len());
+                let name = char::decode_utf16(long_name.units[..end].iter().copied())
+                    .map(|char| char.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect();
+                (name, long_name.start)
+            }
+            _ => (short.display_name(), index),
+        };
+        if name == "." || name == ".." {
+            continue;
+        }
+
+        entries.push(Entry { name, short, slot: index, first_slot });
+    }
+
+    entries
+}
+
+/// Checksum of a short name, stored in the long name entries belonging to it
+fn checksum(short_name: &[u8; 11]) -> u8 {
+    short_name.iter().fold(0u8, |sum, &byte| sum.rotate_right(1).wrapping_add(byte))
+}
+
+/// Check, whether `name` may be used for a new entry.
+fn check_name(name: &str) -> Result<(), Errno> {
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.encode_utf16().count() > MAX_NAME_LENGTH
+        || name.chars().any(|char| char.is_control() || INVALID_NAME_CHARS.contains(char))
+    {
+        return Err(Errno::EINVAL);
+    }
+    Ok(())
+}
+
+/// Generate a short name for `name`, that is not used by any of `entries`
+/// (e.g. "README.TXT" for "readme.txt" and "LONGFI~1.TXT" for "Long file name.txt").
+fn generate_short_name(name: &str, entries: &[Entry]) -> [u8; 11] {
+    let trimmed = name.trim_start_matches('.');
+    let (base, extension) = trimmed.rsplit_once('.').unwrap_or((trimmed, ""));
+    let (base, lossy_base) = short_name_part(base);
+    let (mut extension, lossy_extension) = short_name_part(extension);
+    let lossy = trimmed.len() != name.len() || lossy_base || lossy_extension;
+    let needs_tail = lossy || base.is_empty() || base.len() > 8 || extension.len() > 3;
+    extension.truncate(3);
+
+    let build = |base: &[u8]| -> [u8; 11] {
+        let mut short_name = [b' '; 11];
+        short_name[..base.len()].copy_from_slice(base);
+        short_name[8..8 + extension.len()].copy_from_slice(&extension);
+        short_name
+    };
+    let is_free = |short_name: &[u8; 11]| !entries.iter().any(|entry| entry.short.short_name() == *short_name);
+
+    if !needs_tail && is_free(&build(&base)) {
+        return build(&base);
+    }
+    (1..)
+        .map(|number| {
+            let tail = format!("~{number}");
+            let mut tailed = base[..base.len().min(8 - tail.len())].to_vec();
+            tailed.extend_from_slice(tail.as_bytes());
+            build(&tailed)
+        })
+        .find(is_free)
+        .unwrap()
+}
+
+/// Convert the base name or extension of a long name for a short name.
+/// Also returns, whether characters have been dropped or replaced.
+fn short_name_part(part: &str) -> (Vec<u8>, bool) {
+    let mut lossy = false;
+    let mut converted = Vec::new();
+    for char in part.chars() {
+        match char {
+            ' ' | '.' => lossy = true,
+            _ if char.is_ascii_alphanumeric() => converted.push(char.to_ascii_uppercase() as u8),
+            _ if SHORT_NAME_SPECIAL_CHARS.contains(char) => converted.push(char as u8),
+            _ => {
+                converted.push(b'_');
+                lossy = true;
+            }
+        }
+    }
+
+    (converted, lossy)
+}
+
+/// Long name entries for `name` (in the order they are stored, before the short entry called `short_name`)
+fn long_name_entries(name: &str, short_name: &[u8; 11]) -> Vec<[u8; DIR_ENTRY_SIZE]> {
+    let mut units: Vec<u16> = name.encode_utf16().collect();
+    // der Name wird mit 0 abgeschlossen und mit 0xffff aufgefüllt, wenn er nicht genau passt
+    if units.len() % LONG_NAME_CHARS != 0 {
+        units.push(0);
+        units.resize(units.len().next_multiple_of(LONG_NAME_CHARS), 0xffff);
+    }
+
+    let checksum = checksum(short_name);
+    let count = units.len() / LONG_NAME_CHARS;
+    (0..count).rev()
+        .map(|index| {
+            let mut slot = [0u8; DIR_ENTRY_SIZE];
+            slot[0] = (index + 1) as u8 | if index + 1 == count { LAST_LONG_ENTRY } else { 0 };
+            slot[11] = ATTR_LONG_NAME;
+            slot[13] = checksum;
+            for (unit, offset) in units[index * LONG_NAME_CHARS..(index + 1) * LONG_NAME_CHARS].iter().zip(LONG_NAME_OFFSETS) {
+                slot[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+            }
+            slot
+        })
+        .collect()
+}
+
+/// Current date and time in the FAT format (UTC, as the time zone is unknown)
+fn fat_timestamp() -> (u16, u16) {
+    let now = wallclock::now();
+    match DateTime::from_timestamp(now.seconds, 0) {
+        Some(time) if (DOS_EPOCH_YEAR..DOS_EPOCH_YEAR + 128).contains(&time.year()) => {
+            let date = ((time.year() - DOS_EPOCH_YEAR) as u16) << 9 | (time.month() as u16) << 5 | time.day() as u16;
+            let time = (time.hour() as u16) << 11 | (time.minute() as u16) << 5 | (time.second() / 2) as u16;
+            (date, time)
+        }
+        _ => (DOS_EPOCH_DATE, 0),
+    }
+}
+
+/// Convert a FAT date and time into seconds since the Unix epoch (0 for invalid dates).
+fn unix_time(date: u16, time: u16) -> u64 {
+    NaiveDate::from_ymd_opt(DOS_EPOCH_YEAR + (date >> 9) as i32, ((date >> 5) & 0xf) as u32, (date & 0x1f) as u32)
+        .and_then(|date| date.and_hms_opt((time >> 11) as u32, ((time >> 5) & 0x3f) as u32, ((time & 0x1f) * 2) as u32))
+        .map_or(0, |time| time.and_utc().timestamp().max(0) as u64)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+struct FatDir {
+    volume: Arc<Volume>,
+    location: DirLocation,
+    /// Entries read by the last `readdir()` with index 0
+    entries: Mutex<Vec<DirEntry>>,
+}
+
+impl FatDir {
+    fn new(volume: Arc<Volume>, location: DirLocation) -> FatDir {
+        FatDir { volume, location, entries: Mutex::new(Vec::new()) }
+    }
+
+    fn object(&self, entry: &Entry) -> NamedObject {
+        let volume = Arc::clone(&self.volume);
+        match entry.short.is_dir() {
+            true => (Arc::new(FatDir::new(volume, DirLocation::Clusters(entry.short.first_cluster()))) as Arc<dyn DirectoryObject>).into(),
+            false => (Arc::new(FatFile { volume, parent: self.location, slot: entry.slot }) as Arc<dyn FileObject>).into(),
+        }
+    }
+
+    /// Create a file or directory called `name`.
+    fn create(&self, name: &str, directory: bool) -> Result<NamedObject, Errno> {
+        check_name(name)?;
+        let volume = &self.volume;
+        let _guard = volume.lock.lock();
+
+        let slots = volume.read_dir(self.location)?;
+        let entries = parse_entries(&slots);
+        if entries.iter().any(|entry| entry.name.eq_ignore_ascii_case(name)) {
+            return Err(Errno::EEXIST);
+        }
+
+        // ein neues Verzeichnis bekommt seinen ersten Cluster mit "." und "..", bevor es eingetragen wird
+        let first_cluster = match directory {
+            true => self.create_dir_cluster()?,
+            false => 0,
+        };
+        let attributes = if directory { ATTR_DIRECTORY } else { ATTR_ARCHIVE };
+        let short = ShortEntry::new(generate_short_name(name, &entries), attributes, first_cluster);
+
+        let mut new = Vec::new();
+        if short.display_name() != name {
+            new = long_name_entries(name, &short.short_name());
+        }
+        new.push(short.0);
+
+        match volume.insert_slots(self.location, &slots, &new) {
+            Ok(slot) => {
+                volume.device.flush();
+                Ok(self.object(&Entry { name: name.into(), short, slot, first_slot: slot + 1 - new.len() }))
+            }
+            Err(e) => {
+                if directory {
+                    let _ = volume.free_chain(first_cluster);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Allocate the first cluster of a new subdirectory and write its `.` and `..` entries.
+    fn create_dir_cluster(&self) -> Result<u32, Errno> {
+        let volume = &self.volume;
+        let cluster = volume.allocate_cluster(None)?;
+        // ".." zeigt mit Cluster 0 auf das Wurzelverzeichnis
+        let parent = match self.location {
+            DirLocation::Clusters(cluster) if volume.fat_type == FatType::Fat16 || cluster != volume.root_cluster => cluster,
+            _ => 0,
+        };
+
+        let dot = ShortEntry::new(*b".          ", ATTR_DIRECTORY, cluster);
+        let dot_dot = ShortEntry::new(*b"..         ", ATTR_DIRECTORY, parent);
+        let result = volume.write_slots(DirLocation::Clusters(cluster), 0, &[dot.0, dot_dot.0]);
+        if let Err(e) = result {
+            let _ = volume.free_chain(cluster);
+            return Err(e);
+        }
+
+        Ok(cluster)
+    }
+}
+
+impl DirectoryObject for FatDir {
+    fn lookup(&self, name: &str) -> Result<NamedObject, Errno> {
+        let _guard = self.volume.lock.lock();
+        let slots = self.volume.read_dir(self.location)?;
+        // FAT unterscheidet nicht zwischen Groß- und Kleinschreibung
+        let entries = parse_entries(&slots);
+        let entry = entries.iter()
+            .find(|entry| entry.name == name)
+            .or_else(|| entries.iter().find(|entry| entry.name.eq_ignore_ascii_case(name)))
+            .ok_or(Errno::ENOENT)?;
+
+        Ok(self.object(entry))
+    }
+
+    fn create_file(&self, name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        self.create(name, false)
+    }
+
+    fn create_dir(&self, name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        self.create(name, true)
+    }
+
+    fn create_pipe(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::ENOTSUP)
+    }
+
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(Stat::new(Mode::new(MODE_DIR), 0))
+    }
+
+    fn rename(&self, _old_name: &str, _new_dir: &Arc<dyn DirectoryObject>, _new_name: &str) -> Result<(), Errno> {
+        Err(Errno::ENOTSUP)
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), Errno> {
+        let volume = &self.volume;
+        let _guard = volume.lock.lock();
+        let slots = volume.read_dir(self.location)?;
+        let entries = parse_entries(&slots);
+        let entry = entries.iter()
+            .find(|entry| entry.name == name)
+            .or_else(|| entries.iter().find(|entry| entry.name.eq_ignore_ascii_case(name)))
+            .ok_or(Errno::ENOENT)?;
+
+        let first_cluster = entry.short.first_cluster();
+        if entry.short.is_dir() && !parse_entries(&volume.read_dir(DirLocation::Clusters(first_cluster))?).is_empty() {
+            return Err(Errno::ENOTEMPTY);
+        }
+
+        // der Eintrag wird mit seinem langen Namen als gelöscht markiert, bevor die Cluster freigegeben werden
+        let deleted: Vec<[u8; DIR_ENTRY_SIZE]> = slots[entry.first_slot..=entry.slot].iter()
+            .map(|slot| {
+                let mut slot = *slot;
+                slot[0] = DELETED;
+                slot
+            })
+            .collect();
+        volume.write_slots(self.location, entry.first_slot, &deleted)?;
+        if first_cluster != 0 {
+            volume.free_chain(first_cluster)?;
+        }
+
+        volume.device.flush();
+        Ok(())
+    }
+
+    fn readdir(&self, index: usize) -> Result<Option<DirEntry>, Errno> {
+        let mut entries = self.entries.lock();
+        // a new listing starts with index 0
+        if index == 0 {
+            let _guard = self.volume.lock.lock();
+            let slots = self.volume.read_dir(self.location)?;
+            *entries = parse_entries(&slots)
+                .into_iter()
+                .map(|entry| DirEntry {
+                    file_type: if entry.short.is_dir() { FileType::Directory } else { FileType::Regular },
+                    name: entry.name,
+                })
+                .collect();
+        }
+        Ok(entries.get(index).cloned())
+    }
+}
+
+impl fmt::Debug for FatDir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FatDir").field("location", &self.location).finish()
+    }
+}
+
+struct FatFile {
+    volume: Arc<Volume>,
+    /// The directory containing the entry of the file and the index of the entry
+    parent: DirLocation,
+    slot: usize,
+}
+
+impl FatFile {
+    fn entry(&self) -> Result<ShortEntry, Errno> {
+        self.volume.read_slot(self.parent, self.slot).map(ShortEntry)
+    }
+}
+
+impl FileObject for FatFile {
+    fn stat(&self) -> Result<Stat, Errno> {
+        let _guard = self.volume.lock.lock();
+        Ok(self.entry()?.stat())
+    }
+
+    fn read(&self, buf: &mut [u8], offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        let volume = &self.volume;
+        let _guard = volume.lock.lock();
+        let entry = self.entry()?;
+        let size = entry.size() as usize;
+        if offset >= size {
+            return Ok(0);
+        }
+
+        let length = buf.len().min(size - offset);
+        let cluster_size = volume.cluster_size();
+        let chain = volume.cluster_chain(entry.first_cluster())?;
+        let mut buffer = vec![0u8; cluster_size];
+        let mut done = 0;
+        while done < length {
+            let position = offset + done;
+            let cluster = *chain.get(position / cluster_size).ok_or(Errno::EIO)?;
+            let start = position % cluster_size;
+            let part = (cluster_size - start).min(length - done);
+
+            volume.read_cluster(cluster, &mut buffer)?;
+            buf[done..done + part].copy_from_slice(&buffer[start..start + part]);
+            done += part;
+        }
+
+        Ok(length)
+    }
+
+    fn write(&self, buf: &[u8], offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        let volume = &self.volume;
+        let _guard = volume.lock.lock();
+        let mut entry = self.entry()?;
+        let size = entry.size() as usize;
+        // FAT speichert die Größe in 32 Bit
+        let end = offset.checked_add(buf.len()).filter(|&end| end <= u32::MAX as usize).ok_or(Errno::EINVAL)?;
+
+        // a gap between the end of the file and `offset` is filled with zeros
+        let mut result = Ok(());
+        if offset > size {
+            result = volume.write_data(&mut entry, size, &vec![0u8; offset - size]);
+        }
+        result = result.and_then(|_| volume.write_data(&mut entry, offset, buf));
+        if result.is_ok() {
+            entry.set_size(end.max(size) as u32);
+        }
+
+        // the entry is written even on errors, as clusters may have been allocated
+        entry.touch();
+        volume.write_slots(self.parent, self.slot, &[entry.0])?;
+        volume.device.flush();
+        result.map(|_| buf.len())
+    }
+
+    fn truncate(&self, size: usize) -> Result<(), Errno> {
+        if size > u32::MAX as usize {
+            return Err(Errno::EINVAL);
+        }
+        let volume = &self.volume;
+        let _guard = volume.lock.lock();
+        let mut entry = self.entry()?;
+        let current_size = entry.size() as usize;
+
+        let result = if size > current_size {
+            volume.write_data(&mut entry, current_size, &vec![0u8; size - current_size])
+        } else {
+            // the clusters behind the new end of the file are freed
+            let chain = volume.cluster_chain(entry.first_cluster())?;
+            match size.div_ceil(volume.cluster_size()) {
+                0 => {
+                    entry.set_first_cluster(0);
+                    chain.first().map_or(Ok(()), |&first| volume.free_chain(first))
+                }
+                keep if keep < chain.len() => volume.set_fat_entry(chain[keep - 1], volume.end_of_chain())
+                    .and_then(|_| volume.free_chain(chain[keep])),
+                _ => Ok(()),
+            }
+        };
+        if result.is_ok() {
+            entry.set_size(size as u32);
+        }
+
+        entry.touch();
+        volume.write_slots(self.parent, self.slot, &[entry.0])?;
+        volume.device.flush();
+        result
+    }
+}
+
+impl fmt::Debug for FatFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FatFile").field("parent", &self.parent).field("slot", &self.slot).finish()
+    }
+}
@@ -9,10 +9,12 @@
 use alloc::string::String;
 use alloc::vec::Vec;
 use alloc::sync::Arc;
-use super::api::ROOT;
+use super::api::{self, ROOT};
+use super::stat::{check_access, ACCESS_EXECUTE};
 use super::traits;
 use super::traits::{NamedObject, DirectoryObject};
 use syscall::return_vals::Errno;
+use crate::process_manager;
 
 /// Resolves an absolute path into an `DirectoryLike`
 pub(super) fn lookup_dir(path: &String) -> Result<Arc<dyn DirectoryObject>, Errno> {
@@ -24,14 +26,40 @@ pub(super) fn lookup_dir(path: &String) -> Result<Arc<dyn DirectoryObject>, Errn
 }
 
 /// Resolves absolute `path` into a named object. \
+/// If the current process is sandboxed, `path` is relative to the sandbox's root directory. \
 /// Returns `Ok(NamedObject)` or `Err`
-pub(super) fn lookup_named_object(mut path: &str) -> Result<NamedObject, Errno> {
-    let mut found_named_object;
+pub(super) fn lookup_named_object(path: &str) -> Result<NamedObject, Errno> {
+    resolve_checked(translate_path(path)?.as_str())
+}
 
+/// Translates `path` into the absolute path, that `resolve()` expects,
+/// taking the sandbox of the current process into account.
+pub(super) fn translate_path(mut path: &str) -> Result<String, Errno> {
     if path.starts_with("./") {
         path = &path[2..];
     }
 
+    let sandbox = process_manager().read().current_process().sandbox();
+    sandbox.translate_path(path)
+}
+
+/// Resolves absolute `path` into a named object, ignoring the sandbox and the permissions of the current process
+/// (e.g. for the kernel, loading a program). \
+/// Returns `Ok(NamedObject)` or `Err`
+pub(super) fn resolve(path: &str) -> Result<NamedObject, Errno> {
+    resolve_in(path, false)
+}
+
+/// Like `resolve()`, but the current process needs the right to search (execute) each directory on the way
+/// (`path` must already be translated by `translate_path()`). \
+/// Returns `Ok(NamedObject)` or `Err` (`EACCES`, if a directory may not be searched)
+pub(super) fn resolve_checked(path: &str) -> Result<NamedObject, Errno> {
+    resolve_in(path, true)
+}
+
+fn resolve_in(path: &str, check_search: bool) -> Result<NamedObject, Errno> {
+    let mut found_named_object;
+
     if check_absolute_path(path) {
         if path == "/" {
             found_named_object = traits::as_named_object(ROOT.get().unwrap().root_dir());
@@ -42,15 +70,28 @@ pub(super) fn lookup_named_object(mut path: &str) -> Result<NamedObject, Errno>
 
         // get root directory and open the desired file
         let mut current_dir = ROOT.get().unwrap().root_dir();
+        let mut current_path = String::new();
         let mut len = components.len();
         let mut found;
         for component in &components {
+            if check_search {
+                check_access(&current_dir.stat()?, ACCESS_EXECUTE)?;
+            }
             found = current_dir.lookup(component);
             if found.is_err() {
                 return Err(Errno::ENOENT);
             }
             found_named_object = found.unwrap();
 
+            // a mounted file system replaces the directory it is mounted on
+            current_path.push('/');
+            current_path.push_str(component);
+            if found_named_object.is_dir() {
+                if let Some(root) = api::mounted_root(&current_path) {
+                    found_named_object = traits::as_named_object(root);
+                }
+            }
+
             // if not last component, this must be a directory
             if len > 1 {
                 if !found_named_object.is_dir() {
@@ -1,7 +1,13 @@
 pub mod api;
 pub mod stat;
 
+mod archive;
+mod devfs;
+mod fat;
+mod ninep;
 mod open_objects;
+mod procfs;
+mod readonly;
 mod tmpfs;
 mod lookup;
 mod traits;
\ No newline at end of file
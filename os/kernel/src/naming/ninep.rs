@@ -0,0 +1,620 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: ninep                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Client for the 9P2000.L protocol, giving access to a directory shared   ║
+   ║ by the host via virtio-9p or exported by a server reachable via TCP.    ║
+   ║ Each named object holds a fid (a reference to a file on the server),    ║
+   ║ which is clunked when the object is dropped. Files are opened lazily    ║
+   ║ on their first read or write. All requests are sent synchronously, one  ║
+   ║ at a time.                                                              ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use super::stat::{Mode, Stat, MODE_DIR, MODE_FILE};
+use super::traits::{DirectoryObject, FileObject, FileSystem, NamedObject};
+use crate::device::virtio::Virtio9p;
+use crate::network::tcp_stream::TcpStream;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::fmt;
+use core::sync::atomic::{AtomicU32, Ordering};
+use naming::shared_types::{DirEntry, FileType, OpenOptions};
+use smoltcp::wire::IpAddress;
+use spin::Mutex;
+use syscall::return_vals::Errno;
+
+const VERSION: &str = "9P2000.L";
+/// Size of the header of read and write messages: size[4] type[1] tag[2] fid[4] offset[8] count[4]
+const IO_HEADER_SIZE: u32 = 24;
+/// Largest message we ask for (the host may choose a smaller size)
+const MAX_MESSAGE_SIZE: u32 = 8192 + IO_HEADER_SIZE;
+const NO_TAG: u16 = 0xffff;
+const NO_FID: u32 = 0xffff_ffff;
+const ROOT_FID: u32 = 0;
+
+// Message types (the response to a request always has the type of the request + 1)
+const RLERROR: u8 = 7;
+const TLOPEN: u8 = 12;
+const TLCREATE: u8 = 14;
+const TGETATTR: u8 = 24;
+const TSETATTR: u8 = 26;
+const TRENAMEAT: u8 = 74;
+const TUNLINKAT: u8 = 76;
+const TREADDIR: u8 = 40;
+const TMKDIR: u8 = 72;
+const TVERSION: u8 = 100;
+const TATTACH: u8 = 104;
+const TWALK: u8 = 110;
+const TREAD: u8 = 116;
+const TWRITE: u8 = 118;
+const TCLUNK: u8 = 120;
+
+// Linux flags and modes, as expected by 9P2000.L
+const O_RDONLY: u32 = 0o0;
+const O_WRONLY: u32 = 0o1;
+const O_CREAT: u32 = 0o100;
+const O_EXCL: u32 = 0o200;
+const O_DIRECTORY: u32 = 0o200000;
+const AT_REMOVEDIR: u32 = 0x200;
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const FILE_PERMISSIONS: u32 = 0o644;
+const DIR_PERMISSIONS: u32 = 0o755;
+const PERMISSION_BITS: u32 = 0o777;
+const DT_FIFO: u8 = 1;
+const DT_DIR: u8 = 4;
+const DT_LNK: u8 = 10;
+
+/// Type of a qid, which refers to a directory
+const QID_DIR: u8 = 0x80;
+/// `Tgetattr`: mode, size and times
+const GETATTR_BASIC: u64 = 0x7ff;
+/// `Tsetattr`: only change the size
+const SETATTR_SIZE: u32 = 0x8;
+/// `Tsetattr`: change mode, uid and gid
+const SETATTR_OWNER: u32 = 0x7;
+
+/// A directory shared by the host or a 9P server, mounted with 9P2000.L.
+pub struct NinePFs {
+    root_dir: Arc<NinePDir>,
+}
+
+impl NinePFs {
+    /// Negotiate the protocol version with the host and attach to the root of the virtio-9p share.
+    pub fn attach(device: Arc<Virtio9p>) -> Result<NinePFs, Errno> {
+        Self::attach_via(device)
+    }
+
+    /// Connect to the 9P server at `host` and `port` and attach to the root of its export.
+    pub fn connect(host: IpAddress, port: u16) -> Result<NinePFs, Errno> {
+        let stream = TcpStream::connect(host, port)?;
+        Self::attach_via(Arc::new(TcpTransport(Mutex::new(stream))))
+    }
+
+    fn attach_via(transport: Arc<dyn Transport>) -> Result<NinePFs, Errno> {
+        let mut client = Client { transport, message_size: MAX_MESSAGE_SIZE, next_fid: AtomicU32::new(ROOT_FID + 1) };
+
+        let mut response = client.rpc(Message::new(TVERSION, NO_TAG).u32(MAX_MESSAGE_SIZE).string(VERSION))?;
+        let message_size = response.u32()?;
+        if response.string()? != VERSION {
+            return Err(Errno::ENOTSUP);
+        }
+        client.message_size = message_size.min(MAX_MESSAGE_SIZE);
+
+        let mut response = client.rpc(Message::new(TATTACH, 0).u32(ROOT_FID).u32(NO_FID).string("root").string("").u32(0))?;
+        let qid = response.qid()?;
+        if qid.kind & QID_DIR == 0 {
+            return Err(Errno::ENOTDIR);
+        }
+
+        let node = Node { client: Arc::new(client), fid: ROOT_FID };
+        Ok(NinePFs { root_dir: Arc::new(NinePDir::new(node)) })
+    }
+}
+
+impl FileSystem for NinePFs {
+    fn root_dir(&self) -> Arc<dyn DirectoryObject> {
+        self.root_dir.clone()
+    }
+}
+
+/// Carries messages to the server and its responses back
+trait Transport: Send + Sync {
+    /// Send the message `request` and wait for the response.
+    /// Returns the number of bytes written into `response`.
+    fn request(&self, request: &[u8], response: &mut [u8]) -> Result<usize, Errno>;
+}
+
+impl Transport for Virtio9p {
+    fn request(&self, request: &[u8], response: &mut [u8]) -> Result<usize, Errno> {
+        Virtio9p::request(self, request, response)
+    }
+}
+
+/// A TCP connection to the server. Messages are framed by their size field.
+struct TcpTransport(Mutex<TcpStream>);
+
+impl Transport for TcpTransport {
+    fn request(&self, request: &[u8], response: &mut [u8]) -> Result<usize, Errno> {
+        // die Verbindung bleibt gesperrt, bis die Antwort vollständig gelesen ist
+        let stream = self.0.lock();
+        stream.write_all(request)?;
+
+        stream.read_exact(&mut response[..4])?;
+        let size = u32::from_le_bytes(response[..4].try_into().unwrap()) as usize;
+        if size < 7 || size > response.len() {
+            return Err(Errno::EIO);
+        }
+        stream.read_exact(&mut response[4..size])?;
+        Ok(size)
+    }
+}
+
+/// Sends requests to the server and allocates fids
+struct Client {
+    transport: Arc<dyn Transport>,
+    /// Negotiated maximum size of a message
+    message_size: u32,
+    next_fid: AtomicU32,
+}
+
+/// Unique identifier of a file on the host
+struct Qid {
+    kind: u8,
+}
+
+impl Client {
+    /// Send `request` and return the body of the response.
+    fn rpc(&self, request: Message) -> Result<Reader, Errno> {
+        let request_type = request.0[4];
+        let mut response = vec![0u8; self.message_size as usize];
+        let len = self.transport.request(&request.finish(), &mut response)?;
+        response.truncate(len);
+
+        let mut reader = Reader { data: response, position: 0 };
+        let size = reader.u32()? as usize;
+        let response_type = reader.u8()?;
+        reader.u16()?; // tag
+        if size > len {
+            return Err(Errno::EIO);
+        }
+        match response_type {
+            RLERROR => Err(errno(reader.u32()?)),
+            _ if response_type == request_type + 1 => Ok(reader),
+            _ => Err(Errno::EIO),
+        }
+    }
+
+    fn allocate_fid(&self) -> u32 {
+        self.next_fid.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Walk from `fid` to `names` and return a new fid for the result (an empty walk clones `fid`).
+    fn walk(&self, fid: u32, names: &[&str]) -> Result<(u32, Option<Qid>), Errno> {
+        let new_fid = self.allocate_fid();
+        let mut request = Message::new(TWALK, 0).u32(fid).u32(new_fid).u16(names.len() as u16);
+        for name in names {
+            request = request.string(name);
+        }
+
+        let mut response = self.rpc(request)?;
+        let count = response.u16()? as usize;
+        // if not all names could be walked, the new fid is not created
+        if count < names.len() {
+            return Err(Errno::ENOENT);
+        }
+        let mut qid = None;
+        for _ in 0..count {
+            qid = Some(response.qid()?);
+        }
+        Ok((new_fid, qid))
+    }
+
+    /// Open a new fid for `fid` with the Linux open `flags`.
+    fn open(&self, fid: u32, flags: u32) -> Result<u32, Errno> {
+        let (new_fid, _) = self.walk(fid, &[])?;
+        if let Err(e) = self.rpc(Message::new(TLOPEN, 0).u32(new_fid).u32(flags)) {
+            self.clunk(new_fid);
+            return Err(e);
+        }
+        Ok(new_fid)
+    }
+
+    fn clunk(&self, fid: u32) {
+        let _ = self.rpc(Message::new(TCLUNK, 0).u32(fid));
+    }
+
+    fn getattr(&self, fid: u32) -> Result<Stat, Errno> {
+        let mut response = self.rpc(Message::new(TGETATTR, 0).u32(fid).u64(GETATTR_BASIC))?;
+        response.u64()?; // valid
+        response.qid()?;
+        let mode = response.u32()?;
+        let uid = response.u32()?;
+        let gid = response.u32()?;
+        response.skip(8 + 8)?; // nlink, rdev
+        let size = response.u64()? as usize;
+        response.skip(8 + 8)?; // blksize, blocks
+        let accessed_time = response.u64()?;
+        response.u64()?;
+        let modified_time = response.u64()?;
+        response.u64()?;
+        let created_time = response.u64()?;
+
+        let permissions = mode & PERMISSION_BITS;
+        let mode = if mode & S_IFMT == S_IFDIR { MODE_DIR } else { MODE_FILE };
+        Ok(Stat { mode: Mode::new(mode), size, created_time, modified_time, accessed_time, permissions, uid, gid })
+    }
+
+    /// Change the attributes selected by `valid` (the others are ignored).
+    fn setattr(&self, fid: u32, valid: u32, permissions: u32, uid: u32, gid: u32, size: usize) -> Result<(), Errno> {
+        // mode, uid, gid, size and the times (which are never changed)
+        let request = Message::new(TSETATTR, 0).u32(fid).u32(valid).u32(permissions).u32(uid).u32(gid).u64(size as u64)
+            .u64(0).u64(0).u64(0).u64(0);
+        self.rpc(request).map(|_| ())
+    }
+
+    /// Read at most one message worth of data.
+    fn read(&self, fid: u32, offset: usize, buf: &mut [u8]) -> Result<usize, Errno> {
+        let count = buf.len().min((self.message_size - IO_HEADER_SIZE) as usize);
+        let mut response = self.rpc(Message::new(TREAD, 0).u32(fid).u64(offset as u64).u32(count as u32))?;
+        let data = response.bytes()?;
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Ok(len)
+    }
+
+    /// Write at most one message worth of data.
+    fn write(&self, fid: u32, offset: usize, buf: &[u8]) -> Result<usize, Errno> {
+        let count = buf.len().min((self.message_size - IO_HEADER_SIZE) as usize);
+        let request = Message::new(TWRITE, 0).u32(fid).u64(offset as u64).u32(count as u32).raw(&buf[..count]);
+        Ok(self.rpc(request)?.u32()? as usize)
+    }
+}
+
+/// A fid together with the client it belongs to
+struct Node {
+    client: Arc<Client>,
+    fid: u32,
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        self.client.clunk(self.fid);
+    }
+}
+
+struct NinePDir {
+    node: Node,
+    /// Entries read by the last `readdir()` with index 0
+    entries: Mutex<Vec<DirEntry>>,
+}
+
+impl NinePDir {
+    fn new(node: Node) -> NinePDir {
+        NinePDir { node, entries: Mutex::new(Vec::new()) }
+    }
+
+    /// Read all entries of the directory from the host.
+    fn read_entries(&self) -> Result<Vec<DirEntry>, Errno> {
+        let client = &self.node.client;
+        let fid = client.open(self.node.fid, O_RDONLY | O_DIRECTORY)?;
+        let result = read_dir_entries(client, fid);
+        client.clunk(fid);
+        result
+    }
+}
+
+/// Read all entries of the opened directory `fid`.
+fn read_dir_entries(client: &Client, fid: u32) -> Result<Vec<DirEntry>, Errno> {
+    let count = client.message_size - IO_HEADER_SIZE;
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    loop {
+        let mut response = client.rpc(Message::new(TREADDIR, 0).u32(fid).u64(offset).u32(count))?;
+        let data = response.bytes()?;
+        if data.is_empty() {
+            return Ok(entries);
+        }
+
+        let mut reader = Reader { data: data.to_vec(), position: 0 };
+        while reader.position < reader.data.len() {
+            let (entry, next) = reader.dir_entry()?;
+            offset = next;
+            entries.extend(entry);
+        }
+    }
+}
+
+impl DirectoryObject for NinePDir {
+    fn lookup(&self, name: &str) -> Result<NamedObject, Errno> {
+        let client = &self.node.client;
+        let (fid, qid) = client.walk(self.node.fid, &[name])?;
+        let node = Node { client: client.clone(), fid };
+
+        match qid {
+            Some(qid) if qid.kind & QID_DIR != 0 => Ok((Arc::new(NinePDir::new(node)) as Arc<dyn DirectoryObject>).into()),
+            _ => Ok((Arc::new(NinePFile::new(node)) as Arc<dyn FileObject>).into()),
+        }
+    }
+
+    fn create_file(&self, name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        let client = &self.node.client;
+        // the new fid is opened for the created file, but files are opened lazily on access
+        let (fid, _) = client.walk(self.node.fid, &[])?;
+        let flags = O_WRONLY | O_CREAT | O_EXCL;
+        let result = client.rpc(Message::new(TLCREATE, 0).u32(fid).string(name).u32(flags).u32(FILE_PERMISSIONS).u32(0));
+        client.clunk(fid);
+        result?;
+        self.lookup(name)
+    }
+
+    fn create_dir(&self, name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        let client = &self.node.client;
+        client.rpc(Message::new(TMKDIR, 0).u32(self.node.fid).string(name).u32(DIR_PERMISSIONS).u32(0))?;
+        self.lookup(name)
+    }
+
+    fn create_pipe(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::ENOTSUP)
+    }
+
+    fn stat(&self) -> Result<Stat, Errno> {
+        self.node.client.getattr(self.node.fid)
+    }
+
+    fn readdir(&self, index: usize) -> Result<Option<DirEntry>, Errno> {
+        let mut entries = self.entries.lock();
+        // a new listing starts with index 0
+        if index == 0 {
+            *entries = self.read_entries()?;
+        }
+        Ok(entries.get(index).cloned())
+    }
+
+    fn rename(&self, old_name: &str, new_dir: &Arc<dyn DirectoryObject>, new_name: &str) -> Result<(), Errno> {
+        let new_dir: &dyn Any = new_dir.as_ref();
+        // the host can only move files within the same share
+        let new_dir = new_dir.downcast_ref::<NinePDir>()
+            .filter(|new_dir| Arc::ptr_eq(&new_dir.node.client, &self.node.client))
+            .ok_or(Errno::ENOTSUP)?;
+
+        let request = Message::new(TRENAMEAT, 0).u32(self.node.fid).string(old_name).u32(new_dir.node.fid).string(new_name);
+        self.node.client.rpc(request).map(|_| ())
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), Errno> {
+        // Verzeichnisse müssen dem Host als solche angekündigt werden
+        let client = &self.node.client;
+        let (fid, qid) = client.walk(self.node.fid, &[name])?;
+        client.clunk(fid);
+        let flags = match qid {
+            Some(qid) if qid.kind & QID_DIR != 0 => AT_REMOVEDIR,
+            _ => 0,
+        };
+
+        client.rpc(Message::new(TUNLINKAT, 0).u32(self.node.fid).string(name).u32(flags)).map(|_| ())
+    }
+
+    fn set_attributes(&self, permissions: u32, uid: u32, gid: u32) -> Result<(), Errno> {
+        self.node.client.setattr(self.node.fid, SETATTR_OWNER, permissions, uid, gid, 0)
+    }
+}
+
+impl fmt::Debug for NinePDir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NinePDir").field("fid", &self.node.fid).finish()
+    }
+}
+
+struct NinePFile {
+    node: Node,
+    /// Fids opened for reading and for writing
+    read_fid: Mutex<Option<u32>>,
+    write_fid: Mutex<Option<u32>>,
+}
+
+impl NinePFile {
+    fn new(node: Node) -> NinePFile {
+        NinePFile { node, read_fid: Mutex::new(None), write_fid: Mutex::new(None) }
+    }
+
+    /// Return the fid opened with `flags`, opening it first, if necessary.
+    fn opened(&self, fid: &Mutex<Option<u32>>, flags: u32) -> Result<u32, Errno> {
+        let mut fid = fid.lock();
+        match *fid {
+            Some(fid) => Ok(fid),
+            None => {
+                let opened = self.node.client.open(self.node.fid, flags)?;
+                *fid = Some(opened);
+                Ok(opened)
+            }
+        }
+    }
+}
+
+impl FileObject for NinePFile {
+    fn stat(&self) -> Result<Stat, Errno> {
+        self.node.client.getattr(self.node.fid)
+    }
+
+    fn read(&self, buf: &mut [u8], offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        let fid = self.opened(&self.read_fid, O_RDONLY)?;
+        let mut total = 0;
+        while total < buf.len() {
+            match self.node.client.read(fid, offset + total, &mut buf[total..])? {
+                0 => break,
+                received => total += received,
+            }
+        }
+        Ok(total)
+    }
+
+    fn write(&self, buf: &[u8], offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        let fid = self.opened(&self.write_fid, O_WRONLY)?;
+        let mut total = 0;
+        while total < buf.len() {
+            match self.node.client.write(fid, offset + total, &buf[total..])? {
+                0 => break,
+                written => total += written,
+            }
+        }
+        Ok(total)
+    }
+
+    fn truncate(&self, size: usize) -> Result<(), Errno> {
+        self.node.client.setattr(self.node.fid, SETATTR_SIZE, 0, 0, 0, size)
+    }
+
+    fn set_attributes(&self, permissions: u32, uid: u32, gid: u32) -> Result<(), Errno> {
+        self.node.client.setattr(self.node.fid, SETATTR_OWNER, permissions, uid, gid, 0)
+    }
+}
+
+impl Drop for NinePFile {
+    fn drop(&mut self) {
+        for fid in [self.read_fid.lock().take(), self.write_fid.lock().take()].into_iter().flatten() {
+            self.node.client.clunk(fid);
+        }
+    }
+}
+
+impl fmt::Debug for NinePFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NinePFile").field("fid", &self.node.fid).finish()
+    }
+}
+
+/// A request being assembled (all integers are little endian)
+struct Message(Vec<u8>);
+
+impl Message {
+    /// Start a message with a placeholder for its size.
+    fn new(message_type: u8, tag: u16) -> Message {
+        let mut data = vec![0u8; 4];
+        data.push(message_type);
+        data.extend_from_slice(&tag.to_le_bytes());
+        Message(data)
+    }
+
+    fn u16(mut self, value: u16) -> Message {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u32(mut self, value: u32) -> Message {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u64(mut self, value: u64) -> Message {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn string(self, value: &str) -> Message {
+        self.u16(value.len() as u16).raw(value.as_bytes())
+    }
+
+    fn raw(mut self, data: &[u8]) -> Message {
+        self.0.extend_from_slice(data);
+        self
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let size = self.0.len() as u32;
+        self.0[..4].copy_from_slice(&size.to_le_bytes());
+        self.0
+    }
+}
+
+/// Parses a response
+struct Reader {
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl Reader {
+    fn take(&mut self, len: usize) -> Result<&[u8], Errno> {
+        let data = self.data.get(self.position..self.position + len).ok_or(Errno::EIO)?;
+        self.position += len;
+        Ok(data)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), Errno> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, Errno> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Errno> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, Errno> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Errno> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, Errno> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).to_string())
+    }
+
+    /// Data prefixed with a `u32` length (`Rread` and `Rreaddir`)
+    fn bytes(&mut self) -> Result<&[u8], Errno> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    /// type[1] version[4] path[8]
+    fn qid(&mut self) -> Result<Qid, Errno> {
+        let kind = self.u8()?;
+        self.skip(4 + 8)?;
+        Ok(Qid { kind })
+    }
+
+    /// An entry of `Rreaddir` and the offset of the next one. `.` and `..` are skipped.
+    fn dir_entry(&mut self) -> Result<(Option<DirEntry>, u64), Errno> {
+        self.qid()?;
+        let next = self.u64()?;
+        let file_type = match self.u8()? {
+            DT_DIR => FileType::Directory,
+            DT_FIFO => FileType::NamedPipe,
+            DT_LNK => FileType::Link,
+            _ => FileType::Regular,
+        };
+        let name = self.string()?;
+        if name == "." || name == ".." {
+            return Ok((None, next));
+        }
+        Ok((Some(DirEntry { file_type, name }), next))
+    }
+}
+
+/// Convert a Linux error number into an `Errno`.
+fn errno(linux_errno: u32) -> Errno {
+    match linux_errno {
+        1 | 13 => Errno::EACCES, // EPERM, EACCES
+        2 => Errno::ENOENT,
+        5 => Errno::EIO,
+        9 => Errno::EBADF,
+        12 | 28 => Errno::ENOMEM, // ENOMEM, ENOSPC
+        16 => Errno::EBUSY,
+        17 => Errno::EEXIST,
+        20 => Errno::ENOTDIR,
+        21 | 22 => Errno::EINVAL, // EISDIR, EINVAL
+        30 => Errno::ERDONLY,
+        39 => Errno::ENOTEMPTY,
+        95 => Errno::ENOTSUP,
+        _ => Errno::EUNKN,
+    }
+}
@@ -2,14 +2,21 @@
    ║ Module: open_objects                                                    ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Managing opened objects in a global table (OPEN_OBJECTS). And providing ║
-   ║ all major functions for the naming service.                             ║
+   ║ all major functions for the naming service. Each opened object belongs  ║
+   ║ to the process, that has opened it: other processes can't use its       ║
+   ║ handle and it is closed, when the process exits. An object can be       ║
+   ║ shared with a child process (e.g. as its standard input or output), it  ║
+   ║ is only closed, when the last of its processes has closed it.           ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Michael Schoettner, Univ. Duesseldorf, 23.12.2025               ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
 
 use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::result::Result;
 use core::sync::atomic::{AtomicUsize, Ordering};
@@ -18,9 +25,11 @@ use spin::rwlock::RwLock;
 use log::info;
 
 use super::lookup;
+use super::stat::{check_access, ACCESS_READ, ACCESS_WRITE};
 use super::traits::NamedObject;
+use crate::scheduler;
 use naming::shared_types::{DirEntry, OpenOptions, SeekOrigin};
-use syscall::return_vals::{Errno, SyscallResult};
+use syscall::return_vals::Errno;
 
 /// Max. number of open objetcs
 const MAX_OPEN_OBJECTS: usize = 0x1000;
@@ -40,11 +49,12 @@ pub(super) fn open_object_table_init() {
 pub(super) fn open(path: &str, flags: OpenOptions) -> Result<usize, Errno> {
     info!("open_object::open: open called for path '{}', flags={:?}", path, flags);
     // try to open the named object for the given path
-    let result = lookup::lookup_named_object(path);
-    if result.is_err() {
-        return Err(Errno::ENOENT);
-    }
-    let found_named_object: NamedObject = result.unwrap();
+    let path = lookup::translate_path(path)?;
+    let found_named_object: NamedObject = match lookup::resolve_checked(&path) {
+        Ok(object) => object,
+        Err(Errno::EACCES) => return Err(Errno::EACCES),
+        Err(_) => return Err(Errno::ENOENT),
+    };
 
     // check if path is a directory and this was requested
     if flags.contains(OpenOptions::DIRECTORY) {
@@ -53,19 +63,41 @@ pub(super) fn open(path: &str, flags: OpenOptions) -> Result<usize, Errno> {
         }
     }
 
+    // check the permissions of the current process (every handle, that is not write-only, may be read, see `read()`)
+    let mut access = 0;
+    if !flags.contains(OpenOptions::WRITEONLY) {
+        access |= ACCESS_READ;
+    }
+    if flags.intersects(OpenOptions::READWRITE | OpenOptions::WRITEONLY | OpenOptions::TRUNCATE) {
+        access |= ACCESS_WRITE;
+    }
+    check_access(&found_named_object.stat()?, access)?;
+
     // call the 'open' for pipes specific behavior
     if found_named_object.is_pipe() {
             found_named_object.as_pipe()?.open(flags)?; // ignore return value
     }
 
+    // discard the content of files, if requested (only allowed, if they are opened for writing)
+    if flags.contains(OpenOptions::TRUNCATE) && found_named_object.is_file() {
+        if !flags.intersects(OpenOptions::READWRITE | OpenOptions::WRITEONLY) {
+            return Err(Errno::EACCES);
+        }
+        found_named_object.as_file()?.truncate(0)?;
+    }
+
     // try to allocate an new handle
-    get_open_object_table().allocate_handle(Arc::new(OpenedObject::new(Arc::new(found_named_object), AtomicUsize::new(0), flags)))
+    let (process_id, _) = scheduler().current_ids();
+    get_open_object_table().allocate_handle(Arc::new(OpenedObject::new(Arc::new(found_named_object), path, AtomicUsize::new(0), flags, process_id)))
 }
 
 pub(super) fn write(fh: usize, buf: &[u8]) -> Result<usize, Errno> {
     get_open_object_table().lookup_opened_object(fh).and_then(|opened_object| {
         if opened_object.named_object.is_file() {
             // Make `opened_object` mutable here
+            if !opened_object.options.intersects(OpenOptions::READWRITE | OpenOptions::WRITEONLY) {
+                return Err(Errno::EBADF);
+            }
             return opened_object.named_object.as_file().and_then(|file| {
                 let pos = opened_object.pos.load(Ordering::SeqCst);
                 let bytes_written = file.write(buf, pos, opened_object.options)?;
@@ -74,7 +106,10 @@ pub(super) fn write(fh: usize, buf: &[u8]) -> Result<usize, Errno> {
             });
         }
         if opened_object.named_object.is_pipe() {
-            // Make `opened_object` mutable here
+            // the write access has only been checked, if the pipe has been opened for writing
+            if !opened_object.options.intersects(OpenOptions::READWRITE | OpenOptions::WRITEONLY) {
+                return Err(Errno::EBADF);
+            }
             return opened_object.named_object.as_pipe().and_then(|pipe| {
                 let bytes_written = pipe.write(buf, 0, opened_object.options)?;
                 Ok(bytes_written) // Return the bytes written
@@ -88,6 +123,9 @@ pub(super) fn read(fh: usize, buf: &mut [u8]) -> Result<usize, Errno> {
     get_open_object_table().lookup_opened_object(fh).and_then(|opened_object| {
         if opened_object.named_object.is_file() {
             // Make `opened_object` mutable here
+            if opened_object.options.contains(OpenOptions::WRITEONLY) {
+                return Err(Errno::EBADF);
+            }
             return opened_object.named_object.as_file().and_then(|file| {
                 let pos = opened_object.pos.load(Ordering::SeqCst);
                 let bytes_read = file.read(buf, pos, opened_object.options)?;
@@ -141,15 +179,56 @@ pub(super) fn readdir(fh: usize) -> Result<Option<DirEntry>, Errno> {
 
 pub(super) fn close(fh: usize) -> Result<usize, Errno> {
     info!("open_object::close: close called for fh={}", fh);
-    if let Ok(opened_object) = get_open_object_table().lookup_opened_object(fh) {
-        if opened_object.named_object.is_pipe() {
-            if let Ok(pipe) = opened_object.named_object.as_pipe() {
-                pipe.close(opened_object.options);
-            }
+    // the pipe is only closed, if no other process shares the handle
+    if let Some(opened_object) = get_open_object_table().free_handle(fh)? {
+        if let Ok(pipe) = opened_object.named_object.as_pipe() {
+            pipe.close(opened_object.options);
+        }
+    }
+
+    Ok(0)
+}
+
+pub(super) fn check_handle(fh: usize) -> Result<usize, Errno> {
+    get_open_object_table().lookup_opened_object(fh).map(|_| 0)
+}
+
+/// Let the process `process_id` use the object `fh` of the current process (with the same handle).
+pub(super) fn share(fh: usize, process_id: usize) -> Result<usize, Errno> {
+    let opened_object = get_open_object_table().lookup_opened_object(fh)?;
+    let mut owners = opened_object.owners.write();
+    if !owners.contains(&process_id) {
+        owners.push(process_id);
+    }
+
+    Ok(0)
+}
+
+/// Close all objects opened by the process `process_id` (called, when the process exits).
+pub(super) fn close_for_process(process_id: usize) {
+    let Some(table) = OPEN_OBJECTS.get() else {
+        return;
+    };
+
+    for (handle, opened_object) in table.take_handles_of(process_id) {
+        info!("open_object::close_for_process: closing fh={} of process {}", handle, process_id);
+        if let Ok(pipe) = opened_object.named_object.as_pipe() {
+            pipe.close(opened_object.options);
         }
     }
+}
+
+/// Check whether the object `path` (an absolute path, e.g. a mount point) or one below it is open.
+pub(super) fn is_open_below(path: &str) -> bool {
+    let Some(table) = OPEN_OBJECTS.get() else {
+        return false;
+    };
 
-    get_open_object_table().free_handle(fh)
+    let below = format!("{path}/");
+    table.open_handles.read()
+        .iter()
+        .filter_map(|(_, obj)| obj.as_ref())
+        .any(|obj| obj.path == path || obj.path.starts_with(&below))
 }
 
 /*pub(super) fn dump() {
@@ -167,13 +246,15 @@ impl OpenObjectTable {
         }
     }
 
-    /// Lookup an 'OpenedObject' for a given handle
+    /// Lookup an 'OpenedObject' for a given handle (only objects opened by or shared with the current process are found)
     fn lookup_opened_object(&self, handle: usize) -> Result<Arc<OpenedObject>, Errno> {
+        let (process_id, _) = scheduler().current_ids();
         let guard = self.open_handles.read();
         guard
             .iter()
             .find(|(h, _)| *h == handle)
             .and_then(|(_, obj)| obj.as_ref())
+            .filter(|obj| obj.is_owned_by(process_id))
             .cloned()
             .ok_or(Errno::EINVALH)
     }
@@ -189,19 +270,49 @@ impl OpenObjectTable {
         Ok(handle)
     }
 
-    /// Free handle
-    fn free_handle(&self, handle: usize) -> SyscallResult {
+    /// Free handle for the current process (if it has been allocated by or shared with it).
+    /// Returns the object, if no other process uses the handle anymore.
+    fn free_handle(&self, handle: usize) -> Result<Option<Arc<OpenedObject>>, Errno> {
+        let (process_id, _) = scheduler().current_ids();
         let mut guard = self.open_handles.write();
 
-        if let Some(idx) = guard.iter().position(|(h, _)| *h == handle) {
+        let Some(idx) = guard.iter().position(|(h, obj)| *h == handle && obj.as_ref().is_some_and(|obj| obj.is_owned_by(process_id))) else {
+            return Err(Errno::EINVALH);
+        };
+        let obj = Arc::clone(guard[idx].1.as_ref().unwrap());
+        if obj.release(process_id) {
             guard.swap_remove(idx);
             self.free_handles.write()[handle] = 0;
-            Ok(0)
+            Ok(Some(obj))
         } else {
-            Err(Errno::EINVALH)
+            Ok(None)
         }
     }
 
+    /// Free all handles of the process `process_id` and return the objects, that are not used by other processes anymore
+    fn take_handles_of(&self, process_id: usize) -> Vec<(usize, Arc<OpenedObject>)> {
+        let mut guard = self.open_handles.write();
+        let mut taken = Vec::new();
+
+        guard.retain(|(handle, obj)| match obj {
+            Some(obj) if obj.is_owned_by(process_id) => {
+                if obj.release(process_id) {
+                    taken.push((*handle, Arc::clone(obj)));
+                    false
+                } else {
+                    true
+                }
+            }
+            _ => true,
+        });
+        let mut free = self.free_handles.write();
+        for (handle, _) in taken.iter() {
+            free[*handle] = 0;
+        }
+
+        taken
+    }
+
     /// Helper function of 'allocate' to find a free handle
     fn find_free_handle(&self) -> Option<usize> {
         let mut free = self.free_handles.write(); // hold guard for the whole loop
@@ -237,15 +348,28 @@ fn get_open_object_table() -> Arc<OpenObjectTable> {
 /// ************************ OpenedObject ************************
 
 // Opened object stored in the 'OpenObjectTable'
-// (includes NamedObject, current position within object, and options)
+// (includes NamedObject, its absolute path, current position within object, options and the owning processes)
 pub struct OpenedObject {
     named_object: Arc<NamedObject>,
+    path: String,
     pos: AtomicUsize, // current position within file or number of next DirEntry
     options: OpenOptions,
+    owners: RwLock<Vec<usize>>, // the process, that has opened the object, and the ones it has been shared with
 }
 
 impl OpenedObject {
-    pub fn new(named_object: Arc<NamedObject>, pos: AtomicUsize, options: OpenOptions) -> OpenedObject {
-        OpenedObject { named_object, pos, options }
+    pub fn new(named_object: Arc<NamedObject>, path: String, pos: AtomicUsize, options: OpenOptions, process_id: usize) -> OpenedObject {
+        OpenedObject { named_object, path, pos, options, owners: RwLock::new(vec![process_id]) }
+    }
+
+    fn is_owned_by(&self, process_id: usize) -> bool {
+        self.owners.read().contains(&process_id)
+    }
+
+    /// Remove `process_id` from the owners and return true, if it has been the last one
+    fn release(&self, process_id: usize) -> bool {
+        let mut owners = self.owners.write();
+        owners.retain(|&owner| owner != process_id);
+        owners.is_empty()
     }
 }
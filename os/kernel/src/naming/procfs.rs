@@ -0,0 +1,270 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: procfs                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Pseudo file system with information about processes and the kernel      ║
+   ║ (mounted on /proc), intended for tools like 'ps' or 'free':             ║
+   ║   - <pid>/status         name, state, memory and CPU time of a process  ║
+   ║   - cpuinfo              model and features of the CPU, list of cores   ║
+   ║   - schedstat            thread switches, ready threads and CPU time    ║
+   ║                          (user, system, idle) of each core              ║
+   ║   - meminfo              physical memory and kernel heap                ║
+   ║   - kmsg                 recent output of the kernel log                ║
+   ║   - mounts               mounted file systems with their flags          ║
+   ║   - net/sockets          sockets of all network namespaces              ║
+   ║ The content of a file is generated, when it is opened, so it stays      ║
+   ║ consistent while being read in several steps.                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use super::api;
+use super::stat::{Mode, Stat, MODE_DIR, MODE_FILE};
+use super::traits::{DirectoryObject, FileObject, FileSystem, NamedObject};
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use naming::shared_types::{DirEntry, FileType, MountFlags, OpenOptions};
+use raw_cpuid::CpuId;
+use syscall::return_vals::Errno;
+use crate::interrupt::smp_call;
+use crate::memory::{self, PAGE_SIZE};
+use crate::process::thread::ThreadState;
+use crate::{logger, network, process_manager, scheduler};
+
+pub struct ProcFs {
+    root_dir: Arc<ProcDir>,
+}
+
+impl ProcFs {
+    pub fn new() -> ProcFs {
+        ProcFs { root_dir: Arc::new(ProcDir::Root) }
+    }
+}
+
+impl FileSystem for ProcFs {
+    fn root_dir(&self) -> Arc<dyn DirectoryObject> {
+        self.root_dir.clone()
+    }
+}
+
+/// The directories of procfs, their content is determined on every access
+#[derive(Debug)]
+enum ProcDir {
+    Root,
+    Net,
+    Process(usize),
+}
+
+impl ProcDir {
+    fn entries(&self) -> Vec<(String, FileType)> {
+        match self {
+            ProcDir::Root => {
+                let mut entries = Vec::from([
+                    ("cpuinfo".to_string(), FileType::Regular),
+                    ("schedstat".to_string(), FileType::Regular),
+                    ("meminfo".to_string(), FileType::Regular),
+                    ("kmsg".to_string(), FileType::Regular),
+                    ("mounts".to_string(), FileType::Regular),
+                    ("net".to_string(), FileType::Directory),
+                ]);
+                let processes = process_manager().read().active_process_ids();
+                entries.extend(processes.into_iter().map(|pid| (pid.to_string(), FileType::Directory)));
+                entries
+            }
+            ProcDir::Net => Vec::from([("sockets".to_string(), FileType::Regular)]),
+            ProcDir::Process(_) => Vec::from([("status".to_string(), FileType::Regular)]),
+        }
+    }
+}
+
+impl DirectoryObject for ProcDir {
+    fn lookup(&self, name: &str) -> Result<NamedObject, Errno> {
+        let content = match (self, name) {
+            (ProcDir::Root, "cpuinfo") => cpu_info(),
+            (ProcDir::Root, "schedstat") => scheduler_statistics(),
+            (ProcDir::Root, "meminfo") => memory_info(),
+            (ProcDir::Root, "kmsg") => logger().history(),
+            (ProcDir::Root, "mounts") => mount_list(),
+            (ProcDir::Root, "net") => return Ok((Arc::new(ProcDir::Net) as Arc<dyn DirectoryObject>).into()),
+            (ProcDir::Root, pid) => {
+                let pid: usize = pid.parse().map_err(|_| Errno::ENOENT)?;
+                process_manager().read().process(pid).ok_or(Errno::ENOENT)?;
+                return Ok((Arc::new(ProcDir::Process(pid)) as Arc<dyn DirectoryObject>).into());
+            }
+            (ProcDir::Net, "sockets") => network::socket_status().into_bytes(),
+            (ProcDir::Process(pid), "status") => process_status(*pid)?,
+            _ => return Err(Errno::ENOENT),
+        };
+
+        Ok((Arc::new(ProcFile { content }) as Arc<dyn FileObject>).into())
+    }
+
+    fn create_file(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::EACCES)
+    }
+
+    fn create_dir(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::EACCES)
+    }
+
+    fn create_pipe(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::EACCES)
+    }
+
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(Stat::new(Mode::new(MODE_DIR), 0))
+    }
+
+    fn readdir(&self, index: usize) -> Result<Option<DirEntry>, Errno> {
+        Ok(self.entries().into_iter().nth(index).map(|(name, file_type)| DirEntry { file_type, name }))
+    }
+
+    fn rename(&self, _old_name: &str, _new_dir: &Arc<dyn DirectoryObject>, _new_name: &str) -> Result<(), Errno> {
+        Err(Errno::EACCES)
+    }
+
+    fn unlink(&self, _name: &str) -> Result<(), Errno> {
+        Err(Errno::EACCES)
+    }
+}
+
+/// A file with content generated when it was looked up
+struct ProcFile {
+    content: Vec<u8>,
+}
+
+impl FileObject for ProcFile {
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(Stat::new(Mode::new(MODE_FILE), self.content.len()))
+    }
+
+    fn read(&self, buf: &mut [u8], offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        let Some(content) = self.content.get(offset..) else {
+            return Ok(0);
+        };
+
+        let len = content.len().min(buf.len());
+        buf[..len].copy_from_slice(&content[..len]);
+        Ok(len)
+    }
+
+    fn write(&self, _buf: &[u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        Err(Errno::EACCES)
+    }
+
+    fn truncate(&self, _size: usize) -> Result<(), Errno> {
+        Err(Errno::EACCES)
+    }
+}
+
+impl fmt::Debug for ProcFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProcFile").field("size", &self.content.len()).finish()
+    }
+}
+
+/// Content of `<pid>/status`
+fn process_status(pid: usize) -> Result<Vec<u8>, Errno> {
+    let process = process_manager().read().process(pid).ok_or(Errno::ENOENT)?;
+    let threads = process.thread_ids();
+    let usage = process.resource_usage();
+
+    // Der Zustand des Prozesses ist der "aktivste" Zustand seiner Threads
+    let states: Vec<ThreadState> = threads.iter()
+        .filter_map(|&thread_id| scheduler().thread(thread_id))
+        .map(|thread| thread.state())
+        .collect();
+    let state = [ThreadState::Running, ThreadState::Ready, ThreadState::Parking, ThreadState::Blocked, ThreadState::Sleeping, ThreadState::Created]
+        .into_iter()
+        .find(|state| states.contains(state))
+        .unwrap_or(ThreadState::Exited);
+
+    let mut out = String::new();
+    let _ = writeln!(out, "Name: {}", process.name());
+    let _ = writeln!(out, "Pid: {}", pid);
+    let _ = writeln!(out, "State: {:?}", state);
+    let _ = writeln!(out, "Threads: {}", threads.len());
+    let _ = writeln!(out, "Cwd: {}", process.cwd());
+    let _ = writeln!(out, "HeapMemory: {} kB", process.heap_memory() / 1024);
+    let _ = writeln!(out, "MaxRss: {} kB", usage.max_rss_kib);
+    let _ = writeln!(out, "UserTime: {} us", usage.user_time_us);
+    let _ = writeln!(out, "SystemTime: {} us", usage.system_time_us);
+    let _ = writeln!(out, "VoluntarySwitches: {}", usage.voluntary_switches);
+    let _ = writeln!(out, "InvoluntarySwitches: {}", usage.involuntary_switches);
+    let _ = writeln!(out, "PageFaults: {}", usage.page_faults);
+    let (dispatches, run_delay_ns, max_run_delay_ns) = process.usage().run_delay_ns();
+    let _ = writeln!(out, "Dispatches: {}", dispatches);
+    let _ = writeln!(out, "RunDelay: {} us", run_delay_ns / 1000);
+    let _ = writeln!(out, "MaxRunDelay: {} us", max_run_delay_ns / 1000);
+
+    Ok(out.into_bytes())
+}
+
+/// Content of `schedstat` (a header line and one line per core, times in microseconds)
+fn scheduler_statistics() -> Vec<u8> {
+    let mut out = String::new();
+    let _ = writeln!(out, "core switches ready user system idle");
+    for core in scheduler().core_statistics() {
+        let _ = writeln!(out, "{} {} {} {} {} {}", core.apic_id, core.switches, core.ready, core.user_ns / 1000, core.system_ns / 1000, core.idle_ns / 1000);
+    }
+
+    out.into_bytes()
+}
+
+/// Content of `mounts` (one line per file system)
+fn mount_list() -> Vec<u8> {
+    let mut out = String::new();
+    for (path, mount) in api::mounts() {
+        let mode = if mount.flags.contains(MountFlags::READONLY) { "ro" } else { "rw" };
+        let _ = writeln!(out, "{} {} {} {}", mount.source, path, mount.fs_type, mode);
+    }
+
+    out.into_bytes()
+}
+
+/// Content of `meminfo`
+fn memory_info() -> Vec<u8> {
+    let mut out = String::new();
+    let _ = writeln!(out, "MemTotal: {} kB", memory::get_total_frames() * PAGE_SIZE / 1024);
+    let _ = writeln!(out, "MemFree: {} kB", memory::get_free_frames() * PAGE_SIZE / 1024);
+    let _ = writeln!(out, "KernelHeapFree: {} kB", memory::heap::get_free_bytes() / 1024);
+
+    out.into_bytes()
+}
+
+/// Content of `cpuinfo` (all cores are assumed to be the same)
+fn cpu_info() -> Vec<u8> {
+    let cpuid = CpuId::new();
+    let mut out = String::new();
+
+    if let Some(vendor) = cpuid.get_vendor_info() {
+        let _ = writeln!(out, "vendor: {}", vendor.as_str());
+    }
+    if let Some(brand) = cpuid.get_processor_brand_string() {
+        let _ = writeln!(out, "model name: {}", brand.as_str().trim());
+    }
+    if let Some(features) = cpuid.get_feature_info() {
+        let _ = writeln!(out, "family: {}", features.family_id());
+        let _ = writeln!(out, "model: {}", features.model_id());
+        let _ = writeln!(out, "stepping: {}", features.stepping_id());
+        let flags = [
+            ("fpu", features.has_fpu()), ("tsc", features.has_tsc()), ("msr", features.has_msr()),
+            ("apic", features.has_apic()), ("sse", features.has_sse()), ("sse2", features.has_sse2()),
+            ("sse3", features.has_sse3()), ("ssse3", features.has_ssse3()), ("sse4_1", features.has_sse41()),
+            ("sse4_2", features.has_sse42()), ("x2apic", features.has_x2apic()), ("aes", features.has_aesni()),
+            ("xsave", features.has_xsave()), ("avx", features.has_avx()), ("rdrand", features.has_rdrand()),
+            ("hypervisor", features.has_hypervisor()),
+        ];
+        let flags: Vec<&str> = flags.iter().filter(|(_, present)| *present).map(|(name, _)| *name).collect();
+        let _ = writeln!(out, "flags: {}", flags.join(" "));
+    }
+
+    let cores = smp_call::cpus();
+    let _ = writeln!(out, "cores: {}", cores.len());
+    for apic_id in cores {
+        let _ = writeln!(out, "apic id: {}", apic_id);
+    }
+
+    out.into_bytes()
+}
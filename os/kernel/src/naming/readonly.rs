@@ -0,0 +1,102 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: readonly                                                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Wrapper for file systems mounted with the flag 'READONLY'. Files and    ║
+   ║ directories are passed through for reading, all modifications fail      ║
+   ║ with 'ERDONLY'. Named pipes are not affected, as writing to them does   ║
+   ║ not change the file system.                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use super::stat::{Mode, Stat};
+use super::traits::{DirectoryObject, FileObject, FileSystem, NamedObject};
+use alloc::sync::Arc;
+use naming::shared_types::{DirEntry, OpenOptions};
+use syscall::return_vals::Errno;
+
+pub struct ReadOnlyFs {
+    fs: Arc<dyn FileSystem>,
+}
+
+impl ReadOnlyFs {
+    pub fn new(fs: Arc<dyn FileSystem>) -> ReadOnlyFs {
+        ReadOnlyFs { fs }
+    }
+}
+
+impl FileSystem for ReadOnlyFs {
+    fn root_dir(&self) -> Arc<dyn DirectoryObject> {
+        Arc::new(ReadOnlyDir(self.fs.root_dir()))
+    }
+}
+
+#[derive(Debug)]
+struct ReadOnlyDir(Arc<dyn DirectoryObject>);
+
+impl DirectoryObject for ReadOnlyDir {
+    fn lookup(&self, name: &str) -> Result<NamedObject, Errno> {
+        Ok(match self.0.lookup(name)? {
+            NamedObject::FileObject(file) => (Arc::new(ReadOnlyFile(file)) as Arc<dyn FileObject>).into(),
+            NamedObject::DirectoryObject(dir) => (Arc::new(ReadOnlyDir(dir)) as Arc<dyn DirectoryObject>).into(),
+            pipe @ NamedObject::PipeObject(_) => pipe,
+        })
+    }
+
+    fn create_file(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn create_dir(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn create_pipe(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn stat(&self) -> Result<Stat, Errno> {
+        self.0.stat()
+    }
+
+    fn readdir(&self, index: usize) -> Result<Option<DirEntry>, Errno> {
+        self.0.readdir(index)
+    }
+
+    fn rename(&self, _old_name: &str, _new_dir: &Arc<dyn DirectoryObject>, _new_name: &str) -> Result<(), Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn unlink(&self, _name: &str) -> Result<(), Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn set_attributes(&self, _permissions: u32, _uid: u32, _gid: u32) -> Result<(), Errno> {
+        Err(Errno::ERDONLY)
+    }
+}
+
+#[derive(Debug)]
+struct ReadOnlyFile(Arc<dyn FileObject>);
+
+impl FileObject for ReadOnlyFile {
+    fn stat(&self) -> Result<Stat, Errno> {
+        self.0.stat()
+    }
+
+    fn read(&self, buf: &mut [u8], offset: usize, options: OpenOptions) -> Result<usize, Errno> {
+        self.0.read(buf, offset, options)
+    }
+
+    fn write(&self, _buf: &[u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn truncate(&self, _size: usize) -> Result<(), Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn set_attributes(&self, _permissions: u32, _uid: u32, _gid: u32) -> Result<(), Errno> {
+        Err(Errno::ERDONLY)
+    }
+}
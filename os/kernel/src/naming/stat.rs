@@ -1,16 +1,31 @@
 /* ╔═════════════════════════════════════════════════════════════════════════╗
    ║ Module: stat                                                            ║
    ╟─────────────────────────────────────────────────────────────────────────╢
-   ║ Meta data for each named object.                                        ║
+   ║ Meta data for each named object, including owner and permissions, and   ║
+   ║ the access check based on them.                                         ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Michael Schoettner, 30.12.2024, HHU                             ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
+use syscall::return_vals::Errno;
+use syscall::sandbox::ROOT_ID;
+use crate::process::sandbox;
 
 pub const MODE_FILE: u32 = 0x1;
 pub const MODE_DIR: u32  = 0x2;
 pub const MODE_LINK: u32 = 0x3;
 
+/// Permissions of objects on file systems without owners (everybody may do everything)
+pub const PERMISSIONS_ALL: u32 = 0o777;
+/// Default permissions of new files, directories and named pipes
+pub const PERMISSIONS_FILE: u32 = 0o644;
+pub const PERMISSIONS_DIR: u32 = 0o755;
+pub const PERMISSIONS_PIPE: u32 = 0o666;
+
+/// Access rights, checked against the permission bits of the owner, the group or all others
+pub const ACCESS_READ: u32 = 0o4;
+pub const ACCESS_WRITE: u32 = 0o2;
+pub const ACCESS_EXECUTE: u32 = 0o1;
 
 #[derive(Debug, Copy, Clone)]
 pub struct Stat {
@@ -19,6 +34,10 @@ pub struct Stat {
     pub created_time: u64,
     pub modified_time: u64,
     pub accessed_time: u64,
+    /// Unix style permission bits (e.g. 0o644)
+    pub permissions: u32,
+    pub uid: u32,
+    pub gid: u32,
 }
 
 impl Stat {
@@ -29,6 +48,9 @@ impl Stat {
             created_time: 0,
             modified_time: 0,
             accessed_time: 0,
+            permissions: PERMISSIONS_ALL,
+            uid: ROOT_ID,
+            gid: ROOT_ID,
         }
     }
     pub fn zeroed() -> Stat {
@@ -38,8 +60,32 @@ impl Stat {
             created_time: 0,
             modified_time: 0,
             accessed_time: 0, 
+            permissions: PERMISSIONS_ALL,
+            uid: ROOT_ID,
+            gid: ROOT_ID,
         }
     }
+
+    /// Check whether the user `uid` in the group `gid` has all rights in `access` (root has all rights).
+    pub fn permits(&self, uid: u32, gid: u32, access: u32) -> bool {
+        let granted = match uid {
+            ROOT_ID => return true,
+            uid if uid == self.uid => self.permissions >> 6,
+            _ if gid == self.gid => self.permissions >> 3,
+            _ => self.permissions,
+        };
+        granted & access == access
+    }
+}
+
+/// Check whether the current process has all rights in `access` for an object with the meta data `stat`.
+/// Returns `EACCES` otherwise.
+pub fn check_access(stat: &Stat, access: u32) -> Result<(), Errno> {
+    let (uid, gid) = sandbox::credentials();
+    match stat.permits(uid, gid, access) {
+        true => Ok(()),
+        false => Err(Errno::EACCES),
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
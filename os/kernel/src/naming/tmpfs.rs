@@ -2,19 +2,21 @@
    ║ Module: tmpfs                                                           ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Temporary file system running storing everything in main memory. It     ║
-   ║ supports directories, files, and named pipes.                           ║
+   ║ supports directories, files, and named pipes, which can be renamed and  ║
+   ║ moved between directories (also between different tmpfs instances).     ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Michael Schoettner, Univ. Duesseldorf, 17.1.2026                ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
-use super::stat::Mode;
+use super::stat::{Mode, MODE_DIR, MODE_FILE};
 use super::stat::Stat;
 use super::traits::{DirectoryObject, FileObject, FileSystem, NamedObject, PipeObject};
 use crate::sync::wait_queue::WaitQueue;
-use crate::scheduler;
+use crate::{scheduler, wallclock};
 use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::any::Any;
 use core::fmt::{Debug, Formatter};
 use core::result::Result;
 use core::sync::atomic::AtomicBool;
@@ -27,6 +29,9 @@ use spin::rwlock::RwLock;
 use syscall::return_vals::Errno;
 use log::{info, warn};
 
+/// Serializes renames and unlinks, so that two directories can be locked at once without deadlocks
+static RENAME_LOCK: spin::Mutex<()> = spin::Mutex::new(());
+
 pub struct TmpFs {
     root_dir: Arc<Dir>,
 }
@@ -85,8 +90,8 @@ impl Dir {
         Dir(RwLock::new(DirInner {
             files: Vec::new(),
             stat: Stat {
-                mode: Mode::new(0),
-                ..Stat::zeroed()
+                mode: Mode::new(MODE_DIR),
+                ..created_now()
             },
         }))
     }
@@ -106,6 +111,40 @@ impl Dir {
         // Return the created file as a NamedObject
         Ok((inode as Arc<dyn FileObject>).into())
     }
+
+    /// Check if `dir` is a (direct or indirect) subdirectory of this directory
+    fn contains(&self, dir: &Dir) -> bool {
+        self.0.read().files.iter().any(|(_, inode)| match inode {
+            TmpFsINode::Directory(child) => ptr::eq(child.as_ref(), dir) || child.contains(dir),
+            _ => false,
+        })
+    }
+}
+
+/// Current wall clock time in seconds (for the time stamps in `Stat`)
+fn now() -> u64 {
+    wallclock::now().seconds.max(0) as u64
+}
+
+/// Meta data of an object created just now
+fn created_now() -> Stat {
+    let now = now();
+    Stat { created_time: now, modified_time: now, accessed_time: now, ..Stat::zeroed() }
+}
+
+fn set_attributes(stat: &mut Stat, permissions: u32, uid: u32, gid: u32) {
+    stat.permissions = permissions;
+    stat.uid = uid;
+    stat.gid = gid;
+}
+
+/// Check if `existing` may be replaced by `moved` when renaming (only files and pipes can be replaced, and not by directories)
+fn check_replace(existing: &TmpFsINode, moved: &TmpFsINode) -> Result<(), Errno> {
+    match (existing, moved) {
+        (TmpFsINode::Directory(_), _) => Err(Errno::EEXIST),
+        (_, TmpFsINode::Directory(_)) => Err(Errno::ENOTDIR),
+        _ => Ok(()),
+    }
 }
 
 impl DirectoryObject for Dir {
@@ -201,6 +240,67 @@ impl DirectoryObject for Dir {
         };
         Ok(Some(entry))
     }
+
+    fn rename(&self, old_name: &str, new_dir: &Arc<dyn DirectoryObject>, new_name: &str) -> Result<(), Errno> {
+        let new_dir: &dyn Any = new_dir.as_ref();
+        let new_dir = new_dir.downcast_ref::<Dir>().ok_or(Errno::ENOTSUP)?;
+        if new_name.is_empty() || new_name.contains('/') {
+            return Err(Errno::EINVAL);
+        }
+        let _rename_guard = RENAME_LOCK.lock();
+
+        // a directory can't be moved into itself
+        if let Some((_, TmpFsINode::Directory(moved))) = self.0.read().files.iter().find(|(file_name, _)| file_name == old_name)
+            && (ptr::eq(moved.as_ref(), new_dir) || moved.contains(new_dir)) {
+            return Err(Errno::EINVAL);
+        }
+
+        if ptr::eq(self, new_dir) {
+            let mut dir_lock = self.0.write();
+            let index = dir_lock.files.iter().position(|(file_name, _)| file_name == old_name).ok_or(Errno::ENOENT)?;
+            if old_name == new_name {
+                return Ok(());
+            }
+            if let Some(existing) = dir_lock.files.iter().position(|(file_name, _)| file_name == new_name) {
+                check_replace(&dir_lock.files[existing].1, &dir_lock.files[index].1)?;
+                dir_lock.files.remove(existing);
+            }
+
+            // the index may have moved by removing the replaced entry
+            let index = dir_lock.files.iter().position(|(file_name, _)| file_name == old_name).unwrap();
+            dir_lock.files[index].0 = new_name.to_string();
+            return Ok(());
+        }
+
+        let mut source = self.0.write();
+        let mut target = new_dir.0.write();
+        let index = source.files.iter().position(|(file_name, _)| file_name == old_name).ok_or(Errno::ENOENT)?;
+        if let Some(existing) = target.files.iter().position(|(file_name, _)| file_name == new_name) {
+            check_replace(&target.files[existing].1, &source.files[index].1)?;
+            target.files.remove(existing);
+        }
+
+        let (_, inode) = source.files.remove(index);
+        target.files.push((new_name.to_string(), inode));
+        Ok(())
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), Errno> {
+        let _rename_guard = RENAME_LOCK.lock();
+        let mut dir_lock = self.0.write();
+        let index = dir_lock.files.iter().position(|(file_name, _)| file_name == name).ok_or(Errno::ENOENT)?;
+        if let TmpFsINode::Directory(dir) = &dir_lock.files[index].1 && !dir.0.read().files.is_empty() {
+            return Err(Errno::ENOTEMPTY);
+        }
+
+        dir_lock.files.remove(index);
+        Ok(())
+    }
+
+    fn set_attributes(&self, permissions: u32, uid: u32, gid: u32) -> Result<(), Errno> {
+        set_attributes(&mut self.0.write().stat, permissions, uid, gid);
+        Ok(())
+    }
 }
 
 impl fmt::Debug for Dir {
@@ -219,8 +319,8 @@ impl File {
         File {
             data: RwLock::new(Vec::new()),
             stat: RwLock::new(Stat {
-                mode: Mode::new(0),
-                ..Stat::zeroed()
+                mode: Mode::new(MODE_FILE),
+                ..created_now()
             }),
         }
     }
@@ -246,16 +346,30 @@ impl FileObject for File {
     fn write(&self, buf: &[u8], offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
         let mut data = self.data.write();
 
+        let mut stat = self.stat.write();
         if offset + buf.len() > data.len() {
-            let mut stat = self.stat.write();
             stat.size = offset + buf.len();
-
             data.resize(stat.size, 0);
         }
+        stat.modified_time = now();
 
         data[offset..offset + buf.len()].clone_from_slice(buf);
         Ok(buf.len())
     }
+
+    fn truncate(&self, size: usize) -> Result<(), Errno> {
+        let mut data = self.data.write();
+        data.resize(size, 0);
+        let mut stat = self.stat.write();
+        stat.size = size;
+        stat.modified_time = now();
+        Ok(())
+    }
+
+    fn set_attributes(&self, permissions: u32, uid: u32, gid: u32) -> Result<(), Errno> {
+        set_attributes(&mut self.stat.write(), permissions, uid, gid);
+        Ok(())
+    }
 }
 
 impl Debug for File {
@@ -304,6 +418,10 @@ impl FileObject for StaticFile {
     fn write(&self, _buf: &[u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
         Err(Errno::ERDONLY)
     }
+
+    fn truncate(&self, _size: usize) -> Result<(), Errno> {
+        Err(Errno::ERDONLY)
+    }
 }
 
 impl Debug for StaticFile {
@@ -359,7 +477,7 @@ impl Pipe {
     }
 
     #[inline]
-    fn wait_open<F: Fn() -> bool>(&self, cond: F, why: &'static str) {
+    fn wait_open<F: Fn() -> bool>(&self, cond: F) {
         loop {
             if cond() {
                 return;
@@ -367,7 +485,7 @@ impl Pipe {
             // Sleep until either the condition becomes true OR the epoch changed.
             // Epoch change means "some open/close transition happened, re-check".
            let e = self.epoch();
-           self.open_wq.wait(|| cond() || self.epoch() != e, why);
+           self.open_wq.wait_until(|| cond() || self.epoch() != e);
             // loop to re-check; handles spurious wakes and epoch-only wakes.
         }
     }
@@ -402,7 +520,7 @@ impl Pipe {
     #[inline]
     fn bump_epoch_and_wake_open(&self) {
         self.open_epoch.fetch_add(1, Ordering::SeqCst);
-        self.open_wq.notify_all();
+        self.open_wq.wake_all();
     }
 }
 
@@ -425,7 +543,7 @@ impl PipeObject for Pipe {
                 drop(_g);
 
                 // block until a writer is present.
-                self.wait_open(|| self.has_writer.load(Ordering::SeqCst), "open: reader waiting for writer");
+                self.wait_open(|| self.has_writer.load(Ordering::SeqCst)); // reader waiting for writer
                 Ok(0)
             }
 
@@ -443,7 +561,7 @@ impl PipeObject for Pipe {
                 drop(_g);
 
                 // block until a reader is present
-                self.wait_open(|| self.has_reader.load(Ordering::SeqCst), "open: writer waiting for reader");
+                self.wait_open(|| self.has_reader.load(Ordering::SeqCst)); // writer waiting for reader
                 Ok(0)
             }
 
@@ -472,7 +590,7 @@ impl PipeObject for Pipe {
         }
 
         // Block until data is available or writer has gone
-        self.rx_wq.wait(|| self.has_data() || !self.has_writer(), "read: blocks");
+        self.rx_wq.wait_until(|| self.has_data() || !self.has_writer());
 
         // EOF if no writer is present and no data available
         if !self.has_data() && !self.has_writer() {
@@ -502,7 +620,7 @@ impl PipeObject for Pipe {
                 Err(_) => {
                     // We consumed all available data but need more
                     // We block until more data is available or the writer has gone (-> EOF)
-                    self.rx_wq.wait(|| self.has_data() || !self.has_writer(), "read: blocks");
+                    self.rx_wq.wait_until(|| self.has_data() || !self.has_writer());
                     if !self.has_data() {
                         break;
                     }
@@ -513,7 +631,7 @@ impl PipeObject for Pipe {
         // If we read at least one byte we freed space
         // -> wake potentially blocked writer
         if total_read > 0 {
-            self.wx_wq.notify_one();
+            self.wx_wq.wake_one();
         }
 
         Ok(total_read)
@@ -536,7 +654,7 @@ impl PipeObject for Pipe {
         }
 
         // Block until space is available or reader has gone
-        self.wx_wq.wait(|| self.has_space() || !self.has_reader(), "write: blocks");
+        self.wx_wq.wait_until(|| self.has_space() || !self.has_reader());
 
         // EOF if no writer is present and no data available
         if !self.has_reader() {
@@ -564,7 +682,7 @@ impl PipeObject for Pipe {
                 Err(_) => {
                     // We consumed all available space but need more
                     // We block until more space is available or the reader has gone (-> EOF)
-                    self.wx_wq.wait(|| self.has_space() || !self.has_reader(), "write: blocks");
+                    self.wx_wq.wait_until(|| self.has_space() || !self.has_reader());
                     if !self.has_reader() {
                         return Err(Errno::EPIPE);
                     }
@@ -574,8 +692,8 @@ impl PipeObject for Pipe {
 
         // If we wrote at least one byte we wake up potentially blocked reader
         if total_written > 0 {
-            info!("PipeObject::write: done, total_written={}, notify_one, pid={}, tid={}", total_written, pid, tid);
-            self.rx_wq.notify_one();
+            info!("PipeObject::write: done, total_written={}, wake_one, pid={}, tid={}", total_written, pid, tid);
+            self.rx_wq.wake_one();
         }
         Ok(total_written)
     }
@@ -590,12 +708,12 @@ impl PipeObject for Pipe {
             OpenOptions::READONLY => {
                 self.has_reader.store(false, Ordering::SeqCst);
                 self.bump_epoch_and_wake_open(); // wake open waiters
-                self.wx_wq.notify_all(); // writers blocked on full/space or EPIPE checks
+                self.wx_wq.wake_all(); // writers blocked on full/space or EPIPE checks
             }
             OpenOptions::WRITEONLY => {
                 self.has_writer.store(false, Ordering::SeqCst);
                 self.bump_epoch_and_wake_open(); // wake open waiters
-                self.rx_wq.notify_all(); // readers blocked on empty/EOF checks
+                self.rx_wq.wake_all(); // readers blocked on empty/EOF checks
             }
             _ => {}
         }
@@ -610,6 +728,11 @@ impl PipeObject for Pipe {
             self.count.store(0, Ordering::SeqCst);
         }
     }
+
+    fn set_attributes(&self, permissions: u32, uid: u32, gid: u32) -> Result<(), Errno> {
+        set_attributes(&mut self.stat.write(), permissions, uid, gid);
+        Ok(())
+    }
 }
 
 impl Debug for Pipe {
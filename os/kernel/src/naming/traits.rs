@@ -14,6 +14,7 @@
 
 
 use alloc::sync::Arc;
+use core::any::Any;
 use core::fmt::{self, Debug};
 use core::result::Result;
 
@@ -31,6 +32,12 @@ pub trait FileObject: Debug + Send + Sync {
     fn stat(&self) -> Result<Stat, Errno>;
     fn read(&self, _buf: &mut [u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno>;
     fn write(&self, _buf: &[u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno>;
+    /// Set the size of the file to `size` bytes, discarding the data behind it or appending zeros.
+    fn truncate(&self, size: usize) -> Result<(), Errno>;
+    /// Change permission bits and owner. File systems without owners (e.g. FAT) don't support this.
+    fn set_attributes(&self, _permissions: u32, _uid: u32, _gid: u32) -> Result<(), Errno> {
+        Err(Errno::ENOTSUP)
+    }
 }
 
 /// Pipe object operations
@@ -40,11 +47,15 @@ pub trait PipeObject: Debug + Send + Sync {
     fn read(&self, _buf: &mut [u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno>;
     fn write(&self, _buf: &[u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno>;
     fn close(&self, flags: OpenOptions);
+    fn set_attributes(&self, _permissions: u32, _uid: u32, _gid: u32) -> Result<(), Errno> {
+        Err(Errno::ENOTSUP)
+    }
 }
 
 
 /// Directory object operations
-pub trait DirectoryObject: Debug + Send + Sync {
+/// (`Any` allows a file system to recognize its own directories, e.g. as the target of `rename`)
+pub trait DirectoryObject: Any + Debug + Send + Sync {
     fn lookup(&self, name: &str) -> Result<NamedObject, Errno>;
     fn create_file(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno>;
     fn create_dir(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno>;
@@ -52,6 +63,14 @@ pub trait DirectoryObject: Debug + Send + Sync {
     #[allow(dead_code)]
     fn stat(&self) -> Result<Stat, Errno>;
     fn readdir(&self, index: usize) -> Result<Option<DirEntry>, Errno>;
+    /// Move the entry `old_name` into `new_dir` (which may be this directory) as `new_name`,
+    /// replacing a file with this name. Only works within the same file system.
+    fn rename(&self, old_name: &str, new_dir: &Arc<dyn DirectoryObject>, new_name: &str) -> Result<(), Errno>;
+    /// Remove the entry `name` (a directory must be empty). Objects, that are still open, stay usable.
+    fn unlink(&self, name: &str) -> Result<(), Errno>;
+    fn set_attributes(&self, _permissions: u32, _uid: u32, _gid: u32) -> Result<(), Errno> {
+        Err(Errno::ENOTSUP)
+    }
 }
 
 /// A named object.
@@ -103,6 +122,23 @@ impl NamedObject {
     pub fn is_dir(&self) -> bool {
         matches!(self, NamedObject::DirectoryObject(_))
     }
+
+    pub fn stat(&self) -> Result<Stat, Errno> {
+        match self {
+            NamedObject::FileObject(file) => file.stat(),
+            NamedObject::PipeObject(pipe) => pipe.stat(),
+            NamedObject::DirectoryObject(dir) => dir.stat(),
+        }
+    }
+
+    /// Change permission bits and owner (see `FileObject::set_attributes()`).
+    pub fn set_attributes(&self, permissions: u32, uid: u32, gid: u32) -> Result<(), Errno> {
+        match self {
+            NamedObject::FileObject(file) => file.set_attributes(permissions, uid, gid),
+            NamedObject::PipeObject(pipe) => pipe.set_attributes(permissions, uid, gid),
+            NamedObject::DirectoryObject(dir) => dir.set_attributes(permissions, uid, gid),
+        }
+    }
 }
 
 impl fmt::Debug for NamedObject {
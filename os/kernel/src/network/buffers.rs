@@ -0,0 +1,150 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: buffers                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Accounting of the memory used for socket buffers, which is      ║
+   ║         limited globally and per process. Sockets start with small      ║
+   ║         buffers, which are grown to their full size when the socket is  ║
+   ║         bound or connected (if the limits allow it). Creating a socket  ║
+   ║         fails, if not even the small buffers fit into the limits.       ║
+   ║         Charges are kept until smoltcp's socket is actually removed,    ║
+   ║         so closed sockets still count while they are shutting down.     ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::{icmp, tcp, udp, AnySocket};
+use spin::Mutex;
+use syscall::return_vals::Errno;
+
+/// Size of each buffer (receive and transmit) of a new socket
+pub(super) const INITIAL_BUFFER_SIZE: usize = 4096;
+/// Size of each buffer (receive and transmit) of a bound or connected socket
+pub(super) const FULL_BUFFER_SIZE: usize = 65535;
+/// Number of datagrams, that fit into the buffers of a UDP socket
+const UDP_METADATA_COUNT: usize = 44;
+const ICMP_METADATA_COUNT: usize = 2;
+/// Maximum buffer memory of all sockets (the kernel heap has 16 MiB)
+const GLOBAL_LIMIT: usize = 4 * 1024 * 1024;
+/// Maximum buffer memory of the sockets of a single process
+const PROCESS_LIMIT: usize = 1024 * 1024;
+
+/// Charges of all sockets by namespace id and handle
+static CHARGES: Mutex<Charges> = Mutex::new(Charges { sockets: BTreeMap::new(), total: 0 });
+
+struct Charges {
+    sockets: BTreeMap<(usize, SocketHandle), Charge>,
+    total: usize,
+}
+
+struct Charge {
+    process_id: usize,
+    bytes: usize,
+}
+
+/// The buffers of a socket would exceed a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferLimitError {
+    /// The socket buffers of all processes together have reached `GLOBAL_LIMIT`.
+    Global,
+    /// The socket buffers of the process have reached `PROCESS_LIMIT`.
+    Process,
+}
+
+impl From<BufferLimitError> for Errno {
+    fn from(_: BufferLimitError) -> Self {
+        Errno::ENOBUFS
+    }
+}
+
+/// Charge the buffers of a socket (`bytes` in total, replacing its previous charge). \
+/// This must succeed before the buffers are allocated, so the kernel heap isn't exhausted.
+pub(super) fn charge(namespace: usize, handle: SocketHandle, process_id: usize, bytes: usize) -> Result<(), BufferLimitError> {
+    let mut charges = CHARGES.lock();
+    let previous = charges.sockets.get(&(namespace, handle)).map_or(0, |charge| charge.bytes);
+    if charges.total - previous + bytes > GLOBAL_LIMIT {
+        return Err(BufferLimitError::Global);
+    }
+    if charges.of_process(process_id) - previous + bytes > PROCESS_LIMIT {
+        return Err(BufferLimitError::Process);
+    }
+
+    charges.sockets.insert((namespace, handle), Charge { process_id, bytes });
+    charges.total = charges.total - previous + bytes;
+    Ok(())
+}
+
+/// Release the charge of a socket, after it has been removed from its socket set.
+pub(super) fn release(namespace: usize, handle: SocketHandle) {
+    let mut charges = CHARGES.lock();
+    if let Some(charge) = charges.sockets.remove(&(namespace, handle)) {
+        charges.total -= charge.bytes;
+    }
+}
+
+impl Charges {
+    fn of_process(&self, process_id: usize) -> usize {
+        self.sockets.values()
+            .filter(|charge| charge.process_id == process_id)
+            .map(|charge| charge.bytes)
+            .sum()
+    }
+}
+
+/// Sockets, whose buffers can be replaced as long as they haven't been used
+pub(super) trait ResizableSocket: AnySocket<'static> + Sized {
+    /// The socket has not been bound or connected yet.
+    fn is_unused(&self) -> bool;
+
+    /// Create an unused socket with the same options, but with buffers of `size` bytes (for each direction).
+    fn with_buffers(&self, size: usize) -> Self;
+}
+
+impl ResizableSocket for udp::Socket<'static> {
+    fn is_unused(&self) -> bool {
+        !self.is_open()
+    }
+
+    fn with_buffers(&self, size: usize) -> Self {
+        let mut socket = udp::Socket::new(
+            udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; UDP_METADATA_COUNT], vec![0; size]),
+            udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; UDP_METADATA_COUNT], vec![0; size]),
+        );
+        socket.set_hop_limit(self.hop_limit());
+        socket
+    }
+}
+
+impl ResizableSocket for tcp::Socket<'static> {
+    fn is_unused(&self) -> bool {
+        self.state() == tcp::State::Closed
+    }
+
+    fn with_buffers(&self, size: usize) -> Self {
+        let mut socket = tcp::Socket::new(tcp::SocketBuffer::new(vec![0; size]), tcp::SocketBuffer::new(vec![0; size]));
+        // options may have been set before connecting
+        socket.set_timeout(self.timeout());
+        socket.set_keep_alive(self.keep_alive());
+        socket.set_ack_delay(self.ack_delay());
+        socket.set_nagle_enabled(self.nagle_enabled());
+        socket.set_hop_limit(self.hop_limit());
+        socket
+    }
+}
+
+impl ResizableSocket for icmp::Socket<'static> {
+    fn is_unused(&self) -> bool {
+        !self.is_open()
+    }
+
+    fn with_buffers(&self, size: usize) -> Self {
+        let mut socket = icmp::Socket::new(
+            icmp::PacketBuffer::new(vec![icmp::PacketMetadata::EMPTY; ICMP_METADATA_COUNT], vec![0; size]),
+            icmp::PacketBuffer::new(vec![icmp::PacketMetadata::EMPTY; ICMP_METADATA_COUNT], vec![0; size]),
+        );
+        socket.set_hop_limit(self.hop_limit());
+        socket
+    }
+}
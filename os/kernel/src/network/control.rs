@@ -0,0 +1,236 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: control                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Minimal HTTP endpoint for controlling the kernel in debug       ║
+   ║         builds, so test harnesses don't need to parse the serial        ║
+   ║         console. It is only started if QEMU passes a token via fw_cfg   ║
+   ║         (-fw_cfg name=opt/d3os/control_token,string=<token>), which     ║
+   ║         must be sent as 'Authorization: Bearer <token>'. Actions are    ║
+   ║         posted as JSON, e.g. {"action": "stats"}, {"action":            ║
+   ║         "log_level", "level": "trace"} or {"action": "crash_dump"}.     ║
+   ║         Clients are served one at a time, so a client, that doesn't     ║
+   ║         send its request in time, is disconnected.                      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::net::Ipv4Addr;
+use core::str::{self, FromStr};
+use log::{info, warn, LevelFilter};
+use smoltcp::iface::SocketHandle;
+use smoltcp::wire::IpAddress;
+use spin::Once;
+use crate::device::{qemu_cfg, stats};
+use crate::process::thread::Thread;
+use crate::{built_info, logger, scheduler, timer};
+use super::{accept_tcp, bind_tcp, close_socket, open_tcp, receive_tcp_until, send_tcp};
+
+/// Port the endpoint listens on (all addresses)
+pub const CONTROL_PORT: u16 = 47474;
+/// Name of the fw_cfg file containing the token
+const TOKEN_FILE: &str = "opt/d3os/control_token";
+/// Requests (headers and body) larger than this are rejected
+const MAX_REQUEST_SIZE: usize = 4096;
+/// Time a client has for sending its request (in ms)
+const REQUEST_TIMEOUT_MS: usize = 5000;
+
+static TOKEN: Once<Vec<u8>> = Once::new();
+
+/// Start the control endpoint, if this is a debug build and a token has been configured.
+pub fn init() {
+    if built_info::PROFILE != "debug" {
+        return;
+    }
+    let Some(token) = qemu_cfg::read_file(TOKEN_FILE) else {
+        info!("No control token configured, not starting control endpoint");
+        return;
+    };
+    let token = token.trim_ascii().to_vec();
+    if token.is_empty() {
+        warn!("Control token is empty, not starting control endpoint");
+        return;
+    }
+
+    TOKEN.call_once(|| token);
+    scheduler().ready(Thread::new_kernel_thread(serve, "control"));
+}
+
+extern "sysv64" fn serve() {
+    let mut listen = match open_tcp() {
+        Ok(listen) => listen,
+        Err(e) => {
+            warn!("Failed to start control endpoint: {e:?}");
+            return;
+        }
+    };
+    if let Err(e) = bind_tcp(listen, IpAddress::Ipv4(Ipv4Addr::UNSPECIFIED), CONTROL_PORT) {
+        warn!("Failed to start control endpoint: {e:?}");
+        return;
+    }
+    info!("Control endpoint listening on port {CONTROL_PORT}");
+
+    loop {
+        // after accepting, `listen` is connected to the client and a new listening socket is returned
+        let (client, next) = match accept_tcp(listen) {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Control endpoint stopped: {e:?}");
+                close_socket(listen);
+                return;
+            }
+        };
+        info!("Control request from {client}");
+        handle_connection(listen);
+        close_socket(listen);
+        listen = next;
+    }
+}
+
+fn handle_connection(handle: SocketHandle) {
+    let response = match read_request(handle) {
+        Some(request) => handle_request(&request),
+        None => response(400, r#"{"error": "malformed request"}"#),
+    };
+
+    let mut data = response.as_bytes();
+    while !data.is_empty() {
+        match send_tcp(handle, data) {
+            Ok(sent) => data = &data[sent..],
+            Err(_) => return,
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    authorization: Option<String>,
+    body: String,
+}
+
+/// Read a request with its body (if there is a 'Content-Length' header).
+/// Fails, if it has not been received within `REQUEST_TIMEOUT_MS`.
+fn read_request(handle: SocketHandle) -> Option<Request> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 512];
+    let deadline = timer().systime_ms() + REQUEST_TIMEOUT_MS;
+
+    let header_end = loop {
+        if let Some(pos) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break pos;
+        }
+        if buffer.len() >= MAX_REQUEST_SIZE {
+            return None;
+        }
+        let received = receive_tcp_until(handle, &mut chunk, deadline).ok()??;
+        buffer.extend_from_slice(&chunk[..received]);
+    };
+
+    let header = str::from_utf8(&buffer[..header_end]).ok()?;
+    let mut lines = header.split("\r\n");
+    let method = lines.next()?.split(' ').next()?.into();
+    let mut authorization = None;
+    let mut content_length = 0;
+    for line in lines {
+        let (name, value) = line.split_once(':')?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("Content-Length") {
+            content_length = value.parse().ok()?;
+        } else if name.eq_ignore_ascii_case("Authorization") {
+            authorization = value.strip_prefix("Bearer ").map(String::from);
+        }
+    }
+
+    let body_start = header_end + 4;
+    if body_start + content_length > MAX_REQUEST_SIZE {
+        return None;
+    }
+    while buffer.len() < body_start + content_length {
+        let received = receive_tcp_until(handle, &mut chunk, deadline).ok()??;
+        buffer.extend_from_slice(&chunk[..received]);
+    }
+    let body = str::from_utf8(&buffer[body_start..body_start + content_length]).ok()?.into();
+
+    Some(Request { method, authorization, body })
+}
+
+fn handle_request(request: &Request) -> String {
+    let authorized = request.authorization.as_ref()
+        .zip(TOKEN.get())
+        .is_some_and(|(token, expected)| token_matches(token.as_bytes(), expected));
+    if !authorized {
+        return response(401, r#"{"error": "unauthorized"}"#);
+    }
+    if request.method != "POST" {
+        return response(405, r#"{"error": "only POST is supported"}"#);
+    }
+
+    match json_string(&request.body, "action") {
+        Some("stats") => response(200, &stats_json()),
+        Some("log_level") => {
+            let Some(level) = json_string(&request.body, "level").and_then(|level| LevelFilter::from_str(level).ok()) else {
+                return response(400, r#"{"error": "invalid log level"}"#);
+            };
+            info!("Changing log level to [{level}] (requested via control endpoint)");
+            logger().set_level(level);
+            response(200, r#"{"ok": true}"#)
+        }
+        Some("crash_dump") => {
+            logger().write_crash_dump("Crash dump requested via control endpoint\n");
+            response(200, r#"{"ok": true}"#)
+        }
+        Some(_) => response(400, r#"{"error": "unknown action"}"#),
+        None => response(400, r#"{"error": "missing action"}"#),
+    }
+}
+
+/// Compare the tokens without returning early, so their content can't be guessed by timing the responses.
+fn token_matches(token: &[u8], expected: &[u8]) -> bool {
+    token.len() == expected.len() && token.iter().zip(expected).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Find the string value of `key` in a flat JSON object.
+/// This is no real JSON parser, but sufficient for the simple objects sent to this endpoint (no escape sequences).
+fn json_string<'a>(json: &'a str, key: &str) -> Option<&'a str> {
+    let quoted_key = format!("\"{key}\"");
+    let rest = &json[json.find(&quoted_key)? + quoted_key.len()..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start().strip_prefix('"')?;
+    Some(&rest[..rest.find('"')?])
+}
+
+/// Serialize all device counters as `{"device": {"counter": value, ...}, ...}`.
+fn stats_json() -> String {
+    let mut json = String::from("{");
+    for (i, (device, counters)) in stats::snapshot().iter().enumerate() {
+        if i > 0 {
+            json.push_str(", ");
+        }
+        write!(json, "\"{device}\": {{").unwrap();
+        for (j, (counter, value)) in counters.iter().enumerate() {
+            if j > 0 {
+                json.push_str(", ");
+            }
+            write!(json, "\"{counter}\": {value}").unwrap();
+        }
+        json.push('}');
+    }
+    json.push('}');
+    json
+}
+
+fn response(status: u16, body: &str) -> String {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        405 => "Method Not Allowed",
+        _ => "Unknown",
+    };
+    format!(
+        "HTTP/1.0 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}\r\n",
+        body.len() + 2,
+    )
+}
@@ -1,35 +1,57 @@
-use alloc::collections::btree_map::BTreeMap;
+pub mod buffers;
+pub mod control;
+pub mod namespace;
+pub mod pending;
+pub mod pmtu;
+pub mod tcp_stream;
+pub mod vsock;
+pub mod wol;
+
+use alloc::string::{String, ToString};
 use alloc::sync::Arc;
-use alloc::vec;
 use alloc::vec::Vec;
 use smoltcp::socket::dns::GetQueryResultError;
+use core::fmt::Write;
 use core::net::{Ipv4Addr, Ipv6Addr};
-use core::ops::Deref;
-use core::ptr;
 use log::{info, warn};
-use smoltcp::iface::{self, Interface, SocketHandle, SocketSet};
+use num_enum::TryFromPrimitive;
+use pci_types::PciAddress;
+use smoltcp::iface::{Route, SocketHandle};
 use smoltcp::socket;
 use smoltcp::socket::{dhcpv4, dns, icmp, tcp, udp};
-use smoltcp::time::Instant;
-use smoltcp::wire::{DnsQueryType, HardwareAddress, IpAddress, IpCidr, IpEndpoint};
-use spin::{Once, RwLock};
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{DnsQueryType, IpAddress, IpCidr, IpEndpoint, IpListenEndpoint};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::{Mutex, Once, RwLock};
+use syscall::network::{DhcpLease, RouteEntry, MAX_DNS_SERVERS};
+use syscall::return_vals::Errno;
+use syscall::event::EventSource;
 use crate::device::rtl8139::Rtl8139;
+use crate::device::stats;
+use crate::network::buffers::{BufferLimitError, ResizableSocket, FULL_BUFFER_SIZE, INITIAL_BUFFER_SIZE};
+use crate::network::namespace::{veth_addresses, Namespace, NetDevice, SocketOwner, VethEnd, ROOT_NAMESPACE};
+use crate::network::pending::{notify_sockets, Interrupted, PendingGuard, PendingKind};
 use crate::process::process::Process;
 use crate::{pci_bus, process_manager, scheduler, timer};
 use crate::process::thread::Thread;
+use crate::sync::event;
+use crate::sync::pi_mutex::PiMutex;
+use syscall::priority::Priority;
 
 
-static RTL8139: Once<Arc<Rtl8139>> = Once::new();
+/// The physical NIC. This is reset to `None` if the device is removed.
+static RTL8139: RwLock<Option<Arc<Rtl8139>>> = RwLock::new(None);
 
-static INTERFACES: RwLock<Vec<Interface>> = RwLock::new(Vec::new());
-static SOCKETS: Once<RwLock<SocketSet>> = Once::new();
-/// This maps sockets to the respective process.
-/// We use this to check whether a process can access a particular socket.
-/// We can't just create a SocketSet per process because smoltcp drops all
-/// packets for non-existing sockets when polling.
-static SOCKET_PROCESS: RwLock<BTreeMap<SocketHandle, Arc<Process>>> = RwLock::new(BTreeMap::new());
+/// All network namespaces. The root namespace (containing the physical interfaces) is always the first one.
+static NAMESPACES: RwLock<Vec<Arc<Namespace>>> = RwLock::new(Vec::new());
+/// Held while creating a namespace, so two new namespaces can't get the same id (see `free_namespace_id()`)
+static NAMESPACE_CREATION: PiMutex<()> = PiMutex::new(());
+static POLL_THREAD_RUNNING: AtomicBool = AtomicBool::new(false);
+/// The DNS and DHCP sockets live in the root namespace.
 static DNS_SOCKET: Once<SocketHandle> = Once::new();
 static DHCP_SOCKET: Once<SocketHandle> = Once::new();
+/// The current lease of the DHCP client (applied by user space)
+static DHCP_LEASE: Mutex<Option<DhcpLease>> = Mutex::new(None);
 
 #[derive(Debug)]
 #[repr(u8)]
@@ -38,89 +60,432 @@ pub enum SocketType {
     Udp, Tcp, Icmp,
 }
 
+/// Error of a blocking socket operation: Either it has been cancelled while waiting or the socket failed.
+#[derive(Debug)]
+pub enum BlockingError<E> {
+    Interrupted,
+    Socket(E),
+}
+
+impl<E> From<Interrupted> for BlockingError<E> {
+    fn from(_: Interrupted) -> Self {
+        BlockingError::Interrupted
+    }
+}
+
+/// Error of `accept_tcp()`
+#[derive(Debug)]
+pub enum AcceptError {
+    /// The listening socket has been closed or aborted while waiting.
+    Closed,
+    /// There is not enough buffer memory for the new listening socket.
+    Buffers(BufferLimitError),
+}
+
+/// Options for TCP sockets, see `set_tcp_option()`.
+/// Durations are given in milliseconds, where 0 disables the respective feature.
+/// smoltcp does not allow configuring the retransmission timeout itself,
+/// but the user timeout bounds how long retransmissions are attempted.
+#[derive(Debug, Clone, Copy, TryFromPrimitive)]
+#[repr(usize)]
+pub enum TcpOption {
+    /// Abort the connection, if sent data isn't acknowledged (or keep-alives aren't answered) within this time.
+    Timeout = 0,
+    /// Send keep-alive packets after this time of inactivity.
+    KeepAlive = 1,
+    /// Delay ACKs by up to this time (smoltcp's default is 10 ms).
+    AckDelay = 2,
+    /// Enable (1) or disable (0) Nagle's algorithm.
+    Nagle = 3,
+    /// Hop limit (TTL) of outgoing packets. 0 restores the default.
+    HopLimit = 4,
+}
+
+/// Error for socket handles from user space, that can't be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandleError {
+    /// The socket has been closed (or never existed).
+    Stale,
+    /// The socket belongs to another process.
+    Foreign,
+}
+
+impl From<HandleError> for Errno {
+    fn from(error: HandleError) -> Self {
+        match error {
+            HandleError::Stale => Errno::EBADF,
+            HandleError::Foreign => Errno::EACCES,
+        }
+    }
+}
+
+/// Number of bits of a user space socket handle, that contain smoltcp's handle (see `user_handle()`).
+const HANDLE_INDEX_BITS: usize = 16;
+
+/// Flag for `create_namespace()`: Connect the new namespace to the root namespace with a pair of virtual interfaces.
+pub const NAMESPACE_WITH_INTERFACE: usize = 0x1;
+
 pub fn init() {
-    SOCKETS.call_once(|| RwLock::new(SocketSet::new(Vec::new())));
+    NAMESPACES.write().push(Arc::new(Namespace::new(ROOT_NAMESPACE)));
 
     let devices = pci_bus().search_by_ids(0x10ec, 0x8139);
     if !devices.is_empty() {
-        RTL8139.call_once(|| {
-            info!("Found Realtek RTL8139 network controller");
-            let rtl8139 = Arc::new(Rtl8139::new(devices[0]));
-            info!("RTL8139 MAC address: [{}]", rtl8139.read_mac_address());
+        info!("Found Realtek RTL8139 network controller");
+        let rtl8139 = Arc::new(Rtl8139::new(devices[0]));
+        info!("RTL8139 MAC address: [{}]", rtl8139.read_mac_address());
 
-            Rtl8139::plugin(Arc::clone(&rtl8139));
-            rtl8139
-        });
+        Rtl8139::plugin(Arc::clone(&rtl8139), devices[0]);
+        *RTL8139.write() = Some(rtl8139);
     }
 
-    if let Some(rtl8139) = RTL8139.get() {
-        extern "sysv64" fn poll() {
-            loop { poll_sockets(); scheduler().switch_thread_no_interrupt(); }
-        }
-        scheduler().ready(Thread::new_kernel_thread(poll, "RTL8139"));
-        
+    if let Some(rtl8139) = rtl8139() {
         // Set up network interface
-        let time = timer().systime_ms();
-        let mut conf = iface::Config::new(HardwareAddress::from(rtl8139.read_mac_address()));
-        conf.random_seed = time as u64;
-
-        // The Smoltcp interface struct wants a mutable reference to the device.
-        // However, the RTL8139 driver is designed to work with shared references.
-        // Since smoltcp does not actually store the mutable reference anywhere,
-        // we can safely cast the shared reference to a mutable one.
-        // (Actually, I am not sure why the smoltcp interface wants a mutable reference to the device,
-        // since it does not modify the device itself.)
-        let device = unsafe { ptr::from_ref(rtl8139.deref()).cast_mut().as_mut().unwrap() };
-        add_interface(Interface::new(conf, device, Instant::from_millis(time as i64)));
-
-        let sockets = SOCKETS.get().expect("Socket set not initialized!");
+        let root = root_namespace();
+        root.add_interface(NetDevice::Rtl8139(rtl8139), |_| {});
+        start_poll_thread();
+
         let current_process = process_manager().read().current_process();
-        let mut process_map = SOCKET_PROCESS.write();
+        let mut sockets = root.sockets.lock();
+        let mut process_map = root.owners.write();
         // setup DNS
         DNS_SOCKET.call_once(|| {
             let dns_socket = dns::Socket::new(&[], Vec::new());
-            let dns_handle = sockets.write().add(dns_socket);
+            let dns_handle = sockets.add(dns_socket);
             process_map
-                .try_insert(dns_handle, current_process.clone())
+                .try_insert(dns_handle, SocketOwner::new(current_process.clone()))
                 .expect("failed to insert socket into socket-process map");
             dns_handle
         });
         // request an IP address via DHCP
         DHCP_SOCKET.call_once(|| {
             let dhcp_socket = dhcpv4::Socket::new();
-            let dhcp_handle = sockets
-                .write()
-                .add(dhcp_socket);
+            let dhcp_handle = sockets.add(dhcp_socket);
             process_map
-                .try_insert(dhcp_handle, current_process)
+                .try_insert(dhcp_handle, SocketOwner::new(current_process))
                 .expect("failed to insert socket into socket-process map");
             dhcp_handle
         });
+        drop(process_map);
+        drop(sockets);
+
+        control::init();
+    }
+}
+
+/// Start the kernel thread polling all interfaces (if it is not running yet).
+/// The thread exits when there are no interfaces left.
+fn start_poll_thread() {
+    extern "sysv64" fn poll() {
+        loop {
+            while has_interfaces() {
+                if rtl8139().is_some_and(|rtl8139| rtl8139.is_removed()) {
+                    remove_rtl8139();
+                }
+                poll_sockets();
+                scheduler().switch_thread_no_interrupt();
+            }
+
+            // an interface might have been added, while we were stopping
+            POLL_THREAD_RUNNING.store(false, Ordering::Release);
+            if !has_interfaces() || POLL_THREAD_RUNNING.swap(true, Ordering::AcqRel) {
+                info!("No network interfaces left, stopping poll thread");
+                return;
+            }
+        }
+    }
+
+    if !POLL_THREAD_RUNNING.swap(true, Ordering::AcqRel) {
+        let thread = Thread::new_kernel_thread(poll, "network");
+        thread.set_priority(Priority::High);
+        scheduler().ready(thread);
+    }
+}
+
+fn rtl8139() -> Option<Arc<Rtl8139>> {
+    RTL8139.read().clone()
+}
+
+fn has_interfaces() -> bool {
+    // don't block while holding the list of namespaces
+    let namespaces = NAMESPACES.read().clone();
+    namespaces.iter().any(|namespace| !namespace.interfaces.lock().is_empty())
+}
+
+/// Handle the removal of the PCI function at `address` from the bus (found by rescanning the bus).
+pub fn remove_pci_device(address: PciAddress) {
+    if rtl8139().is_some_and(|rtl8139| rtl8139.pci_address() == address) {
+        remove_rtl8139();
+    }
+}
+
+/// Handle the removal of the RTL8139 (e.g. by hot-unplugging). This is done by the poll thread,
+/// if the driver notices the removal, or by rescanning the PCI bus (see `remove_pci_device()`).
+///
+/// The interface is taken down and sockets bound to its addresses are failed, so that waiting threads
+/// return with an error instead of waiting forever: TCP sockets are aborted, UDP sockets are closed.
+/// ICMP sockets are not bound to an address, so they are only reset, when the last interface is gone.
+/// Sockets bound to any address stay usable, if there are other interfaces left.
+/// When the last interface is gone, the poll thread stops.
+pub fn remove_rtl8139() {
+    let Some(rtl8139) = RTL8139.write().take() else {
+        return;
+    };
+    rtl8139.mark_removed();
+    warn!("RTL8139 has been removed, taking down its interface");
+    stats::unregister("rtl8139");
+    event::notify(EventSource::Device);
+    event::notify(EventSource::Link);
+    if DHCP_LEASE.lock().take().is_some() {
+        event::notify(EventSource::Dhcp);
+    }
+
+    let root = root_namespace();
+    let (removed_addrs, interfaces_left) = {
+        let mut interfaces = root.interfaces.lock();
+        let removed_addrs: Vec<IpAddress> = interfaces.iter()
+            .filter(|interface| interface.is_rtl8139())
+            .flat_map(|interface| interface.iface.ip_addrs())
+            .map(IpCidr::address)
+            .collect();
+        interfaces.retain(|interface| !interface.is_rtl8139());
+        (removed_addrs, !interfaces.is_empty())
+    };
+    let affected = |addr: Option<IpAddress>| !interfaces_left || addr.is_some_and(|addr| removed_addrs.contains(&addr));
+
+    let mut sockets = root.sockets.lock();
+    for (handle, socket) in sockets.iter_mut() {
+        match socket {
+            socket::Socket::Tcp(socket) => {
+                let local_addr = socket.local_endpoint().map(|endpoint| endpoint.addr)
+                    .or(socket.listen_endpoint().addr);
+                if affected(local_addr) && socket.is_open() {
+                    info!("Aborting socket {} because its interface is gone", handle);
+                    socket.abort();
+                }
+            }
+            socket::Socket::Udp(socket) => {
+                if affected(socket.endpoint().addr) && socket.is_open() {
+                    info!("Closing socket {} because its interface is gone", handle);
+                    socket.close();
+                }
+            }
+            socket::Socket::Icmp(socket) => {
+                if affected(None) && socket.is_open() {
+                    info!("Unbinding socket {} because the last interface is gone", handle);
+                    // smoltcp can't unbind ICMP sockets, so the socket is replaced by an unbound one with the same buffers
+                    *socket = socket.with_buffers(socket.payload_recv_capacity());
+                }
+            }
+            _ => {}
+        }
     }
+    drop(sockets);
+
+    // let waiting accept and receive calls notice the aborted sockets
+    notify_sockets();
+}
+
+fn namespace(id: usize) -> Option<Arc<Namespace>> {
+    NAMESPACES.read().iter().find(|namespace| namespace.id() == id).cloned()
+}
+
+fn root_namespace() -> Arc<Namespace> {
+    namespace(ROOT_NAMESPACE).expect("Root network namespace not initialized!")
 }
 
-fn check_ownership(handle: SocketHandle) {
-    // TODO: these panics should probably kill the process that made the call, not the kernel
-    let lock = SOCKET_PROCESS.read();
-    let owning_process = lock
+/// Get the network namespace of the current process.
+fn current_namespace() -> Arc<Namespace> {
+    let id = process_manager().read().current_process().net_namespace();
+    namespace(id).expect("process is in a non-existent network namespace")
+}
+
+fn check_ownership(namespace: &Namespace, handle: SocketHandle) {
+    // handles from user space have already been checked by `resolve_handle()`, so this should never fail
+    let lock = namespace.owners.read();
+    let owner = lock
         .get(&handle)
         .expect("process tried accessing non-existent socket");
-    if *owning_process != process_manager().read().current_process() {
+    if owner.process != process_manager().read().current_process() {
         panic!("process tried to access socket of a different process");
     }
 }
 
+/// Record the current process as the owner of the new socket `handle`.
+fn add_owner(namespace: &Namespace, handle: SocketHandle) {
+    namespace.owners
+        .write()
+        .try_insert(handle, SocketOwner::new(process_manager().read().current_process()))
+        .expect("failed to insert socket into socket-process map");
+}
+
+/// Get the handle for user space of the socket `handle`, owned by the current process.
+///
+/// The lower bits contain smoltcp's handle, the upper bits its generation.
+/// This way, a handle that is used after the socket has been closed is detected,
+/// even if smoltcp has reused its handle for a new socket in the meantime.
+pub fn user_handle(handle: SocketHandle) -> usize {
+    // SocketHandle.0 is private, sadly, so just hope this works
+    let index = unsafe { core::mem::transmute::<SocketHandle, usize>(handle) };
+    assert!(index < 1 << HANDLE_INDEX_BITS, "too many sockets");
+    let generation = current_namespace().owners.read()
+        .get(&handle)
+        .expect("socket has no owner")
+        .generation;
+    generation << HANDLE_INDEX_BITS | index
+}
+
+/// Check a handle from user space (see `user_handle()`) and get the respective socket handle.
+///
+/// Fails if the socket has been closed in the meantime or belongs to another process.
+pub fn resolve_handle(user_handle: usize) -> Result<SocketHandle, HandleError> {
+    let index = user_handle & ((1 << HANDLE_INDEX_BITS) - 1);
+    let generation = user_handle >> HANDLE_INDEX_BITS;
+    let handle = unsafe { core::mem::transmute::<usize, SocketHandle>(index) };
+
+    let namespace = current_namespace();
+    let owners = namespace.owners.read();
+    match owners.get(&handle) {
+        Some(owner) if owner.generation == generation => {
+            if owner.process == process_manager().read().current_process() {
+                Ok(handle)
+            } else {
+                Err(HandleError::Foreign)
+            }
+        }
+        _ => Err(HandleError::Stale),
+    }
+}
+
 // for lifetime-reasons this must be a macro
 macro_rules! get_socket_for_current_process {
     ($socket:ident, $handle:ident, $type:ty) => {
-        check_ownership($handle);
-        let mut sockets = SOCKETS.get().expect("Socket set not initialized!").write();
+        let namespace = current_namespace();
+        check_ownership(&namespace, $handle);
+        let mut sockets = namespace.sockets.lock();
         let $socket = sockets.get_mut::<$type>($handle);
     }
 }
 
-fn add_interface(interface: Interface) {
-    INTERFACES.write().push(interface);
+/// Move the current process into a new network namespace with its own socket table and return the namespace's id.
+///
+/// If `flags` contains `NAMESPACE_WITH_INTERFACE`, the namespace is connected to the root namespace
+/// with a veth-like pair of interfaces: 10.77.<id>.1/24 in the root namespace and 10.77.<id>.2/24
+/// in the new one. There is no forwarding between namespaces, so only the root namespace is reachable.
+/// Without an interface, sockets in the namespace can't reach anything at all.
+///
+/// The process must not own any sockets. Child processes inherit the namespace.
+/// The previous namespace is dropped, if the process has been its last member.
+pub fn create_namespace(flags: usize) -> Result<usize, Errno> {
+    let process = process_manager().read().current_process();
+    // fail early, before creating any interfaces (this is checked again, when switching)
+    if owns_sockets(&current_namespace(), &process) {
+        return Err(Errno::EBUSY);
+    }
+
+    let creation = NAMESPACE_CREATION.lock();
+    let mut namespace = Namespace::create(free_namespace_id());
+    if flags & NAMESPACE_WITH_INTERFACE != 0 {
+        // the addresses are derived from the id, so there can only be 254 namespaces with an interface
+        let (root_addr, namespace_addr) = veth_addresses(namespace.id()).ok_or(Errno::EAGAIN)?;
+        let (root_end, namespace_end) = VethEnd::pair(namespace.id());
+
+        root_namespace().add_interface(NetDevice::Veth(Arc::clone(&root_end)), |interface| {
+            interface.update_ip_addrs(|addrs| addrs.push(root_addr).unwrap());
+        });
+        namespace.root_end = Some(root_end);
+        namespace.add_interface(NetDevice::Veth(namespace_end), |interface| {
+            interface.update_ip_addrs(|addrs| addrs.push(namespace_addr).unwrap());
+            if let IpAddress::Ipv4(gateway) = root_addr.address() {
+                interface.routes_mut().add_default_ipv4_route(gateway).unwrap();
+            }
+        });
+        start_poll_thread();
+    }
+
+    let id = namespace.id();
+    NAMESPACES.write().push(Arc::new(namespace));
+    drop(creation);
+
+    // The check and the switch happen while the sockets of the old namespace are locked,
+    // so another thread of the process can't open a socket in between (see `open_socket()`).
+    let previous = loop {
+        let current = current_namespace();
+        let sockets = current.sockets.lock();
+        if process.net_namespace() != current.id() {
+            // another thread of the process has switched the namespace in the meantime
+            continue;
+        }
+        if owns_sockets(&current, &process) {
+            drop(sockets);
+            release_namespace(id);
+            return Err(Errno::EBUSY);
+        }
+        break process.set_net_namespace(id);
+    };
+    release_namespace(previous);
+    info!("Process [{}] moved to network namespace [{}]", process.id(), id);
+
+    Ok(id)
+}
+
+/// The smallest id, that is not used by a live namespace: The ids of dropped namespaces are reused,
+/// since only 254 of them fit into the addresses of veth pairs (see `veth_addresses()`).
+fn free_namespace_id() -> usize {
+    let namespaces = NAMESPACES.read();
+    (ROOT_NAMESPACE + 1..)
+        .find(|id| namespaces.iter().all(|namespace| namespace.id() != *id))
+        .expect("no free network namespace id")
+}
+
+fn owns_sockets(namespace: &Namespace, process: &Arc<Process>) -> bool {
+    namespace.owners.read().values().any(|owner| owner.process == *process)
+}
+
+/// Put the new `process` into the network namespace `id` (child processes inherit the namespace of their parent).
+pub(crate) fn join_namespace(process: &Process, id: usize) {
+    if id != ROOT_NAMESPACE {
+        // the namespace can't be dropped, while the list is locked (see `release_namespace()`)
+        let namespaces = NAMESPACES.read();
+        let namespace = namespaces.iter()
+            .find(|namespace| namespace.id() == id)
+            .expect("process joined a non-existent network namespace");
+        namespace.members.fetch_add(1, Ordering::Relaxed);
+    }
+    process.set_net_namespace(id);
+}
+
+/// Remove an exited `process` from its network namespace (after its sockets have been closed).
+/// Calling this more than once is harmless, because the process is moved back into the root namespace.
+pub(crate) fn leave_namespace(process: &Process) {
+    release_namespace(process.set_net_namespace(ROOT_NAMESPACE));
+}
+
+/// A member has left the namespace `id`. The last member drops it, together with its end of the veth pair in the root namespace.
+fn release_namespace(id: usize) {
+    if id == ROOT_NAMESPACE {
+        return;
+    }
+
+    let namespace = {
+        let mut namespaces = NAMESPACES.write();
+        let Some(index) = namespaces.iter().position(|namespace| namespace.id() == id) else {
+            return;
+        };
+        if namespaces[index].members.fetch_sub(1, Ordering::Relaxed) > 1 {
+            return;
+        }
+        namespaces.remove(index)
+    };
+
+    if let Some(root_end) = &namespace.root_end {
+        root_namespace().interfaces.lock().retain(|interface| !interface.is_veth(root_end));
+        event::notify(EventSource::Link);
+    }
+    // closed sockets are only garbage collected by the poll thread
+    for (handle, _) in namespace.sockets.lock().iter() {
+        buffers::release(id, handle);
+    }
+    info!("Network namespace [{}] has been dropped", id);
 }
 
 /// Get IP addresses for a host.
@@ -130,10 +495,15 @@ pub fn get_ip_addresses(host: Option<&str>) -> Vec<IpAddress> {
     if let Some(host) = host {
         let handle = DNS_SOCKET.get().expect("DNS socket does not exist yet");
         // first, start the queries
+        // DNS queries are always sent from the root namespace
+        let root = root_namespace();
         let mut query_handles: Vec<_> = {
-            let mut interfaces = INTERFACES.write();
-            let interface = interfaces.get_mut(0).expect("network interface is missing");
-            let mut sockets = SOCKETS.get().expect("Socket set not initialized!").write();
+            let mut interfaces = root.interfaces.lock();
+            let Some(interface) = interfaces.get_mut(0).map(|interface| &mut interface.iface) else {
+                warn!("Can't resolve {host}: no network interface");
+                return Vec::new();
+            };
+            let mut sockets = root.sockets.lock();
             let socket = sockets.get_mut::<dns::Socket>(*handle);
             [DnsQueryType::Aaaa, DnsQueryType::A, DnsQueryType::Cname]
                 .into_iter()
@@ -150,9 +520,10 @@ pub fn get_ip_addresses(host: Option<&str>) -> Vec<IpAddress> {
         };
         // then, see if they've returned something
         let mut resulting_ips = Vec::new();
+        let pending = PendingGuard::register(None, PendingKind::Resolve);
         loop {
             {
-                let mut sockets = SOCKETS.get().expect("Socket set not initialized!").write();
+                let mut sockets = root.sockets.lock();
                 let socket = sockets.get_mut::<dns::Socket>(*handle);
                 let mut remaining: Vec<_> = query_handles
                     .drain(..)
@@ -181,76 +552,242 @@ pub fn get_ip_addresses(host: Option<&str>) -> Vec<IpAddress> {
                 query_handles.append(&mut remaining);
             }
             // release the locks and sleep
-            scheduler().sleep(50);
+            if pending.wait().is_err() {
+                // cancelled, return what we have so far
+                break;
+            }
         }
         resulting_ips
     } else {
-        INTERFACES
-            .read()
+        current_namespace()
+            .interfaces
+            .lock()
             .iter()
-            .flat_map(Interface::ip_addrs)
+            .flat_map(|interface| interface.iface.ip_addrs())
             .map(IpCidr::address)
             .collect()
     }
 }
 
-pub fn open_udp() -> SocketHandle {
-    let sockets = SOCKETS.get().expect("Socket set not initialized!");
+/// Add the address `cidr` to the interface with index `interface` in the network namespace of the current process.
+pub fn add_address(interface: usize, cidr: IpCidr) -> Result<(), Errno> {
+    let namespace = current_namespace();
+    let mut interfaces = namespace.interfaces.lock();
+    let iface = &mut interfaces.get_mut(interface).ok_or(Errno::ENOENT)?.iface;
 
-    let rx_buffer = udp::PacketBuffer::new(
-        vec![udp::PacketMetadata::EMPTY; 44],
-        vec![0; 65535],
-    );
-    let tx_buffer = udp::PacketBuffer::new(
-        vec![udp::PacketMetadata::EMPTY; 44],
-        vec![0; 65535],
-    );
+    if iface.has_ip_addr(cidr.address()) {
+        return Err(Errno::EEXIST);
+    }
+    let mut result = Ok(());
+    iface.update_ip_addrs(|addrs| {
+        // the number of addresses per interface is fixed in smoltcp
+        result = addrs.push(cidr).map_err(|_| Errno::ENOMEM);
+    });
+    if result.is_ok() {
+        info!("Added address {} to interface [{}]", cidr, interface);
+    }
+    result
+}
 
-    let handle = sockets.write().add(udp::Socket::new(rx_buffer, tx_buffer));
-    SOCKET_PROCESS
-        .write()
-        .try_insert(handle, process_manager().read().current_process())
-        .expect("failed to insert socket into socket-process map");
-    handle
+/// Remove the address `cidr` from the interface with index `interface` in the network namespace of the current process.
+/// Routes via gateways that are not reachable through the remaining addresses are removed as well.
+pub fn remove_address(interface: usize, cidr: IpCidr) -> Result<(), Errno> {
+    let namespace = current_namespace();
+    let mut interfaces = namespace.interfaces.lock();
+    let iface = &mut interfaces.get_mut(interface).ok_or(Errno::ENOENT)?.iface;
+
+    if !iface.ip_addrs().contains(&cidr) {
+        return Err(Errno::EADDRNOTAVAIL);
+    }
+    remove_interface_address(iface, cidr);
+    info!("Removed address {} from interface [{}]", cidr, interface);
+    Ok(())
 }
 
-pub fn open_tcp() -> SocketHandle {
-    let sockets = SOCKETS.get().expect("Socket set not initialized!");
-    let rx_buffer = tcp::SocketBuffer::new(vec![0; 65535]);
-    let tx_buffer = tcp::SocketBuffer::new(vec![0; 65535]);
+/// Remove `cidr` from `iface` and drop all routes whose gateway is no longer on a local subnet.
+fn remove_interface_address(iface: &mut smoltcp::iface::Interface, cidr: IpCidr) {
+    iface.update_ip_addrs(|addrs| addrs.retain(|addr| *addr != cidr));
 
-    let handle = sockets.write().add(tcp::Socket::new(rx_buffer, tx_buffer));
-    SOCKET_PROCESS
-        .write()
-        .try_insert(handle, process_manager().read().current_process())
-        .expect("failed to insert socket into socket-process map");
-    handle
-}
-
-pub fn open_icmp() -> SocketHandle {
-    let sockets = SOCKETS.get().expect("Socket set not initialized!");
-    
-    let rx_buffer = icmp::PacketBuffer::new(
-        vec![icmp::PacketMetadata::EMPTY, icmp::PacketMetadata::EMPTY],
-        vec![0; 65535],
-    );
-    let tx_buffer = icmp::PacketBuffer::new(
-        vec![icmp::PacketMetadata::EMPTY, icmp::PacketMetadata::EMPTY],
-        vec![0; 65535],
-    );
-
-    let handle = sockets.write().add(icmp::Socket::new(rx_buffer, tx_buffer));
-    SOCKET_PROCESS
-        .write()
-        .try_insert(handle, process_manager().read().current_process())
-        .expect("failed to insert socket into socket-process map");
-    handle
+    let remaining: Vec<IpCidr> = iface.ip_addrs().to_vec();
+    iface.routes_mut().update(|routes| {
+        routes.retain(|route| remaining.iter().any(|addr| addr.contains_addr(&route.via_router)));
+    });
+}
+
+/// Add a route to `cidr` via the gateway `via` to the interface with index `interface` in the network namespace of the current process.
+pub fn add_route(interface: usize, cidr: IpCidr, via: IpAddress) -> Result<(), Errno> {
+    if cidr.address().version() != via.version() || via.is_unspecified() {
+        return Err(Errno::EINVAL);
+    }
+    let namespace = current_namespace();
+    let mut interfaces = namespace.interfaces.lock();
+    let iface = &mut interfaces.get_mut(interface).ok_or(Errno::ENOENT)?.iface;
+
+    let mut result = Ok(());
+    iface.routes_mut().update(|routes| {
+        if routes.iter().any(|route| route.cidr == cidr) {
+            result = Err(Errno::EEXIST);
+            return;
+        }
+        let route = Route { cidr, via_router: via, preferred_until: None, expires_at: None };
+        // the number of routes per interface is fixed in smoltcp
+        result = routes.push(route).map_err(|_| Errno::ENOMEM);
+    });
+    if result.is_ok() {
+        info!("Added route to {} via {} to interface [{}]", cidr, via, interface);
+    }
+    result
+}
+
+/// Remove the route to `cidr` from the interface with index `interface` in the network namespace of the current process.
+pub fn remove_route(interface: usize, cidr: IpCidr) -> Result<(), Errno> {
+    let namespace = current_namespace();
+    let mut interfaces = namespace.interfaces.lock();
+    let iface = &mut interfaces.get_mut(interface).ok_or(Errno::ENOENT)?.iface;
+
+    let mut found = false;
+    iface.routes_mut().update(|routes| {
+        let len = routes.len();
+        routes.retain(|route| route.cidr != cidr);
+        found = routes.len() != len;
+    });
+    if !found {
+        return Err(Errno::ENOENT);
+    }
+    info!("Removed route to {} from interface [{}]", cidr, interface);
+    Ok(())
+}
+
+/// Get the routes of the interface with index `interface` in the network namespace of the current process.
+pub fn routes(interface: usize) -> Result<Vec<RouteEntry>, Errno> {
+    let namespace = current_namespace();
+    let mut interfaces = namespace.interfaces.lock();
+    let iface = &mut interfaces.get_mut(interface).ok_or(Errno::ENOENT)?.iface;
+
+    let mut entries = Vec::new();
+    iface.routes_mut().update(|routes| {
+        entries.extend(routes.iter().map(|route| RouteEntry::new(route.cidr.address().into(), route.cidr.prefix_len(), route.via_router.into())));
+    });
+    Ok(entries)
+}
+
+/// Set the DNS servers used by `get_ip_addresses()`. Only processes in the root namespace may do this,
+/// because the kernel's resolver is shared by all namespaces.
+pub fn set_dns_servers(servers: &[IpAddress]) -> Result<(), Errno> {
+    if servers.len() > MAX_DNS_SERVERS {
+        return Err(Errno::EINVAL);
+    }
+    if current_namespace().id() != ROOT_NAMESPACE {
+        return Err(Errno::EACCES);
+    }
+    let handle = DNS_SOCKET.get().ok_or(Errno::ENOTSUP)?;
+
+    root_namespace().sockets.lock().get_mut::<dns::Socket>(*handle).update_servers(servers);
+    info!("DNS servers: {:?}", servers);
+    Ok(())
+}
+
+/// Check whether the kernel's DHCP client is running (i.e. there is a physical interface).
+pub fn dhcp_enabled() -> bool {
+    DHCP_SOCKET.get().is_some()
+}
+
+/// Get the lease of the kernel's DHCP client, if it has one.
+pub fn dhcp_lease() -> Option<DhcpLease> {
+    *DHCP_LEASE.lock()
+}
+
+/// Drop the current DHCP lease and start over with discovering a DHCP server (e.g. after the link has come back).
+pub fn renew_dhcp() -> Result<(), Errno> {
+    let handle = DHCP_SOCKET.get().ok_or(Errno::ENOTSUP)?;
+    root_namespace().sockets.lock().get_mut::<dhcpv4::Socket>(*handle).reset();
+    info!("Restarting DHCP");
+    Ok(())
+}
+
+/// Check whether sockets can be bound to `addr`.
+/// This is the case for the unspecified address (meaning "any") and for addresses assigned to an interface
+/// in the network namespace of the current process.
+pub fn is_local_address(addr: IpAddress) -> bool {
+    addr.is_unspecified() || current_namespace().interfaces.lock().iter().any(|interface| interface.iface.has_ip_addr(addr))
+}
+
+/// Open a UDP socket. Its buffers are small until it is bound.
+pub fn open_udp() -> Result<SocketHandle, BufferLimitError> {
+    open_socket(udp::Socket::new(
+        udp::PacketBuffer::new(Vec::new(), Vec::new()),
+        udp::PacketBuffer::new(Vec::new(), Vec::new()),
+    ))
+}
+
+/// Open a TCP socket. Its buffers are small until it is bound or connected.
+pub fn open_tcp() -> Result<SocketHandle, BufferLimitError> {
+    open_socket(tcp::Socket::new(tcp::SocketBuffer::new(Vec::new()), tcp::SocketBuffer::new(Vec::new())))
+}
+
+/// Open an ICMP socket. Its buffers are small until it is bound.
+pub fn open_icmp() -> Result<SocketHandle, BufferLimitError> {
+    open_socket(icmp::Socket::new(
+        icmp::PacketBuffer::new(Vec::new(), Vec::new()),
+        icmp::PacketBuffer::new(Vec::new(), Vec::new()),
+    ))
+}
+
+/// Add a socket without buffers to the namespace of the current process and then give it
+/// buffers of the initial size. If this exceeds the limits, the socket is removed again.
+fn open_socket<T: ResizableSocket>(socket: T) -> Result<SocketHandle, BufferLimitError> {
+    let process = process_manager().read().current_process();
+    let (namespace, handle) = loop {
+        let namespace = current_namespace();
+        let mut sockets = namespace.sockets.lock();
+        // the process might have been moved into another namespace, before the sockets were locked (see `create_namespace()`)
+        if process.net_namespace() == namespace.id() {
+            let handle = sockets.add(socket);
+            add_owner(&namespace, handle);
+            drop(sockets);
+            break (namespace, handle);
+        }
+    };
+
+    if let Err(e) = resize_buffers::<T>(&namespace, handle, INITIAL_BUFFER_SIZE) {
+        warn!("Not enough socket buffer memory for a new socket: {:?}", e);
+        namespace.owners.write().remove(&handle);
+        namespace.sockets.lock().remove(handle);
+        buffers::release(namespace.id(), handle);
+        return Err(e);
+    }
+    Ok(handle)
+}
+
+/// Grow the buffers of a socket to their full size, before it is bound or connected.
+/// If this exceeds the limits, the socket just keeps its small buffers.
+fn grow_buffers<T: ResizableSocket>(handle: SocketHandle) {
+    let namespace = current_namespace();
+    check_ownership(&namespace, handle);
+    if !namespace.sockets.lock().get_mut::<T>(handle).is_unused() {
+        return;
+    }
+    if let Err(e) = resize_buffers::<T>(&namespace, handle, FULL_BUFFER_SIZE) {
+        info!("Socket {} keeps its small buffers: {:?}", handle, e);
+    }
+}
+
+/// Replace the buffers of an unused socket with new ones of `size` bytes (for each direction).
+fn resize_buffers<T: ResizableSocket>(namespace: &Namespace, handle: SocketHandle, size: usize) -> Result<(), BufferLimitError> {
+    let process_id = namespace.owners.read().get(&handle).expect("socket without owner").process.id();
+    buffers::charge(namespace.id(), handle, process_id, 2 * size)?;
+
+    let mut sockets = namespace.sockets.lock();
+    let socket = sockets.get_mut::<T>(handle);
+    *socket = socket.with_buffers(size);
+    Ok(())
 }
 
 pub fn close_socket(handle: SocketHandle) {
-    let mut sockets = SOCKETS.get().expect("Socket set not initialized!").write();
+    let namespace = current_namespace();
+    let mut sockets = namespace.sockets.lock();
 
-    check_ownership(handle);
+    check_ownership(&namespace, handle);
 
     let socket_ref = sockets.iter_mut()
         .find(|(h, _)| *h == handle)
@@ -268,10 +805,11 @@ pub fn close_socket(handle: SocketHandle) {
 
     // Remove permission for the process
     // The socket remains in the set until poll_sockets() garbage collects it.
-    SOCKET_PROCESS.write().remove(&handle).unwrap();
+    namespace.owners.write().remove(&handle).unwrap();
 }
 
 pub fn bind_udp(handle: SocketHandle, addr: IpAddress, port: u16) -> Result<(), udp::BindError> {
+    grow_buffers::<udp::Socket>(handle);
     get_socket_for_current_process!(socket, handle, udp::Socket);
     let port = pick_port(port);
     match addr {
@@ -284,6 +822,7 @@ pub fn bind_udp(handle: SocketHandle, addr: IpAddress, port: u16) -> Result<(),
 }
 
 pub fn bind_tcp(handle: SocketHandle, addr: IpAddress, port: u16) -> Result<(), tcp::ListenError> {
+    grow_buffers::<tcp::Socket>(handle);
     get_socket_for_current_process!(socket, handle, tcp::Socket);
     let port = pick_port(port);
     match addr {
@@ -296,6 +835,7 @@ pub fn bind_tcp(handle: SocketHandle, addr: IpAddress, port: u16) -> Result<(),
 }
 
 pub fn bind_icmp(handle: SocketHandle, ident: u16) -> Result<(), icmp::BindError> {
+    grow_buffers::<icmp::Socket>(handle);
     get_socket_for_current_process!(socket, handle, icmp::Socket);
     socket.bind(icmp::Endpoint::Ident(ident))
 }
@@ -303,35 +843,57 @@ pub fn bind_icmp(handle: SocketHandle, ident: u16) -> Result<(), icmp::BindError
 /// Accept a new connection from a TCP socket.
 /// 
 /// This returns the client that opened the new connection and a **new listening socket**.
-pub fn accept_tcp(handle: SocketHandle) -> Result<(IpEndpoint, SocketHandle), tcp::ConnectError> {
-    let (client, listen) = loop {
+/// The new socket is opened before waiting, so the connection can't get lost, because there are no buffers left for it.
+/// If the operation is cancelled while waiting (see `pending`), this returns `BlockingError::Interrupted`.
+pub fn accept_tcp(handle: SocketHandle) -> Result<(IpEndpoint, SocketHandle), BlockingError<AcceptError>> {
+    let listen_handle = open_tcp().map_err(|e| BlockingError::Socket(AcceptError::Buffers(e)))?;
+    let (client, listen) = match wait_for_connection(handle) {
+        Ok(connection) => connection,
+        Err(e) => {
+            close_socket(listen_handle);
+            return Err(e);
+        }
+    };
+    // now we have a socket that is connected
+    // and the new one takes over listening to be able to accept additional connections
+    bind_tcp(listen_handle, listen.addr.unwrap_or(
+        IpAddress::Ipv6(Ipv6Addr::UNSPECIFIED)
+    ), listen.port).expect("failed to create new listening socket");
+    Ok((client, listen_handle))
+}
+
+/// Wait until the listening TCP socket `handle` is connected and return the client and the endpoint, it has listened on.
+fn wait_for_connection(handle: SocketHandle) -> Result<(IpEndpoint, IpListenEndpoint), BlockingError<AcceptError>> {
+    let pending = PendingGuard::register(Some(handle), PendingKind::Accept);
+    loop {
         // this extra block is needed so that we don't block all sockets
         {
             get_socket_for_current_process!(socket, handle, tcp::Socket);
             if socket.is_active() {
-                break (
+                return Ok((
                     socket.remote_endpoint().expect("failed to get remote endpoint"),
                     socket.listen_endpoint(),
-                );
+                ));
+            }
+            // the socket has been aborted (e.g. because the interface is gone)
+            if !socket.is_open() {
+                return Err(BlockingError::Socket(AcceptError::Closed));
             }
         }
-        scheduler().sleep(100);
-    };
-    // now we have a socket that is connected
-    // but we need to have to create a new one to be able to accept additional connections
-    let listen_handle = open_tcp();
-    bind_tcp(listen_handle, listen.addr.unwrap_or(
-        IpAddress::Ipv6(Ipv6Addr::UNSPECIFIED)
-    ), listen.port).expect("failed to create new listening socket");
-    Ok((client, listen_handle))
+        pending.wait_for_event()?;
+    }
 }
 
-pub fn connect_tcp(handle: SocketHandle, host: IpAddress, port: u16) -> Result<IpEndpoint, tcp::ConnectError> {    get_socket_for_current_process!(socket, handle, tcp::Socket);
-    let mut interfaces = INTERFACES.write();
+pub fn connect_tcp(handle: SocketHandle, host: IpAddress, port: u16) -> Result<IpEndpoint, tcp::ConnectError> {
+    grow_buffers::<tcp::Socket>(handle);
+    // the interfaces are locked before the sockets (see `Namespace`)
+    let namespace = current_namespace();
+    let mut interfaces = namespace.interfaces.lock();
     let interface = interfaces.get_mut(0).ok_or(tcp::ConnectError::InvalidState)?;
+    get_socket_for_current_process!(socket, handle, tcp::Socket);
     let local_port = pick_port(0);
 
-    socket.connect(interface.context(), (host, port), local_port)?;
+    socket.connect(interface.iface.context(), (host, port), local_port)?;
     Ok(socket.local_endpoint().unwrap())
 }
 
@@ -340,19 +902,24 @@ pub fn send_datagram(handle: SocketHandle, destination: IpAddress, port: u16, da
     socket.send_slice(data, (destination, port))
 }
 
-pub fn send_tcp(handle: SocketHandle, data: &[u8]) -> Result<usize, tcp::SendError> {
+/// Wait until the socket can send and then send as much of `data` as fits into the buffer.
+/// Waiting can be cancelled (see `pending`).
+pub fn send_tcp(handle: SocketHandle, data: &[u8]) -> Result<usize, BlockingError<tcp::SendError>> {
+    let pending = PendingGuard::register(Some(handle), PendingKind::Send);
     loop {
         // this extra block is needed so that we don't block all sockets
         {
             get_socket_for_current_process!(socket, handle, tcp::Socket);
-            if socket.can_send() {
+            // if the connection has been closed, send_slice will tell us
+            if socket.can_send() || !socket.may_send() {
                 break;
             }
         }
-        scheduler().sleep(100);
+        pending.wait()?;
     }
+    drop(pending);
     get_socket_for_current_process!(socket, handle, tcp::Socket);
-    socket.send_slice(data)
+    socket.send_slice(data).map_err(BlockingError::Socket)
 }
 
 pub fn send_icmp(handle: SocketHandle, destination: IpAddress, data: &[u8]) -> Result<(), icmp::SendError> {
@@ -365,19 +932,45 @@ pub fn receive_datagram(handle: SocketHandle, data: &mut [u8]) -> Result<(usize,
     socket.recv_slice(data)
 }
 
-pub fn receive_tcp(handle: SocketHandle, data: &mut [u8]) -> Result<usize, tcp::RecvError> {
+/// Wait until the socket has data (or the connection is closed) and then receive it.
+/// Waiting can be cancelled (see `pending`).
+pub fn receive_tcp(handle: SocketHandle, data: &mut [u8]) -> Result<usize, BlockingError<tcp::RecvError>> {
+    let pending = PendingGuard::register(Some(handle), PendingKind::Receive);
     loop {
         // this extra block is needed so that we don't block all sockets
         {
             get_socket_for_current_process!(socket, handle, tcp::Socket);
-            if socket.can_recv() {
+            // if the remote host closed the connection, recv_slice will tell us
+            if socket.can_recv() || !socket.may_recv() {
                 break;
             }
         }
-        scheduler().sleep(100);
+        pending.wait_for_event()?;
     }
+    drop(pending);
     get_socket_for_current_process!(socket, handle, tcp::Socket);
-    socket.recv_slice(data)
+    socket.recv_slice(data).map_err(BlockingError::Socket)
+}
+
+/// Like `receive_tcp()`, but gives up, if nothing has arrived, when the system time reaches `deadline` (in ms).
+/// Returns `Ok(None)` in that case.
+pub fn receive_tcp_until(handle: SocketHandle, data: &mut [u8], deadline: usize) -> Result<Option<usize>, BlockingError<tcp::RecvError>> {
+    let pending = PendingGuard::register(Some(handle), PendingKind::Receive);
+    loop {
+        // this extra block is needed so that we don't block all sockets
+        {
+            get_socket_for_current_process!(socket, handle, tcp::Socket);
+            if socket.can_recv() || !socket.may_recv() {
+                break;
+            }
+        }
+        if !pending.wait_for_event_until(deadline)? {
+            return Ok(None);
+        }
+    }
+    drop(pending);
+    get_socket_for_current_process!(socket, handle, tcp::Socket);
+    socket.recv_slice(data).map(Some).map_err(BlockingError::Socket)
 }
 
 pub fn receive_icmp(handle: SocketHandle, data: &mut [u8]) -> Result<(usize, IpAddress), icmp::RecvError> {
@@ -385,6 +978,24 @@ pub fn receive_icmp(handle: SocketHandle, data: &mut [u8]) -> Result<(usize, IpA
     socket.recv_slice(data)
 }
 
+/// Set an option of a TCP socket. Options can be changed at any time, also while connected.
+pub fn set_tcp_option(handle: SocketHandle, option: TcpOption, value: usize) -> Result<(), Errno> {
+    get_socket_for_current_process!(socket, handle, tcp::Socket);
+    let duration = || (value != 0).then(|| Duration::from_millis(value as u64));
+    match option {
+        TcpOption::Timeout => socket.set_timeout(duration()),
+        TcpOption::KeepAlive => socket.set_keep_alive(duration()),
+        TcpOption::AckDelay => socket.set_ack_delay(duration()),
+        TcpOption::Nagle => socket.set_nagle_enabled(value != 0),
+        TcpOption::HopLimit => socket.set_hop_limit(match value {
+            0 => None,
+            1..=255 => Some(value as u8),
+            _ => return Err(Errno::EINVAL),
+        }),
+    }
+    Ok(())
+}
+
 pub fn can_recv(handle: SocketHandle, protocol: SocketType) -> bool {
     match protocol {
         SocketType::Udp => {
@@ -419,83 +1030,78 @@ pub fn can_send(handle: SocketHandle, protocol: SocketType) -> bool {
     }
 }
 
-/// Try to poll all sockets.
-/// 
-/// This returns None, if it failed to get all needed locks.
-/// This is needed, because we otherwise might get a deadlock, because an
-/// application has the lock on `sockets` while we have the lock on `interfaces`.
-fn poll_sockets() -> Option<()> {
-    let rtl8139 = RTL8139.get().expect("RTL8139 not initialized");
-    let mut interfaces = INTERFACES.try_write()?;
-    let mut sockets = SOCKETS.get().expect("Socket set not initialized!").try_write()?;
+/// Poll the interfaces of all network namespaces.
+fn poll_sockets() {
+    // don't block here, someone might be creating a namespace
+    let Some(namespaces) = NAMESPACES.try_read().map(|namespaces| namespaces.clone()) else {
+        return;
+    };
+
+    for namespace in namespaces {
+        poll_namespace(&namespace);
+    }
+}
+
+/// Poll all sockets of a namespace.
+///
+/// If an application holds the interfaces or sockets, this waits for it,
+/// lending it the high priority of the poll thread (see `Namespace`).
+fn poll_namespace(namespace: &Namespace) {
+    let mut interfaces = namespace.interfaces.lock();
+    let mut sockets = namespace.sockets.lock();
     let time = Instant::from_millis(timer().systime_ms() as i64);
 
-    // Smoltcp expects a mutable reference to the device, but the RTL8139 driver is built
-    // to work with a shared reference. We can safely cast the shared reference to a mutable.
-    let device = unsafe { ptr::from_ref(rtl8139.deref()).cast_mut().as_mut().unwrap() };
-    let interface = interfaces.get_mut(0).expect("failed to get interface");
-
-    let mut poll_budget = 16;
-    while poll_budget > 0 {
-        match interface.poll(time, device, &mut sockets) {
-            iface::PollResult::None => break,
-            iface::PollResult::SocketStateChanged => {
-                poll_budget -= 1;
-            },
-        }
+    let mut changed = false;
+    for interface in interfaces.iter_mut() {
+        changed |= interface.poll(time, &mut sockets);
     }
 
+    // the DHCP and DNS sockets only exist in the root namespace
+    let system_sockets = if namespace.id() == ROOT_NAMESPACE {
+        DHCP_SOCKET.get().zip(DNS_SOCKET.get())
+    } else {
+        None
+    };
+
     // DHCP handling is based on https://github.com/smoltcp-rs/smoltcp/blob/main/examples/dhcp_client.rs
-    let dhcp_handle = DHCP_SOCKET.get().expect("DHCP socket does not exist yet");
-    let dhcp_socket = sockets.get_mut::<dhcpv4::Socket>(*dhcp_handle);
-    if let Some(event) = dhcp_socket.poll() {
-        match event {
-            dhcpv4::Event::Deconfigured => {
-                info!("lost DHCP lease");
-                interface.update_ip_addrs(|addrs| addrs.clear());
-                interface.routes_mut().remove_default_ipv4_route();
-            },
-            dhcpv4::Event::Configured(config) => {
-                info!("acquired DHCP lease:");
-                info!("IP address: {}", config.address);
-                interface.update_ip_addrs(|addrs| {
-                    addrs.clear();
-                    addrs.push(IpCidr::Ipv4(config.address)).unwrap();
-                });
-
-                if let Some(router) = config.router {
-                    info!("default gateway: {router}");
-                    interface
-                        .routes_mut()
-                        .add_default_ipv4_route(router)
-                        .unwrap();
-                } else {
-                    info!("no default gateway");
-                    interface
-                        .routes_mut()
-                        .remove_default_ipv4_route();
-                }
-                info!("DNS servers: {:?}", config.dns_servers);
-                let dns_servers: Vec<_> = config.dns_servers
-                    .iter()
-                    .map(|ip| IpAddress::Ipv4(*ip))
-                    .collect();
-                let dns_handle = DNS_SOCKET.get().expect("DNS socket does not exist yet");
-                let dns_socket = sockets.get_mut::<dns::Socket>(*dns_handle);
-                dns_socket.update_servers(&dns_servers);
-            },
+    // The lease is only recorded here, applying addresses, routes and DNS servers is up to user space (netmand).
+    if let Some((dhcp_handle, _)) = system_sockets
+        && let Some(interface) = interfaces.iter().position(|interface| interface.is_rtl8139())
+    {
+        let dhcp_socket = sockets.get_mut::<dhcpv4::Socket>(*dhcp_handle);
+        if let Some(event) = dhcp_socket.poll() {
+            let lease = match event {
+                dhcpv4::Event::Deconfigured => {
+                    info!("lost DHCP lease");
+                    None
+                },
+                dhcpv4::Event::Configured(config) => {
+                    info!("acquired DHCP lease:");
+                    info!("IP address: {}", config.address);
+                    info!("default gateway: {:?}", config.router);
+                    info!("DNS servers: {:?}", config.dns_servers);
+                    Some(DhcpLease::new(
+                        interface as u32,
+                        config.address.address(),
+                        config.address.prefix_len(),
+                        config.router,
+                        &config.dns_servers,
+                    ))
+                },
+            };
+            *DHCP_LEASE.lock() = lease;
+            event::notify(EventSource::Dhcp);
         }
     }
 
     // Remove closed sockets
     let mut sockets_to_remove = Vec::new();
 
-    let socket_map = SOCKET_PROCESS.read();
-    let dns_handle = DNS_SOCKET.get().expect("DNS socket does not exist yet");
+    let socket_map = namespace.owners.read();
 
     for (handle, socket) in sockets.iter() {
         // Skip system sockets
-        if handle == *dhcp_handle || handle == *dns_handle {
+        if system_sockets.is_some_and(|(dhcp_handle, dns_handle)| handle == *dhcp_handle || handle == *dns_handle) {
             continue;
         }
 
@@ -516,23 +1122,66 @@ fn poll_sockets() -> Option<()> {
     for handle in sockets_to_remove {
         info!("Garbage collecting closed socket: {}", handle);
         sockets.remove(handle);
+        buffers::release(namespace.id(), handle);
     }
 
-    Some(())
+    // wake up threads blocked in accept or receive, after releasing the locks they need
+    drop(socket_map);
+    drop(sockets);
+    drop(interfaces);
+    if changed {
+        notify_sockets();
+    }
+}
+
+/// One line for each socket of each namespace (for the kernel shell). \
+/// Namespaces, whose sockets are locked, are only reported as such, so this doesn't hang, if the network stack does.
+pub fn socket_status() -> String {
+    let mut out = String::new();
+    let Some(namespaces) = NAMESPACES.try_read() else {
+        return String::from("Network namespaces are locked\n");
+    };
+
+    for namespace in namespaces.iter() {
+        let (Some(sockets), Some(owners)) = (namespace.sockets.try_lock(), namespace.owners.try_read()) else {
+            let _ = writeln!(out, "Namespace {}: sockets are locked", namespace.id());
+            continue;
+        };
+        for (handle, socket) in sockets.iter() {
+            let owner = owners.get(&handle).map_or(String::from("kernel"), |owner| owner.process.id().to_string());
+            let _ = write!(out, "Namespace: {}, Socket: {}, PID: {}, ", namespace.id(), handle, owner);
+            let _ = match socket {
+                socket::Socket::Tcp(socket) => writeln!(
+                    out, "TCP {} {} -> {}",
+                    socket.state(),
+                    socket.local_endpoint().map_or(socket.listen_endpoint().to_string(), |endpoint| endpoint.to_string()),
+                    socket.remote_endpoint().map_or(String::from("*"), |endpoint| endpoint.to_string()),
+                ),
+                socket::Socket::Udp(socket) => writeln!(out, "UDP {}", socket.endpoint()),
+                socket::Socket::Icmp(socket) => writeln!(out, "ICMP {}", if socket.is_open() { "bound" } else { "unbound" }),
+                _ => writeln!(out, "system"),
+            };
+        }
+    }
+    out
 }
 
-pub(crate) fn close_sockets_for_process(process: &mut Process) {
-    let mut lock = SOCKET_PROCESS.write();
-    let mut sockets = SOCKETS.get().expect("Socket set not initialized!").write();
+pub(crate) fn close_sockets_for_process(process: &Process) {
+    let Some(namespace) = namespace(process.net_namespace()) else {
+        return;
+    };
+    let mut sockets = namespace.sockets.lock();
+    let mut lock = namespace.owners.write();
     let handles: Vec<_> = lock
         .iter()
-        .filter(|(_handle, proc)| ***proc == *process)
-        .map(|(handle, _proc)| handle)
+        .filter(|(_handle, owner)| *owner.process == *process)
+        .map(|(handle, _owner)| handle)
         .copied()
         .collect();
     for handle in handles {
         lock.remove(&handle).unwrap();
         sockets.remove(handle);
+        buffers::release(namespace.id(), handle);
     }
 }
 
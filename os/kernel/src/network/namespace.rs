@@ -0,0 +1,273 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: namespace                                                       ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Experimental network namespaces ("namespace-lite").             ║
+   ║         Each namespace has its own socket table and interfaces, so      ║
+   ║         processes in different namespaces can't see each other's        ║
+   ║         sockets or bind conflicting ports. A new namespace may get a    ║
+   ║         veth-like pair of virtual interfaces, connecting it to the root ║
+   ║         namespace. Processes inherit the namespace of their parent.     ║
+   ║         A namespace is dropped together with its last member.           ║
+   ║         There is no routing between namespaces and DNS queries are      ║
+   ║         always handled by the root namespace.                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Deref;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use smoltcp::iface::{self, Interface, SocketHandle, SocketSet};
+use smoltcp::phy::{self, DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+use smoltcp::wire::{EthernetAddress, HardwareAddress, IpCidr, Ipv4Address};
+use syscall::event::EventSource;
+use log::warn;
+use spin::{Mutex, RwLock};
+use crate::device::rtl8139::Rtl8139;
+use crate::network::pmtu;
+use crate::process::process::Process;
+use crate::sync::event;
+use crate::sync::pi_mutex::PiMutex;
+use crate::{entropy_pool, timer};
+
+/// Id of the namespace, that all processes start in.
+pub const ROOT_NAMESPACE: usize = 0;
+
+/// Frames that are not picked up by the other end of a veth pair are dropped when the queue is full.
+const VETH_QUEUE_CAP: usize = 64;
+const VETH_MTU: usize = 1514;
+
+static SOCKET_GENERATION_COUNTER: AtomicUsize = AtomicUsize::new(1);
+
+/// The interfaces and sockets are locked for a long time (e.g. while the poll thread processes packets),
+/// so they are protected by `PiMutex`es: Waiting threads block instead of spinning through their time slice,
+/// while a preempted owner waits for the CPU. And instead of skipping a namespace, whose locks are held
+/// by an application (delaying its packets until the next round), the poll thread waits for them,
+/// and the application runs with the poll thread's priority until it has released them.
+/// They must be locked in the order `interfaces`, `sockets`, `owners`.
+pub struct Namespace {
+    id: usize,
+    pub(super) interfaces: PiMutex<Vec<NetInterface>>,
+    pub(super) sockets: PiMutex<SocketSet<'static>>,
+    /// This maps sockets to the respective process.
+    /// We use this to check whether a process can access a particular socket.
+    /// We can't just create a SocketSet per process because smoltcp drops all
+    /// packets for non-existing sockets when polling.
+    pub(super) owners: RwLock<BTreeMap<SocketHandle, SocketOwner>>,
+    /// Number of processes in the namespace (not counted for the root namespace, which is never dropped)
+    pub(super) members: AtomicUsize,
+    /// The end of the veth pair in the root namespace (removed together with this namespace)
+    pub(super) root_end: Option<Arc<VethEnd>>,
+}
+
+/// The process owning a socket and the generation of the socket's handle.
+/// smoltcp reuses handles after a socket has been removed, but generations are never reused.
+pub(super) struct SocketOwner {
+    pub process: Arc<Process>,
+    pub generation: usize,
+}
+
+/// A smoltcp interface together with the device it sends and receives frames on.
+pub(super) struct NetInterface {
+    pub iface: Interface,
+    pub device: NetDevice,
+}
+
+pub(super) enum NetDevice {
+    Rtl8139(Arc<Rtl8139>),
+    Veth(Arc<VethEnd>),
+}
+
+/// One end of a veth pair. Frames transmitted on one end are received on the other.
+pub struct VethEnd {
+    mac: EthernetAddress,
+    rx: Arc<Mutex<VecDeque<Vec<u8>>>>,
+    tx: Arc<Mutex<VecDeque<Vec<u8>>>>,
+}
+
+/// smoltcp wants a mutable device, but a veth end is shared between the interface and its peer.
+pub struct VethDevice<'a>(&'a VethEnd);
+
+pub struct VethRxToken(Vec<u8>);
+
+pub struct VethTxToken<'a>(&'a Mutex<VecDeque<Vec<u8>>>);
+
+impl Namespace {
+    pub(super) fn new(id: usize) -> Self {
+        Self {
+            id,
+            interfaces: PiMutex::new(Vec::new()),
+            sockets: PiMutex::new(SocketSet::new(Vec::new())),
+            owners: RwLock::new(BTreeMap::new()),
+            members: AtomicUsize::new(0),
+            root_end: None,
+        }
+    }
+
+    /// Create a new, empty namespace with the unused id `id` and the creating process as its only member.
+    pub(super) fn create(id: usize) -> Self {
+        let namespace = Self::new(id);
+        namespace.members.store(1, Ordering::Relaxed);
+        namespace
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub(super) fn add_interface(&self, device: NetDevice, configure: impl FnOnce(&mut Interface)) {
+        let time = timer().systime_ms();
+        let mut iface = match &device {
+            NetDevice::Rtl8139(rtl8139) => {
+                let mut conf = iface::Config::new(HardwareAddress::from(rtl8139.read_mac_address()));
+                conf.random_seed = random_seed(time);
+                // The Smoltcp interface struct wants a mutable reference to the device.
+                // However, the RTL8139 driver is designed to work with shared references.
+                // Since smoltcp does not actually store the mutable reference anywhere,
+                // we can safely cast the shared reference to a mutable one.
+                let device = unsafe { ptr::from_ref(rtl8139.deref()).cast_mut().as_mut().unwrap() };
+                Interface::new(conf, device, Instant::from_millis(time as i64))
+            }
+            NetDevice::Veth(veth) => {
+                let mut conf = iface::Config::new(HardwareAddress::from(veth.mac));
+                conf.random_seed = random_seed(time);
+                Interface::new(conf, &mut VethDevice(veth), Instant::from_millis(time as i64))
+            }
+        };
+        configure(&mut iface);
+
+        self.interfaces.lock().push(NetInterface { iface, device });
+        event::notify(EventSource::Link);
+    }
+}
+
+/// Seed for smoltcp's random number generator (used e.g. for TCP sequence numbers and DHCP transaction ids).
+/// Falls back to the system time, if the entropy pool has not been seeded yet.
+fn random_seed(time: usize) -> u64 {
+    let mut seed = [0u8; 8];
+    match entropy_pool().get_random(&mut seed, true) {
+        Ok(_) => u64::from_ne_bytes(seed),
+        Err(_) => {
+            warn!("Entropy pool not seeded yet, using system time as network seed");
+            time as u64
+        }
+    }
+}
+
+impl SocketOwner {
+    /// Create an owner entry with a new, unique generation.
+    pub(super) fn new(process: Arc<Process>) -> Self {
+        Self { process, generation: SOCKET_GENERATION_COUNTER.fetch_add(1, Ordering::Relaxed) }
+    }
+}
+
+impl NetInterface {
+    /// Poll the interface with the given sockets until nothing happens anymore (or the budget is exhausted).
+    /// Returns true, if the state of any socket might have changed.
+    pub(super) fn poll(&mut self, time: Instant, sockets: &mut SocketSet<'static>) -> bool {
+        let mut poll_budget = 16;
+        let mut changed = false;
+        while poll_budget > 0 {
+            let result = match &self.device {
+                NetDevice::Rtl8139(rtl8139) => {
+                    // Smoltcp expects a mutable reference to the device, but the RTL8139 driver is built
+                    // to work with a shared reference. We can safely cast the shared reference to a mutable.
+                    let device = unsafe { ptr::from_ref(rtl8139.deref()).cast_mut().as_mut().unwrap() };
+                    self.iface.poll(time, device, sockets)
+                }
+                NetDevice::Veth(veth) => self.iface.poll(time, &mut VethDevice(veth), sockets),
+            };
+
+            match result {
+                iface::PollResult::None => break,
+                iface::PollResult::SocketStateChanged => {
+                    changed = true;
+                    poll_budget -= 1;
+                }
+            }
+        }
+
+        changed
+    }
+
+    pub(super) fn is_rtl8139(&self) -> bool {
+        matches!(self.device, NetDevice::Rtl8139(_))
+    }
+
+    pub(super) fn is_veth(&self, end: &Arc<VethEnd>) -> bool {
+        matches!(&self.device, NetDevice::Veth(veth) if Arc::ptr_eq(veth, end))
+    }
+}
+
+impl VethEnd {
+    /// Create a connected pair of veth ends. `id` is used to generate (locally administered) MAC addresses.
+    pub fn pair(id: usize) -> (Arc<VethEnd>, Arc<VethEnd>) {
+        let a_to_b = Arc::new(Mutex::new(VecDeque::new()));
+        let b_to_a = Arc::new(Mutex::new(VecDeque::new()));
+        let mac = |end: u8| EthernetAddress([0x02, 0x00, 0x00, (id >> 8) as u8, id as u8, end]);
+
+        let a = VethEnd { mac: mac(1), rx: Arc::clone(&b_to_a), tx: Arc::clone(&a_to_b) };
+        let b = VethEnd { mac: mac(2), rx: a_to_b, tx: b_to_a };
+        (Arc::new(a), Arc::new(b))
+    }
+}
+
+impl phy::RxToken for VethRxToken {
+    fn consume<R, F>(mut self, f: F) -> R
+    where F: FnOnce(&[u8]) -> R {
+        pmtu::process_received_frame(&mut self.0);
+        f(&self.0)
+    }
+}
+
+impl<'a> phy::TxToken for VethTxToken<'a> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where F: FnOnce(&mut [u8]) -> R {
+        let mut frame = vec![0; len];
+        let result = f(&mut frame);
+        pmtu::process_transmitted_frame(&mut frame);
+
+        let mut queue = self.0.lock();
+        if queue.len() < VETH_QUEUE_CAP {
+            queue.push_back(frame);
+        }
+
+        result
+    }
+}
+
+impl<'a> phy::Device for VethDevice<'a> {
+    type RxToken<'b> = VethRxToken where Self: 'b;
+    type TxToken<'b> = VethTxToken<'b> where Self: 'b;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let frame = self.0.rx.lock().pop_front()?;
+        Some((VethRxToken(frame), VethTxToken(&self.0.tx)))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        Some(VethTxToken(&self.0.tx))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = VETH_MTU;
+        caps.medium = Medium::Ethernet;
+        caps
+    }
+}
+
+/// Addresses of a veth pair for namespace `id`: 10.77.<id>.1/24 in the root namespace and 10.77.<id>.2/24 in the new one.
+pub(super) fn veth_addresses(id: usize) -> Option<(IpCidr, IpCidr)> {
+    let subnet = u8::try_from(id).ok().filter(|subnet| *subnet != 0 && *subnet != 255)?;
+    Some((
+        IpCidr::new(Ipv4Address::new(10, 77, subnet, 1).into(), 24),
+        IpCidr::new(Ipv4Address::new(10, 77, subnet, 2).into(), 24),
+    ))
+}
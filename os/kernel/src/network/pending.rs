@@ -0,0 +1,219 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: pending                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Registry of blocking socket operations (accept, send, receive   ║
+   ║         and DNS lookups). Each waiting thread registers a cancellation  ║
+   ║         token, which is checked every time the thread wakes up. If the  ║
+   ║         operation is cancelled, the thread returns with `Interrupted`.  ║
+   ║         If it has been killed, it exits instead of returning to user    ║
+   ║         mode. Accept and receive block on a wait queue, which is woken  ║
+   ║         up by the poll thread, whenever the state of a socket changed.  ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use smoltcp::iface::SocketHandle;
+use spin::Mutex;
+use syscall::return_vals::Errno;
+use crate::scheduler;
+use crate::sync::wait_queue::WaitQueue;
+
+/// Pending operations of all threads. A thread can only wait for one operation at a time.
+static PENDING: Mutex<Vec<(PendingOperation, CancellationToken)>> = Mutex::new(Vec::new());
+
+/// Threads waiting for any socket to change its state (or for their operation to be cancelled).
+static SOCKET_EVENTS: WaitQueue = WaitQueue::new();
+
+/// Incremented every time the poll thread reports a socket state change.
+static SOCKET_EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+/// How long a waiting thread sleeps before it checks its socket (and its token) again.
+const WAIT_INTERVAL_MS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PendingKind {
+    Accept,
+    Connect,
+    Send,
+    Receive,
+    Resolve,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CancelReason {
+    /// The operation has been cancelled, the thread returns with `Interrupted`.
+    Cancelled = 1,
+    /// The thread has been killed and exits as soon as it wakes up.
+    Killed = 2,
+}
+
+/// A blocking socket operation, as seen from the outside.
+#[derive(Debug, Clone, Copy)]
+pub struct PendingOperation {
+    pub thread_id: usize,
+    pub process_id: usize,
+    /// `None` for DNS lookups, which use the shared DNS socket, and for vsock sockets.
+    pub handle: Option<SocketHandle>,
+    pub kind: PendingKind,
+}
+
+/// The operation has been cancelled before it could complete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupted;
+
+impl From<Interrupted> for Errno {
+    fn from(_: Interrupted) -> Self {
+        Errno::EINTR
+    }
+}
+
+#[derive(Clone)]
+pub struct CancellationToken(Arc<AtomicU8>);
+
+/// Registration of the current thread's blocking operation. It is removed again, when this is dropped.
+pub(super) struct PendingGuard {
+    thread_id: usize,
+    token: CancellationToken,
+    /// Socket epoch at the time the socket was last checked.
+    seen: Cell<usize>,
+}
+
+impl CancellationToken {
+    fn new() -> Self {
+        Self(Arc::new(AtomicU8::new(0)))
+    }
+
+    /// Cancel the operation. The first reason wins.
+    pub fn cancel(&self, reason: CancelReason) {
+        let _ = self.0.compare_exchange(0, reason as u8, Ordering::AcqRel, Ordering::Acquire);
+    }
+
+    pub fn reason(&self) -> Option<CancelReason> {
+        match self.0.load(Ordering::Acquire) {
+            1 => Some(CancelReason::Cancelled),
+            2 => Some(CancelReason::Killed),
+            _ => None,
+        }
+    }
+}
+
+impl PendingGuard {
+    /// Register a blocking operation for the current thread.
+    pub(super) fn register(handle: Option<SocketHandle>, kind: PendingKind) -> Self {
+        let (process_id, thread_id) = scheduler().current_ids();
+        let token = CancellationToken::new();
+        let operation = PendingOperation { thread_id, process_id, handle, kind };
+
+        let mut pending = PENDING.lock();
+        pending.retain(|(op, _)| op.thread_id != thread_id);
+        pending.push((operation, token.clone()));
+
+        Self { thread_id, token, seen: Cell::new(SOCKET_EPOCH.load(Ordering::Acquire)) }
+    }
+
+    /// Sleep until the socket should be checked again.
+    /// Returns `Err(Interrupted)` if the operation has been cancelled in the meantime.
+    /// If the thread has been killed, this does not return.
+    pub(super) fn wait(&self) -> Result<(), Interrupted> {
+        self.check()?;
+        scheduler().sleep(WAIT_INTERVAL_MS);
+        self.check()
+    }
+
+    /// Block until the state of a socket has changed since the last call (or since registration).
+    /// Returns `Err(Interrupted)` if the operation has been cancelled in the meantime.
+    /// If the thread has been killed, this does not return.
+    pub(super) fn wait_for_event(&self) -> Result<(), Interrupted> {
+        self.check()?;
+        let seen = self.seen.get();
+        // Only look at atomics here, the wait queue is locked while the condition is evaluated
+        SOCKET_EVENTS.wait_until(|| SOCKET_EPOCH.load(Ordering::Acquire) != seen || self.token.reason().is_some());
+        self.seen.set(SOCKET_EPOCH.load(Ordering::Acquire));
+        self.check()
+    }
+
+    /// Like `wait_for_event()`, but gives up, when the system time reaches `deadline` (in ms).
+    /// Returns `Ok(false)` in that case.
+    pub(super) fn wait_for_event_until(&self, deadline: usize) -> Result<bool, Interrupted> {
+        self.check()?;
+        let seen = self.seen.get();
+        let changed = SOCKET_EVENTS.wait_until_deadline(deadline, || {
+            SOCKET_EPOCH.load(Ordering::Acquire) != seen || self.token.reason().is_some()
+        });
+        self.seen.set(SOCKET_EPOCH.load(Ordering::Acquire));
+        self.check()?;
+        Ok(changed)
+    }
+
+    fn check(&self) -> Result<(), Interrupted> {
+        match self.token.reason() {
+            None => Ok(()),
+            Some(CancelReason::Cancelled) => Err(Interrupted),
+            Some(CancelReason::Killed) => {
+                // exit() does not return, so we have to unregister manually
+                unregister(self.thread_id);
+                scheduler().exit();
+            }
+        }
+    }
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        unregister(self.thread_id);
+    }
+}
+
+fn unregister(thread_id: usize) {
+    PENDING.lock().retain(|(op, _)| op.thread_id != thread_id);
+}
+
+/// Get all blocking socket operations, optionally only those of the process `process_id`.
+pub fn pending_operations(process_id: Option<usize>) -> Vec<PendingOperation> {
+    PENDING.lock()
+        .iter()
+        .map(|(op, _)| *op)
+        .filter(|op| process_id.is_none_or(|pid| op.process_id == pid))
+        .collect()
+}
+
+/// Wake up all threads blocked in `PendingGuard::wait_for_event()`, so they check their sockets again.
+/// Called by the poll thread after the state of a socket has changed.
+pub(super) fn notify_sockets() {
+    SOCKET_EPOCH.fetch_add(1, Ordering::AcqRel);
+    SOCKET_EVENTS.wake_all();
+}
+
+/// Cancel the blocking socket operation of thread `thread_id`.
+/// Returns `true` if the thread was waiting for one. It will notice the cancellation the next time it wakes up.
+pub fn cancel(thread_id: usize, reason: CancelReason) -> bool {
+    let found = PENDING.lock()
+        .iter()
+        .find(|(op, _)| op.thread_id == thread_id)
+        .map(|(_, token)| token.cancel(reason))
+        .is_some();
+    if found {
+        SOCKET_EVENTS.wake_all();
+    }
+
+    found
+}
+
+/// Cancel all blocking socket operations of process `process_id` and return how many were cancelled.
+pub fn cancel_for_process(process_id: usize, reason: CancelReason) -> usize {
+    let count = PENDING.lock()
+        .iter()
+        .filter(|(op, _)| op.process_id == process_id)
+        .inspect(|(_, token)| token.cancel(reason))
+        .count();
+    if count > 0 {
+        SOCKET_EVENTS.wake_all();
+    }
+
+    count
+}
@@ -0,0 +1,261 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: pmtu                                                            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Path MTU discovery (RFC 1191 and RFC 8201). Received ICMPv4     ║
+   ║         "fragmentation needed" and ICMPv6 "packet too big" messages     ║
+   ║         are recorded in a cache of per-destination MTUs, which age out  ║
+   ║         after ten minutes, so larger MTUs are probed again.             ║
+   ║         smoltcp has no way to change the segment size of a socket, so   ║
+   ║         the MSS option of received SYN segments from a cached           ║
+   ║         destination is clamped instead. This way, new TCP connections   ║
+   ║         over the path never send segments, that don't fit. Existing     ║
+   ║         connections keep their MSS, but for IPv4 the "don't fragment"   ║
+   ║         flag of packets, that are too big for the path, is cleared, so  ║
+   ║         routers fragment them and the transfer doesn't stall.           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use log::info;
+use smoltcp::wire::{
+    EthernetFrame, EthernetProtocol, Icmpv4Message, Icmpv4Packet, Icmpv6Message, Icmpv6Packet, IpAddress, IpProtocol, Ipv4Packet, Ipv6Packet,
+    TcpPacket,
+};
+use spin::Mutex;
+use syscall::network::PathMtuEntry;
+use crate::timer;
+
+/// Cached MTUs are forgotten after this time (the value suggested by RFC 1191)
+const PMTU_TIMEOUT_MS: usize = 10 * 60 * 1000;
+/// Maximum number of cached destinations. If the cache is full, the oldest entry is replaced.
+const MAX_ENTRIES: usize = 128;
+/// Smaller reported MTUs are ignored, so forged messages can't make us send tiny segments
+const MIN_IPV4_MTU: usize = 552;
+const MIN_IPV6_MTU: usize = 1280;
+/// MTU of Ethernet. Reports above this don't reduce anything.
+const MAX_MTU: usize = 1500;
+/// Code of "fragmentation needed and DF set" for ICMPv4 "destination unreachable"
+const ICMPV4_FRAG_NEEDED: u8 = 4;
+/// Plateaus from RFC 1191, used if an old router reports an MTU of 0
+const PLATEAUS: &[usize] = &[1492, 1006, 576];
+
+static CACHE: Mutex<BTreeMap<IpAddr, CachedMtu>> = Mutex::new(BTreeMap::new());
+
+#[derive(Clone, Copy)]
+struct CachedMtu {
+    mtu: usize,
+    learned_ms: usize,
+}
+
+/// Get the cached path MTU towards `destination`, if there is one.
+pub fn lookup(destination: IpAddr) -> Option<usize> {
+    let now = timer().systime_ms();
+    let mut cache = CACHE.lock();
+    match cache.get(&destination) {
+        Some(entry) if now - entry.learned_ms < PMTU_TIMEOUT_MS => Some(entry.mtu),
+        Some(_) => {
+            cache.remove(&destination);
+            None
+        }
+        None => None,
+    }
+}
+
+/// Get all cached path MTUs (expired ones are removed).
+pub fn entries() -> Vec<PathMtuEntry> {
+    let now = timer().systime_ms();
+    let mut cache = CACHE.lock();
+    cache.retain(|_, entry| now - entry.learned_ms < PMTU_TIMEOUT_MS);
+    cache.iter()
+        .map(|(destination, entry)| PathMtuEntry::new(*destination, entry.mtu as u32, (now - entry.learned_ms) as u32))
+        .collect()
+}
+
+/// Called by drivers for each received Ethernet `frame`, before it is handed to smoltcp.
+/// Records path MTUs from ICMP messages and clamps the MSS option of SYN segments (adjusting the checksum).
+pub fn process_received_frame(frame: &mut [u8]) {
+    let Ok(mut frame) = EthernetFrame::new_checked(frame) else {
+        return;
+    };
+
+    match frame.ethertype() {
+        EthernetProtocol::Ipv4 => {
+            let Ok(mut packet) = Ipv4Packet::new_checked(frame.payload_mut()) else {
+                return;
+            };
+            if packet.more_frags() || packet.frag_offset() != 0 {
+                return;
+            }
+
+            let src = packet.src_addr();
+            let dst = packet.dst_addr();
+            match packet.next_header() {
+                IpProtocol::Icmp => process_icmpv4(packet.payload()),
+                IpProtocol::Tcp => clamp_mss(packet.payload_mut(), IpAddress::Ipv4(src), IpAddress::Ipv4(dst)),
+                _ => {}
+            }
+        }
+        EthernetProtocol::Ipv6 => {
+            let Ok(mut packet) = Ipv6Packet::new_checked(frame.payload_mut()) else {
+                return;
+            };
+
+            let src = packet.src_addr();
+            let dst = packet.dst_addr();
+            // extension headers are not handled here
+            match packet.next_header() {
+                IpProtocol::Icmpv6 => process_icmpv6(packet.payload(), &src, &dst),
+                IpProtocol::Tcp => clamp_mss(packet.payload_mut(), IpAddress::Ipv6(src), IpAddress::Ipv6(dst)),
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Called by drivers for each Ethernet `frame` written by smoltcp, before it is sent.
+/// IPv4 packets, that don't fit the cached path MTU, may be fragmented by routers.
+pub fn process_transmitted_frame(frame: &mut [u8]) {
+    let Ok(mut frame) = EthernetFrame::new_checked(frame) else {
+        return;
+    };
+    if frame.ethertype() != EthernetProtocol::Ipv4 {
+        return;
+    }
+    let Ok(mut packet) = Ipv4Packet::new_checked(frame.payload_mut()) else {
+        return;
+    };
+    if !packet.dont_frag() {
+        return;
+    }
+
+    if lookup(IpAddr::V4(packet.dst_addr())).is_some_and(|mtu| (packet.total_len() as usize) > mtu) {
+        packet.set_dont_frag(false);
+        packet.fill_checksum();
+    }
+}
+
+fn process_icmpv4(payload: &[u8]) {
+    let Ok(icmp) = Icmpv4Packet::new_checked(payload) else {
+        return;
+    };
+    if icmp.msg_type() != Icmpv4Message::DstUnreachable || icmp.msg_code() != ICMPV4_FRAG_NEEDED || !icmp.verify_checksum() {
+        return;
+    }
+
+    // Die ICMP-Nachricht enthält den Anfang des verworfenen Pakets, dessen Ziel uns interessiert
+    let original = icmp.data();
+    if original.len() < 20 || original[0] >> 4 != 4 {
+        return;
+    }
+    let destination = Ipv4Addr::new(original[16], original[17], original[18], original[19]);
+
+    // the next-hop MTU is in the second half of the "unused" field (RFC 1191)
+    let mtu = match u16::from_be_bytes([payload[6], payload[7]]) as usize {
+        0 => {
+            let total_len = u16::from_be_bytes([original[2], original[3]]) as usize;
+            PLATEAUS.iter().copied().find(|plateau| *plateau < total_len).unwrap_or(MIN_IPV4_MTU)
+        }
+        mtu => mtu,
+    };
+    record(IpAddr::V4(destination), mtu.max(MIN_IPV4_MTU));
+}
+
+fn process_icmpv6(payload: &[u8], src: &Ipv6Addr, dst: &Ipv6Addr) {
+    let Ok(icmp) = Icmpv6Packet::new_checked(payload) else {
+        return;
+    };
+    if icmp.msg_type() != Icmpv6Message::PktTooBig || !icmp.verify_checksum(src, dst) {
+        return;
+    }
+
+    let original = icmp.payload();
+    if original.len() < 40 || original[0] >> 4 != 6 {
+        return;
+    }
+    let mut destination = [0; 16];
+    destination.copy_from_slice(&original[24..40]);
+
+    let mtu = usize::try_from(icmp.pkt_too_big_mtu()).unwrap_or(usize::MAX);
+    record(IpAddr::V6(Ipv6Addr::from(destination)), mtu.max(MIN_IPV6_MTU));
+}
+
+/// Remember `mtu` for `destination`. The path MTU only decreases, until the entry has aged out.
+fn record(destination: IpAddr, mtu: usize) {
+    if mtu >= MAX_MTU {
+        return;
+    }
+
+    let now = timer().systime_ms();
+    let mut cache = CACHE.lock();
+    if let Some(entry) = cache.get_mut(&destination) && now - entry.learned_ms < PMTU_TIMEOUT_MS {
+        if mtu < entry.mtu {
+            *entry = CachedMtu { mtu, learned_ms: now };
+            info!("Path MTU to [{}] reduced to [{}]", destination, mtu);
+        }
+        return;
+    }
+
+    if cache.len() >= MAX_ENTRIES && !cache.contains_key(&destination) {
+        let oldest = cache.iter()
+            .min_by_key(|(_, entry)| entry.learned_ms)
+            .map(|(destination, _)| *destination);
+        if let Some(oldest) = oldest {
+            cache.remove(&oldest);
+        }
+    }
+    cache.insert(destination, CachedMtu { mtu, learned_ms: now });
+    info!("Path MTU to [{}] is [{}]", destination, mtu);
+}
+
+/// Reduce the MSS option of a SYN segment from a destination with a cached path MTU, so smoltcp's replies fit.
+fn clamp_mss(payload: &mut [u8], src: IpAddress, dst: IpAddress) {
+    let Ok(segment) = TcpPacket::new_checked(&*payload) else {
+        return;
+    };
+    if !segment.syn() {
+        return;
+    }
+    let header_len = segment.header_len() as usize;
+    let Some(mtu) = lookup(src.into()) else {
+        return;
+    };
+
+    let ip_header_len = match src {
+        IpAddress::Ipv4(_) => 20,
+        IpAddress::Ipv6(_) => 40,
+    };
+    let max_mss = (mtu - ip_header_len - 20) as u16;
+
+    let options = &mut payload[20..header_len];
+    let mut index = 0;
+    let mut clamped = false;
+    while index < options.len() {
+        match options[index] {
+            // end of option list
+            0 => break,
+            // no operation
+            1 => index += 1,
+            // maximum segment size
+            2 if index + 4 <= options.len() && options[index + 1] == 4 => {
+                let mss = u16::from_be_bytes([options[index + 2], options[index + 3]]);
+                if mss > max_mss {
+                    options[index + 2..index + 4].copy_from_slice(&max_mss.to_be_bytes());
+                    clamped = true;
+                }
+                break;
+            }
+            _ => match options.get(index + 1) {
+                Some(len) if *len >= 2 => index += *len as usize,
+                _ => break,
+            },
+        }
+    }
+
+    if clamped {
+        TcpPacket::new_unchecked(payload).fill_checksum(&src, &dst);
+    }
+}
@@ -0,0 +1,118 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: tcp_stream                                                      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: TCP connections used by the kernel itself (e.g. by the 9P       ║
+   ║         client). The socket lives in the root namespace and belongs to  ║
+   ║         the kernel process, so any thread may use it, regardless of the ║
+   ║         process it belongs to. Waiting works like for sockets of user   ║
+   ║         space (see 'pending'), so a killed thread still exits.          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::tcp;
+use smoltcp::time::Duration;
+use smoltcp::wire::IpAddress;
+use syscall::return_vals::Errno;
+use crate::process_manager;
+use super::buffers::FULL_BUFFER_SIZE;
+use super::namespace::{Namespace, SocketOwner};
+use super::pending::{PendingGuard, PendingKind};
+use super::{pick_port, resize_buffers, root_namespace};
+
+/// Abort the connection, if sent data isn't acknowledged within this time
+const TIMEOUT_MS: u64 = 30_000;
+
+/// A connected TCP socket of the kernel. It is closed, when this is dropped.
+pub struct TcpStream {
+    namespace: Arc<Namespace>,
+    handle: SocketHandle,
+}
+
+impl TcpStream {
+    /// Connect to `port` on `host` and wait until the connection is established.
+    pub fn connect(host: IpAddress, port: u16) -> Result<TcpStream, Errno> {
+        let kernel = process_manager().read().kernel_process().ok_or(Errno::EUNKN)?;
+        let namespace = root_namespace();
+        let socket = tcp::Socket::new(tcp::SocketBuffer::new(Vec::new()), tcp::SocketBuffer::new(Vec::new()));
+        let handle = namespace.sockets.lock().add(socket);
+        namespace.owners.write().insert(handle, SocketOwner::new(kernel));
+        // from here on, dropping the stream releases the socket
+        let stream = TcpStream { namespace, handle };
+
+        resize_buffers::<tcp::Socket>(&stream.namespace, handle, FULL_BUFFER_SIZE).map_err(|_| Errno::ENOBUFS)?;
+        {
+            let mut interfaces = stream.namespace.interfaces.lock();
+            let interface = interfaces.get_mut(0).ok_or(Errno::EADDRNOTAVAIL)?;
+
+            let mut sockets = stream.namespace.sockets.lock();
+            let socket = sockets.get_mut::<tcp::Socket>(handle);
+            socket.set_timeout(Some(Duration::from_millis(TIMEOUT_MS)));
+            socket.connect(interface.iface.context(), (host, port), pick_port(0)).map_err(|_| Errno::EINVAL)?;
+        }
+
+        let pending = PendingGuard::register(Some(handle), PendingKind::Connect);
+        loop {
+            match stream.with_socket(|socket| socket.state()) {
+                tcp::State::Established => break,
+                // refused (reset) or timed out
+                tcp::State::Closed => return Err(Errno::ECONNRESET),
+                _ => pending.wait()?,
+            }
+        }
+
+        Ok(stream)
+    }
+
+    /// Send all of `data`, waiting for space in the send buffer as needed.
+    pub fn write_all(&self, mut data: &[u8]) -> Result<(), Errno> {
+        let pending = PendingGuard::register(Some(self.handle), PendingKind::Send);
+        while !data.is_empty() {
+            let sent = self.with_socket(|socket| match socket.may_send() {
+                true => socket.send_slice(data).map_err(|_| Errno::EPIPE),
+                false => Err(Errno::EPIPE),
+            })?;
+            data = &data[sent..];
+            if sent == 0 {
+                pending.wait()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fill `buffer` with received data, waiting as needed.
+    /// Fails with `ECONNRESET`, if the connection is closed before.
+    pub fn read_exact(&self, buffer: &mut [u8]) -> Result<(), Errno> {
+        let pending = PendingGuard::register(Some(self.handle), PendingKind::Receive);
+        let mut received = 0;
+        while received < buffer.len() {
+            let count = self.with_socket(|socket| match socket.can_recv() || socket.may_recv() {
+                true => socket.recv_slice(&mut buffer[received..]).map_err(|_| Errno::ECONNRESET),
+                false => Err(Errno::ECONNRESET),
+            })?;
+            received += count;
+            if count == 0 {
+                pending.wait_for_event()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn with_socket<R>(&self, f: impl FnOnce(&mut tcp::Socket<'static>) -> R) -> R {
+        let mut sockets = self.namespace.sockets.lock();
+        f(sockets.get_mut::<tcp::Socket>(self.handle))
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        // the poll thread removes the socket, once the connection is closed
+        self.with_socket(|socket| socket.close());
+        self.namespace.owners.write().remove(&self.handle);
+    }
+}
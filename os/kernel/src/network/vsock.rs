@@ -0,0 +1,302 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: vsock                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Stream sockets for communicating with the host via              ║
+   ║         virtio-vsock, independent of the network stack. The connection  ║
+   ║         manager of virtio-drivers buffers received data and accepts     ║
+   ║         connections to listening ports. Its events are polled whenever  ║
+   ║         a socket is used, there is no interrupt handling.               ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use log::{info, warn};
+use spin::Mutex;
+use syscall::return_vals::Errno;
+use syscall::vsock::VsockAddr;
+use virtio::device::socket::{SocketError, VsockAddr as DeviceAddr, VsockEventType};
+use virtio::Error as VirtioError;
+use crate::device::virtio::virtio_socket;
+use crate::scheduler;
+use super::pending::{PendingGuard, PendingKind};
+
+/// Outgoing connections use local ports starting here
+const EPHEMERAL_PORT_START: u32 = 49152;
+/// At most this many bytes are sent at once, so they fit into the receive buffer of the peer
+const MAX_SEND_SIZE: usize = 1024;
+
+/// All vsock sockets by their handle
+static SOCKETS: Mutex<BTreeMap<usize, VsockSocket>> = Mutex::new(BTreeMap::new());
+static NEXT_HANDLE: AtomicUsize = AtomicUsize::new(1);
+static NEXT_PORT: AtomicU32 = AtomicU32::new(EPHEMERAL_PORT_START);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Listening,
+    Connecting,
+    Connected,
+    /// The peer has closed the connection, but there may still be data to receive
+    Closed,
+}
+
+struct VsockSocket {
+    process_id: usize,
+    state: State,
+    local_port: u32,
+    /// `None` for listening sockets
+    peer: Option<DeviceAddr>,
+    /// Connections to a listening socket, which haven't been accepted yet
+    backlog: VecDeque<DeviceAddr>,
+}
+
+/// Listen for connections on `port`. Returns the handle of the listening socket.
+pub fn listen(port: u32) -> Result<usize, Errno> {
+    let device = virtio_socket().ok_or(Errno::ENOTSUP)?;
+    let mut sockets = SOCKETS.lock();
+    if sockets.values().any(|socket| socket.state == State::Listening && socket.local_port == port) {
+        return Err(Errno::EEXIST);
+    }
+
+    device.lock().listen(port);
+    info!("vsock: listening on port {port}");
+    Ok(insert(&mut sockets, State::Listening, port, None))
+}
+
+/// Wait for a connection to the listening socket `handle`.
+/// Returns the handle of the new, connected socket and the address of the peer.
+pub fn accept(handle: usize) -> Result<(usize, VsockAddr), Errno> {
+    let pending = PendingGuard::register(None, PendingKind::Accept);
+    loop {
+        poll_events();
+        {
+            let mut sockets = SOCKETS.lock();
+            let socket = own_socket(&mut sockets, handle)?;
+            if socket.state != State::Listening {
+                return Err(Errno::EINVAL);
+            }
+            if let Some(peer) = socket.backlog.pop_front() {
+                let local_port = socket.local_port;
+                let handle = insert(&mut sockets, State::Connected, local_port, Some(peer));
+                return Ok((handle, VsockAddr { cid: peer.cid, port: peer.port }));
+            }
+        }
+        pending.wait().map_err(|_| Errno::EINTR)?;
+    }
+}
+
+/// Connect to `port` on the machine with the context id `cid` (e.g. `VMADDR_CID_HOST`).
+/// Returns the handle of the connected socket.
+pub fn connect(cid: u64, port: u32) -> Result<usize, Errno> {
+    let device = virtio_socket().ok_or(Errno::ENOTSUP)?;
+    let peer = DeviceAddr { cid, port };
+    let local_port = NEXT_PORT.fetch_add(1, Ordering::Relaxed);
+
+    device.lock().connect(peer, local_port).map_err(errno)?;
+    let handle = insert(&mut SOCKETS.lock(), State::Connecting, local_port, Some(peer));
+    info!("vsock: connecting to {cid}:{port} from port {local_port}");
+
+    let pending = PendingGuard::register(None, PendingKind::Connect);
+    loop {
+        poll_events();
+        match SOCKETS.lock().get(&handle).map(|socket| socket.state) {
+            Some(State::Connected) => return Ok(handle),
+            Some(State::Connecting) => {}
+            // the peer has refused the connection
+            _ => {
+                let _ = close(handle);
+                return Err(Errno::ECONNRESET);
+            }
+        }
+        if pending.wait().is_err() {
+            let _ = close(handle);
+            return Err(Errno::EINTR);
+        }
+    }
+}
+
+/// Wait until the peer has enough buffer space and then send as much of `data` as possible.
+pub fn send(handle: usize, data: &[u8]) -> Result<usize, Errno> {
+    let device = virtio_socket().ok_or(Errno::ENOTSUP)?;
+    let data = &data[..data.len().min(MAX_SEND_SIZE)];
+    let pending = PendingGuard::register(None, PendingKind::Send);
+    loop {
+        poll_events();
+        let (peer, local_port) = connection(handle)?;
+        match device.lock().send(peer, local_port, data) {
+            Ok(()) => return Ok(data.len()),
+            Err(VirtioError::SocketDeviceError(SocketError::InsufficientBufferSpaceInPeer)) => {}
+            Err(e) => return Err(errno(e)),
+        }
+        pending.wait().map_err(|_| Errno::EINTR)?;
+    }
+}
+
+/// Wait until there is data (or the connection is closed) and then receive it.
+pub fn receive(handle: usize, data: &mut [u8]) -> Result<usize, Errno> {
+    let device = virtio_socket().ok_or(Errno::ENOTSUP)?;
+    let pending = PendingGuard::register(None, PendingKind::Receive);
+    loop {
+        poll_events();
+        let (peer, local_port, state) = {
+            let mut sockets = SOCKETS.lock();
+            let socket = own_socket(&mut sockets, handle)?;
+            (socket.peer.ok_or(Errno::EINVAL)?, socket.local_port, socket.state)
+        };
+
+        let mut manager = device.lock();
+        if manager.recv_buffer_available_bytes(peer, local_port).unwrap_or(0) > 0 {
+            let received = manager.recv(peer, local_port, data).map_err(errno)?;
+            // tell the peer, that there is buffer space again
+            manager.update_credit(peer, local_port).map_err(errno)?;
+            return Ok(received);
+        }
+        drop(manager);
+
+        if state == State::Closed {
+            return Err(Errno::ECONNRESET);
+        }
+        pending.wait().map_err(|_| Errno::EINTR)?;
+    }
+}
+
+/// Close a socket. Connections are shut down, pending connections of a listening socket are aborted.
+pub fn close(handle: usize) -> Result<(), Errno> {
+    let socket = {
+        let mut sockets = SOCKETS.lock();
+        own_socket(&mut sockets, handle)?;
+        sockets.remove(&handle).unwrap()
+    };
+    close_socket(socket);
+    Ok(())
+}
+
+/// Close all sockets of a process (when it exits).
+pub(crate) fn close_sockets_for_process(process_id: usize) {
+    let sockets: Vec<_> = {
+        let mut sockets = SOCKETS.lock();
+        let handles: Vec<_> = sockets.iter()
+            .filter(|(_, socket)| socket.process_id == process_id)
+            .map(|(handle, _)| *handle)
+            .collect();
+        handles.into_iter().filter_map(|handle| sockets.remove(&handle)).collect()
+    };
+    for socket in sockets {
+        close_socket(socket);
+    }
+}
+
+fn close_socket(socket: VsockSocket) {
+    let Some(device) = virtio_socket() else {
+        return;
+    };
+    let mut manager = device.lock();
+    // errors are ignored, the connection may already be gone
+    match (socket.state, socket.peer) {
+        (State::Listening, _) => {
+            manager.unlisten(socket.local_port);
+            for peer in socket.backlog {
+                let _ = manager.force_close(peer, socket.local_port);
+            }
+        }
+        (State::Closed, Some(peer)) => {
+            let _ = manager.force_close(peer, socket.local_port);
+        }
+        (_, Some(peer)) => {
+            let _ = manager.shutdown(peer, socket.local_port);
+        }
+        (_, None) => {}
+    }
+}
+
+fn insert(sockets: &mut BTreeMap<usize, VsockSocket>, state: State, local_port: u32, peer: Option<DeviceAddr>) -> usize {
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    let (process_id, _) = scheduler().current_ids();
+    sockets.insert(handle, VsockSocket { process_id, state, local_port, peer, backlog: VecDeque::new() });
+    handle
+}
+
+/// Get a socket of the current process.
+fn own_socket(sockets: &mut BTreeMap<usize, VsockSocket>, handle: usize) -> Result<&mut VsockSocket, Errno> {
+    let (process_id, _) = scheduler().current_ids();
+    sockets.get_mut(&handle)
+        .filter(|socket| socket.process_id == process_id)
+        .ok_or(Errno::EINVALH)
+}
+
+/// Get peer and local port of a connected socket.
+fn connection(handle: usize) -> Result<(DeviceAddr, u32), Errno> {
+    let mut sockets = SOCKETS.lock();
+    let socket = own_socket(&mut sockets, handle)?;
+    match (socket.state, socket.peer) {
+        (State::Connected, Some(peer)) => Ok((peer, socket.local_port)),
+        (State::Closed, _) => Err(Errno::ECONNRESET),
+        _ => Err(Errno::EINVAL),
+    }
+}
+
+/// Take all events from the connection manager and update the sockets.
+fn poll_events() {
+    let Some(device) = virtio_socket() else {
+        return;
+    };
+    let mut events = Vec::new();
+    {
+        let mut manager = device.lock();
+        loop {
+            match manager.poll() {
+                Ok(Some(event)) => events.push(event),
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("vsock: failed to poll device: {e:?}");
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut sockets = SOCKETS.lock();
+    for event in events {
+        let (peer, local_port) = (event.source, event.destination.port);
+        match event.event_type {
+            // the connection manager has already accepted the connection
+            VsockEventType::ConnectionRequest => {
+                match sockets.values_mut().find(|socket| socket.state == State::Listening && socket.local_port == local_port) {
+                    Some(listener) => listener.backlog.push_back(peer),
+                    None => warn!("vsock: connection request for port {local_port}, but nobody is listening"),
+                }
+            }
+            VsockEventType::Connected => set_state(&mut sockets, peer, local_port, State::Connected),
+            VsockEventType::Disconnected { reason } => {
+                info!("vsock: connection from {}:{} to port {local_port} closed ({reason:?})", peer.cid, peer.port);
+                set_state(&mut sockets, peer, local_port, State::Closed);
+                // a connection, that hasn't been accepted yet, is gone
+                for socket in sockets.values_mut().filter(|socket| socket.state == State::Listening && socket.local_port == local_port) {
+                    socket.backlog.retain(|pending| *pending != peer);
+                }
+            }
+            // received data is buffered by the connection manager, credit is handled there, too
+            _ => {}
+        }
+    }
+}
+
+fn set_state(sockets: &mut BTreeMap<usize, VsockSocket>, peer: DeviceAddr, local_port: u32, state: State) {
+    if let Some(socket) = sockets.values_mut().find(|socket| socket.peer == Some(peer) && socket.local_port == local_port) {
+        socket.state = state;
+    }
+}
+
+fn errno(error: VirtioError) -> Errno {
+    match error {
+        VirtioError::SocketDeviceError(SocketError::ConnectionExists) => Errno::EEXIST,
+        VirtioError::SocketDeviceError(SocketError::NotConnected | SocketError::PeerSocketShutdown) => Errno::ECONNRESET,
+        VirtioError::SocketDeviceError(SocketError::InsufficientBufferSpaceInPeer) => Errno::EAGAIN,
+        e => {
+            warn!("vsock: {e:?}");
+            Errno::EUNKN
+        }
+    }
+}
@@ -0,0 +1,63 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: wol                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Wake-on-LAN. Sends magic packets to power on other machines     ║
+   ║         and arms our own NIC to wake the system before power down.      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::vec::Vec;
+use core::ops::Deref;
+use core::ptr;
+use log::info;
+use smoltcp::phy::{self, TxToken};
+use smoltcp::time::Instant;
+use smoltcp::wire::EthernetAddress;
+use syscall::return_vals::Errno;
+use crate::network::rtl8139;
+use crate::timer;
+
+/// EtherType reserved for Wake-on-LAN
+const ETHERTYPE_WOL: u16 = 0x0842;
+/// The payload of a magic packet is 6 bytes of 0xff followed by the target MAC address 16 times
+const MAGIC_PACKET_REPETITIONS: usize = 16;
+
+/// Build an Ethernet frame containing a magic packet for `target`.
+/// The frame is sent to the broadcast address, so it reaches the target even though it is powered down.
+pub fn magic_packet(target: EthernetAddress, source: EthernetAddress) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(14 + 6 + 6 * MAGIC_PACKET_REPETITIONS);
+    frame.extend_from_slice(EthernetAddress::BROADCAST.as_bytes());
+    frame.extend_from_slice(source.as_bytes());
+    frame.extend_from_slice(&ETHERTYPE_WOL.to_be_bytes());
+
+    frame.extend_from_slice(&[0xff; 6]);
+    for _ in 0..MAGIC_PACKET_REPETITIONS {
+        frame.extend_from_slice(target.as_bytes());
+    }
+
+    frame
+}
+
+/// Broadcast a magic packet to power on the machine with the MAC address `target`.
+/// This works without an IP address, since the packet is sent as a raw Ethernet frame.
+pub fn send_magic_packet(target: EthernetAddress) -> Result<(), Errno> {
+    let rtl8139 = rtl8139().ok_or(Errno::ENOTSUP)?;
+    let frame = magic_packet(target, rtl8139.read_mac_address());
+    info!("Sending Wake-on-LAN magic packet to [{target}]");
+
+    // Smoltcp expects a mutable reference to the device, but the RTL8139 driver is built
+    // to work with a shared reference. We can safely cast the shared reference to a mutable.
+    let device = unsafe { ptr::from_ref(rtl8139.deref()).cast_mut().as_mut().unwrap() };
+    let token = phy::Device::transmit(device, Instant::from_millis(timer().systime_ms() as i64)).ok_or(Errno::EBUSY)?;
+    token.consume(frame.len(), |buffer| buffer.copy_from_slice(&frame));
+
+    Ok(())
+}
+
+/// Arm Wake-on-LAN on all NICs, that support it. Called before the system is powered down.
+pub fn prepare_power_down() {
+    if let Some(rtl8139) = rtl8139() {
+        rtl8139.enable_wake_on_lan();
+    }
+}
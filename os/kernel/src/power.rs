@@ -0,0 +1,61 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: power                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Powering down the system via ACPI (sleep state S5).             ║
+   ║         Devices get a chance to prepare first (e.g. arming WoL or       ║
+   ║         writing back the block cache).                                  ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use acpi::address::{AddressSpace, GenericAddress};
+use acpi::fadt::Fadt;
+use log::{info, warn};
+use x86_64::instructions::{hlt, interrupts};
+use x86_64::instructions::port::PortWriteOnly;
+use crate::{acpi_tables, network, storage};
+
+/// Sleep enable bit in the PM1 control registers
+const SLP_EN: u16 = 1 << 13;
+/// Sleep type for S5. The correct value is stored in the DSDT's `\_S5` object, but we can't evaluate AML.
+/// QEMU uses 0, which is what we mostly run on.
+const SLP_TYP_S5: u16 = 0;
+
+/// Prepare all devices and power down the system.
+pub fn power_off() -> ! {
+    info!("Powering down");
+    network::wol::prepare_power_down();
+    if !storage::cache::sync() {
+        warn!("Failed to write back cached sectors");
+    }
+
+    interrupts::disable();
+    match acpi_tables().lock().find_table::<Fadt>() {
+        Ok(fadt) => {
+            let fadt = fadt.get();
+            match fadt.pm1a_control_block() {
+                Ok(block) => write_pm1_control(&block),
+                Err(err) => warn!("PM1a control block not available: {err:?}"),
+            }
+            if let Ok(Some(block)) = fadt.pm1b_control_block() {
+                write_pm1_control(&block);
+            }
+        }
+        Err(err) => warn!("FADT not available: {err:?}"),
+    }
+
+    warn!("Failed to power down, halting");
+    loop {
+        hlt();
+    }
+}
+
+fn write_pm1_control(block: &GenericAddress) {
+    if block.address_space != AddressSpace::SystemIo {
+        warn!("PM1 control block is not in I/O space");
+        return;
+    }
+
+    let mut port = PortWriteOnly::<u16>::new(block.address as u16);
+    unsafe { port.write(SLP_EN | (SLP_TYP_S5 << 10)); }
+}
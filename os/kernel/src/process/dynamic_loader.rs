@@ -0,0 +1,342 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: dynamic_loader                                                  ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Dynamic linking of applications against shared libraries (ELF shared    ║
+   ║ objects in /lib). Each library is loaded and relocated only once, at a  ║
+   ║ fixed address in the library area of the user space. Its frames are     ║
+   ║ then mapped into every process using it: Code and read-only data are    ║
+   ║ shared, writable data is mapped copy-on-write, so each process gets its ║
+   ║ own copy of a page on the first write access.                           ║
+   ║                                                                         ║
+   ║ Since the relocations of a library are applied once for all processes,  ║
+   ║ its symbols are resolved in the library itself and its dependencies     ║
+   ║ only (not in the executable). Libraries are never unloaded and may not  ║
+   ║ have thread-local variables or initialization functions.                ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - link           load the libraries needed by an executable, map      ║
+   ║                    them into its process and relocate the executable    ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use goblin::elf::Elf;
+use goblin::elf::header::ET_DYN;
+use goblin::elf::program_header::{PT_LOAD, PT_TLS};
+use goblin::elf::reloc::{R_X86_64_64, R_X86_64_GLOB_DAT, R_X86_64_JUMP_SLOT, R_X86_64_NONE, R_X86_64_RELATIVE};
+use goblin::elf::section_header::{SHN_ABS, SHN_UNDEF};
+use goblin::elf::sym::{STB_LOCAL, STB_WEAK};
+use log::{error, info};
+use x86_64::VirtAddr;
+use x86_64::structures::paging::frame::PhysFrameRange;
+use x86_64::structures::paging::page::PageRange;
+use x86_64::structures::paging::{Page, PageTableFlags};
+use crate::consts::{MAX_USER_SPACE_LIB_SIZE, USER_SPACE_LIB_START};
+use crate::memory::vma::VmaType;
+use crate::memory::vmm::VirtualAddressSpace;
+use crate::memory::{self, pages, MemorySpace, PAGE_SIZE};
+use crate::naming;
+use crate::process::process::Process;
+use crate::process::thread::ProcessLoadError;
+use crate::sync::pi_mutex::PiMutex;
+
+/// Directory, in which libraries are searched (if `DT_NEEDED` is not an absolute path)
+const LIBRARY_DIRECTORY: &str = "/lib";
+
+/// All libraries loaded so far, by their name in `DT_NEEDED`
+/// (a blocking mutex, since it is held while reading and relocating a library)
+static LIBRARIES: PiMutex<Libraries> = PiMutex::new(Libraries { loaded: BTreeMap::new(), next_base: USER_SPACE_LIB_START as u64 });
+
+struct Libraries {
+    loaded: BTreeMap<String, Arc<SharedObject>>,
+    /// Address for the next library (libraries are loaded one after another and never unloaded)
+    next_base: u64,
+}
+
+/// A loaded and relocated library
+struct SharedObject {
+    name: String,
+    segments: Vec<Segment>,
+    /// Addresses of the global symbols defined by the library
+    symbols: BTreeMap<String, u64>,
+    /// Libraries needed by this one (`DT_NEEDED`)
+    dependencies: Vec<Arc<SharedObject>>,
+}
+
+/// A loadable segment of a library and the frames holding its shared image
+struct Segment {
+    pages: PageRange,
+    frames: PhysFrameRange,
+    writable: bool,
+}
+
+impl Drop for SharedObject {
+    fn drop(&mut self) {
+        // Only happens, if loading the library has failed (loaded libraries are kept forever)
+        for segment in &self.segments {
+            memory::free_frames(segment.frames);
+        }
+    }
+}
+
+/// Load the libraries needed by the executable `elf` (and their dependencies), if they are not loaded yet,
+/// and map them into the address space of `process`. \
+/// Then apply the dynamic relocations of the executable, which has already been mapped into `process`.
+pub fn link(process: &Process, elf: &Elf) -> Result<(), ProcessLoadError> {
+    let needed = {
+        let mut libraries = LIBRARIES.lock();
+        elf.libraries.iter().map(|name| libraries.load(name, &mut Vec::new())).collect::<Result<Vec<_>, _>>()?
+    };
+
+    let address_space = &process.virtual_address_space;
+    let scope = scope(&needed);
+    for library in &scope {
+        library.map_into(address_space)?;
+    }
+
+    // Executables are linked at a fixed address, so relocations don't need a base address
+    relocate(elf, 0, &scope, |addr, value| {
+        let within_code = [addr, addr + size_of::<u64>() as u64 - 1].into_iter()
+            .all(|addr| address_space.is_address_within_vma(addr, VmaType::Code).is_some());
+        let phys = address_space.get_phys(addr).filter(|_| within_code && addr % size_of::<u64>() as u64 == 0).ok_or_else(|| {
+            error!("ELF: Invalid relocation offset 0x{addr:x}");
+            ProcessLoadError::ElfInvalid
+        })?;
+        unsafe { (phys.as_u64() as *mut u64).write(value) };
+        Ok(())
+    })
+}
+
+impl Libraries {
+    /// Return the library `name`, loading it and its dependencies, if necessary. \
+    /// `loading` contains the libraries, whose dependencies are being loaded (to detect circular dependencies).
+    fn load(&mut self, name: &str, loading: &mut Vec<String>) -> Result<Arc<SharedObject>, ProcessLoadError> {
+        if let Some(library) = self.loaded.get(name) {
+            return Ok(Arc::clone(library));
+        }
+        if loading.iter().any(|other| other == name) {
+            error!("Circular dependency of library [{name}]");
+            return Err(ProcessLoadError::ElfInvalid);
+        }
+
+        let path = match name.starts_with('/') {
+            true => name.to_string(),
+            false => format!("{LIBRARY_DIRECTORY}/{name}"),
+        };
+        let buffer = naming::api::read_file(&path).map_err(|_| {
+            error!("Library [{path}] not found");
+            ProcessLoadError::NotFound
+        })?;
+        let elf = Elf::parse(&buffer).map_err(|e| {
+            error!("Failed to parse library [{path}]: {e:?}");
+            ProcessLoadError::ElfInvalid
+        })?;
+        if elf.header.e_type != ET_DYN || elf.program_headers.iter().any(|header| header.p_type == PT_TLS) {
+            error!("Library [{path}] is not a shared object or has thread-local variables");
+            return Err(ProcessLoadError::ElfInvalid);
+        }
+
+        loading.push(name.to_string());
+        let dependencies = elf.libraries.iter().map(|dependency| self.load(dependency, loading)).collect::<Result<Vec<_>, _>>();
+        loading.pop();
+        let dependencies = dependencies?;
+
+        let (base, end) = self.address_range(&elf, &path)?;
+        let library = SharedObject {
+            name: name.to_string(),
+            segments: load_segments(&elf, &buffer, base, &path)?,
+            symbols: global_symbols(&elf, base),
+            dependencies,
+        };
+        relocate(&elf, base, &scope(&library.dependencies), |addr, value| library.write(addr, value))?;
+
+        info!("Loaded library [{path}] at 0x{base:x}");
+        // Leave one unmapped page between libraries
+        self.next_base = end + PAGE_SIZE as u64;
+        let library = Arc::new(library);
+        self.loaded.insert(name.to_string(), Arc::clone(&library));
+        Ok(library)
+    }
+
+    /// Find the address range for all loadable segments of the library `elf` and return its base and end address.
+    /// The range is only taken (by advancing `next_base`), once the library has been loaded successfully.
+    fn address_range(&self, elf: &Elf, path: &str) -> Result<(u64, u64), ProcessLoadError> {
+        let size = elf.program_headers.iter()
+            .filter(|header| header.p_type == PT_LOAD)
+            .map(|header| header.p_vaddr.saturating_add(header.p_memsz))
+            .max()
+            .unwrap_or(0)
+            .next_multiple_of(PAGE_SIZE as u64);
+
+        let base = self.next_base;
+        let end = base.saturating_add(size);
+        if size == 0 || end > (USER_SPACE_LIB_START + MAX_USER_SPACE_LIB_SIZE) as u64 {
+            error!("No room for library [{path}] ({size} bytes)");
+            return Err(ProcessLoadError::ElfInvalid);
+        }
+
+        Ok((base, end))
+    }
+}
+
+impl SharedObject {
+    /// Map the segments of this library into `address_space` (writable ones copy-on-write).
+    fn map_into(&self, address_space: &VirtualAddressSpace) -> Result<(), ProcessLoadError> {
+        for segment in &self.segments {
+            let vma = address_space
+                .alloc_vma(Some(segment.pages.start), segment.pages.len(), MemorySpace::User, VmaType::SharedLibrary, &self.name)
+                .ok_or_else(|| {
+                    error!("Library [{}] overlaps with the executable", self.name);
+                    ProcessLoadError::ElfInvalid
+                })?;
+
+            let mut flags = PageTableFlags::USER_ACCESSIBLE | pages::SHARED;
+            if segment.writable {
+                flags |= pages::COPY_ON_WRITE;
+            }
+            address_space.map_pfr_for_vma(&vma, segment.frames, flags).map_err(|_| ProcessLoadError::ElfInvalid)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write `value` at the (virtual) address `addr` of the shared image of this library.
+    fn write(&self, addr: u64, value: u64) -> Result<(), ProcessLoadError> {
+        let size = size_of::<u64>() as u64;
+        let segment = self.segments.iter()
+            .find(|segment| addr >= segment.pages.start.start_address().as_u64() && addr + size <= segment.pages.end.start_address().as_u64())
+            .filter(|_| addr % size == 0)
+            .ok_or_else(|| {
+                error!("Library [{}]: Invalid relocation offset 0x{addr:x}", self.name);
+                ProcessLoadError::ElfInvalid
+            })?;
+
+        // The frames of a segment are contiguous and identity mapped in kernel space
+        let offset = addr - segment.pages.start.start_address().as_u64();
+        let target = segment.frames.start.start_address().as_u64() + offset;
+        unsafe { (target as *mut u64).write(value) };
+        Ok(())
+    }
+}
+
+/// Allocate frames for the loadable segments of the library `elf` at `base` and copy their contents from `buffer`.
+fn load_segments(elf: &Elf, buffer: &[u8], base: u64, path: &str) -> Result<Vec<Segment>, ProcessLoadError> {
+    let mut segments: Vec<Segment> = Vec::new();
+    for header in elf.program_headers.iter().filter(|header| header.p_type == PT_LOAD && header.p_memsz > 0) {
+        let start = base + header.p_vaddr;
+        let pages = PageRange {
+            start: Page::containing_address(VirtAddr::new(start)),
+            end: Page::containing_address(VirtAddr::new((start + header.p_memsz).next_multiple_of(PAGE_SIZE as u64))),
+        };
+
+        // Segments must not share pages, since they may have different access rights
+        let in_file = header.p_offset.checked_add(header.p_filesz).is_some_and(|end| end <= buffer.len() as u64);
+        let overlaps = segments.iter().any(|other| pages.start < other.pages.end && other.pages.start < pages.end);
+        if !in_file || header.p_filesz > header.p_memsz || overlaps {
+            error!("Library [{path}]: Invalid segment {header:?}");
+            segments.iter().for_each(|segment| memory::free_frames(segment.frames));
+            return Err(ProcessLoadError::ElfInvalid);
+        }
+
+        let frames = memory::alloc_frames(pages.len() as usize);
+        unsafe {
+            let image = frames.start.start_address().as_u64() as *mut u8;
+            image.write_bytes(0, pages.len() as usize * PAGE_SIZE);
+
+            let source = buffer.as_ptr().add(header.p_offset as usize);
+            image.add((start - pages.start.start_address().as_u64()) as usize).copy_from(source, header.p_filesz as usize);
+        }
+
+        segments.push(Segment { pages, frames, writable: header.is_write() });
+    }
+
+    Ok(segments)
+}
+
+/// Addresses of the global symbols defined by the library `elf`, which has been loaded at `base`.
+fn global_symbols(elf: &Elf, base: u64) -> BTreeMap<String, u64> {
+    let mut symbols = BTreeMap::new();
+    for symbol in elf.dynsyms.iter() {
+        if symbol.st_shndx == SHN_UNDEF as usize || symbol.st_bind() == STB_LOCAL {
+            continue;
+        }
+
+        if let Some(name) = elf.dynstrtab.get_at(symbol.st_name).filter(|name| !name.is_empty()) {
+            let addr = if symbol.st_shndx == SHN_ABS as usize { symbol.st_value } else { base + symbol.st_value };
+            symbols.entry(name.to_string()).or_insert(addr);
+        }
+    }
+
+    symbols
+}
+
+/// Libraries in the order, in which symbols are searched: `roots` first, then their dependencies (breadth-first).
+fn scope(roots: &[Arc<SharedObject>]) -> Vec<Arc<SharedObject>> {
+    let mut scope: Vec<Arc<SharedObject>> = Vec::new();
+    let mut queue: VecDeque<Arc<SharedObject>> = roots.iter().cloned().collect();
+    while let Some(library) = queue.pop_front() {
+        if scope.iter().any(|other| Arc::ptr_eq(other, &library)) {
+            continue;
+        }
+
+        queue.extend(library.dependencies.iter().cloned());
+        scope.push(library);
+    }
+
+    scope
+}
+
+/// Apply the dynamic relocations of `elf`, which has been loaded at `base`, by calling `write` with the address and the value.
+/// Undefined symbols are searched in the libraries of `scope`. \
+/// Function addresses are resolved right away (there is no lazy binding).
+fn relocate(elf: &Elf, base: u64, scope: &[Arc<SharedObject>], mut write: impl FnMut(u64, u64) -> Result<(), ProcessLoadError>) -> Result<(), ProcessLoadError> {
+    if !elf.dynrels.is_empty() {
+        error!("ELF: Relocations without addend are not supported");
+        return Err(ProcessLoadError::ElfInvalid);
+    }
+
+    for reloc in elf.dynrelas.iter().chain(elf.pltrelocs.iter()) {
+        let addend = reloc.r_addend.unwrap_or(0) as u64;
+        let value = match reloc.r_type {
+            R_X86_64_NONE => continue,
+            R_X86_64_RELATIVE => base.wrapping_add(addend),
+            R_X86_64_64 => resolve(elf, base, reloc.r_sym, scope)?.wrapping_add(addend),
+            R_X86_64_GLOB_DAT | R_X86_64_JUMP_SLOT => resolve(elf, base, reloc.r_sym, scope)?,
+            typ => {
+                error!("ELF: Unsupported relocation type {typ}");
+                return Err(ProcessLoadError::ElfInvalid);
+            }
+        };
+
+        write(base.wrapping_add(reloc.r_offset), value)?;
+    }
+
+    Ok(())
+}
+
+/// Address of the symbol with the index `index` in the dynamic symbol table of `elf`, which has been loaded at `base`.
+fn resolve(elf: &Elf, base: u64, index: usize, scope: &[Arc<SharedObject>]) -> Result<u64, ProcessLoadError> {
+    let symbol = elf.dynsyms.get(index).ok_or(ProcessLoadError::ElfInvalid)?;
+    if symbol.st_shndx == SHN_ABS as usize {
+        return Ok(symbol.st_value);
+    }
+    if symbol.st_shndx != SHN_UNDEF as usize {
+        return Ok(base + symbol.st_value);
+    }
+
+    let name = elf.dynstrtab.get_at(symbol.st_name).unwrap_or("");
+    if let Some(&addr) = scope.iter().find_map(|library| library.symbols.get(name)) {
+        return Ok(addr);
+    }
+    if symbol.st_bind() == STB_WEAK {
+        return Ok(0);
+    }
+
+    error!("ELF: Undefined symbol [{name}]");
+    Err(ProcessLoadError::ElfInvalid)
+}
@@ -0,0 +1,64 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: idle                                                            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Putting a core to sleep, while it runs its idle thread. If the CPU      ║
+   ║ supports MONITOR/MWAIT, the core waits with MWAIT in C1 and also wakes  ║
+   ║ up, when its wakeup counter is written. Otherwise, it waits with HLT.   ║
+   ║ Either way, interrupts are enabled in the shadow of STI right before    ║
+   ║ the waiting instruction, so a reschedule IPI or timer interrupt, that   ║
+   ║ arrives after the idle thread has checked its ready queue (with         ║
+   ║ interrupts disabled), is not lost but ends the wait right away.         ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - wait                 sleep until the next interrupt                 ║
+   ║   - uses_mwait           check if MONITOR/MWAIT is used                 ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::arch::asm;
+use core::ptr;
+use core::sync::atomic::AtomicUsize;
+use log::info;
+use raw_cpuid::CpuId;
+use spin::Once;
+use x86_64::instructions::interrupts;
+
+/// MWAIT hint for C1 (deeper C-states need the hints from the ACPI `_CST` object, which we can't evaluate)
+const MWAIT_HINT_C1: u32 = 0x00;
+
+static MWAIT: Once<bool> = Once::new();
+
+/// A wakeup counter in a cache line of its own, so only writes to it end MWAIT
+#[repr(align(64))]
+pub struct Wakeup(pub AtomicUsize);
+
+impl Wakeup {
+    pub const fn new() -> Self {
+        Self(AtomicUsize::new(0))
+    }
+}
+
+/// Check if idle cores wait with MONITOR/MWAIT (instead of HLT).
+pub fn uses_mwait() -> bool {
+    *MWAIT.call_once(|| {
+        let cpuid = CpuId::new();
+        let mwait = cpuid.get_feature_info().is_some_and(|features| features.has_monitor_mwait());
+        info!("Idle cores wait with [{}]", if mwait { "MWAIT" } else { "HLT" });
+        mwait
+    })
+}
+
+/// Sleep until the next interrupt or until `wakeup` is written (only with MWAIT). \
+/// Must be called with interrupts disabled, they are enabled, when this returns.
+pub fn wait(wakeup: &Wakeup) {
+    if !uses_mwait() {
+        interrupts::enable_and_hlt();
+        return;
+    }
+
+    unsafe {
+        asm!("monitor", in("rax") ptr::from_ref(&wakeup.0), in("ecx") 0, in("edx") 0, options(nostack, preserves_flags));
+        asm!("sti", "mwait", in("eax") MWAIT_HINT_C1, in("ecx") 0, options(nostack));
+    }
+}
@@ -1,4 +1,8 @@
 pub mod scheduler;
 pub mod thread;
+pub mod idle;
+pub mod dynamic_loader;
 pub mod process;
-pub mod process_manager;
\ No newline at end of file
+pub mod process_manager;
+pub mod sandbox;
+pub mod signal;
\ No newline at end of file
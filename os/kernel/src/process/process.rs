@@ -6,13 +6,24 @@
    ║ Author: Fabian Ruhland, HHU                                             ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
+use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize};
 use core::sync::atomic::Ordering::Relaxed;
-use crate::{ network, process_manager, scheduler};
+use spin::RwLock;
+use syscall::priority::Priority;
+use syscall::return_vals::Errno;
+use syscall::signal::Signal;
+use syscall::spawn::STDIO_TERMINAL;
+use syscall::usage::ResourceUsage;
+use crate::{ naming, network, process_manager, scheduler};
 use crate::memory::pages::Paging;
+use crate::memory::PAGE_SIZE;
 use crate::memory::vmm::VirtualAddressSpace;
+use crate::network::namespace::ROOT_NAMESPACE;
+use crate::process::sandbox::Sandbox;
+use crate::sync::event;
 
 static PROCESS_ID_COUNTER: AtomicUsize = AtomicUsize::new(1);
 
@@ -24,12 +35,89 @@ fn next_process_id() -> usize {
 pub struct Process {
     pub id: usize,
     pub virtual_address_space: VirtualAddressSpace,
+    /// Name of the program running in the process
+    name: RwLock<String>,
+    /// Id of the network namespace, the process' sockets belong to
+    net_namespace: AtomicUsize,
+    sandbox: RwLock<Sandbox>,
+    /// Current working directory (an absolute path, inherited by child processes)
+    cwd: RwLock<String>,
+    /// Heap memory mapped by the process (in bytes), checked against the sandbox's limit
+    heap_memory: AtomicUsize,
+    /// Id of the first thread, which identifies the process to user space (0 for the kernel process)
+    main_thread: AtomicUsize,
+    /// Id of the process, which has started this one (0 for the kernel process)
+    parent: AtomicUsize,
+    /// Priority of new threads (inherited by child processes)
+    priority: AtomicUsize,
+    /// Signals, that have been sent to the process, but not yet delivered (see `Signal::mask()`)
+    pending_signals: AtomicU32,
+    /// Set, when the process has been terminated, its remaining threads exit as soon as possible
+    killed: AtomicBool,
+    /// Handles of the objects used as standard input and output (`STDIO_TERMINAL` = the terminal)
+    stdin: AtomicUsize,
+    stdout: AtomicUsize,
+    usage: UsageCounters,
+    /// Initial values of the thread-local variables of the program (if it has any)
+    tls_template: RwLock<Option<TlsTemplate>>,
+}
+
+/// Location of the `PT_TLS` segment of a program, which is copied into the TLS block of each thread
+#[derive(Debug, Clone, Copy)]
+pub struct TlsTemplate {
+    /// Address of the initialized variables (`.tdata`) in the address space of the process
+    pub addr: u64,
+    pub file_size: usize,
+    /// Size including the variables initialized with zeroes (`.tbss`)
+    pub mem_size: usize,
+    /// Alignment of the TLS block (a power of two, at most `PAGE_SIZE`)
+    pub align: usize,
+}
+
+impl TlsTemplate {
+    /// Size of the variables, rounded up to the alignment, so the thread pointer directly behind them is aligned
+    pub fn block_size(&self) -> usize {
+        self.mem_size.next_multiple_of(self.align)
+    }
+}
+
+/// Counters for the resource usage of a process (see `ResourceUsage`) or of a single thread (see `Thread::usage()`).
+/// They are updated from interrupt handlers and the scheduler, so they must not lock.
+#[derive(Default)]
+pub struct UsageCounters {
+    user_time_ns: AtomicU64,
+    system_time_ns: AtomicU64,
+    voluntary_switches: AtomicU64,
+    involuntary_switches: AtomicU64,
+    page_faults: AtomicU64,
+    /// Number of times a thread has been taken from a ready queue to run
+    dispatches: AtomicU64,
+    /// Total and longest time between a thread becoming ready and running
+    run_delay_ns: AtomicU64,
+    max_run_delay_ns: AtomicU64,
 }
 
 
 impl Process {
     pub fn new(page_tables: Arc<Paging>) -> Self {
-        Self { id: next_process_id(), virtual_address_space: VirtualAddressSpace::new(page_tables) }
+        Self {
+            id: next_process_id(),
+            virtual_address_space: VirtualAddressSpace::new(page_tables),
+            name: RwLock::new(String::new()),
+            net_namespace: AtomicUsize::new(ROOT_NAMESPACE),
+            sandbox: RwLock::new(Sandbox::unrestricted()),
+            cwd: RwLock::new("/".to_string()),
+            heap_memory: AtomicUsize::new(0),
+            main_thread: AtomicUsize::new(0),
+            parent: AtomicUsize::new(0),
+            priority: AtomicUsize::new(Priority::Normal.into()),
+            pending_signals: AtomicU32::new(0),
+            killed: AtomicBool::new(false),
+            stdin: AtomicUsize::new(STDIO_TERMINAL),
+            stdout: AtomicUsize::new(STDIO_TERMINAL),
+            usage: UsageCounters::default(),
+            tls_template: RwLock::new(None),
+        }
     }
 
     /// Return the id of the process
@@ -37,6 +125,167 @@ impl Process {
         self.id
     }
 
+    /// Return the name of the program running in the process
+    pub fn name(&self) -> String {
+        self.name.read().clone()
+    }
+
+    /// Set the name of the program running in the process
+    pub fn set_name(&self, name: &str) {
+        *self.name.write() = name.to_string();
+    }
+
+    /// Return the id of the network namespace of the process
+    pub fn net_namespace(&self) -> usize {
+        self.net_namespace.load(Relaxed)
+    }
+
+    /// Move the process into another network namespace and return the id of the previous one. \
+    /// This does not update the members of the namespaces (see `network::join_namespace()` and `network::leave_namespace()`).
+    pub fn set_net_namespace(&self, namespace: usize) -> usize {
+        self.net_namespace.swap(namespace, Relaxed)
+    }
+
+    /// Return the sandbox of the process
+    pub fn sandbox(&self) -> Sandbox {
+        self.sandbox.read().clone()
+    }
+
+    /// Replace the sandbox of the process (must only be done before the process is started)
+    pub fn set_sandbox(&self, sandbox: Sandbox) {
+        *self.sandbox.write() = sandbox;
+    }
+
+    /// Return the priority of new threads of the process
+    pub fn priority(&self) -> Priority {
+        Priority::try_from(self.priority.load(Relaxed)).unwrap_or(Priority::Normal)
+    }
+
+    /// Set the priority of new threads (existing ones are changed by `Scheduler::set_process_priority()`)
+    pub fn set_priority(&self, priority: Priority) {
+        self.priority.store(priority.into(), Relaxed);
+    }
+
+    /// Mark `signal` as pending, it is delivered by `signal::deliver()`
+    pub fn raise_signal(&self, signal: Signal) {
+        self.pending_signals.fetch_or(signal.mask(), Relaxed);
+    }
+
+    /// Return true, if any signal is waiting to be delivered
+    pub fn has_pending_signals(&self) -> bool {
+        self.pending_signals.load(Relaxed) != 0
+    }
+
+    /// Take all pending signals and return the most urgent one (if any)
+    pub fn take_signal(&self) -> Option<Signal> {
+        let pending = self.pending_signals.swap(0, Relaxed);
+        [Signal::Kill, Signal::Terminate, Signal::Interrupt].into_iter()
+            .find(|signal| pending & signal.mask() != 0)
+    }
+
+    /// Mark the process as terminated (see `ProcessManager::kill()`)
+    pub fn set_killed(&self) {
+        self.killed.store(true, Relaxed);
+    }
+
+    /// Return true, if the process has been terminated and its threads must not continue
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Relaxed)
+    }
+
+    /// Return the handle of the object used as standard input (`None` = the terminal)
+    pub fn stdin(&self) -> Option<usize> {
+        Some(self.stdin.load(Relaxed)).filter(|&handle| handle != STDIO_TERMINAL)
+    }
+
+    /// Return the handle of the object used as standard output (`None` = the terminal)
+    pub fn stdout(&self) -> Option<usize> {
+        Some(self.stdout.load(Relaxed)).filter(|&handle| handle != STDIO_TERMINAL)
+    }
+
+    /// Set the handles used as standard input and output (`None` = the terminal), they must be usable by the process
+    pub fn set_stdio(&self, stdin: Option<usize>, stdout: Option<usize>) {
+        self.stdin.store(stdin.unwrap_or(STDIO_TERMINAL), Relaxed);
+        self.stdout.store(stdout.unwrap_or(STDIO_TERMINAL), Relaxed);
+    }
+
+    /// Return the current working directory of the process
+    pub fn cwd(&self) -> String {
+        self.cwd.read().clone()
+    }
+
+    /// Change the current working directory of the process (`path` must be an existing directory)
+    pub fn set_cwd(&self, path: String) {
+        *self.cwd.write() = path;
+    }
+
+    /// Account for `size` bytes of newly mapped heap memory.
+    /// Returns `ENOMEM` if this would exceed the memory limit of the sandbox.
+    pub fn charge_heap_memory(&self, size: usize) -> Result<(), Errno> {
+        let limit = self.sandbox.read().max_memory();
+        self.heap_memory.fetch_update(Relaxed, Relaxed, |used| {
+            let used = used.checked_add(size)?;
+            (limit == 0 || used <= limit).then_some(used)
+        }).map(|_| ()).map_err(|_| Errno::ENOMEM)
+    }
+
+    /// Return the heap memory mapped by the process (in bytes)
+    pub fn heap_memory(&self) -> usize {
+        self.heap_memory.load(Relaxed)
+    }
+
+    /// Undo `charge_heap_memory()`, e.g. if mapping the memory failed.
+    pub fn release_heap_memory(&self, size: usize) {
+        self.heap_memory.fetch_sub(size, Relaxed);
+    }
+
+    /// Return the location of the thread-local variables of the program (`None`, if it has none)
+    pub fn tls_template(&self) -> Option<TlsTemplate> {
+        *self.tls_template.read()
+    }
+
+    /// Set by the ELF loader (must only be done before the process is started)
+    pub fn set_tls_template(&self, template: TlsTemplate) {
+        *self.tls_template.write() = Some(template);
+    }
+
+    /// Return the id of the first thread of the process
+    pub fn main_thread(&self) -> usize {
+        self.main_thread.load(Relaxed)
+    }
+
+    /// Remember the first thread of the process (must only be done before the process is started)
+    pub fn set_main_thread(&self, thread_id: usize) {
+        self.main_thread.store(thread_id, Relaxed);
+    }
+
+    /// Return the id of the process, which has started this one
+    pub fn parent(&self) -> usize {
+        self.parent.load(Relaxed)
+    }
+
+    /// Remember the process, which has started this one (must only be done before the process is started)
+    pub fn set_parent(&self, process_id: usize) {
+        self.parent.store(process_id, Relaxed);
+    }
+
+    pub fn usage(&self) -> &UsageCounters {
+        &self.usage
+    }
+
+    /// Get the current resource usage of the process.
+    pub fn resource_usage(&self) -> ResourceUsage {
+        let usage = &self.usage;
+        ResourceUsage {
+            user_time_us: usage.user_time_ns.load(Relaxed) / 1000,
+            system_time_us: usage.system_time_ns.load(Relaxed) / 1000,
+            max_rss_kib: self.virtual_address_space.max_resident_pages() * PAGE_SIZE as u64 / 1024,
+            voluntary_switches: usage.voluntary_switches.load(Relaxed),
+            involuntary_switches: usage.involuntary_switches.load(Relaxed),
+            page_faults: usage.page_faults.load(Relaxed),
+        }
+    }
+
     pub fn exit(&self) {
         process_manager().write().exit(self.id);
     }
@@ -49,10 +298,14 @@ impl Process {
             }).copied().collect()
     }
 
-    pub fn kill_all_threads_but_current(&self) {
-        self.thread_ids().iter()
-            .filter(|&&thread_id| thread_id != scheduler().current_thread().id())
-            .for_each(|&thread_id| scheduler().kill(thread_id));
+    /// Close the sockets, events and open objects of the process. \
+    /// This must be done explicitly, once all threads have exited, because the sockets keep a reference to the process.
+    pub fn release_resources(&self) {
+        network::close_sockets_for_process(self);
+        network::leave_namespace(self);
+        network::vsock::close_sockets_for_process(self.id());
+        event::close_for_process(self.id());
+        naming::api::close_for_process(self.id());
     }
 
     pub fn dump(&self) {
@@ -61,6 +314,45 @@ impl Process {
 
 }
 
+impl UsageCounters {
+    /// Charge a timer tick of `ns` nanoseconds.
+    pub fn add_cpu_time(&self, user: bool, ns: u64) {
+        let counter = if user { &self.user_time_ns } else { &self.system_time_ns };
+        counter.fetch_add(ns, Relaxed);
+    }
+
+    pub fn add_context_switch(&self, voluntary: bool) {
+        let counter = if voluntary { &self.voluntary_switches } else { &self.involuntary_switches };
+        counter.fetch_add(1, Relaxed);
+    }
+
+    pub fn add_page_fault(&self) {
+        self.page_faults.fetch_add(1, Relaxed);
+    }
+
+    /// Count a dispatch of a thread, that has waited `ns` nanoseconds in a ready queue.
+    pub fn add_run_delay(&self, ns: u64) {
+        self.dispatches.fetch_add(1, Relaxed);
+        self.run_delay_ns.fetch_add(ns, Relaxed);
+        self.max_run_delay_ns.fetch_max(ns, Relaxed);
+    }
+
+    /// Time spent in user and in kernel mode (in nanoseconds)
+    pub fn cpu_time_ns(&self) -> (u64, u64) {
+        (self.user_time_ns.load(Relaxed), self.system_time_ns.load(Relaxed))
+    }
+
+    /// Number of voluntary and involuntary context switches
+    pub fn context_switches(&self) -> (u64, u64) {
+        (self.voluntary_switches.load(Relaxed), self.involuntary_switches.load(Relaxed))
+    }
+
+    /// Number of dispatches and the total and longest time spent in a ready queue before them (in nanoseconds)
+    pub fn run_delay_ns(&self) -> (u64, u64, u64) {
+        (self.dispatches.load(Relaxed), self.run_delay_ns.load(Relaxed), self.max_run_delay_ns.load(Relaxed))
+    }
+}
+
 impl PartialEq for Process {
     fn eq(&self, other: &Self) -> bool {
         self.id == other.id
@@ -75,6 +367,6 @@ impl core::fmt::Debug for Process {
 
 impl Drop for Process {
     fn drop(&mut self) {
-        network::close_sockets_for_process(self)
+        self.release_resources();
     }
 }
@@ -9,18 +9,26 @@
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use log::info;
+use syscall::usage::ResourceUsage;
 use x86_64::structures::paging::frame::PhysFrameRange;
 use x86_64::structures::paging::Page;
 use x86_64::VirtAddr;
 
 use crate::memory::{vmm, MemorySpace};
 use crate::memory::vma::VmaType;
+use crate::network::pending::{self, CancelReason};
 use crate::process::process::Process;
 use crate::scheduler;
+use crate::sync::futex;
+
+/// Number of exited processes, whose resource usage is kept until it is picked up
+const MAX_EXITED_USAGE: usize = 32;
 
 pub struct ProcessManager {
     active_processes: Vec<Arc<Process>>,
     exited_processes: Vec<Arc<Process>>, // processed by cleanup thread later
+    /// Final resource usage of exited processes, identified by their first thread, together with the id of their parent
+    exited_usage: Vec<(usize, usize, ResourceUsage)>,
 }
 
 impl ProcessManager {
@@ -28,6 +36,7 @@ impl ProcessManager {
         Self {
             active_processes: Vec::new(),
             exited_processes: Vec::new(),
+            exited_usage: Vec::new(),
         }
     }
 
@@ -49,6 +58,7 @@ impl ProcessManager {
 
         let paging = vmm::create_kernel_address_space();
         let kernel_process = Arc::new(Process::new(paging));
+        kernel_process.set_name("kernel");
         self.active_processes.push(Arc::clone(&kernel_process));
 
         // TODO: adjust this when removing 1:1 mapping
@@ -86,6 +96,11 @@ impl ProcessManager {
         self.active_processes.iter().map(|process| process.id()).collect()
     }
 
+    /// Get reference to the active process with the id `process_id`
+    pub fn process(&self, process_id: usize) -> Option<Arc<Process>> {
+        self.active_processes.iter().find(|process| process.id() == process_id).map(Arc::clone)
+    }
+
     /// Get reference to kernel process
     pub fn kernel_process(&self) -> Option<Arc<Process>> {
         self.active_processes.first().map(Arc::clone)
@@ -100,41 +115,68 @@ impl ProcessManager {
         }
     }
 
-    /// Exit a process by its id
+    /// Exit a process by its id (called by one of its threads, which exits afterwards).
+    /// The other threads are terminated like by `kill()`.
     pub fn exit(&mut self, process_id: usize) {
-        let index = self
-            .active_processes
-            .iter()
-            .position(|process| process.id == process_id)
-            .expect("Process: Trying to exit a non-existent process!");
+        self.terminate(process_id);
+    }
 
-        let process = Arc::clone(&self.active_processes[index]);
-        process.kill_all_threads_but_current();
+    /// Get (and forget) the final resource usage of the exited process, whose first thread had the id `thread_id`.
+    /// Only its parent process (with the id `parent`) may do this.
+    pub fn take_exited_usage(&mut self, thread_id: usize, parent: usize) -> Option<ResourceUsage> {
+        let index = self.exited_usage.iter().position(|(id, parent_id, _)| *id == thread_id && *parent_id == parent)?;
+        Some(self.exited_usage.remove(index).2)
+    }
 
-        self.active_processes.swap_remove(index);
-        self.exited_processes.push(process);
+    fn record_usage(&mut self, process: &Process) {
+        let thread_id = process.main_thread();
+        if thread_id == 0 {
+            return;
+        }
+        // nobody is interested in the oldest entries anymore
+        if self.exited_usage.len() >= MAX_EXITED_USAGE {
+            self.exited_usage.remove(0);
+        }
+        self.exited_usage.push((thread_id, process.parent(), process.resource_usage()));
     }
 
-    /// Kill a process by its id
+    /// Kill a process by its id. \
+    /// Its threads can't just be removed from the scheduler, because they may hold locks in the kernel.
+    /// Instead, they exit at the next point, where they hold none: Blocked and sleeping threads are woken up and exit right away,
+    /// running ones exit, when they would block or return to user mode.
+    /// Once all of them have exited, `drop_exited_process()` closes the sockets and open objects and frees the address space.
     pub fn kill(&mut self, process_id: usize) {
-        let index = self
-            .active_processes
-            .iter()
-            .position(|process| process.id == process_id)
-            .expect("Process: Trying to kill a non-existent process!");
-
-        let process = Arc::clone(&self.active_processes[index]);
-        for thread_id in process.thread_ids() {
-            scheduler().kill(thread_id);
-        }
+        self.terminate(process_id);
+    }
+
+    fn terminate(&mut self, process_id: usize) {
+        // Several threads may terminate the process at the same time
+        let Some(index) = self.active_processes.iter().position(|process| process.id == process_id) else {
+            return;
+        };
+
+        let process = self.active_processes.swap_remove(index);
+        process.set_killed();
+        pending::cancel_for_process(process_id, CancelReason::Killed);
+        futex::wake_process(process_id);
+        scheduler().wake_process(process_id);
 
-        self.active_processes.swap_remove(index);
+        self.record_usage(&process);
         self.exited_processes.push(process);
     }
 
-    /// 
+    /// Drop the exited processes, whose threads have all exited (called by the cleanup thread). \
+    /// The resources of a process are released explicitly, because its sockets keep it alive otherwise.
     pub fn drop_exited_process(&mut self) {
-        self.exited_processes.clear();
+        let threads = scheduler().threads();
+        self.exited_processes.retain(|process| {
+            if threads.iter().any(|thread| thread.process().id() == process.id()) {
+                return true;
+            }
+
+            process.release_resources();
+            false
+        });
     }
 
     /// Dump all active processes
@@ -0,0 +1,136 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: sandbox                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Container-style sandbox for processes, combining                ║
+   ║           - a root directory override for the naming service,           ║
+   ║           - a capability mask, checked by the system calls,             ║
+   ║           - resource limits (threads and heap memory) and               ║
+   ║           - the user and group id, checked by the naming service.       ║
+   ║         Sandboxes are inherited by child processes and can only be      ║
+   ║         restricted further, never relaxed.                              ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::format;
+use alloc::string::{String, ToString};
+use syscall::return_vals::Errno;
+use syscall::sandbox::{Capabilities, ROOT_ID};
+use crate::process_manager;
+
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    /// Absolute path of the directory, that is the root directory of the naming service for this process
+    root: Option<String>,
+    capabilities: Capabilities,
+    /// 0 means unlimited
+    max_threads: usize,
+    /// 0 means unlimited
+    max_memory: usize,
+    uid: u32,
+    gid: u32,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self::unrestricted()
+    }
+}
+
+impl Sandbox {
+    pub const fn unrestricted() -> Self {
+        Self { root: None, capabilities: Capabilities::all(), max_threads: 0, max_memory: 0, uid: ROOT_ID, gid: ROOT_ID }
+    }
+
+    /// Create a sandbox for a child process, which is at most as permissive as `self`.
+    /// `root` is interpreted relative to the current root. Only root may change the user and group id.
+    pub fn restrict(&self, root: Option<&str>, capabilities: Capabilities, max_threads: usize, max_memory: usize, uid: Option<u32>, gid: Option<u32>) -> Result<Self, Errno> {
+        let uid = uid.unwrap_or(self.uid);
+        let gid = gid.unwrap_or(self.gid);
+        if self.uid != ROOT_ID && (uid != self.uid || gid != self.gid) {
+            return Err(Errno::EACCES);
+        }
+
+        let root = match root {
+            Some(root) => {
+                if !root.starts_with('/') || root.split('/').any(|component| component == "..") {
+                    return Err(Errno::EINVAL);
+                }
+                match root.trim_end_matches('/') {
+                    "" => self.root.clone(),
+                    root => Some(format!("{}{}", self.root.as_deref().unwrap_or(""), root)),
+                }
+            }
+            None => self.root.clone(),
+        };
+
+        Ok(Self {
+            root,
+            capabilities: self.capabilities & capabilities,
+            max_threads: stricter_limit(self.max_threads, max_threads),
+            max_memory: stricter_limit(self.max_memory, max_memory),
+            uid,
+            gid,
+        })
+    }
+
+    pub fn root(&self) -> Option<&str> {
+        self.root.as_deref()
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    pub fn max_threads(&self) -> usize {
+        self.max_threads
+    }
+
+    pub fn max_memory(&self) -> usize {
+        self.max_memory
+    }
+
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// Translate an absolute path of the sandboxed process into a path of the global naming service.
+    pub fn translate_path(&self, path: &str) -> Result<String, Errno> {
+        match &self.root {
+            // relative paths are not supported by the naming service anyway
+            None => Ok(path.to_string()),
+            Some(_) if !path.starts_with('/') => Ok(path.to_string()),
+            // paths must not leave the root directory
+            Some(_) if path.split('/').any(|component| component == "..") => Err(Errno::EACCES),
+            Some(root) if path == "/" => Ok(root.clone()),
+            Some(root) => Ok(format!("{root}{path}")),
+        }
+    }
+}
+
+/// Check whether the current process has `capability`.
+/// Returns `EACCES` otherwise.
+pub fn check_capability(capability: Capabilities) -> Result<(), Errno> {
+    if process_manager().read().current_process().sandbox().capabilities().contains(capability) {
+        Ok(())
+    } else {
+        Err(Errno::EACCES)
+    }
+}
+
+/// User and group id of the current process
+pub fn credentials() -> (u32, u32) {
+    let sandbox = process_manager().read().current_process().sandbox();
+    (sandbox.uid(), sandbox.gid())
+}
+
+fn stricter_limit(current: usize, requested: usize) -> usize {
+    match (current, requested) {
+        (0, limit) | (limit, 0) => limit,
+        (current, requested) => current.min(requested),
+    }
+}
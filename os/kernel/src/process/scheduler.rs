@@ -1,89 +1,381 @@
 /* ╔═════════════════════════════════════════════════════════════════════════╗
    ║ Module: scheduler                                                       ║
    ╟─────────────────────────────────────────────────────────────────────────╢
-   ║ Implementation of a basic round-robin scheduler.                        ║
+   ║ Implementation of a scheduler with two classes (see 'SchedulingClass'): ║
+   ║ Strict threads (by default kernel threads) are scheduled round-robin    ║
+   ║ with priorities: A ready thread of the highest priority runs next,      ║
+   ║ threads of the same priority share the CPU. Fair threads (by default    ║
+   ║ user threads) share the CPU weighted by their priority, like in Linux'  ║
+   ║ CFS: Each one has a virtual runtime, which grows with the time it runs, ║
+   ║ divided by its weight ('FAIR_WEIGHTS'), and the one with the smallest   ║
+   ║ virtual runtime runs next. Woken up threads start slightly behind the   ║
+   ║ others ('SLEEPER_CREDIT_NS'), so interactive threads run right away,    ║
+   ║ while CPU-bound threads can't starve them. Strict threads of priority   ║
+   ║ 'Normal' and higher run before all fair threads, strict 'Background'    ║
+   ║ threads after them. To prevent starvation, threads waiting for longer   ║
+   ║ than 'STARVATION_MS' run next, regardless of their class and priority.  ║
+   ║                                                                         ║
+   ║ Each core, that runs threads, has its own ready queue, current thread   ║
+   ║ and idle thread. New and woken up threads are inserted into the queue   ║
+   ║ of the least loaded core (waking it up with a reschedule IPI). Every    ║
+   ║ 'BALANCE_TICKS' ticks and whenever its queue is empty, a core takes     ║
+   ║ over threads from the busiest core. Other cores' queues are only        ║
+   ║ locked with 'try_lock()' while holding the own one, so this can't       ║
+   ║ deadlock. Threads only run on the cores allowed by their affinity: A    ║
+   ║ thread, that becomes ready on another core, is put into a list of       ║
+   ║ migrating threads, from which an allowed core takes it. Idle threads    ║
+   ║ put their core to sleep until the next interrupt (see 'idle').          ║
+   ║ A core taken offline (e.g. to park it) gives its ready threads and its  ║
+   ║ current thread to the other cores and only runs its idle thread.        ║
    ║                                                                         ║
    ║ Public functions                                                        ║
    ║   - active_thread_ids      get a list of all active thread IDs          ║
-   ║   - current_thread         get the currently running thread             ║
+   ║   - current_thread         get the thread running on the calling core   ║
    ║   - current_ids            get the (pid, tid) of the current thread     ║
    ║   - exit                   exit the calling thread                      ║
    ║   - join                   wait for a thread to finish                  ║
    ║   - kill                   kill a thread                                ║
    ║   - set_init               set the scheduler as initialized             ║
    ║   - thread                 get reference to a thread                    ║
-   ║   - ready                  insert a thread in the ready queue           ║
+   ║   - threads                get a snapshot of all threads                ║
+   ║   - ready                  insert a thread in the ready queue of a core ║
+   ║                            (and wake up that core via IPI)              ║
    ║   - sleep                  put the caller into sleeping mode            ║
-   ║   - start                  start the scheduler                          ║
+   ║   - set_priority           change the priority and class of a thread    ║
+   ║   - inherit_priority       raise the priority of a lock holder          ║
+   ║   - set_process_priority   change the priority of a process' threads    ║
+   ║   - set_affinity           restrict a thread to some cores              ║
+   ║   - postpone_timeouts      delay the wakeup of all sleeping threads     ║
+   ║   - runs_threads           check if a core runs threads                 ║
+   ║   - take_offline           stop running threads on a core               ║
+   ║   - bring_online           run threads on an offline core again         ║
+   ║   - is_idle                check if a core can be stopped               ║
+   ║   - start                  start the scheduler on the calling core      ║
    ║   - switch_thread_from_interrupt  switch thread, called from interrupt  ║
+   ║   - tick                   timer tick of a core (preempts and balances) ║
+   ║   - account_tick           charge a timer tick to the current thread    ║
+   ║   - core_statistics        get switches, queue length and idle time     ║
+   ║                            of each core                                 ║
    ║   - switch_thread_no_interrupt    switch thread, not called from int.   ║
    ║   - current_ids            get the (pid, tid) of the current thread     ║
-   ║   - prepare_to_block       prepare the calling thread to block          ║
+   ║   - park_current           prepare the calling thread to block          ║
    ║   - block_if_allowed       block the calling thread (if ok)             ║
+   ║   - block_holding_locks    same, but doesn't exit for killed processes  ║
+   ║   - block_if_allowed_until same, but with a timeout                     ║
    ║   - unblock                unblock a given thread                       ║
+   ║   - try_wake_early         same, but for interrupt handlers             ║
+   ║   - wake_process           wake up all waiting threads of a process     ║
    ║   - get_status             for ps command - get all processes & threads ║
+   ║   - status                 same as get_status, but as string            ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Fabian Ruhland & Michael Schopettner, 04.01.2026, HHU           ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
-use crate::process::thread::{Thread, ThreadState};
+use crate::network;
+use crate::network::pending::CancelReason;
+use crate::process::idle::{self, Wakeup};
+use crate::process::process::Process;
+use crate::process::thread::{affinity_allows, Thread, ThreadState};
+use crate::interrupt::ipi::{self, Ipi};
+use crate::interrupt::interrupt_handler::InterruptHandler;
 use crate::{allocator, apic, scheduler, timer, tss};
+use alloc::boxed::Box;
 use alloc::collections::VecDeque;
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use log::debug;
 use core::fmt::Write;
-use core::sync::atomic::AtomicUsize;
-use core::sync::atomic::Ordering::Relaxed;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize};
+use core::sync::atomic::Ordering::{Acquire, AcqRel, Relaxed, Release};
+use core::ops::RangeInclusive;
 use core::{panic, ptr};
 use smallmap::Map;
-use spin::{Mutex, MutexGuard};
+use spin::{Mutex, MutexGuard, Once};
+use syscall::priority::{Priority, SchedulingClass};
 use syscall::return_vals::Errno;
+use x86_64::instructions::interrupts;
 
 use crate::memory;
 use log::info;
 
+/// No core (for threads, that haven't run yet, and free entries in `Scheduler::cores`)
+pub(super) const NO_CORE: u32 = u32::MAX;
+/// Maximum number of cores running threads
+const MAX_CORES: usize = 64;
+/// A ready thread, that has waited this long, runs next regardless of its priority
+const STARVATION_MS: usize = 200;
+/// Every this many timer ticks, a core takes over threads from the busiest core
+const BALANCE_TICKS: usize = 10;
+/// Weights of fair threads by priority (a thread with twice the weight gets twice the CPU time)
+const FAIR_WEIGHTS: [u64; Priority::COUNT] = [8192, 3072, 1024, 128];
+/// Weight of a fair thread, whose virtual runtime grows as fast as the time it runs
+const NORMAL_WEIGHT: u64 = FAIR_WEIGHTS[Priority::Normal as usize];
+/// A woken up fair thread starts with a virtual runtime this much smaller than the smallest one of its queue
+const SLEEPER_CREDIT_NS: u64 = 5_000_000;
+/// A running fair thread is only preempted by a fair thread, whose virtual runtime is smaller by this much
+const MIN_GRANULARITY_NS: u64 = 1_000_000;
+
 // thread IDs
 static THREAD_ID_COUNTER: AtomicUsize = AtomicUsize::new(1);
 
+/// The reschedule IPI is registered by the first core starting the scheduler
+static RESCHEDULE_IPI: Once = Once::new();
+
 pub fn next_thread_id() -> usize {
     THREAD_ID_COUNTER.fetch_add(1, Relaxed)
 }
 
-/// Everything related to the threads in ready state in the scheduler
+/// Everything related to the threads in ready state on one core
 struct ReadyState {
-    initialized: bool,
     current_thread: Option<Arc<Thread>>,
-    ready_queue: VecDeque<Arc<Thread>>,
+    /// Runs, if there is no other ready thread (never inserted into a queue)
+    idle_thread: Option<Arc<Thread>>,
+    ready_queue: RunQueue,
+    /// Time, up to which the current thread has been charged to its virtual runtime (in ns)
+    charged_until: usize,
 }
 
 impl ReadyState {
-    pub fn new() -> Self {
+    const fn new() -> Self {
         Self {
-            initialized: false,
             current_thread: None,
-            ready_queue: VecDeque::new(),
+            idle_thread: None,
+            ready_queue: RunQueue::new(),
+            charged_until: 0,
         }
     }
+
+    fn is_idle(&self, thread: &Arc<Thread>) -> bool {
+        self.idle_thread.as_ref().is_some_and(|idle| Arc::ptr_eq(idle, thread))
+    }
+}
+
+/// Ready threads: One queue for each priority of strict threads and a list of fair threads. \
+/// Strict threads are inserted at the front and taken from the back. All threads are stored together
+/// with the time they became ready (in ns).
+struct RunQueue {
+    queues: [VecDeque<(Arc<Thread>, usize)>; Priority::COUNT],
+    fair: Vec<(Arc<Thread>, usize)>,
+    /// Smallest virtual runtime of the fair threads, that have run (never decreases)
+    min_vruntime: u64,
+}
+
+impl RunQueue {
+    const fn new() -> Self {
+        Self { queues: [const { VecDeque::new() }; Priority::COUNT], fair: Vec::new(), min_vruntime: 0 }
+    }
+
+    fn push(&mut self, thread: Arc<Thread>) {
+        self.push_since(thread, timer().systime_ns());
+    }
+
+    /// Insert `thread`, which has become ready at `since` (in ns), e.g. when moving it from another queue.
+    fn push_since(&mut self, thread: Arc<Thread>, since: usize) {
+        match thread.scheduling_class() {
+            SchedulingClass::Strict => self.queues[usize::from(thread.priority())].push_front((thread, since)),
+            SchedulingClass::Fair => {
+                thread.set_vruntime(self.placed_vruntime(&thread));
+                self.fair.push((thread, since));
+            }
+        }
+    }
+
+    /// Insert the fair or strict `thread` taken from the queue of another core with the smallest virtual runtime
+    /// `other_min_vruntime`, keeping its distance to the other threads.
+    fn push_migrated(&mut self, thread: Arc<Thread>, since: usize, other_min_vruntime: u64) {
+        thread.set_vruntime((thread.vruntime() + self.min_vruntime).saturating_sub(other_min_vruntime));
+        self.push_since(thread, since);
+    }
+
+    /// Virtual runtime of the fair `thread`, when it is inserted: A thread, that has slept, could otherwise
+    /// run for as long as it has slept, so it starts only slightly behind the others.
+    fn placed_vruntime(&self, thread: &Thread) -> u64 {
+        thread.vruntime().max(self.min_vruntime.saturating_sub(SLEEPER_CREDIT_NS))
+    }
+
+    /// Take the next thread to run: A thread, that has waited for more than `STARVATION_MS`, or else strict threads
+    /// of priority `Normal` and higher, the fair thread with the smallest virtual runtime and strict `Background` threads.
+    /// Strict threads of the same priority run in the order, in which they have become ready. \
+    /// `preempted` is the running thread, if it is preempted: It is only replaced by a strict thread of at least
+    /// the same priority or by a fair thread, whose virtual runtime is smaller by `MIN_GRANULARITY_NS` (if it is fair).
+    /// Threads, for which `runnable` returns false, are skipped. Returns the thread and the time it has become ready.
+    fn pop(&mut self, preempted: Option<&Thread>, runnable: impl Fn(&Thread) -> bool) -> Option<(Arc<Thread>, usize)> {
+        let now = timer().systime_ns();
+        let starving = |since: usize| now.saturating_sub(since) >= STARVATION_MS * 1_000_000;
+        let oldest = |queue: &VecDeque<(Arc<Thread>, usize)>| queue.iter().rposition(|(thread, _)| runnable(thread.as_ref()));
+
+        let starving_strict = self.queues.iter().enumerate().find_map(|(level, queue)| {
+            let index = oldest(queue)?;
+            starving(queue[index].1).then_some((level, index))
+        });
+        if let Some((level, index)) = starving_strict {
+            return self.queues[level].remove(index);
+        }
+        let starving_fair = self.fair.iter()
+            .position(|(thread, since)| starving(*since) && runnable(thread.as_ref()));
+        if let Some(index) = starving_fair {
+            return Some(self.take_fair(index));
+        }
+
+        // the lowest priority of strict threads, that may run, and the virtual runtime, that a fair thread must be below
+        let (lowest, fair_limit) = match preempted {
+            None => (Priority::Background, Some(u64::MAX)),
+            Some(thread) => match thread.scheduling_class() {
+                SchedulingClass::Strict => (thread.priority(), (thread.priority() == Priority::Background).then_some(u64::MAX)),
+                SchedulingClass::Fair => (Priority::Normal, Some(thread.vruntime().saturating_sub(MIN_GRANULARITY_NS))),
+            },
+        };
+
+        let strict = |queues: &[VecDeque<(Arc<Thread>, usize)>], levels: RangeInclusive<usize>| {
+            levels.clone().zip(&queues[levels]).find_map(|(level, queue)| oldest(queue).map(|index| (level, index)))
+        };
+        if let Some((level, index)) = strict(&self.queues, 0..=usize::from(lowest.min(Priority::Normal))) {
+            return self.queues[level].remove(index);
+        }
+        if let Some(limit) = fair_limit {
+            let next = self.fair.iter().enumerate()
+                .filter(|(_, (thread, _))| thread.vruntime() < limit && runnable(thread.as_ref()))
+                .min_by_key(|(_, (thread, _))| thread.vruntime());
+            if let Some((index, _)) = next {
+                return Some(self.take_fair(index));
+            }
+        }
+        if lowest == Priority::Background {
+            let (level, index) = strict(&self.queues, usize::from(Priority::Background)..=usize::from(Priority::Background))?;
+            return self.queues[level].remove(index);
+        }
+        None
+    }
+
+    /// Remove the fair thread at `index` to run it
+    fn take_fair(&mut self, index: usize) -> (Arc<Thread>, usize) {
+        let (thread, since) = self.fair.swap_remove(index);
+        self.min_vruntime = self.min_vruntime.max(thread.vruntime());
+        (thread, since)
+    }
+
+    /// Remove the thread `thread_id`, if it is ready.
+    fn remove(&mut self, thread_id: usize) -> Option<Arc<Thread>> {
+        if let Some(position) = self.fair.iter().position(|(thread, _)| thread.id() == thread_id) {
+            return Some(self.fair.swap_remove(position).0);
+        }
+        self.queues.iter_mut().find_map(|queue| {
+            let position = queue.iter().position(|(thread, _)| thread.id() == thread_id)?;
+            queue.remove(position).map(|(thread, _)| thread)
+        })
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&Arc<Thread>) -> bool) {
+        self.queues.iter_mut().for_each(|queue| queue.retain(|(thread, _)| keep(thread)));
+        self.fair.retain(|(thread, _)| keep(thread));
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Arc<Thread>> {
+        self.queues.iter().flatten().chain(self.fair.iter()).map(|(thread, _)| thread)
+    }
+
+    fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum::<usize>() + self.fair.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty) && self.fair.is_empty()
+    }
+}
+
+/// Scheduling state of one core. Entries in `Scheduler::cores` are assigned on first use.
+struct CoreQueue {
+    /// Local APIC id of the core (`NO_CORE` for a free entry)
+    apic_id: AtomicU32,
+    /// Set by `Scheduler::start()`, only then threads are inserted into the queue of the core
+    /// (cleared again by `Scheduler::take_offline()`)
+    online: AtomicBool,
+    /// Number of completed thread switches (see `Scheduler::is_switched_out()`)
+    switches: AtomicUsize,
+    /// Number of timer ticks (for load balancing)
+    ticks: AtomicUsize,
+    /// Sampled time spent in the idle thread, in user mode and in kernel mode (see `account_tick()`)
+    idle_ns: AtomicU64,
+    user_ns: AtomicU64,
+    system_ns: AtomicU64,
+    /// Written by `Scheduler::wake_core()`, ends the wait of the idle thread (see `idle::wait()`)
+    wakeup: Wakeup,
+    state: Mutex<ReadyState>,
+}
+
+impl CoreQueue {
+    const fn new() -> Self {
+        Self {
+            apic_id: AtomicU32::new(NO_CORE),
+            online: AtomicBool::new(false),
+            switches: AtomicUsize::new(0),
+            ticks: AtomicUsize::new(0),
+            idle_ns: AtomicU64::new(0),
+            user_ns: AtomicU64::new(0),
+            system_ns: AtomicU64::new(0),
+            wakeup: Wakeup::new(),
+            state: Mutex::new(ReadyState::new()),
+        }
+    }
+
+    fn apic_id(&self) -> u32 {
+        self.apic_id.load(Relaxed)
+    }
+}
+
+/// Scheduling statistics of a core (see `Scheduler::core_statistics()`)
+pub struct CoreStatistics {
+    pub apic_id: u32,
+    /// Number of completed thread switches
+    pub switches: usize,
+    /// Number of threads in the ready queue
+    pub ready: usize,
+    /// Sampled time spent in the idle thread, in user mode and in kernel mode (in nanoseconds)
+    pub idle_ns: u64,
+    pub user_ns: u64,
+    pub system_ns: u64,
 }
 
 /// Main struct of the scheduler
 pub struct Scheduler {
-    ready_state: Mutex<ReadyState>,
+    initialized: AtomicBool,
+    cores: [CoreQueue; MAX_CORES],
     sleep_list: Mutex<Vec<(Arc<Thread>, usize)>>,
     blocked_list: Mutex<Vec<Arc<Thread>>>,
+    /// Ready threads, that may not run on the core, on which they became ready (see `push_local()`)
+    migrating: Mutex<Vec<Arc<Thread>>>,
     join_map: Mutex<Map<usize, Vec<Arc<Thread>>>>, // manage which threads are waiting for a thread-id to terminate
 }
 
 unsafe impl Send for Scheduler {}
 unsafe impl Sync for Scheduler {}
 
+/// Handler for the reschedule IPI, sent by other cores after making a thread ready
+struct RescheduleInterruptHandler;
+
+impl InterruptHandler for RescheduleInterruptHandler {
+    fn trigger(&self) {
+        scheduler().switch_thread_from_interrupt();
+    }
+}
+
 /// Called from assembly code, after the thread has been switched
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn unlock_scheduler() {
+    let core = scheduler().local();
+    core.switches.fetch_add(1, Release);
     unsafe {
-        scheduler().ready_state.force_unlock();
+        core.state.force_unlock();
+    }
+}
+
+/// Entry function of the idle threads
+extern "sysv64" fn idle() {
+    loop {
+        scheduler().switch_thread_no_interrupt();
+        scheduler().idle_wait();
     }
 }
 
@@ -91,46 +383,50 @@ impl Scheduler {
     /// Create and initialize the scheduler.
     pub fn new() -> Self {
         Self {
-            ready_state: Mutex::new(ReadyState::new()),
+            initialized: AtomicBool::new(false),
+            cores: [const { CoreQueue::new() }; MAX_CORES],
             sleep_list: Mutex::new(Vec::new()),
             blocked_list: Mutex::new(Vec::new()),
+            migrating: Mutex::new(Vec::new()),
             join_map: Mutex::new(Map::new()),
         }
     }
 
     /// Called after the scheduler has been fully initialized
     pub fn set_init(&self) {
-        self.get_ready_state().initialized = true;
+        self.initialized.store(true, Release);
     }
 
     /// Get all active thread IDs
     pub fn active_thread_ids(&self) -> Vec<usize> {
-        let state = self.get_ready_state();
-        let sleep_list = self.sleep_list.lock();
+        let mut ids = Vec::new();
+        for core in self.registered_cores() {
+            let state = self.lock_state(core);
+            ids.extend(state.ready_queue.iter().map(|thread| thread.id()));
+        }
 
-        state
-            .ready_queue
-            .iter()
-            .map(|thread| thread.id())
-            .collect::<Vec<usize>>()
-            .into_iter()
-            .chain(sleep_list.iter().map(|entry| entry.0.id()))
-            .collect()
+        let sleep_list = self.sleep_list.lock();
+        ids.extend(sleep_list.iter().map(|entry| entry.0.id()));
+        ids
     }
 
-    /// Try to return reference to current thread (called from interrupt dispatcher)
+    /// Try to return reference to the current thread of the calling core (called from interrupt dispatcher)
     pub fn try_get_current_thread(&self) -> Option<Arc<Thread>> {
-        if self.ready_state.is_locked() {
+        if !self.is_initialized() {
+            return None;
+        }
+        let core = self.local();
+        if core.state.is_locked() {
             return None;
         }
         if allocator().is_locked() {
             return None;
         }
-        let state = self.get_ready_state();
-        Some(Scheduler::current(&state))
+        let state = self.lock_state(core);
+        state.current_thread.clone()
     }
 
-    /// Return reference to current thread
+    /// Return reference to the current thread of the calling core
     pub fn current_thread(&self) -> Arc<Thread> {
         let state = self.get_ready_state();
         Scheduler::current(&state)
@@ -139,24 +435,25 @@ impl Scheduler {
     /// Return reference to thread identified by `thread_id`
     pub fn thread(&self, thread_id: usize) -> Option<Arc<Thread>> {
         debug!("Scheduler::thread: Searching for thread id {}", thread_id);
-        
-        // First check if it's the current thread
-        let state = self.ready_state.lock();
-        if let Some(current) = state.current_thread.as_ref() {
-            if current.id() == thread_id {
-                return Some(Arc::clone(current));
+
+        for core in self.registered_cores() {
+            // First check if it's the current thread of the core
+            let state = self.lock_state(core);
+            if let Some(current) = state.current_thread.as_ref() {
+                if current.id() == thread_id {
+                    return Some(Arc::clone(current));
+                }
+            }
+
+            // Check ready queue
+            if let Some(thread) = state.ready_queue
+                .iter()
+                .find(|thread| thread.id() == thread_id)
+                .cloned() {
+                    return Some(thread);
             }
         }
-        
-        // Check ready queue
-        if let Some(thread) = state.ready_queue
-            .iter()
-            .find(|thread| thread.id() == thread_id)
-            .cloned() {
-                return Some(thread);
-        }
-        drop(state);
-        
+
         // Check sleep list
         if let Some(thread) = self.sleep_list.lock()
             .iter()
@@ -164,7 +461,7 @@ impl Scheduler {
             .map(|(thread, _)| thread.clone()) {
                 return Some(thread);
         }
-        
+
         // Check blocked list
         if let Some(thread) = self.blocked_list.lock()
             .iter()
@@ -172,13 +469,36 @@ impl Scheduler {
             .cloned() {
                 return Some(thread);
         }
-        
+
         None
     }
 
+    /// Return a snapshot of all threads (except the idle threads), e.g. for listing them in user space
+    pub fn threads(&self) -> Vec<Arc<Thread>> {
+        let mut threads = Vec::new();
+        for core in self.registered_cores() {
+            let state = self.lock_state(core);
+            if let Some(current) = state.current_thread.as_ref() {
+                if !state.is_idle(current) {
+                    threads.push(Arc::clone(current));
+                }
+            }
+            threads.extend(state.ready_queue.iter().cloned());
+        }
+
+        threads.extend(self.sleep_list.lock().iter().map(|(thread, _)| Arc::clone(thread)));
+        threads.extend(self.blocked_list.lock().iter().cloned());
+        threads.extend(self.migrating.lock().iter().cloned());
+
+        // A thread may be seen twice, if it has been moved while collecting
+        threads.sort_by_key(|thread| thread.id());
+        threads.dedup_by_key(|thread| thread.id());
+        threads
+    }
+
     /// Check if scheduler is initialized
     pub fn is_initialized(&self) -> bool {
-        self.ready_state.lock().initialized
+        self.initialized.load(Acquire)
     }
 
 
@@ -189,50 +509,179 @@ impl Scheduler {
         (pid, tid)
     }
 
-    /// Start the scheduler, called only once from `boot.rs`
+    /// Start the scheduler on the calling core, called once by each core, that should run threads
+    /// (from `boot.rs` for the bootstrap processor). \
+    /// Starts with a thread from the queue of the core, one taken over from another core or the idle thread.
     pub fn start(&self) {
-        // TODO: make sure this is actually called just once
-        let mut state = self.get_ready_state();
-        state.current_thread = state.ready_queue.pop_back();
+        RESCHEDULE_IPI.call_once(|| ipi::register(Ipi::Reschedule, Box::new(RescheduleInterruptHandler)));
+
+        let idle_thread = Thread::new_idle_thread(idle);
+        let core = self.local();
+        if core.online.swap(true, AcqRel) {
+            panic!("Scheduler: Started twice on core [{}]!", core.apic_id());
+        }
+
+        let mut state = self.lock_state(core);
+        state.idle_thread = Some(Arc::clone(&idle_thread));
+        let mut first = self.next_thread(core, &mut state, None);
+        if first.is_none() {
+            self.take_migrating(core, &mut state);
+            self.balance(core, &mut state);
+            first = self.next_thread(core, &mut state, None);
+        }
+        let first = first.unwrap_or(idle_thread);
+        first.set_core(core.apic_id(), core.switches.load(Relaxed) + 1);
+        state.current_thread = Some(first);
 
         unsafe {
             Thread::start_first(state.current_thread.as_ref().expect("Failed to dequeue first thread!").as_ref());
         }
     }
 
-    /// Insert `thread` into the ready queue of the scheduler
+    /// Insert `thread` into the ready queue of a core (see `lock_target()`)
     pub fn ready(&self, thread: Arc<Thread>) {
         let id = thread.id();
 
         thread.set_state(ThreadState::Ready);
-        // If we get the lock on 'self.state' but not on 'self.join_map' the system hangs.
-        // The scheduler is not able to switch threads anymore, because of 'self.state' is locked,
+        // If we get the lock on a ready queue but not on 'self.join_map' the system may hang.
+        // If it's the queue of this core, the scheduler is not able to switch threads anymore,
         // and we will never be able to get the lock on 'self.join_map'.
-        // To solve this, we need to release the lock on 'self.state' in case we do not get
+        // To solve this, we need to release the lock on the queue in case we do not get
         // the lock on 'self.join_map' and let the scheduler switch threads until we get both locks.
-        let (mut state, mut join_map) = loop {
-            let state = self.get_ready_state();
+        let (core, mut state, mut join_map) = loop {
+            let (core, state) = self.lock_target(&thread);
             if let Some(join_map) = self.join_map.try_lock() {
-                break (state, join_map);
+                break (core, state, join_map);
             }
+            drop(state);
             self.switch_thread_no_interrupt();
         };
 
-        state.ready_queue.push_front(thread);
+        let preempt = Scheduler::should_preempt(&state, &thread);
+        state.ready_queue.push(thread);
         join_map.insert(id, Vec::new());
+        drop(join_map);
+        drop(state);
+
+        if preempt {
+            self.wake_core(core);
+        }
+    }
+
+    /// Check if the core with the local APIC id `apic_id` runs threads
+    pub fn runs_threads(&self, apic_id: u32) -> bool {
+        self.core(apic_id).is_some_and(|core| core.online.load(Acquire))
+    }
+
+    /// Stop running threads on the core with the local APIC id `apic_id` (before parking it): It is skipped by
+    /// `lock_target()` and `balance()`, its ready threads are moved to the list of migrating threads, and it gives
+    /// its current thread away at its next switch (see `switch_thread()`). \
+    /// Returns false, if the core doesn't run threads. Fails with `EBUSY` for the last core running threads.
+    pub fn take_offline(&self, apic_id: u32) -> Result<bool, Errno> {
+        let Some(core) = self.core(apic_id).filter(|core| core.online.load(Acquire)) else {
+            return Ok(false);
+        };
+        if !self.online_cores().any(|other| !ptr::eq(other, core)) {
+            return Err(Errno::EBUSY);
+        }
+        core.online.store(false, Release);
+
+        // threads, that have been inserted before `online` has been cleared, are moved as well
+        let mut state = self.lock_state(core);
+        let mut migrating = self.migrating.lock();
+        state.ready_queue.retain(|thread| {
+            migrating.push(Arc::clone(thread));
+            false
+        });
+        drop(migrating);
+        drop(state);
+
+        // switch away from the current thread right away, instead of at the next timer tick
+        ipi::send_reschedule(apic_id);
+        Ok(true)
+    }
+
+    /// Run threads on the core with the local APIC id `apic_id` again, after it has been taken offline
+    /// (cores, on which the scheduler has never been started, stay offline).
+    pub fn bring_online(&self, apic_id: u32) {
+        if let Some(core) = self.core(apic_id)
+            && self.lock_state(core).idle_thread.is_some()
+        {
+            core.online.store(true, Release);
+        }
+    }
+
+    /// Check if the core with the local APIC id `apic_id` can be stopped without stopping a thread:
+    /// It doesn't run threads or runs its idle thread, and its ready queue is not locked right now.
+    pub fn is_idle(&self, apic_id: u32) -> bool {
+        let Some(core) = self.core(apic_id) else {
+            return true;
+        };
+        core.state.try_lock().is_some_and(|state| match state.current_thread.as_ref() {
+            None => true,
+            Some(current) => state.is_idle(current),
+        })
+    }
+
+    /// Make `core` switch threads right away, instead of at its next timer tick. \
+    /// This is only needed, if a thread has been made ready by another core: The core
+    /// would keep running its current thread (or idle thread) until then.
+    fn wake_core(&self, core: &CoreQueue) {
+        let apic_id = core.apic_id();
+        if core.online.load(Acquire) && apic_id != apic().local_apic_id() {
+            core.wakeup.0.fetch_add(1, Release);
+            ipi::send_reschedule(apic_id);
+        }
+    }
+
+    /// Check if the core with `state` should switch right away, after `thread` has been inserted into its queue:
+    /// Its queue has been empty (so it may be idle), or `thread` is more important than its current thread
+    /// (see `RunQueue::pop()`).
+    fn should_preempt(state: &ReadyState, thread: &Thread) -> bool {
+        let Some(current) = state.current_thread.as_ref() else {
+            return true;
+        };
+        if state.ready_queue.is_empty() || state.is_idle(current) {
+            return true;
+        }
+
+        match (thread.scheduling_class(), current.scheduling_class()) {
+            (SchedulingClass::Strict, SchedulingClass::Strict) => thread.priority() < current.priority(),
+            (SchedulingClass::Strict, SchedulingClass::Fair) => thread.priority() <= Priority::Normal,
+            (SchedulingClass::Fair, SchedulingClass::Strict) => current.priority() == Priority::Background,
+            (SchedulingClass::Fair, SchedulingClass::Fair) => {
+                state.ready_queue.placed_vruntime(thread) + MIN_GRANULARITY_NS < current.vruntime()
+            }
+        }
+    }
+
+    /// Insert `thread` into the ready queue of a core (see `lock_target()`), without registering it for `join()`.
+    fn enqueue(&self, thread: Arc<Thread>) {
+        let (core, mut state) = self.lock_target(&thread);
+        let preempt = Scheduler::should_preempt(&state, &thread);
+        state.ready_queue.push(thread);
+        drop(state);
+
+        if preempt {
+            self.wake_core(core);
+        }
     }
 
     /// Put calling thread to sleep for `ms` milliseconds
     pub fn sleep(&self, ms: usize) {
-        let mut state = self.get_ready_state();
-
-        if !state.initialized {
+        if !self.is_initialized() {
             // Scheduler is not initialized yet, so this function has been called during the boot process
             // So we do active waiting
             timer().wait(ms);
         } else {
             // Scheduler is initialized, so we can block the calling thread
+            let state = self.get_ready_state();
             let thread = Scheduler::current(&state);
+            if thread.process().is_killed() {
+                drop(thread);
+                drop(state);
+                self.exit();
+            }
             thread.set_state(ThreadState::Sleeping);
             let wakeup_time = timer().systime_ms() + ms;
 
@@ -243,11 +692,97 @@ impl Scheduler {
             }
 
             self.block_switch(state);
+            self.exit_if_killed();
+        }
+    }
+
+    /// Change the priority and scheduling class of `thread` (moving it to another queue, if it is ready). \
+    /// A running thread keeps the CPU until its next timer tick.
+    pub fn set_priority(&self, thread: &Arc<Thread>, priority: Priority, class: SchedulingClass) {
+        thread.set_priority(priority);
+        thread.set_scheduling_class(class);
+        self.requeue(thread);
+    }
+
+    /// Let `thread` run with at least `priority` (or with `None` its own priority again), because it holds a `PiMutex`,
+    /// that a thread of this priority waits for. Like `set_priority()`, this moves a ready thread to the right queue.
+    pub fn inherit_priority(&self, thread: &Arc<Thread>, priority: Option<Priority>) {
+        thread.set_inherited_priority(priority);
+        self.requeue(thread);
+    }
+
+    /// Move `thread`, if it is ready, to the queue of its current priority.
+    fn requeue(&self, thread: &Arc<Thread>) {
+        for core in self.registered_cores() {
+            let mut state = self.lock_state(core);
+            if let Some(thread) = state.ready_queue.remove(thread.id()) {
+                state.ready_queue.push(thread);
+                return;
+            }
+        }
+    }
+
+    /// Change the priority of all threads of `process` and of the threads it creates later.
+    pub fn set_process_priority(&self, process: &Process, priority: Priority) {
+        process.set_priority(priority);
+
+        // Threads, that are not ready, are queued according to their new priority, when they become ready
+        let sleeping: Vec<Arc<Thread>> = self.sleep_list.lock().iter().map(|(thread, _)| Arc::clone(thread)).collect();
+        let blocked: Vec<Arc<Thread>> = self.blocked_list.lock().clone();
+        sleeping.iter().chain(blocked.iter())
+            .filter(|thread| thread.process().id() == process.id())
+            .for_each(|thread| thread.set_priority(priority));
+
+        for core in self.registered_cores() {
+            let mut state = self.lock_state(core);
+            if let Some(current) = state.current_thread.as_ref().filter(|thread| thread.process().id() == process.id()) {
+                current.set_priority(priority);
+            }
+            let ready: Vec<Arc<Thread>> = state.ready_queue.iter()
+                .filter(|thread| thread.process().id() == process.id())
+                .cloned()
+                .collect();
+            for thread in ready {
+                state.ready_queue.remove(thread.id());
+                thread.set_priority(priority);
+                state.ready_queue.push(thread);
+            }
+        }
+    }
+
+    /// Restrict `thread` to the cores allowed by `affinity` (see `Thread::may_run_on()`), moving it, if it is ready on another core. \
+    /// A running thread moves at its next thread switch. Fails with `EINVAL`, if none of the allowed cores runs threads.
+    pub fn set_affinity(&self, thread: &Arc<Thread>, affinity: u64) -> Result<(), Errno> {
+        if !self.online_cores().any(|core| affinity_allows(affinity, core.apic_id())) {
+            return Err(Errno::EINVAL);
+        }
+
+        thread.set_affinity(affinity);
+        for core in self.registered_cores() {
+            if core.online.load(Acquire) && thread.may_run_on(core.apic_id()) {
+                continue;
+            }
+
+            let removed = self.lock_state(core).ready_queue.remove(thread.id());
+            if let Some(thread) = removed {
+                self.enqueue(thread);
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Delay the wakeup of all sleeping threads by `ms` milliseconds. \
+    /// Called after the system time has jumped ahead, so sleeping threads don't wake up early.
+    pub fn postpone_timeouts(&self, ms: usize) {
+        for entry in self.sleep_list.lock().iter_mut() {
+            entry.1 += ms;
         }
     }
 
     /// Prepare to block the calling thread
-    /// Used from wait_queue to prepare the thread for blocking and get its (pid, tid) for later `notify_one` and `notify_all` calls
+    /// Used from wait_queue to prepare the thread for blocking and get its (pid, tid) for later `wake_one` and `wake_all` calls
     /// Returns (pid, tid)
     pub fn park_current(&self) -> (usize, usize) {
         let state = self.get_ready_state();
@@ -256,42 +791,147 @@ impl Scheduler {
         (thread.process().id(), thread.id())
     }
 
-    /// Unblock thread with given (pid, tid). \
-    /// Returns true if thread was found and unblocked, false otherwise.
-    pub fn unblock(&self, pid: usize, tid: usize) -> bool {
-       // info!("Unblock: Thread with PID={}, TID={}", pid, tid);
+    /// Block the calling thread after `park_current()`, until it is unblocked by `unblock()`. \
+    /// If this has happened in the meantime, the thread is not parking anymore and this returns right away.
+    /// If the process has been killed, the thread exits instead of returning.
+    pub fn block_if_allowed(&self) {
+        self.block_parked(None, true);
+        self.exit_if_killed();
+    }
 
-        // Synchronize against `thread_switch`
-        let mut state = self.ready_state.lock();
+    /// Like `block_if_allowed()`, but the thread blocks and returns, even if its process has been killed,
+    /// because it may hold locks (used by `PiMutex`, which doesn't know, if the caller holds other ones).
+    /// It is still woken up once by `wake_process()`, when its process is killed.
+    pub fn block_holding_locks(&self) {
+        self.block_parked(None, false);
+    }
 
-        // 1) Check if the given thread is in the blocked list -> need to be woken up
-        let blocked_thread: Option<Arc<Thread>> = {
-            let mut block_list = self.blocked_list.lock();
-            if let Some(pos) = block_list.iter().position(|t| t.id() == tid && t.process().id() == pid) {
-                Some(block_list.remove(pos))
-            } else {
-                None
+    /// Like `block_if_allowed()`, but the thread is also unblocked, when the system time reaches `wakeup_time` (in ms). \
+    /// The caller has to find out by itself, whether it has been unblocked by `unblock()` or by the timeout.
+    pub fn block_if_allowed_until(&self, wakeup_time: usize) {
+        self.block_parked(Some(wakeup_time), true);
+        self.exit_if_killed();
+    }
+
+    /// Block the parking thread (see `block_if_allowed()`). If `killable` is set, it doesn't block, once its process has been killed.
+    fn block_parked(&self, wakeup_time: Option<usize>, killable: bool) {
+        let state = self.get_ready_state();
+        let current = Scheduler::current(&state);
+        // Checked with the state locked, so `wake_process()` finds the thread, if it has been killed after this
+        if killable && current.process().is_killed() {
+            current.set_state(ThreadState::Running);
+            return;
+        }
+        if !current.compare_and_set(ThreadState::Parking, ThreadState::Blocked) {
+            return;
+        }
+
+        // The list is unlocked right away, because block_switch() does not return
+        match wakeup_time {
+            None => self.blocked_list.lock().push(current),
+            // The thread stays blocked, but is kept in the sleep list, so the timeout is checked
+            Some(wakeup_time) => self.sleep_list.lock().push((current, wakeup_time)),
+        }
+
+        self.block_switch(state);
+    }
+
+    /// Wake up all blocked, sleeping and joining threads of the process `process_id`, after it has been killed.
+    /// They exit, before they return to their callers (see `exit_if_killed()`).
+    pub fn wake_process(&self, process_id: usize) {
+        let of_process = |thread: &Arc<Thread>| thread.process().id() == process_id;
+        let mut woken = Vec::new();
+
+        for core in self.registered_cores() {
+            // Synchronize against `block_parked()` on this core, which moves parking threads into the blocked list
+            let state = self.lock_state(core);
+            if let Some(current) = state.current_thread.as_ref().filter(|thread| of_process(thread)) {
+                current.compare_and_set(ThreadState::Parking, ThreadState::Running);
             }
-        };
 
-        // If we found a blocked thread in the block_list, wake it up
-        if let Some(thread) = blocked_thread {
-//            let mut state = self.get_ready_state();
+            woken.extend(self.blocked_list.lock().extract_if(.., |thread| of_process(thread)));
+            woken.extend(self.sleep_list.lock().extract_if(.., |(thread, _)| of_process(thread)).map(|(thread, _)| thread));
+            for join_list in self.join_map.lock().values_mut() {
+                woken.extend(join_list.extract_if(.., |thread| of_process(thread)));
+            }
+        }
+
+        for thread in woken {
             thread.set_state(ThreadState::Ready);
-            state.ready_queue.push_front(Arc::clone(&thread));
-            return true;
+            self.enqueue(thread);
+        }
+    }
+
+    /// Let the calling thread exit, if its process has been killed.
+    /// Only called, when the thread has been woken up, so it does not hold any locks.
+    fn exit_if_killed(&self) {
+        if self.current_thread().process().is_killed() {
+            self.exit();
         }
+    }
+
+    /// Let the thread (pid, tid), which is blocked with a timeout (see `block_if_allowed_until()`), wake up at the
+    /// next timer tick instead. The sleep list is only tried, so this can be called from interrupt handlers. \
+    /// Returns false, if the thread is not blocked with a timeout or the sleep list is locked right now.
+    pub fn try_wake_early(&self, pid: usize, tid: usize) -> bool {
+        let Some(mut sleep_list) = self.sleep_list.try_lock() else {
+            return false;
+        };
+        match sleep_list.iter_mut().find(|(t, _)| t.id() == tid && t.process().id() == pid && t.state() == ThreadState::Blocked) {
+            Some(entry) => {
+                entry.1 = 0;
+                true
+            }
+            None => false,
+        }
+    }
 
-        // 2a) Check if the thread to be woken up is the current thread (it has not been blocked)
-        if let Some(curr_thread) = &state.current_thread {
-            if curr_thread.id() == tid && curr_thread.process().id() == pid {
-                curr_thread.set_state(ThreadState::Running);
+    /// Unblock thread with given (pid, tid). \
+    /// Returns true if thread was found and unblocked, false otherwise.
+    pub fn unblock(&self, pid: usize, tid: usize) -> bool {
+       // info!("Unblock: Thread with PID={}, TID={}", pid, tid);
+
+        for core in self.registered_cores() {
+            // Synchronize against `thread_switch` on this core, which moves parking threads into the blocked list
+            let state = self.lock_state(core);
+
+            // 1) Check if the given thread is in the blocked list -> need to be woken up
+            let blocked_thread: Option<Arc<Thread>> = {
+                let mut block_list = self.blocked_list.lock();
+                if let Some(pos) = block_list.iter().position(|t| t.id() == tid && t.process().id() == pid) {
+                    Some(block_list.remove(pos))
+                } else {
+                    None
+                }
+            };
+
+            // 1b) Check if the given thread is blocked with a timeout (see `block_if_allowed_until()`),
+            // sleeping threads are in the same list, but must not be woken up early
+            let blocked_thread = blocked_thread.or_else(|| {
+                let mut sleep_list = self.sleep_list.lock();
+                sleep_list.iter()
+                    .position(|(t, _)| t.id() == tid && t.process().id() == pid && t.state() == ThreadState::Blocked)
+                    .map(|pos| sleep_list.remove(pos).0)
+            });
+
+            // If we found a blocked thread in the block_list or sleep_list, wake it up
+            if let Some(thread) = blocked_thread {
+                drop(state);
+                thread.set_state(ThreadState::Ready);
+                self.enqueue(thread);
                 return true;
             }
 
-        // 2b) Check if the thread to be woken up is in the ready queue
-        if let Some(thread) = state.ready_queue.iter().find(|t| t.id() == tid && t.process().id() == pid) {
-                curr_thread.set_state(ThreadState::Ready);
+            // 2a) Check if the thread to be woken up is the current thread of the core (it has not been blocked)
+            if let Some(curr_thread) = &state.current_thread {
+                if curr_thread.id() == tid && curr_thread.process().id() == pid {
+                    curr_thread.set_state(ThreadState::Running);
+                    return true;
+                }
+            }
+
+            // 2b) Check if the thread to be woken up is in the ready queue
+            if state.ready_queue.iter().any(|t| t.id() == tid && t.process().id() == pid) {
                 return true;
             }
         }
@@ -300,39 +940,63 @@ impl Scheduler {
         false
     }
 
-    /// Switch from current to next thread (from ready queue). \
+    /// Switch from current to next thread (from the ready queue of the calling core). \
     /// If `interrupt` is true, the function is called from an ISR and will send EOI to APIC otherwise not.
     fn switch_thread(&self, interrupt: bool) {
-        if let Some(mut state) = self.ready_state.try_lock() {
-            if !state.initialized {
+        if !self.is_initialized() {
+            return;
+        }
+
+        let core = self.local();
+        if let Some(mut state) = core.state.try_lock() {
+            // The scheduler has not been started on this core
+            let Some(current) = state.current_thread.clone() else {
                 return;
-            }
+            };
 
             if let Some(mut sleep_list) = self.sleep_list.try_lock() {
-                Scheduler::check_sleep_list(&mut state, &mut sleep_list);
+                self.check_sleep_list(core, &mut state, &mut sleep_list);
             }
-
-            // Get clone of the current thread
-            let current = Scheduler::current(&state);
+            self.take_migrating(core, &mut state);
 
             // Current thread is initializing itself and may not be interrupted
             if current.stacks_locked() || tss().is_locked() {
                 return;
             }
 
-            // Try to get the next thread from the ready queue
-            let next = match state.ready_queue.pop_back() {
+            if state.ready_queue.is_empty() || (interrupt && core.ticks.load(Relaxed) % BALANCE_TICKS == 0) {
+                self.balance(core, &mut state);
+            }
+
+            // Try to get the next thread from the ready queue.
+            // A running thread is only preempted by more important threads (see `RunQueue::pop()`),
+            // but if it gives up the CPU voluntarily, any thread may run (it might wait for a lock held by that one).
+            let is_idle = state.is_idle(&current);
+            let preempted = match current.state() {
+                ThreadState::Running if interrupt && !is_idle => Some(current.as_ref()),
+                _ => None,
+            };
+            let next = match self.next_thread(core, &mut state, preempted) {
                 Some(thread) => thread,
+                // an offline core gives its current thread to the other cores (see `push_local()`)
+                None if !is_idle && !core.online.load(Acquire) => {
+                    Arc::clone(state.idle_thread.as_ref().expect("Scheduler: No idle thread!"))
+                }
                 None => return,
             };
 
             let current_ptr = ptr::from_ref(current.as_ref());
             let next_ptr = ptr::from_ref(next.as_ref());
 
+            // only a switch from the timer interrupt preempts the current thread, otherwise it gives up the CPU itself
+            if !is_idle {
+                current.add_context_switch(preempted.is_none());
+            }
             next.set_state(ThreadState::Running);
+            next.set_core(core.apic_id(), core.switches.load(Relaxed) + 1);
             state.current_thread = Some(next);
-            
-            
+
+
             if current.state() == ThreadState::Parking {
                 current.set_state(ThreadState::Blocked);
                 let mut block_list = self.blocked_list.lock();
@@ -340,9 +1004,12 @@ impl Scheduler {
             }
             else {
                current.set_state(ThreadState::Ready);
-               state.ready_queue.push_front(current);
+               // the idle thread is never queued, it is only switched to by 'block_switch'
+               if !is_idle {
+                   self.push_local(core, &mut state, current);
+               }
             }
- 
+
 
             if interrupt {
                 apic().end_of_interrupt();
@@ -355,6 +1022,21 @@ impl Scheduler {
         }
     }
 
+    /// Let the calling core (in its idle thread) sleep until the next timer tick or reschedule IPI. \
+    /// The ready queue is checked with interrupts disabled, so a thread becoming ready after the last switch
+    /// either is seen here or its IPI is pending and ends the wait right away.
+    fn idle_wait(&self) {
+        let core = self.local();
+        interrupts::disable();
+        // a locked queue is probably being filled by another core
+        let has_work = core.state.try_lock().is_none_or(|state| !state.ready_queue.is_empty());
+        if has_work {
+            interrupts::enable();
+        } else {
+            idle::wait(&core.wakeup);
+        }
+    }
+
     /// Helper function for switching a thread not caused by an interrupt
     pub fn switch_thread_no_interrupt(&self) {
         self.switch_thread(false);
@@ -365,10 +1047,61 @@ impl Scheduler {
         self.switch_thread(true);
     }
 
+    /// Called by the local APIC timer of each core. \
+    /// Cores, that don't run threads, return right away.
+    pub fn tick(&self) {
+        self.local().ticks.fetch_add(1, Relaxed);
+        self.switch_thread_from_interrupt();
+    }
+
+    /// Charge a timer tick of `ns` nanoseconds on the calling core to its current thread (and its process)
+    /// or to the idle time of the core. `user` tells, if the tick has interrupted user mode. \
+    /// CPU time is sampled: The whole tick is charged to the interrupted thread.
+    /// Ticks, that interrupt the scheduler on this core, are not counted.
+    pub fn account_tick(&self, user: bool, ns: u64) {
+        if !self.is_initialized() {
+            return;
+        }
+
+        let core = self.local();
+        let Some(state) = core.state.try_lock() else {
+            return;
+        };
+        let Some(current) = state.current_thread.as_ref() else {
+            return;
+        };
+
+        if state.is_idle(current) {
+            core.idle_ns.fetch_add(ns, Relaxed);
+        } else {
+            current.add_cpu_time(user, ns);
+            let counter = if user { &core.user_ns } else { &core.system_ns };
+            counter.fetch_add(ns, Relaxed);
+        }
+    }
+
+    /// Scheduling statistics of all cores running threads (e.g. for `/proc/schedstat`)
+    pub fn core_statistics(&self) -> Vec<CoreStatistics> {
+        self.online_cores().map(|core| CoreStatistics {
+            apic_id: core.apic_id(),
+            switches: core.switches.load(Relaxed),
+            ready: self.lock_state(core).ready_queue.len(),
+            idle_ns: core.idle_ns.load(Relaxed),
+            user_ns: core.user_ns.load(Relaxed),
+            system_ns: core.system_ns.load(Relaxed),
+        }).collect()
+    }
+
     /// Calling thread will block until thread with `thread_id` has terminated
     pub fn join(&self, thread_id: usize)  -> Result<usize, Errno> {
-        let mut state = self.get_ready_state();
+        let state = self.get_ready_state();
         let thread = Scheduler::current(&state);
+        // Checked with the state locked, so `wake_process()` finds the thread, if it has been killed after this
+        if thread.process().is_killed() {
+            drop(thread);
+            drop(state);
+            self.exit();
+        }
 
         {
             // Execute in own block, so that the lock is released automatically (block() does not return)
@@ -382,6 +1115,7 @@ impl Scheduler {
         }
 
         self.block_switch(state);
+        self.exit_if_killed();
         Ok(0)
     }
 
@@ -403,14 +1137,15 @@ impl Scheduler {
          //   info!("Scheduler: searching join-list");
             let join_list = join_map.get_mut(&current.id()).expect("Missing join_map entry!");
 
+            let core = self.local();
             for thread in join_list {
-                ready_state.ready_queue.push_front(Arc::clone(thread));
+                self.push_local(core, &mut ready_state, Arc::clone(thread));
             }
 
             join_map.remove(&current.id());
         }
-       
-        
+
+
         info!("kheap: free bytes    {}", memory::heap::get_free_bytes());
         info!("frames: free frames #{}", memory::get_free_frames());
 
@@ -420,6 +1155,7 @@ impl Scheduler {
     }
 
     /// Kill the thread with the id `thread_id`.
+    /// A thread blocked in a socket operation is not removed immediately, but exits when it wakes up next.
     pub fn kill(&self, thread_id: usize) {
         {
             // Check if current thread tries to kill itself (illegal)
@@ -431,49 +1167,67 @@ impl Scheduler {
             }
         }
 
+        // A thread waiting for a socket operation is woken up by the network stack and exits by itself
+        if network::pending::cancel(thread_id, CancelReason::Killed) {
+            return;
+        }
+
         let state = self.get_ready_state_and_join_map();
         let mut ready_state = state.0;
         let mut join_map = state.1;
 
         let join_list = join_map.get_mut(&thread_id).expect("Missing join map entry!");
 
+        let core = self.local();
         for thread in join_list {
-            ready_state.ready_queue.push_front(Arc::clone(thread));
+            self.push_local(core, &mut ready_state, Arc::clone(thread));
         }
 
         join_map.remove(&thread_id);
-        ready_state.ready_queue.retain(|thread| thread.id() != thread_id);
+        drop(join_map);
+        drop(ready_state);
+
+        // The thread may be ready on any core
+        for core in self.registered_cores() {
+            self.lock_state(core).ready_queue.retain(|thread| thread.id() != thread_id);
+        }
     }
 
     /// Switch to next thread, called from 'exit', 'sleep', and 'block'
     /// the lock to the ReadyState must be held when calling this function,
     /// since it will be dropped in 'switch' and the scheduler needs to be able to switch to another thread in the meantime
-    /// Will panic if there is no thread to switch to
+    /// If there is no other ready thread, the idle thread of the core runs.
     fn block_switch(&self, mut state: MutexGuard<'_, ReadyState>) {
-        let mut next_thread = state.ready_queue.pop_back();
+        let core = self.local();
+        let mut next_thread = self.next_thread(core, &mut state, None);
 
-        {
+        if next_thread.is_none() {
             // Execute in own block, so that the lock is released automatically (block() does not return)
             let mut sleep_list = self.sleep_list.lock();
-            while next_thread.is_none() {
-                Scheduler::check_sleep_list(&mut state, &mut sleep_list);
-                next_thread = state.ready_queue.pop_back();
-            }
+            self.check_sleep_list(core, &mut state, &mut sleep_list);
+            drop(sleep_list);
+            self.take_migrating(core, &mut state);
+
+            self.balance(core, &mut state);
+            next_thread = self.next_thread(core, &mut state, None);
         }
 
         let current = Scheduler::current(&state);
-
-        // Panic if no threads to switch to (should not happen, since we should always have an idle thread)
-        let next = next_thread.unwrap();
+        let next = next_thread.unwrap_or_else(|| Arc::clone(state.idle_thread.as_ref().expect("Scheduler: No idle thread!")));
 
         // Thread has enqueued itself into sleep list and waited so long, that it dequeued itself in the meantime
         if current.id() == next.id() {
-            panic!("Scheduler: No threads to switch to!");
+            current.set_state(ThreadState::Running);
+            return;
         }
 
         let current_ptr = ptr::from_ref(current.as_ref());
         let next_ptr = ptr::from_ref(next.as_ref());
 
+        // the current thread blocks, sleeps or exits
+        current.add_context_switch(true);
+        next.set_state(ThreadState::Running);
+        next.set_core(core.apic_id(), core.switches.load(Relaxed) + 1);
         state.current_thread = Some(next);
         drop(current); // Decrease Rc manually, because Thread::switch does not return
 
@@ -483,19 +1237,118 @@ impl Scheduler {
         }
     }
 
+    /// Take the next thread from the ready queue of `core` (see `RunQueue::pop()`),
+    /// skipping threads, that are still being switched out on another core.
+    /// The time the thread has waited in the queue is counted as its run delay.
+    fn next_thread(&self, core: &CoreQueue, state: &mut ReadyState, preempted: Option<&Thread>) -> Option<Arc<Thread>> {
+        let now = Scheduler::update_vruntime(state);
+        let (thread, since) = state.ready_queue.pop(preempted, |thread| self.is_switched_out(thread, core) && thread.may_run_on(core.apic_id()))?;
+        thread.add_run_delay(now.saturating_sub(since) as u64);
+        Some(thread)
+    }
+
+    /// Charge the time since the last call to the virtual runtime of the current thread of `state`, if it is fair. \
+    /// This is done before each scheduling decision, so the time until then belongs to the thread running before.
+    /// Returns the current time (in ns).
+    fn update_vruntime(state: &mut ReadyState) -> usize {
+        let now = timer().systime_ns();
+        let elapsed = now.saturating_sub(state.charged_until) as u64;
+        state.charged_until = now;
+
+        if let Some(current) = state.current_thread.as_ref()
+            && !state.is_idle(current)
+            && current.scheduling_class() == SchedulingClass::Fair
+        {
+            let weight = FAIR_WEIGHTS[usize::from(current.priority())];
+            current.set_vruntime(current.vruntime() + elapsed * NORMAL_WEIGHT / weight);
+        }
+        now
+    }
+
+    /// Check if `thread` may run on `core`: A thread, that has run on another core, can be inserted into a queue
+    /// (e.g. after being woken up) before that core has saved its registers in `Thread::switch()`.
+    /// This is done, once the other core has completed another switch after the one to `thread`.
+    fn is_switched_out(&self, thread: &Thread, core: &CoreQueue) -> bool {
+        match thread.core() {
+            None => true,
+            Some(apic_id) if apic_id == core.apic_id() => true,
+            Some(apic_id) => self.core(apic_id).is_none_or(|other| other.switches.load(Acquire) > thread.switch_seq()),
+        }
+    }
+
+    /// Take over ready threads from the busiest other core, until both queues have about the same length. \
+    /// The queue of `core` is locked already, so the other ones are only tried (two cores balancing at the same time
+    /// would deadlock otherwise). Busy queues are skipped, they are balanced at a later tick.
+    fn balance(&self, core: &CoreQueue, state: &mut ReadyState) {
+        if !core.online.load(Acquire) {
+            return;
+        }
+
+        let own_load = state.ready_queue.len();
+        let mut busiest: Option<MutexGuard<'_, ReadyState>> = None;
+
+        for other in self.online_cores().filter(|other| !ptr::eq(*other, core)) {
+            let Some(other_state) = other.state.try_lock() else {
+                continue;
+            };
+            let busiest_load = busiest.as_ref().map_or(own_load + 1, |busiest| busiest.ready_queue.len());
+            if other_state.ready_queue.len() > busiest_load {
+                busiest = Some(other_state);
+            }
+        }
+
+        if let Some(mut busiest) = busiest {
+            for _ in 0..(busiest.ready_queue.len() - own_load) / 2 {
+                match busiest.ready_queue.pop(None, |thread| self.is_switched_out(thread, core) && thread.may_run_on(core.apic_id())) {
+                    Some((thread, since)) => state.ready_queue.push_migrated(thread, since, busiest.ready_queue.min_vruntime),
+                    None => break,
+                }
+            }
+        }
+    }
+
     /// Return current running thread
     fn current(state: &ReadyState) -> Arc<Thread> {
         Arc::clone(state.current_thread.as_ref().expect("Trying to access current thread before initialization!"))
     }
 
+    /// Insert a ready thread into the queue of `core` (locked as `state`) or, if it may not run there or `core`
+    /// is offline, into the list of migrating threads, from which an allowed core takes it (see `take_migrating()`).
+    fn push_local(&self, core: &CoreQueue, state: &mut ReadyState, thread: Arc<Thread>) {
+        if core.online.load(Acquire) && thread.may_run_on(core.apic_id()) {
+            state.ready_queue.push(thread);
+        } else {
+            self.migrating.lock().push(thread);
+        }
+    }
+
+    /// Move the migrating threads, that may run on `core`, into its queue (locked as `state`).
+    fn take_migrating(&self, core: &CoreQueue, state: &mut ReadyState) {
+        if !core.online.load(Acquire) {
+            return;
+        }
+        let Some(mut migrating) = self.migrating.try_lock() else {
+            return;
+        };
+
+        migrating.retain(|thread| {
+            if thread.may_run_on(core.apic_id()) {
+                state.ready_queue.push(Arc::clone(thread));
+                false
+            } else {
+                true
+            }
+        });
+    }
+
     /// Check sleep list for threads that need to be waken up
-    fn check_sleep_list(state: &mut ReadyState, sleep_list: &mut Vec<(Arc<Thread>, usize)>) {
+    fn check_sleep_list(&self, core: &CoreQueue, state: &mut ReadyState, sleep_list: &mut Vec<(Arc<Thread>, usize)>) {
         let time = timer().systime_ms();
 
         sleep_list.retain(|entry| {
             if time >= entry.1 {
                 entry.0.set_state(ThreadState::Ready);
-                state.ready_queue.push_front(Arc::clone(&entry.0));
+                self.push_local(core, state, Arc::clone(&entry.0));
                 false
             } else {
                 true
@@ -503,33 +1356,95 @@ impl Scheduler {
         });
     }
 
-    /// Helper function returning `ReadyState` of scheduler in a MutexGuard
-    fn get_ready_state(&self) -> MutexGuard<'_, ReadyState> {
-        let state;
+    /// Scheduling state of the calling core (an entry in `self.cores` is assigned on first use)
+    fn local(&self) -> &CoreQueue {
+        let own_id = apic().local_apic_id();
+        for core in self.cores.iter() {
+            // Entries are assigned in order and never released, so the first free entry is ours
+            match core.apic_id.compare_exchange(NO_CORE, own_id, AcqRel, Acquire) {
+                Ok(_) => return core,
+                Err(apic_id) if apic_id == own_id => return core,
+                Err(_) => {}
+            }
+        }
+
+        panic!("Scheduler: Too many cores, core [{}] has no ready queue!", own_id);
+    }
+
+    /// Scheduling state of the core with the local APIC id `apic_id`
+    fn core(&self, apic_id: u32) -> Option<&CoreQueue> {
+        self.registered_cores().find(|core| core.apic_id() == apic_id)
+    }
+
+    fn registered_cores(&self) -> impl Iterator<Item = &CoreQueue> {
+        self.cores.iter().take_while(|core| core.apic_id.load(Acquire) != NO_CORE)
+    }
+
+    fn online_cores(&self) -> impl Iterator<Item = &CoreQueue> {
+        self.registered_cores().filter(|core| core.online.load(Acquire))
+    }
+
+    /// Lock the ready queue of the core, that should run `thread`: The core running threads with the shortest queue,
+    /// preferring the core, that has run `thread` before (its caches may still be warm). Cores, whose queue is locked
+    /// right now, are skipped. Before the scheduler has been started, this is the calling core.
+    fn lock_target(&self, thread: &Thread) -> (&CoreQueue, MutexGuard<'_, ReadyState>) {
+        if self.online_cores().next().is_none() {
+            let core = self.local();
+            return (core, self.lock_state(core));
+        }
+
+        // only cores allowed by the affinity of the thread (if there are any)
+        let any_allowed = self.online_cores().any(|core| thread.may_run_on(core.apic_id()));
+        loop {
+            let mut target: Option<(&CoreQueue, MutexGuard<'_, ReadyState>)> = None;
+            for core in self.online_cores().filter(|core| !any_allowed || thread.may_run_on(core.apic_id())) {
+                let Some(state) = core.state.try_lock() else {
+                    continue;
+                };
+                let better = match &target {
+                    None => true,
+                    Some((_, target_state)) => state.ready_queue.len() < target_state.ready_queue.len()
+                        || (state.ready_queue.len() == target_state.ready_queue.len() && thread.core() == Some(core.apic_id())),
+                };
+                if better {
+                    target = Some((core, state));
+                }
+            }
 
+            // see 'lock_state()'
+            if let Some(target) = target.filter(|_| !allocator().is_locked()) {
+                return target;
+            }
+            spin_loop();
+        }
+    }
+
+    /// Lock the ready queue of `core`
+    fn lock_state<'a>(&self, core: &'a CoreQueue) -> MutexGuard<'a, ReadyState> {
         // We need to make sure, that both the kernel memory manager and the ready queue are currently not locked.
         // Otherwise, a deadlock may occur: Since we are holding the ready queue lock,
         // the scheduler won't switch threads anymore, and none of the locks will ever be released
         loop {
-            let state_tmp = self.ready_state.lock();
-            if allocator().is_locked() {
-                continue;
+            let state = core.state.lock();
+            if !allocator().is_locked() {
+                return state;
             }
-
-            state = state_tmp;
-            break;
         }
+    }
 
-        state
+    /// Helper function returning `ReadyState` of the calling core in a MutexGuard
+    fn get_ready_state(&self) -> MutexGuard<'_, ReadyState> {
+        self.lock_state(self.local())
     }
 
-    /// Helper function returning `ReadyState` and `Map` of scheduler, each in a MutexGuard
+    /// Helper function returning `ReadyState` of the calling core and `Map` of scheduler, each in a MutexGuard
     fn get_ready_state_and_join_map(&self) -> (MutexGuard<'_, ReadyState>, MutexGuard<'_, Map<usize, Vec<Arc<Thread>>>>) {
         loop {
             let ready_state = self.get_ready_state();
             if let Some(join_map) = self.join_map.try_lock() {
                 return (ready_state, join_map);
             } else {
+                drop(ready_state);
                 self.switch_thread_no_interrupt();
             }
         }
@@ -537,16 +1452,34 @@ impl Scheduler {
 
     /// For ps command - get all processes & threads
     pub fn get_status(&self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        let out = self.status();
+
+        // Copy to caller buffer (truncate if needed)
+        let bytes = out.as_bytes();
+        let len = core::cmp::min(bytes.len(), buffer.len());
+        buffer[..len].copy_from_slice(&bytes[..len]);
+        Ok(len)
+    }
+
+    /// One line (PID, TID, state) for each thread, running ones first
+    pub fn status(&self) -> String {
         let mut out = String::new();
 
-        // Current
+        // Current (of the calling core)
         let cur = self.current_thread();
         let _ = writeln!(out, "PID: {}, TID: {}, State: {:?}", cur.process().id(), cur.id(), ThreadState::Running);
 
-        // Ready Queue
-        let state = self.get_ready_state();
-        for thread in state.ready_queue.iter() {
-            let _ = writeln!(out, "PID: {}, TID: {}, State: {:?}", thread.process().id(), thread.id(), thread.state());
+        // Current of the other cores and all ready queues
+        for core in self.registered_cores() {
+            let state = self.lock_state(core);
+            if let Some(current) = state.current_thread.as_ref() {
+                if current.id() != cur.id() && !state.is_idle(current) {
+                    let _ = writeln!(out, "PID: {}, TID: {}, State: {:?}", current.process().id(), current.id(), ThreadState::Running);
+                }
+            }
+            for thread in state.ready_queue.iter() {
+                let _ = writeln!(out, "PID: {}, TID: {}, State: {:?}", thread.process().id(), thread.id(), thread.state());
+            }
         }
 
         // Sleep List
@@ -565,11 +1498,7 @@ impl Scheduler {
         }
         drop(block_list);
 
-        // Copy to caller buffer (truncate if needed)
-        let bytes = out.as_bytes();
-        let len = core::cmp::min(bytes.len(), buffer.len());
-        buffer[..len].copy_from_slice(&bytes[..len]);
-        Ok(len)
+        out
     }
 
     /// Voluntarily yield the CPU to another runnable thread.
@@ -578,12 +1507,13 @@ impl Scheduler {
     /// - Must be called when it is safe to switch (no stack locks, tss not locked).
     /// - Does not change Parking/Blocked semantics; caller should set state beforehand if needed.
     pub fn yield_now(&self) {
-        let mut state = self.get_ready_state();
-
-        if !state.initialized {
+        if !self.is_initialized() {
             return;
         }
 
+        let core = self.local();
+        let mut state = self.lock_state(core);
+
         // Current thread (Arc clone of state.current_thread)
         let current = Scheduler::current(&state);
 
@@ -592,40 +1522,28 @@ impl Scheduler {
             return;
         }
 
+        // Pick next before requeueing the current thread, so it yields even to threads of a lower priority.
         // If there is nobody else runnable, don't bother.
         // (Note: ready_queue does NOT include the current thread yet.)
-        if state.ready_queue.is_empty() {
-            return;
-        }
-
-        // Requeue current as Ready
-        current.set_state(ThreadState::Ready);
-        state.ready_queue.push_front(Arc::clone(&current));
-
-        // Pick next
-        let next = match state.ready_queue.pop_back() {
+        let next = match self.next_thread(core, &mut state, None) {
             Some(t) => t,
-            None => {
-                // Shouldn't happen because we checked !empty, but be safe
-                current.set_state(ThreadState::Running);
-                return;
-            }
+            None => return,
         };
 
-        // If we ended up picking ourselves (possible if only ourselves was in queue),
-        // restore running state and return.
-        if next.id() == current.id() {
-            next.set_state(ThreadState::Running);
-            state.current_thread = Some(next);
-            return;
+        // Requeue current as Ready (the idle thread is never queued)
+        current.set_state(ThreadState::Ready);
+        if !state.is_idle(&current) {
+            self.push_local(core, &mut state, Arc::clone(&current));
         }
 
-        // Switch to next
+        // Switch to next (the current thread gives up the CPU itself, so this is a voluntary switch)
+        current.add_context_switch(true);
         next.set_state(ThreadState::Running);
+        next.set_core(core.apic_id(), core.switches.load(Relaxed) + 1);
         state.current_thread = Some(Arc::clone(&next));
 
-        let current_ptr = core::ptr::from_ref(current.as_ref());
-        let next_ptr = core::ptr::from_ref(next.as_ref());
+        let current_ptr = ptr::from_ref(current.as_ref());
+        let next_ptr = ptr::from_ref(next.as_ref());
 
         // ready_state is unlocked in your asm trampoline via unlock_scheduler()
         // (Thread::switch ultimately calls unlock_scheduler after switch)
@@ -0,0 +1,73 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: signal                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Signals for processes. Sending a signal only marks it as        ║
+   ║         pending in the target process and interrupts its blocking       ║
+   ║         socket operations and futex waits. The signal is delivered,     ║
+   ║         when a thread of the process returns from a system call or is   ║
+   ║         interrupted by the timer while running in user mode. There are  ║
+   ║         no signal handlers yet, so the process is always terminated.    ║
+   ║         `Signal::Kill` does not wait for the delivery: The process is   ║
+   ║         killed right away, which also wakes up its blocked threads.     ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - send                 mark a signal as pending in a process          ║
+   ║   - deliver              deliver pending signals of the current process ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use log::info;
+use syscall::return_vals::Errno;
+use syscall::signal::Signal;
+use crate::network::pending::{self, CancelReason};
+use crate::process::process::Process;
+use crate::sync::{event, futex};
+use crate::{process_manager, scheduler};
+
+/// Send `signal` to the process `target`.
+/// The kernel process can't receive signals.
+pub fn send(target: &Process, signal: Signal) -> Result<(), Errno> {
+    if process_manager().read().kernel_process().is_some_and(|kernel| kernel.id() == target.id()) {
+        return Err(Errno::EINVAL);
+    }
+
+    // A killed process is terminated right away, its threads are woken up and exit, even if they are blocked
+    if signal == Signal::Kill && target.id() != process_manager().read().current_process().id() {
+        info!("Process [{}] killed", target.id());
+        process_manager().write().kill(target.id());
+        return Ok(());
+    }
+
+    target.raise_signal(signal);
+    // threads waiting for a socket, a futex or an event counter return with EINTR and get the signal delivered on their way back
+    pending::cancel_for_process(target.id(), CancelReason::Cancelled);
+    futex::wake_process(target.id());
+    event::wake_process(target.id());
+    Ok(())
+}
+
+/// Deliver the pending signals of the current process (if any).
+/// Called right before returning to user mode (from the system call handler and the timer interrupt),
+/// so the thread holds no locks. Does not return, if the process is terminated.
+pub extern "sysv64" fn deliver() {
+    let Some(process) = scheduler().try_get_current_thread().map(|thread| thread.process()) else {
+        return;
+    };
+    if process.is_killed() {
+        // the process has already been terminated by another thread
+        drop(process);
+        scheduler().exit();
+    }
+    if !process.has_pending_signals() {
+        return;
+    }
+
+    if let Some(signal) = process.take_signal() {
+        info!("Process [{}] terminated by signal {:?}", process.id(), signal);
+        process.exit();
+        // exit() does not return, so nothing may keep the process alive
+        drop(process);
+        scheduler().exit();
+    }
+}
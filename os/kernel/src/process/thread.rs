@@ -6,8 +6,13 @@
    ║                                                                         ║
    ║ Public functions:                                                       ║
    ║  - new_kernel_thread  create a new kernel-only thread                   ║
+   ║  - new_idle_thread    create the idle thread of a core                  ║
    ║  - load_application   load application, create process, and main thread ║
    ║  - new_user_thread    create and additional user thread in a process    ║
+   ║  - new_user_thread_with_stack  create an additional user thread with an ║
+   ║                       argument and a custom stack size                  ║
+   ║  - free_user_stack    free the user stack of an exiting thread          ║
+   ║  - free_tls           free the TLS block of an exiting thread           ║
    ║  - start_first        start a thread, called once by scheduler          ║
    ║  - switch             switch threads, called by scheduler               ║
    ║  - stacks_locked      check if stacks are locked, called by scheduler   ║
@@ -20,16 +25,47 @@
    ║  - state              get current state of the thread                   ║
    ║  - set_state          set current state of the thread                   ║
    ║  - compare_and_set    atomic state transition                           ║
+   ║  - priority           get the scheduling priority of the thread         ║
+   ║  - base_priority      get the priority without inherited ones           ║
+   ║  - set_priority       set the priority (before the thread is ready)     ║
+   ║  - inherited_priority get the priority inherited via a 'PiMutex'        ║
+   ║  - set_inherited_priority  set it (before the thread is ready)          ║
+   ║  - scheduling_class   get the class (fair or strict) of the thread      ║
+   ║  - base_scheduling_class  get the class without inherited priorities    ║
+   ║  - set_scheduling_class   set it (before the thread is ready)           ║
+   ║  - vruntime           get the virtual runtime, called by scheduler      ║
+   ║  - set_vruntime       set the virtual runtime, called by scheduler      ║
+   ║  - add_pi_lock        count an acquired 'PiMutex'                       ║
+   ║  - remove_pi_lock     count a released 'PiMutex'                        ║
+   ║  - core               get the core, that runs or has run the thread     ║
+   ║  - set_core           set the core, called by scheduler                 ║
+   ║  - affinity           get the cores, on which the thread may run        ║
+   ║  - set_affinity       set the cores (before the thread is ready)        ║
+   ║  - may_run_on         check if the thread may run on a core             ║
+   ║  - switch_seq         get the switch number of the core, called by      ║
+   ║                       scheduler                                         ║
+   ║  - usage              get CPU time and scheduling statistics            ║
+   ║  - add_cpu_time       charge a timer tick to the thread and its process ║
+   ║  - add_context_switch count a switch away from the thread               ║
+   ║  - add_run_delay      count a dispatch after waiting in a ready queue   ║
    ║                                                                         ║
    ║ Thread stack:                                                           ║
    ║  Kernel threads have a stack of 'KERNEL_STACK_PAGES'. User threads have ║
-   ║  an additional stack with a logical size of 'MAX_USER_STACK_SIZE' and   ║
+   ║  an additional stack with a logical size of 'MAX_USER_STACK_SIZE' (or   ║
+   ║  the size passed to 'new_user_thread_with_stack') and                   ║
    ║  an initial phyiscal size of one page. Additional pages are allocated   ║
    ║  for user stacks as need until 'MAX_USER_STACK_SIZE' is reached.        ║
    ║  A thread is killed if this limit is exceeded. The stack of a user      ║
    ║  thread stack within one processes is logically allocated at            ║
    ║  'MAIN_USER_STACK_START'. The next stack for the next user stack is     ║
    ║  allocated at 'MAIN_USER_STACK_START' + 'MAX_USER_STACK_SIZE' and so on.║
+   ║  The user stack is freed, when the thread exits ('free_user_stack').    ║
+   ║                                                                         ║
+   ║ Thread-local storage:                                                   ║
+   ║  Each user thread gets a TLS block with a copy of the 'PT_TLS' segment  ║
+   ║  of the program, followed by a thread control block ('TCB_SIZE'). The   ║
+   ║  FS base points to the thread control block and is saved and restored   ║
+   ║  on each thread switch. The block is freed by 'free_tls'.               ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Fabian Ruhland & Michael Schoettner, 04.01.2026, HHU            ║
    ╚═════════════════════════════════════════════════════════════════════════╝
@@ -38,12 +74,14 @@
 use crate::consts::MAIN_USER_STACK_START;
 use crate::consts::MAX_USER_STACK_SIZE;
 use crate::consts::USER_SPACE_ENV_START;
-use crate::initrd;
-use crate::memory::PAGE_SIZE;
+use crate::naming;
+use crate::network;
+use crate::memory::{MemorySpace, PAGE_SIZE};
 use crate::memory::stack;
 use crate::memory::stack::StackAllocator;
 use crate::memory::vma::VmaType;
-use crate::process::process::Process;
+use crate::process::dynamic_loader;
+use crate::process::process::{Process, TlsTemplate, UsageCounters};
 use crate::process::scheduler;
 use crate::syscall::syscall_dispatcher::CORE_LOCAL_STORAGE_TSS_RSP0_PTR_INDEX;
 use crate::{process_manager, scheduler, tss};
@@ -51,18 +89,26 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::arch::naked_asm;
 use core::ptr;
-use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
 use goblin::elf::Elf;
 use goblin::elf64;
 use log::error;
 use log::info;
 use log::warn;
 use spin::Mutex;
+use syscall::cpu::AFFINITY_ALL;
+use syscall::priority::{Priority, SchedulingClass};
+use syscall::return_vals::Errno;
+use syscall::tls::TCB_SIZE;
 use x86_64::PrivilegeLevel::Ring3;
 use x86_64::VirtAddr;
 use x86_64::structures::gdt::SegmentSelector;
+use x86_64::registers::model_specific::FsBase;
 use x86_64::structures::paging::Page;
 
+/// Value of `Thread::inherited_priority`, if the thread has not inherited a priority
+const NO_INHERITED_PRIORITY: usize = usize::MAX;
+
 /// kernel & user stack of a thread
 struct Stacks {
     kernel_stack: Vec<u64, StackAllocator>,
@@ -97,10 +143,31 @@ pub struct Thread {
     process: Arc<Process>, // reference to my process
     /// for user threads: the address to jump to
     user_kickoff: VirtAddr,
-    /// the actual entry point (eg. for user threads the single parameter to kickoff)
+    /// the actual entry point (eg. for user threads the first parameter to kickoff)
     entry: extern "sysv64" fn(),
+    /// for user threads: the second parameter to kickoff (passed on to `entry`)
+    user_arg: usize,
     state: AtomicU8,
     wake_pending: AtomicBool, // false => allowed to block; true => do NOT block (wake pending)
+    priority: AtomicUsize,
+    /// Priority inherited from threads waiting for a `PiMutex` held by this thread (`NO_INHERITED_PRIORITY`, if none)
+    inherited_priority: AtomicUsize,
+    /// Number of `PiMutex`es held by this thread
+    pi_locks: AtomicUsize,
+    /// `SchedulingClass` of the thread
+    class: AtomicUsize,
+    /// for fair threads: CPU time weighted by the priority (in ns, see `Scheduler::update_vruntime()`)
+    vruntime: AtomicU64,
+    /// Local APIC id of the core, that runs or has run the thread last (`NO_CORE` if it hasn't run yet)
+    core: AtomicU32,
+    /// Number of completed thread switches of `core`, once the switch to this thread has been completed
+    switch_seq: AtomicUsize,
+    /// Cores, on which the thread may run (bit n = core with local APIC id n, see `may_run_on()`)
+    affinity: AtomicU64,
+    /// for user threads: start of the TLS block, which is initialized, when the thread starts (see `init_tls()`)
+    tls_block: AtomicU64,
+    /// CPU time and scheduling statistics of this thread (also counted for its process)
+    usage: UsageCounters,
 }
 
 impl Stacks {
@@ -118,11 +185,29 @@ impl Thread {
     /// `entry` is the thread entry function.
     pub fn new_kernel_thread(entry: extern "sysv64" fn(), tag_str: &str) -> Arc<Thread> {
         let process = process_manager().read().current_process();
+        Thread::new_kernel_thread_in(&process, entry, tag_str)
+    }
+
+    /// Create the idle thread of a core, which runs, when there is no other ready thread. \
+    /// It is never inserted into a ready queue, so it is created in the kernel process
+    /// (the scheduler may not run any thread yet, so there is no current process).
+    pub fn new_idle_thread(entry: extern "sysv64" fn()) -> Arc<Thread> {
+        let process = process_manager()
+            .read()
+            .kernel_process()
+            .expect("Trying to create an idle thread before process initialization!");
+        let thread = Thread::new_kernel_thread_in(&process, entry, "idle");
+        thread.set_priority(Priority::Background);
+        thread
+    }
+
+    /// Create a kernel thread, whose kernel stack is allocated in the address space of `process`.
+    fn new_kernel_thread_in(process: &Arc<Process>, entry: extern "sysv64" fn(), tag_str: &str) -> Arc<Thread> {
         let pid = process.id();
         let tid = scheduler::next_thread_id();
 
         // Allocate the kernel stack for the kernel thread
-        let kernel_stack = stack::alloc_kernel_stack(&process, pid, tid, tag_str);
+        let kernel_stack = stack::alloc_kernel_stack(process, pid, tid, tag_str);
 
         // Create empty user stack, so need to add it to the virtual address space
         let user_stack: Vec<u64, StackAllocator> = stack::alloc_user_stack(pid, tid, MAIN_USER_STACK_START, 0);
@@ -137,8 +222,19 @@ impl Thread {
                 .expect("Trying to create a kernel thread before process initialization!"),
             user_kickoff: VirtAddr::zero(),
             entry,
+            user_arg: 0,
             state: AtomicU8::new(ThreadState::Created.as_u8()),
             wake_pending: AtomicBool::new(false),
+            priority: AtomicUsize::new(Priority::Normal.into()),
+            inherited_priority: AtomicUsize::new(NO_INHERITED_PRIORITY),
+            pi_locks: AtomicUsize::new(0),
+            class: AtomicUsize::new(SchedulingClass::Strict.into()),
+            vruntime: AtomicU64::new(0),
+            core: AtomicU32::new(scheduler::NO_CORE),
+            switch_seq: AtomicUsize::new(0),
+            affinity: AtomicU64::new(AFFINITY_ALL),
+            tls_block: AtomicU64::new(0),
+            usage: UsageCounters::default(),
         };
 
         thread.prepare_kernel_stack();
@@ -149,22 +245,35 @@ impl Thread {
     /// `name` is the name of the application, `args` are the arguments passed to the application. \
     /// Returns the main thread of the application which is not yet registered in the scheduler.
     pub fn load_application(path: &str, name: &str, args: &Vec<&str>) -> Result<Arc<Thread>, ProcessLoadError> {
-        let elf_buffer = match initrd().entries().find(|entry| entry.filename().as_str().unwrap() == path) {
-            Some(app) => app.data(),
-            None => return Err(ProcessLoadError::NotFound),
-        };
+        Thread::load_application_with_env(path, name, args, &[])
+    }
+
+    /// Like `load_application()`, but also passes the environment variables `env` ("KEY=value") to the application.
+    pub fn load_application_with_env(path: &str, name: &str, args: &[&str], env: &[&str]) -> Result<Arc<Thread>, ProcessLoadError> {
+        if Thread::env_size(name, args, env) > PAGE_SIZE {
+            return Err(ProcessLoadError::ArgsTooLong);
+        }
+
+        // Programme werden über den Namensdienst geladen (normalerweise aus /bin in der initialen Ramdisk)
+        let elf_buffer = naming::api::read_file(path).map_err(|_| ProcessLoadError::NotFound)?;
 
         let current_process = process_manager().read().current_process();
         let new_process = process_manager().write().create_process();
+        new_process.set_name(name);
+        new_process.set_parent(current_process.id());
+        network::join_namespace(&new_process, current_process.net_namespace());
+        new_process.set_sandbox(current_process.sandbox());
+        new_process.set_cwd(current_process.cwd());
+        new_process.set_priority(current_process.priority());
         let pid = new_process.id();
 
         info!("load_application: pid = {pid}, name = {name}");
 
         // parse elf file headers and map and copy code if successful
-        let entry = Thread::parse_and_map_elf_bin(&current_process, &new_process, elf_buffer, name)?;
+        let entry = Thread::parse_and_map_elf_bin(&current_process, &new_process, &elf_buffer, name)?;
 
         // create environment for the application and copy arguments
-        Thread::copy_args(&new_process, name, args);
+        Thread::copy_args(&new_process, name, args, env);
 
         // create thread
         // this first thread is special in that there is not really a kickoff;
@@ -182,34 +291,62 @@ impl Thread {
     /// with the `entry` function is the parameter. \
     /// This indirection ensures that the thread calls exit when it is done, see `library::concurrent::thread`.
     pub fn new_user_thread(parent: Arc<Process>, kickoff_addr: VirtAddr, entry: extern "sysv64" fn()) -> Arc<Thread> {
+        Thread::new_user_thread_with_stack(parent, kickoff_addr, entry, 0, MAX_USER_STACK_SIZE)
+            .expect("could not create user stack")
+    }
+
+    /// Like `new_user_thread()`, but `arg` is passed as second parameter to the kickoff function
+    /// and the user stack has a logical size of `stack_size` bytes (a multiple of `PAGE_SIZE`, at most `MAX_USER_STACK_SIZE`). \
+    /// Returns `None`, if there is no room for the stack in the address space of `parent`.
+    pub fn new_user_thread_with_stack(parent: Arc<Process>, kickoff_addr: VirtAddr, entry: extern "sysv64" fn(), arg: usize, stack_size: usize) -> Option<Arc<Thread>> {
+        assert!(stack_size % PAGE_SIZE == 0 && stack_size > 0 && stack_size <= MAX_USER_STACK_SIZE);
         let pid = parent.id();
+
+        // Create the TLS block and the user stack for the thread
+        let tls_size = parent.tls_template().map_or(0, |template| template.block_size()) + TCB_SIZE;
+        let tls_vma = parent
+            .virtual_address_space
+            .user_alloc_map_full(None, tls_size.div_ceil(PAGE_SIZE) as u64, VmaType::Tls, "tls")?;
+        let Some(stack_vma) = parent
+            .virtual_address_space
+            .user_alloc_map_partial(None, (stack_size / PAGE_SIZE) as u64, VmaType::UserStack, "usrstack", 1, true) else {
+            parent.virtual_address_space.free_vma(tls_vma);
+            return None;
+        };
+
         let tid = scheduler::next_thread_id(); // get id for new thread
 
-        // Allocate kernel stack for the main thread
+        // Allocate kernel stack for the thread
         let kernel_stack = stack::alloc_kernel_stack(&parent, pid, tid, "userthread");
 
-        // Create user stack for the application
-        let stack_vma = parent
-            .virtual_address_space
-            .user_alloc_map_partial(None, (MAX_USER_STACK_SIZE / PAGE_SIZE) as u64, VmaType::UserStack, "usrstack", 1, true)
-            .expect("could not create user stack");
-
         // Make a Vec for the user stack
-        let user_stack: Vec<u64, StackAllocator> = stack::alloc_user_stack(pid, tid, stack_vma.start().as_u64() as usize, MAX_USER_STACK_SIZE);
+        let user_stack: Vec<u64, StackAllocator> = stack::alloc_user_stack(pid, tid, stack_vma.start().as_u64() as usize, stack_size);
 
         // create user thread and prepare the stack for starting it later
+        let priority = parent.priority();
         let thread = Thread {
             id: tid,
             stacks: Mutex::new(Stacks::new(kernel_stack, user_stack)),
             process: parent,
             user_kickoff: kickoff_addr,
             entry,
+            user_arg: arg,
             state: AtomicU8::new(ThreadState::Created.as_u8()),
             wake_pending: AtomicBool::new(false),
+            priority: AtomicUsize::new(priority.into()),
+            inherited_priority: AtomicUsize::new(NO_INHERITED_PRIORITY),
+            pi_locks: AtomicUsize::new(0),
+            class: AtomicUsize::new(SchedulingClass::Fair.into()),
+            vruntime: AtomicU64::new(0),
+            core: AtomicU32::new(scheduler::NO_CORE),
+            switch_seq: AtomicUsize::new(0),
+            affinity: AtomicU64::new(AFFINITY_ALL),
+            tls_block: AtomicU64::new(tls_vma.start().as_u64()),
+            usage: UsageCounters::default(),
         };
 
         thread.prepare_kernel_stack();
-        Arc::new(thread)
+        Some(Arc::new(thread))
     }
 
     /// Called first for both a new kernel and a new user thread
@@ -276,13 +413,25 @@ impl Thread {
         VirtAddr::new(stacks.user_stack.as_ptr() as u64)
     }
 
+    /// Free the user stack of the calling thread, when it exits (otherwise it is only freed with the address space of the process). \
+    /// Must only be called from kernel mode, right before `scheduler().exit()`.
+    pub fn free_user_stack(&self) {
+        if self.is_kernel_thread() {
+            return;
+        }
+
+        let address_space = &self.process.virtual_address_space;
+        if let Some(vma) = address_space.is_address_within_vma(self.user_stack_start().as_u64(), VmaType::UserStack) {
+            address_space.free_vma(vma);
+        }
+    }
+
     /// Return reference to my process
     pub fn process(&self) -> Arc<Process> {
         Arc::clone(&self.process)
     }
 
     /// Calling thread will wait until 'self' terminates
-    #[allow(dead_code)]
     pub fn join(&self) {
         scheduler().join(self.id());
     }
@@ -338,9 +487,45 @@ impl Thread {
         stacks.old_rsp0 = VirtAddr::new((top_of_stack as usize - 8 * 20) as u64);
     }
 
+    /// Free the TLS block of the calling thread, when it exits (otherwise it is only freed with the address space of the process). \
+    /// Must only be called from kernel mode, right before `scheduler().exit()`.
+    pub fn free_tls(&self) {
+        let tls_block = self.tls_block.swap(0, Ordering::Relaxed);
+        if tls_block == 0 {
+            return;
+        }
+
+        let address_space = &self.process.virtual_address_space;
+        if let Some(vma) = address_space.is_address_within_vma(tls_block, VmaType::Tls) {
+            address_space.free_vma(vma);
+        }
+    }
+
+    /// Copy the initial values of the thread-local variables into the TLS block of the thread
+    /// and point the FS base at the thread control block behind them (x86-64 ELF TLS, variant II). \
+    /// Called in the address space of the thread, before it switches to user mode for the first time.
+    fn init_tls(&self) {
+        let template = self.process.tls_template();
+        let block_size = template.map_or(0, |template| template.block_size());
+        let block = self.tls_block.load(Ordering::Relaxed) as *mut u8;
+
+        unsafe {
+            block.write_bytes(0, block_size + TCB_SIZE);
+            if let Some(template) = template {
+                block.copy_from_nonoverlapping(template.addr as *const u8, template.file_size);
+            }
+
+            // The first word of the thread control block points to itself, so user space can get the thread pointer from FS:0
+            let tcb = block.add(block_size) as *mut u64;
+            tcb.write(tcb as u64);
+            FsBase::write(VirtAddr::from_ptr(tcb));
+        }
+    }
+
     /// Switch a thread to user mode by preparing a fake stackframe
     fn switch_to_user_mode(&self) -> ! {
         let old_rsp0: u64;
+        self.init_tls();
 
         {
             // Separate block to make sure that the lock is released, before calling `thread_user_start()`.
@@ -363,7 +548,7 @@ impl Thread {
         }
 
         unsafe {
-            thread_user_start(old_rsp0, self.entry);
+            thread_user_start(old_rsp0, self.entry, self.user_arg);
         }
     }
 
@@ -393,80 +578,86 @@ impl Thread {
                 // Calc number of pages needed for the .text section = 'p_filesz'
                 let code_page_count = header.p_filesz.div_ceil(PAGE_SIZE.try_into().unwrap());
 
-                // create mapping for 'total_page_count'
+                // create mapping for the pages with content from the file
                 let virt_start = Page::from_start_address(VirtAddr::new(header.p_vaddr)).map_err(|e| {
                     error!("ELF: Program section not page aligned: {e:?}");
                     ProcessLoadError::ElfInvalid
                 })?;
-                let vma = new_process
-                    .virtual_address_space
-                    .user_alloc_map_full(Some(virt_start), total_page_count as u64, VmaType::Code, name)
-                    .expect("user_alloc_map_full failed");
-
-                // copy code from the ELF file to the allocated frames
-                // as the target address space is not loaded we need to copy page by page by retrieving physical addresses manually from page tables of the target process
-                unsafe {
-                    let src_ptr = elf_buffer.as_ptr().offset(header.p_offset as isize);
-                    current_process.virtual_address_space.copy_to_addr_space(
-                        src_ptr,
-                        &new_process.virtual_address_space,
-                        &vma,
-                        header.p_filesz,
-                        true,
-                    );
+                if code_page_count > 0 {
+                    let vma = new_process
+                        .virtual_address_space
+                        .user_alloc_map_full(Some(virt_start), code_page_count, VmaType::Code, name)
+                        .expect("user_alloc_map_full failed");
+
+                    // copy code from the ELF file to the allocated frames (the rest of the last page is zeroed)
+                    // as the target address space is not loaded we need to copy page by page by retrieving physical addresses manually from page tables of the target process
+                    unsafe {
+                        let src_ptr = elf_buffer.as_ptr().offset(header.p_offset as isize);
+                        current_process.virtual_address_space.copy_to_addr_space(
+                            src_ptr,
+                            &new_process.virtual_address_space,
+                            &vma,
+                            header.p_filesz,
+                            true,
+                        );
+                    }
                 }
 
-                // Zero remaining pages for .bss
+                // The remaining pages of .bss are zeroed and mapped on their first access
                 if total_page_count > code_page_count {
-                    let bss_page_count = total_page_count - code_page_count;
-                    let dest_page_start = vma.range.start.start_address().as_u64();
-                    let mut dest_offset: u64 = code_page_count as u64 * PAGE_SIZE as u64;
-
-                    // copy remaining pages
-                    for _i in 0..bss_page_count {
-                        // get destination physical address
-                        let dest_phys_addr = new_process
-                            .virtual_address_space
-                            .get_phys(dest_page_start + dest_offset)
-                            .expect("get_phys failed");
-                        let dest = dest_phys_addr.as_u64() as *mut u8;
-
-                        // zero rest of the page
-                        unsafe {
-                            dest.write_bytes(0, PAGE_SIZE);
-                        }
-                        dest_offset += PAGE_SIZE as u64;
-                    }
+                    new_process
+                        .virtual_address_space
+                        .alloc_vma(Some(virt_start + code_page_count), total_page_count - code_page_count, MemorySpace::User, VmaType::Anonymous, "bss")
+                        .expect("alloc_vma failed");
                 }
                 Ok(())
             })?;
 
+        // Load the shared libraries needed by the application and resolve its references to them
+        if elf.dynamic.is_some() {
+            dynamic_loader::link(new_process, &elf)?;
+        }
+
+        if let Some(header) = elf.program_headers.iter().find(|header| header.p_type == elf64::program_header::PT_TLS) {
+            let template = TlsTemplate {
+                addr: header.p_vaddr,
+                file_size: header.p_filesz as usize,
+                mem_size: header.p_memsz as usize,
+                align: header.p_align.max(1) as usize,
+            };
+            // The initial values are copied from the loaded program, so they must lie within it
+            let address_space = &new_process.virtual_address_space;
+            let loaded = template.file_size == 0 || [template.addr, template.addr + template.file_size as u64 - 1].into_iter()
+                .all(|addr| address_space.is_address_within_vma(addr, VmaType::Code).is_some());
+            if !loaded || template.file_size > template.mem_size || !template.align.is_power_of_two() || template.align > PAGE_SIZE {
+                error!("ELF: Invalid TLS segment {header:?}");
+                return Err(ProcessLoadError::ElfInvalid);
+            }
+            new_process.set_tls_template(template);
+        }
+
         Ok(elf.entry)
     }
 
-    /// Helper function to provide arguments to a new application
-    /// Used only by `load_application()`
-    fn copy_args(new_process: &Arc<Process>, name: &str, args: &Vec<&str>) {
-        let args_size = args.iter().map(|arg| arg.len()).sum::<usize>();
-        let env_size = args_size;
+    /// Size of the environment page for the given arguments and environment variables
+    /// (`argc`, `argv`, `envc` and `envp` followed by the null-terminated strings).
+    fn env_size(name: &str, args: &[&str], env: &[&str]) -> usize {
+        let strings = name.len() + 1 + args.iter().chain(env).map(|s| s.len() + 1).sum::<usize>();
+        (2 + 1 + args.len() + env.len()) * size_of::<usize>() + strings
+    }
 
+    /// Helper function to provide arguments and environment variables to a new application.
+    /// The environment page contains `argc`, the `argv` array, `envc` and the `envp` array, followed by the strings.
+    /// Used only by `load_application_with_env()`, which checks that everything fits into one page.
+    fn copy_args(new_process: &Arc<Process>, name: &str, args: &[&str], env: &[&str]) {
         let env_virt_start = Page::from_start_address(VirtAddr::new(USER_SPACE_ENV_START as u64)).unwrap();
-        let env_page_count = if env_size > 0 && env_size % PAGE_SIZE == 0 {
-            env_size / PAGE_SIZE
-        } else {
-            (env_size / PAGE_SIZE) + 1
-        };
 
-        // create mapping for 'total_page_count'
+        // create mapping for one page
         let _vma = new_process
             .virtual_address_space
-            .user_alloc_map_full(Some(env_virt_start), env_page_count as u64, VmaType::Environment, "env")
+            .user_alloc_map_full(Some(env_virt_start), 1, VmaType::Environment, "env")
             .expect("user_alloc_map_full failed");
 
-        if env_page_count > 1 {
-            panic!("Environment size exceeds one page, which is not supported yet");
-        }
-
         let env_frame = new_process
             .virtual_address_space
             .get_phys(env_virt_start.start_address().as_u64())
@@ -477,28 +668,29 @@ impl Thread {
         let argc = env_addr.as_mut_ptr::<usize>(); // First entry in environment is argc (number of arguments)
         let argv = (env_addr + size_of::<usize>() as u64).as_mut_ptr::<*const u8>(); // Second entry in environment is argv (array of pointers to arguments)
 
-        // copy arguments directly behind argv array and store pointers to them in argv
         unsafe {
             argc.write(args.len() + 1);
-
-            let args_begin = argv.add(args.len() + 1) as *mut u8; // Physical start address of arguments (we use this address to copy them)
-            let args_begin_virt = env_virt_start.start_address() + size_of::<usize>() as u64 + ((args.len() + 1) * size_of::<usize>()) as u64; // Virtual start address of arguments (they will be visible here in user space)
-
-            // copy program name as first argument
-            args_begin.copy_from(name.as_bytes().as_ptr(), name.len());
-            args_begin.add(name.len()).write(0); // null-terminate the string for C compatibility
-            argv.write(args_begin_virt.as_ptr());
-
-            let mut offset = name.len() + 1;
-
-            // copy remaining arguments
-            for (i, arg) in args.iter().enumerate() {
-                let target = args_begin.add(offset);
-                target.copy_from(arg.as_bytes().as_ptr(), arg.len());
-                target.add(arg.len()).write(0); // null-terminate the string for C compatibility
-
-                argv.add(i + 1).write((args_begin_virt + offset as u64).as_ptr());
-                offset += arg.len() + 1;
+            // envc and envp follow directly behind argv
+            let envc = argv.add(args.len() + 1) as *mut usize;
+            let envp = envc.add(1) as *mut *const u8;
+            envc.write(env.len());
+
+            // the strings are copied directly behind envp
+            let strings_offset = (2 + args.len() + 1 + env.len()) * size_of::<usize>();
+            let strings_begin = env_addr.as_mut_ptr::<u8>().add(strings_offset); // Physical start address of strings (we use this address to copy them)
+            let strings_begin_virt = env_virt_start.start_address() + strings_offset as u64; // Virtual start address of strings (they will be visible here in user space)
+            let mut offset = 0;
+
+            // program name is the first argument
+            let pointers = core::iter::once((argv, name)).chain(args.iter().enumerate().map(|(i, arg)| (argv.add(i + 1), *arg)))
+                .chain(env.iter().enumerate().map(|(i, var)| (envp.add(i), *var)));
+            for (pointer, string) in pointers {
+                let target = strings_begin.add(offset);
+                target.copy_from(string.as_bytes().as_ptr(), string.len());
+                target.add(string.len()).write(0); // null-terminate the string for C compatibility
+
+                pointer.write((strings_begin_virt + offset as u64).as_ptr());
+                offset += string.len() + 1;
             }
         }
     }
@@ -518,6 +710,137 @@ impl Thread {
         self.state.store(new.as_u8(), Ordering::Release);
     }
 
+    /// Get the scheduling priority of the thread: Its own priority or the one it has inherited, if that is higher.
+    pub fn priority(&self) -> Priority {
+        match self.inherited_priority() {
+            Some(inherited) => inherited.min(self.base_priority()),
+            None => self.base_priority(),
+        }
+    }
+
+    /// Get the priority of the thread, that has been set for it (ignoring inherited priorities)
+    pub fn base_priority(&self) -> Priority {
+        Priority::try_from(self.priority.load(Ordering::Relaxed)).unwrap_or(Priority::Normal)
+    }
+
+    /// Get the priority inherited from threads waiting for a `PiMutex` held by this thread
+    pub fn inherited_priority(&self) -> Option<Priority> {
+        Priority::try_from(self.inherited_priority.load(Ordering::Relaxed)).ok()
+    }
+
+    /// Set (or with `None` drop) the inherited priority. \
+    /// Like `set_priority()`, this must be done by `Scheduler::inherit_priority()` for ready threads.
+    pub fn set_inherited_priority(&self, priority: Option<Priority>) {
+        self.inherited_priority.store(priority.map_or(NO_INHERITED_PRIORITY, usize::from), Ordering::Relaxed);
+    }
+
+    /// Get the scheduling class of the thread. While it has inherited a priority, it is scheduled strictly,
+    /// so it releases the `PiMutex` quickly.
+    pub fn scheduling_class(&self) -> SchedulingClass {
+        match self.inherited_priority() {
+            Some(_) => SchedulingClass::Strict,
+            None => self.base_scheduling_class(),
+        }
+    }
+
+    /// Get the scheduling class, that has been set for the thread
+    pub fn base_scheduling_class(&self) -> SchedulingClass {
+        SchedulingClass::try_from(self.class.load(Ordering::Relaxed)).unwrap_or(SchedulingClass::Fair)
+    }
+
+    /// Set the scheduling class of the thread. \
+    /// Like `set_priority()`, this must be done by `Scheduler::set_priority()` for ready threads.
+    pub fn set_scheduling_class(&self, class: SchedulingClass) {
+        self.class.store(class.into(), Ordering::Relaxed);
+    }
+
+    /// Get the virtual runtime of the thread (in ns)
+    pub fn vruntime(&self) -> u64 {
+        self.vruntime.load(Ordering::Relaxed)
+    }
+
+    /// Set the virtual runtime, called by the scheduler, when the thread is queued
+    pub fn set_vruntime(&self, vruntime: u64) {
+        self.vruntime.store(vruntime, Ordering::Relaxed);
+    }
+
+    /// Count a `PiMutex` acquired by this thread
+    pub fn add_pi_lock(&self) {
+        self.pi_locks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a `PiMutex` released by this thread and return the number of those still held
+    pub fn remove_pi_lock(&self) -> usize {
+        self.pi_locks.fetch_sub(1, Ordering::Relaxed) - 1
+    }
+
+    /// Set the scheduling priority of the thread. \
+    /// Once the thread has been made ready, this must be done by `Scheduler::set_priority()`,
+    /// which also moves it to the right queue.
+    pub fn set_priority(&self, priority: Priority) {
+        self.priority.store(priority.into(), Ordering::Relaxed);
+    }
+
+    /// Local APIC id of the core, that runs the thread or has run it last (`None`, if it hasn't run yet)
+    pub fn core(&self) -> Option<u32> {
+        let core = self.core.load(Ordering::Relaxed);
+        (core != scheduler::NO_CORE).then_some(core)
+    }
+
+    /// Called by the scheduler, before switching to the thread on the core with the local APIC id `apic_id`. \
+    /// `switch_seq` is the number of completed switches of the core, once this switch has been completed.
+    pub fn set_core(&self, apic_id: u32, switch_seq: usize) {
+        self.core.store(apic_id, Ordering::Relaxed);
+        self.switch_seq.store(switch_seq, Ordering::Relaxed);
+    }
+
+    /// Number of completed switches of `core()`, after the switch to this thread.
+    /// Its registers have been saved, once the core has completed another switch.
+    pub fn switch_seq(&self) -> usize {
+        self.switch_seq.load(Ordering::Relaxed)
+    }
+
+    /// Cores, on which the thread may run (bit n = core with local APIC id n)
+    pub fn affinity(&self) -> u64 {
+        self.affinity.load(Ordering::Relaxed)
+    }
+
+    /// Set the cores, on which the thread may run. \
+    /// Once the thread has been made ready, this must be done by `Scheduler::set_affinity()`,
+    /// which also moves it to an allowed core.
+    pub fn set_affinity(&self, affinity: u64) {
+        self.affinity.store(affinity, Ordering::Relaxed);
+    }
+
+    /// Check if the thread may run on the core with the local APIC id `apic_id`.
+    /// Cores with an id above 63 can't be selected, so only threads without restrictions run there.
+    pub fn may_run_on(&self, apic_id: u32) -> bool {
+        affinity_allows(self.affinity(), apic_id)
+    }
+
+    /// CPU time and scheduling statistics of the thread (those of its process are updated as well)
+    pub fn usage(&self) -> &UsageCounters {
+        &self.usage
+    }
+
+    /// Charge a timer tick of `ns` nanoseconds to the thread and its process.
+    pub fn add_cpu_time(&self, user: bool, ns: u64) {
+        self.usage.add_cpu_time(user, ns);
+        self.process.usage().add_cpu_time(user, ns);
+    }
+
+    /// Count a switch away from the thread, because it has blocked (`voluntary`) or has been preempted.
+    pub fn add_context_switch(&self, voluntary: bool) {
+        self.usage.add_context_switch(voluntary);
+        self.process.usage().add_context_switch(voluntary);
+    }
+
+    /// Count a dispatch of the thread, after it has waited `ns` nanoseconds in a ready queue.
+    pub fn add_run_delay(&self, ns: u64) {
+        self.usage.add_run_delay(ns);
+        self.process.usage().add_run_delay(ns);
+    }
+
     /// Atomic state transition (very important)
     pub fn compare_and_set(&self, expected: ThreadState, new: ThreadState) -> bool {
         self.state
@@ -583,10 +906,12 @@ unsafe extern "C" fn thread_kernel_start(old_rsp0: u64) {
 /// Low-level function for starting a thread in user mode
 #[unsafe(naked)]
 #[allow(improper_ctypes_definitions)] // 'entry' takes no arguments and has no return value, so we just assume that the "C" and "Rust" ABIs act the same way in this case
-unsafe extern "C" fn thread_user_start(old_rsp0: u64, entry: extern "sysv64" fn()) -> ! {
+unsafe extern "C" fn thread_user_start(old_rsp0: u64, entry: extern "sysv64" fn(), arg: usize) -> ! {
     naked_asm!(
         "mov rsp, rdi", // Load 'old_rsp' (first parameter)
         "mov rdi, rsi", // Second parameter becomes first parameter for 'kickoff_user_thread()'
+        "mov rsi, rdx", // Third parameter becomes second parameter for 'kickoff_user_thread()'
+        "xor ebp, ebp", // Terminate the chain of frame pointers (and don't leak a kernel address)
         "iretq"         // Switch to user-mode
     )
 }
@@ -654,10 +979,30 @@ unsafe extern "C" fn thread_switch(current_rsp0: *mut u64, next_rsp0: u64, next_
     )
 }
 
+impl From<ProcessLoadError> for Errno {
+    fn from(error: ProcessLoadError) -> Self {
+        match error {
+            ProcessLoadError::NotFound => Errno::ENOENT,
+            ProcessLoadError::ElfInvalid => Errno::EBADF,
+            ProcessLoadError::ArgsTooLong => Errno::EINVAL,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ProcessLoadError {
     NotFound,
     ElfInvalid,
+    /// Arguments and environment variables don't fit into the environment page
+    ArgsTooLong,
+}
+
+/// Check if `affinity` (see `Thread::affinity()`) allows the core with the local APIC id `apic_id`.
+pub fn affinity_allows(affinity: u64, apic_id: u32) -> bool {
+    match 1u64.checked_shl(apic_id) {
+        Some(bit) => affinity & bit != 0,
+        None => affinity == AFFINITY_ALL,
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -0,0 +1,264 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: splash                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Graphical boot screen, showing the progress of the boot         ║
+   ║         sequence on the framebuffer. It is driven by the subsystem      ║
+   ║         events reported by 'boot.rs'. The splash is also registered as  ║
+   ║         log stream and keeps the most recent lines, so it can fall back ║
+   ║         to a plain text log, if a subsystem fails or the kernel panics. ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::sync::Arc;
+use graphic::color::{Color, BLACK, GREY, HHU_BLUE, HHU_GREEN, RED, WHITE, YELLOW};
+use graphic::lfb::{DEFAULT_CHAR_HEIGHT, DEFAULT_CHAR_WIDTH, LFB};
+use spin::{Mutex, Once};
+use stream::OutputStream;
+use x86_64::instructions::interrupts;
+use crate::{buffered_lfb, logger};
+
+/// Number of log lines kept for the text fallback
+const MAX_LINES: usize = 64;
+/// Longer log lines are truncated
+const MAX_COLUMNS: usize = 160;
+/// Scale factor of the title
+const TITLE_SCALE: u32 = 4;
+const PROGRESS_BAR_HEIGHT: u32 = 16;
+
+/// Events reported by the boot sequence for each subsystem it initializes
+#[derive(Debug, Clone, Copy)]
+pub enum SubsystemEvent {
+    Started(&'static str),
+    Finished(&'static str),
+    /// The subsystem has logged an error during its initialization
+    Failed(&'static str),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// The splash has not been shown (or boot has finished)
+    Off,
+    Graphic,
+    /// Something went wrong, the log is printed as text instead
+    Text,
+}
+
+/// The state is statically allocated, because log messages may also be written
+/// from interrupt handlers, where we must not allocate.
+struct Splash {
+    mode: Mode,
+    /// Number of subsystems initialized during boot
+    total: usize,
+    finished: usize,
+    /// Ring buffer of recent log lines (without ANSI escape sequences)
+    lines: [Line; MAX_LINES],
+    /// Index of the line currently being written
+    current: usize,
+    /// Number of complete lines in the ring buffer
+    complete: usize,
+    /// Position of the next line in text mode (in pixels)
+    text_y: u32,
+    /// Currently inside an ANSI escape sequence
+    escape: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Line {
+    text: [u8; MAX_COLUMNS],
+    len: usize,
+}
+
+/// Log stream forwarding to `SPLASH`
+struct SplashLog;
+
+static SPLASH: Mutex<Splash> = Mutex::new(Splash::new());
+static SPLASH_LOG: Once<Arc<SplashLog>> = Once::new();
+
+/// Show the boot screen and start collecting log lines. `total` is the number of subsystems,
+/// for which `report()` will be called.
+pub fn show(total: usize) {
+    interrupts::without_interrupts(|| {
+        let mut splash = SPLASH.lock();
+        splash.mode = Mode::Graphic;
+        splash.total = total;
+        splash.draw_background();
+        splash.draw_progress(None);
+    });
+    logger().register(Arc::clone(SPLASH_LOG.call_once(|| Arc::new(SplashLog))));
+}
+
+/// Update the boot screen. A failed subsystem switches to the text log.
+pub fn report(event: SubsystemEvent) {
+    interrupts::without_interrupts(|| {
+        let mut splash = SPLASH.lock();
+        match event {
+            SubsystemEvent::Started(name) => splash.draw_progress(Some(name)),
+            SubsystemEvent::Finished(_) => {
+                splash.finished += 1;
+                splash.draw_progress(None);
+            }
+            SubsystemEvent::Failed(_) => {
+                splash.finished += 1;
+                splash.switch_to_text();
+            }
+        }
+    });
+}
+
+/// Stop updating the framebuffer, because the applications take it over.
+pub fn finish() {
+    if let Some(log) = SPLASH_LOG.get() {
+        logger().remove(log.as_ref());
+    }
+    interrupts::without_interrupts(|| SPLASH.lock().mode = Mode::Off);
+}
+
+/// Called from the panic handler: show the log as text (if the splash is active),
+/// so the panic message is not hidden by the boot screen.
+pub unsafe fn force_text_mode() {
+    unsafe { SPLASH.force_unlock() };
+    let mut splash = SPLASH.lock();
+    // the framebuffer may not even be initialized, if the splash has not been shown
+    if splash.mode == Mode::Graphic {
+        unsafe { buffered_lfb().force_unlock() };
+        splash.switch_to_text();
+    }
+}
+
+impl OutputStream for SplashLog {
+    fn write_byte(&self, b: u8) {
+        SPLASH.lock().push(b);
+    }
+
+    fn write_str(&self, string: &str) {
+        let mut splash = SPLASH.lock();
+        string.bytes().for_each(|b| splash.push(b));
+    }
+}
+
+impl Splash {
+    const fn new() -> Self {
+        Self {
+            mode: Mode::Off,
+            total: 0,
+            finished: 0,
+            lines: [Line { text: [0; MAX_COLUMNS], len: 0 }; MAX_LINES],
+            current: 0,
+            complete: 0,
+            text_y: 0,
+            escape: false,
+        }
+    }
+
+    fn push(&mut self, b: u8) {
+        if self.mode == Mode::Off {
+            return;
+        }
+        // skip ANSI escape sequences, they end with a letter
+        if self.escape {
+            self.escape = !b.is_ascii_alphabetic();
+            return;
+        }
+
+        match b {
+            0x1b => self.escape = true,
+            b'\n' => {
+                if self.mode == Mode::Text {
+                    self.draw_text_line(self.current);
+                }
+                self.current = (self.current + 1) % MAX_LINES;
+                self.complete = (self.complete + 1).min(MAX_LINES - 1);
+                self.lines[self.current].len = 0;
+            }
+            _ => {
+                let line = &mut self.lines[self.current];
+                if line.len < MAX_COLUMNS {
+                    // the font only has a fixed width for ASCII characters
+                    line.text[line.len] = if b.is_ascii() && !b.is_ascii_control() { b } else { b'?' };
+                    line.len += 1;
+                }
+            }
+        }
+    }
+
+    fn switch_to_text(&mut self) {
+        if self.mode != Mode::Graphic {
+            return;
+        }
+        self.mode = Mode::Text;
+        buffered_lfb().lock().direct_lfb().clear();
+        self.text_y = 0;
+
+        let first = (self.current + MAX_LINES - self.complete) % MAX_LINES;
+        for i in 0..self.complete {
+            self.draw_text_line((first + i) % MAX_LINES);
+        }
+    }
+
+    fn draw_text_line(&mut self, index: usize) {
+        let mut lfb = buffered_lfb().lock();
+        let lfb = lfb.direct_lfb();
+        if self.text_y + DEFAULT_CHAR_HEIGHT > lfb.height() {
+            lfb.scroll_up(DEFAULT_CHAR_HEIGHT);
+            self.text_y -= DEFAULT_CHAR_HEIGHT;
+        }
+
+        let line = &self.lines[index];
+        let columns = ((lfb.width() / DEFAULT_CHAR_WIDTH) as usize).min(line.len);
+        // only printable ASCII characters have been stored
+        let text = core::str::from_utf8(&line.text[..columns]).unwrap_or("");
+        lfb.draw_string(0, self.text_y, color_of(text), BLACK, text);
+        self.text_y += DEFAULT_CHAR_HEIGHT;
+    }
+
+    fn draw_background(&self) {
+        let mut lfb = buffered_lfb().lock();
+        let lfb = lfb.direct_lfb();
+        lfb.fill_rect(0, 0, lfb.width(), lfb.height(), BLACK);
+
+        let title = "D3OS";
+        let title_width = title.len() as u32 * DEFAULT_CHAR_WIDTH * TITLE_SCALE;
+        let x = lfb.width().saturating_sub(title_width) / 2;
+        let y = lfb.height() / 3;
+        lfb.draw_string_scaled(x, y, TITLE_SCALE, TITLE_SCALE, HHU_BLUE.bright(), BLACK, title);
+    }
+
+    /// Draw the progress bar and the name of the subsystem being initialized.
+    fn draw_progress(&self, subsystem: Option<&str>) {
+        if self.mode != Mode::Graphic {
+            return;
+        }
+        let mut lfb = buffered_lfb().lock();
+        let lfb = lfb.direct_lfb();
+        let width = lfb.width() / 2;
+        let x = lfb.width() / 4;
+        let y = lfb.height() / 2;
+
+        let done = if self.total == 0 { 0 } else { width * self.finished.min(self.total) as u32 / self.total as u32 };
+        lfb.fill_rect(x, y, done, PROGRESS_BAR_HEIGHT, HHU_GREEN);
+        lfb.fill_rect(x + done, y, width - done, PROGRESS_BAR_HEIGHT, GREY);
+
+        let text_y = y + PROGRESS_BAR_HEIGHT + DEFAULT_CHAR_HEIGHT;
+        lfb.fill_rect(0, text_y, lfb.width(), DEFAULT_CHAR_HEIGHT, BLACK);
+        if let Some(name) = subsystem {
+            draw_centered(lfb, text_y, name);
+        }
+    }
+}
+
+fn draw_centered(lfb: &mut LFB, y: u32, text: &str) {
+    let x = lfb.width().saturating_sub(text.len() as u32 * DEFAULT_CHAR_WIDTH) / 2;
+    lfb.draw_string(x, y, WHITE, BLACK, text);
+}
+
+/// Highlight warnings and errors (the level token is part of each log line).
+fn color_of(line: &str) -> Color {
+    if line.contains("[ERR]") {
+        RED.bright()
+    } else if line.contains("[WRN]") {
+        YELLOW.bright()
+    } else {
+        WHITE
+    }
+}
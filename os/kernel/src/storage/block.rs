@@ -1,5 +1,7 @@
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
+use log::warn;
 use mbrs::Mbr;
 
 /// Trait for accessing devices that can read and write data in fixed-size blocks (sectors)
@@ -12,6 +14,13 @@ pub trait BlockDevice {
     /// Write a given number of sectors from the provided buffer.
     fn write(&self, sector: u64, count: usize, buffer: &[u8]) -> usize;
 
+    /// Make sure that all written sectors have reached the storage medium.
+    /// Returns false if the device reported an error.
+    /// Devices without a write cache don't need to implement this.
+    fn flush(&self) -> bool {
+        true
+    }
+
     /// Get the size of the device in bytes.
     fn sector_count(&self) -> u64;
 
@@ -29,15 +38,42 @@ pub fn lba_to_chs(lba: u64, heads: u8, sectors_per_cylinder: u8) -> (u16, u8, u8
     (cylinder, head, sector)
 }
 
-/// Scan a block device for partitions using the MBR (Master Boot Record) partition table.
+/// Partition type of the protective MBR in front of a GPT
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xee;
+/// Offset of the type of the first entry in the MBR partition table
+const MBR_FIRST_TYPE_OFFSET: usize = 446 + 4;
+const GPT_SIGNATURE: &[u8] = b"EFI PART";
+/// Minimum size of a GPT header and a GPT partition entry
+const GPT_HEADER_SIZE: usize = 92;
+const GPT_ENTRY_SIZE: usize = 128;
+/// Upper limit for the number of GPT entries (the usual value is 128)
+const GPT_MAX_ENTRIES: usize = 1024;
+
+/// Scan a block device for partitions using the GPT (GUID Partition Table) or,
+/// if there is none, the MBR (Master Boot Record) partition table.
 /// The device is given as an Arc reference to allow sharing it between partitions.
 pub fn scan_partitions(device: &Arc<dyn BlockDevice + Send + Sync>) -> Vec<Arc<dyn BlockDevice + Send + Sync>> {
     // Read the MBR (Master Boot Record) from the device
     let mut buffer = [0u8; 512];
-    device.read(0, 1, &mut buffer);
+    if device.read(0, 1, &mut buffer) != 1 {
+        return Vec::new();
+    }
 
     let mut partitions = Vec::<Arc<dyn BlockDevice + Send + Sync>>::new();
 
+    // A GPT is announced by a single MBR entry covering the whole disk
+    if buffer[MBR_FIRST_TYPE_OFFSET] == MBR_TYPE_GPT_PROTECTIVE {
+        match scan_gpt(device) {
+            Some(ranges) => {
+                for (start_sector, sector_count) in ranges {
+                    partitions.push(Arc::new(Partition::new(Arc::clone(device), start_sector, sector_count)));
+                }
+                return partitions;
+            }
+            None => warn!("Invalid GPT, using the protective MBR instead"),
+        }
+    }
+
     // Iterate over the partition entries and create a Partition object for each valid one
     if let Ok(mbr) = Mbr::try_from_bytes(&buffer) {
         for entry in mbr.partition_table.entries.into_iter().flatten() {
@@ -48,6 +84,77 @@ pub fn scan_partitions(device: &Arc<dyn BlockDevice + Send + Sync>) -> Vec<Arc<d
     partitions
 }
 
+/// Read the GPT of `device`, using the backup at the end of the device, if the primary one is damaged.
+/// Returns start sector and sector count of all used entries or `None`, if there is no valid GPT.
+fn scan_gpt(device: &Arc<dyn BlockDevice + Send + Sync>) -> Option<Vec<(u64, u64)>> {
+    let last_sector = device.sector_count().checked_sub(1)?;
+    [1, last_sector].into_iter().find_map(|header_sector| read_gpt(device, header_sector))
+}
+
+/// Read the GPT with the header in `header_sector` and check its checksums.
+fn read_gpt(device: &Arc<dyn BlockDevice + Send + Sync>, header_sector: u64) -> Option<Vec<(u64, u64)>> {
+    let sector_size = device.sector_size() as usize;
+    let mut header = vec![0u8; sector_size];
+    if device.read(header_sector, 1, &mut header) != 1 || !header.starts_with(GPT_SIGNATURE) {
+        return None;
+    }
+
+    let header_size = u32_at(&header, 12) as usize;
+    if !(GPT_HEADER_SIZE..=sector_size).contains(&header_size) {
+        return None;
+    }
+    // Die Prüfsumme wird mit genullter Prüfsumme im Header berechnet
+    let header_crc = u32_at(&header, 16);
+    header[16..20].fill(0);
+    if crc32(&header[..header_size]) != header_crc {
+        return None;
+    }
+
+    let entries_sector = u64_at(&header, 72);
+    let entry_count = u32_at(&header, 80) as usize;
+    let entry_size = u32_at(&header, 84) as usize;
+    if entry_count > GPT_MAX_ENTRIES || entry_size < GPT_ENTRY_SIZE {
+        return None;
+    }
+
+    let sector_count = (entry_count * entry_size).div_ceil(sector_size);
+    let mut entries = vec![0u8; sector_count * sector_size];
+    if device.read(entries_sector, sector_count, &mut entries) != sector_count {
+        return None;
+    }
+    let entries = &entries[..entry_count * entry_size];
+    if crc32(entries) != u32_at(&header, 88) {
+        return None;
+    }
+
+    // Unused entries have a type GUID of zero, the last sector of a partition is inclusive
+    let partitions = entries.chunks_exact(entry_size)
+        .filter(|entry| entry[..16].iter().any(|&byte| byte != 0))
+        .map(|entry| (u64_at(entry, 32), u64_at(entry, 40)))
+        .filter(|&(first, last)| first <= last && last < device.sector_count())
+        .map(|(first, last)| (first, last - first + 1))
+        .collect();
+
+    Some(partitions)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// CRC-32 (as used by GPT and Ethernet)
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 }
+        })
+    })
+}
+
 /// A partition on a block device.
 /// Holds a reference to the device it is one and passes through read/write requests.
 /// Sector boundaries are checked to prevent reading/writing outside the partition.
@@ -69,8 +176,8 @@ impl BlockDevice for Partition {
             return 0;
         }
 
-        let sector = sector + self.start_sector;
         let count = count.min((self.sector_count - sector) as usize);
+        let sector = sector + self.start_sector;
         self.device.read(sector, count, buffer)
     }
 
@@ -79,11 +186,15 @@ impl BlockDevice for Partition {
             return 0;
         }
 
-        let sector = sector + self.start_sector;
         let count = count.min((self.sector_count - sector) as usize);
+        let sector = sector + self.start_sector;
         self.device.write(sector, count, buffer)
     }
 
+    fn flush(&self) -> bool {
+        self.device.flush()
+    }
+
     fn sector_count(&self) -> u64 {
         self.sector_count
     }
@@ -0,0 +1,287 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: cache                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Block cache between the file systems and the drivers. Every     ║
+   ║         device registered with 'add_block_device()' is wrapped in a     ║
+   ║         'BlockCache', which keeps recently used sectors in memory (the  ║
+   ║         least recently used ones are evicted first). If a read starts   ║
+   ║         where the previous one ended, the following sectors are read    ║
+   ║         ahead in the same request. Writes only go to the cache, dirty   ║
+   ║         sectors are written back when they are evicted, by the flush    ║
+   ║         thread every few seconds or by 'sync()'. The size of each cache ║
+   ║         is set with 'blockcache=<size>' on the kernel command line      ║
+   ║         (e.g. "blockcache=4M", 0 disables caching).                     ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - init                 set the cache size and start the flush thread  ║
+   ║   - sync                 write back the dirty sectors of all devices    ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use log::warn;
+use spin::{Mutex, RwLock};
+
+use crate::device::stats::{self, DeviceStats};
+use crate::process::thread::Thread;
+use crate::scheduler;
+use crate::storage::block::BlockDevice;
+use crate::storage::ramdisk;
+use syscall::priority::Priority;
+
+/// Size of the cache of each device, if none is given on the kernel command line
+const DEFAULT_CACHE_SIZE: usize = 1024 * 1024;
+/// Interval in which the flush thread writes back dirty sectors
+const FLUSH_INTERVAL_MS: usize = 5000;
+/// Maximum number of sectors read ahead
+const MAX_READ_AHEAD: usize = 64;
+const STATS_COUNTERS: &[&str] = &["hits", "misses", "sectors_read_ahead", "sectors_written_back"];
+
+static CACHE_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_CACHE_SIZE);
+static CACHES: RwLock<Vec<Arc<BlockCache>>> = RwLock::new(Vec::new());
+
+/// Set the cache size requested on the kernel command line and start the flush thread.
+/// Must be called before the first block device is registered.
+pub fn init(cmdline: Option<&str>) {
+    let size = cmdline
+        .into_iter()
+        .flat_map(str::split_whitespace)
+        .find_map(|arg| arg.strip_prefix("blockcache="));
+    if let Some(size) = size {
+        match ramdisk::parse_size(size) {
+            Some(size) => CACHE_SIZE.store(size, Ordering::Relaxed),
+            None => warn!("Invalid block cache size [{size}] on the kernel command line"),
+        }
+    }
+
+    let thread = Thread::new_kernel_thread(flush_thread, "blockcache");
+    thread.set_priority(Priority::Background);
+    scheduler().ready(thread);
+}
+
+/// Write back the dirty sectors of all devices and flush their write caches.
+/// Returns false if a device reported an error.
+pub fn sync() -> bool {
+    let caches = CACHES.read().clone();
+    caches.iter().fold(true, |success, cache| cache.flush() && success)
+}
+
+/// Put a cache in front of the device `name` (unless caching is disabled).
+pub(super) fn wrap(name: &str, device: Arc<dyn BlockDevice + Send + Sync>) -> Arc<dyn BlockDevice + Send + Sync> {
+    let capacity = CACHE_SIZE.load(Ordering::Relaxed) / device.sector_size() as usize;
+    if capacity == 0 {
+        return device;
+    }
+
+    let cache = Arc::new(BlockCache::new(device, capacity));
+    stats::register(&format!("{name}-cache"), Arc::clone(&cache.stats));
+    CACHES.write().push(Arc::clone(&cache));
+    cache
+}
+
+extern "sysv64" fn flush_thread() {
+    loop {
+        scheduler().sleep(FLUSH_INTERVAL_MS);
+
+        let caches = CACHES.read().clone();
+        for cache in caches {
+            if !cache.write_back() {
+                warn!("Failed to write back cached sectors");
+            }
+        }
+    }
+}
+
+struct CachedSector {
+    data: Box<[u8]>,
+    dirty: bool,
+    last_use: u64,
+}
+
+struct CacheState {
+    sectors: BTreeMap<u64, CachedSector>,
+    /// Cached sectors by their last use (the first one is evicted next)
+    lru: BTreeMap<u64, u64>,
+    clock: u64,
+    /// Sector following the previous read (a read starting there is sequential)
+    next_read: u64,
+}
+
+impl CacheState {
+    /// Mark `sector` as used just now.
+    fn touch(&mut self, sector: u64) {
+        if let Some(entry) = self.sectors.get_mut(&sector) {
+            self.lru.remove(&entry.last_use);
+            self.clock += 1;
+            entry.last_use = self.clock;
+            self.lru.insert(self.clock, sector);
+        }
+    }
+}
+
+/// A block device with a write-back cache of `capacity` sectors.
+pub struct BlockCache {
+    device: Arc<dyn BlockDevice + Send + Sync>,
+    capacity: usize,
+    state: Mutex<CacheState>,
+    stats: Arc<DeviceStats>,
+}
+
+impl BlockCache {
+    fn new(device: Arc<dyn BlockDevice + Send + Sync>, capacity: usize) -> Self {
+        let state = CacheState { sectors: BTreeMap::new(), lru: BTreeMap::new(), clock: 0, next_read: u64::MAX };
+        Self { device, capacity, state: Mutex::new(state), stats: DeviceStats::new(STATS_COUNTERS) }
+    }
+
+    /// Add `sector` read from the device, unless it is cached already (the cached data may be newer).
+    fn fill(&self, state: &mut CacheState, sector: u64, data: &[u8]) {
+        if !state.sectors.contains_key(&sector) {
+            self.insert(state, sector, data, false);
+        }
+    }
+
+    /// Set the content of `sector` to `data` and mark it as dirty.
+    fn store(&self, state: &mut CacheState, sector: u64, data: &[u8]) {
+        match state.sectors.get_mut(&sector) {
+            Some(entry) => {
+                entry.data.copy_from_slice(data);
+                entry.dirty = true;
+                state.touch(sector);
+            }
+            None => self.insert(state, sector, data, true),
+        }
+    }
+
+    fn insert(&self, state: &mut CacheState, sector: u64, data: &[u8], dirty: bool) {
+        if state.sectors.len() >= self.capacity {
+            self.evict(state);
+        }
+
+        state.clock += 1;
+        let last_use = state.clock;
+        state.sectors.insert(sector, CachedSector { data: Box::from(data), dirty, last_use });
+        state.lru.insert(last_use, sector);
+    }
+
+    /// Remove the least recently used sector, writing it back if it is dirty.
+    /// If that fails, it stays in the cache (which is then larger than its capacity for a while).
+    fn evict(&self, state: &mut CacheState) {
+        let Some((&last_use, &sector)) = state.lru.iter().next() else {
+            return;
+        };
+
+        let entry = &state.sectors[&sector];
+        if entry.dirty {
+            if self.device.write(sector, 1, &entry.data) != 1 {
+                warn!("Failed to write back sector [{sector}] on eviction");
+                return;
+            }
+            self.stats.inc("sectors_written_back");
+        }
+
+        state.lru.remove(&last_use);
+        state.sectors.remove(&sector);
+    }
+
+    /// Write all dirty sectors to the device (contiguous ones in a single request).
+    /// Returns false if a write failed, those sectors stay dirty.
+    fn write_back(&self) -> bool {
+        let sector_size = self.device.sector_size() as usize;
+        let mut state = self.state.lock();
+        let dirty: Vec<u64> = state.sectors.iter().filter(|(_, entry)| entry.dirty).map(|(&sector, _)| sector).collect();
+
+        let mut success = true;
+        for run in dirty.chunk_by(|a, b| a + 1 == *b) {
+            let mut buffer = Vec::with_capacity(run.len() * sector_size);
+            run.iter().for_each(|sector| buffer.extend_from_slice(&state.sectors[sector].data));
+
+            let written = self.device.write(run[0], run.len(), &buffer);
+            for sector in &run[..written.min(run.len())] {
+                state.sectors.get_mut(sector).unwrap().dirty = false;
+            }
+            self.stats.add("sectors_written_back", written as u64);
+            success &= written == run.len();
+        }
+
+        success
+    }
+}
+
+impl BlockDevice for BlockCache {
+    fn read(&self, sector: u64, count: usize, buffer: &mut [u8]) -> usize {
+        let sector_size = self.device.sector_size() as usize;
+        let count = count.min(buffer.len() / sector_size).min(self.sector_count().saturating_sub(sector) as usize);
+        let mut state = self.state.lock();
+        let sequential = sector == state.next_read;
+        state.next_read = sector + count as u64;
+
+        let mut done = 0;
+        while done < count {
+            let current = sector + done as u64;
+            if let Some(entry) = state.sectors.get(&current) {
+                buffer[done * sector_size..(done + 1) * sector_size].copy_from_slice(&entry.data);
+                state.touch(current);
+                self.stats.inc("hits");
+                done += 1;
+                continue;
+            }
+
+            // Alle fehlenden Sektoren am Stück lesen, bei sequentiellem Lesen auch die folgenden
+            let missing = (done..count).take_while(|&i| !state.sectors.contains_key(&(sector + i as u64))).count();
+            let read_ahead = if sequential && done + missing == count {
+                let remaining = self.sector_count().saturating_sub(current + missing as u64) as usize;
+                MAX_READ_AHEAD.min(self.capacity / 4).min(remaining)
+            } else {
+                0
+            };
+
+            let mut data = vec![0; (missing + read_ahead) * sector_size];
+            let read = self.device.read(current, missing + read_ahead, &mut data);
+            for (i, sector_data) in data.chunks_exact(sector_size).take(read).enumerate() {
+                self.fill(&mut state, current + i as u64, sector_data);
+            }
+            self.stats.add("misses", missing as u64);
+            self.stats.add("sectors_read_ahead", read.saturating_sub(missing) as u64);
+
+            let copied = read.min(missing);
+            buffer[done * sector_size..(done + copied) * sector_size].copy_from_slice(&data[..copied * sector_size]);
+            done += copied;
+            if copied < missing {
+                break;
+            }
+        }
+
+        done
+    }
+
+    fn write(&self, sector: u64, count: usize, buffer: &[u8]) -> usize {
+        let sector_size = self.device.sector_size() as usize;
+        let count = count.min(buffer.len() / sector_size).min(self.sector_count().saturating_sub(sector) as usize);
+        let mut state = self.state.lock();
+        for (i, data) in buffer.chunks_exact(sector_size).take(count).enumerate() {
+            self.store(&mut state, sector + i as u64, data);
+        }
+
+        count
+    }
+
+    fn flush(&self) -> bool {
+        let written_back = self.write_back();
+        self.device.flush() && written_back
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.device.sector_count()
+    }
+
+    fn sector_size(&self) -> u16 {
+        self.device.sector_size()
+    }
+}
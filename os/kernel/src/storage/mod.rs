@@ -1,42 +1,54 @@
 use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use log::info;
 use smallmap::Map;
 use spin::{Mutex, Once, RwLock};
-use crate::device::ide;
+use system_info::devices::{DeviceClass, DeviceInfo};
+use crate::device::{ide, registry, usb};
 use crate::storage::block::BlockDevice;
 
 pub mod block;
+pub mod cache;
+pub mod ramdisk;
 
 static BLOCK_DEVICES: Once<RwLock<Map<String, Arc<dyn BlockDevice + Send + Sync>>>> = Once::new();
 static DEVICE_TYPES: Once<Mutex<Map<String, usize>>> = Once::new();
 
-/// Initialize all storage drivers
-pub fn init() {
+/// Initialize the block cache and all storage drivers
+pub fn init(cmdline: Option<&str>) {
+    cache::init(cmdline);
     ide::init();
+    usb::init();
 }
 
-/// Register a block device with the given type
+/// Register a block device with the given type and return its name
 /// The type is used to generate a unique name for the device (e.g. type "ata" will generate names "ata0", "ata1", etc.)
-pub fn add_block_device(typ: &str, drive: Arc<dyn BlockDevice + Send + Sync>) {
+pub fn add_block_device(typ: &str, drive: Arc<dyn BlockDevice + Send + Sync>) -> String {
     let typ = typ.to_string();
     let mut types = DEVICE_TYPES.call_once(|| Mutex::new(Map::new())).lock();
     let index = *types.get(&typ).unwrap_or(&0);
     let name = format!("{typ}{index}");
     types.insert(typ, index + 1);
 
+    // Partitionen liegen über dem Cache, damit sie ihn mit dem Laufwerk teilen
+    let drive = cache::wrap(&name, drive);
     let partitions = block::scan_partitions(&drive);
 
     let mut drives = BLOCK_DEVICES.call_once(|| RwLock::new(Map::new())).write();
     drives.insert(name.clone(), drive);
     info!("Registered block device [{name}]");
+    register_device(&name, None);
 
     for (index, partition) in partitions.into_iter().enumerate() {
-        let name = format!("{name}p{index}");
-        drives.insert(name.clone(), partition);
-        info!("Registered partition [{name}]");
+        let partition_name = format!("{name}p{index}");
+        drives.insert(partition_name.clone(), partition);
+        info!("Registered partition [{partition_name}]");
+        register_device(&partition_name, Some(&name));
     }
+
+    name
 }
 
 /// Get a block device by its name
@@ -45,4 +57,21 @@ pub fn block_device(name: &str) -> Option<Arc<dyn BlockDevice + Send + Sync>> {
         None => None,
         Some(device) => Some(Arc::clone(device))
     }
-}
\ No newline at end of file
+}
+
+/// Get the names of all block devices and partitions (in no particular order)
+pub fn block_device_names() -> Vec<String> {
+    BLOCK_DEVICES.call_once(|| RwLock::new(Map::new())).read().iter().map(|(name, _)| name.clone()).collect()
+}
+
+/// Make a block device (or partition of `parent`) visible in the device registry.
+fn register_device(name: &str, parent: Option<&str>) {
+    registry::register(DeviceInfo {
+        name: name.to_string(),
+        class: DeviceClass::Storage,
+        bus: "block".to_string(),
+        parent: parent.map(str::to_string),
+        driver: None,
+        resources: Vec::new(),
+    });
+}
@@ -0,0 +1,152 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: ramdisk                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Block devices backed by kernel memory (registered as "ram0",    ║
+   ║         "ram1", etc.). They exist on every machine and are useful for   ║
+   ║         testing filesystems and the block cache. A ramdisk is created   ║
+   ║         empty with 'ramdisk=<size>' on the kernel command line (e.g.    ║
+   ║         "ramdisk=16M") or by the 'RamdiskCreate' syscall, or from a     ║
+   ║         boot module called "ramdisk", whose content it keeps. The       ║
+   ║         memory of a ramdisk is never freed.                             ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - init                 create the ramdisks requested at boot time     ║
+   ║   - create               allocate and register an empty ramdisk         ║
+   ║   - parse_size           parse a size like "512K", "16M" or "1G"        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::slice;
+use log::{info, warn};
+use spin::RwLock;
+use x86_64::structures::paging::frame::PhysFrameRange;
+
+use crate::device::stats::{self, DeviceStats};
+use crate::memory;
+use crate::memory::PAGE_SIZE;
+use crate::storage::add_block_device;
+use crate::storage::block::BlockDevice;
+
+const SECTOR_SIZE: usize = 512;
+const STATS_COUNTERS: &[&str] = &["read_requests", "write_requests", "sectors_read", "sectors_written"];
+
+/// A block device in physically contiguous, identity mapped memory.
+pub struct Ramdisk {
+    data: RwLock<&'static mut [u8]>,
+    stats: Arc<DeviceStats>,
+}
+
+impl Ramdisk {
+    /// Use `frames` as backing memory, keeping their content. The first `size` bytes are used (rounded up to whole sectors).
+    fn from_frames(frames: PhysFrameRange, size: usize) -> Self {
+        let size = size.div_ceil(SECTOR_SIZE) * SECTOR_SIZE;
+        assert!(size <= frames.len() as usize * PAGE_SIZE, "Ramdisk is larger than its memory");
+
+        let data = unsafe { slice::from_raw_parts_mut(frames.start.start_address().as_u64() as *mut u8, size) };
+        Self { data: RwLock::new(data), stats: DeviceStats::new(STATS_COUNTERS) }
+    }
+
+    /// Return the byte range of a request for `count` sectors starting at `sector`, clamped to the size of the disk and the buffer.
+    fn range(sector: u64, count: usize, buffer_len: usize, size: usize) -> Option<(usize, usize)> {
+        let start = usize::try_from(sector).ok()?.checked_mul(SECTOR_SIZE)?;
+        if start >= size {
+            return None;
+        }
+
+        let count = count.min((size - start) / SECTOR_SIZE).min(buffer_len / SECTOR_SIZE);
+        Some((start, count))
+    }
+}
+
+impl BlockDevice for Ramdisk {
+    fn read(&self, sector: u64, count: usize, buffer: &mut [u8]) -> usize {
+        let data = self.data.read();
+        let Some((start, count)) = Self::range(sector, count, buffer.len(), data.len()) else {
+            return 0;
+        };
+
+        let length = count * SECTOR_SIZE;
+        buffer[..length].copy_from_slice(&data[start..start + length]);
+
+        self.stats.inc("read_requests");
+        self.stats.add("sectors_read", count as u64);
+        count
+    }
+
+    fn write(&self, sector: u64, count: usize, buffer: &[u8]) -> usize {
+        let mut data = self.data.write();
+        let Some((start, count)) = Self::range(sector, count, buffer.len(), data.len()) else {
+            return 0;
+        };
+
+        let length = count * SECTOR_SIZE;
+        data[start..start + length].copy_from_slice(&buffer[..length]);
+
+        self.stats.inc("write_requests");
+        self.stats.add("sectors_written", count as u64);
+        count
+    }
+
+    fn sector_count(&self) -> u64 {
+        (self.data.read().len() / SECTOR_SIZE) as u64
+    }
+
+    fn sector_size(&self) -> u16 {
+        SECTOR_SIZE as u16
+    }
+}
+
+/// Create the ramdisks requested at boot time: One with the content of the boot module `module` (if there is one)
+/// and an empty one for each `ramdisk=<size>` on the kernel command line.
+pub fn init(cmdline: Option<&str>, module: Option<(PhysFrameRange, usize)>) {
+    if let Some((frames, size)) = module {
+        let name = register(Ramdisk::from_frames(frames, size));
+        info!("Loaded boot module into ramdisk [{name}] ({size} bytes)");
+    }
+
+    let sizes = cmdline
+        .into_iter()
+        .flat_map(str::split_whitespace)
+        .filter_map(|arg| arg.strip_prefix("ramdisk="));
+    for size in sizes {
+        match parse_size(size) {
+            Some(size) if size > 0 => {
+                create(size);
+            }
+            _ => warn!("Invalid ramdisk size [{size}] on the kernel command line"),
+        }
+    }
+}
+
+/// Allocate an empty ramdisk with `size` bytes (rounded up to whole pages) and return its name.
+pub fn create(size: usize) -> String {
+    let frames = memory::alloc_frames(size.div_ceil(PAGE_SIZE));
+    let ramdisk = Ramdisk::from_frames(frames, frames.len() as usize * PAGE_SIZE);
+    ramdisk.data.write().fill(0);
+
+    let name = register(ramdisk);
+    info!("Created ramdisk [{name}] ({} bytes)", frames.len() as usize * PAGE_SIZE);
+    name
+}
+
+/// Parse a size in bytes with an optional binary suffix (K, M or G).
+pub fn parse_size(size: &str) -> Option<usize> {
+    let (number, shift) = match size.as_bytes().last()? {
+        b'K' | b'k' => (&size[..size.len() - 1], 10),
+        b'M' | b'm' => (&size[..size.len() - 1], 20),
+        b'G' | b'g' => (&size[..size.len() - 1], 30),
+        _ => (size, 0),
+    };
+
+    number.parse::<usize>().ok()?.checked_mul(1 << shift)
+}
+
+fn register(ramdisk: Ramdisk) -> String {
+    let stats = Arc::clone(&ramdisk.stats);
+    let name = add_block_device("ram", Arc::new(ramdisk));
+    stats::register(&name, stats);
+    name
+}
@@ -0,0 +1,209 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: event                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Event counters for delivering asynchronous kernel events to user space  ║
+   ║ (similar to Linux' eventfd). Each counter belongs to a process and is   ║
+   ║ subscribed to one source: Kernel subsystems call `notify()` (also from  ║
+   ║ interrupt handlers), which increments all counters subscribed to the    ║
+   ║ source. Timer counters are incremented for each expired interval.       ║
+   ║ Reading a counter returns its value and resets it, `poll()` blocks      ║
+   ║ until one of several counters is non-zero or a signal is pending.       ║
+   ║ Interrupt handlers can only try to wake up pollers, so a poller checks  ║
+   ║ its counters after `WAKEUP_TIMEOUT_MS` anyway. Each process may have at ║
+   ║ most `MAX_COUNTERS_PER_PROCESS` counters.                               ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - create               create a counter for the calling process       ║
+   ║   - signal               increment a counter of the calling process     ║
+   ║   - read                 get and reset a counter                        ║
+   ║   - poll                 wait until one of several counters is non-zero ║
+   ║   - close                destroy a counter                              ║
+   ║   - notify               signal all counters subscribed to a source     ║
+   ║   - wake_process         wake up the pollers of a process (for signals) ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use syscall::event::{EventSource, PollEntry, POLL_FOREVER};
+use syscall::return_vals::Errno;
+use crate::sync::irqsave_spinlock::IrqSaveSpinlock;
+use crate::sync::wait_queue::WaitQueue;
+use crate::{scheduler, timer};
+
+/// Longest time `poll()` blocks without checking the counters, in case a wakeup from an interrupt handler has been missed
+const WAKEUP_TIMEOUT_MS: usize = 100;
+/// Timer counters with a shorter interval are rejected
+const MIN_TIMER_INTERVAL_MS: usize = 1;
+/// Creating more counters in one process fails with `EAGAIN`
+const MAX_COUNTERS_PER_PROCESS: usize = 256;
+
+/// All counters by their handle. `notify()` may be called from interrupt handlers, so interrupts are disabled while locked.
+static COUNTERS: IrqSaveSpinlock<BTreeMap<usize, Arc<EventCounter>>> = IrqSaveSpinlock::new(BTreeMap::new());
+static NEXT_HANDLE: AtomicUsize = AtomicUsize::new(1);
+/// Threads blocked in `poll()`
+static POLLERS: WaitQueue = WaitQueue::new();
+
+struct EventCounter {
+    process_id: usize,
+    source: EventSource,
+    value: AtomicUsize,
+    /// Interval and next expiration of timer counters
+    timer: Option<(usize, AtomicUsize)>,
+}
+
+impl EventCounter {
+    fn add(&self, count: usize) {
+        // saturating, so a counter, that is never read, can't wrap around to zero
+        let _ = self.value.fetch_update(Ordering::AcqRel, Ordering::Acquire, |value| Some(value.saturating_add(count)));
+    }
+
+    /// Add the expirations of a timer counter since the last check.
+    fn update_timer(&self) {
+        let Some((interval, next)) = &self.timer else {
+            return;
+        };
+
+        let now = timer().systime_ms();
+        let due = next.load(Ordering::Acquire);
+        if now < due {
+            return;
+        }
+        let expirations = (now - due) / interval + 1;
+        if next.compare_exchange(due, due + expirations * interval, Ordering::AcqRel, Ordering::Acquire).is_ok() {
+            self.add(expirations);
+        }
+    }
+
+    fn is_ready(&self) -> bool {
+        self.update_timer();
+        self.value.load(Ordering::Acquire) > 0
+    }
+
+    /// The time, when a timer counter expires next (`None` for other counters)
+    fn next_expiration(&self) -> Option<usize> {
+        self.timer.as_ref().map(|(_, next)| next.load(Ordering::Acquire))
+    }
+}
+
+/// Create a counter for the calling process, that is incremented by `source`.
+/// `interval_ms` is only used for timers. Returns the handle of the counter.
+pub fn create(source: EventSource, interval_ms: usize) -> Result<usize, Errno> {
+    let timer = match source {
+        EventSource::Timer if interval_ms < MIN_TIMER_INTERVAL_MS => return Err(Errno::EINVAL),
+        EventSource::Timer => Some((interval_ms, AtomicUsize::new(timer().systime_ms() + interval_ms))),
+        _ => None,
+    };
+
+    let (process_id, _) = scheduler().current_ids();
+    let counter = Arc::new(EventCounter { process_id, source, value: AtomicUsize::new(0), timer });
+    let mut counters = COUNTERS.lock();
+    if counters.values().filter(|counter| counter.process_id == process_id).count() >= MAX_COUNTERS_PER_PROCESS {
+        return Err(Errno::EAGAIN);
+    }
+
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    counters.insert(handle, counter);
+    Ok(handle)
+}
+
+/// Increment the counter `handle` of the calling process by `count`.
+pub fn signal(handle: usize, count: usize) -> Result<(), Errno> {
+    own_counter(handle)?.add(count);
+    POLLERS.wake_all();
+    Ok(())
+}
+
+/// Get the value of the counter `handle` of the calling process and reset it to zero.
+pub fn read(handle: usize) -> Result<usize, Errno> {
+    let counter = own_counter(handle)?;
+    counter.update_timer();
+    Ok(counter.value.swap(0, Ordering::AcqRel))
+}
+
+/// Block until at least one of the counters in `entries` is non-zero, but at most `timeout_ms` milliseconds
+/// (`POLL_FOREVER` waits without a timeout). Marks the non-zero counters as ready and returns their number.
+/// Returns `EINTR`, if a signal is pending for the calling process before a counter is ready.
+pub fn poll(entries: &mut [PollEntry], timeout_ms: usize) -> Result<usize, Errno> {
+    let counters = entries.iter()
+        .map(|entry| own_counter(entry.handle))
+        .collect::<Result<Vec<_>, _>>()?;
+    let process = scheduler().current_thread().process();
+    let deadline = match timeout_ms {
+        POLL_FOREVER => usize::MAX,
+        _ => timer().systime_ms().saturating_add(timeout_ms),
+    };
+    let any_ready = || counters.iter().any(|counter| counter.is_ready());
+
+    loop {
+        if any_ready() {
+            break;
+        }
+        // a signal is delivered on the way back to user mode (and a killed process exits there)
+        if process.has_pending_signals() || process.is_killed() {
+            return Err(Errno::EINTR);
+        }
+        let now = timer().systime_ms();
+        if now >= deadline {
+            break;
+        }
+
+        // Nobody increments timer counters, so the poller wakes up at their next expiration
+        let wakeup = counters.iter()
+            .filter_map(|counter| counter.next_expiration())
+            .fold(deadline.min(now + WAKEUP_TIMEOUT_MS), usize::min);
+        POLLERS.wait_until_deadline(wakeup, || any_ready() || process.has_pending_signals() || process.is_killed());
+    }
+
+    let mut ready = 0;
+    for (entry, counter) in entries.iter_mut().zip(counters.iter()) {
+        entry.ready = counter.is_ready();
+        ready += entry.ready as usize;
+    }
+    Ok(ready)
+}
+
+/// Destroy the counter `handle` of the calling process.
+pub fn close(handle: usize) -> Result<(), Errno> {
+    let (process_id, _) = scheduler().current_ids();
+    let mut counters = COUNTERS.lock();
+    match counters.get(&handle) {
+        Some(counter) if counter.process_id == process_id => {
+            counters.remove(&handle);
+            Ok(())
+        }
+        _ => Err(Errno::EINVALH),
+    }
+}
+
+/// Destroy all counters of a process (when it exits).
+pub(crate) fn close_for_process(process_id: usize) {
+    COUNTERS.lock().retain(|_, counter| counter.process_id != process_id);
+    // its threads have exited in `poll()` without removing themselves from the queue
+    POLLERS.wake_process(process_id);
+}
+
+/// Signal all counters subscribed to `source` (may be called from interrupt handlers).
+pub fn notify(source: EventSource) {
+    for counter in COUNTERS.lock().values().filter(|counter| counter.source == source) {
+        counter.add(1);
+    }
+    POLLERS.wake_all_from_interrupt();
+}
+
+/// Wake up the threads of the process `process_id` blocked in `poll()`, so they return with `EINTR` (after a signal has been sent to it).
+pub fn wake_process(process_id: usize) {
+    POLLERS.wake_process(process_id);
+}
+
+fn own_counter(handle: usize) -> Result<Arc<EventCounter>, Errno> {
+    let (process_id, _) = scheduler().current_ids();
+    COUNTERS.lock()
+        .get(&handle)
+        .filter(|counter| counter.process_id == process_id)
+        .cloned()
+        .ok_or(Errno::EINVALH)
+}
@@ -0,0 +1,157 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: futex                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Fast user space mutexes (similar to Linux' futex): User space only      ║
+   ║ calls into the kernel, if it has to wait for a 32 bit value to change,  ║
+   ║ or if it has changed the value and there may be waiters. Waiters are    ║
+   ║ queued by the physical address of the value, so futexes also work in    ║
+   ║ shared memory. Wakeups may be spurious, so user space has to check the  ║
+   ║ value again after `wait` has returned.                                  ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - wait                 block, if the value still has the expected one ║
+   ║   - wake                 wake up threads waiting for a value            ║
+   ║   - wake_process         wake up all waiting threads of a process       ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::collections::{BTreeMap, VecDeque};
+use core::sync::atomic::{AtomicU32, Ordering};
+use syscall::futex::WAIT_FOREVER;
+use syscall::return_vals::Errno;
+use x86_64::VirtAddr;
+use x86_64::structures::idt::PageFaultErrorCode;
+use crate::consts::USER_SPACE_START;
+use crate::memory::vma::VmaType;
+use crate::sync::irqsave_spinlock::IrqSaveSpinlock;
+use crate::{process_manager, scheduler, timer};
+
+/// Waiting threads (pid, tid) by the physical address of the value they are waiting for
+static FUTEXES: IrqSaveSpinlock<BTreeMap<u64, VecDeque<(usize, usize)>>> = IrqSaveSpinlock::new(BTreeMap::new());
+
+/// Block the calling thread, if the value at `addr` is `expected`, until it is woken up by `wake()`,
+/// but at most `timeout_ms` milliseconds (`WAIT_FOREVER` waits without a timeout). \
+/// Fails with `EAGAIN`, if the value is different, with `ETIMEDOUT` after the timeout,
+/// and with `EINTR`, if a signal has been sent to the process.
+pub fn wait(addr: usize, expected: u32, timeout_ms: usize) -> Result<(), Errno> {
+    let key = key(addr)?;
+    let value = unsafe { &*(addr as *const AtomicU32) };
+    let deadline = timer().systime_ms().saturating_add(timeout_ms);
+
+    let ids = {
+        let mut futexes = FUTEXES.lock();
+        // checked with the waiters locked, so a wakeup after changing the value can't get lost
+        if value.load(Ordering::SeqCst) != expected {
+            return Err(Errno::EAGAIN);
+        }
+
+        // park before we are visible to wakers
+        let ids = scheduler().park_current();
+        futexes.entry(key).or_default().push_back(ids);
+        ids
+    };
+
+    // Returns immediately, if we have been woken up in the meantime
+    match timeout_ms {
+        WAIT_FOREVER => scheduler().block_if_allowed(),
+        _ => scheduler().block_if_allowed_until(deadline),
+    }
+
+    // `wake()` dequeues the threads it wakes up, so we are still queued after a timeout
+    let still_queued = remove_waiters(&mut FUTEXES.lock(), key, |waiter| waiter == ids) > 0;
+    if process_manager().read().current_process().has_pending_signals() {
+        return Err(Errno::EINTR);
+    }
+    if still_queued && timeout_ms != WAIT_FOREVER && timer().systime_ms() >= deadline {
+        return Err(Errno::ETIMEDOUT);
+    }
+
+    Ok(())
+}
+
+/// Wake up at most `count` threads waiting for the value at `addr` (`WAKE_ALL` for all of them).
+/// Returns the number of woken up threads.
+pub fn wake(addr: usize, count: usize) -> Result<usize, Errno> {
+    let key = key(addr)?;
+    let mut futexes = FUTEXES.lock();
+    let Some(waiters) = futexes.get_mut(&key) else {
+        return Ok(0);
+    };
+
+    let mut woke = 0;
+    while woke < count {
+        let Some((pid, tid)) = waiters.pop_front() else {
+            break;
+        };
+        if scheduler().unblock(pid, tid) {
+            woke += 1;
+        }
+        // else: stale waiter (killed/exited) -> keep going
+    }
+
+    if waiters.is_empty() {
+        futexes.remove(&key);
+    }
+    Ok(woke)
+}
+
+/// Wake up all waiting threads of the process `process_id` (e.g. because a signal has been sent to it).
+pub fn wake_process(process_id: usize) {
+    let mut futexes = FUTEXES.lock();
+    for waiters in futexes.values_mut() {
+        waiters.retain(|&(pid, tid)| {
+            if pid != process_id {
+                return true;
+            }
+            scheduler().unblock(pid, tid);
+            false
+        });
+    }
+    futexes.retain(|_, waiters| !waiters.is_empty());
+}
+
+/// Remove the waiters for `key`, for which `filter` returns true, and return their number.
+fn remove_waiters(futexes: &mut BTreeMap<u64, VecDeque<(usize, usize)>>, key: u64, mut filter: impl FnMut((usize, usize)) -> bool) -> usize {
+    let Some(waiters) = futexes.get_mut(&key) else {
+        return 0;
+    };
+
+    let before = waiters.len();
+    waiters.retain(|&waiter| !filter(waiter));
+    let removed = before - waiters.len();
+    if waiters.is_empty() {
+        futexes.remove(&key);
+    }
+    removed
+}
+
+/// Physical address of the value at `addr` in the current process.
+/// Demand paged memory (e.g. heap and stack pages) is only mapped on its first access, so it is mapped here, if it is not mapped yet.
+/// The page is not touched, because an invalid access (e.g. to a page mapped without read access) would panic in the kernel.
+fn key(addr: usize) -> Result<u64, Errno> {
+    if addr < USER_SPACE_START || addr % align_of::<AtomicU32>() != 0 {
+        return Err(Errno::EINVAL);
+    }
+
+    let process = process_manager().read().current_process();
+    let address_space = &process.virtual_address_space;
+    // Pages of shared libraries get a private frame on their first write, which would change the key
+    if address_space.is_address_within_vma(addr as u64, VmaType::SharedLibrary).is_some() {
+        address_space.copy_on_write(addr as u64);
+    }
+    if let Some(phys) = address_space.get_phys(addr as u64) {
+        return Ok(phys.as_u64());
+    }
+
+    // The same access as reading the value from user space (fails for pages, that may not be read)
+    match address_space.resolve_page_fault(VirtAddr::new(addr as u64), PageFaultErrorCode::USER_MODE) {
+        Ok(mapped) => {
+            if mapped {
+                process.usage().add_page_fault();
+            }
+            address_space.get_phys(addr as u64).map(|phys| phys.as_u64()).ok_or(Errno::EINVAL)
+        }
+        Err(_) => Err(Errno::EINVAL),
+    }
+}
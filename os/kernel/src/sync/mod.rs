@@ -1,2 +1,5 @@
 pub mod wait_queue;
-pub mod irqsave_spinlock;
\ No newline at end of file
+pub mod irqsave_spinlock;
+pub mod event;
+pub mod futex;
+pub mod pi_mutex;
\ No newline at end of file
@@ -0,0 +1,183 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: pi_mutex                                                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ A blocking mutex with priority inheritance for locks, that are held     ║
+   ║ for a long time (e.g. while smoltcp processes the packets of an         ║
+   ║ interface). A thread finding the mutex locked blocks instead of         ║
+   ║ spinning, and if it is more important than the owner, the owner runs    ║
+   ║ with the waiter's priority, until it has released all of its PiMutexes  ║
+   ║ (this may keep the priority slightly longer than needed, when nested    ║
+   ║ mutexes are released in a different order). Without inheritance, a      ║
+   ║ high priority thread would wait for a low priority owner, which itself  ║
+   ║ waits for the CPU behind all threads of medium priority. On unlock, the ║
+   ║ most important waiter is woken up. Inheritance is not transitive: If    ║
+   ║ the owner itself waits for another PiMutex, the owner of that one does  ║
+   ║ not get the priority of the first waiter.                               ║
+   ║                                                                         ║
+   ║ The mutex must not be used in interrupt handlers. Before the scheduler  ║
+   ║ runs, there is only one thread, so it is locked without an owner.       ║
+   ║ Waiting threads of killed processes keep blocking until they get the    ║
+   ║ mutex, since they may hold other locks (see                             ║
+   ║ 'Scheduler::block_holding_locks()').                                    ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - lock                 lock the mutex, blocking until it is free      ║
+   ║   - try_lock             lock the mutex, if it is free                  ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use syscall::priority::Priority;
+use crate::process::thread::Thread;
+use crate::scheduler;
+use crate::sync::irqsave_spinlock::IrqSaveSpinlock;
+
+pub struct PiMutex<T> {
+    state: IrqSaveSpinlock<PiState>,
+    value: UnsafeCell<T>,
+}
+
+// Safety: the value is only accessed through a guard, which exists only once at a time
+unsafe impl<T: Send> Send for PiMutex<T> {}
+unsafe impl<T: Send> Sync for PiMutex<T> {}
+
+struct PiState {
+    locked: bool,
+    /// The thread holding the mutex (`None` before the scheduler runs)
+    owner: Option<Arc<Thread>>,
+    /// (pid, tid, priority) of the blocked threads
+    waiters: Vec<(usize, usize, Priority)>,
+}
+
+pub struct PiMutexGuard<'a, T> {
+    mutex: &'a PiMutex<T>,
+}
+
+impl<T> PiMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: IrqSaveSpinlock::new(PiState { locked: false, owner: None, waiters: Vec::new() }),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Lock the mutex. If it is locked, the calling thread blocks (and lends its priority to the owner).
+    pub fn lock(&self) -> PiMutexGuard<'_, T> {
+        if !scheduler().is_initialized() {
+            let mut state = self.state.lock();
+            assert!(!state.locked, "PiMutex: Locked twice before the scheduler runs!");
+            state.locked = true;
+            return PiMutexGuard { mutex: self };
+        }
+
+        let current = scheduler().current_thread();
+        loop {
+            {
+                let mut state = self.state.lock();
+                if !state.locked {
+                    self.acquire(&mut state, current);
+                    return PiMutexGuard { mutex: self };
+                }
+
+                let priority = current.priority();
+                if let Some(owner) = state.owner.as_ref() {
+                    assert!(!Arc::ptr_eq(owner, &current), "PiMutex: Thread [{}] locked a mutex twice!", current.id());
+                    if priority < owner.priority() {
+                        scheduler().inherit_priority(owner, Some(priority));
+                    }
+                }
+
+                // park before we are visible to `unlock()`
+                let (pid, tid) = scheduler().park_current();
+                // we are still queued, if we have been woken up by someone else (e.g. because our process has been killed)
+                if !state.waiters.iter().any(|(waiter_pid, waiter_tid, _)| *waiter_pid == pid && *waiter_tid == tid) {
+                    state.waiters.push((pid, tid, priority));
+                }
+            }
+
+            // Returns immediately, if we have been woken up in the meantime.
+            // Another thread may have taken the mutex before us, so we check again.
+            scheduler().block_holding_locks();
+        }
+    }
+
+    /// Lock the mutex, if it is not locked.
+    pub fn try_lock(&self) -> Option<PiMutexGuard<'_, T>> {
+        let mut state = self.state.lock();
+        if state.locked {
+            return None;
+        }
+
+        if scheduler().is_initialized() {
+            self.acquire(&mut state, scheduler().current_thread());
+        } else {
+            state.locked = true;
+        }
+        Some(PiMutexGuard { mutex: self })
+    }
+
+    /// Make `owner` the owner of the unlocked mutex. Threads still waiting lend it their priority.
+    fn acquire(&self, state: &mut PiState, owner: Arc<Thread>) {
+        state.locked = true;
+        owner.add_pi_lock();
+        // a thread woken up by someone else than `unlock()` is still queued, but must not be woken up later
+        let (pid, tid) = (owner.process().id(), owner.id());
+        state.waiters.retain(|(waiter_pid, waiter_tid, _)| *waiter_pid != pid || *waiter_tid != tid);
+        if let Some(priority) = state.waiters.iter().map(|(_, _, priority)| *priority).min()
+            && priority < owner.priority()
+        {
+            scheduler().inherit_priority(&owner, Some(priority));
+        }
+        state.owner = Some(owner);
+    }
+
+    /// Unlock the mutex and wake up the most important waiter (the first one of them).
+    /// Returns true, if that one is more important than the calling thread.
+    fn unlock(&self) -> bool {
+        let mut state = self.state.lock();
+        state.locked = false;
+        let owner = state.owner.take();
+        if let Some(owner) = owner.as_ref()
+            && owner.remove_pi_lock() == 0
+            && owner.inherited_priority().is_some()
+        {
+            scheduler().inherit_priority(owner, None);
+        }
+
+        // skip stale waiters (killed/exited)
+        while let Some(index) = state.waiters.iter().enumerate().min_by_key(|(_, (_, _, priority))| *priority).map(|(index, _)| index) {
+            let (pid, tid, priority) = state.waiters.remove(index);
+            if scheduler().unblock(pid, tid) {
+                return owner.is_some_and(|owner| priority < owner.priority());
+            }
+        }
+        false
+    }
+}
+
+impl<T> Deref for PiMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for PiMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for PiMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // let the woken up thread run right away instead of at the next timer tick
+        if self.mutex.unlock() {
+            scheduler().switch_thread_no_interrupt();
+        }
+    }
+}
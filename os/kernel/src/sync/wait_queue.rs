@@ -1,21 +1,24 @@
 /* ╔═════════════════════════════════════════════════════════════════════════╗
    ║ Module: wait_queue                                                      ║
    ╟─────────────────────────────────────────────────────────────────────────╢
-   ║ Wait queues for blocking i/o.                                           ║
+   ║ Wait queues for blocking i/o. A thread waiting for a condition is       ║
+   ║ blocked in the scheduler and unblocked by `wake_one` or `wake_all`.     ║
    ║                                                                         ║
    ║ Public functions:                                                       ║
-   ║   - wait:       Blocks calling thread if the given predicate is true.   ║
-   ║   - notify_one: Deblocks one waiting thread (if any).                   ║
-   ║   - notify_all: Deblocks all waiting threads (if any).                  ║
+   ║   - wait_until: Blocks calling thread until the given condition holds.  ║
+   ║   - wait_until_deadline: Same, but gives up at the given time.          ║
+   ║   - wake_one:   Deblocks one waiting thread (if any).                   ║
+   ║   - wake_all:   Deblocks all waiting threads (if any).                  ║
+   ║   - wake_all_from_interrupt: Same, but safe in interrupt handlers.      ║
+   ║   - wake_process: Deblocks all waiting threads of a process.            ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Michael Schoettner, Univ. Duesseldorf, 16.02.2026               ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
 
 use alloc::collections::VecDeque;
-use log::info;
 
-use crate::scheduler;
+use crate::{scheduler, timer};
 use crate::sync::irqsave_spinlock::IrqSaveSpinlock;
 
 pub struct WaitQueue {
@@ -23,76 +26,93 @@ pub struct WaitQueue {
 }
 
 impl WaitQueue {
-    pub fn new() -> WaitQueue {
+    pub const fn new() -> WaitQueue {
         WaitQueue {
-            queue: IrqSaveSpinlock::new(VecDeque::<(usize, usize)>::new()),
+            queue: IrqSaveSpinlock::new(VecDeque::new()),
         }
     }
 
-    /// Block until `pred()` becomes true.
-    pub fn wait<F>(&self, mut pred: F, message: &str)
+    /// Block until `cond()` becomes true. \
+    /// Whoever makes the condition true must call `wake_one` or `wake_all` afterwards.
+    /// The condition is evaluated with the queue locked (interrupts disabled),
+    /// so it must not block or take locks that are held while waking up.
+    pub fn wait_until<F>(&self, mut cond: F)
     where
         F: FnMut() -> bool,
     {
-        //        info!("WaitQueue::wait");
-        let (pid, tid) = scheduler().current_ids();
-
         loop {
-            if pred() {
+            if cond() {
                 return;
             }
 
             {
                 let mut guard = self.queue.lock();
 
-                // re-check under lock
-                if pred() {
+                // re-check under lock, a wakeup cannot get lost from here on
+                if cond() {
                     return;
                 }
 
-                guard.push_back((pid, tid));
-
-                // park after we are visible to notifiers
-                scheduler().park_current();
+                // park before we are visible to wakers
+                guard.push_back(scheduler().park_current());
             }
 
-            scheduler().yield_now(); 
+            // Returns immediately, if we have been woken up in the meantime.
+            // Wakeups may be spurious, so we loop and check the condition again.
+            scheduler().block_if_allowed();
         }
+    }
 
-        info!("WaitQueue::wait: Thread with PID={}, TID={} is now waiting, message = {}", pid, tid, message);
-
-        // Check predicate without acquiring the queue lock.
-        // The scheduler will block us, as long as no `notify_one` and `notify_all` have arrived
-        // But even after waking up we need to check for spurious wakeups, so we loop here.
+    /// Like `wait_until`, but gives up, when the system time reaches `deadline` (in ms).
+    /// Returns whether `cond()` holds.
+    pub fn wait_until_deadline<F>(&self, deadline: usize, mut cond: F) -> bool
+    where
+        F: FnMut() -> bool,
+    {
         loop {
-            if pred() {
-                return;
+            if cond() {
+                return true;
             }
-            core::hint::spin_loop();
+
+            let waiter = {
+                let mut guard = self.queue.lock();
+
+                if cond() {
+                    return true;
+                }
+                if timer().systime_ms() >= deadline {
+                    return false;
+                }
+
+                let waiter = scheduler().park_current();
+                guard.push_back(waiter);
+                waiter
+            };
+
+            scheduler().block_if_allowed_until(deadline);
+
+            // Remove our entry after a timeout, so `wake_one` doesn't spend its wakeup on us.
+            self.queue.lock().retain(|entry| *entry != waiter);
         }
     }
 
     /// Wake up exactly one waiter (if any). Returns true if someone was woken up.
-    pub fn notify_one(&self) -> bool {
-        //  info!("WaitQueue::notify_one");
-
+    pub fn wake_one(&self) -> bool {
         let mut guard = self.queue.lock();
 
         while let Some((pid, tid)) = guard.pop_front() {
             if scheduler().unblock(pid, tid) {
-                // info!("WaitQueue::notify_one: found a waiter");
                 return true;
             }
             // else: stale waiter (killed/exited) -> keep going
         }
-        //    info!("WaitQueue::notify_one: no waiter found");
 
         false
     }
 
     /// Wake up all waiters currently queued.
     /// Returns the number of threads actually unblocked (stale entries are ignored).
-    pub fn notify_all(&self) -> usize {
+    pub fn wake_all(&self) -> usize {
         let mut guard = self.queue.lock();
         let mut woke = 0;
 
@@ -105,4 +125,22 @@ impl WaitQueue {
 
         woke
     }
+
+    /// Wake up all waiters of the process `process_id` (e.g. because a signal has been sent to it).
+    pub fn wake_process(&self, process_id: usize) {
+        self.queue.lock().retain(|&(pid, tid)| {
+            if pid != process_id {
+                return true;
+            }
+            scheduler().unblock(pid, tid);
+            false
+        });
+    }
+
+    /// Like `wake_all`, but safe to call from interrupt handlers, since the scheduler's locks are only tried
+    /// (see `Scheduler::try_wake_early()`): Only waiters blocked in `wait_until_deadline` are woken up
+    /// (at the next timer tick), the others stay queued and wake up at their deadline at the latest.
+    pub fn wake_all_from_interrupt(&self) {
+        self.queue.lock().retain(|&(pid, tid)| !scheduler().try_wake_early(pid, tid));
+    }
 }
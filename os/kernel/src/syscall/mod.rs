@@ -18,6 +18,10 @@ pub mod sys_input;
 pub mod sys_system_info;
 pub mod sys_logger;
 pub mod sys_shm;
+pub mod sys_random;
+pub mod sys_event;
+pub mod sys_audio;
+pub mod sys_storage;
 
 
 pub mod syscall_dispatcher;
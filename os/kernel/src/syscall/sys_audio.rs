@@ -0,0 +1,45 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: sys_audio                                                       ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Systemcall for playing PCM samples through the mixer.           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::slice::from_raw_parts;
+use syscall::audio::CHANNELS;
+use syscall::return_vals::Errno;
+use syscall::sandbox::Capabilities;
+use crate::device::mixer;
+use crate::process::sandbox::check_capability;
+use crate::{process_manager, scheduler};
+
+/// How long to wait for the queue of the process to drain, if it is full
+const RETRY_INTERVAL_MS: usize = 10;
+
+/// SystemCall implementation for SystemCall::AudioWrite.
+/// Queues `count` samples (interleaved stereo, see `syscall::audio`) for playback and blocks, until all of them have been queued.
+/// Fails with `ENOTSUP`, if there is no audio device. Needs the `DEVICES` capability.
+pub extern "sysv64" fn sys_audio_write(samples: *const i16, count: usize) -> isize {
+    if let Err(errno) = check_capability(Capabilities::DEVICES) {
+        return errno.into();
+    }
+    if samples.is_null() || !samples.is_aligned() || count % CHANNELS != 0 {
+        return Errno::EINVAL.into();
+    }
+    if !mixer::available() {
+        return Errno::ENOTSUP.into();
+    }
+
+    let samples = unsafe { from_raw_parts(samples, count) };
+    let process = process_manager().read().current_process().id();
+    let mut queued = 0;
+    while queued < samples.len() {
+        queued += mixer::submit(process, &samples[queued..]);
+        if queued < samples.len() {
+            scheduler().sleep(RETRY_INTERVAL_MS);
+        }
+    }
+
+    queued as isize
+}
@@ -6,23 +6,43 @@
    ║ Author: Fabian Ruhland, 04.01.2026, HHU                                 ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
-use crate::process::thread::{ProcessLoadError, Thread};
+use crate::consts::MAX_USER_STACK_SIZE;
+use crate::memory::PAGE_SIZE;
+use crate::naming::api;
+use crate::process::process::Process;
+use crate::process::sandbox::{self, check_capability, Sandbox};
+use crate::process::signal;
+use crate::process::thread::{Thread, ThreadState};
+use crate::sync::futex;
 use crate::{process_manager, scheduler};
 use alloc::format;
 use alloc::slice;
+use alloc::string::ToString;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::ptr::slice_from_raw_parts;
 use core::str::from_utf8;
+use log::info;
+use syscall::priority::{Priority, SchedulingClass};
+use syscall::process_info::{ThreadInfo, ThreadStatus, NO_CORE};
 use syscall::return_vals::{self, Errno};
+use syscall::sandbox::{Capabilities, SandboxConfig, KEEP_ID, ROOT_ID};
+use syscall::signal::Signal;
+use syscall::spawn::{SpawnConfig, STDIO_INHERIT, STDIO_TERMINAL};
+use syscall::usage::{ResourceUsage, USAGE_SELF};
 use x86_64::VirtAddr;
 
 pub extern "sysv64" fn sys_process_id() -> isize {
     process_manager().read().current_process().id() as isize
 }
 
-pub extern "sysv64" fn sys_process_exit() -> ! {
-    scheduler().current_thread().process().exit();
+pub extern "sysv64" fn sys_process_exit(code: isize) -> ! {
+    let process = scheduler().current_thread().process();
+    // Exit codes aren't passed to the parent yet
+    if code != 0 {
+        info!("Process [{}] exited with code [{}]", process.id(), code);
+    }
+    process.exit();
     scheduler().exit();
 }
 
@@ -41,8 +61,42 @@ pub extern "sysv64" fn sys_process_status(buffer: *mut u8, buffer_length: usize)
     return_vals::convert_syscall_result_to_ret_code(scheduler().get_status(buf))
 }
 
-pub extern "sysv64" fn sys_thread_create(kickoff_addr: u64, entry: extern "sysv64" fn()) -> isize {
-    let thread = Thread::new_user_thread(process_manager().read().current_process(), VirtAddr::new(kickoff_addr), entry);
+/// Fill `threads` with a snapshot of at most `count` threads and their processes (`count` = 0 only counts them).
+/// Returns the number of all threads, which may be more than `count`.
+pub extern "sysv64" fn sys_process_list(threads: *mut ThreadInfo, count: usize) -> isize {
+    if threads.is_null() && count > 0 {
+        return Errno::EINVAL.into();
+    }
+
+    let snapshot = scheduler().threads();
+    if count > 0 {
+        let infos = unsafe { slice::from_raw_parts_mut(threads, count) };
+        for (info, thread) in infos.iter_mut().zip(snapshot.iter()) {
+            *info = thread_info(thread);
+        }
+    }
+
+    snapshot.len() as isize
+}
+
+/// Create a thread in the calling process, which calls `kickoff_addr(entry, arg)` in user mode.
+/// Its user stack has `stack_size` bytes (rounded up to pages, 0 = `MAX_USER_STACK_SIZE`) and is freed, when the thread exits.
+pub extern "sysv64" fn sys_thread_create(kickoff_addr: u64, entry: extern "sysv64" fn(), arg: usize, stack_size: usize) -> isize {
+    let stack_size = match stack_size {
+        0 => MAX_USER_STACK_SIZE,
+        size if size > MAX_USER_STACK_SIZE => return Errno::EINVAL.into(),
+        size => size.next_multiple_of(PAGE_SIZE),
+    };
+
+    let process = process_manager().read().current_process();
+    let max_threads = process.sandbox().max_threads();
+    if max_threads != 0 && process.thread_ids().len() >= max_threads {
+        return Errno::EAGAIN.into();
+    }
+
+    let Some(thread) = Thread::new_user_thread_with_stack(process, VirtAddr::new(kickoff_addr), entry, arg, stack_size) else {
+        return Errno::ENOMEM.into();
+    };
     let id = thread.id();
 
     scheduler().ready(thread);
@@ -73,6 +127,10 @@ pub extern "sysv64" fn sys_thread_kill(id: usize) -> isize {
 }
 
 pub extern "sysv64" fn sys_thread_exit() -> ! {
+    let thread = scheduler().current_thread();
+    thread.free_user_stack();
+    thread.free_tls();
+    drop(thread); // exit() does not return
     scheduler().exit();
 }
 
@@ -80,16 +138,348 @@ pub extern "sysv64" fn sys_thread_count() -> isize {
     scheduler().active_thread_ids().len() as isize
 }
 
+/// Set the priority and scheduling class of the thread `id` (0 = calling thread).
+/// Only root may raise a priority above `Normal`, select the strict class or change threads of other processes.
+pub extern "sysv64" fn sys_thread_set_priority(id: usize, priority: usize, class: usize) -> isize {
+    let (Ok(priority), Ok(class)) = (Priority::try_from(priority), SchedulingClass::try_from(class)) else {
+        return Errno::EINVAL.into();
+    };
+    let thread = match id {
+        0 => scheduler().current_thread(),
+        id => match scheduler().thread(id) {
+            Some(thread) => thread,
+            None => return Errno::ESRCH.into(),
+        },
+    };
+    if let Err(errno) = check_priority_change(&thread.process(), priority) {
+        return errno.into();
+    }
+    if class == SchedulingClass::Strict && sandbox::credentials().0 != ROOT_ID {
+        return Errno::EACCES.into();
+    }
+
+    scheduler().set_priority(&thread, priority, class);
+    0
+}
+
+/// Get the priority of the thread `id` (0 = calling thread) and write its scheduling class to `class` (if not null).
+pub extern "sysv64" fn sys_thread_get_priority(id: usize, class: *mut usize) -> isize {
+    let thread = match id {
+        0 => scheduler().current_thread(),
+        id => match scheduler().thread(id) {
+            Some(thread) => thread,
+            None => return Errno::ESRCH.into(),
+        },
+    };
+    if !class.is_null() {
+        unsafe { class.write(thread.base_scheduling_class().into()) };
+    }
+    usize::from(thread.base_priority()) as isize
+}
+
+/// Restrict the thread `id` (0 = calling thread) to the cores in `affinity` (bit n = core with local APIC id n).
+/// Only root may change threads of other processes.
+pub extern "sysv64" fn sys_thread_set_affinity(id: usize, affinity: u64) -> isize {
+    let thread = match id {
+        0 => scheduler().current_thread(),
+        id => match scheduler().thread(id) {
+            Some(thread) => thread,
+            None => return Errno::ESRCH.into(),
+        },
+    };
+    let own = thread.process().id() == process_manager().read().current_process().id();
+    if !own && sandbox::credentials().0 != ROOT_ID {
+        return Errno::EACCES.into();
+    }
+
+    match scheduler().set_affinity(&thread, affinity) {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Write the affinity of the thread `id` (0 = calling thread) to `affinity`
+/// (it does not fit into the return value, if the highest bit is set).
+pub extern "sysv64" fn sys_thread_get_affinity(id: usize, affinity: *mut u64) -> isize {
+    if affinity.is_null() {
+        return Errno::EINVAL.into();
+    }
+    let thread = match id {
+        0 => scheduler().current_thread(),
+        id => match scheduler().thread(id) {
+            Some(thread) => thread,
+            None => return Errno::ESRCH.into(),
+        },
+    };
+
+    unsafe { affinity.write(thread.affinity()) };
+    0
+}
+
+/// Set the priority of all threads of the process `id` (0 = calling process).
+/// Threads created by the process afterwards inherit this priority.
+pub extern "sysv64" fn sys_process_set_priority(id: usize, priority: usize) -> isize {
+    let Ok(priority) = Priority::try_from(priority) else {
+        return Errno::EINVAL.into();
+    };
+    let process = match id {
+        0 => process_manager().read().current_process(),
+        id => match process_manager().read().process(id) {
+            Some(process) => process,
+            None => return Errno::ESRCH.into(),
+        },
+    };
+    if let Err(errno) = check_priority_change(&process, priority) {
+        return errno.into();
+    }
+
+    scheduler().set_process_priority(&process, priority);
+    0
+}
+
+/// Send the signal `signal` to the process `id` (0 = calling process).
+/// Only root may send signals to processes of other users.
+pub extern "sysv64" fn sys_process_kill(id: usize, signal: usize) -> isize {
+    let Ok(signal) = Signal::try_from(signal) else {
+        return Errno::EINVAL.into();
+    };
+    let process = match id {
+        0 => process_manager().read().current_process(),
+        id => match process_manager().read().process(id) {
+            Some(process) => process,
+            None => return Errno::ESRCH.into(),
+        },
+    };
+    let uid = sandbox::credentials().0;
+    if uid != ROOT_ID && uid != process.sandbox().uid() {
+        return Errno::EACCES.into();
+    }
+
+    match signal::send(&process, signal) {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
+}
+
+fn check_priority_change(target: &Process, priority: Priority) -> Result<(), Errno> {
+    let is_root = sandbox::credentials().0 == ROOT_ID;
+    let own = target.id() == process_manager().read().current_process().id();
+    if is_root || (own && priority >= Priority::Normal) {
+        Ok(())
+    } else {
+        Err(Errno::EACCES)
+    }
+}
+
 pub unsafe extern "sysv64" fn sys_process_execute_binary(name_buffer: *const u8, name_length: usize, args: *const Vec<&str>) -> isize {
+    if let Err(errno) = check_capability(Capabilities::SPAWN) {
+        return errno.into();
+    }
+    let app_name = from_utf8(unsafe { slice_from_raw_parts(name_buffer, name_length).as_ref().unwrap() }).unwrap();
+    execute_binary(app_name, unsafe { args.as_ref().unwrap() }, None)
+}
+
+/// Like `sys_process_execute_binary()`, but the new process is restricted by the sandbox described in `config`.
+/// The sandbox is combined with the one of the calling process, so it can't be used to gain capabilities.
+pub unsafe extern "sysv64" fn sys_process_execute_sandboxed(name_buffer: *const u8, name_length: usize, args: *const Vec<&str>, config: *const SandboxConfig) -> isize {
+    if let Err(errno) = check_capability(Capabilities::SPAWN) {
+        return errno.into();
+    }
+    let Some(config) = (unsafe { config.as_ref() }) else {
+        return Errno::EINVAL.into();
+    };
     let app_name = from_utf8(unsafe { slice_from_raw_parts(name_buffer, name_length).as_ref().unwrap() }).unwrap();
-    let path = format!("bin/{}", app_name);
 
-    match Thread::load_application(&path, app_name, unsafe { args.as_ref().unwrap() }) {
+    let root = if config.root.is_null() {
+        None
+    } else {
+        match from_utf8(unsafe { slice::from_raw_parts(config.root, config.root_len) }) {
+            Ok(root) => Some(root),
+            Err(_) => return Errno::EBADSTR.into(),
+        }
+    };
+    if root.is_some_and(|root| !api::is_dir(root)) {
+        return Errno::ENOTDIR.into();
+    }
+
+    let parent = process_manager().read().current_process().sandbox();
+    let capabilities = Capabilities::from_bits_truncate(config.capabilities);
+    let uid = (config.uid != KEEP_ID).then_some(config.uid);
+    let gid = (config.gid != KEEP_ID).then_some(config.gid);
+    match parent.restrict(root, capabilities, config.max_threads, config.max_memory, uid, gid) {
+        Ok(sandbox) => execute_binary(app_name, unsafe { args.as_ref().unwrap() }, Some(sandbox)),
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Write the resource usage of the calling process (`who` = `USAGE_SELF`) or of an exited child to `usage`.
+/// A child is identified by the id of its first thread and its usage can only be retrieved once (and only by its parent).
+pub extern "sysv64" fn sys_process_usage(who: usize, usage: *mut ResourceUsage) -> isize {
+    let Some(usage) = (unsafe { usage.as_mut() }) else {
+        return Errno::EINVAL.into();
+    };
+
+    let result = if who == USAGE_SELF {
+        Some(process_manager().read().current_process().resource_usage())
+    } else {
+        let parent = process_manager().read().current_process().id();
+        process_manager().write().take_exited_usage(who, parent)
+    };
+    match result {
+        Some(result) => {
+            *usage = result;
+            0
+        }
+        None => Errno::ESRCH.into(),
+    }
+}
+
+fn thread_info(thread: &Thread) -> ThreadInfo {
+    let process = thread.process();
+    let usage = process.resource_usage();
+    let status = match thread.state() {
+        ThreadState::Created => ThreadStatus::Created,
+        ThreadState::Ready => ThreadStatus::Ready,
+        ThreadState::Running => ThreadStatus::Running,
+        ThreadState::Parking | ThreadState::Blocked => ThreadStatus::Blocked,
+        ThreadState::Sleeping => ThreadStatus::Sleeping,
+        ThreadState::Exited => ThreadStatus::Exited,
+    };
+
+    let (user_time_ns, system_time_ns) = thread.usage().cpu_time_ns();
+    let (voluntary_switches, involuntary_switches) = thread.usage().context_switches();
+    let (dispatches, run_delay_ns, max_run_delay_ns) = thread.usage().run_delay_ns();
+    let mut info = ThreadInfo {
+        pid: process.id(),
+        tid: thread.id(),
+        status,
+        priority: thread.priority(),
+        class: thread.scheduling_class(),
+        core: thread.core().unwrap_or(NO_CORE),
+        memory_kib: usage.max_rss_kib,
+        user_time_us: user_time_ns / 1000,
+        system_time_us: system_time_ns / 1000,
+        voluntary_switches,
+        involuntary_switches,
+        dispatches,
+        run_delay_us: run_delay_ns / 1000,
+        max_run_delay_us: max_run_delay_ns / 1000,
+        ..ThreadInfo::default()
+    };
+    info.set_name(&process.name());
+    info
+}
+
+fn execute_binary(app_name: &str, args: &Vec<&str>, sandbox: Option<Sandbox>) -> isize {
+    let path = format!("/bin/{}", app_name);
+
+    match Thread::load_application(&path, app_name, args) {
         Ok(thread) => {
+            // the process has not been started yet, so it can't do anything outside the sandbox
+            if let Some(sandbox) = sandbox {
+                thread.process().set_sandbox(sandbox);
+            }
+            thread.process().set_main_thread(thread.id());
             scheduler().ready(Arc::clone(&thread));
             thread.id() as isize
         }
-        Err(ProcessLoadError::NotFound) => Errno::ENOENT.into(),
-        Err(ProcessLoadError::ElfInvalid) => Errno::EBADF.into(),
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+/// Start the program described by `config` and return the id of the new process.
+/// Handles passed as standard input or output are shared with the new process, so the caller may close them afterwards.
+pub unsafe extern "sysv64" fn sys_process_spawn(config: *const SpawnConfig) -> isize {
+    if let Err(errno) = check_capability(Capabilities::SPAWN) {
+        return errno.into();
+    }
+    let Some(config) = (unsafe { config.as_ref() }) else {
+        return Errno::EINVAL.into();
+    };
+    if config.path.is_null() || (config.args.is_null() && config.args_len > 0) || (config.env.is_null() && config.env_len > 0) {
+        return Errno::EINVAL.into();
+    }
+    let Ok(path) = from_utf8(unsafe { slice::from_raw_parts(config.path, config.path_len) }) else {
+        return Errno::EBADSTR.into();
+    };
+    let args = match config.args_len {
+        0 => &[][..],
+        len => unsafe { slice::from_raw_parts(config.args, len) },
+    };
+    let env = match config.env_len {
+        0 => &[][..],
+        len => unsafe { slice::from_raw_parts(config.env, len) },
+    };
+
+    // programs without a path are looked up in /bin (like with `sys_process_execute_binary()`)
+    let path = match path.contains('/') {
+        true => path.to_string(),
+        false => format!("/bin/{}", path),
+    };
+    let name = path.rsplit('/').next().unwrap_or(&path);
+
+    // look at the handles first, so we don't have to get rid of the process, if they are invalid
+    let parent = process_manager().read().current_process();
+    let stdin = match config.stdin {
+        STDIO_INHERIT => parent.stdin(),
+        STDIO_TERMINAL => None,
+        handle => Some(handle),
+    };
+    let stdout = match config.stdout {
+        STDIO_INHERIT => parent.stdout(),
+        STDIO_TERMINAL => None,
+        handle => Some(handle),
+    };
+    for handle in [stdin, stdout].into_iter().flatten() {
+        if let Err(errno) = api::check_handle(handle) {
+            return errno.into();
+        }
+    }
+
+    let thread = match Thread::load_application_with_env(&path, name, args, env) {
+        Ok(thread) => thread,
+        Err(error) => return Errno::from(error).into(),
+    };
+    let process = thread.process();
+    for handle in [stdin, stdout].into_iter().flatten() {
+        api::share(handle, process.id()).expect("Failed to share standard input or output");
+    }
+    process.set_stdio(stdin, stdout);
+    process.set_main_thread(thread.id());
+    scheduler().ready(thread);
+
+    process.id() as isize
+}
+
+/// Wait until the process `id` has exited.
+/// Fails with `ESRCH`, if there is no such process (e.g. because it has already exited).
+pub extern "sysv64" fn sys_process_wait(id: usize) -> isize {
+    let Some(process) = process_manager().read().process(id) else {
+        return Errno::ESRCH.into();
+    };
+    let main_thread = process.main_thread();
+    drop(process);
+
+    // the process ends with its main thread (like when returning from `main()`)
+    match scheduler().join(main_thread) {
+        Ok(_) | Err(Errno::ESRCH) => 0,
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Block the calling thread, while the 32 bit value at `addr` is `expected`,
+/// but at most `timeout_ms` milliseconds (see `sync::futex::wait()`).
+pub extern "sysv64" fn sys_futex_wait(addr: usize, expected: usize, timeout_ms: usize) -> isize {
+    match futex::wait(addr, expected as u32, timeout_ms) {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Wake up at most `count` threads waiting for the value at `addr` and return their number.
+pub extern "sysv64" fn sys_futex_wake(addr: usize, count: usize) -> isize {
+    match futex::wake(addr, count) {
+        Ok(woke) => woke as isize,
+        Err(errno) => errno.into(),
     }
 }
@@ -0,0 +1,60 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: sys_event                                                       ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: All system calls related to event counters.                     ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::slice;
+use syscall::event::{EventSource, PollEntry};
+use syscall::return_vals::Errno;
+use crate::sync::event;
+
+/// Create an event counter for `source` (see `syscall::event::EventSource`) and return its handle.
+/// `interval_ms` is the interval of timer counters and ignored otherwise.
+pub extern "sysv64" fn sys_event_create(source: usize, interval_ms: usize) -> isize {
+    let Ok(source) = EventSource::try_from(source) else {
+        return Errno::EINVAL.into();
+    };
+    match event::create(source, interval_ms) {
+        Ok(handle) => handle as isize,
+        Err(errno) => errno.into(),
+    }
+}
+
+pub extern "sysv64" fn sys_event_signal(handle: usize, count: usize) -> isize {
+    match event::signal(handle, count) {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Return the value of an event counter (saturated to `isize::MAX`) and reset it.
+pub extern "sysv64" fn sys_event_read(handle: usize) -> isize {
+    match event::read(handle) {
+        Ok(value) => value.min(isize::MAX as usize) as isize,
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Wait until one of the `count` counters at `entries` is non-zero (at most `timeout_ms` milliseconds).
+/// Returns the number of non-zero counters, which are marked as ready.
+pub extern "sysv64" fn sys_event_poll(entries: *mut PollEntry, count: usize, timeout_ms: usize) -> isize {
+    if entries.is_null() && count > 0 {
+        return Errno::EINVAL.into();
+    }
+
+    let entries = if count > 0 { unsafe { slice::from_raw_parts_mut(entries, count) } } else { &mut [] };
+    match event::poll(entries, timeout_ms) {
+        Ok(ready) => ready as isize,
+        Err(errno) => errno.into(),
+    }
+}
+
+pub extern "sysv64" fn sys_event_close(handle: usize) -> isize {
+    match event::close(handle) {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
+}
@@ -1,6 +1,12 @@
+use core::slice::from_raw_parts_mut;
 use crate::buffered_lfb;
+use crate::device::display;
+use crate::process::sandbox::check_capability;
 use drawer::{drawer::DrawerCommand, rect_data::RectData};
 use graphic::color::BLACK;
+use syscall::display::DisplayMode;
+use syscall::return_vals::Errno;
+use syscall::sandbox::Capabilities;
 
 pub extern "sysv64" fn sys_write_graphic(command_ptr: *const DrawerCommand) {
     let enum_val = unsafe { command_ptr.as_ref().unwrap() };
@@ -189,3 +195,50 @@ pub struct LfbInfo {
     pub height: u32,
     pub bpp: u8,
 }
+
+/// SystemCall implementation for SystemCall::DisplayModes.
+/// Copies up to `count` modes, that the display driver can switch to, to `modes` and returns the number of all modes
+/// (0, if the mode can't be changed).
+pub extern "sysv64" fn sys_display_modes(modes: *mut DisplayMode, count: usize) -> isize {
+    if modes.is_null() && count > 0 {
+        return Errno::EINVAL.into();
+    }
+
+    let available = display::modes();
+    if count > 0 {
+        let modes = unsafe { from_raw_parts_mut(modes, count) };
+        for (mode, available) in modes.iter_mut().zip(available.iter()) {
+            *mode = *available;
+        }
+    }
+
+    available.len() as isize
+}
+
+/// SystemCall implementation for SystemCall::DisplayModeGet.
+/// Writes the mode of the current framebuffer to `mode`.
+pub extern "sysv64" fn sys_display_mode_get(mode: *mut DisplayMode) -> isize {
+    let Some(mode) = (unsafe { mode.as_mut() }) else {
+        return Errno::EINVAL.into();
+    };
+
+    *mode = display::current_mode();
+    0
+}
+
+/// SystemCall implementation for SystemCall::DisplayModeSet.
+/// Switches to `mode` (one of the modes returned by `DisplayModes`). Applications, that have mapped the framebuffer,
+/// need to map it again. Needs the `DEVICES` capability.
+pub extern "sysv64" fn sys_display_mode_set(mode: *const DisplayMode) -> isize {
+    if let Err(errno) = check_capability(Capabilities::DEVICES) {
+        return errno.into();
+    }
+    let Some(mode) = (unsafe { mode.as_ref() }) else {
+        return Errno::EINVAL.into();
+    };
+
+    match display::set_mode(*mode) {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
+}
@@ -1,7 +1,10 @@
-use input::ReadKeyboardOption;
+use input::{MouseEvent, ReadKeyboardOption};
 use stream::{event_to_u16, DecodedInputStream, RawInputStream};
+use syscall::return_vals::Errno;
 
-use crate::{keyboard, mouse};
+use crate::device::{keyboard_wait, pointer};
+use crate::device::virtio::{virtio_console, virtio_keyboard};
+use crate::{keyboard, mouse, scheduler};
 
 pub extern "sysv64" fn sys_read_mouse() -> usize {
     match mouse() {
@@ -10,39 +13,47 @@ pub extern "sysv64" fn sys_read_mouse() -> usize {
     }
 }
 
+/// SystemCall implementation for SystemCall::MouseReadEvent.
+/// Reads the next event of any mouse or tablet (PS/2 or virtio). Returns 1, if an event has been written to `event`, and 0 otherwise.
+pub extern "sysv64" fn sys_read_mouse_event(event: *mut MouseEvent) -> isize {
+    if event.is_null() {
+        return Errno::EINVAL.into();
+    }
+    match pointer::read() {
+        Some(mouse_event) => {
+            unsafe { event.write(mouse_event) };
+            1
+        }
+        None => 0,
+    }
+}
+
 /// SystemCall implementation for SystemCall::KeyboardRead.
 /// Reads from keyboard with given mode (Raw or Decoded).
+/// The PS/2 keyboard, the virtio keyboard and (only decoded) the virtio console are all used as input sources.
 pub extern "sysv64" fn sys_read_keyboard(option: ReadKeyboardOption, blocking: bool) -> isize {
-    // if VIRTIO_INPUT_PENDING.load(Ordering::Acquire) {
-    //     if let Some(input_dev_mutex) = virtio_input() {
-    //         if let Some(mut input_dev) = input_dev_mutex.try_lock() {
-    //             let mut event_processed = false;
-    //             while let Some(event) = input_dev.pop_pending_event(){
-    //                 event_processed = true;
-    //                 if event.event_type == 1 && event.value == 1 {
-    //                     info!("VirtIO Input Event (from Terminal): type={}, code={}, value={}", event.event_type, event.code, event.value);
-    //                 }
-    //             }
-    //         }
-    //     }
-    // }
-    let keyboard = keyboard().expect("Failed to read from keyboard!");
+    let ps2_keyboard = keyboard();
+    let virtio_keyboard = virtio_keyboard();
+    let console = virtio_console();
 
-    match option {
-        ReadKeyboardOption::Raw => {
-            let event = if blocking {
-                Some(keyboard.read_event())
-            } else {
-                keyboard.read_event_nb()
-            };
-            if let Some(event) = event {
-                event_to_u16(event).try_into().unwrap()
-            } else { 0 }
-        },
-        ReadKeyboardOption::Decode => (if blocking {
-            keyboard.decoded_read_byte()
-        } else {
-            keyboard.decoded_try_read_byte().unwrap_or_default()
-        } as isize),
+    loop {
+        // taken before reading, so input arriving in between ends the wait right away
+        let seen = keyboard_wait::epoch();
+        let value = match option {
+            ReadKeyboardOption::Raw => virtio_keyboard.as_ref().and_then(|keyboard| keyboard.read_event_nb())
+                .or_else(|| ps2_keyboard.as_ref().and_then(|keyboard| keyboard.read_event_nb()))
+                .map(|event| event_to_u16(event) as isize),
+            ReadKeyboardOption::Decode => console.as_ref().and_then(|console| console.try_read_byte()).map(|byte| byte as isize)
+                .or_else(|| virtio_keyboard.as_ref().and_then(|keyboard| keyboard.decoded_try_read_byte()).map(|value| value as isize))
+                .or_else(|| ps2_keyboard.as_ref().and_then(|keyboard| keyboard.decoded_try_read_byte()).map(|value| value as isize)),
+        };
+        if let Some(value) = value {
+            return value;
+        }
+        // a killed process exits on the way back to user mode
+        if !blocking || scheduler().current_thread().process().is_killed() {
+            return 0;
+        }
+        keyboard_wait::wait(seen);
     }
 }
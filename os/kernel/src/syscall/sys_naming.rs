@@ -11,15 +11,24 @@ use alloc::string::{String, ToString};
 use core::ptr::slice_from_raw_parts;
 use core::str::from_utf8;
 use core::mem;
-use naming::shared_types::{OpenOptions, SeekOrigin, RawDirent};
+use naming::shared_types::{FileStat, MountFlags, OpenOptions, SeekOrigin, RawDirent};
 use syscall::return_vals::{self, Errno};
+use syscall::sandbox::Capabilities;
 use num_enum::FromPrimitive;
 
 use crate::naming::api;
+use crate::process::sandbox::check_capability;
 
 pub unsafe extern "sysv64" fn sys_open(path: *const u8, flag_bits: usize) -> isize {
-    let flags = OpenOptions::from_bits(flag_bits).unwrap();
-    return_vals::convert_syscall_result_to_ret_code(api::open(&unsafe { ptr_to_string(path).unwrap() }, flags))
+    let Some(flags) = OpenOptions::from_bits(flag_bits) else {
+        return Errno::EINVAL.into();
+    };
+    if flags.intersects(OpenOptions::CREATE | OpenOptions::READWRITE | OpenOptions::WRITEONLY | OpenOptions::TRUNCATE)
+        && let Err(errno) = check_capability(Capabilities::FS_WRITE) {
+        return errno.into();
+    }
+    let result = unsafe { ptr_to_string(path) }.and_then(|path| api::open(&path, flags));
+    return_vals::convert_syscall_result_to_ret_code(result)
 }
 
 pub unsafe extern "sysv64" fn sys_read(fh: usize, buffer: *mut u8, buffer_length: usize) -> isize {
@@ -53,15 +62,99 @@ pub extern "sysv64" fn sys_close(fh: usize) -> isize {
 }
 
 pub unsafe extern "sysv64" fn sys_mkdir(path: *const u8) -> isize {
-    return_vals::convert_syscall_result_to_ret_code(api::mkdir(&unsafe { ptr_to_string(path).unwrap() }))
+    if let Err(errno) = check_capability(Capabilities::FS_WRITE) {
+        return errno.into();
+    }
+    let result = unsafe { ptr_to_string(path) }.and_then(|path| api::mkdir(&path));
+    return_vals::convert_syscall_result_to_ret_code(result)
 }
 
 pub unsafe extern "sysv64" fn sys_touch(path: *const u8) -> isize {
-    return_vals::convert_syscall_result_to_ret_code(api::touch(&unsafe { ptr_to_string(path).unwrap() }))
+    if let Err(errno) = check_capability(Capabilities::FS_WRITE) {
+        return errno.into();
+    }
+    let result = unsafe { ptr_to_string(path) }.and_then(|path| api::touch(&path));
+    return_vals::convert_syscall_result_to_ret_code(result)
 }
 
 pub unsafe extern "sysv64" fn sys_mkfifo(path: *const u8) -> isize {
-    return_vals::convert_syscall_result_to_ret_code(api::mkfifo(&unsafe { ptr_to_string(path).unwrap() }))
+    if let Err(errno) = check_capability(Capabilities::FS_WRITE) {
+        return errno.into();
+    }
+    let result = unsafe { ptr_to_string(path) }.and_then(|path| api::mkfifo(&path));
+    return_vals::convert_syscall_result_to_ret_code(result)
+}
+
+pub unsafe extern "sysv64" fn sys_unlink(path: *const u8) -> isize {
+    if let Err(errno) = check_capability(Capabilities::FS_WRITE) {
+        return errno.into();
+    }
+    let result = unsafe { ptr_to_string(path) }.and_then(|path| api::unlink(&path));
+    return_vals::convert_syscall_result_to_ret_code(result)
+}
+
+pub unsafe extern "sysv64" fn sys_rename(old_path: *const u8, new_path: *const u8) -> isize {
+    if let Err(errno) = check_capability(Capabilities::FS_WRITE) {
+        return errno.into();
+    }
+    let old_path = unsafe { ptr_to_string(old_path) };
+    let new_path = unsafe { ptr_to_string(new_path) };
+    let result = old_path.and_then(|old_path| api::rename(&old_path, &new_path?));
+    return_vals::convert_syscall_result_to_ret_code(result)
+}
+
+pub unsafe extern "sysv64" fn sys_stat(path: *const u8, buffer: *mut u8, buffer_length: usize) -> isize {
+    if buffer.is_null() || buffer_length < mem::size_of::<FileStat>() {
+        return Errno::EINVAL as isize;
+    }
+    let result = unsafe { ptr_to_string(path) }.and_then(|path| api::stat(&path)).map(|stat| {
+        unsafe { (buffer as *mut FileStat).write_unaligned(stat) };
+        0
+    });
+    return_vals::convert_syscall_result_to_ret_code(result)
+}
+
+/// Mount a file system of type `fs_type` from `device` on `path` (see `api::mount_device`).
+/// Needs the `DEVICES` capability.
+pub unsafe extern "sysv64" fn sys_mount(device: *const u8, path: *const u8, fs_type: *const u8, flag_bits: usize) -> isize {
+    if let Err(errno) = check_capability(Capabilities::DEVICES) {
+        return errno.into();
+    }
+    let Some(flags) = MountFlags::from_bits(flag_bits) else {
+        return Errno::EINVAL.into();
+    };
+    let device = unsafe { ptr_to_string(device) };
+    let path = unsafe { ptr_to_string(path) };
+    let fs_type = unsafe { ptr_to_string(fs_type) };
+    let result = device.and_then(|device| api::mount_device(&device, &path?, &fs_type?, flags));
+    return_vals::convert_syscall_result_to_ret_code(result)
+}
+
+/// Unmount the file system mounted on `path`. Needs the `DEVICES` capability.
+pub unsafe extern "sysv64" fn sys_umount(path: *const u8) -> isize {
+    if let Err(errno) = check_capability(Capabilities::DEVICES) {
+        return errno.into();
+    }
+    let result = unsafe { ptr_to_string(path) }.and_then(|path| api::umount(&path));
+    return_vals::convert_syscall_result_to_ret_code(result)
+}
+
+/// Set the permission bits of `path` (see `api::chmod`).
+pub unsafe extern "sysv64" fn sys_chmod(path: *const u8, permissions: u32) -> isize {
+    if let Err(errno) = check_capability(Capabilities::FS_WRITE) {
+        return errno.into();
+    }
+    let result = unsafe { ptr_to_string(path) }.and_then(|path| api::chmod(&path, permissions));
+    return_vals::convert_syscall_result_to_ret_code(result)
+}
+
+/// Change the owner and group of `path` (see `api::chown`).
+pub unsafe extern "sysv64" fn sys_chown(path: *const u8, uid: u32, gid: u32) -> isize {
+    if let Err(errno) = check_capability(Capabilities::FS_WRITE) {
+        return errno.into();
+    }
+    let result = unsafe { ptr_to_string(path) }.and_then(|path| api::chown(&path, uid, gid));
+    return_vals::convert_syscall_result_to_ret_code(result)
 }
 
 /// Convert a raw pointer resulting from a CString to a UTF-8 String
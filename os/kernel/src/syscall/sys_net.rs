@@ -1,34 +1,60 @@
 use core::str::FromStr;
 
-use alloc::{ffi::CString, string::ToString};
+use alloc::{ffi::CString, string::ToString, vec::Vec};
 use log::{debug, info, warn};
-use smoltcp::{iface::SocketHandle, socket::{icmp, tcp, udp}, wire::IpAddress};
+use smoltcp::{iface::SocketHandle, socket::{icmp, tcp, udp}, wire::{EthernetAddress, IpAddress, IpCidr}};
+use syscall::network::{decode_address, encode_capabilities, DhcpLease, NetworkCapabilities, PathMtuEntry, RouteEntry, NETWORK_ABI_VERSION};
 use syscall::return_vals::Errno;
+use syscall::sandbox::Capabilities;
+use syscall::vsock::VsockAddr;
 
-use crate::{network::{accept_tcp, bind_icmp, bind_tcp, bind_udp, close_socket, connect_tcp, get_ip_addresses, open_icmp, open_tcp, open_udp, receive_datagram, receive_icmp, receive_tcp, send_datagram, send_icmp, send_tcp, can_recv, can_send, SocketType}, syscall::sys_naming::ptr_to_string};
+use crate::process::sandbox::check_capability;
+use crate::{network::{accept_tcp, AcceptError, add_address, add_route, bind_icmp, bind_tcp, bind_udp, close_socket, connect_tcp, get_ip_addresses, is_local_address, open_icmp, open_tcp, open_udp, receive_datagram, receive_icmp, receive_tcp, pmtu, remove_address, send_datagram, send_icmp, send_tcp, can_recv, can_send, create_namespace, dhcp_lease, remove_route, renew_dhcp, resolve_handle, routes, set_dns_servers, set_tcp_option, user_handle, vsock, wol, BlockingError, SocketType, TcpOption}, syscall::sys_naming::ptr_to_string};
 
 /// This module contains all network-related system calls.
 
 pub extern "sysv64" fn sys_sock_open(protocol: SocketType) -> isize {
     info!("opening a {protocol:?} socket");
+    if let Err(errno) = check_capability(Capabilities::NETWORK) {
+        return errno.into();
+    }
     // TODO: what happens when we get a type thats not in the enum?
     #[allow(unreachable_patterns)]
-    let handle = match protocol {
+    let result = match protocol {
         SocketType::Udp => open_udp(),
         SocketType::Tcp => open_tcp(),
         SocketType::Icmp => open_icmp(),
         _ => return Errno::ENOTSUP.into(),
     };
-    // handle.0 is private, sadly, so just hope this works
-    unsafe { core::mem::transmute::<SocketHandle, usize>(handle) }.try_into().unwrap()
+    match result {
+        Ok(handle) => user_handle(handle).try_into().unwrap(),
+        // the socket buffers would exceed the limits of the process or the system
+        Err(e) => Errno::from(e).into(),
+    }
+}
+
+/// Check a socket handle from user space. If it is stale or belongs to another process, get the error code instead.
+fn socket_handle(handle: usize) -> Result<SocketHandle, isize> {
+    resolve_handle(handle).map_err(|error| {
+        warn!("rejecting socket handle {handle:#x}: {error:?}");
+        Errno::from(error).into()
+    })
 }
 
 pub unsafe fn sys_sock_bind(
-    handle: SocketHandle, protocol: SocketType, addr_ptr: *const u8, port: u16,
+    handle: usize, protocol: SocketType, addr_ptr: *const u8, port: u16,
 ) -> isize {
+    let handle = match socket_handle(handle) {
+        Ok(handle) => handle,
+        Err(errno) => return errno,
+    };
     // TODO: somehow check that the protocol is correct for handle?
     if let Ok(addr_str) = unsafe { ptr_to_string(addr_ptr) } && let Ok(addr) = IpAddress::from_str(&addr_str) {
         info!("binding {handle:?} to {addr:?}:{port}");
+        // ICMP sockets are only bound to an ident, so they don't need a valid address
+        if !matches!(protocol, SocketType::Icmp) && !is_local_address(addr) {
+            return Errno::EADDRNOTAVAIL.into();
+        }
         #[allow(unreachable_patterns)]
         match protocol {
             SocketType::Udp => match bind_udp(handle, addr, port) {
@@ -61,10 +87,14 @@ pub unsafe fn sys_sock_bind(
 }
 
 pub unsafe fn sys_sock_accept(
-    handle: SocketHandle,
+    handle: usize,
     protocol: SocketType,
     addr_buf: *mut u8,
 ) -> isize {
+    let handle = match socket_handle(handle) {
+        Ok(handle) => handle,
+        Err(errno) => return errno,
+    };
     if matches!(protocol, SocketType::Tcp) {
         info!("accepting connections on {handle:?}");
         match accept_tcp(handle) {
@@ -77,12 +107,19 @@ pub unsafe fn sys_sock_accept(
                     addr_bytes.as_ptr(), addr_bytes.len(),
                 ) };
                 let mut result: isize = endpoint.port.try_into().unwrap();
-                result |= isize::try_from(unsafe {
-                    core::mem::transmute::<SocketHandle, usize>(listen_handle)
-                }).unwrap().checked_shl(16).unwrap();
+                result |= isize::try_from(user_handle(listen_handle)).unwrap().checked_shl(16).unwrap();
                 result
             },
-            Err(e) => panic!("failed to accept: {e:?}"),
+            Err(BlockingError::Interrupted) => Errno::EINTR.into(),
+            // the socket has been closed or aborted while waiting
+            Err(BlockingError::Socket(AcceptError::Closed)) => {
+                warn!("failed to accept: socket closed");
+                Errno::ECONNRESET.into()
+            },
+            Err(BlockingError::Socket(AcceptError::Buffers(e))) => {
+                warn!("failed to accept: {e:?}");
+                Errno::from(e).into()
+            },
         }
     } else {
         Errno::ENOTSUP.into()
@@ -90,12 +127,16 @@ pub unsafe fn sys_sock_accept(
 }
 
 pub unsafe fn sys_sock_connect(
-    handle: SocketHandle,
+    handle: usize,
     protocol: SocketType,
     remote_addr_ptr: *const u8,
     port: u16,
     local_addr_ptr: *mut u8,
 ) -> isize {
+    let handle = match socket_handle(handle) {
+        Ok(handle) => handle,
+        Err(errno) => return errno,
+    };
     if matches!(protocol, SocketType::Tcp) {
         if let Ok(addr_str) = unsafe { ptr_to_string(remote_addr_ptr) } && let Ok(addr) = IpAddress::from_str(&addr_str) {
             info!("connecting to {addr:?}:{port}");
@@ -121,13 +162,17 @@ pub unsafe fn sys_sock_connect(
 }
 
 pub unsafe fn sys_sock_send(
-    handle: SocketHandle,
+    handle: usize,
     protocol: SocketType,
     data: *const u8,
     len: usize,
     addr_ptr: *const u8,
     port: u16,
 ) -> isize {
+    let handle = match socket_handle(handle) {
+        Ok(handle) => handle,
+        Err(errno) => return errno,
+    };
     let data = unsafe { core::slice::from_raw_parts(data, len) };
     debug!("sending {len} bytes on {handle:?}");
     #[allow(unreachable_patterns)]
@@ -148,7 +193,8 @@ pub unsafe fn sys_sock_send(
         SocketType::Tcp => match send_tcp(handle, data) {
             Ok(len) => len.try_into().unwrap(),
             // socket can't send (yet)
-            Err(tcp::SendError::InvalidState) => Errno::EINVAL.into(),
+            Err(BlockingError::Socket(tcp::SendError::InvalidState)) => Errno::EINVAL.into(),
+            Err(BlockingError::Interrupted) => Errno::EINTR.into(),
         },
         SocketType::Icmp => {
             if let Ok(addr_str) = unsafe { ptr_to_string(addr_ptr) } && let Ok(addr) = IpAddress::from_str(&addr_str) {
@@ -167,18 +213,26 @@ pub unsafe fn sys_sock_send(
     }
 }
 
-pub fn sys_sock_can_send(handle: SocketHandle, protocol: SocketType) -> isize {
+pub fn sys_sock_can_send(handle: usize, protocol: SocketType) -> isize {
+    let handle = match socket_handle(handle) {
+        Ok(handle) => handle,
+        Err(errno) => return errno,
+    };
     can_send(handle, protocol).into()
 }
 
 
 pub unsafe fn sys_sock_receive(
-    handle: SocketHandle,
+    handle: usize,
     protocol: SocketType,
     data_ptr: *mut u8,
     data_len: usize,
     addr_buf: *mut u8,
 ) -> isize {
+    let handle = match socket_handle(handle) {
+        Ok(handle) => handle,
+        Err(errno) => return errno,
+    };
     let data = unsafe { core::slice::from_raw_parts_mut(data_ptr, data_len) };
     debug!("receiving up to {data_len} bytes on {handle:?}");
     #[allow(unreachable_patterns)]
@@ -207,12 +261,13 @@ pub unsafe fn sys_sock_receive(
         },
         SocketType::Tcp => match receive_tcp(handle, data) {
             Ok(len) => len.try_into().unwrap(),
-            Err(tcp::RecvError::InvalidState) => {
+            Err(BlockingError::Socket(tcp::RecvError::InvalidState)) => {
                 warn!("TCP socket is in an invalid state");
                 Errno::EINVALH.into()
             },
             // the remote host closed the connection
-            Err(tcp::RecvError::Finished) => Errno::ECONNRESET.into(),
+            Err(BlockingError::Socket(tcp::RecvError::Finished)) => Errno::ECONNRESET.into(),
+            Err(BlockingError::Interrupted) => Errno::EINTR.into(),
         },
         SocketType::Icmp => match receive_icmp(handle, data) {
             Ok((len, address)) => {
@@ -237,13 +292,40 @@ pub unsafe fn sys_sock_receive(
     }
 }
 
-pub extern "sysv64" fn sys_sock_close(handle: SocketHandle) -> isize {
+pub extern "sysv64" fn sys_sock_close(handle: usize) -> isize {
+    let handle = match socket_handle(handle) {
+        Ok(handle) => handle,
+        Err(errno) => return errno,
+    };
     info!("closing {handle} socket");
     close_socket(handle);
     0
 }
 
-pub fn sys_sock_can_recv(handle: SocketHandle, protocol: SocketType) -> isize {
+/// Set a socket option (see `TcpOption`). Only TCP sockets have options for now.
+pub extern "sysv64" fn sys_sock_set_option(handle: usize, protocol: SocketType, option: usize, value: usize) -> isize {
+    let handle = match socket_handle(handle) {
+        Ok(handle) => handle,
+        Err(errno) => return errno,
+    };
+    if !matches!(protocol, SocketType::Tcp) {
+        return Errno::ENOTSUP.into();
+    }
+    let Ok(option) = TcpOption::try_from(option) else {
+        return Errno::EINVAL.into();
+    };
+    debug!("setting {option:?} to {value} on {handle:?}");
+    match set_tcp_option(handle, option, value) {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
+}
+
+pub fn sys_sock_can_recv(handle: usize, protocol: SocketType) -> isize {
+    let handle = match socket_handle(handle) {
+        Ok(handle) => handle,
+        Err(errno) => return errno,
+    };
     can_recv(handle, protocol).into()
 }
 
@@ -259,6 +341,10 @@ pub unsafe fn sys_get_ip_adresses(ptr: *mut u8, len: usize, host_ptr: *const u8)
             Err(errno) => return errno.into(),
         }
     };
+    // resolving other hosts needs network access, getting our own addresses does not
+    if host.is_some() && let Err(errno) = check_capability(Capabilities::NETWORK) {
+        return errno.into();
+    }
     info!("resolving host {host:?}");
     let target = unsafe { core::slice::from_raw_parts_mut(ptr, len) };
     let mut idx = 0;
@@ -271,3 +357,255 @@ pub unsafe fn sys_get_ip_adresses(ptr: *mut u8, len: usize, host_ptr: *const u8)
     }
     0
 }
+
+/// Move the calling process into a new network namespace and return the namespace's id.
+///
+/// If `flags` contains `NAMESPACE_WITH_INTERFACE` (1), the namespace is connected to the root namespace.
+/// Fails with `EBUSY`, if the process still owns sockets.
+pub extern "sysv64" fn sys_net_namespace_create(flags: usize) -> isize {
+    if let Err(errno) = check_capability(Capabilities::NETWORK) {
+        return errno.into();
+    }
+    match create_namespace(flags) {
+        Ok(id) => id as isize,
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Broadcast a Wake-on-LAN magic packet for the 6 byte MAC address at `mac_ptr`.
+pub extern "sysv64" fn sys_wake_on_lan(mac_ptr: *const u8) -> isize {
+    if mac_ptr.is_null() {
+        return Errno::EINVAL.into();
+    }
+    if let Err(errno) = check_capability(Capabilities::NETWORK) {
+        return errno.into();
+    }
+
+    let mac = EthernetAddress::from_bytes(unsafe { core::slice::from_raw_parts(mac_ptr, 6) });
+    match wol::send_magic_packet(mac) {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Add the address at `addr_ptr` with the given prefix length to interface `interface`.
+pub unsafe extern "sysv64" fn sys_net_address_add(interface: usize, addr_ptr: *const u8, prefix_len: u8) -> isize {
+    match unsafe { parse_cidr(addr_ptr, prefix_len) } {
+        Ok(cidr) => match add_address(interface, cidr) {
+            Ok(()) => 0,
+            Err(errno) => errno.into(),
+        },
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Remove the address at `addr_ptr` with the given prefix length from interface `interface`.
+pub unsafe extern "sysv64" fn sys_net_address_remove(interface: usize, addr_ptr: *const u8, prefix_len: u8) -> isize {
+    match unsafe { parse_cidr(addr_ptr, prefix_len) } {
+        Ok(cidr) => match remove_address(interface, cidr) {
+            Ok(()) => 0,
+            Err(errno) => errno.into(),
+        },
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Check the capability and build an address with prefix length from user space arguments.
+unsafe fn parse_cidr(addr_ptr: *const u8, prefix_len: u8) -> Result<IpCidr, Errno> {
+    check_capability(Capabilities::NETWORK)?;
+    let addr_str = unsafe { ptr_to_string(addr_ptr) }?;
+    let addr = IpAddress::from_str(&addr_str).map_err(|_| Errno::EINVAL)?;
+    let max_len = match addr {
+        IpAddress::Ipv4(_) => 32,
+        IpAddress::Ipv6(_) => 128,
+    };
+    if prefix_len > max_len {
+        return Err(Errno::EINVAL);
+    }
+    Ok(IpCidr::new(addr, prefix_len))
+}
+
+/// Listen for vsock connections on `port`. Returns the handle of the listening socket.
+pub extern "sysv64" fn sys_vsock_listen(port: u32) -> isize {
+    if let Err(errno) = check_capability(Capabilities::NETWORK) {
+        return errno.into();
+    }
+    match vsock::listen(port) {
+        Ok(handle) => handle as isize,
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Wait for a connection to a listening vsock socket. The address of the peer is written to `peer`.
+/// Returns the handle of the new, connected socket.
+pub unsafe extern "sysv64" fn sys_vsock_accept(handle: usize, peer: *mut VsockAddr) -> isize {
+    if peer.is_null() {
+        return Errno::EINVAL.into();
+    }
+    match vsock::accept(handle) {
+        Ok((handle, addr)) => {
+            unsafe { peer.write(addr) };
+            handle as isize
+        }
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Connect to `port` on the machine with the context id `cid`. Returns the handle of the connected socket.
+pub extern "sysv64" fn sys_vsock_connect(cid: u64, port: u32) -> isize {
+    if let Err(errno) = check_capability(Capabilities::NETWORK) {
+        return errno.into();
+    }
+    match vsock::connect(cid, port) {
+        Ok(handle) => handle as isize,
+        Err(errno) => errno.into(),
+    }
+}
+
+pub unsafe extern "sysv64" fn sys_vsock_send(handle: usize, data: *const u8, len: usize) -> isize {
+    if data.is_null() {
+        return Errno::EINVAL.into();
+    }
+    let data = unsafe { core::slice::from_raw_parts(data, len) };
+    match vsock::send(handle, data) {
+        Ok(len) => len as isize,
+        Err(errno) => errno.into(),
+    }
+}
+
+pub unsafe extern "sysv64" fn sys_vsock_receive(handle: usize, data: *mut u8, len: usize) -> isize {
+    if data.is_null() {
+        return Errno::EINVAL.into();
+    }
+    let data = unsafe { core::slice::from_raw_parts_mut(data, len) };
+    match vsock::receive(handle, data) {
+        Ok(len) => len as isize,
+        Err(errno) => errno.into(),
+    }
+}
+
+pub extern "sysv64" fn sys_vsock_close(handle: usize) -> isize {
+    match vsock::close(handle) {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Report the version of the socket syscalls and the socket features of this kernel,
+/// so the network library can avoid syscalls or options, that would fail.
+pub extern "sysv64" fn sys_network_capabilities() -> isize {
+    // Nicht-blockierende Sockets gibt es noch nicht, nur das Abfragen mit SockCanSend/SockCanReceive
+    let capabilities = NetworkCapabilities::POLL | NetworkCapabilities::IPV6 | NetworkCapabilities::SOCKET_OPTIONS | NetworkCapabilities::PATH_MTU | NetworkCapabilities::CONFIGURATION;
+    encode_capabilities(NETWORK_ABI_VERSION, capabilities) as isize
+}
+
+/// Copy the entries of the path MTU cache to the array of `capacity` entries at `entries`.
+/// Always returns the number of cached entries, so User-Space can retry with a bigger array if necessary.
+pub extern "sysv64" fn sys_net_path_mtu(entries: *mut PathMtuEntry, capacity: usize) -> isize {
+    if entries.is_null() && capacity > 0 {
+        return Errno::EINVAL.into();
+    }
+
+    let cache = pmtu::entries();
+    let copy_len = cache.len().min(capacity);
+    if copy_len > 0 {
+        let target = unsafe { core::slice::from_raw_parts_mut(entries, copy_len) };
+        target.copy_from_slice(&cache[..copy_len]);
+    }
+    cache.len() as isize
+}
+
+/// Add a route to the network at `dest_ptr` with the given prefix length via the gateway at `via_ptr` to interface `interface`.
+pub unsafe extern "sysv64" fn sys_net_route_add(interface: usize, dest_ptr: *const u8, prefix_len: u8, via_ptr: *const u8) -> isize {
+    if let Err(errno) = check_capability(Capabilities::NETWORK) {
+        return errno.into();
+    }
+    let result = unsafe { parse_cidr(dest_ptr, prefix_len) }
+        .and_then(|cidr| {
+            let via_str = unsafe { ptr_to_string(via_ptr) }?;
+            let via = IpAddress::from_str(&via_str).map_err(|_| Errno::EINVAL)?;
+            add_route(interface, cidr, via)
+        });
+    match result {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Remove the route to the network at `dest_ptr` with the given prefix length from interface `interface`.
+pub unsafe extern "sysv64" fn sys_net_route_remove(interface: usize, dest_ptr: *const u8, prefix_len: u8) -> isize {
+    if let Err(errno) = check_capability(Capabilities::NETWORK) {
+        return errno.into();
+    }
+    match unsafe { parse_cidr(dest_ptr, prefix_len) }.and_then(|cidr| remove_route(interface, cidr)) {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Copy the routes of interface `interface` to the array of `capacity` entries at `entries`.
+/// Like `sys_net_path_mtu`, this always returns the number of routes.
+pub unsafe extern "sysv64" fn sys_net_routes(interface: usize, entries: *mut RouteEntry, capacity: usize) -> isize {
+    if entries.is_null() && capacity > 0 {
+        return Errno::EINVAL.into();
+    }
+
+    let routes = match routes(interface) {
+        Ok(routes) => routes,
+        Err(errno) => return errno.into(),
+    };
+    let copy_len = routes.len().min(capacity);
+    if copy_len > 0 {
+        let target = unsafe { core::slice::from_raw_parts_mut(entries, copy_len) };
+        target.copy_from_slice(&routes[..copy_len]);
+    }
+    routes.len() as isize
+}
+
+/// Replace the DNS servers of the kernel's resolver with the `count` addresses at `servers` (encoded with `encode_address`).
+pub unsafe extern "sysv64" fn sys_net_dns_servers(servers: *const [u8; 16], count: usize) -> isize {
+    if servers.is_null() && count > 0 {
+        return Errno::EINVAL.into();
+    }
+    if let Err(errno) = check_capability(Capabilities::NETWORK) {
+        return errno.into();
+    }
+
+    let servers: Vec<IpAddress> = if count > 0 {
+        unsafe { core::slice::from_raw_parts(servers, count) }
+            .iter()
+            .map(|server| decode_address(*server).into())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    match set_dns_servers(&servers) {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Copy the lease of the kernel's DHCP client to `lease`. Returns 1, if there is a lease, and 0 otherwise.
+pub unsafe extern "sysv64" fn sys_net_dhcp_lease(lease: *mut DhcpLease) -> isize {
+    if lease.is_null() {
+        return Errno::EINVAL.into();
+    }
+    match dhcp_lease() {
+        Some(current) => {
+            unsafe { lease.write(current) };
+            1
+        }
+        None => 0,
+    }
+}
+
+/// Restart the kernel's DHCP client, dropping its current lease.
+pub extern "sysv64" fn sys_net_dhcp_renew() -> isize {
+    if let Err(errno) = check_capability(Capabilities::NETWORK) {
+        return errno.into();
+    }
+    match renew_dhcp() {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
+}
@@ -0,0 +1,23 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: sys_random                                                      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Systemcall for getting random bytes from the entropy pool.      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::slice::from_raw_parts_mut;
+use syscall::return_vals::{self, Errno};
+use crate::entropy_pool;
+
+/// Do not block, if the entropy pool has not been seeded yet (return `EAGAIN` instead).
+pub const GRND_NONBLOCK: usize = 0x1;
+
+pub extern "sysv64" fn sys_get_random(buf: *mut u8, len: usize, flags: usize) -> isize {
+    if buf.is_null() || flags & !GRND_NONBLOCK != 0 {
+        return Errno::EINVAL.into();
+    }
+
+    let buf = unsafe { from_raw_parts_mut(buf, len) };
+    return_vals::convert_syscall_result_to_ret_code(entropy_pool().get_random(buf, flags & GRND_NONBLOCK != 0))
+}
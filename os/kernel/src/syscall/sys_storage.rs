@@ -0,0 +1,46 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: sys_storage                                                     ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Systemcalls for managing block devices.                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::slice::from_raw_parts_mut;
+use syscall::return_vals::Errno;
+use syscall::sandbox::Capabilities;
+use crate::memory::{self, PAGE_SIZE};
+use crate::process::sandbox::check_capability;
+use crate::storage::{cache, ramdisk};
+
+/// SystemCall implementation for SystemCall::RamdiskCreate.
+/// Creates an empty ramdisk with `size` bytes (rounded up to whole pages) and copies its name (e.g. "ram1") to `name`.
+/// Returns the length of the name, which is truncated if `name_len` is too small. Needs the `DEVICES` capability.
+/// Fails with `ENOMEM`, if the ramdisk would take more than half of the free memory.
+pub extern "sysv64" fn sys_ramdisk_create(size: usize, name: *mut u8, name_len: usize) -> isize {
+    if let Err(errno) = check_capability(Capabilities::DEVICES) {
+        return errno.into();
+    }
+    if size == 0 || name.is_null() {
+        return Errno::EINVAL.into();
+    }
+    if size.div_ceil(PAGE_SIZE) > memory::get_free_frames() / 2 {
+        return Errno::ENOMEM.into();
+    }
+
+    let created = ramdisk::create(size);
+    let name = unsafe { from_raw_parts_mut(name, name_len) };
+    let length = created.len().min(name.len());
+    name[..length].copy_from_slice(&created.as_bytes()[..length]);
+
+    created.len() as isize
+}
+
+/// SystemCall implementation for SystemCall::Sync.
+/// Writes back the block cache of all devices. Fails with `EIO`, if a device reported an error.
+pub extern "sysv64" fn sys_sync() -> isize {
+    match cache::sync() {
+        true => 0,
+        false => Errno::EIO.into(),
+    }
+}
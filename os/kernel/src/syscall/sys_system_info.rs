@@ -1,9 +1,13 @@
 use alloc::string::{String, ToString};
 use log::error;
+use syscall::cpu::CpuSensors;
 use syscall::return_vals::Errno;
+use syscall::sandbox::Capabilities;
 use system_info::build_info::BuildInfo;
 
-use crate::{boot_info, built_info};
+use crate::device::{cpu_sensors, hotplug, registry, stats};
+use crate::process::sandbox::check_capability;
+use crate::{boot_info, built_info, interrupt, power};
 
 /// SystemCall implementation for SystemCall::MapSystemInfo.
 /// Exposes build infos to User-Space.
@@ -32,6 +36,72 @@ pub extern "sysv64" fn sys_map_build_info(address: *mut u8, length: usize, info_
     value_len as isize
 }
 
+/// SystemCall implementation for SystemCall::DeviceStats.
+/// Copies the serialized counters of all device drivers to User-Space.
+/// Always returns the full length, so User-Space can retry with a bigger buffer if necessary.
+pub extern "sysv64" fn sys_device_stats(address: *mut u8, length: usize) -> isize {
+    if address.is_null() {
+        error!("Unable to map device stats, buffer is null");
+        return Errno::EINVAL as isize;
+    }
+
+    let stats = stats::serialize();
+    let stats_bytes = stats.as_bytes();
+    let copy_len = stats_bytes.len().min(length);
+
+    let buffer = unsafe { core::slice::from_raw_parts_mut(address, length) };
+    buffer[..copy_len].copy_from_slice(&stats_bytes[..copy_len]);
+    stats_bytes.len() as isize
+}
+
+/// SystemCall implementation for SystemCall::Devices.
+/// Copies the serialized device registry to User-Space (see `system_info::devices` for the format).
+/// Always returns the full length, so User-Space can retry with a bigger buffer if necessary.
+pub extern "sysv64" fn sys_devices(address: *mut u8, length: usize) -> isize {
+    if address.is_null() {
+        error!("Unable to map devices, buffer is null");
+        return Errno::EINVAL as isize;
+    }
+
+    let devices = registry::serialize();
+    let devices_bytes = devices.as_bytes();
+    let copy_len = devices_bytes.len().min(length);
+
+    let buffer = unsafe { core::slice::from_raw_parts_mut(address, length) };
+    buffer[..copy_len].copy_from_slice(&devices_bytes[..copy_len]);
+    devices_bytes.len() as isize
+}
+
+/// SystemCall implementation for SystemCall::DeviceRescan.
+/// Rescans the PCI bus for devices, that have been added or removed, and returns the number of changes.
+pub extern "sysv64" fn sys_device_rescan() -> isize {
+    if let Err(errno) = check_capability(Capabilities::DEVICES) {
+        return errno.into();
+    }
+
+    hotplug::rescan() as isize
+}
+
+/// SystemCall implementation for SystemCall::CpuSensors.
+/// Reads the temperature and frequency of up to `count` cores into `sensors` and returns the number of all cores.
+/// With `count == 0`, only the number of cores is returned (without reading the sensors, which would restart the averaging period).
+pub extern "sysv64" fn sys_cpu_sensors(sensors: *mut CpuSensors, count: usize) -> isize {
+    if sensors.is_null() && count > 0 {
+        return Errno::EINVAL.into();
+    }
+    if count == 0 {
+        return interrupt::smp_call::cpus().len() as isize;
+    }
+
+    let readouts = cpu_sensors::read_all();
+    let sensors = unsafe { core::slice::from_raw_parts_mut(sensors, count) };
+    for (sensor, readout) in sensors.iter_mut().zip(readouts.iter()) {
+        *sensor = *readout;
+    }
+
+    readouts.len() as isize
+}
+
 /// Helper function.
 /// Maps BuildInfo type to its value.
 ///
@@ -80,3 +150,33 @@ fn map_build_info(info_type: BuildInfo) -> String {
     };
     info.to_string()
 }
+
+/// SystemCall implementation for SystemCall::PowerOff.
+/// Powers down the system (arming Wake-on-LAN first). Only returns if the process is not allowed to power down,
+/// otherwise the system is halted if powering down fails.
+pub extern "sysv64" fn sys_power_off() -> isize {
+    if let Err(errno) = check_capability(Capabilities::POWER) {
+        return errno.into();
+    }
+    power::power_off()
+}
+
+/// SystemCall implementation for SystemCall::CpuPark.
+/// Takes the core with the local APIC id `apic_id` offline (`park` = 1) or brings it back (`park` = 0).
+pub extern "sysv64" fn sys_cpu_park(apic_id: usize, park: usize) -> isize {
+    if let Err(errno) = check_capability(Capabilities::POWER) {
+        return errno.into();
+    }
+    let Ok(apic_id) = u32::try_from(apic_id) else {
+        return Errno::EINVAL.into();
+    };
+
+    let result = match park {
+        0 => interrupt::park::unpark(apic_id),
+        _ => interrupt::park::park(apic_id),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
+}
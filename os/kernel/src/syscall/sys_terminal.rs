@@ -9,14 +9,17 @@
 use core::slice::from_raw_parts;
 use core::slice::from_raw_parts_mut;
 use log::error;
-use syscall::return_vals::Errno;
+use syscall::return_vals::{self, Errno};
+use syscall::sandbox::ROOT_ID;
 use terminal::{TerminalInputState, TerminalMode};
 
 use crate::device::tty::TtyInputState;
-use crate::{tty_input, tty_output};
+use crate::naming::api;
+use crate::process::sandbox;
+use crate::{process_manager, scheduler, tty_input, tty_output};
 
 /// SystemCall implementation for SystemCall::TerminalWriteOutput.
-/// Used by applications to write output in the terminal.
+/// Used by applications to write output in the terminal (or to the object, their standard output has been redirected to).
 ///
 /// Author: Sebastian Keller
 pub extern "sysv64" fn sys_terminal_write_output(address: *const u8, length: usize) -> isize {
@@ -26,6 +29,10 @@ pub extern "sysv64" fn sys_terminal_write_output(address: *const u8, length: usi
     }
 
     let bytes = unsafe { from_raw_parts(address, length) };
+    if let Some(handle) = process_manager().read().current_process().stdout() {
+        return return_vals::convert_syscall_result_to_ret_code(api::write(handle, bytes));
+    }
+
     tty_output().write(bytes) as isize
 }
 
@@ -59,7 +66,7 @@ pub extern "sysv64" fn sys_terminal_write_input(address: *mut u8, length: usize,
 }
 
 /// SystemCall implementation for SystemCall::TerminalReadInput.
-/// Used by applications to read input from the terminal.
+/// Used by applications to read input from the terminal (or from the object, their standard input has been redirected to).
 ///
 /// Author: Sebastian Keller
 pub extern "sysv64" fn sys_terminal_read_input(address: *mut u8, length: usize, mode: usize) -> isize {
@@ -70,6 +77,11 @@ pub extern "sysv64" fn sys_terminal_read_input(address: *mut u8, length: usize,
 
     let mode = TerminalMode::from(mode);
     let buffer = unsafe { from_raw_parts_mut(address, length) };
+    if let Some(handle) = process_manager().read().current_process().stdin() {
+        // files and pipes have no line discipline, so the mode does not matter
+        return return_vals::convert_syscall_result_to_ret_code(api::read(handle, buffer));
+    }
+
     tty_input().read(buffer, mode) as isize
 }
 
@@ -90,3 +102,50 @@ pub extern "sysv64" fn sys_terminal_check_input_state() -> isize {
         TerminalMode::Raw => TerminalInputState::Raw as isize,
     }
 }
+
+/// SystemCall implementation for SystemCall::TerminalSetForeground.
+/// Used by the shell to tell, which application runs in the foreground.
+/// The application is identified by its first thread (as returned when starting it), 0 means none.
+/// Only root may choose (or replace) a process, that is neither the calling process nor one of its children.
+pub extern "sysv64" fn sys_terminal_set_foreground(thread_id: usize) -> isize {
+    let process_id = match thread_id {
+        0 => 0,
+        id => match scheduler().thread(id) {
+            Some(thread) => thread.process().id(),
+            None => return Errno::ESRCH.into(),
+        },
+    };
+
+    if !may_set_foreground(process_id) || !may_set_foreground(tty_input().foreground()) {
+        return Errno::EACCES.into();
+    }
+    tty_input().set_foreground(process_id);
+    0
+}
+
+/// SystemCall implementation for SystemCall::TerminalInterrupt.
+/// Used by terminal to interrupt the foreground application, when Ctrl+C has been pressed.
+/// Like `sys_process_kill()`, only root may interrupt a process of another user.
+pub extern "sysv64" fn sys_terminal_interrupt() -> isize {
+    let uid = sandbox::credentials().0;
+    if let Some(process) = process_manager().read().process(tty_input().foreground())
+        && uid != ROOT_ID && uid != process.sandbox().uid() {
+        return Errno::EACCES.into();
+    }
+
+    match tty_input().interrupt() {
+        true => 0,
+        false => Errno::ESRCH.into(),
+    }
+}
+
+/// Check whether the calling process may make the process `process_id` the foreground process
+/// (or replace it as such): It must be the calling process itself or one of its children, unless the caller is root.
+/// Exited processes and 0 (none) are always allowed.
+fn may_set_foreground(process_id: usize) -> bool {
+    let Some(process) = process_manager().read().process(process_id) else {
+        return true;
+    };
+    let current = process_manager().read().current_process().id();
+    sandbox::credentials().0 == ROOT_ID || process.id() == current || process.parent() == current
+}
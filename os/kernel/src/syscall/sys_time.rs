@@ -7,64 +7,56 @@
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
 
-use alloc::format;
-use alloc::string::ToString;
-use chrono::{DateTime, Datelike, TimeDelta, Timelike};
-use uefi::runtime::{Time, TimeParams};
-use crate::{efi_services_available, timer};
+use syscall::return_vals::Errno;
+use syscall::sandbox::Capabilities;
+use syscall::time::Timespec;
+use crate::process::sandbox::check_capability;
+use crate::{timer, wallclock};
 
+const NANOS_PER_MS: i128 = 1_000_000;
 
 pub extern "sysv64" fn sys_get_system_time() -> isize {
     timer().systime_ms() as isize
 }
 
 pub extern "sysv64" fn sys_get_date() -> isize {
-    if !efi_services_available() {
-        return 0;
+    wallclock::now().as_nanos().div_euclid(NANOS_PER_MS) as isize
+}
+
+pub extern "sysv64" fn sys_set_date(date_ms: usize) -> isize {
+    if let Err(errno) = check_capability(Capabilities::TIME) {
+        return errno.into();
     }
-    
-    match uefi::runtime::get_time() {
-        Ok(time) => {
-            if time.is_valid().is_ok() {
-                let timezone = match time.time_zone() {
-                    Some(timezone) => {
-                        let delta = TimeDelta::try_minutes(timezone as i64).expect("Failed to create TimeDelta struct from timezone");
-                        if timezone >= 0 {
-                            format!("+{:0>2}:{:0>2}", delta.num_hours(), delta.num_minutes() % 60)
-                        } else {
-                            format!("-{:0>2}:{:0>2}", delta.num_hours(), delta.num_minutes() % 60)
-                        }
-                    }
-                    None => "Z".to_string(),
-                };
 
-                DateTime::parse_from_rfc3339(format!("{}-{:0>2}-{:0>2}T{:0>2}:{:0>2}:{:0>2}.{:0>9}{}", time.year(), time.month(), time.day(), time.hour(), time.minute(), time.second(), time.nanosecond(), timezone).as_str())
-                    .expect("Failed to parse date from EFI runtime services")
-                    .timestamp_millis() as isize
-            } else {
-                0
-            }
-        }
-        Err(_) => 0
+    match wallclock::set(Timespec::from_nanos(date_ms as isize as i128 * NANOS_PER_MS)) {
+        Ok(()) => true as isize,
+        Err(errno) => errno.into(),
     }
 }
 
-pub extern "sysv64" fn sys_set_date(date_ms: usize) -> isize {
-    let date = DateTime::from_timestamp_millis(date_ms as i64).expect("Failed to parse date from milliseconds");
-    let uefi_date = Time::new(TimeParams {
-        year: date.year() as u16,
-        month: date.month() as u8,
-        day: date.day() as u8,
-        hour: date.hour() as u8,
-        minute: date.minute() as u8,
-        second: date.second() as u8,
-        nanosecond: date.nanosecond(),
-        time_zone: None,
-        daylight: Default::default(),
-    }).expect("Failed to create EFI date");
+/// SystemCall implementation for SystemCall::TimeGet.
+/// Writes the current wall clock time to `time`.
+pub extern "sysv64" fn sys_time_get(time: *mut Timespec) -> isize {
+    let Some(time) = (unsafe { time.as_mut() }) else {
+        return Errno::EINVAL.into();
+    };
+
+    *time = wallclock::now();
+    0
+}
+
+/// SystemCall implementation for SystemCall::TimeSet.
+/// Sets the wall clock (and the RTC) to `time`. Needs the `TIME` capability.
+pub extern "sysv64" fn sys_time_set(time: *const Timespec) -> isize {
+    if let Err(errno) = check_capability(Capabilities::TIME) {
+        return errno.into();
+    }
+    let Some(time) = (unsafe { time.as_ref() }) else {
+        return Errno::EINVAL.into();
+    };
 
-    match unsafe { uefi::runtime::set_time(&uefi_date) } {
-        Ok(_) => true as isize,
-        Err(_) => false as isize,
+    match wallclock::set(*time) {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
     }
 }
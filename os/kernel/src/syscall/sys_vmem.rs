@@ -6,33 +6,67 @@
    ║ Author: Fabian Ruhland & Michael Schoettner, 24.5.2025, HHU             ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
+use alloc::vec::Vec;
 use multiboot2::FramebufferTag;
-use spin::once::Once;
+use spin::RwLock;
 use x86_64::structures::paging::frame::PhysFrameRange;
 use x86_64::{PhysAddr, VirtAddr};
 use x86_64::structures::paging::{Page, PageTableFlags, PhysFrame};
+use x86_64::structures::paging::page::PageRange;
 use graphic::lfb::FramebufferInfo;
 use crate::memory::vma::VmaType;
 use crate::memory::{MemorySpace, PAGE_SIZE};
 use crate::process_manager;
+use syscall::memory::{MapFlags, Protection};
 use syscall::return_vals::Errno;
+use syscall::sandbox::Capabilities;
+use crate::process::sandbox::check_capability;
 
-static FB_INFO: Once<FramebufferInfo> = Once::new();
+static FB_INFO: RwLock<Option<FramebufferInfo>> = RwLock::new(None);
 
 pub fn init_fb_info(tag: &FramebufferTag) {
-    FB_INFO.call_once(|| {
-        let start = PhysAddr::new(tag.address());
-
-        FramebufferInfo {
-            addr: start.as_u64(),
-            width: tag.width(),
-            height: tag.height(),
-            pitch: tag.pitch(),
-            bpp: tag.bpp()
-        }
+    let start = PhysAddr::new(tag.address());
+
+    set_fb_info(FramebufferInfo {
+        addr: start.as_u64(),
+        width: tag.width(),
+        height: tag.height(),
+        pitch: tag.pitch(),
+        bpp: tag.bpp()
     });
 }
 
+/// Replace the framebuffer, that is mapped by `sys_map_frame_buffer` (e.g. when a graphics driver takes over).
+/// `info.addr` is the physical address of the framebuffer. \
+/// Existing mappings of the old framebuffer are moved to the new one, because the driver may free the old one afterwards.
+/// They keep their size, so applications have to map the framebuffer again to use all of it.
+pub fn set_fb_info(info: FramebufferInfo) {
+    let new_frames = fb_frames(&info);
+    let mut fb_info = FB_INFO.write();
+    if let Some(old) = fb_info.replace(info) && old.addr != new_frames.start.start_address().as_u64() {
+        let old_frames = fb_frames(&old);
+        let processes: Vec<_> = {
+            let process_manager = process_manager().read();
+            process_manager.active_process_ids().iter().filter_map(|&id| process_manager.process(id)).collect()
+        };
+        for process in processes {
+            process.virtual_address_space.remap_device_memory(old_frames, new_frames, FB_FLAGS);
+        }
+    }
+}
+
+/// Flags of the framebuffer mappings in user space
+const FB_FLAGS: PageTableFlags = PageTableFlags::PRESENT.union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::USER_ACCESSIBLE)
+    .union(PageTableFlags::NO_CACHE);
+
+/// The page frames of the framebuffer described by `fb_info`
+fn fb_frames(fb_info: &FramebufferInfo) -> PhysFrameRange {
+    let num_pages = (fb_info.height * fb_info.pitch).div_ceil(PAGE_SIZE as u32) as u64;
+    let start_frame = PhysFrame::from_start_address(PhysAddr::new(fb_info.addr)).unwrap();
+    PhysFrameRange { start: start_frame, end: start_frame + num_pages }
+}
+
 /// Map memory to a process.
 ///
 /// This just sets up the VMA, no page tables are created yet.
@@ -43,6 +77,9 @@ pub extern "sysv64" fn sys_map_memory(start: usize, size: usize) -> isize {
     let start_addr = VirtAddr::new(start.try_into().unwrap());
     let start_page = Page::containing_address(start_addr);
     let num_pages = size.div_ceil(PAGE_SIZE);
+    if let Err(errno) = process.charge_heap_memory(num_pages * PAGE_SIZE) {
+        return errno.into();
+    }
 
     let vma = process.virtual_address_space.alloc_vma(
         Some(start_page),
@@ -52,24 +89,79 @@ pub extern "sysv64" fn sys_map_memory(start: usize, size: usize) -> isize {
         "heap",
     );
     if vma.is_none() {
+        process.release_heap_memory(num_pages * PAGE_SIZE);
         Errno::EUNKN as isize
     } else {
         0
     }
 }
 
+/// Map `len` bytes of anonymous memory with the access rights `prot` (see `Protection`) anywhere into the process. \
+/// Like the heap, the pages are only backed by zeroed frames on their first access, but count against the memory limit right away. \
+/// Returns the start address of the memory.
+pub extern "sysv64" fn sys_mmap(len: usize, prot: usize, flags: usize) -> isize {
+    let (Some(prot), Some(flags)) = (Protection::from_bits(prot), MapFlags::from_bits(flags)) else {
+        return Errno::EINVAL.into();
+    };
+    if len == 0 || !flags.contains(MapFlags::ANONYMOUS) {
+        return Errno::EINVAL.into();
+    }
+
+    let process = process_manager().read().current_process();
+    let num_pages = len.div_ceil(PAGE_SIZE);
+    if let Err(errno) = process.charge_heap_memory(num_pages * PAGE_SIZE) {
+        return errno.into();
+    }
+
+    match process.virtual_address_space.alloc_vma(None, num_pages as u64, MemorySpace::User, VmaType::Mapped { prot }, "mmap") {
+        Some(vma) => vma.start().as_u64() as isize,
+        None => {
+            process.release_heap_memory(num_pages * PAGE_SIZE);
+            Errno::ENOMEM.into()
+        }
+    }
+}
+
+/// Unmap the pages in [`addr`, `addr` + `len`), which must have been mapped with `sys_mmap` (but not necessarily at once),
+/// and free their frames. `addr` must be page aligned. Pages in this range, that are not mapped, are ignored.
+pub extern "sysv64" fn sys_munmap(addr: usize, len: usize) -> isize {
+    let Some(end) = addr.checked_add(len).and_then(|end| end.checked_next_multiple_of(PAGE_SIZE)) else {
+        return Errno::EINVAL.into();
+    };
+    let (Ok(start_addr), Ok(end_addr)) = (VirtAddr::try_new(addr as u64), VirtAddr::try_new(end as u64)) else {
+        return Errno::EINVAL.into();
+    };
+    let Ok(start_page) = Page::from_start_address(start_addr) else {
+        return Errno::EINVAL.into();
+    };
+    if len == 0 {
+        return Errno::EINVAL.into();
+    }
+
+    let process = process_manager().read().current_process();
+    let pages = PageRange { start: start_page, end: Page::containing_address(end_addr) };
+    match process.virtual_address_space.unmap_mapped(pages) {
+        Some(removed) => {
+            process.release_heap_memory(removed as usize * PAGE_SIZE);
+            0
+        }
+        None => Errno::EINVAL.into(),
+    }
+}
+
 pub extern "sysv64" fn sys_map_frame_buffer(fb_info_user: *mut FramebufferInfo) -> isize {
+    if let Err(errno) = check_capability(Capabilities::DEVICES) {
+        return errno.into();
+    }
     let process = process_manager().read().current_process();
 
-    let fb_info = FB_INFO.get().unwrap();
-    let size = fb_info.height * fb_info.pitch;
-    let num_pages = size.div_ceil(PAGE_SIZE as u32) as u64;
-    let start_frame = PhysFrame::from_start_address(PhysAddr::new(fb_info.addr)).unwrap();
-    let end_frame = start_frame + num_pages;
+    let fb_info = FB_INFO.read();
+    let fb_info = fb_info.as_ref().unwrap();
+    let frames = fb_frames(fb_info);
 
     let vma = process.virtual_address_space.alloc_vma(
         None,
-        num_pages,
+        frames.end - frames.start,
         MemorySpace::User,
         VmaType::DeviceMemory,
         "framebuffer",
@@ -78,10 +170,7 @@ pub extern "sysv64" fn sys_map_frame_buffer(fb_info_user: *mut FramebufferInfo)
         return Errno::EUNKN as isize
     }
 
-    let res = process.virtual_address_space.map_pfr_for_vma(
-        vma.as_ref().unwrap(),
-        PhysFrameRange{ start: start_frame, end: end_frame },
-        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE | PageTableFlags::NO_CACHE);
+    let res = process.virtual_address_space.map_pfr_for_vma(vma.as_ref().unwrap(), frames, FB_FLAGS);
     if res.is_err() {
         return Errno::EUNKN as isize;
     }
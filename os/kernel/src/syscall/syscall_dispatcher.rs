@@ -11,42 +11,55 @@ use core::mem::size_of;
 use core::ops::Deref;
 use core::ptr;
 use syscall::NUM_SYSCALLS;
+use syscall::return_vals::Errno;
 use x86_64::registers::control::{Efer, EferFlags};
 use x86_64::registers::model_specific::{KernelGsBase, LStar, SFMask, Star};
 use x86_64::structures::gdt::SegmentSelector;
 use x86_64::{PrivilegeLevel, VirtAddr};
 
+use crate::process::signal;
 use crate::{core_local_storage, tss};
 use log::info;
 use x86_64::registers::rflags::RFlags;
 
 use super::sys_concurrent::{
     sys_process_count, sys_process_execute_binary, sys_process_exit,
-    sys_process_id, sys_thread_count, sys_process_status, 
+    sys_process_execute_sandboxed, sys_process_id, sys_thread_count, sys_process_status, 
     sys_thread_create, sys_thread_exit, sys_thread_id, sys_thread_join, 
-    sys_thread_kill, sys_thread_sleep, sys_thread_switch,
+    sys_thread_kill, sys_thread_sleep, sys_thread_switch, sys_process_usage,
+    sys_thread_set_priority, sys_thread_get_priority, sys_process_set_priority, sys_process_kill,
+    sys_process_spawn, sys_process_wait, sys_futex_wait, sys_futex_wake,
+    sys_thread_set_affinity, sys_thread_get_affinity, sys_process_list,
 };
-use super::sys_graphic::{sys_get_graphic_resolution, sys_write_graphic};
-use super::sys_input::{sys_read_keyboard, sys_read_mouse};
+use super::sys_audio::sys_audio_write;
+use super::sys_event::{sys_event_close, sys_event_create, sys_event_poll, sys_event_read, sys_event_signal};
+use super::sys_graphic::{sys_display_mode_get, sys_display_mode_set, sys_display_modes, sys_get_graphic_resolution, sys_write_graphic};
+use super::sys_input::{sys_read_keyboard, sys_read_mouse, sys_read_mouse_event};
 use super::sys_logger::sys_log;
 use super::sys_naming::{
-    sys_close, sys_cd, sys_cwd, sys_mkdir, sys_mkfifo, sys_open, sys_read,
-    sys_readdir, sys_seek, sys_touch, sys_write,
+    sys_chmod, sys_chown, sys_close, sys_cd, sys_cwd, sys_mkdir, sys_mkfifo, sys_mount, sys_open, sys_read,
+    sys_readdir, sys_rename, sys_seek, sys_stat, sys_touch, sys_umount, sys_unlink, sys_write,
 };
 use super::sys_net::{
     sys_sock_accept, sys_sock_bind, sys_sock_close, sys_sock_connect,
     sys_get_ip_adresses, sys_sock_open, sys_sock_receive, sys_sock_send,
-    sys_sock_can_recv, sys_sock_can_send
+    sys_sock_can_recv, sys_sock_can_send, sys_net_namespace_create, sys_wake_on_lan,
+    sys_sock_set_option, sys_net_address_add, sys_net_address_remove,
+    sys_vsock_listen, sys_vsock_accept, sys_vsock_connect, sys_vsock_send, sys_vsock_receive, sys_vsock_close,
+    sys_network_capabilities, sys_net_path_mtu,
+    sys_net_route_add, sys_net_route_remove, sys_net_routes, sys_net_dns_servers, sys_net_dhcp_lease, sys_net_dhcp_renew,
 };
-use super::sys_system_info::sys_map_build_info;
+use super::sys_system_info::{sys_cpu_park, sys_cpu_sensors, sys_device_rescan, sys_device_stats, sys_devices, sys_map_build_info, sys_power_off};
 use super::sys_terminal::{
     sys_terminal_check_input_state, sys_terminal_read_input,
     sys_terminal_read_output, sys_terminal_write_input,
-    sys_terminal_write_output,
+    sys_terminal_write_output, sys_terminal_set_foreground, sys_terminal_interrupt,
 };
-use super::sys_time::{sys_get_date, sys_get_system_time, sys_set_date};
-use super::sys_vmem::{sys_map_memory, sys_map_frame_buffer};
+use super::sys_time::{sys_get_date, sys_get_system_time, sys_set_date, sys_time_get, sys_time_set};
+use super::sys_vmem::{sys_map_memory, sys_map_frame_buffer, sys_mmap, sys_munmap};
+use super::sys_storage::{sys_ramdisk_create, sys_sync};
 use super::sys_shm::{self, sys_shm_attach, sys_shm_detach, sys_shm_open, sys_shm_unlink};
+use super::sys_random::sys_get_random;
 
 
 pub const CORE_LOCAL_STORAGE_TSS_RSP0_PTR_INDEX: u64 = 0x00;
@@ -169,6 +182,70 @@ impl SyscallTable {
                 sys_shm_attach as *const _,
                 sys_shm_detach as *const _,
                 sys_shm_unlink as *const _,
+                sys_get_random as *const _,
+                sys_device_stats as *const _,
+                sys_net_namespace_create as *const _,
+                sys_wake_on_lan as *const _,
+                sys_power_off as *const _,
+                sys_process_execute_sandboxed as *const _,
+                sys_sock_set_option as *const _,
+                sys_net_address_add as *const _,
+                sys_net_address_remove as *const _,
+                sys_process_usage as *const _,
+                sys_read_mouse_event as *const _,
+                sys_vsock_listen as *const _,
+                sys_vsock_accept as *const _,
+                sys_vsock_connect as *const _,
+                sys_vsock_send as *const _,
+                sys_vsock_receive as *const _,
+                sys_vsock_close as *const _,
+                sys_network_capabilities as *const _,
+                sys_cpu_park as *const _,
+                sys_net_path_mtu as *const _,
+                sys_event_create as *const _,
+                sys_event_signal as *const _,
+                sys_event_read as *const _,
+                sys_event_poll as *const _,
+                sys_event_close as *const _,
+                sys_net_route_add as *const _,
+                sys_net_route_remove as *const _,
+                sys_net_routes as *const _,
+                sys_net_dns_servers as *const _,
+                sys_net_dhcp_lease as *const _,
+                sys_net_dhcp_renew as *const _,
+                sys_time_get as *const _,
+                sys_time_set as *const _,
+                sys_audio_write as *const _,
+                sys_ramdisk_create as *const _,
+                sys_display_modes as *const _,
+                sys_display_mode_get as *const _,
+                sys_display_mode_set as *const _,
+                sys_devices as *const _,
+                sys_device_rescan as *const _,
+                sys_cpu_sensors as *const _,
+                sys_unlink as *const _,
+                sys_rename as *const _,
+                sys_stat as *const _,
+                sys_sync as *const _,
+                sys_mount as *const _,
+                sys_umount as *const _,
+                sys_chmod as *const _,
+                sys_chown as *const _,
+                sys_thread_set_priority as *const _,
+                sys_thread_get_priority as *const _,
+                sys_process_set_priority as *const _,
+                sys_process_kill as *const _,
+                sys_terminal_set_foreground as *const _,
+                sys_terminal_interrupt as *const _,
+                sys_process_spawn as *const _,
+                sys_process_wait as *const _,
+                sys_futex_wait as *const _,
+                sys_futex_wake as *const _,
+                sys_thread_set_affinity as *const _,
+                sys_thread_get_affinity as *const _,
+                sys_process_list as *const _,
+                sys_mmap as *const _,
+                sys_munmap as *const _,
             ],
         }
     }
@@ -216,11 +293,23 @@ unsafe extern "sysv64" fn syscall_handler() {
     "sti",
 
     // Check if system call ID is in bounds
+    // (unknown IDs, e.g. from applications built for a newer kernel, fail with ENOSYS)
     "cmp rax, {NUM_SYSCALLS}",
-    "jge syscall_abort", // Panics and does not return
+    "jb 2f",
+    "mov rax, {ENOSYS}",
+    "jmp 3f",
 
     // Call system call handler, corresponding to ID (in rax)
+    "2:",
     "call [{SYSCALL_TABLE} + 8 * rax]",
+    "3:",
+
+    // Deliver pending signals (might terminate the process and not return)
+    "push rax",
+    "push rdx",
+    "call {DELIVER_SIGNALS}",
+    "pop rdx",
+    "pop rax",
 
     // Restore registers
     "pop r11", // Pop the alignment 0
@@ -241,22 +330,10 @@ unsafe extern "sysv64" fn syscall_handler() {
     // Interrupts will be enabled automatically, because rflags is restored from r11
     "sysretq",
     NUM_SYSCALLS = const NUM_SYSCALLS,
+    ENOSYS = const Errno::ENOSYS as isize,
     CORE_LOCAL_STORAGE_TSS_RSP0_PTR_INDEX = const CORE_LOCAL_STORAGE_TSS_RSP0_PTR_INDEX,
     CORE_LOCAL_STORAGE_USER_RSP_INDEX = const CORE_LOCAL_STORAGE_USER_RSP_INDEX,
-    SYSCALL_TABLE = sym SYSCALL_TABLE
+    SYSCALL_TABLE = sym SYSCALL_TABLE,
+    DELIVER_SIGNALS = sym signal::deliver
     );
 }
-
-#[unsafe(no_mangle)]
-#[unsafe(naked)]
-unsafe extern "C" fn syscall_abort() {
-    naked_asm!(
-        "mov rdi, rax", // Move syscall number to first argument (rdi)
-        "call syscall_abort_panic"
-    )
-}
-
-#[unsafe(no_mangle)]
-unsafe extern "C" fn syscall_abort_panic(syscall_number: u64) {
-    panic!("System call with id [{}] does not exist!", syscall_number);
-}
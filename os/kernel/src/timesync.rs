@@ -0,0 +1,85 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: timesync                                                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Detection of time jumps after the guest has been paused (e.g.   ║
+   ║         suspend/resume or live migration of the virtual machine).       ║
+   ║         With a pvclock, the timer already leaves out the paused time,   ║
+   ║         so the system time (and thus sleeping threads, network polling  ║
+   ║         and TCP timers and timestamps) continues where it stopped.      ║
+   ║         Without one, the system time is compared to the RTC: If it has  ║
+   ║         run ahead (because lost timer interrupts have been re-injected  ║
+   ║         at once), the wakeup of sleeping threads is postponed.          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use log::{info, warn};
+use crate::device::pit::ClockSource;
+use crate::device::pvclock::pvclock;
+use crate::device::rtc;
+use crate::process::thread::Thread;
+use crate::{scheduler, timer};
+use syscall::priority::Priority;
+
+/// How often the clocks are compared
+const SYNC_INTERVAL_MS: usize = 1000;
+/// The RTC only has a resolution of one second, so smaller differences are ignored
+const MAX_RTC_SKEW_MS: usize = 2000;
+
+/// Start the thread watching for time jumps.
+pub fn init() {
+    let thread = Thread::new_kernel_thread(sync, "timesync");
+    thread.set_priority(Priority::Background);
+    scheduler().ready(thread);
+}
+
+extern "sysv64" fn sync() {
+    let mut last_paused_ms = timer().paused_ms();
+    let mut last_systime_ms = timer().systime_ms();
+    let mut last_rtc_ms = rtc_ms();
+    let mut last_rtc_writes = rtc::write_count();
+
+    loop {
+        scheduler().sleep(SYNC_INTERVAL_MS);
+        let systime_ms = timer().systime_ms();
+        let rtc = rtc_ms();
+        let rtc_writes = rtc::write_count();
+
+        match pvclock().filter(|_| timer().clock_source() == ClockSource::Pvclock) {
+            Some(clock) => {
+                let paused_ms = timer().paused_ms();
+                let stopped = clock.take_guest_stopped();
+                if paused_ms != last_paused_ms {
+                    info!("Guest has been paused for [{} ms], which is left out of the system time", paused_ms - last_paused_ms);
+                } else if stopped {
+                    info!("Guest has been paused briefly");
+                }
+                last_paused_ms = paused_ms;
+            }
+            // the RTC has been set in between, so it can't be compared
+            None if rtc_writes != last_rtc_writes => {}
+            None => {
+                if let (Some(rtc), Some(last_rtc)) = (rtc, last_rtc_ms) {
+                    let systime_delta = systime_ms - last_systime_ms;
+                    let rtc_delta = rtc.saturating_sub(last_rtc);
+                    if systime_delta > rtc_delta + MAX_RTC_SKEW_MS {
+                        let skew = systime_delta - rtc_delta.max(SYNC_INTERVAL_MS);
+                        warn!("System time has run ahead of the RTC by [{} ms], postponing timeouts", skew);
+                        scheduler().postpone_timeouts(skew);
+                    } else if rtc_delta > systime_delta + MAX_RTC_SKEW_MS {
+                        info!("RTC has run ahead of the system time by [{} ms] (guest has been paused)", rtc_delta - systime_delta);
+                    }
+                }
+            }
+        }
+
+        last_systime_ms = systime_ms;
+        last_rtc_ms = rtc;
+        last_rtc_writes = rtc_writes;
+    }
+}
+
+/// Read the RTC (in milliseconds).
+fn rtc_ms() -> Option<usize> {
+    usize::try_from(rtc::read()?.and_utc().timestamp_millis()).ok()
+}
@@ -0,0 +1,70 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: wallclock                                                       ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Wall clock time (since the Unix epoch). The RTC is read once at ║
+   ║         boot and the time is then kept as an offset to the system time, ║
+   ║         which is much more precise than the RTC and cheaper to read.    ║
+   ║         Time left out of the system time while the guest was paused is  ║
+   ║         added back, so the wall clock continues to match real time.     ║
+   ║         Setting the time moves the offset and writes the RTC.           ║
+   ║                                                                         ║
+   ║ Public functions                                                        ║
+   ║   - init                 read the RTC (must be called after the timer)  ║
+   ║   - now                  get the current time                           ║
+   ║   - set                  set the current time                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use chrono::DateTime;
+use core::sync::atomic::{AtomicI64, Ordering};
+use log::{info, warn};
+use syscall::return_vals::Errno;
+use syscall::time::Timespec;
+use crate::device::rtc;
+use crate::timer;
+
+const NANOS_PER_MS: i128 = 1_000_000;
+
+/// Wall clock time at system time 0 (in nanoseconds since the epoch)
+static BOOT_TIME_NS: AtomicI64 = AtomicI64::new(0);
+
+/// Initialize the wall clock from the RTC. Without a readable RTC, the clock starts at the epoch.
+pub fn init() {
+    match rtc::read() {
+        Some(time) => {
+            let boot_time = time.and_utc().timestamp_nanos_opt().unwrap_or(0) - elapsed_ns() as i64;
+            BOOT_TIME_NS.store(boot_time, Ordering::Relaxed);
+            info!("Wall clock time is [{}]", time);
+        }
+        None => warn!("Failed to read the RTC, wall clock starts at the epoch"),
+    }
+}
+
+/// Get the current wall clock time.
+pub fn now() -> Timespec {
+    Timespec::from_nanos(BOOT_TIME_NS.load(Ordering::Relaxed) as i128 + elapsed_ns())
+}
+
+/// Set the wall clock to `time` and write it to the RTC.
+/// Fails with `EINVAL`, if the time is out of range (roughly the years 1678 to 2262 and those the RTC can store).
+pub fn set(time: Timespec) -> Result<(), Errno> {
+    if time.nanoseconds >= Timespec::NANOS_PER_SECOND {
+        return Err(Errno::EINVAL);
+    }
+    let boot_time = i64::try_from(time.as_nanos() - elapsed_ns()).map_err(|_| Errno::EINVAL)?;
+    let date = DateTime::from_timestamp(time.seconds, time.nanoseconds).ok_or(Errno::EINVAL)?;
+    if !rtc::write(&date.naive_utc()) {
+        return Err(Errno::EINVAL);
+    }
+
+    BOOT_TIME_NS.store(boot_time, Ordering::Relaxed);
+    info!("Wall clock set to [{}]", date);
+    Ok(())
+}
+
+/// Time since system time 0, including the time the guest has been paused
+fn elapsed_ns() -> i128 {
+    let timer = timer();
+    timer.systime_ns() as i128 + timer.paused_ms() as i128 * NANOS_PER_MS
+}
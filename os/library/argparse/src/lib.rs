@@ -0,0 +1,383 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: lib                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Command line argument parser for applications. A `Parser`       ║
+   ║         describes flags (-v, --verbose), options with a value           ║
+   ║         (-n 5, -n5, --count 5, --count=5) and positional arguments.     ║
+   ║         Short flags can be combined (-rv), '--' ends the options and    ║
+   ║         '-h'/'--help' are handled automatically. The help text is       ║
+   ║         generated from the descriptions.                                ║
+   ║                                                                         ║
+   ║         let parser = Parser::new("heapprof", "Show live allocations")   ║
+   ║             .option(Some('n'), "top", "N", "Number of call stacks")     ║
+   ║             .positional("pid", "Id of the profiled process");           ║
+   ║         let matches = match parser.parse(env::args().skip(1)) { .. };   ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+#![no_std]
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+/// Description of a flag or an option (a flag with a value)
+struct Opt {
+    short: Option<char>,
+    long: &'static str,
+    /// Name of the value shown in the help (`None` for flags)
+    value: Option<&'static str>,
+    help: &'static str,
+}
+
+struct Positional {
+    name: &'static str,
+    help: &'static str,
+    required: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// '-h' or '--help' has been given (the caller should print `Parser::help()`)
+    Help,
+    UnknownOption(String),
+    MissingValue(String),
+    /// A value has been given for a flag (e.g. '--verbose=yes')
+    UnexpectedValue(String),
+    MissingArgument(&'static str),
+    TooManyArguments(String),
+    /// Returned by `Matches::parse_value()`, if the value can't be converted
+    InvalidValue { name: &'static str, value: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Help => write!(f, "Help requested"),
+            ParseError::UnknownOption(option) => write!(f, "Unknown option '{}'", option),
+            ParseError::MissingValue(option) => write!(f, "Option '{}' requires a value", option),
+            ParseError::UnexpectedValue(option) => write!(f, "Option '{}' doesn't take a value", option),
+            ParseError::MissingArgument(name) => write!(f, "Missing argument <{}>", name),
+            ParseError::TooManyArguments(argument) => write!(f, "Unexpected argument '{}'", argument),
+            ParseError::InvalidValue { name, value } => write!(f, "Invalid value '{}' for '{}'", value, name),
+        }
+    }
+}
+
+/// Description of the arguments of an application.
+pub struct Parser {
+    program: &'static str,
+    description: &'static str,
+    options: Vec<Opt>,
+    positionals: Vec<Positional>,
+    /// Name of additional positional arguments (if they are allowed)
+    rest: Option<(&'static str, &'static str)>,
+}
+
+/// Result of `Parser::parse()`. Flags and options are looked up by their long name, positional arguments by their name.
+#[derive(Debug, Default)]
+pub struct Matches {
+    flags: BTreeMap<&'static str, usize>,
+    values: BTreeMap<&'static str, Vec<String>>,
+    rest: Vec<String>,
+}
+
+impl Parser {
+    pub fn new(program: &'static str, description: &'static str) -> Self {
+        Self { program, description, options: Vec::new(), positionals: Vec::new(), rest: None }
+    }
+
+    /// Add a flag without a value (e.g. `-v`/`--verbose`).
+    pub fn flag(mut self, short: Option<char>, long: &'static str, help: &'static str) -> Self {
+        self.options.push(Opt { short, long, value: None, help });
+        self
+    }
+
+    /// Add an option with a value (e.g. `-n 5`/`--count=5`). `value` is the name of the value in the help.
+    pub fn option(mut self, short: Option<char>, long: &'static str, value: &'static str, help: &'static str) -> Self {
+        self.options.push(Opt { short, long, value: Some(value), help });
+        self
+    }
+
+    /// Add a required positional argument. Must not follow optional ones.
+    pub fn positional(mut self, name: &'static str, help: &'static str) -> Self {
+        assert!(self.positionals.iter().all(|positional| positional.required), "Required argument <{}> after optional ones", name);
+        self.positionals.push(Positional { name, help, required: true });
+        self
+    }
+
+    /// Add an optional positional argument.
+    pub fn optional_positional(mut self, name: &'static str, help: &'static str) -> Self {
+        self.positionals.push(Positional { name, help, required: false });
+        self
+    }
+
+    /// Allow any number of additional positional arguments (returned by `Matches::rest()`).
+    pub fn rest(mut self, name: &'static str, help: &'static str) -> Self {
+        self.rest = Some((name, help));
+        self
+    }
+
+    /// Parse `args` (without the program name, e.g. `env::args().skip(1)`).
+    pub fn parse<I: IntoIterator<Item = String>>(&self, args: I) -> Result<Matches, ParseError> {
+        let mut args = args.into_iter();
+        let mut matches = Matches::default();
+        let mut positionals = Vec::new();
+        let mut only_positionals = false;
+
+        while let Some(arg) = args.next() {
+            if only_positionals || !self.is_option(&arg) {
+                positionals.push(arg);
+            } else if arg == "--" {
+                only_positionals = true;
+            } else if let Some(long) = arg.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let opt = match self.options.iter().find(|opt| opt.long == name) {
+                    Some(opt) => opt,
+                    None if name == "help" => return Err(ParseError::Help),
+                    None => return Err(ParseError::UnknownOption(format!("--{}", name))),
+                };
+                Self::apply(opt, value, &mut args, &mut matches)?;
+            } else {
+                // group of short options (e.g. "-rv" or "-n5")
+                for (index, short) in arg.char_indices().skip(1) {
+                    let opt = match self.options.iter().find(|opt| opt.short == Some(short)) {
+                        Some(opt) => opt,
+                        None if short == 'h' => return Err(ParseError::Help),
+                        None => return Err(ParseError::UnknownOption(format!("-{}", short))),
+                    };
+
+                    if opt.value.is_some() {
+                        // the rest of the group is the value
+                        let value = &arg[index + short.len_utf8()..];
+                        Self::apply(opt, (!value.is_empty()).then(|| value.to_string()), &mut args, &mut matches)?;
+                        break;
+                    }
+                    Self::apply(opt, None, &mut args, &mut matches)?;
+                }
+            }
+        }
+
+        let mut positionals = positionals.into_iter();
+        for positional in self.positionals.iter() {
+            match positionals.next() {
+                Some(value) => matches.values.entry(positional.name).or_default().push(value),
+                None if positional.required => return Err(ParseError::MissingArgument(positional.name)),
+                None => break,
+            }
+        }
+        matches.rest = positionals.collect();
+        if self.rest.is_none() && let Some(argument) = matches.rest.first() {
+            return Err(ParseError::TooManyArguments(argument.clone()));
+        }
+
+        Ok(matches)
+    }
+
+    /// One line overview of the arguments (e.g. "Usage: heapprof [options] <pid>").
+    pub fn usage(&self) -> String {
+        let mut usage = format!("Usage: {} [options]", self.program);
+        for positional in self.positionals.iter() {
+            match positional.required {
+                true => usage.push_str(&format!(" <{}>", positional.name)),
+                false => usage.push_str(&format!(" [{}]", positional.name)),
+            }
+        }
+        if let Some((name, _)) = self.rest {
+            usage.push_str(&format!(" [{}...]", name));
+        }
+
+        usage
+    }
+
+    /// Usage, description and a list of all arguments.
+    pub fn help(&self) -> String {
+        let mut arguments: Vec<(String, &str)> = self.positionals.iter()
+            .map(|positional| (format!("<{}>", positional.name), positional.help))
+            .collect();
+        if let Some((name, help)) = self.rest {
+            arguments.push((format!("[{}...]", name), help));
+        }
+
+        let mut options: Vec<(String, &str)> = self.options.iter()
+            .map(|opt| {
+                let short = opt.short.map(|short| format!("-{}, ", short)).unwrap_or_else(|| String::from("    "));
+                let value = opt.value.map(|value| format!(" <{}>", value)).unwrap_or_default();
+                (format!("{}--{}{}", short, opt.long, value), opt.help)
+            })
+            .collect();
+        if !self.options.iter().any(|opt| opt.long == "help") {
+            let short = if self.options.iter().any(|opt| opt.short == Some('h')) { "    " } else { "-h, " };
+            options.push((format!("{}--help", short), "Show this help"));
+        }
+
+        let width = arguments.iter().chain(options.iter()).map(|(name, _)| name.len()).max().unwrap_or(0) + 2;
+        let mut help = format!("{}\n\n{}\n", self.usage(), self.description);
+        if !arguments.is_empty() {
+            help.push_str("\nArguments:\n");
+            arguments.iter().for_each(|(name, text)| help.push_str(&format!("  {:<width$}{}\n", name, text, width = width)));
+        }
+        help.push_str("\nOptions:\n");
+        options.iter().for_each(|(name, text)| help.push_str(&format!("  {:<width$}{}\n", name, text, width = width)));
+
+        help
+    }
+
+    /// Text to show the user for the result of `parse()`: the help for `ParseError::Help`, otherwise the error and the usage.
+    pub fn report(&self, error: &ParseError) -> String {
+        match error {
+            ParseError::Help => self.help(),
+            error => format!("{}\n{}\nTry '{} --help' for more information.", error, self.usage(), self.program),
+        }
+    }
+
+    /// Everything starting with '-' is an option, except for '-' itself and negative numbers (if there is no digit flag).
+    fn is_option(&self, arg: &str) -> bool {
+        let Some(rest) = arg.strip_prefix('-') else {
+            return false;
+        };
+        match rest.chars().next() {
+            None => false,
+            Some(first) if first.is_ascii_digit() => self.options.iter().any(|opt| opt.short == Some(first)),
+            Some(_) => true,
+        }
+    }
+
+    fn apply(opt: &Opt, value: Option<String>, args: &mut impl Iterator<Item = String>, matches: &mut Matches) -> Result<(), ParseError> {
+        if opt.value.is_none() {
+            if value.is_some() {
+                return Err(ParseError::UnexpectedValue(format!("--{}", opt.long)));
+            }
+            *matches.flags.entry(opt.long).or_default() += 1;
+            return Ok(());
+        }
+
+        let value = value.or_else(|| args.next()).ok_or_else(|| ParseError::MissingValue(format!("--{}", opt.long)))?;
+        matches.values.entry(opt.long).or_default().push(value);
+        Ok(())
+    }
+}
+
+impl Matches {
+    /// Has the flag `long` been given?
+    pub fn flag(&self, long: &str) -> bool {
+        self.count(long) > 0
+    }
+
+    /// How often has the flag `long` been given (e.g. 2 for "-vv")?
+    pub fn count(&self, long: &str) -> usize {
+        self.flags.get(long).copied().unwrap_or(0)
+    }
+
+    /// Value of the option or positional argument `name` (the last one, if the option has been given several times).
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values.get(name).and_then(|values| values.last()).map(String::as_str)
+    }
+
+    /// All values of the option `name` in the given order.
+    pub fn values(&self, name: &str) -> impl Iterator<Item = &str> {
+        self.values.get(name).into_iter().flatten().map(String::as_str)
+    }
+
+    /// Convert the value of `name` with `FromStr` (`Ok(None)`, if it hasn't been given).
+    pub fn parse_value<T: FromStr>(&self, name: &'static str) -> Result<Option<T>, ParseError> {
+        self.value(name)
+            .map(|value| value.parse().map_err(|_| ParseError::InvalidValue { name, value: value.to_string() }))
+            .transpose()
+    }
+
+    /// Convert the value of a required argument `name` with `FromStr`.
+    pub fn required<T: FromStr>(&self, name: &'static str) -> Result<T, ParseError> {
+        self.parse_value(name)?.ok_or(ParseError::MissingArgument(name))
+    }
+
+    /// Additional positional arguments (see `Parser::rest()`).
+    pub fn rest(&self) -> &[String] {
+        &self.rest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn parser() -> Parser {
+        Parser::new("test", "Test program")
+            .flag(Some('v'), "verbose", "Print more")
+            .flag(Some('r'), "reverse", "Reverse")
+            .option(Some('n'), "count", "N", "Number of items")
+            .option(None, "name", "NAME", "Name")
+            .positional("input", "Input file")
+            .optional_positional("output", "Output file")
+    }
+
+    fn parse(parser: &Parser, args: &[&str]) -> Result<Matches, ParseError> {
+        parser.parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn flags_and_options() {
+        let matches = parse(&parser(), &["-rv", "-n5", "--name=foo", "in", "-v", "out"]).unwrap();
+        assert!(matches.flag("reverse"));
+        assert_eq!(matches.count("verbose"), 2);
+        assert_eq!(matches.parse_value::<u32>("count"), Ok(Some(5)));
+        assert_eq!(matches.value("name"), Some("foo"));
+        assert_eq!(matches.value("input"), Some("in"));
+        assert_eq!(matches.value("output"), Some("out"));
+
+        let matches = parse(&parser(), &["--count", "3", "-n", "4", "in"]).unwrap();
+        assert_eq!(matches.values("count").collect::<Vec<_>>(), vec!["3", "4"]);
+        assert_eq!(matches.value("count"), Some("4"));
+        assert_eq!(matches.value("output"), None);
+    }
+
+    #[test]
+    fn positionals() {
+        let matches = parse(&parser(), &["--", "-v", "-1"]).unwrap();
+        assert!(!matches.flag("verbose"));
+        assert_eq!(matches.value("input"), Some("-v"));
+        assert_eq!(matches.value("output"), Some("-1"));
+
+        let matches = parse(&parser(), &["-5", "-"]).unwrap();
+        assert_eq!(matches.value("input"), Some("-5"));
+        assert_eq!(matches.value("output"), Some("-"));
+
+        let matches = parse(&parser().rest("files", "More files"), &["a", "b", "c", "d"]).unwrap();
+        assert_eq!(matches.rest(), ["c", "d"]);
+    }
+
+    #[test]
+    fn errors() {
+        let parser = parser();
+        assert_eq!(parse(&parser, &[]).unwrap_err(), ParseError::MissingArgument("input"));
+        assert_eq!(parse(&parser, &["a", "b", "c"]).unwrap_err(), ParseError::TooManyArguments("c".to_string()));
+        assert_eq!(parse(&parser, &["-x", "a"]).unwrap_err(), ParseError::UnknownOption("-x".to_string()));
+        assert_eq!(parse(&parser, &["a", "-n"]).unwrap_err(), ParseError::MissingValue("--count".to_string()));
+        assert_eq!(parse(&parser, &["--verbose=1", "a"]).unwrap_err(), ParseError::UnexpectedValue("--verbose".to_string()));
+        assert_eq!(parse(&parser, &["a", "-vh"]).unwrap_err(), ParseError::Help);
+        assert_eq!(parse(&parser, &["--help"]).unwrap_err(), ParseError::Help);
+        assert!(parse(&parser, &["-n", "x", "a"]).unwrap().parse_value::<u32>("count").is_err());
+        assert_eq!(parse(&parser, &["a"]).unwrap().required::<u32>("count"), Err(ParseError::MissingArgument("count")));
+    }
+
+    #[test]
+    fn help() {
+        let parser = parser();
+        assert_eq!(parser.usage(), "Usage: test [options] <input> [output]");
+        let help = parser.help();
+        assert!(help.contains("  -n, --count <N>    Number of items\n"));
+        assert!(help.contains("      --name <NAME>  Name\n"));
+        assert!(help.contains("  <input>            Input file\n"));
+        assert!(help.contains("  -h, --help         Show this help\n"));
+    }
+}
@@ -0,0 +1,72 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: event                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Event counters, which are incremented by the kernel (timers,    ║
+   ║         device hotplug, link state, DHCP leases) or by other threads,   ║
+   ║         and can be waited on together with `poll`.                      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::vec::Vec;
+use core::time::Duration;
+use syscall::{SystemCall, syscall, return_vals::Errno};
+use syscall::event::{PollEntry, POLL_FOREVER};
+
+pub use syscall::event::EventSource;
+
+/// An event counter of the current process. It is closed, when dropped.
+pub struct EventCounter {
+    handle: usize,
+}
+
+impl EventCounter {
+    /// Create a counter, that is incremented by the kernel on each event of `source`
+    /// (or only by `signal` for `EventSource::User`).
+    pub fn new(source: EventSource) -> Result<Self, Errno> {
+        Self::create(source, 0)
+    }
+
+    /// Create a counter, that is incremented every `interval`.
+    pub fn timer(interval: Duration) -> Result<Self, Errno> {
+        Self::create(EventSource::Timer, interval.as_millis() as usize)
+    }
+
+    fn create(source: EventSource, interval_ms: usize) -> Result<Self, Errno> {
+        let handle = syscall(SystemCall::EventCreate, &[source.into(), interval_ms])?;
+        Ok(Self { handle })
+    }
+
+    /// Increment the counter by `count`.
+    pub fn signal(&self, count: usize) -> Result<(), Errno> {
+        syscall(SystemCall::EventSignal, &[self.handle, count]).map(|_| ())
+    }
+
+    /// Get the number of events since the last read and reset the counter (without blocking).
+    pub fn read(&self) -> Result<usize, Errno> {
+        syscall(SystemCall::EventRead, &[self.handle])
+    }
+
+    /// Wait until the counter is non-zero and then read it.
+    pub fn wait(&self) -> Result<usize, Errno> {
+        poll(&[self], None)?;
+        self.read()
+    }
+}
+
+impl Drop for EventCounter {
+    fn drop(&mut self) {
+        let _ = syscall(SystemCall::EventClose, &[self.handle]);
+    }
+}
+
+/// Wait until at least one of `counters` is non-zero, but at most `timeout` (forever, if `None`).
+/// Returns, which counters are non-zero (all `false` after a timeout). The counters are not reset.
+pub fn poll(counters: &[&EventCounter], timeout: Option<Duration>) -> Result<Vec<bool>, Errno> {
+    let mut entries: Vec<PollEntry> = counters.iter()
+        .map(|counter| PollEntry { handle: counter.handle, ready: false })
+        .collect();
+    let timeout_ms = timeout.map_or(POLL_FOREVER, |timeout| (timeout.as_millis() as usize).min(POLL_FOREVER - 1));
+    syscall(SystemCall::EventPoll, &[entries.as_mut_ptr() as usize, entries.len(), timeout_ms])?;
+    Ok(entries.iter().map(|entry| entry.ready).collect())
+}
@@ -0,0 +1,33 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: futex                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Waiting for a 32 bit value to change without spinning. These    ║
+   ║         are the building blocks for `runtime::sync::Mutex` and          ║
+   ║         `Condvar`. Wakeups may be spurious, so the value has to be      ║
+   ║         checked again after `wait` has returned.                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::sync::atomic::AtomicU32;
+use core::time::Duration;
+use syscall::{SystemCall, syscall, return_vals::Errno};
+use syscall::futex::{WAIT_FOREVER, WAKE_ALL};
+
+/// Block, while `futex` has the value `expected`, until another thread calls `wake` for it,
+/// but at most `timeout` (`None` waits without a timeout). \
+/// Fails with `EAGAIN`, if the value is different, and with `ETIMEDOUT` after the timeout.
+pub fn wait(futex: &AtomicU32, expected: u32, timeout: Option<Duration>) -> Result<(), Errno> {
+    let timeout_ms = timeout.map_or(WAIT_FOREVER, |timeout| (timeout.as_millis() as usize).min(WAIT_FOREVER - 1));
+    syscall(SystemCall::FutexWait, &[futex.as_ptr() as usize, expected as usize, timeout_ms]).map(|_| ())
+}
+
+/// Wake up at most `count` threads waiting for `futex` and return their number.
+pub fn wake(futex: &AtomicU32, count: usize) -> usize {
+    syscall(SystemCall::FutexWake, &[futex.as_ptr() as usize, count]).unwrap_or(0)
+}
+
+/// Wake up all threads waiting for `futex` and return their number.
+pub fn wake_all(futex: &AtomicU32) -> usize {
+    wake(futex, WAKE_ALL)
+}
@@ -5,3 +5,6 @@ extern crate alloc;
 pub mod process;
 pub mod thread;
 pub mod shm;
+pub mod event;
+pub mod futex;
+pub mod memory;
@@ -0,0 +1,25 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: memory                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Map and unmap anonymous memory. The pages are zeroed and only   ║
+   ║         backed by physical memory, when they are accessed for the       ║
+   ║         first time.                                                     ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use syscall::memory::{MapFlags, Protection};
+use syscall::return_vals::Errno;
+use syscall::{syscall, SystemCall};
+
+/// Map `len` bytes (rounded up to whole pages) of anonymous memory with the access rights `prot`. \
+/// Returns the page aligned start address or `ENOMEM`, e.g. if the memory limit of the sandbox is reached.
+pub fn mmap(len: usize, prot: Protection) -> Result<*mut u8, Errno> {
+    syscall(SystemCall::MemoryMap, &[len, prot.bits(), MapFlags::ANONYMOUS.bits()]).map(|addr| addr as *mut u8)
+}
+
+/// Unmap the pages in [`addr`, `addr` + `len`), which must have been mapped with `mmap()` (`addr` must be page aligned). \
+/// Parts of a mapping may be unmapped, the rest stays accessible.
+pub fn munmap(addr: *mut u8, len: usize) -> Result<(), Errno> {
+    syscall(SystemCall::MemoryUnmap, &[addr as usize, len]).map(|_| ())
+}
@@ -6,7 +6,15 @@
    ║ Author: Fabian Ruhland, Michael Schoettner, 26.12.2025, HHU             ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
+use alloc::vec;
+use alloc::vec::Vec;
 use syscall::{SystemCall, return_vals::Errno, syscall};
+use syscall::priority::Priority;
+use syscall::process_info::ThreadInfo;
+use syscall::signal::Signal;
+use syscall::usage::{ResourceUsage, USAGE_SELF};
+
+use crate::thread::Thread;
 
 pub struct Process {
     id: usize,
@@ -20,6 +28,17 @@ impl Process {
     pub fn id(&self) -> usize {
         self.id
     }
+
+    /// Set the priority of all threads of this process, including the ones created later.
+    /// Only root may raise the priority above `Priority::Normal` or change other processes.
+    pub fn set_priority(&self, priority: Priority) -> Result<(), Errno> {
+        syscall(SystemCall::ProcessSetPriority, &[self.id, priority.into()]).map(|_| ())
+    }
+
+    /// Send `signal` to this process. Only root may signal processes of other users.
+    pub fn kill(&self, signal: Signal) -> Result<(), Errno> {
+        kill(self.id, signal)
+    }
 }
 
 pub fn current() -> Option<Process> {
@@ -30,6 +49,11 @@ pub fn current() -> Option<Process> {
     }    
 }
 
+/// Send `signal` to the process `id` (see `Process::kill()`).
+pub fn kill(id: usize, signal: Signal) -> Result<(), Errno> {
+    syscall(SystemCall::ProcessKill, &[id, signal.into()]).map(|_| ())
+}
+
 pub fn exit() {
     syscall(SystemCall::ProcessExit, &[]).expect("Failed to exit process");
 }
@@ -49,3 +73,33 @@ pub fn ps(buf: &mut [u8]) -> Result<usize, Errno> {
     ])
 }
 
+/// Get a snapshot of all threads with their process (id, name, state, priority, memory and CPU time).
+pub fn list() -> Result<Vec<ThreadInfo>, Errno> {
+    loop {
+        let count = syscall(SystemCall::ProcessList, &[0, 0])?;
+        // leave room for threads started in the meantime
+        let mut threads = vec![ThreadInfo::default(); count + 8];
+        let total = syscall(SystemCall::ProcessList, &[threads.as_mut_ptr() as usize, threads.len()])?;
+        if total <= threads.len() {
+            threads.truncate(total);
+            return Ok(threads);
+        }
+    }
+}
+
+/// Get the resource usage (CPU time, page faults, ...) of the calling process.
+pub fn usage() -> Result<ResourceUsage, Errno> {
+    get_usage(USAGE_SELF)
+}
+
+/// Get the resource usage of a child process, that has been started by `thread::start_application()`
+/// and has exited (e.g. after joining `child`). It can only be retrieved once.
+pub fn child_usage(child: &Thread) -> Result<ResourceUsage, Errno> {
+    get_usage(child.id())
+}
+
+fn get_usage(who: usize) -> Result<ResourceUsage, Errno> {
+    let mut usage = ResourceUsage::default();
+    syscall(SystemCall::ProcessUsage, &[who, &raw mut usage as usize])?;
+    Ok(usage)
+}
@@ -6,13 +6,16 @@
    ║ Author: Fabian Ruhland, Michael Schoettner, 04.01.2026, HHU             ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
-use alloc::boxed::Box;
 use alloc::vec::Vec;
 use core::arch::asm;
 use core::ptr;
 use chrono::TimeDelta;
 use time::systime;
 use syscall::{SystemCall, syscall,return_vals::Errno};
+use syscall::priority::{Priority, SchedulingClass};
+pub use syscall::cpu::AFFINITY_ALL;
+use syscall::sandbox::{Capabilities, SandboxConfig, KEEP_ID};
+use syscall::tls::TCB_SIZE;
 
 pub struct Thread {
     id: usize,
@@ -23,6 +26,17 @@ pub struct ThreadEnvironment {
     start_time: TimeDelta,
 }
 
+/// Thread control block, which the FS base points to. It is created by the kernel directly behind the thread-local variables
+/// (see `syscall::tls`), so `#[thread_local]` statics can be used from the first instruction on.
+#[repr(C)]
+struct ThreadControlBlock {
+    /// Address of the block itself, as required by the TLS ABI
+    this: *mut ThreadControlBlock,
+    env: ThreadEnvironment,
+}
+
+const _: () = assert!(size_of::<ThreadControlBlock>() <= TCB_SIZE);
+
 impl Thread {
     const fn new(id: usize) -> Self {
         Self { id }
@@ -40,6 +54,34 @@ impl Thread {
         let _ = syscall(SystemCall::ThreadKill, &[self.id]);
     }
 
+    /// Only root may raise the priority above `Priority::Normal` or select `SchedulingClass::Strict`.
+    pub fn set_priority(&self, priority: Priority, class: SchedulingClass) -> Result<(), Errno> {
+        syscall(SystemCall::ThreadSetPriority, &[self.id, priority.into(), class.into()]).map(|_| ())
+    }
+
+    pub fn priority(&self) -> Result<Priority, Errno> {
+        let priority = syscall(SystemCall::ThreadGetPriority, &[self.id, 0])?;
+        Priority::try_from(priority).map_err(|_| Errno::EUNKN)
+    }
+
+    pub fn scheduling_class(&self) -> Result<SchedulingClass, Errno> {
+        let mut class = 0usize;
+        syscall(SystemCall::ThreadGetPriority, &[self.id, ptr::from_mut(&mut class) as usize])?;
+        SchedulingClass::try_from(class).map_err(|_| Errno::EUNKN)
+    }
+
+    /// Only run the thread on the cores in `affinity` (bit n = core with local APIC id n, `AFFINITY_ALL` for all cores).
+    /// Fails with `EINVAL`, if none of them runs threads. Only root may change threads of other processes.
+    pub fn set_affinity(&self, affinity: u64) -> Result<(), Errno> {
+        syscall(SystemCall::ThreadSetAffinity, &[self.id, affinity as usize]).map(|_| ())
+    }
+
+    pub fn affinity(&self) -> Result<u64, Errno> {
+        let mut affinity = 0u64;
+        syscall(SystemCall::ThreadGetAffinity, &[self.id, ptr::from_mut(&mut affinity) as usize])?;
+        Ok(affinity)
+    }
+
     pub fn start_time(&self) -> TimeDelta {
         let thread_env = thread_environment();
         thread_env.start_time
@@ -47,34 +89,24 @@ impl Thread {
 }
 
 pub fn thread_environment() -> &'static mut ThreadEnvironment {
-    let thread_env: *mut ThreadEnvironment;
+    let tcb: *mut ThreadControlBlock;
 
     unsafe {
         asm!(
-        "rdfsbase {0}",
-        out(reg) thread_env,
+        "mov {0}, fs:0",
+        out(reg) tcb,
         );
 
-        &mut *thread_env
+        &mut (*tcb).env
     }
 }
 
 pub fn init_thread_environment() {
-    let thread_env = Box::new(ThreadEnvironment {
-        start_time: systime(),
-    });
-
-    let thread_env_ptr = Box::into_raw(thread_env);
-    unsafe {
-        asm!(
-        "wrfsbase {0}",
-        in(reg) thread_env_ptr,
-        );
-    }
+    thread_environment().start_time = systime();
 }
 
 extern "sysv64" fn kickoff_user_thread(entry: extern "sysv64" fn()) {
-    // set up the thread environment, which is stored at FS:0
+    // set up the thread environment, which is stored in the thread control block at FS:0
     init_thread_environment();
 
     // entry has no parameters, so we don't really need to ensure the calling convention
@@ -82,6 +114,13 @@ extern "sysv64" fn kickoff_user_thread(entry: extern "sysv64" fn()) {
     exit();
 }
 
+extern "sysv64" fn kickoff_user_thread_with_arg(entry: extern "sysv64" fn(usize), arg: usize) {
+    init_thread_environment();
+
+    entry(arg);
+    exit();
+}
+
 pub fn create(entry: fn()) -> Option<Thread> {
     let res = syscall(SystemCall::ThreadCreate, &[kickoff_user_thread as *const () as usize,
         entry as usize,]);
@@ -91,6 +130,16 @@ pub fn create(entry: fn()) -> Option<Thread> {
     }    
 }
 
+/// Create a thread calling `entry(arg)` with a user stack of `stack_size` bytes (rounded up to pages, 0 = default size of 1 GiB).
+/// The stack is only mapped as needed and freed, when the thread exits.
+pub fn create_with_arg(entry: extern "sysv64" fn(usize), arg: usize, stack_size: usize) -> Result<Thread, Errno> {
+    syscall(SystemCall::ThreadCreate, &[kickoff_user_thread_with_arg as *const () as usize,
+        entry as usize,
+        arg,
+        stack_size,
+    ]).map(Thread::new)
+}
+
 pub fn current() -> Option<Thread> {
     let res = syscall(SystemCall::ThreadId, &[]);
     match res {
@@ -127,3 +176,45 @@ pub fn start_application(name: &str, args: Vec<&str>) -> Option<Thread> {
         Err(_) => None,
     }    
 }
+
+/// Options for `sandbox_spawn()`.
+pub struct SandboxOptions<'a> {
+    /// Directory, that becomes the root directory of the naming service for the new process
+    pub root: Option<&'a str>,
+    /// Capabilities of the new process (it never gets more than the calling process has)
+    pub capabilities: Capabilities,
+    /// Maximum number of threads (0 = unlimited)
+    pub max_threads: usize,
+    /// Maximum heap memory in bytes (0 = unlimited)
+    pub max_memory: usize,
+    /// User and group id of the new process (only a process running as root may change them)
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl Default for SandboxOptions<'_> {
+    /// The most restrictive sandbox: no capabilities, but also no resource limits and no root directory.
+    fn default() -> Self {
+        Self { root: None, capabilities: Capabilities::empty(), max_threads: 0, max_memory: 0, uid: None, gid: None }
+    }
+}
+
+/// Start an application in a sandbox, restricting its view of the naming service, its capabilities and its resources.
+/// All processes started by the application inherit the sandbox.
+pub fn sandbox_spawn(name: &str, args: Vec<&str>, options: &SandboxOptions) -> Result<Thread, Errno> {
+    let config = SandboxConfig {
+        root: options.root.map_or(ptr::null(), |root| root.as_ptr()),
+        root_len: options.root.map_or(0, |root| root.len()),
+        capabilities: options.capabilities.bits(),
+        max_threads: options.max_threads,
+        max_memory: options.max_memory,
+        uid: options.uid.unwrap_or(KEEP_ID),
+        gid: options.gid.unwrap_or(KEEP_ID),
+    };
+
+    syscall(SystemCall::ProcessExecuteSandboxed, &[name.as_bytes().as_ptr() as usize,
+        name.len(),
+        ptr::from_ref(&args) as usize,
+        ptr::from_ref(&config) as usize,
+    ]).map(Thread::new)
+}
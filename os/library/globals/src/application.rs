@@ -168,4 +168,9 @@ pub const APPLICATION_REGISTRY: &'static [Application] = &[
         single_value: &[],
         key_value_pair: &[],
     },
+    Application {
+        namespace: "wget",
+        single_value: &["-h", "--help"],
+        key_value_pair: &[("-O", &["-"])],
+    },
 ];
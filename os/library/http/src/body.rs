@@ -0,0 +1,165 @@
+//! Buffered reading of responses, including chunked bodies.
+
+use alloc::vec::Vec;
+use network::{NetworkError, TcpStream};
+use syscall::return_vals::Errno;
+
+use crate::HttpError;
+
+/// Something we can read a response from.
+pub(crate) trait Source {
+    /// Read up to `buf.len()` bytes. Returns 0 at the end of the stream.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, HttpError>;
+}
+
+impl Source for TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, HttpError> {
+        match TcpStream::read(self, buf) {
+            Ok(len) => Ok(len),
+            // the server closed the connection
+            Err(NetworkError::Unknown(Errno::ECONNRESET)) => Ok(0),
+            Err(e) => Err(HttpError::Network(e)),
+        }
+    }
+}
+
+impl Source for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, HttpError> {
+        let len = buf.len().min(self.len());
+        buf[..len].copy_from_slice(&self[..len]);
+        *self = &self[len..];
+        Ok(len)
+    }
+}
+
+pub(crate) struct BufferedReader<S: Source> {
+    source: S,
+    buffer: Vec<u8>,
+    /// position of the first unconsumed byte in `buffer`
+    pos: usize,
+}
+
+impl<S: Source> BufferedReader<S> {
+    pub fn new(source: S) -> Self {
+        Self { source, buffer: Vec::new(), pos: 0 }
+    }
+
+    /// Read more data from the source. Returns false at the end of the stream.
+    fn fill(&mut self) -> Result<bool, HttpError> {
+        // drop data that has already been consumed
+        self.buffer.drain(..self.pos);
+        self.pos = 0;
+
+        let mut chunk = [0u8; 4096];
+        let len = self.source.read(&mut chunk)?;
+        self.buffer.extend_from_slice(&chunk[..len]);
+        Ok(len > 0)
+    }
+
+    /// Read a line terminated by `\n` (with an optional `\r`), not including the line ending.
+    pub fn read_line(&mut self) -> Result<Vec<u8>, HttpError> {
+        loop {
+            if let Some(idx) = self.buffer[self.pos..].iter().position(|&b| b == b'\n') {
+                let mut line = self.buffer[self.pos..self.pos + idx].to_vec();
+                self.pos += idx + 1;
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                return Ok(line);
+            }
+            if !self.fill()? {
+                return Err(HttpError::InvalidResponse);
+            }
+        }
+    }
+
+    /// Read the header block (status line and headers) including the final empty line.
+    pub fn read_head(&mut self) -> Result<Vec<u8>, HttpError> {
+        let mut head = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            head.extend_from_slice(&line);
+            head.extend_from_slice(b"\r\n");
+            if line.is_empty() {
+                return Ok(head);
+            }
+        }
+    }
+
+    /// Read exactly `len` bytes and append them to `out`.
+    pub fn read_exact(&mut self, len: usize, out: &mut Vec<u8>) -> Result<(), HttpError> {
+        while self.buffer.len() - self.pos < len {
+            if !self.fill()? {
+                return Err(HttpError::InvalidResponse);
+            }
+        }
+        out.extend_from_slice(&self.buffer[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(())
+    }
+
+    /// Read until the end of the stream and append everything to `out`.
+    pub fn read_to_end(&mut self, out: &mut Vec<u8>) -> Result<(), HttpError> {
+        while self.fill()? {}
+        out.extend_from_slice(&self.buffer[self.pos..]);
+        self.pos = self.buffer.len();
+        Ok(())
+    }
+
+    /// Decode a body with `Transfer-Encoding: chunked` and append it to `out`.
+    pub fn read_chunked(&mut self, out: &mut Vec<u8>) -> Result<(), HttpError> {
+        loop {
+            let line = self.read_line()?;
+            // ignore chunk extensions
+            let size = line.split(|&b| b == b';').next().unwrap();
+            let size = core::str::from_utf8(size).map_err(|_| HttpError::InvalidResponse)?;
+            let size = usize::from_str_radix(size.trim(), 16).map_err(|_| HttpError::InvalidResponse)?;
+
+            if size == 0 {
+                // skip the trailer
+                while !self.read_line()?.is_empty() {}
+                return Ok(());
+            }
+
+            self.read_exact(size, out)?;
+            if !self.read_line()?.is_empty() {
+                return Err(HttpError::InvalidResponse);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunked() {
+        let data: &[u8] = b"4\r\nWiki\r\n7;ext=1\r\npedia i\r\nB\r\nn \r\nchunks.\r\n0\r\nExpires: never\r\n\r\nrest";
+        let mut reader = BufferedReader::new(data);
+        let mut body = Vec::new();
+        reader.read_chunked(&mut body).unwrap();
+        assert_eq!(body, b"Wikipedia in \r\nchunks.");
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"rest");
+    }
+
+    #[test]
+    fn chunked_truncated() {
+        let data: &[u8] = b"10\r\nshort\r\n";
+        let mut body = Vec::new();
+        assert_eq!(BufferedReader::new(data).read_chunked(&mut body), Err(HttpError::InvalidResponse));
+    }
+
+    #[test]
+    fn head_and_length() {
+        let data: &[u8] = b"HTTP/1.1 200 OK\nContent-Length: 5\r\n\r\nhello";
+        let mut reader = BufferedReader::new(data);
+        assert_eq!(reader.read_head().unwrap(), b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n");
+        let mut body = Vec::new();
+        reader.read_exact(5, &mut body).unwrap();
+        assert_eq!(body, b"hello");
+    }
+}
@@ -0,0 +1,322 @@
+//! A simple, blocking HTTP/1.1 client.
+//!
+//! Only plain `http://` is supported. Responses are read completely into memory;
+//! bodies may be delimited by `Content-Length`, `Transfer-Encoding: chunked` or
+//! the server closing the connection. Redirects are followed automatically.
+
+#![no_std]
+extern crate alloc;
+
+mod body;
+pub mod url;
+
+use core::fmt::{self, Display};
+use core::net::SocketAddr;
+
+use alloc::{format, string::{String, ToString}, vec::Vec};
+use httparse::{EMPTY_HEADER, Status};
+use network::{NetworkError, TcpStream, resolve_hostname};
+
+use body::BufferedReader;
+pub use url::Url;
+
+/// Maximum number of headers we accept in a response.
+const MAX_HEADERS: usize = 64;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum HttpError {
+    /// The URL could not be parsed.
+    InvalidUrl,
+    /// Only `http://` is supported.
+    UnsupportedScheme,
+    /// The host name could not be resolved.
+    HostNotFound,
+    /// No connection could be established, or it failed while sending or receiving.
+    Network(NetworkError),
+    /// The server sent something that isn't a valid HTTP response.
+    InvalidResponse,
+    /// The redirect limit has been reached.
+    TooManyRedirects,
+}
+
+impl From<NetworkError> for HttpError {
+    fn from(e: NetworkError) -> Self {
+        Self::Network(e)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+}
+
+impl Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Self::Get => "GET",
+            Self::Head => "HEAD",
+            Self::Post => "POST",
+            Self::Put => "PUT",
+            Self::Delete => "DELETE",
+        })
+    }
+}
+
+/// An HTTP request, created with [`Request::new`] or one of the shortcuts like [`Request::get`].
+#[derive(Debug, Clone)]
+pub struct Request {
+    method: Method,
+    url: Url,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    pub fn new(method: Method, url: &str) -> Result<Self, HttpError> {
+        Ok(Self { method, url: Url::parse(url)?, headers: Vec::new(), body: Vec::new() })
+    }
+
+    pub fn get(url: &str) -> Result<Self, HttpError> {
+        Self::new(Method::Get, url)
+    }
+
+    pub fn post(url: &str, body: Vec<u8>) -> Result<Self, HttpError> {
+        Ok(Self::new(Method::Post, url)?.body(body))
+    }
+
+    /// Add a header. `Host`, `Content-Length` and `Connection` are set automatically.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: Vec<u8>) -> Self {
+        self.body = body;
+        self
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Serialize the request head.
+    fn head(&self, user_agent: &str) -> String {
+        let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\n", self.method, self.url.path(), self.url.authority());
+        head.push_str(&format!("User-Agent: {user_agent}\r\nConnection: close\r\n"));
+        if !self.body.is_empty() || matches!(self.method, Method::Post | Method::Put) {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str("\r\n");
+        head
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    status: u16,
+    reason: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    /// the URL this response was received from (after following redirects)
+    url: Url,
+}
+
+impl Response {
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// Whether the status is in the 2xx range.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Get the value of the first header with this name (case insensitive).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+}
+
+pub struct Client {
+    max_redirects: usize,
+    user_agent: String,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Self { max_redirects: 5, user_agent: "D3OS http".to_string() }
+    }
+
+    /// Set how many redirects are followed before giving up. 0 disables redirects.
+    pub fn max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    /// Send a request and return the final response (after following redirects).
+    pub fn send(&self, mut request: Request) -> Result<Response, HttpError> {
+        let mut redirects = 0;
+        loop {
+            let response = self.send_once(&request)?;
+            let location = match (response.status, response.header("Location")) {
+                (301 | 302 | 303 | 307 | 308, Some(location)) => location,
+                _ => return Ok(response),
+            };
+
+            if redirects == self.max_redirects {
+                return if self.max_redirects == 0 { Ok(response) } else { Err(HttpError::TooManyRedirects) };
+            }
+            redirects += 1;
+
+            request.url = request.url.join(location)?;
+            // 303 always switches to GET, and so do 301 and 302 for POST (like all browsers)
+            if response.status == 303 || (matches!(response.status, 301 | 302) && request.method == Method::Post) {
+                if request.method != Method::Head {
+                    request.method = Method::Get;
+                }
+                request.body.clear();
+            }
+        }
+    }
+
+    pub fn get(&self, url: &str) -> Result<Response, HttpError> {
+        self.send(Request::get(url)?)
+    }
+
+    pub fn post(&self, url: &str, body: Vec<u8>) -> Result<Response, HttpError> {
+        self.send(Request::post(url, body)?)
+    }
+
+    /// Send a request on a new connection, without following redirects.
+    fn send_once(&self, request: &Request) -> Result<Response, HttpError> {
+        let stream = connect(request.url.host(), request.url.port())?;
+        write_all(&stream, request.head(&self.user_agent).as_bytes())?;
+        write_all(&stream, &request.body)?;
+
+        let mut reader = BufferedReader::new(stream);
+        let head = reader.read_head()?;
+        let mut headers = [EMPTY_HEADER; MAX_HEADERS];
+        let mut parsed = httparse::Response::new(&mut headers);
+        match parsed.parse(&head) {
+            Ok(Status::Complete(_)) => {},
+            _ => return Err(HttpError::InvalidResponse),
+        }
+
+        let status = parsed.code.ok_or(HttpError::InvalidResponse)?;
+        let mut response = Response {
+            status,
+            reason: parsed.reason.unwrap_or("").to_string(),
+            headers: parsed.headers
+                .iter()
+                .map(|header| (header.name.to_string(), String::from_utf8_lossy(header.value).into_owned()))
+                .collect(),
+            body: Vec::new(),
+            url: request.url.clone(),
+        };
+
+        // some responses never have a body
+        if request.method == Method::Head || (100..200).contains(&status) || status == 204 || status == 304 {
+            return Ok(response);
+        }
+
+        let chunked = response
+            .header("Transfer-Encoding")
+            .is_some_and(|encoding| encoding.to_ascii_lowercase().contains("chunked"));
+        if chunked {
+            reader.read_chunked(&mut response.body)?;
+        } else if let Some(length) = response.header("Content-Length") {
+            let length = length.trim().parse().map_err(|_| HttpError::InvalidResponse)?;
+            reader.read_exact(length, &mut response.body)?;
+        } else {
+            reader.read_to_end(&mut response.body)?;
+        }
+
+        Ok(response)
+    }
+}
+
+/// Send a GET request with the default client.
+pub fn get(url: &str) -> Result<Response, HttpError> {
+    Client::new().get(url)
+}
+
+/// Connect to the first reachable address of `host`.
+fn connect(host: &str, port: u16) -> Result<TcpStream, HttpError> {
+    let mut last_error = HttpError::HostNotFound;
+    for ip in resolve_hostname(host) {
+        match TcpStream::connect(SocketAddr::new(ip, port)) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = HttpError::Network(e),
+        }
+    }
+    Err(last_error)
+}
+
+fn write_all(stream: &TcpStream, mut buf: &[u8]) -> Result<(), HttpError> {
+    while !buf.is_empty() {
+        let written = stream.write(buf)?;
+        if written == 0 {
+            return Err(HttpError::Network(NetworkError::DeviceBusy));
+        }
+        buf = &buf[written..];
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_head() {
+        let request = Request::post("http://example.org:8080/upload?x=1", b"data".to_vec())
+            .unwrap()
+            .header("Accept", "*/*");
+        assert_eq!(
+            request.head("test"),
+            "POST /upload?x=1 HTTP/1.1\r\nHost: example.org:8080\r\nUser-Agent: test\r\nConnection: close\r\n\
+             Content-Length: 4\r\nAccept: */*\r\n\r\n"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Minimal parser for `http://` URLs.
+
+use core::fmt::{self, Display};
+
+use alloc::{format, string::{String, ToString}};
+
+use crate::HttpError;
+
+const DEFAULT_PORT: u16 = 80;
+
+/// An absolute `http://` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+    /// host name or IP address (IPv6 addresses are stored without brackets)
+    host: String,
+    port: u16,
+    /// path including the query, always starting with `/`
+    path: String,
+}
+
+impl Url {
+    pub fn parse(url: &str) -> Result<Self, HttpError> {
+        let (scheme, rest) = url.split_once("://").ok_or(HttpError::InvalidUrl)?;
+        if !scheme.eq_ignore_ascii_case("http") {
+            return Err(HttpError::UnsupportedScheme);
+        }
+
+        // the fragment is never sent to the server
+        let rest = rest.split('#').next().unwrap();
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(idx) => (&rest[..idx], &rest[idx..]),
+            None => (rest, "/"),
+        };
+        let path = if path.starts_with('?') { format!("/{path}") } else { path.to_string() };
+
+        // we don't support credentials in URLs
+        if authority.contains('@') {
+            return Err(HttpError::InvalidUrl);
+        }
+
+        let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+            // IPv6 literal
+            let (host, rest) = rest.split_once(']').ok_or(HttpError::InvalidUrl)?;
+            let port = match rest.strip_prefix(':') {
+                Some(port) => Some(port),
+                None if rest.is_empty() => None,
+                None => return Err(HttpError::InvalidUrl),
+            };
+            (host, port)
+        } else {
+            match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            }
+        };
+        if host.is_empty() {
+            return Err(HttpError::InvalidUrl);
+        }
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| HttpError::InvalidUrl)?,
+            None => DEFAULT_PORT,
+        };
+
+        Ok(Self { host: host.to_string(), port, path })
+    }
+
+    /// Resolve `location` (e.g. from a redirect) relative to this URL.
+    pub fn join(&self, location: &str) -> Result<Self, HttpError> {
+        if location.contains("://") {
+            Self::parse(location)
+        } else if let Some(rest) = location.strip_prefix("//") {
+            Self::parse(&format!("http://{rest}"))
+        } else if location.starts_with('/') {
+            Ok(Self { host: self.host.clone(), port: self.port, path: location.to_string() })
+        } else {
+            // relative to the current "directory"
+            let base = self.path.split('?').next().unwrap();
+            let dir = &base[..=base.rfind('/').unwrap_or(0)];
+            Ok(Self { host: self.host.clone(), port: self.port, path: format!("{dir}{location}") })
+        }
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The value of the `Host` header for this URL.
+    pub fn authority(&self) -> String {
+        let host = if self.host.contains(':') { format!("[{}]", self.host) } else { self.host.clone() };
+        if self.port == DEFAULT_PORT {
+            host
+        } else {
+            format!("{host}:{}", self.port)
+        }
+    }
+}
+
+impl Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}{}", self.authority(), self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_simple() {
+        let url = Url::parse("http://example.org").unwrap();
+        assert_eq!(url.host(), "example.org");
+        assert_eq!(url.port(), 80);
+        assert_eq!(url.path(), "/");
+    }
+
+    #[test]
+    fn parse_port_path_query() {
+        let url = Url::parse("HTTP://10.0.2.2:8080/index.html?a=b#top").unwrap();
+        assert_eq!(url.host(), "10.0.2.2");
+        assert_eq!(url.port(), 8080);
+        assert_eq!(url.path(), "/index.html?a=b");
+        assert_eq!(url.authority(), "10.0.2.2:8080");
+    }
+
+    #[test]
+    fn parse_ipv6() {
+        let url = Url::parse("http://[fe80::1]:1797/").unwrap();
+        assert_eq!(url.host(), "fe80::1");
+        assert_eq!(url.port(), 1797);
+        assert_eq!(url.authority(), "[fe80::1]:1797");
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Url::parse("https://example.org"), Err(HttpError::UnsupportedScheme));
+        assert_eq!(Url::parse("example.org"), Err(HttpError::InvalidUrl));
+        assert_eq!(Url::parse("http://:80/"), Err(HttpError::InvalidUrl));
+        assert_eq!(Url::parse("http://host:port/"), Err(HttpError::InvalidUrl));
+    }
+
+    #[test]
+    fn join() {
+        let base = Url::parse("http://example.org:81/dir/file?x").unwrap();
+        assert_eq!(base.join("/other").unwrap().to_string(), "http://example.org:81/other");
+        assert_eq!(base.join("sibling").unwrap().to_string(), "http://example.org:81/dir/sibling");
+        assert_eq!(base.join("http://d3os.org/").unwrap().to_string(), "http://d3os.org/");
+        assert_eq!(base.join("//d3os.org/x").unwrap().to_string(), "http://d3os.org/x");
+    }
+}
@@ -0,0 +1,146 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: compose                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Dead keys and compose sequences on top of the decoded keys of   ║
+   ║         a keyboard layout. A dead key (e.g. '^' on the German layout)   ║
+   ║         is combined with the next character ('^' + 'e' = 'ê'), typing   ║
+   ║         it twice or followed by space produces the character itself.    ║
+   ║         The compose key (the menu key) combines the next two            ║
+   ║         characters ('s' 's' = 'ß', '"' 'a' = 'ä', 'e' '=' = '€').       ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use pc_keyboard::{DecodedKey, KeyCode};
+
+/// Dead keys of the German layout (`De105Key`): circumflex, acute and grave
+pub const DE105_DEAD_KEYS: &[char] = &['^', '´', '`'];
+
+/// Starts a compose sequence
+pub const COMPOSE_KEY: KeyCode = KeyCode::Apps;
+
+/// Accents as (marks, base characters, results).
+/// The marks are the dead keys and the characters used in compose sequences.
+const ACCENTS: &[(&str, &str, &str)] = &[
+    ("´'", "aeiouyAEIOUYcnszCNSZ", "áéíóúýÁÉÍÓÚÝćńśźĆŃŚŹ"),
+    ("`", "aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+    ("^", "aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+    ("~", "anoANO", "ãñõÃÑÕ"),
+    ("\"¨", "aeiouyAEIOU", "äëïöüÿÄËÏÖÜ"),
+    (",¸", "cC", "çÇ"),
+    ("*", "aAuU", "åÅůŮ"),
+];
+
+/// Compose sequences, that are not accents (both orders are accepted)
+const SEQUENCES: &[(char, char, char)] = &[
+    ('s', 's', 'ß'),
+    ('a', 'e', 'æ'),
+    ('A', 'E', 'Æ'),
+    ('o', 'e', 'œ'),
+    ('O', 'E', 'Œ'),
+    ('o', '/', 'ø'),
+    ('O', '/', 'Ø'),
+    ('o', 'a', 'å'),
+    ('o', 'A', 'Å'),
+    ('e', '=', '€'),
+    ('L', '-', '£'),
+    ('Y', '=', '¥'),
+    ('o', 'c', '©'),
+    ('o', 'r', '®'),
+    ('o', 'o', '°'),
+    ('<', '<', '«'),
+    ('>', '>', '»'),
+    ('!', '!', '¡'),
+    ('?', '?', '¿'),
+    ('+', '-', '±'),
+    ('x', 'x', '×'),
+    (':', '-', '÷'),
+    ('^', '2', '²'),
+    ('^', '3', '³'),
+    ('1', '2', '½'),
+    ('1', '4', '¼'),
+    ('3', '4', '¾'),
+];
+
+#[derive(Clone, Copy)]
+enum State {
+    Idle,
+    /// A dead key has been pressed
+    Dead(char),
+    /// The compose key has been pressed
+    Compose,
+    /// The compose key and one character have been pressed
+    ComposeFirst(char),
+}
+
+/// Turns the decoded keys of a layout into the keys, that are passed on to applications.
+pub struct Composer {
+    dead_keys: &'static [char],
+    state: State,
+}
+
+impl Composer {
+    pub const fn new(dead_keys: &'static [char]) -> Self {
+        Self { dead_keys, state: State::Idle }
+    }
+
+    /// Process a decoded key and return the resulting keys (none while a sequence is incomplete,
+    /// two if a dead key can't be combined with the following key).
+    pub fn process(&mut self, key: DecodedKey) -> impl Iterator<Item = DecodedKey> + use<> {
+        let (first, second) = self.step(key);
+        [first, second].into_iter().flatten()
+    }
+
+    fn step(&mut self, key: DecodedKey) -> (Option<DecodedKey>, Option<DecodedKey>) {
+        match (core::mem::replace(&mut self.state, State::Idle), key) {
+            // the compose key always starts a new sequence
+            (_, DecodedKey::RawKey(COMPOSE_KEY)) => {
+                self.state = State::Compose;
+                (None, None)
+            }
+
+            (State::Idle, DecodedKey::Unicode(c)) if self.dead_keys.contains(&c) => {
+                self.state = State::Dead(c);
+                (None, None)
+            }
+            (State::Idle, key) => (Some(key), None),
+
+            (State::Dead(dead), DecodedKey::Unicode(c)) if c == dead || c == ' ' => (Some(DecodedKey::Unicode(dead)), None),
+            (State::Dead(dead), DecodedKey::Unicode(c)) => match combine(dead, c) {
+                Some(combined) => (Some(DecodedKey::Unicode(combined)), None),
+                None if self.dead_keys.contains(&c) => {
+                    self.state = State::Dead(c);
+                    (Some(DecodedKey::Unicode(dead)), None)
+                }
+                None => (Some(DecodedKey::Unicode(dead)), Some(key)),
+            },
+            (State::Dead(dead), key) => (Some(DecodedKey::Unicode(dead)), Some(key)),
+
+            // escape cancels a compose sequence
+            (State::Compose | State::ComposeFirst(_), DecodedKey::Unicode('\x1b')) => (None, None),
+            (State::Compose, DecodedKey::Unicode(c)) if !c.is_control() => {
+                self.state = State::ComposeFirst(c);
+                (None, None)
+            }
+            // unknown sequences produce nothing
+            (State::ComposeFirst(first), DecodedKey::Unicode(c)) if !c.is_control() => (compose(first, c).map(DecodedKey::Unicode), None),
+            // other keys (e.g. arrows or enter) cancel the sequence and are passed on
+            (State::Compose | State::ComposeFirst(_), key) => (Some(key), None),
+        }
+    }
+}
+
+/// Combine the accent `mark` with `base` (e.g. '^' and 'a' to 'â').
+fn combine(mark: char, base: char) -> Option<char> {
+    let (_, bases, results) = ACCENTS.iter().find(|(marks, _, _)| marks.contains(mark))?;
+    let index = bases.chars().position(|c| c == base)?;
+    results.chars().nth(index)
+}
+
+fn compose(first: char, second: char) -> Option<char> {
+    SEQUENCES.iter()
+        .find(|(a, b, _)| (*a, *b) == (first, second) || (*b, *a) == (first, second))
+        .map(|(_, _, result)| *result)
+        .or_else(|| combine(first, second))
+        .or_else(|| combine(second, first))
+}
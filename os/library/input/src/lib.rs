@@ -6,6 +6,34 @@ pub enum ReadKeyboardOption {
     Decode,
 }
 
+/// Bits of `MouseEvent::buttons`
+pub const MOUSE_BUTTON_LEFT: u8 = 0x01;
+pub const MOUSE_BUTTON_RIGHT: u8 = 0x02;
+pub const MOUSE_BUTTON_MIDDLE: u8 = 0x04;
+/// Side buttons ("back" and "forward", buttons 4 and 5 of PS/2 mice)
+pub const MOUSE_BUTTON_SIDE: u8 = 0x08;
+pub const MOUSE_BUTTON_EXTRA: u8 = 0x10;
+
+/// A pointer event from a PS/2 mouse or a virtio-input device (mouse or tablet), read with `SystemCall::MouseReadEvent`.
+/// All changes reported by the device at once are combined into a single event.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MouseEvent {
+    /// Relative movement (mice), positive `dy` is downwards
+    pub dx: i32,
+    pub dy: i32,
+    /// Wheel movement (positive = away from the user)
+    pub wheel: i32,
+    /// Absolute position, as reported by the device (tablets, QEMU uses 0 to 32767); only valid if `absolute` is set
+    pub x: u32,
+    pub y: u32,
+    pub absolute: bool,
+    /// Currently pressed buttons (`MOUSE_BUTTON_*`)
+    pub buttons: u8,
+}
+
+#[cfg(feature = "userspace")]
+pub mod compose;
 #[cfg(feature = "userspace")]
 pub mod keyboard;
 #[cfg(feature = "userspace")]
@@ -6,6 +6,8 @@
 use bitflags::bitflags;
 use syscall::{SystemCall, syscall};
 
+use crate::MouseEvent;
+
 bitflags! {
     pub struct MouseFlags: u8 {
         const LEFT_BUTTON = 0x01;
@@ -103,3 +105,13 @@ pub fn try_read_mouse() -> Option<MousePacket> {
         Err(_) => None,
     }
 }
+
+/// Read the next event of any mouse or tablet (non-blocking).
+/// The raw packets of PS/2 mice can still be read with `try_read_mouse()`.
+pub fn try_read_mouse_event() -> Option<MouseEvent> {
+    let mut event = MouseEvent::default();
+    match syscall(SystemCall::MouseReadEvent, &[&raw mut event as usize]) {
+        Ok(1) => Some(event),
+        _ => None,
+    }
+}
@@ -0,0 +1,9 @@
+#include "runtime.h"
+
+int main(int argc, char *argv[]);
+
+/* Entry function of applications, which link the runtime dynamically (see os/library/shared_runtime):
+   The shared runtime can't refer to main() of the application, so it gets it from here. */
+void entry(void) {
+    runtime_start(main);
+}
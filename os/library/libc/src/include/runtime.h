@@ -3,4 +3,7 @@
 
 void terminal_write(const char *str);
 
-#endif
\ No newline at end of file
+/* Initialize the runtime and run main() (only called by crt0.c) */
+void runtime_start(int (*main)(int argc, char *argv[])) __attribute__((noreturn));
+
+#endif
@@ -2,6 +2,8 @@
 
 extern crate alloc;
 
+pub mod service;
+
 use alloc::format;
 use log::{Level, Log, Metadata, Record};
 use syscall::{SystemCall, syscall};
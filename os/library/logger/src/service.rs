@@ -0,0 +1,189 @@
+//! Client side of the logging service (`logd`).
+//!
+//! `logd` creates a named pipe in `SPOOL_DIRECTORY` for every log it manages and writes
+//! the received lines to `LOG_DIRECTORY/<name>.log`, rotating the file when it gets too large.
+//! Lines are sent as a little endian `u16` length followed by the UTF-8 bytes of the line,
+//! because reading from a pipe only returns once the whole buffer has been filled.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use concurrent::thread;
+use naming::shared_types::{FileType, OpenOptions, SeekOrigin};
+use naming::{close, open, read, readdir, seek, write};
+use syscall::return_vals::Errno;
+
+/// Directory containing the pipes of all logs managed by `logd`
+pub const SPOOL_DIRECTORY: &str = "/run/log";
+/// Directory containing the log files
+pub const LOG_DIRECTORY: &str = "/var/log";
+/// Longer lines are truncated
+pub const MAX_LINE_LENGTH: usize = 1024;
+/// Interval for checking a followed log for new lines (in milliseconds)
+const FOLLOW_INTERVAL_MS: usize = 100;
+
+/// Path of the pipe for the log `name`
+pub fn spool_path(name: &str) -> String {
+    format!("{SPOOL_DIRECTORY}/{name}")
+}
+
+/// Path of the current file of the log `name` (rotated files have the suffix `.1`, `.2`, ...)
+pub fn log_path(name: &str) -> String {
+    format!("{LOG_DIRECTORY}/{name}.log")
+}
+
+/// Connection to a log managed by `logd`.
+/// Each log accepts only one writer at a time.
+pub struct LogStream {
+    handle: usize,
+}
+
+impl LogStream {
+    /// Connect to the log `name`. Returns `ENOENT`, if `logd` doesn't manage this log,
+    /// and `EBUSY`, if another application is already writing to it.
+    pub fn connect(name: &str) -> Result<Self, Errno> {
+        // opening a pipe blocks until there is a reader, so check first whether logd has created it
+        if !spool_contains(name)? {
+            return Err(Errno::ENOENT);
+        }
+        let handle = open(&spool_path(name), OpenOptions::WRITEONLY)?;
+        Ok(Self { handle })
+    }
+
+    /// Send a line to the log. Line breaks are replaced with spaces.
+    pub fn write_line(&self, line: &str) -> Result<(), Errno> {
+        let mut length = line.len().min(MAX_LINE_LENGTH);
+        while !line.is_char_boundary(length) {
+            length -= 1;
+        }
+        let mut message = Vec::with_capacity(length + 2);
+        message.extend_from_slice(&(length as u16).to_le_bytes());
+        message.extend(line[..length].bytes().map(|byte| if byte == b'\n' || byte == b'\r' { b' ' } else { byte }));
+
+        let mut sent = 0;
+        while sent < message.len() {
+            sent += write(self.handle, &message[sent..])?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LogStream {
+    fn drop(&mut self) {
+        let _ = close(self.handle);
+    }
+}
+
+/// Receive a line sent with `LogStream::write_line()` from the pipe `handle`.
+/// Returns `None`, if the writer has closed the pipe.
+pub fn receive_line(handle: usize) -> Option<String> {
+    let mut length = [0u8; 2];
+    if read(handle, &mut length).ok()? != length.len() {
+        return None;
+    }
+    let mut line = alloc::vec![0u8; u16::from_le_bytes(length) as usize];
+    if !line.is_empty() && read(handle, &mut line).ok()? != line.len() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&line).into_owned())
+}
+
+fn spool_contains(name: &str) -> Result<bool, Errno> {
+    let dir = open(SPOOL_DIRECTORY, OpenOptions::DIRECTORY)?;
+    let mut found = false;
+    while let Ok(Some(entry)) = readdir(dir) {
+        if entry.file_type == FileType::NamedPipe && entry.name == name {
+            found = true;
+            break;
+        }
+    }
+    close(dir)?;
+    Ok(found)
+}
+
+/// Follows a log file like `tail -f`, also across rotations.
+pub struct Follower {
+    handle: usize,
+    position: usize,
+    pending: Vec<u8>,
+}
+
+/// Follow the log `name`, starting at the end of the current file.
+/// If `last_lines` is greater than 0, that many lines before the end are returned first.
+pub fn follow(name: &str, last_lines: usize) -> Result<Follower, Errno> {
+    let handle = open(&log_path(name), OpenOptions::READONLY)?;
+    let size = seek(handle, 0, SeekOrigin::End)?;
+    let mut follower = Follower { handle, position: size, pending: Vec::new() };
+
+    if last_lines > 0 {
+        let mut content = alloc::vec![0u8; size];
+        seek(handle, 0, SeekOrigin::Start)?;
+        let mut received = 0;
+        while received < size {
+            match read(handle, &mut content[received..])? {
+                0 => break,
+                n => received += n,
+            }
+        }
+        content.truncate(received);
+
+        // skip the final line break, so it isn't counted as an empty line
+        let end = content.strip_suffix(b"\n").map_or(content.len(), |line| line.len());
+        let start = content[..end].iter().enumerate().rev()
+            .filter(|(_, byte)| **byte == b'\n')
+            .nth(last_lines - 1)
+            .map_or(0, |(pos, _)| pos + 1);
+        follower.pending = content[start..].to_vec();
+        follower.position = received;
+    }
+
+    Ok(follower)
+}
+
+impl Follower {
+    /// Return the next line, if there is a complete one (non-blocking).
+    pub fn try_next_line(&mut self) -> Result<Option<String>, Errno> {
+        if let Some(line) = self.take_line() {
+            return Ok(Some(line));
+        }
+
+        // a rotated file starts from the beginning again
+        let size = seek(self.handle, 0, SeekOrigin::End)?;
+        if size < self.position {
+            self.position = 0;
+            self.pending.clear();
+        }
+        if size > self.position {
+            let mut buffer = alloc::vec![0u8; size - self.position];
+            seek(self.handle, self.position as isize, SeekOrigin::Start)?;
+            let received = read(self.handle, &mut buffer)?;
+            self.pending.extend_from_slice(&buffer[..received]);
+            self.position += received;
+        }
+
+        Ok(self.take_line())
+    }
+
+    /// Wait for the next line.
+    pub fn next_line(&mut self) -> Result<String, Errno> {
+        loop {
+            if let Some(line) = self.try_next_line()? {
+                return Ok(line);
+            }
+            thread::sleep(FOLLOW_INTERVAL_MS);
+        }
+    }
+
+    fn take_line(&mut self) -> Option<String> {
+        let end = self.pending.iter().position(|byte| *byte == b'\n')?;
+        let line = String::from_utf8_lossy(&self.pending[..end]).into_owned();
+        self.pending.drain(..=end);
+        Some(line)
+    }
+}
+
+impl Drop for Follower {
+    fn drop(&mut self) {
+        let _ = close(self.handle);
+    }
+}
@@ -0,0 +1,353 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: lib                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Small implementation of the LZ4 block format. The compressor    ║
+   ║         is a simple greedy single-pass matcher, which is fast and good  ║
+   ║         enough for highly repetitive data like log messages.            ║
+   ║         Output can be decoded by any standard LZ4 block decoder.        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+/// Minimum length of a match (encoded as 0 in the token).
+const MIN_MATCH: usize = 4;
+/// The last 5 bytes of a block must always be literals.
+const LAST_LITERALS: usize = 5;
+/// The last match must start at least 12 bytes before the end of a block.
+const MF_LIMIT: usize = 12;
+/// Offsets are encoded in 16 bits.
+const MAX_DISTANCE: usize = 0xffff;
+/// Size of the hash table used to find matches (2^HASH_LOG entries).
+const HASH_LOG: u32 = 12;
+
+/// Destination of the compressed data (a growing vector or a fixed buffer).
+trait Output {
+    fn push(&mut self, byte: u8);
+    fn extend_from_slice(&mut self, bytes: &[u8]);
+}
+
+impl Output for Vec<u8> {
+    fn push(&mut self, byte: u8) {
+        Vec::push(self, byte);
+    }
+
+    fn extend_from_slice(&mut self, bytes: &[u8]) {
+        Vec::extend_from_slice(self, bytes);
+    }
+}
+
+/// Fixed buffer, which is filled from its start.
+struct SliceOutput<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl Output for SliceOutput<'_> {
+    fn push(&mut self, byte: u8) {
+        self.buffer[self.len] = byte;
+        self.len += 1;
+    }
+
+    fn extend_from_slice(&mut self, bytes: &[u8]) {
+        self.buffer[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DecompressError {
+    /// The input ended in the middle of a sequence.
+    Truncated,
+    /// A match referenced data before the start of the output.
+    InvalidOffset,
+    /// The decompressed data would exceed the given maximum size.
+    OutputTooLarge,
+    /// The size prefix does not match the decompressed data.
+    SizeMismatch,
+}
+
+/// Worst case size of the compressed data for an input of `len` bytes.
+pub const fn compress_bound(len: usize) -> usize {
+    len + len / 255 + 16
+}
+
+/// Compress `input` into a new LZ4 block.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(compress_bound(input.len()));
+    compress_into(input, &mut output);
+    output
+}
+
+/// Compress `input` into a new LZ4 block, prefixed with the uncompressed size (u32, little endian).
+pub fn compress_prepend_size(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(compress_bound(input.len()) + 4);
+    output.extend_from_slice(&(input.len() as u32).to_le_bytes());
+    compress_into(input, &mut output);
+    output
+}
+
+/// Compress `input` and append the resulting LZ4 block to `output`.
+pub fn compress_into(input: &[u8], output: &mut Vec<u8>) {
+    compress_to(input, output);
+}
+
+/// Compress `input` into the fixed buffer `output` without any heap allocations and return the length of the LZ4 block.
+/// Panics, if `output` is smaller than `compress_bound(input.len())`.
+pub fn compress_into_slice(input: &[u8], output: &mut [u8]) -> usize {
+    assert!(output.len() >= compress_bound(input.len()), "Output buffer is too small for the compressed data");
+    let mut output = SliceOutput { buffer: output, len: 0 };
+    compress_to(input, &mut output);
+    output.len
+}
+
+fn compress_to(input: &[u8], output: &mut impl Output) {
+    let mut table = [0u32; 1 << HASH_LOG];
+    let mut anchor = 0;
+    let mut pos = 0;
+
+    if input.len() > MF_LIMIT {
+        let match_limit = input.len() - MF_LIMIT;
+        let end_limit = input.len() - LAST_LITERALS;
+
+        while pos < match_limit {
+            let sequence = read_u32(input, pos);
+            let hash = hash(sequence);
+            let candidate = table[hash] as usize;
+            table[hash] = pos as u32;
+
+            if candidate >= pos || pos - candidate > MAX_DISTANCE || read_u32(input, candidate) != sequence {
+                pos += 1;
+                continue;
+            }
+
+            // Extend the match backwards into the pending literals
+            let mut start = pos;
+            let mut source = candidate;
+            while start > anchor && source > 0 && input[start - 1] == input[source - 1] {
+                start -= 1;
+                source -= 1;
+            }
+
+            // Extend the match forwards, keeping the last literals untouched
+            let mut len = pos - start + MIN_MATCH;
+            while start + len < end_limit && input[start + len] == input[source + len] {
+                len += 1;
+            }
+
+            write_sequence(output, &input[anchor..start], start - source, len);
+            pos = start + len;
+            anchor = pos;
+        }
+    }
+
+    write_last_literals(output, &input[anchor..]);
+}
+
+/// Decompress an LZ4 block, producing at most `max_size` bytes.
+pub fn decompress(input: &[u8], max_size: usize) -> Result<Vec<u8>, DecompressError> {
+    let mut output = Vec::new();
+    decompress_into(input, &mut output, max_size)?;
+    Ok(output)
+}
+
+/// Decompress an LZ4 block, that is prefixed with its uncompressed size (see [`compress_prepend_size`]).
+pub fn decompress_size_prepended(input: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    if input.len() < 4 {
+        return Err(DecompressError::Truncated);
+    }
+
+    let size = u32::from_le_bytes([input[0], input[1], input[2], input[3]]) as usize;
+    let mut output = Vec::with_capacity(size);
+    decompress_into(&input[4..], &mut output, size)?;
+
+    if output.len() != size {
+        return Err(DecompressError::SizeMismatch);
+    }
+    Ok(output)
+}
+
+/// Decompress an LZ4 block and append the data to `output`, which may grow by at most `max_size` bytes.
+pub fn decompress_into(input: &[u8], output: &mut Vec<u8>, max_size: usize) -> Result<(), DecompressError> {
+    let output_start = output.len();
+    let output_limit = output_start + max_size;
+    let mut pos = 0;
+
+    loop {
+        let token = *input.get(pos).ok_or(DecompressError::Truncated)?;
+        pos += 1;
+
+        // Copy literals
+        let mut literal_len = (token >> 4) as usize;
+        if literal_len == 15 {
+            literal_len += read_length(input, &mut pos)?;
+        }
+        let literals = input.get(pos..pos + literal_len).ok_or(DecompressError::Truncated)?;
+        if output.len() + literal_len > output_limit {
+            return Err(DecompressError::OutputTooLarge);
+        }
+        output.extend_from_slice(literals);
+        pos += literal_len;
+
+        // The last sequence consists of literals only
+        if pos == input.len() {
+            return Ok(());
+        }
+
+        // Copy match
+        let offset = input.get(pos..pos + 2).ok_or(DecompressError::Truncated)?;
+        let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+        pos += 2;
+        if offset == 0 || offset > output.len() - output_start {
+            return Err(DecompressError::InvalidOffset);
+        }
+
+        let mut match_len = (token & 0x0f) as usize;
+        if match_len == 15 {
+            match_len += read_length(input, &mut pos)?;
+        }
+        match_len += MIN_MATCH;
+        if output.len() + match_len > output_limit {
+            return Err(DecompressError::OutputTooLarge);
+        }
+
+        // Source and destination may overlap, so we need to copy byte by byte
+        let source = output.len() - offset;
+        for i in 0..match_len {
+            output.push(output[source + i]);
+        }
+    }
+}
+
+fn read_u32(input: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([input[pos], input[pos + 1], input[pos + 2], input[pos + 3]])
+}
+
+fn hash(sequence: u32) -> usize {
+    (sequence.wrapping_mul(2654435761) >> (32 - HASH_LOG)) as usize
+}
+
+fn read_length(input: &[u8], pos: &mut usize) -> Result<usize, DecompressError> {
+    let mut len = 0;
+    loop {
+        let byte = *input.get(*pos).ok_or(DecompressError::Truncated)?;
+        *pos += 1;
+        len += byte as usize;
+        if byte != 255 {
+            return Ok(len);
+        }
+    }
+}
+
+fn write_length(output: &mut impl Output, mut len: usize) {
+    while len >= 255 {
+        output.push(255);
+        len -= 255;
+    }
+    output.push(len as u8);
+}
+
+fn write_sequence(output: &mut impl Output, literals: &[u8], offset: usize, match_len: usize) {
+    let match_len = match_len - MIN_MATCH;
+    let token = (literals.len().min(15) << 4) | match_len.min(15);
+    output.push(token as u8);
+
+    if literals.len() >= 15 {
+        write_length(output, literals.len() - 15);
+    }
+    output.extend_from_slice(literals);
+    output.extend_from_slice(&(offset as u16).to_le_bytes());
+
+    if match_len >= 15 {
+        write_length(output, match_len - 15);
+    }
+}
+
+fn write_last_literals(output: &mut impl Output, literals: &[u8]) {
+    output.push((literals.len().min(15) << 4) as u8);
+    if literals.len() >= 15 {
+        write_length(output, literals.len() - 15);
+    }
+    output.extend_from_slice(literals);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn roundtrip(data: &[u8]) {
+        let compressed = compress(data);
+        assert!(compressed.len() <= compress_bound(data.len()));
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+
+        let compressed = compress_prepend_size(data);
+        assert_eq!(decompress_size_prepended(&compressed).unwrap(), data);
+
+        let mut buffer = vec![0; compress_bound(data.len())];
+        let len = compress_into_slice(data, &mut buffer);
+        assert_eq!(buffer[..len], compress(data));
+    }
+
+    #[test]
+    fn roundtrip_empty() {
+        roundtrip(b"");
+        assert_eq!(compress(b""), vec![0]);
+    }
+
+    #[test]
+    fn roundtrip_short() {
+        roundtrip(b"D3OS");
+        roundtrip(b"Hello World, Hello World!");
+    }
+
+    #[test]
+    fn roundtrip_repetitive() {
+        let mut data = Vec::new();
+        for i in 0..1000 {
+            data.extend_from_slice(b"[0.123][INF][boot.rs@042] Initializing thread ");
+            data.push(b'0' + (i % 10) as u8);
+            data.push(b'\n');
+        }
+        roundtrip(&data);
+        assert!(compress(&data).len() < data.len() / 10);
+    }
+
+    #[test]
+    fn roundtrip_long_runs() {
+        roundtrip(&[0xaa; 70000]);
+        roundtrip(&[0; 300]);
+    }
+
+    #[test]
+    fn roundtrip_pseudo_random() {
+        let mut state = 0x12345678u32;
+        let data: Vec<u8> = (0..5000)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        roundtrip(&data);
+    }
+
+    #[test]
+    fn decompress_overlapping_match() {
+        let block = [0x10, b'a', 1, 0, 0x50, b'b', b'b', b'b', b'b', b'b'];
+        assert_eq!(decompress(&block, 64).unwrap(), b"aaaaabbbbb");
+    }
+
+    #[test]
+    fn decompress_errors() {
+        assert_eq!(decompress(&[], 64), Err(DecompressError::Truncated));
+        assert_eq!(decompress(&[0x10, b'a', 2, 0, 0x00], 64), Err(DecompressError::InvalidOffset));
+        assert_eq!(decompress(&[0x10, b'a', 1], 64), Err(DecompressError::Truncated));
+        assert_eq!(decompress(&[0x10, b'a', 1, 0, 0x00], 4), Err(DecompressError::OutputTooLarge));
+        assert_eq!(decompress_size_prepended(&[3, 0, 0, 0, 0x10, b'a']), Err(DecompressError::SizeMismatch));
+    }
+}
@@ -23,7 +23,7 @@ use alloc::ffi::CString;
 use core::mem;
 
 #[cfg(feature = "userspace")]
-use shared_types::{DirEntry, FileType, OpenOptions, RawDirent, SeekOrigin};
+use shared_types::{DirEntry, FileStat, FileType, MountFlags, OpenOptions, RawDirent, SeekOrigin};
 #[cfg(feature = "userspace")]
 use syscall::{SystemCall, return_vals::Errno, syscall};
 
@@ -97,14 +97,8 @@ pub fn readdir(fh: usize) -> Result<Option<DirEntry>, Errno> {
 #[cfg(feature = "userspace")]
 impl DirEntry {
     pub fn from_dirent(dirent: &RawDirent) -> Option<Self> {
-        // Convert d_type to a FileType enum
-        let file_type = match dirent.d_type {
-            1 => FileType::NamedPipe,
-            4 => FileType::Directory,
-            8 => FileType::Regular,
-            10 => FileType::Link,
-            _ => return None, // Return None for unsupported file types
-        };
+        // Convert d_type to a FileType enum (None for unsupported file types)
+        let file_type = FileType::from_raw(dirent.d_type)?;
 
         // Convert d_name (null-terminated) to a Rust String
         let name = dirent
@@ -156,3 +150,77 @@ pub fn mkfifo(path: &str) -> Result<usize, Errno> {
         Err(_) => Err(Errno::EBADSTR),
     }
 }
+
+/// Remove the file, pipe or (empty) directory `path`.
+#[cfg(feature = "userspace")]
+pub fn unlink(path: &str) -> Result<usize, Errno> {
+    match CString::new(path) {
+        Ok(c_path) => syscall(SystemCall::Unlink, &[c_path.as_bytes().as_ptr() as usize]),
+        Err(_) => Err(Errno::EBADSTR),
+    }
+}
+
+/// Rename (or move) `old_path` to `new_path`, replacing a file at `new_path`.
+#[cfg(feature = "userspace")]
+pub fn rename(old_path: &str, new_path: &str) -> Result<usize, Errno> {
+    match (CString::new(old_path), CString::new(new_path)) {
+        (Ok(c_old_path), Ok(c_new_path)) => syscall(SystemCall::Rename, &[
+            c_old_path.as_bytes().as_ptr() as usize,
+            c_new_path.as_bytes().as_ptr() as usize,
+        ]),
+        _ => Err(Errno::EBADSTR),
+    }
+}
+
+#[cfg(feature = "userspace")]
+pub fn stat(path: &str) -> Result<FileStat, Errno> {
+    let mut stat = FileStat::default();
+    let c_path = CString::new(path).map_err(|_| Errno::EBADSTR)?;
+    syscall(SystemCall::Stat, &[
+        c_path.as_bytes().as_ptr() as usize,
+        &mut stat as *mut FileStat as usize,
+        mem::size_of::<FileStat>(),
+    ])?;
+    Ok(stat)
+}
+
+/// Mount a file system of type `fs_type` ("fat", "9p", "tmpfs", "devfs" or "procfs") on the directory `path`.
+/// `device` is a block device (e.g. "/dev/ata0p0") for "fat" and the tag of the share for "9p".
+#[cfg(feature = "userspace")]
+pub fn mount(device: &str, path: &str, fs_type: &str, flags: MountFlags) -> Result<usize, Errno> {
+    match (CString::new(device), CString::new(path), CString::new(fs_type)) {
+        (Ok(c_device), Ok(c_path), Ok(c_fs_type)) => syscall(SystemCall::Mount, &[
+            c_device.as_bytes().as_ptr() as usize,
+            c_path.as_bytes().as_ptr() as usize,
+            c_fs_type.as_bytes().as_ptr() as usize,
+            flags.bits(),
+        ]),
+        _ => Err(Errno::EBADSTR),
+    }
+}
+
+#[cfg(feature = "userspace")]
+pub fn umount(path: &str) -> Result<usize, Errno> {
+    match CString::new(path) {
+        Ok(c_path) => syscall(SystemCall::Umount, &[c_path.as_bytes().as_ptr() as usize]),
+        Err(_) => Err(Errno::EBADSTR),
+    }
+}
+
+/// Set the permission bits (e.g. 0o644) of `path`, only allowed for its owner.
+#[cfg(feature = "userspace")]
+pub fn chmod(path: &str, permissions: u32) -> Result<usize, Errno> {
+    match CString::new(path) {
+        Ok(c_path) => syscall(SystemCall::Chmod, &[c_path.as_bytes().as_ptr() as usize, permissions as usize]),
+        Err(_) => Err(Errno::EBADSTR),
+    }
+}
+
+/// Change the owner and group of `path` (`syscall::sandbox::KEEP_ID` keeps the current one), only allowed for root.
+#[cfg(feature = "userspace")]
+pub fn chown(path: &str, uid: u32, gid: u32) -> Result<usize, Errno> {
+    match CString::new(path) {
+        Ok(c_path) => syscall(SystemCall::Chown, &[c_path.as_bytes().as_ptr() as usize, uid as usize, gid as usize]),
+        Err(_) => Err(Errno::EBADSTR),
+    }
+}
@@ -18,6 +18,14 @@ bitflags! {
         const EXCLUSIVE = 8;
         const DIRECTORY = 16;
         const WRITEONLY = 32; // relevant for pipes
+        const TRUNCATE  = 64; // discard the content of files
+    }
+}
+
+bitflags! {
+    /// Description: Option flags for mounting file systems
+    pub struct MountFlags: usize {
+        const READONLY = 1; // all modifications fail with `ERDONLY`
     }
 }
 
@@ -37,11 +45,28 @@ pub enum SeekOrigin {
 #[non_exhaustive]
 pub enum FileType {
     NamedPipe = 1,
+    CharDevice = 2,
     Directory = 4,
+    BlockDevice = 6,
     Regular = 8,
     Link = 16,
 }
 
+impl FileType {
+    /// Convert the type of a `RawDirent` or `FileStat` back into a `FileType`.
+    pub fn from_raw(raw: usize) -> Option<FileType> {
+        match raw {
+            1 => Some(FileType::NamedPipe),
+            2 => Some(FileType::CharDevice),
+            4 => Some(FileType::Directory),
+            6 => Some(FileType::BlockDevice),
+            8 => Some(FileType::Regular),
+            16 => Some(FileType::Link),
+            _ => None,
+        }
+    }
+}
+
 /// A directory entry 
 #[derive(Debug, Clone)]
 pub struct DirEntry {
@@ -72,3 +97,16 @@ impl RawDirent {
     }
 }
 
+/// Description: meta data of a named object, filled in by the `stat` syscall
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct FileStat {
+    pub file_type: usize, // see `FileType`
+    pub size: usize,      // in bytes
+    pub created: u64,     // time stamps in seconds since the epoch (0, if unknown)
+    pub modified: u64,
+    pub accessed: u64,
+    pub permissions: u32, // rwx bits for owner, group and others (e.g. 0o644)
+    pub uid: u32,         // owner
+    pub gid: u32,         // group
+}
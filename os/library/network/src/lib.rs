@@ -3,23 +3,34 @@
 #![no_std]
 extern crate alloc;
 
-use core::{ffi::CStr, net::{IpAddr, Ipv6Addr, SocketAddr}, ptr, str::FromStr};
+use core::{ffi::CStr, net::{IpAddr, Ipv6Addr, SocketAddr}, str::FromStr, sync::atomic::{AtomicUsize, Ordering}, time::Duration};
 
 use alloc::{ffi::CString, format, string::ToString, vec::Vec, vec};
-use syscall::{return_vals::Errno, syscall, SystemCall};
+use syscall::{network::{decode_capabilities, encode_address}, return_vals::Errno, syscall, SystemCall};
+
+pub use syscall::network::{DhcpLease, NetworkCapabilities, PathMtuEntry, RouteEntry, MAX_DNS_SERVERS};
+
+/// File, in which `netmand` records the DNS servers (one `nameserver <address>` line each)
+pub const RESOLVER_CONFIG: &str = "/etc/resolv.conf";
 
 pub struct UdpSocket {
     handle: usize,
     /// the (local) address this socket is bound to
     address: SocketAddr,
+    /// the default destination set by `connect`
+    peer: Option<SocketAddr>,
 }
 
 impl UdpSocket {
+    /// Bind to a local address. The unspecified address (`0.0.0.0` or `::`) means any address,
+    /// otherwise the address must be assigned to one of the interfaces.
     pub fn bind(address: SocketAddr) -> Result<Self, NetworkError> {
+        check_ip_version(address.ip())?;
         let protocol = 0;
         let handle = syscall(SystemCall::SockOpen, &[protocol])
             .map_err(|errno| match errno {
                 Errno::ENOTSUP => panic!("invalid protocol"),
+                Errno::ENOBUFS => NetworkError::NoBufferSpace,
                 errno => NetworkError::Unknown(errno),
             })?;
         // valid addresses do not contain 0 bytes
@@ -33,12 +44,53 @@ impl UdpSocket {
             .map_err(|errno| match errno {
                 Errno::EEXIST => panic!("socket has already been openend"),
                 Errno::EINVAL => NetworkError::InvalidAddress,
+                Errno::EADDRNOTAVAIL => NetworkError::AddressNotAvailable,
                 errno => NetworkError::Unknown(errno)
             })?;
-        Ok(Self { handle, address })
+        Ok(Self { handle, address, peer: None })
+    }
+
+    /// Set the default destination for `send` and only receive datagrams from this address with `recv`.
+    ///
+    /// As UDP is connectionless, this doesn't send anything.
+    pub fn connect(&mut self, address: SocketAddr) -> Result<(), NetworkError> {
+        if address.ip().is_unspecified() || address.port() == 0 {
+            return Err(NetworkError::InvalidAddress);
+        }
+        self.peer = Some(address);
+        Ok(())
+    }
+
+    /// Send data to the address this socket is connected to.
+    pub fn send(&self, buf: &[u8]) -> Result<usize, NetworkError> {
+        let peer = self.peer.ok_or(NetworkError::NotConnected)?;
+        self.send_to(buf, peer)
+    }
+
+    /// Receive a datagram from the address this socket is connected to.
+    ///
+    /// Datagrams from other addresses are discarded.
+    /// Returns 0, if no datagram is available.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize, NetworkError> {
+        let peer = self.peer.ok_or(NetworkError::NotConnected)?;
+        loop {
+            let (num_bytes, address) = self.recv_from(buf)?;
+            if num_bytes == 0 || address == peer {
+                return Ok(num_bytes);
+            }
+        }
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr, NetworkError> {
+        self.peer.ok_or(NetworkError::NotConnected)
     }
 
     pub fn send_to(&self, buf: &[u8], address: SocketAddr) -> Result<usize, NetworkError> {
+        check_ip_version(address.ip())?;
         let protocol = 0;
         // valid addresses do not contain 0 bytes
         let addr = CString::new(address.ip().to_string()).unwrap();
@@ -122,7 +174,7 @@ impl UdpSocket {
 impl Drop for UdpSocket {
     fn drop(&mut self) {
         let protocol = 0;
-        syscall(SystemCall::SockClose, &[ptr::from_ref(&self.handle) as usize, protocol])
+        syscall(SystemCall::SockClose, &[self.handle, protocol])
             .expect("failed to close socket");
     }
 }
@@ -134,11 +186,15 @@ pub struct TcpListener {
 }
 
 impl TcpListener {
+    /// Bind to a local address. The unspecified address (`0.0.0.0` or `::`) means any address,
+    /// otherwise the address must be assigned to one of the interfaces.
     pub fn bind(address: SocketAddr) -> Result<Self, NetworkError> {
+        check_ip_version(address.ip())?;
         let protocol = 1;
         let handle = syscall(SystemCall::SockOpen, &[protocol])
             .map_err(|errno| match errno {
                 Errno::ENOTSUP => panic!("invalid protocol"),
+                Errno::ENOBUFS => NetworkError::NoBufferSpace,
                 errno => NetworkError::Unknown(errno),
             })?;
         // valid addresses do not contain 0 bytes
@@ -152,6 +208,7 @@ impl TcpListener {
             .map_err(|errno| match errno {
                 Errno::EEXIST => panic!("socket as already been opened"),
                 Errno::EINVAL => NetworkError::InvalidAddress,
+                Errno::EADDRNOTAVAIL => NetworkError::AddressNotAvailable,
                 errno => NetworkError::Unknown(errno),
             })?;
         Ok(Self { handle, address })
@@ -172,6 +229,7 @@ impl TcpListener {
             .map_err(|errno| match errno {
                 Errno::EEXIST => panic!("socket as already been opened"),
                 Errno::EINVAL => NetworkError::InvalidAddress,
+                Errno::ENOBUFS => NetworkError::NoBufferSpace,
                 errno => NetworkError::Unknown(errno),
             })?;
         let old_handle = self.handle;
@@ -204,6 +262,7 @@ pub struct TcpStream {
 
 impl TcpStream {
     pub fn connect(address: SocketAddr) -> Result<Self, NetworkError> {
+        check_ip_version(address.ip())?;
         let protocol = 1;
         // this should be the maximum length for an IP address
         let mut addr_buf = [0u8; 40];
@@ -212,6 +271,7 @@ impl TcpStream {
         let handle = syscall(SystemCall::SockOpen, &[protocol])
             .map_err(|errno| match errno {
                 Errno::ENOTSUP => panic!("invalid protocol"),
+                Errno::ENOBUFS => NetworkError::NoBufferSpace,
                 errno => NetworkError::Unknown(errno),
             })?;
         let local_port: u16 = syscall(SystemCall::SockConnect, &[
@@ -266,6 +326,51 @@ impl TcpStream {
         Ok(num_bytes)
     }
 
+    /// Abort the connection, if sent data is not acknowledged within `timeout`.
+    /// Together with `set_keep_alive`, this bounds how long a dead connection goes unnoticed.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), NetworkError> {
+        self.set_option(TcpOption::Timeout, duration_to_ms(timeout))
+    }
+
+    /// Send keep-alive packets, if nothing has been received for `interval`.
+    pub fn set_keep_alive(&self, interval: Option<Duration>) -> Result<(), NetworkError> {
+        self.set_option(TcpOption::KeepAlive, duration_to_ms(interval))
+    }
+
+    /// Delay ACKs by up to `delay`, so they can be sent together with data. `None` acknowledges immediately.
+    pub fn set_ack_delay(&self, delay: Option<Duration>) -> Result<(), NetworkError> {
+        self.set_option(TcpOption::AckDelay, duration_to_ms(delay))
+    }
+
+    /// Disable (or re-enable) Nagle's algorithm.
+    pub fn set_nodelay(&self, nodelay: bool) -> Result<(), NetworkError> {
+        self.set_option(TcpOption::Nagle, (!nodelay).into())
+    }
+
+    /// Set the hop limit (TTL) of outgoing packets. `None` restores the default.
+    pub fn set_ttl(&self, ttl: Option<u8>) -> Result<(), NetworkError> {
+        self.set_option(TcpOption::HopLimit, ttl.unwrap_or(0).into())
+    }
+
+    fn set_option(&self, option: TcpOption, value: usize) -> Result<(), NetworkError> {
+        if !capabilities().contains(NetworkCapabilities::SOCKET_OPTIONS) {
+            return Err(NetworkError::NotSupported);
+        }
+        let protocol = 1;
+        syscall(SystemCall::SockSetOption, &[
+            self.handle,
+            protocol,
+            option as usize,
+            value,
+        ])
+            .map(|_| ())
+            .map_err(|errno| match errno {
+                Errno::ENOTSUP => panic!("invalid protocol"),
+                Errno::EINVAL => NetworkError::InvalidArgument,
+                errno => NetworkError::Unknown(errno),
+            })
+    }
+
     /// Check whether the receive half of the full-duplex connection buffer is open, and the receive buffer is not empty.
     pub fn can_recv(&self) -> Result<bool, NetworkError> {
         let protocol = 1;
@@ -324,6 +429,7 @@ impl IcmpSocket {
         let handle = syscall(SystemCall::SockOpen, &[protocol])
         .map_err(|errno| match errno {
             Errno::ENOTSUP => panic!("invalid protocol"),
+            Errno::ENOBUFS => NetworkError::NoBufferSpace,
             errno => NetworkError::Unknown(errno),
         })?;
         // ICMP doesn't bind to an IP address, but the syscall still expects one.
@@ -342,7 +448,44 @@ impl IcmpSocket {
         Ok(Self { handle, ident })
     }
 
+    /// The identifier this socket is bound to. Only echo replies with this identifier are received.
+    pub fn ident(&self) -> u16 {
+        self.ident
+    }
+
+    /// Send an echo request (ICMPv4 or ICMPv6, depending on `address`) with this socket's identifier.
+    ///
+    /// Returns the system time (in ms) at which the request has been sent,
+    /// so the round trip time can be calculated with [`EchoReply::rtt`].
+    pub fn send_echo_request(&self, address: IpAddr, seq_no: u16, data: &[u8]) -> Result<u64, NetworkError> {
+        let packet = echo_request(address.is_ipv6(), self.ident, seq_no, data);
+        let timestamp = systime();
+        self.send_to(&packet, address)?;
+        Ok(timestamp)
+    }
+
+    /// Receive the next echo reply for this socket's identifier.
+    ///
+    /// Other ICMP packets are discarded.
+    /// Returns `None`, if no reply is available.
+    pub fn recv_echo_reply(&self) -> Result<Option<EchoReply>, NetworkError> {
+        let mut buf = [0u8; 4096];
+        loop {
+            let (num_bytes, address) = self.recv(&mut buf)?;
+            // take the timestamp as early as possible
+            let timestamp = systime();
+            if num_bytes == 0 {
+                return Ok(None);
+            }
+            if let Some(reply) = parse_echo_reply(&buf[..num_bytes], address, timestamp)
+                && reply.ident == self.ident {
+                return Ok(Some(reply));
+            }
+        }
+    }
+
     pub fn send_to(&self, buf: &[u8], address: IpAddr) -> Result<usize, NetworkError> {
+        check_ip_version(address)?;
         let protocol = 2;
         // valid addresses do not contain 0 bytes
         let addr = CString::new(address.to_string()).unwrap();
@@ -361,7 +504,7 @@ impl IcmpSocket {
             })
     }
 
-     pub fn recv(&self, buf: &mut [u8]) -> Result<(usize, IpAddr), NetworkError> {
+    pub fn recv(&self, buf: &mut [u8]) -> Result<(usize, IpAddr), NetworkError> {
         let protocol = 2;
         // this should be the maximum length for an IP address
         let mut addr_buf = [0u8; 40];
@@ -390,19 +533,159 @@ impl IcmpSocket {
 impl Drop for IcmpSocket {
     fn drop(&mut self) {
         let protocol = 2;
-        syscall(SystemCall::SockClose, &[ptr::from_ref(&self.handle) as usize, protocol])
+        syscall(SystemCall::SockClose, &[self.handle, protocol])
             .expect("failed to close socket");
     }
 }
 
 
-#[derive(Debug)]
+const ICMPV4_ECHO_REPLY: u8 = 0;
+const ICMPV4_ECHO_REQUEST: u8 = 8;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+
+/// An echo reply received with [`IcmpSocket::recv_echo_reply`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EchoReply {
+    /// the host that sent this reply
+    pub source: IpAddr,
+    pub ident: u16,
+    pub seq_no: u16,
+    pub data: Vec<u8>,
+    /// the system time (in ms) at which this reply has been received
+    pub timestamp: u64,
+}
+
+impl EchoReply {
+    /// Round trip time (in ms) for a request sent at `sent` (as returned by [`IcmpSocket::send_echo_request`]).
+    pub fn rtt(&self, sent: u64) -> u64 {
+        self.timestamp.saturating_sub(sent)
+    }
+}
+
+/// Build an ICMP echo request packet.
+///
+/// The checksum of ICMPv6 packets covers the IPv6 pseudo header, so it is left empty and filled in by the kernel.
+pub fn echo_request(ipv6: bool, ident: u16, seq_no: u16, data: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(8 + data.len());
+    packet.push(if ipv6 { ICMPV6_ECHO_REQUEST } else { ICMPV4_ECHO_REQUEST });
+    // code
+    packet.push(0);
+    // checksum
+    packet.extend_from_slice(&[0, 0]);
+    packet.extend_from_slice(&ident.to_be_bytes());
+    packet.extend_from_slice(&seq_no.to_be_bytes());
+    packet.extend_from_slice(data);
+
+    if !ipv6 {
+        let checksum = icmpv4_checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+    packet
+}
+
+/// Parse an ICMP packet received from `source`. Returns `None`, if it isn't an echo reply.
+fn parse_echo_reply(packet: &[u8], source: IpAddr, timestamp: u64) -> Option<EchoReply> {
+    if packet.len() < 8 || packet[1] != 0 {
+        return None;
+    }
+    let reply_type = if source.is_ipv6() { ICMPV6_ECHO_REPLY } else { ICMPV4_ECHO_REPLY };
+    if packet[0] != reply_type {
+        return None;
+    }
+
+    Some(EchoReply {
+        source,
+        ident: u16::from_be_bytes([packet[4], packet[5]]),
+        seq_no: u16::from_be_bytes([packet[6], packet[7]]),
+        data: packet[8..].to_vec(),
+        timestamp,
+    })
+}
+
+/// The internet checksum (RFC 1071) over the whole packet.
+fn icmpv4_checksum(packet: &[u8]) -> u16 {
+    let mut sum: u32 = packet
+        .chunks(2)
+        .map(|chunk| u16::from_be_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// The current system time in ms.
+fn systime() -> u64 {
+    syscall(SystemCall::GetSystemTime, &[]).expect("failed to get system time") as u64
+}
+
+/// Options of TCP sockets, must match the kernel's `TcpOption`.
+#[repr(usize)]
+enum TcpOption {
+    Timeout = 0,
+    KeepAlive = 1,
+    AckDelay = 2,
+    Nagle = 3,
+    HopLimit = 4,
+}
+
+/// Convert a duration to milliseconds for the kernel, where 0 means "disabled".
+fn duration_to_ms(duration: Option<Duration>) -> usize {
+    duration.map_or(0, |duration| (duration.as_millis() as usize).max(1))
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub enum NetworkError {
     DeviceBusy,
     InvalidAddress,
+    /// The address to bind to is not assigned to any interface.
+    AddressNotAvailable,
+    /// The socket has no default destination (see `UdpSocket::connect`).
+    NotConnected,
+    InvalidArgument,
+    /// The socket buffer memory of the process (or of the whole system) is exhausted.
+    NoBufferSpace,
+    /// The running kernel doesn't support this feature (see [`capabilities`]).
+    NotSupported,
     Unknown(Errno),
 }
 
+/// Cached result of `SystemCall::NetworkCapabilities`, marked with `QUERIED` (0 = not queried yet)
+static KERNEL_CAPABILITIES: AtomicUsize = AtomicUsize::new(0);
+const QUERIED: usize = 1 << 63;
+
+/// Get the socket features of the running kernel.
+///
+/// Kernels, that predate this query, are assumed to support none of the optional features.
+/// The result is cached, so checking it before each call is cheap.
+pub fn capabilities() -> NetworkCapabilities {
+    kernel_network_info().1
+}
+
+/// Get the version of the running kernel's socket syscalls (0, if the kernel predates this query).
+pub fn abi_version() -> u32 {
+    kernel_network_info().0
+}
+
+fn kernel_network_info() -> (u32, NetworkCapabilities) {
+    let mut value = KERNEL_CAPABILITIES.load(Ordering::Relaxed);
+    if value == 0 {
+        // older kernels don't know this syscall and return ENOSYS
+        value = syscall(SystemCall::NetworkCapabilities, &[]).unwrap_or(0) | QUERIED;
+        KERNEL_CAPABILITIES.store(value, Ordering::Relaxed);
+    }
+    decode_capabilities(value & !QUERIED)
+}
+
+/// IPv6 addresses can only be used, if the kernel supports them.
+fn check_ip_version(address: IpAddr) -> Result<(), NetworkError> {
+    if address.is_ipv6() && !capabilities().contains(NetworkCapabilities::IPV6) {
+        return Err(NetworkError::NotSupported);
+    }
+    Ok(())
+}
+
 /// Get all IP addresses of this host.
 pub fn get_ip_addresses() -> Vec<IpAddr> {
     let mut buf = [0u8; 4096];
@@ -432,6 +715,166 @@ pub fn resolve_hostname(host: &str) -> Vec<IpAddr> {
     }
 }
 
+/// Move the current process (and all processes started by it afterwards) into a new network namespace
+/// with its own sockets, returning the namespace's id. This is experimental.
+///
+/// If `with_interface` is set, the namespace gets an interface (10.77.<id>.2/24), which is connected to
+/// the root namespace (10.77.<id>.1/24). Otherwise, sockets in the new namespace can't reach anything.
+/// The process must not have any open sockets.
+pub fn unshare_network(with_interface: bool) -> Result<usize, NetworkError> {
+    syscall(SystemCall::NetNamespaceCreate, &[with_interface as usize])
+        .map_err(|e| match e {
+            Errno::EBUSY => NetworkError::DeviceBusy,
+            e => NetworkError::Unknown(e),
+        })
+}
+
+/// Power on another machine by broadcasting a Wake-on-LAN magic packet for its MAC address.
+pub fn wake_on_lan(mac: [u8; 6]) -> Result<(), NetworkError> {
+    syscall(SystemCall::WakeOnLan, &[mac.as_ptr() as usize])
+        .map(|_| ())
+        .map_err(|e| match e {
+            Errno::EBUSY => NetworkError::DeviceBusy,
+            e => NetworkError::Unknown(e),
+        })
+}
+
+/// Add the address `address` with the prefix length `prefix_len` to the interface with index `interface`.
+pub fn add_address(interface: usize, address: IpAddr, prefix_len: u8) -> Result<(), NetworkError> {
+    change_address(SystemCall::NetAddressAdd, interface, address, prefix_len)
+}
+
+/// Remove the address `address` with the prefix length `prefix_len` from the interface with index `interface`.
+pub fn remove_address(interface: usize, address: IpAddr, prefix_len: u8) -> Result<(), NetworkError> {
+    change_address(SystemCall::NetAddressRemove, interface, address, prefix_len)
+}
+
+fn change_address(call: SystemCall, interface: usize, address: IpAddr, prefix_len: u8) -> Result<(), NetworkError> {
+    let addr_c = CString::new(address.to_string()).unwrap();
+    syscall(call, &[
+        interface,
+        addr_c.as_bytes_with_nul().as_ptr() as usize,
+        prefix_len as usize,
+    ])
+        .map(|_| ())
+        .map_err(|e| match e {
+            Errno::EINVAL => NetworkError::InvalidAddress,
+            Errno::EADDRNOTAVAIL => NetworkError::AddressNotAvailable,
+            e => NetworkError::Unknown(e),
+        })
+}
+
+/// Get the path MTUs, that the kernel has learned from ICMP messages (one entry per destination).
+pub fn path_mtu_cache() -> Result<Vec<PathMtuEntry>, NetworkError> {
+    if !capabilities().contains(NetworkCapabilities::PATH_MTU) {
+        return Err(NetworkError::NotSupported);
+    }
+
+    let mut entries = vec![PathMtuEntry::default(); 16];
+    loop {
+        // the syscall always returns the number of cached entries, even if the array is too small
+        let len = syscall(SystemCall::NetPathMtu, &[entries.as_mut_ptr() as usize, entries.len()])
+            .map_err(NetworkError::Unknown)?;
+        if len <= entries.len() {
+            entries.truncate(len);
+            return Ok(entries);
+        }
+        entries.resize(len, PathMtuEntry::default());
+    }
+}
+
+/// Add a route to `destination`/`prefix_len` via the gateway `via` to the interface with index `interface`.
+pub fn add_route(interface: usize, destination: IpAddr, prefix_len: u8, via: IpAddr) -> Result<(), NetworkError> {
+    check_configuration()?;
+    let dest_c = CString::new(destination.to_string()).unwrap();
+    let via_c = CString::new(via.to_string()).unwrap();
+    syscall(SystemCall::NetRouteAdd, &[
+        interface,
+        dest_c.as_bytes_with_nul().as_ptr() as usize,
+        prefix_len as usize,
+        via_c.as_bytes_with_nul().as_ptr() as usize,
+    ])
+        .map(|_| ())
+        .map_err(|e| match e {
+            Errno::EINVAL => NetworkError::InvalidAddress,
+            e => NetworkError::Unknown(e),
+        })
+}
+
+/// Remove the route to `destination`/`prefix_len` from the interface with index `interface`.
+pub fn remove_route(interface: usize, destination: IpAddr, prefix_len: u8) -> Result<(), NetworkError> {
+    check_configuration()?;
+    let dest_c = CString::new(destination.to_string()).unwrap();
+    syscall(SystemCall::NetRouteRemove, &[
+        interface,
+        dest_c.as_bytes_with_nul().as_ptr() as usize,
+        prefix_len as usize,
+    ])
+        .map(|_| ())
+        .map_err(|e| match e {
+            Errno::EINVAL => NetworkError::InvalidAddress,
+            e => NetworkError::Unknown(e),
+        })
+}
+
+/// Get the routes of the interface with index `interface`.
+pub fn routes(interface: usize) -> Result<Vec<RouteEntry>, NetworkError> {
+    check_configuration()?;
+    let mut entries = vec![RouteEntry::default(); 8];
+    loop {
+        // like `NetPathMtu`, the syscall always returns the number of routes
+        let len = syscall(SystemCall::NetRoutes, &[interface, entries.as_mut_ptr() as usize, entries.len()])
+            .map_err(NetworkError::Unknown)?;
+        if len <= entries.len() {
+            entries.truncate(len);
+            return Ok(entries);
+        }
+        entries.resize(len, RouteEntry::default());
+    }
+}
+
+/// Replace the DNS servers used by [`resolve_hostname`] (at most [`MAX_DNS_SERVERS`]).
+/// Only processes in the root network namespace may change them.
+pub fn set_dns_servers(servers: &[IpAddr]) -> Result<(), NetworkError> {
+    check_configuration()?;
+    if servers.len() > MAX_DNS_SERVERS {
+        return Err(NetworkError::InvalidArgument);
+    }
+    let encoded: Vec<[u8; 16]> = servers.iter().map(|server| encode_address(*server)).collect();
+    syscall(SystemCall::NetDnsServers, &[encoded.as_ptr() as usize, encoded.len()])
+        .map(|_| ())
+        .map_err(NetworkError::Unknown)
+}
+
+/// Get the lease of the kernel's DHCP client, if it has one.
+///
+/// The kernel doesn't apply the lease itself, this is done by `netmand`.
+pub fn dhcp_lease() -> Result<Option<DhcpLease>, NetworkError> {
+    check_configuration()?;
+    let mut lease = DhcpLease::default();
+    let found = syscall(SystemCall::NetDhcpLease, &[&mut lease as *mut DhcpLease as usize])
+        .map_err(NetworkError::Unknown)?;
+    Ok((found != 0).then_some(lease))
+}
+
+/// Drop the current DHCP lease and look for a DHCP server again.
+pub fn renew_dhcp() -> Result<(), NetworkError> {
+    check_configuration()?;
+    syscall(SystemCall::NetDhcpRenew, &[])
+        .map(|_| ())
+        .map_err(|e| match e {
+            Errno::ENOTSUP => NetworkError::NotSupported,
+            e => NetworkError::Unknown(e),
+        })
+}
+
+/// Routes, DNS servers and DHCP leases can only be handled, if the kernel supports it.
+fn check_configuration() -> Result<(), NetworkError> {
+    if !capabilities().contains(NetworkCapabilities::CONFIGURATION) {
+        return Err(NetworkError::NotSupported);
+    }
+    Ok(())
+}
 
 /// Split a \0-byte seperated list of IP addresses
 fn split_ips(buf: &[u8]) -> Vec<IpAddr> {
@@ -0,0 +1,32 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: lib                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Syscalls for getting random numbers from the kernel.            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+#![no_std]
+
+use syscall::return_vals::Errno;
+use syscall::{syscall, SystemCall};
+
+/// Do not block, if the kernel entropy pool has not been seeded yet.
+const GRND_NONBLOCK: usize = 0x1;
+
+/// Fill `buf` with random bytes. Blocks until the kernel has gathered enough entropy.
+pub fn getrandom(buf: &mut [u8]) -> Result<usize, Errno> {
+    syscall(SystemCall::GetRandom, &[buf.as_mut_ptr() as usize, buf.len(), 0])
+}
+
+/// Fill `buf` with random bytes. Returns `EAGAIN`, if the kernel has not gathered enough entropy yet.
+pub fn try_getrandom(buf: &mut [u8]) -> Result<usize, Errno> {
+    syscall(SystemCall::GetRandom, &[buf.as_mut_ptr() as usize, buf.len(), GRND_NONBLOCK])
+}
+
+/// Get a random `u64`.
+pub fn random_u64() -> u64 {
+    let mut buf = [0u8; 8];
+    getrandom(&mut buf).expect("Syscall: GetRandom failed.");
+    u64::from_le_bytes(buf)
+}
@@ -0,0 +1,130 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: clock                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Wall clock time, modelled after `std::time::SystemTime`. It is  ║
+   ║         not called `time`, to avoid a clash with the `time` library in  ║
+   ║         applications importing `runtime::*`.                            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::fmt;
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+use core::time::Duration;
+use syscall::return_vals::Errno;
+use syscall::time::Timespec;
+use syscall::{syscall, SystemCall};
+
+/// A point in time as shown by the wall clock. Unlike the system time, it can jump (when the clock is set).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemTime(Timespec);
+
+pub const UNIX_EPOCH: SystemTime = SystemTime::UNIX_EPOCH;
+
+/// Returned by `SystemTime::duration_since()`, if the other time is later.
+#[derive(Debug, Clone)]
+pub struct SystemTimeError(Duration);
+
+impl SystemTime {
+    pub const UNIX_EPOCH: SystemTime = SystemTime(Timespec { seconds: 0, nanoseconds: 0 });
+
+    pub fn now() -> Self {
+        let mut time = Timespec::default();
+        syscall(SystemCall::TimeGet, &[&raw mut time as usize]).expect("Syscall: TimeGet failed.");
+        Self(time)
+    }
+
+    /// Time elapsed from `earlier` to this time, or an error containing the difference, if `earlier` is later.
+    pub fn duration_since(&self, earlier: SystemTime) -> Result<Duration, SystemTimeError> {
+        let nanos = self.0.as_nanos() - earlier.0.as_nanos();
+        if nanos >= 0 {
+            Ok(duration_from_nanos(nanos as u128))
+        } else {
+            Err(SystemTimeError(duration_from_nanos(nanos.unsigned_abs())))
+        }
+    }
+
+    /// Time elapsed since this time (fails, if the clock has been set back in the meantime).
+    pub fn elapsed(&self) -> Result<Duration, SystemTimeError> {
+        SystemTime::now().duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<SystemTime> {
+        self.offset(i128::try_from(duration.as_nanos()).ok()?)
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<SystemTime> {
+        self.offset(-i128::try_from(duration.as_nanos()).ok()?)
+    }
+
+    /// Set the wall clock (and the RTC) to this time. Needs the `TIME` capability in a sandbox.
+    pub fn set(&self) -> Result<(), Errno> {
+        syscall(SystemCall::TimeSet, &[&raw const self.0 as usize])?;
+        Ok(())
+    }
+
+    fn offset(&self, nanos: i128) -> Option<SystemTime> {
+        let nanos = self.0.as_nanos().checked_add(nanos)?;
+        let time = Timespec::from_nanos(nanos);
+        // seconds are truncated by 'from_nanos()', so check that they have survived
+        (time.as_nanos() == nanos).then_some(SystemTime(time))
+    }
+}
+
+impl From<Timespec> for SystemTime {
+    fn from(time: Timespec) -> Self {
+        Self(time)
+    }
+}
+
+impl From<SystemTime> for Timespec {
+    fn from(time: SystemTime) -> Self {
+        time.0
+    }
+}
+
+impl Add<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn add(self, duration: Duration) -> SystemTime {
+        self.checked_add(duration).expect("Overflow when adding duration to system time")
+    }
+}
+
+impl AddAssign<Duration> for SystemTime {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn sub(self, duration: Duration) -> SystemTime {
+        self.checked_sub(duration).expect("Overflow when subtracting duration from system time")
+    }
+}
+
+impl SubAssign<Duration> for SystemTime {
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
+impl SystemTimeError {
+    /// How much later the other time is
+    pub fn duration(&self) -> Duration {
+        self.0
+    }
+}
+
+impl fmt::Display for SystemTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "second time provided was later than self")
+    }
+}
+
+fn duration_from_nanos(nanos: u128) -> Duration {
+    let nanos_per_second = Timespec::NANOS_PER_SECOND as u128;
+    Duration::new((nanos / nanos_per_second) as u64, (nanos % nanos_per_second) as u32)
+}
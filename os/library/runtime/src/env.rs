@@ -8,20 +8,14 @@ unsafe extern "C" {
 
 // Duplicated from 'kernel/src/consts.rs'
 const USER_SPACE_START: usize = 0x10000000000;
-const USER_SPACE_CODE_START: usize = USER_SPACE_START;
+pub(crate) const USER_SPACE_CODE_START: usize = USER_SPACE_START;
 const USER_SPACE_ENV_START: usize = USER_SPACE_CODE_START + 0x40000000;
+pub(crate) const USER_SPACE_CODE_END: usize = USER_SPACE_ENV_START;
 const USER_SPACE_ARG_START: usize = USER_SPACE_ENV_START;
 
 pub(crate) const ARGC_PTR: *const usize = USER_SPACE_ARG_START as *const usize;
 pub(crate) const ARGV_PTR: *const *const u8 = (USER_SPACE_ARG_START + size_of::<*const usize>()) as *const *const u8;
-
-/// The heap can be as large as 1 TB, but only a tiny fraction (1 MB) is mapped
-/// at the beginning. Additional chunks will be mapped as needed, but userspace
-/// doesn't really notice.
-// TODO: move to USER_SPACE_ENV_START + 0x40000000 when stacks are at the top
-// It currently occupies the last TB.
-pub(crate) const HEAP_START: usize = 63 * 1024 * 1024 * 1024 * 1024;
-pub(crate) const HEAP_SIZE: usize = 1024 * 1024 * 1024 * 1024;
+// envc and envp follow directly behind argv
 
 pub fn args() -> Args {
     Args::new()
@@ -56,4 +50,57 @@ impl Iterator for Args {
                 .ok()
         }
     }
-}
\ No newline at end of file
+}
+
+/// Read the null-terminated string at `ptr` (set up by the kernel in the user space environment)
+unsafe fn string_at(ptr: *const u8) -> Option<String> {
+    unsafe {
+        let len = strlen(ptr as *const c_char);
+        CStr::from_bytes_with_nul(slice_from_raw_parts(ptr, len + 1).as_ref()?)
+            .ok()
+            .and_then(|cstr| cstr.to_str().ok())
+            .map(|str| str.to_string())
+    }
+}
+
+/// Environment variables ("KEY=value"), which the process has been started with (see `process::Command::env()`)
+pub fn vars() -> Vars {
+    Vars::new()
+}
+
+/// Value of the environment variable `key`
+pub fn var(key: &str) -> Option<String> {
+    vars().find(|(k, _)| k == key).map(|(_, value)| value)
+}
+
+pub struct Vars {
+    index: usize
+}
+
+impl Vars {
+    fn new() -> Self {
+        Vars { index: 0 }
+    }
+}
+
+impl Iterator for Vars {
+    type Item = (String, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        unsafe {
+            let envc_ptr = ARGV_PTR.add(*ARGC_PTR) as *const usize;
+            let envp = envc_ptr.add(1) as *const *const u8;
+            while self.index < *envc_ptr {
+                let var = string_at(*envp.add(self.index));
+                self.index += 1;
+
+                // skip entries without '='
+                if let Some((key, value)) = var.as_deref().and_then(|var| var.split_once('=')) {
+                    return Some((key.to_string(), value.to_string()));
+                }
+            }
+
+            None
+        }
+    }
+}
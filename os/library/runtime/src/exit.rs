@@ -0,0 +1,63 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: exit                                                            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Terminating the process with `exit()` (running the hooks        ║
+   ║         registered with `atexit()`, e.g. for flushing buffered writers) ║
+   ║         or with `abort()` (without running them). Returning from        ║
+   ║         `main()` is the same as calling `exit(0)`.                      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::sync::atomic::{AtomicUsize, Ordering};
+use syscall::return_vals::Errno;
+use syscall::{syscall, SystemCall};
+
+/// Maximum number of hooks (C requires at least 32)
+const MAX_HOOKS: usize = 32;
+/// Exit code of `abort()` (like a process killed by SIGABRT on Unix)
+pub const ABORT_CODE: i32 = 134;
+
+/// Registered hooks as function pointers (0 = free or already run).
+/// This doesn't need the heap or a lock, so hooks can be registered at any time.
+static HOOKS: [AtomicUsize; MAX_HOOKS] = [const { AtomicUsize::new(0) }; MAX_HOOKS];
+static HOOK_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Register `hook` to be called by `exit()`. Hooks are called in reverse order of their registration.
+/// Fails with `ENOMEM`, if `MAX_HOOKS` hooks have been registered already.
+pub fn atexit(hook: fn()) -> Result<(), Errno> {
+    let index = HOOK_COUNT.fetch_add(1, Ordering::AcqRel);
+    if index >= MAX_HOOKS {
+        HOOK_COUNT.fetch_sub(1, Ordering::AcqRel);
+        return Err(Errno::ENOMEM);
+    }
+
+    HOOKS[index].store(hook as usize, Ordering::Release);
+    Ok(())
+}
+
+/// Run all hooks registered with `atexit()` and terminate the process (with all of its threads).
+/// The kernel doesn't pass `code` to the parent yet, but logs it, if it isn't 0.
+pub fn exit(code: i32) -> ! {
+    let count = HOOK_COUNT.load(Ordering::Acquire).min(MAX_HOOKS);
+    for hook in HOOKS[..count].iter().rev() {
+        // each hook only runs once, even if it calls exit() itself
+        let hook = hook.swap(0, Ordering::AcqRel);
+        if hook != 0 {
+            let hook: fn() = unsafe { core::mem::transmute(hook) };
+            hook();
+        }
+    }
+
+    terminate(code)
+}
+
+/// Terminate the process immediately, without running the hooks registered with `atexit()`.
+pub fn abort() -> ! {
+    terminate(ABORT_CODE)
+}
+
+fn terminate(code: i32) -> ! {
+    let _ = syscall(SystemCall::ProcessExit, &[code as isize as usize]);
+    panic!("System call 'ProcessExit' has returned!")
+}
@@ -0,0 +1,335 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: fs                                                              ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Files and directories of the naming service, modelled after     ║
+   ║         `std::fs`. A `File` or `ReadDir` owns its handle and closes it, ║
+   ║         when it is dropped.                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::string::String;
+use alloc::vec::Vec;
+use naming::shared_types::{self, DirEntry, FileStat, FileType, SeekOrigin};
+use syscall::return_vals::Errno;
+use syscall::{syscall, SystemCall};
+
+/// Size of the chunks, in which `read_to_end()` reads files
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// Position for `File::seek()`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(usize),
+    End(isize),
+    Current(isize),
+}
+
+/// Options for opening a file, e.g. `OpenOptions::new().write(true).create(true).open(path)`
+#[derive(Debug, Default, Copy, Clone)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    create: bool,
+    create_new: bool,
+    truncate: bool,
+}
+
+impl OpenOptions {
+    /// No access at all, at least `read` or `write` must be set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    /// Create the file, if it does not exist (requires `write`).
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Create the file and fail with `EEXIST`, if it already exists (requires `write`).
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Discard the content of the file (requires `write`).
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    pub fn open(&self, path: &str) -> Result<File, Errno> {
+        let mut flags = match (self.read, self.write) {
+            (true, true) => shared_types::OpenOptions::READWRITE,
+            (false, true) => shared_types::OpenOptions::WRITEONLY,
+            (true, false) => shared_types::OpenOptions::READONLY,
+            (false, false) => return Err(Errno::EINVAL),
+        };
+        if (self.create || self.create_new || self.truncate) && !self.write {
+            return Err(Errno::EINVAL);
+        }
+
+        if self.create_new {
+            flags |= shared_types::OpenOptions::CREATE | shared_types::OpenOptions::EXCLUSIVE;
+        } else if self.create {
+            flags |= shared_types::OpenOptions::CREATE;
+        }
+        if self.truncate {
+            flags |= shared_types::OpenOptions::TRUNCATE;
+        }
+
+        naming::open(path, flags).map(|handle| File { handle })
+    }
+}
+
+/// An open file, closed when dropped
+#[derive(Debug)]
+pub struct File {
+    handle: usize,
+}
+
+impl File {
+    /// Open an existing file for reading.
+    pub fn open(path: &str) -> Result<File, Errno> {
+        OpenOptions::new().read(true).open(path)
+    }
+
+    /// Open a file for writing, creating it, if it does not exist, and discarding its content otherwise.
+    pub fn create(path: &str) -> Result<File, Errno> {
+        OpenOptions::new().write(true).create(true).truncate(true).open(path)
+    }
+
+    /// The handle of the file for the functions of the `naming` library
+    pub fn handle(&self) -> usize {
+        self.handle
+    }
+
+    /// Read up to `buf.len()` bytes at the current position. Returns 0 at the end of the file.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, Errno> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        naming::read(self.handle, buf)
+    }
+
+    /// Read everything from the current position to the end of the file and append it to `buf`.
+    pub fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize, Errno> {
+        let start = buf.len();
+        loop {
+            let len = buf.len();
+            buf.resize(len + READ_CHUNK_SIZE, 0);
+            match self.read(&mut buf[len..]) {
+                Ok(0) => {
+                    buf.truncate(len);
+                    return Ok(len - start);
+                }
+                Ok(count) => buf.truncate(len + count),
+                Err(e) => {
+                    buf.truncate(len);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Like `read_to_end()`, but the content must be valid UTF-8 (otherwise `EBADSTR` is returned).
+    pub fn read_to_string(&mut self, buf: &mut String) -> Result<usize, Errno> {
+        let mut bytes = Vec::new();
+        self.read_to_end(&mut bytes)?;
+        let content = String::from_utf8(bytes).map_err(|_| Errno::EBADSTR)?;
+        buf.push_str(&content);
+        Ok(content.len())
+    }
+
+    /// Write up to `buf.len()` bytes at the current position.
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize, Errno> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        naming::write(self.handle, buf)
+    }
+
+    /// Write all of `buf`, fails with `EIO`, if the file doesn't take any more bytes.
+    pub fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Errno> {
+        while !buf.is_empty() {
+            match self.write(buf)? {
+                0 => return Err(Errno::EIO),
+                count => buf = &buf[count..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the position, returns the new position (from the start of the file).
+    pub fn seek(&mut self, pos: SeekFrom) -> Result<usize, Errno> {
+        match pos {
+            SeekFrom::Start(offset) => naming::seek(self.handle, offset as isize, SeekOrigin::Start),
+            SeekFrom::End(offset) => naming::seek(self.handle, offset, SeekOrigin::End),
+            SeekFrom::Current(offset) => naming::seek(self.handle, offset, SeekOrigin::Current),
+        }
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        let _ = naming::close(self.handle);
+    }
+}
+
+impl core::fmt::Write for File {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write_all(s.as_bytes()).map_err(|_| core::fmt::Error)
+    }
+}
+
+/// Read the whole file at `path`.
+pub fn read(path: &str) -> Result<Vec<u8>, Errno> {
+    let mut content = Vec::new();
+    File::open(path)?.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Read the whole file at `path`, which must contain valid UTF-8.
+pub fn read_to_string(path: &str) -> Result<String, Errno> {
+    let mut content = String::new();
+    File::open(path)?.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// Replace the content of the file at `path` with `content`, creating the file, if necessary.
+pub fn write(path: &str, content: &[u8]) -> Result<(), Errno> {
+    File::create(path)?.write_all(content)
+}
+
+/// Type, size, time stamps and owner of a named object, see `metadata()`
+#[derive(Debug, Copy, Clone)]
+pub struct Metadata {
+    stat: FileStat,
+}
+
+impl Metadata {
+    /// `None` for types unknown to this library
+    pub fn file_type(&self) -> Option<FileType> {
+        FileType::from_raw(self.stat.file_type)
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == Some(FileType::Directory)
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type() == Some(FileType::Regular)
+    }
+
+    /// Size in bytes
+    pub fn len(&self) -> usize {
+        self.stat.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stat.size == 0
+    }
+
+    /// Time stamps in seconds since the epoch (0, if the file system doesn't know them)
+    pub fn created(&self) -> u64 {
+        self.stat.created
+    }
+
+    pub fn modified(&self) -> u64 {
+        self.stat.modified
+    }
+
+    pub fn accessed(&self) -> u64 {
+        self.stat.accessed
+    }
+
+    /// rwx bits for owner, group and others (e.g. 0o644)
+    pub fn permissions(&self) -> u32 {
+        self.stat.permissions
+    }
+
+    pub fn uid(&self) -> u32 {
+        self.stat.uid
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.stat.gid
+    }
+}
+
+/// Get the type, size and time stamps of `path`.
+pub fn metadata(path: &str) -> Result<Metadata, Errno> {
+    naming::stat(path).map(|stat| Metadata { stat })
+}
+
+/// Iterator over the entries of a directory, see `read_dir()`
+#[derive(Debug)]
+pub struct ReadDir {
+    handle: usize,
+}
+
+impl Iterator for ReadDir {
+    type Item = Result<DirEntry, Errno>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        naming::readdir(self.handle).transpose()
+    }
+}
+
+impl Drop for ReadDir {
+    fn drop(&mut self) {
+        let _ = naming::close(self.handle);
+    }
+}
+
+/// Iterate over the entries of the directory `path`.
+pub fn read_dir(path: &str) -> Result<ReadDir, Errno> {
+    naming::open(path, shared_types::OpenOptions::DIRECTORY).map(|handle| ReadDir { handle })
+}
+
+/// Create the directory `path` (its parent must exist).
+pub fn create_dir(path: &str) -> Result<(), Errno> {
+    naming::mkdir(path).map(|_| ())
+}
+
+/// Rename (or move) `from` to `to` within the same file system, replacing `to`, if it exists.
+pub fn rename(from: &str, to: &str) -> Result<(), Errno> {
+    naming::rename(from, to).map(|_| ())
+}
+
+/// Remove the file (or pipe) `path`, fails with `EINVAL` for directories.
+pub fn remove_file(path: &str) -> Result<(), Errno> {
+    if metadata(path)?.is_dir() {
+        return Err(Errno::EINVAL);
+    }
+    naming::unlink(path).map(|_| ())
+}
+
+/// Remove the empty directory `path`.
+pub fn remove_dir(path: &str) -> Result<(), Errno> {
+    if !metadata(path)?.is_dir() {
+        return Err(Errno::ENOTDIR);
+    }
+    naming::unlink(path).map(|_| ())
+}
+
+/// Set the permission bits (e.g. 0o644) of `path`, only allowed for its owner.
+pub fn set_permissions(path: &str, permissions: u32) -> Result<(), Errno> {
+    naming::chmod(path, permissions).map(|_| ())
+}
+
+/// Write all data cached by the kernel to the storage devices.
+pub fn sync() -> Result<(), Errno> {
+    syscall(SystemCall::Sync, &[]).map(|_| ())
+}
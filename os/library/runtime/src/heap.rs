@@ -0,0 +1,112 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: heap                                                            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Global allocator of applications. Its memory is obtained with   ║
+   ║         `concurrent::memory::mmap()`, so it only counts against the     ║
+   ║         memory limit of a sandbox, when it is actually needed. Small    ║
+   ║         allocations are served from chunks managed by                   ║
+   ║         linked_list_allocator. A new chunk is mapped, when all chunks   ║
+   ║         are full, and each one is twice as large as the previous one,   ║
+   ║         so there are only a few of them. Large allocations get pages of ║
+   ║         their own, which are returned to the kernel, when they are      ║
+   ║         freed.                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+use concurrent::memory;
+use linked_list_allocator::Heap;
+use syscall::memory::Protection;
+use crate::sync::Mutex;
+
+const PAGE_SIZE: usize = 4096;
+/// Size of the first chunk (including its header)
+const FIRST_CHUNK_SIZE: usize = 1024 * 1024;
+/// Chunks don't grow beyond this size (unless a single allocation needs more)
+const MAX_CHUNK_SIZE: usize = 1024 * 1024 * 1024;
+/// Allocations of at least this size are mapped on their own
+const LARGE_ALLOCATION: usize = 128 * 1024;
+
+/// Header at the beginning of each chunk, followed by the memory managed by `heap`
+struct Chunk {
+    heap: Heap,
+    /// The previously mapped chunk (or null)
+    next: *mut Chunk,
+}
+
+struct Chunks {
+    /// The chunk mapped last (or null)
+    first: *mut Chunk,
+    next_size: usize,
+}
+
+// Safety: the chunks are only accessed while holding the mutex
+unsafe impl Send for Chunks {}
+
+pub struct Allocator {
+    chunks: Mutex<Chunks>,
+}
+
+impl Allocator {
+    pub const fn new() -> Self {
+        Self { chunks: Mutex::new(Chunks { first: ptr::null_mut(), next_size: FIRST_CHUNK_SIZE }) }
+    }
+}
+
+/// Large allocations are mapped on their own (larger alignments than a page can't be guaranteed by `mmap()`)
+fn is_large(layout: &Layout) -> bool {
+    layout.size() >= LARGE_ALLOCATION && layout.align() <= PAGE_SIZE
+}
+
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if is_large(&layout) {
+            return memory::mmap(layout.size(), Protection::READ | Protection::WRITE).unwrap_or(ptr::null_mut());
+        }
+
+        let mut chunks = self.chunks.lock();
+        let mut chunk = chunks.first;
+        while let Some(current) = unsafe { chunk.as_mut() } {
+            if let Ok(ptr) = current.heap.allocate_first_fit(layout) {
+                return ptr.as_ptr();
+            }
+            chunk = current.next;
+        }
+
+        // All chunks are full, so a new one is mapped
+        let needed = (size_of::<Chunk>() + layout.size() + layout.align()).next_multiple_of(PAGE_SIZE);
+        let size = chunks.next_size.max(needed);
+        let Ok(memory) = memory::mmap(size, Protection::READ | Protection::WRITE) else {
+            return ptr::null_mut();
+        };
+
+        let chunk = memory as *mut Chunk;
+        unsafe {
+            let heap = Heap::new(memory.add(size_of::<Chunk>()), size - size_of::<Chunk>());
+            chunk.write(Chunk { heap, next: chunks.first });
+        }
+        chunks.first = chunk;
+        chunks.next_size = (size * 2).min(MAX_CHUNK_SIZE);
+
+        unsafe { (*chunk).heap.allocate_first_fit(layout).map_or(ptr::null_mut(), |ptr| ptr.as_ptr()) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if is_large(&layout) {
+            let _ = memory::munmap(ptr, layout.size());
+            return;
+        }
+
+        let chunks = self.chunks.lock();
+        let mut chunk = chunks.first;
+        while let Some(current) = unsafe { chunk.as_mut() } {
+            if (current.heap.bottom()..current.heap.top()).contains(&ptr) {
+                unsafe { current.heap.deallocate(NonNull::new_unchecked(ptr), layout) };
+                return;
+            }
+            chunk = current.next;
+        }
+    }
+}
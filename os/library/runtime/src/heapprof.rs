@@ -0,0 +1,173 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: heapprof                                                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Heap profiling for applications. With the feature 'heapprof',   ║
+   ║         the global allocator records every allocation and free (with    ║
+   ║         size and call stack) in a ring buffer in the shared memory      ║
+   ║         region 'heapprof-<pid>', which the 'heapprof' application       ║
+   ║         reads and aggregates, e.g. to find leaks in long running        ║
+   ║         daemons. Call stacks are found by following frame pointers, so  ║
+   ║         the application should be built with                            ║
+   ║         RUSTFLAGS="-C force-frame-pointers=yes". The addresses can be   ║
+   ║         resolved with addr2line on the application binary.              ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::sync::atomic::AtomicU64;
+
+/// Size of the shared memory region (the maximum the kernel allows)
+pub const BUFFER_SIZE: usize = 0x100000;
+/// Identifies an initialized buffer ("HPRF")
+pub const MAGIC: u64 = 0x4650_5248;
+/// Number of return addresses recorded per event
+pub const STACK_DEPTH: usize = 8;
+
+pub const EVENT_ALLOC: u64 = 1;
+pub const EVENT_FREE: u64 = 2;
+
+/// Beginning of the shared memory region, followed by `capacity` events.
+#[repr(C)]
+pub struct Header {
+    pub magic: u64,
+    pub capacity: u64,
+    /// Number of events recorded so far (the event `n` is in slot `n % capacity`)
+    pub next: AtomicU64,
+}
+
+#[repr(C)]
+pub struct Event {
+    /// Number of the event plus one (0 = empty slot), written after the other fields
+    pub sequence: AtomicU64,
+    /// `EVENT_ALLOC` or `EVENT_FREE`
+    pub kind: u64,
+    pub address: u64,
+    pub size: u64,
+    /// Return addresses, innermost first (unused entries are 0)
+    pub stack: [u64; STACK_DEPTH],
+}
+
+/// Name of the shared memory region of the process `pid`.
+pub fn buffer_name(pid: usize) -> alloc::string::String {
+    alloc::format!("heapprof-{}", pid)
+}
+
+/// Number of events, that fit into the buffer.
+pub const fn capacity() -> usize {
+    (BUFFER_SIZE - size_of::<Header>()) / size_of::<Event>()
+}
+
+#[cfg(feature = "heapprof")]
+pub(crate) use profiler::{init, ProfilingAllocator};
+
+#[cfg(feature = "heapprof")]
+mod profiler {
+    use core::alloc::{GlobalAlloc, Layout};
+    use core::arch::asm;
+    use core::ptr;
+    use core::sync::atomic::{AtomicPtr, Ordering};
+    use concurrent::{process, shm};
+    use super::{buffer_name, capacity, Event, Header, BUFFER_SIZE, EVENT_ALLOC, EVENT_FREE, MAGIC, STACK_DEPTH};
+    use crate::{env, exit, ALLOCATOR};
+
+    /// Frame pointers further away from the stack pointer are considered invalid
+    const MAX_STACK_WALK: usize = 0x10000;
+
+    /// The shared buffer (null until `init()` has been called, allocations before are not recorded)
+    static BUFFER: AtomicPtr<Header> = AtomicPtr::new(ptr::null_mut());
+
+    /// Wraps the heap of the runtime and records all allocations and frees.
+    pub struct ProfilingAllocator;
+
+    unsafe impl GlobalAlloc for ProfilingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = unsafe { ALLOCATOR.alloc(layout) };
+            if !ptr.is_null() {
+                record(EVENT_ALLOC, ptr, layout.size());
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            record(EVENT_FREE, ptr, layout.size());
+            unsafe { ALLOCATOR.dealloc(ptr, layout) };
+        }
+    }
+
+    /// Create the shared buffer (needs the heap) and start recording.
+    pub fn init() {
+        let Some(pid) = process::current().map(|process| process.id()) else {
+            return;
+        };
+        let name = buffer_name(pid);
+        // the region may still exist, if a previous process with the same id hasn't exited cleanly
+        let Ok(id) = shm::shm_open(&name, BUFFER_SIZE, true).or_else(|_| shm::shm_open(&name, BUFFER_SIZE, false)) else {
+            return;
+        };
+        let Ok(buffer) = shm::shm_attach(id, false) else {
+            return;
+        };
+
+        let header = buffer as *mut Header;
+        unsafe {
+            header.write(Header { magic: MAGIC, capacity: capacity() as u64, next: Default::default() });
+            ptr::write_bytes(header.add(1) as *mut Event, 0, capacity());
+        }
+        BUFFER.store(header, Ordering::Release);
+
+        // the region is only needed while the process is running
+        let _ = exit::atexit(|| {
+            if let Some(process) = process::current() {
+                let _ = shm::shm_unlink(&buffer_name(process.id()));
+            }
+        });
+    }
+
+    /// Store an event in the next slot. This must not allocate.
+    fn record(kind: u64, address: *mut u8, size: usize) {
+        let header = BUFFER.load(Ordering::Acquire);
+        if header.is_null() {
+            return;
+        }
+
+        let header = unsafe { &*header };
+        let number = header.next.fetch_add(1, Ordering::AcqRel);
+        let event = unsafe { &mut *(ptr::from_ref(header).add(1) as *mut Event).add((number % header.capacity) as usize) };
+        // the reader skips the slot, while it is being written
+        event.sequence.store(0, Ordering::Release);
+        event.kind = kind;
+        event.address = address as u64;
+        event.size = size as u64;
+        event.stack = call_stack();
+        event.sequence.store(number + 1, Ordering::Release);
+    }
+
+    /// Follow the frame pointers, as long as they lie on the stack and the return addresses in the code.
+    #[inline(never)]
+    fn call_stack() -> [u64; STACK_DEPTH] {
+        let mut stack = [0; STACK_DEPTH];
+        let (mut frame, rsp): (usize, usize);
+        unsafe {
+            asm!("mov {}, rbp", out(reg) frame);
+            asm!("mov {}, rsp", out(reg) rsp);
+        }
+
+        for entry in stack.iter_mut() {
+            // the kernel starts threads with rbp = 0, which ends the chain
+            if frame == 0 || frame % 8 != 0 || frame < rsp || frame - rsp > MAX_STACK_WALK {
+                break;
+            }
+            let (next, return_address) = unsafe { (*(frame as *const usize), *(frame as *const usize).add(1)) };
+            if !(env::USER_SPACE_CODE_START..env::USER_SPACE_CODE_END).contains(&return_address) {
+                break;
+            }
+            *entry = return_address as u64;
+            if next <= frame {
+                break;
+            }
+            frame = next;
+        }
+
+        stack
+    }
+}
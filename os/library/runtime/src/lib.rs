@@ -12,41 +12,59 @@
 
 extern crate alloc;
 
+pub mod clock;
 pub mod env;
+pub mod exit;
+pub mod fs;
+pub mod heap;
+pub mod heapprof;
+pub mod process;
+pub mod sync;
+pub mod thread;
 
-use concurrent::{process, thread};
 use core::panic::PanicInfo;
 use terminal::println;
-use linked_list_allocator::LockedHeap;
-use syscall::{syscall, SystemCall};
 
+#[cfg(not(feature = "shared"))]
 unsafe extern "C" {
     fn main(argc: isize, argv: *const *const u8) -> isize;
 }
 
+#[cfg_attr(not(feature = "heapprof"), global_allocator)]
+pub static ALLOCATOR: heap::Allocator = heap::Allocator::new();
+
+/// Records all allocations for the `heapprof` application (see `heapprof` module)
+#[cfg(feature = "heapprof")]
 #[global_allocator]
-pub static ALLOCATOR: LockedHeap = LockedHeap::empty();
+static PROFILER: heapprof::ProfilingAllocator = heapprof::ProfilingAllocator;
 
 #[cfg(not(any(test, feature = "std")))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     println!("Panic: {}!", info);
-    thread::exit();
+    concurrent::thread::exit();
 }
 
+/// Entry function of applications, which link the runtime statically
+#[cfg(not(feature = "shared"))]
 #[unsafe(no_mangle)]
 extern "sysv64" fn entry() {
-    syscall(SystemCall::MapMemory, &[env::HEAP_START, env::HEAP_SIZE])
-        .expect("Could not create user heap.");
+    runtime_start(main);
+}
 
-    unsafe {
-        ALLOCATOR.lock().init(env::HEAP_START as *mut u8, env::HEAP_SIZE);
-    }
+/// Initialize the runtime and run `main()`. \
+/// The shared runtime (see `os/library/shared_runtime`) can't refer to the `main()` of the application,
+/// so dynamically linked applications call this from their own `entry()` (see `crt0.c` in the libc).
+#[unsafe(no_mangle)]
+pub extern "sysv64" fn runtime_start(main: unsafe extern "C" fn(argc: isize, argv: *const *const u8) -> isize) -> ! {
+    concurrent::thread::init_thread_environment();
 
-    thread::init_thread_environment();
+    #[cfg(feature = "heapprof")]
+    heapprof::init();
 
+    // Applications define `main()` without a return value, so the result is meaningless
     unsafe {
         main(*env::ARGC_PTR as isize, env::ARGV_PTR);
     }
-    process::exit();
+    exit::exit(0);
 }
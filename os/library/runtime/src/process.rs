@@ -0,0 +1,139 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: process                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Starting programs, modelled after `std::process`, e.g.          ║
+   ║         `Command::new("ls").arg("/bin").spawn()?.wait()`. A child       ║
+   ║         inherits the environment and the standard input and output of   ║
+   ║         its parent, unless configured otherwise.                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use concurrent::process;
+use syscall::return_vals::Errno;
+use syscall::signal::Signal;
+use syscall::spawn::{SpawnConfig, STDIO_INHERIT, STDIO_TERMINAL};
+use syscall::{syscall, SystemCall};
+
+use crate::env;
+
+/// Standard input or output of a child process
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stdio {
+    /// Use the same as the parent (default)
+    Inherit,
+    /// Use the terminal, even if the parent has been redirected
+    Terminal,
+    /// Use an open object of the parent (e.g. `File::handle()` or a pipe), the parent may close it after `spawn()`
+    Handle(usize),
+}
+
+impl Stdio {
+    fn raw(self) -> usize {
+        match self {
+            Stdio::Inherit => STDIO_INHERIT,
+            Stdio::Terminal => STDIO_TERMINAL,
+            Stdio::Handle(handle) => handle,
+        }
+    }
+}
+
+/// Builder for starting a program
+pub struct Command {
+    program: String,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    stdin: Stdio,
+    stdout: Stdio,
+}
+
+impl Command {
+    /// Start `program` (a path or the name of a program in `/bin`) with the environment of the current process.
+    pub fn new(program: &str) -> Self {
+        Command { program: program.to_string(), args: Vec::new(), env: env::vars().collect(), stdin: Stdio::Inherit, stdout: Stdio::Inherit }
+    }
+
+    pub fn arg(&mut self, arg: &str) -> &mut Self {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    pub fn args<'a>(&mut self, args: impl IntoIterator<Item = &'a str>) -> &mut Self {
+        self.args.extend(args.into_iter().map(|arg| arg.to_string()));
+        self
+    }
+
+    /// Set the environment variable `key` (replacing an inherited value).
+    pub fn env(&mut self, key: &str, value: &str) -> &mut Self {
+        self.env.retain(|(k, _)| k != key);
+        self.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Don't pass any inherited environment variables.
+    pub fn env_clear(&mut self) -> &mut Self {
+        self.env.clear();
+        self
+    }
+
+    pub fn stdin(&mut self, stdin: Stdio) -> &mut Self {
+        self.stdin = stdin;
+        self
+    }
+
+    pub fn stdout(&mut self, stdout: Stdio) -> &mut Self {
+        self.stdout = stdout;
+        self
+    }
+
+    /// Start the program. Fails with `ENOENT`, if it does not exist, or with `EINVALH`, if a handle is invalid.
+    pub fn spawn(&self) -> Result<Child, Errno> {
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        let env: Vec<String> = self.env.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+        let env: Vec<&str> = env.iter().map(String::as_str).collect();
+
+        let config = SpawnConfig {
+            path: self.program.as_ptr(),
+            path_len: self.program.len(),
+            args: args.as_ptr(),
+            args_len: args.len(),
+            env: env.as_ptr(),
+            env_len: env.len(),
+            stdin: self.stdin.raw(),
+            stdout: self.stdout.raw(),
+        };
+
+        let id = syscall(SystemCall::ProcessSpawn, &[&config as *const SpawnConfig as usize])?;
+        Ok(Child { id })
+    }
+}
+
+/// A process started by `Command::spawn()`. It keeps running, when this is dropped.
+#[derive(Debug)]
+pub struct Child {
+    id: usize,
+}
+
+impl Child {
+    /// Id of the process
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Wait until the process has exited.
+    pub fn wait(&self) -> Result<(), Errno> {
+        match syscall(SystemCall::ProcessWait, &[self.id]) {
+            // the process may already have exited
+            Ok(_) | Err(Errno::ESRCH) => Ok(()),
+            Err(errno) => Err(errno),
+        }
+    }
+
+    /// Send `signal` to the process (e.g. `Signal::Kill`).
+    pub fn kill(&self, signal: Signal) -> Result<(), Errno> {
+        process::kill(self.id, signal)
+    }
+}
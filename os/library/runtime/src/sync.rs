@@ -0,0 +1,166 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: sync                                                            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Blocking synchronization primitives modelled after `std::sync`, ║
+   ║         built on futexes: Threads waiting for a `Mutex` or a `Condvar`  ║
+   ║         are blocked in the kernel instead of spinning. The uncontended  ║
+   ║         case does not need a system call at all.                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
+use concurrent::futex;
+use syscall::return_vals::Errno;
+
+/// States of a `Mutex`
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+/// Locked and there may be waiting threads, which have to be woken up when unlocking
+const CONTENDED: u32 = 2;
+
+/// A mutual exclusion lock, which blocks waiting threads
+pub struct Mutex<T: ?Sized> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self { state: AtomicU32::new(UNLOCKED), data: UnsafeCell::new(data) }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Lock the mutex, blocking until it is available.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if self.state.compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            self.lock_contended();
+        }
+
+        MutexGuard { mutex: self }
+    }
+
+    /// Lock the mutex, if it is available.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state.compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    /// No locking needed, since the borrow checker ensures exclusive access.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn lock_contended(&self) {
+        // We don't know, if there are other waiters, so the mutex stays contended, once we have got it
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            let _ = futex::wait(&self.state, CONTENDED, None);
+        }
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            futex::wake(&self.state, 1);
+        }
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+/// Access to the data of a locked `Mutex`. The mutex is unlocked, when this is dropped.
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// A condition variable for waiting with a locked `Mutex` until another thread has changed its data.
+/// Wakeups may be spurious, so the condition has to be checked again after waiting (or use `wait_while()`).
+pub struct Condvar {
+    /// Incremented by each notification, so a notification between unlocking and waiting is not lost
+    seq: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self { seq: AtomicU32::new(0) }
+    }
+
+    /// Unlock the mutex of `guard`, wait for a notification and lock it again.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        self.wait_timeout(guard, None).0
+    }
+
+    /// Wait (see `wait()`) until `condition` returns false.
+    pub fn wait_while<'a, T: ?Sized>(&self, mut guard: MutexGuard<'a, T>, mut condition: impl FnMut(&mut T) -> bool) -> MutexGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Like `wait()`, but at most for `timeout` (`None` waits without a timeout).
+    /// Returns the guard and true, if the timeout has expired.
+    pub fn wait_timeout<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>, timeout: Option<Duration>) -> (MutexGuard<'a, T>, bool) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        let mutex = guard.mutex;
+        drop(guard);
+
+        let timed_out = futex::wait(&self.seq, seq, timeout) == Err(Errno::ETIMEDOUT);
+        (mutex.lock(), timed_out)
+    }
+
+    /// Wake up one waiting thread.
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        futex::wake(&self.seq, 1);
+    }
+
+    /// Wake up all waiting threads.
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        futex::wake_all(&self.seq);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
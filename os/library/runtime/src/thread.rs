@@ -0,0 +1,107 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: thread                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Threads running closures, modelled after `std::thread`, e.g.    ║
+   ║         `thread::spawn(move || work(data)).join()`. Each thread has its ║
+   ║         own user stack and its own copy of the `#[thread_local]`        ║
+   ║         statics, which are freed, when the thread exits.                ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::cell::UnsafeCell;
+use concurrent::thread::{self as raw, Thread};
+use syscall::return_vals::Errno;
+
+/// Result of a thread, written by the thread before it exits and read by `JoinHandle::join()` afterwards
+struct Packet<T> {
+    result: UnsafeCell<Option<T>>,
+}
+
+// The result is only accessed by the thread and, after it has exited, by the joining thread
+unsafe impl<T: Send> Sync for Packet<T> {}
+
+/// Passed to `start()` as argument of the new thread
+struct Start<F, T> {
+    f: F,
+    packet: Arc<Packet<T>>,
+}
+
+extern "sysv64" fn start<F: FnOnce() -> T, T>(arg: usize) {
+    let start = unsafe { Box::from_raw(arg as *mut Start<F, T>) };
+    let result = (start.f)();
+    unsafe { *start.packet.result.get() = Some(result) };
+}
+
+/// Builder for threads with a custom stack size
+#[derive(Debug, Default)]
+pub struct Builder {
+    stack_size: usize,
+}
+
+impl Builder {
+    /// Threads get the default stack size of 1 GiB (which is only mapped as needed).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Size of the user stack in bytes (rounded up to pages)
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = size;
+        self
+    }
+
+    /// Start a thread running `f`. Fails with `EAGAIN`, if the sandbox allows no more threads.
+    pub fn spawn<F, T>(self, f: F) -> Result<JoinHandle<T>, Errno>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let packet = Arc::new(Packet { result: UnsafeCell::new(None) });
+        let arg = Box::into_raw(Box::new(Start { f, packet: Arc::clone(&packet) }));
+
+        match raw::create_with_arg(start::<F, T>, arg as usize, self.stack_size) {
+            Ok(thread) => Ok(JoinHandle { thread, packet }),
+            Err(errno) => {
+                // the thread has not been started, so the closure is still ours
+                drop(unsafe { Box::from_raw(arg) });
+                Err(errno)
+            }
+        }
+    }
+}
+
+/// Start a thread running `f` (see `Builder` for a custom stack size).
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    Builder::new().spawn(f).expect("Failed to create thread")
+}
+
+/// A thread started by `spawn()`. It keeps running, when this is dropped.
+pub struct JoinHandle<T> {
+    thread: Thread,
+    packet: Arc<Packet<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn thread(&self) -> &Thread {
+        &self.thread
+    }
+
+    /// Wait until the thread has exited and return the result of its closure.
+    /// Fails with `EUNKN`, if the thread has not returned from it (e.g. because it panicked or has been killed).
+    pub fn join(self) -> Result<T, Errno> {
+        match self.thread.join() {
+            // the thread may already have exited
+            Ok(_) | Err(Errno::ESRCH) => {}
+            Err(errno) => return Err(errno),
+        }
+
+        unsafe { (*self.packet.result.get()).take() }.ok_or(Errno::EUNKN)
+    }
+}
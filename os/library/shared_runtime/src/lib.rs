@@ -0,0 +1,17 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: lib                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: The runtime and the libc as one shared library, which is linked ║
+   ║         to /lib/libruntime.so (see Makefile.toml) and loaded by the     ║
+   ║         dynamic loader of the kernel. Applications linking it must      ║
+   ║         bring their own `entry()`, which calls `runtime_start()` with   ║
+   ║         their `main()` (see `crt0.c` in the libc).                      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+#![no_std]
+
+// Only linked for their exported functions, the allocator and the panic handler
+pub use libc;
+pub use runtime;
@@ -0,0 +1,14 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: audio                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Format of the samples passed to `SystemCall::AudioWrite`, used  ║
+   ║         both in user and kernel mode.                                   ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+
+/// Description: Samples per second and channel
+pub const SAMPLE_RATE: u32 = 48000;
+/// Description: Samples are signed 16 bit values, interleaved (left, right, left, ...)
+pub const CHANNELS: usize = 2;
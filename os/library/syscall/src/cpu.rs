@@ -0,0 +1,35 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: cpu                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Temperature and frequency of a core, as returned by             ║
+   ║         `SystemCall::CpuSensors`, and thread affinities, used both in   ║
+   ║         user and kernel mode.                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+
+/// Description: Thread affinity (see `SystemCall::ThreadSetAffinity`), that allows all cores.
+/// Otherwise, bit n allows the core with the local APIC id n.
+pub const AFFINITY_ALL: u64 = u64::MAX;
+
+/// Description: Value of `CpuSensors::temperature`, if the core has no digital thermal sensor
+pub const TEMPERATURE_UNKNOWN: i32 = i32::MIN;
+
+/// Description: Readout of the sensors of a single core.
+/// The frequency is averaged over the time since the previous readout (of any process),
+/// so the first readout after boot covers the whole uptime.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CpuSensors {
+    /// Local APIC id of the core
+    pub apic_id: u32,
+    /// Core temperature in degrees Celsius (or `TEMPERATURE_UNKNOWN`)
+    pub temperature: i32,
+    /// Actual frequency relative to the base frequency (in permille, 0 if the core has no APERF/MPERF counters)
+    pub performance_permille: u32,
+    /// Base frequency in MHz (0 if unknown)
+    pub base_frequency_mhz: u32,
+    /// Actual frequency in MHz (0 if unknown)
+    pub frequency_mhz: u32,
+}
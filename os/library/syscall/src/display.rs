@@ -0,0 +1,21 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: display                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Display modes, as returned by `SystemCall::DisplayModes` and    ║
+   ║         `SystemCall::DisplayModeGet` and passed to                      ║
+   ║         `SystemCall::DisplayModeSet`, used both in user and kernel      ║
+   ║         mode.                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+
+/// Description: Resolution and color depth of the framebuffer
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DisplayMode {
+    pub width: u32,
+    pub height: u32,
+    /// Bits per pixel
+    pub bpp: u8,
+}
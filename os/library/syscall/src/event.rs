@@ -0,0 +1,38 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: event                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Event counters for delivering asynchronous kernel events to     ║
+   ║         user space, used both in user and kernel mode.                  ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+/// Description: Wait forever in `SystemCall::EventPoll`
+pub const POLL_FOREVER: usize = usize::MAX;
+
+/// Description: What increments an event counter, chosen with `SystemCall::EventCreate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+#[repr(usize)]
+pub enum EventSource {
+    /// Only `SystemCall::EventSignal`
+    User = 0,
+    /// Expiration of a periodic timer (the interval is given in milliseconds)
+    Timer = 1,
+    /// A device has been added or removed
+    Device = 2,
+    /// A network interface has been added or removed, or its link state has changed
+    Link = 3,
+    /// The kernel's DHCP client has acquired, renewed or lost a lease
+    Dhcp = 4,
+}
+
+/// Description: An event counter to wait for with `SystemCall::EventPoll`.
+/// The kernel sets `ready`, if the counter is not zero. The counter is not reset by polling.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct PollEntry {
+    pub handle: usize,
+    pub ready: bool,
+}
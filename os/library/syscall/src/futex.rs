@@ -0,0 +1,15 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: futex                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Consts for `SystemCall::FutexWait` and `FutexWake`, used both   ║
+   ║         in user and kernel mode.                                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+
+/// Description: Wait without a timeout in `SystemCall::FutexWait`
+pub const WAIT_FOREVER: usize = usize::MAX;
+
+/// Description: Wake up all waiting threads with `SystemCall::FutexWake`
+pub const WAKE_ALL: usize = usize::MAX;
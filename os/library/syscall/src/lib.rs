@@ -12,7 +12,23 @@
 use core::mem;
 use crate::return_vals::SyscallResult;
 
+pub mod audio;
+pub mod cpu;
+pub mod display;
+pub mod event;
+pub mod futex;
+pub mod memory;
+pub mod network;
+pub mod priority;
+pub mod process_info;
 pub mod return_vals;
+pub mod sandbox;
+pub mod signal;
+pub mod spawn;
+pub mod time;
+pub mod tls;
+pub mod usage;
+pub mod vsock;
 
 /// Enum with all known system calls
 #[repr(u16)] // Cannot use full size of rax, because ax is needed to set up fs/gs in syscall_handler()
@@ -72,6 +88,70 @@ pub enum SystemCall {
     ShmAttach,
     ShmDetach,
     ShmUnlink,
+    GetRandom,
+    DeviceStats,
+    NetNamespaceCreate,
+    WakeOnLan,
+    PowerOff,
+    ProcessExecuteSandboxed,
+    SockSetOption,
+    NetAddressAdd,
+    NetAddressRemove,
+    ProcessUsage,
+    MouseReadEvent,
+    VsockListen,
+    VsockAccept,
+    VsockConnect,
+    VsockSend,
+    VsockReceive,
+    VsockClose,
+    NetworkCapabilities,
+    CpuPark,
+    NetPathMtu,
+    EventCreate,
+    EventSignal,
+    EventRead,
+    EventPoll,
+    EventClose,
+    NetRouteAdd,
+    NetRouteRemove,
+    NetRoutes,
+    NetDnsServers,
+    NetDhcpLease,
+    NetDhcpRenew,
+    TimeGet,
+    TimeSet,
+    AudioWrite,
+    RamdiskCreate,
+    DisplayModes,
+    DisplayModeGet,
+    DisplayModeSet,
+    Devices,
+    DeviceRescan,
+    CpuSensors,
+    Unlink,
+    Rename,
+    Stat,
+    Sync,
+    Mount,
+    Umount,
+    Chmod,
+    Chown,
+    ThreadSetPriority,
+    ThreadGetPriority,
+    ProcessSetPriority,
+    ProcessKill,
+    TerminalSetForeground,
+    TerminalInterrupt,
+    ProcessSpawn,
+    ProcessWait,
+    FutexWait,
+    FutexWake,
+    ThreadSetAffinity,
+    ThreadGetAffinity,
+    ProcessList,
+    MemoryMap,
+    MemoryUnmap,
 }
 
 pub const NUM_SYSCALLS: usize = mem::variant_count::<SystemCall>() as usize;
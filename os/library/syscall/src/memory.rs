@@ -0,0 +1,29 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: memory                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Flags for `SystemCall::MemoryMap`, used both in user and kernel ║
+   ║         mode.                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use bitflags::bitflags;
+
+bitflags! {
+    /// Description: Allowed accesses to memory mapped with `SystemCall::MemoryMap`.
+    /// Without any of them, every access fails (e.g. for guard pages).
+    /// Pages are always executable, if they can be read (no-execute pages are not used yet).
+    pub struct Protection: usize {
+        const READ  = 1;
+        const WRITE = 2;
+        const EXEC  = 4;
+    }
+}
+
+bitflags! {
+    /// Description: Kind of memory mapped with `SystemCall::MemoryMap`
+    pub struct MapFlags: usize {
+        /// Memory, that is not backed by a file, but zeroed on its first access (the only kind supported yet)
+        const ANONYMOUS = 1;
+    }
+}
@@ -0,0 +1,155 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: network                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Version and features of the kernel's network stack and the      ║
+   ║         structures exchanged by its configuration syscalls (path MTU    ║
+   ║         cache, routes and DHCP leases), used both in user and kernel    ║
+   ║         mode.                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use bitflags::bitflags;
+
+/// Description: Version of the socket syscalls. Incremented, when their parameters change incompatibly.
+pub const NETWORK_ABI_VERSION: u32 = 1;
+
+bitflags! {
+    /// Description: Socket features of the running kernel, reported by `SystemCall::NetworkCapabilities`.
+    /// Kernels without this syscall return `ENOSYS` and are assumed to support none of them.
+    pub struct NetworkCapabilities: u32 {
+        /// `SockCanSend` and `SockCanReceive` (checking a socket without blocking)
+        const POLL           = 1;
+        /// Sockets can be switched to return `EAGAIN` instead of blocking
+        const NON_BLOCKING   = 2;
+        /// IPv6 addresses can be used for binding, connecting and sending
+        const IPV6           = 4;
+        /// `SockSetOption` (TCP timeouts, keep-alive, Nagle, hop limit)
+        const SOCKET_OPTIONS = 8;
+        /// `NetPathMtu` (inspecting the path MTU cache)
+        const PATH_MTU       = 16;
+        /// `NetRouteAdd`, `NetRouteRemove`, `NetRoutes`, `NetDnsServers`, `NetDhcpLease` and `NetDhcpRenew`
+        const CONFIGURATION  = 32;
+    }
+}
+
+/// Description: Combine version and capabilities into the return value of `SystemCall::NetworkCapabilities`.
+pub fn encode_capabilities(version: u32, capabilities: NetworkCapabilities) -> usize {
+    ((version as usize) << 32) | capabilities.bits() as usize
+}
+
+/// Description: Split the return value of `SystemCall::NetworkCapabilities` into version and capabilities.
+/// Unknown bits (of newer kernels) are ignored.
+pub fn decode_capabilities(value: usize) -> (u32, NetworkCapabilities) {
+    ((value >> 32) as u32, NetworkCapabilities::from_bits_truncate(value as u32))
+}
+
+/// Description: An entry of the kernel's path MTU cache, as copied to user space by `SystemCall::NetPathMtu`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PathMtuEntry {
+    /// IPv4 destinations are stored as IPv4-mapped IPv6 addresses
+    destination: [u8; 16],
+    /// Largest IP packet (including the IP header), that reaches the destination without fragmentation
+    pub mtu: u32,
+    /// Time since the MTU has been learned (in milliseconds)
+    pub age_ms: u32,
+}
+
+impl PathMtuEntry {
+    pub fn new(destination: IpAddr, mtu: u32, age_ms: u32) -> Self {
+        Self { destination: encode_address(destination), mtu, age_ms }
+    }
+
+    pub fn destination(&self) -> IpAddr {
+        decode_address(self.destination)
+    }
+}
+
+/// Description: A route of an interface, as copied to user space by `SystemCall::NetRoutes`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RouteEntry {
+    destination: [u8; 16],
+    /// Prefix length of the destination network (relative to the IP version of the destination)
+    pub prefix_len: u8,
+    via: [u8; 16],
+}
+
+impl RouteEntry {
+    pub fn new(destination: IpAddr, prefix_len: u8, via: IpAddr) -> Self {
+        Self { destination: encode_address(destination), prefix_len, via: encode_address(via) }
+    }
+
+    pub fn destination(&self) -> IpAddr {
+        decode_address(self.destination)
+    }
+
+    pub fn via(&self) -> IpAddr {
+        decode_address(self.via)
+    }
+}
+
+/// Description: Maximum number of DNS servers used by the kernel's resolver
+pub const MAX_DNS_SERVERS: usize = 4;
+
+/// Description: The current DHCP lease of the kernel's DHCP client, as copied to user space by `SystemCall::NetDhcpLease`.
+/// The kernel only records the lease, applying it is left to user space (`netmand`).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DhcpLease {
+    /// Index of the interface in the root network namespace, that the lease has been acquired on
+    pub interface: u32,
+    address: [u8; 4],
+    pub prefix_len: u8,
+    has_router: bool,
+    router: [u8; 4],
+    dns_count: u8,
+    dns_servers: [[u8; 4]; MAX_DNS_SERVERS],
+}
+
+impl DhcpLease {
+    /// Additional DNS servers beyond `MAX_DNS_SERVERS` are ignored.
+    pub fn new(interface: u32, address: Ipv4Addr, prefix_len: u8, router: Option<Ipv4Addr>, dns_servers: &[Ipv4Addr]) -> Self {
+        let mut lease = Self {
+            interface,
+            address: address.octets(),
+            prefix_len,
+            has_router: router.is_some(),
+            router: router.unwrap_or(Ipv4Addr::UNSPECIFIED).octets(),
+            ..Self::default()
+        };
+        for (slot, server) in lease.dns_servers.iter_mut().zip(dns_servers) {
+            *slot = server.octets();
+            lease.dns_count += 1;
+        }
+        lease
+    }
+
+    pub fn address(&self) -> Ipv4Addr {
+        Ipv4Addr::from(self.address)
+    }
+
+    /// The default gateway, if the DHCP server has sent one
+    pub fn router(&self) -> Option<Ipv4Addr> {
+        self.has_router.then(|| Ipv4Addr::from(self.router))
+    }
+
+    pub fn dns_servers(&self) -> impl Iterator<Item = Ipv4Addr> + '_ {
+        self.dns_servers[..(self.dns_count as usize).min(MAX_DNS_SERVERS)].iter().map(|server| Ipv4Addr::from(*server))
+    }
+}
+
+/// Description: Encode an address for a syscall. IPv4 addresses are stored as IPv4-mapped IPv6 addresses.
+pub fn encode_address(address: IpAddr) -> [u8; 16] {
+    match address {
+        IpAddr::V4(address) => address.to_ipv6_mapped(),
+        IpAddr::V6(address) => address,
+    }.octets()
+}
+
+/// Description: Decode an address encoded by `encode_address()`.
+pub fn decode_address(address: [u8; 16]) -> IpAddr {
+    Ipv6Addr::from(address).to_canonical()
+}
@@ -0,0 +1,45 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: priority                                                        ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Scheduling priorities of threads and processes, used both in    ║
+   ║         user and kernel mode.                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+/// Description: Priority of a thread, set with `SystemCall::ThreadSetPriority` or `SystemCall::ProcessSetPriority`.
+/// Its meaning depends on the `SchedulingClass` of the thread.
+/// Threads waiting for too long run nevertheless, so lower priorities don't starve completely.
+/// Only root may raise a priority above `Normal`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, IntoPrimitive, TryFromPrimitive)]
+#[repr(usize)]
+pub enum Priority {
+    /// Threads with deadlines, e.g. for audio (should block most of the time)
+    Realtime = 0,
+    /// Kernel threads serving devices, e.g. the network stack
+    High = 1,
+    /// Default for applications
+    Normal = 2,
+    /// Housekeeping, that can wait for the system to be idle
+    Background = 3,
+}
+
+impl Priority {
+    /// Number of priority levels
+    pub const COUNT: usize = 4;
+}
+
+/// Description: How a thread shares the CPU, set with `SystemCall::ThreadSetPriority`. \
+/// Strict threads of priority `Normal` or higher run before all fair threads, strict `Background` threads after them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+#[repr(usize)]
+pub enum SchedulingClass {
+    /// The thread gets a share of the CPU weighted by its priority, measured by its virtual runtime.
+    /// Default for user threads.
+    Fair = 0,
+    /// The scheduler picks a ready thread of the highest priority, threads of the same priority share the CPU.
+    /// Default for kernel threads, only root may select this for user threads.
+    Strict = 1,
+}
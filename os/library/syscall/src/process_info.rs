@@ -0,0 +1,111 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: process_info                                                    ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Snapshot of a thread and its process, as returned by            ║
+   ║         `SystemCall::ProcessList`, used both in user and kernel mode.   ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use crate::priority::{Priority, SchedulingClass};
+
+/// Description: Maximum length of `ThreadInfo::name` (longer names are truncated)
+pub const NAME_LEN: usize = 32;
+
+/// Description: Value of `ThreadInfo::core`, if the thread has not run yet
+pub const NO_CORE: u32 = u32::MAX;
+
+/// Description: Scheduling state of a thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ThreadStatus {
+    /// Not started yet
+    Created = 0,
+    /// Waiting to be scheduled
+    Ready = 1,
+    /// Currently executing on a core
+    Running = 2,
+    /// Waiting for an event, e.g. a futex, a lock or input
+    Blocked = 3,
+    /// Sleeping for some time
+    Sleeping = 4,
+    /// Finished, waiting to be reaped
+    Exited = 5,
+}
+
+/// Description: A thread and its process, filled in by `SystemCall::ProcessList`.
+/// Memory belongs to the whole process, so it is the same for all of its threads.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadInfo {
+    /// Id of the process
+    pub pid: usize,
+    /// Id of the thread
+    pub tid: usize,
+    /// Name of the program running in the process (UTF-8, `name_len` bytes are valid)
+    pub name: [u8; NAME_LEN],
+    pub name_len: usize,
+    pub status: ThreadStatus,
+    pub priority: Priority,
+    pub class: SchedulingClass,
+    /// Local APIC id of the core, that runs the thread or has run it last (or `NO_CORE`)
+    pub core: u32,
+    /// Memory mapped on demand for the process (stack and heap pages, in KiB)
+    pub memory_kib: u64,
+    /// Time the thread has spent executing in user mode (in microseconds)
+    pub user_time_us: u64,
+    /// Time the thread has spent executing in kernel mode (in microseconds)
+    pub system_time_us: u64,
+    /// Number of times the thread gave up the CPU, e.g. by blocking or sleeping
+    pub voluntary_switches: u64,
+    /// Number of times the thread was preempted
+    pub involuntary_switches: u64,
+    /// Number of times the thread has been taken from a ready queue to run
+    pub dispatches: u64,
+    /// Total time the thread has waited in a ready queue before running (in microseconds)
+    pub run_delay_us: u64,
+    /// Longest time the thread has waited in a ready queue (in microseconds)
+    pub max_run_delay_us: u64,
+}
+
+impl ThreadInfo {
+    /// Name of the program running in the process
+    pub fn name(&self) -> &str {
+        let len = self.name_len.min(NAME_LEN);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+
+    /// Store `name`, truncated to `NAME_LEN` bytes (at a character boundary)
+    pub fn set_name(&mut self, name: &str) {
+        let mut len = name.len().min(NAME_LEN);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.name = [0; NAME_LEN];
+        self.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        self.name_len = len;
+    }
+}
+
+impl Default for ThreadInfo {
+    fn default() -> Self {
+        Self {
+            pid: 0,
+            tid: 0,
+            name: [0; NAME_LEN],
+            name_len: 0,
+            status: ThreadStatus::Created,
+            priority: Priority::Normal,
+            class: SchedulingClass::Fair,
+            core: NO_CORE,
+            memory_kib: 0,
+            user_time_us: 0,
+            system_time_us: 0,
+            voluntary_switches: 0,
+            involuntary_switches: 0,
+            dispatches: 0,
+            run_delay_us: 0,
+            max_run_delay_us: 0,
+        }
+    }
+}
@@ -34,6 +34,12 @@ pub enum Errno {
     EOF        = -18, // End of file
     EPIPE      = -19, // Broken pipe
     ENOMEM     = -20, // Not enough space / cannot allocate memory
+    EADDRNOTAVAIL = -21, // Address not available
+    EINTR      = -22, // Interrupted operation
+    EIO        = -23, // Input/output error
+    ENOBUFS    = -24, // No buffer space available
+    ENOSYS     = -25, // System call not implemented
+    ETIMEDOUT  = -26, // Operation timed out
 }
 
 
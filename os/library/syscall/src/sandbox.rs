@@ -0,0 +1,52 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: sandbox                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Types for running processes in a sandbox, used both in user     ║
+   ║         and kernel mode.                                                ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use bitflags::bitflags;
+
+/// User and group id of the administrator, who may access all objects and change owners
+pub const ROOT_ID: u32 = 0;
+/// Value of `SandboxConfig::uid` and `gid` (and of the arguments of `SystemCall::Chown`), that keeps the current id
+pub const KEEP_ID: u32 = u32::MAX;
+
+bitflags! {
+    /// Description: Capabilities of a process. Syscalls needing a missing capability fail with `EACCES`.
+    pub struct Capabilities: u64 {
+        /// Open sockets, resolve host names, create network namespaces, send WoL packets
+        const NETWORK  = 1;
+        /// Start other applications
+        const SPAWN    = 2;
+        /// Create directories, files and pipes and open objects for writing
+        const FS_WRITE = 4;
+        /// Map the framebuffer, set the display mode, play audio, create ramdisks and rescan for devices
+        const DEVICES  = 8;
+        /// Power down the system and park cores
+        const POWER    = 16;
+        /// Set the wall clock time
+        const TIME     = 32;
+    }
+}
+
+/// Description: Sandbox for a new process, passed to `SystemCall::ProcessExecuteSandboxed`.
+/// A sandbox can only restrict a process further, so a sandboxed process can't escape by spawning children.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SandboxConfig {
+    /// Directory used as root of the naming service (UTF-8, not null terminated; null pointer = no change)
+    pub root: *const u8,
+    pub root_len: usize,
+    /// Bits of `Capabilities`
+    pub capabilities: u64,
+    /// Maximum number of threads of the process (0 = unlimited)
+    pub max_threads: usize,
+    /// Maximum number of bytes of heap memory the process may map (0 = unlimited)
+    pub max_memory: usize,
+    /// User and group id of the process (`KEEP_ID` = no change, only root may change them)
+    pub uid: u32,
+    pub gid: u32,
+}
@@ -0,0 +1,32 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: signal                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Signals, that can be sent to processes, used both in user and   ║
+   ║         kernel mode.                                                    ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+/// Description: Signal sent to a process with `SystemCall::ProcessKill` (numbered like on Unix).
+/// Signals are delivered when a thread of the process returns from a system call or is interrupted by the timer.
+/// There are no handlers yet, so every signal terminates the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+#[repr(usize)]
+pub enum Signal {
+    /// Ctrl+C has been pressed in the terminal
+    Interrupt = 2,
+    /// Terminate immediately (will never be handled by the process).
+    /// Sent to another process, it is not delivered, but the process is killed right away, even if its threads are blocked.
+    Kill = 9,
+    /// Polite request to terminate
+    Terminate = 15,
+}
+
+impl Signal {
+    /// Bit of the signal in a set of pending signals
+    pub const fn mask(self) -> u32 {
+        1 << self as usize
+    }
+}
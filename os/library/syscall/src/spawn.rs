@@ -0,0 +1,33 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: spawn                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Types for starting programs with `SystemCall::ProcessSpawn`,    ║
+   ║         used both in user and kernel mode.                              ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+
+/// Description: Value of `SpawnConfig::stdin` and `stdout`: use the same as the calling process
+pub const STDIO_INHERIT: usize = usize::MAX;
+/// Description: Value of `SpawnConfig::stdin` and `stdout`: use the terminal
+pub const STDIO_TERMINAL: usize = usize::MAX - 1;
+
+/// Description: Program to be started by `SystemCall::ProcessSpawn`, which returns the id of the new process.
+/// The new process inherits the working directory, sandbox, priority and network namespace of the caller.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SpawnConfig<'a> {
+    /// Path of the program (UTF-8, not null terminated). Without a slash, the program is looked up in `/bin`.
+    pub path: *const u8,
+    pub path_len: usize,
+    /// Arguments (without the name of the program)
+    pub args: *const &'a str,
+    pub args_len: usize,
+    /// Environment variables ("KEY=value")
+    pub env: *const &'a str,
+    pub env_len: usize,
+    /// Handle of an open object (shared with the new process), `STDIO_INHERIT` or `STDIO_TERMINAL`
+    pub stdin: usize,
+    pub stdout: usize,
+}
@@ -0,0 +1,34 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: time                                                            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Wall clock time, used both in user and kernel mode.             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+
+/// Description: A point in time relative to the Unix epoch (like 'timespec'),
+/// filled in by `SystemCall::TimeGet` and passed to `SystemCall::TimeSet`.
+/// Times before the epoch have negative seconds, but the nanoseconds always count forward.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timespec {
+    pub seconds: i64,
+    /// Always less than 1,000,000,000
+    pub nanoseconds: u32,
+}
+
+impl Timespec {
+    pub const NANOS_PER_SECOND: u32 = 1_000_000_000;
+
+    /// Description: Create a timespec from nanoseconds since the epoch.
+    pub const fn from_nanos(nanos: i128) -> Self {
+        let nanos_per_second = Self::NANOS_PER_SECOND as i128;
+        Self { seconds: nanos.div_euclid(nanos_per_second) as i64, nanoseconds: nanos.rem_euclid(nanos_per_second) as u32 }
+    }
+
+    /// Description: Nanoseconds since the epoch.
+    pub const fn as_nanos(&self) -> i128 {
+        self.seconds as i128 * Self::NANOS_PER_SECOND as i128 + self.nanoseconds as i128
+    }
+}
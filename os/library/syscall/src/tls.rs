@@ -0,0 +1,14 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: tls                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Layout of the thread-local storage of user threads (x86-64 ELF  ║
+   ║         TLS, variant II), used both in user and kernel mode.            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+
+/// Description: Size of the thread control block, which the FS base of each user thread points to.
+/// The thread-local variables (`PT_TLS` segment) lie directly below it.
+/// The first word contains the address of the block itself (as required by the ABI), the rest belongs to the runtime.
+pub const TCB_SIZE: usize = 64;
@@ -0,0 +1,32 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: usage                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Resource usage of a process (like 'getrusage'), used both in    ║
+   ║         user and kernel mode.                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+
+/// Description: Passed to `SystemCall::ProcessUsage` to get the usage of the calling process.
+/// Any other value is the id of the first thread of an exited child process (as returned when starting it).
+pub const USAGE_SELF: usize = 0;
+
+/// Description: Resource usage of a process, filled in by `SystemCall::ProcessUsage`.
+/// CPU times are sampled on each timer tick, so they are only as precise as the timer interval.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ResourceUsage {
+    /// Time spent executing in user mode (in microseconds)
+    pub user_time_us: u64,
+    /// Time spent executing in kernel mode, e.g. in system calls (in microseconds)
+    pub system_time_us: u64,
+    /// Largest amount of memory mapped on demand (stack and heap pages, in KiB)
+    pub max_rss_kib: u64,
+    /// Number of times a thread gave up the CPU, e.g. by blocking or sleeping
+    pub voluntary_switches: u64,
+    /// Number of times a thread was preempted
+    pub involuntary_switches: u64,
+    /// Number of page faults that have been resolved by mapping a page
+    pub page_faults: u64,
+}
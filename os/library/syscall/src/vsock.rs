@@ -0,0 +1,21 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: vsock                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Addresses of virtio-vsock sockets, used both in user and        ║
+   ║         kernel mode.                                                    ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+
+/// Description: Context id of the host (the hypervisor, e.g. QEMU)
+pub const VMADDR_CID_HOST: u64 = 2;
+
+/// Description: Address of a vsock socket, consisting of a context id (identifying the machine) and a port.
+/// Filled in by `SystemCall::VsockAccept` with the address of the peer.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct VsockAddr {
+    pub cid: u64,
+    pub port: u32,
+}
@@ -0,0 +1,59 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: device_stats                                                    ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Statistics counters of device drivers. The kernel transfers     ║
+   ║         them as text, one counter per line: "<device> <counter> <value>"║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+#[cfg(feature = "userspace")]
+use alloc::vec;
+#[cfg(feature = "userspace")]
+use syscall::{SystemCall, syscall};
+
+/// A single counter of a device driver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceStat {
+    pub device: String,
+    pub counter: String,
+    pub value: u64,
+}
+
+/// Append a counter to `out`. Used by the kernel to serialize the counters.
+pub fn write_entry(out: &mut String, device: &str, counter: &str, value: u64) {
+    writeln!(out, "{device} {counter} {value}").expect("Failed to write device statistics");
+}
+
+/// Parse serialized counters. Malformed lines are skipped.
+pub fn parse(data: &str) -> Vec<DeviceStat> {
+    data.lines()
+        .filter_map(|line| {
+            let mut parts = line.split(' ');
+            let device = parts.next()?;
+            let counter = parts.next()?;
+            let value = parts.next()?.parse().ok()?;
+            Some(DeviceStat { device: device.to_string(), counter: counter.to_string(), value })
+        })
+        .collect()
+}
+
+/// Get the counters of all device drivers.
+///
+/// Author: Niklas Sombert
+#[cfg(feature = "userspace")]
+pub fn device_stats() -> Vec<DeviceStat> {
+    let mut buffer = vec![0u8; 4096];
+    loop {
+        // the syscall always returns the full length, even if the buffer is too small
+        let len = syscall(SystemCall::DeviceStats, &[buffer.as_mut_ptr() as usize, buffer.len()])
+            .expect("Unable to get device statistics");
+        if len <= buffer.len() {
+            return parse(&String::from_utf8_lossy(&buffer[..len]));
+        }
+        buffer.resize(len, 0);
+    }
+}
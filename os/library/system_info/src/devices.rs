@@ -0,0 +1,180 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: devices                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Devices known to the kernel. The kernel transfers them as text, ║
+   ║         one device per line:                                            ║
+   ║         "<name> <class> <bus> <parent> <driver> <resources>"            ║
+   ║         A missing parent or driver and an empty list of resources are   ║
+   ║         written as "-". Resources are separated by commas and written   ║
+   ║         as "irq=<vector>", "io=<start>+<size>" or "mem=<start>+<size>"  ║
+   ║         (all numbers in hexadecimal).                                   ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Author: Niklas Sombert, HHU                                             ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::fmt::Write;
+#[cfg(feature = "userspace")]
+use alloc::vec;
+#[cfg(feature = "userspace")]
+use syscall::{SystemCall, syscall};
+
+/// What a device is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceClass {
+    /// Bridges and controllers, that other devices are attached to
+    Bus,
+    Storage,
+    Network,
+    Display,
+    Audio,
+    Input,
+    Serial,
+    Other,
+}
+
+/// An address range or interrupt, that a device uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    Irq(u8),
+    Io { start: u64, size: u64 },
+    Memory { start: u64, size: u64 },
+}
+
+/// A single device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// Unique name (e.g. "0000:00:03.0" for a PCI function or "ata0" for a drive)
+    pub name: String,
+    pub class: DeviceClass,
+    /// The bus, the device is attached to (e.g. "pci", "isa", "usb" or "virtual")
+    pub bus: String,
+    /// Name of the device, this one is attached to
+    pub parent: Option<String>,
+    /// Name of the driver, that has claimed the device
+    pub driver: Option<String>,
+    pub resources: Vec<Resource>,
+}
+
+impl DeviceClass {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeviceClass::Bus => "bus",
+            DeviceClass::Storage => "storage",
+            DeviceClass::Network => "network",
+            DeviceClass::Display => "display",
+            DeviceClass::Audio => "audio",
+            DeviceClass::Input => "input",
+            DeviceClass::Serial => "serial",
+            DeviceClass::Other => "other",
+        }
+    }
+
+    pub fn parse(class: &str) -> Option<Self> {
+        match class {
+            "bus" => Some(DeviceClass::Bus),
+            "storage" => Some(DeviceClass::Storage),
+            "network" => Some(DeviceClass::Network),
+            "display" => Some(DeviceClass::Display),
+            "audio" => Some(DeviceClass::Audio),
+            "input" => Some(DeviceClass::Input),
+            "serial" => Some(DeviceClass::Serial),
+            "other" => Some(DeviceClass::Other),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::Irq(vector) => write!(f, "irq={vector:x}"),
+            Resource::Io { start, size } => write!(f, "io={start:x}+{size:x}"),
+            Resource::Memory { start, size } => write!(f, "mem={start:x}+{size:x}"),
+        }
+    }
+}
+
+impl Resource {
+    pub fn parse(resource: &str) -> Option<Self> {
+        let (kind, value) = resource.split_once('=')?;
+        if kind == "irq" {
+            return u8::from_str_radix(value, 16).ok().map(Resource::Irq);
+        }
+
+        let (start, size) = value.split_once('+')?;
+        let start = u64::from_str_radix(start, 16).ok()?;
+        let size = u64::from_str_radix(size, 16).ok()?;
+        match kind {
+            "io" => Some(Resource::Io { start, size }),
+            "mem" => Some(Resource::Memory { start, size }),
+            _ => None,
+        }
+    }
+}
+
+/// Append a device to `out`. Used by the kernel to serialize its devices.
+pub fn write_entry(out: &mut String, device: &DeviceInfo) {
+    let mut resources = String::new();
+    for resource in device.resources.iter() {
+        if !resources.is_empty() {
+            resources.push(',');
+        }
+        write!(resources, "{resource}").expect("Failed to write device resource");
+    }
+
+    writeln!(
+        out,
+        "{} {} {} {} {} {}",
+        device.name,
+        device.class.as_str(),
+        device.bus,
+        device.parent.as_deref().unwrap_or("-"),
+        device.driver.as_deref().unwrap_or("-"),
+        if resources.is_empty() { "-" } else { &resources }
+    ).expect("Failed to write device");
+}
+
+/// Parse serialized devices. Malformed lines are skipped.
+pub fn parse(data: &str) -> Vec<DeviceInfo> {
+    data.lines()
+        .filter_map(|line| {
+            let mut parts = line.split(' ');
+            let name = parts.next()?.to_string();
+            let class = DeviceClass::parse(parts.next()?)?;
+            let bus = parts.next()?.to_string();
+            let parent = parts.next()?;
+            let driver = parts.next()?;
+            let resources = match parts.next()? {
+                "-" => Vec::new(),
+                resources => resources.split(',').filter_map(Resource::parse).collect(),
+            };
+
+            Some(DeviceInfo {
+                name,
+                class,
+                bus,
+                parent: (parent != "-").then(|| parent.to_string()),
+                driver: (driver != "-").then(|| driver.to_string()),
+                resources,
+            })
+        })
+        .collect()
+}
+
+/// Get all devices known to the kernel (in the order of registration, so parents are listed before their children).
+#[cfg(feature = "userspace")]
+pub fn devices() -> Vec<DeviceInfo> {
+    let mut buffer = vec![0u8; 4096];
+    loop {
+        // the syscall always returns the full length, even if the buffer is too small
+        let len = syscall(SystemCall::Devices, &[buffer.as_mut_ptr() as usize, buffer.len()])
+            .expect("Unable to get devices");
+        if len <= buffer.len() {
+            return parse(&String::from_utf8_lossy(&buffer[..len]));
+        }
+        buffer.resize(len, 0);
+    }
+}
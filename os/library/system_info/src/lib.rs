@@ -3,3 +3,5 @@
 extern crate alloc;
 
 pub mod build_info;
+pub mod device_stats;
+pub mod devices;
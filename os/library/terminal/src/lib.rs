@@ -43,6 +43,22 @@ pub enum DecodedKeyType {
     RawKey = 1,
 }
 
+/// Longest encoding of a key in fluid mode (`DecodedKeyType` and a character in UTF-8)
+pub const MAX_FLUID_KEY_LEN: usize = 5;
+
+/// Append `key` to `buffer` in the format used in fluid mode: the `DecodedKeyType`,
+/// followed by the character in UTF-8 or by the key code.
+#[cfg(feature = "userspace")]
+pub fn encode_fluid_key(key: DecodedKey, buffer: &mut alloc::vec::Vec<u8>) {
+    match key {
+        DecodedKey::Unicode(ch) => {
+            buffer.push(DecodedKeyType::Unicode as u8);
+            buffer.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+        DecodedKey::RawKey(code) => buffer.extend_from_slice(&[DecodedKeyType::RawKey as u8, code as u8]),
+    }
+}
+
 #[cfg(feature = "userspace")]
 static LOGGER: Once<Logger> = Once::new();
 
@@ -55,3 +71,10 @@ pub fn init_logger() {
         .map(|()| log::set_max_level(LevelFilter::Debug))
         .expect("Failed to initialize logger!");
 }
+
+/// Tell the terminal, which application runs in the foreground and is interrupted by Ctrl+C.
+/// The application is identified by its first thread (as returned when starting it), `None` means no application.
+#[cfg(feature = "userspace")]
+pub fn set_foreground(thread_id: Option<usize>) -> Result<(), syscall::return_vals::Errno> {
+    syscall::syscall(syscall::SystemCall::TerminalSetForeground, &[thread_id.unwrap_or(0)]).map(|_| ())
+}
@@ -1,5 +1,7 @@
+use alloc::collections::VecDeque;
 use alloc::string::{String, ToString};
-use pc_keyboard::{DecodedKey, KeyEvent};
+use pc_keyboard::{DecodedKey, KeyCode, KeyEvent};
+use spin::Mutex;
 use stream::event_from_u16;
 /* ╔═════════════════════════════════════════════════════════════════════════╗
    ║ Module: read                                                            ║
@@ -11,7 +13,11 @@ use stream::event_from_u16;
 */
 use syscall::{SystemCall, syscall};
 
-use crate::{DecodedKeyType, TerminalMode};
+use crate::{DecodedKeyType, TerminalMode, MAX_FLUID_KEY_LEN};
+
+/// Keys, that have been read in fluid mode, but not returned yet.
+/// The terminal may send several keys at once (e.g. a dead key, that can't be combined, and the following key).
+static PENDING_KEYS: Mutex<VecDeque<DecodedKey>> = Mutex::new(VecDeque::new());
 
 /// Read from terminal in canonical mode.
 ///
@@ -41,33 +47,46 @@ pub fn read() -> String {
 ///
 /// The terminal will not echo.
 /// The application will not block.
-/// Returns decoded key as well as raw special keys.
+/// Returns decoded key (any Unicode character) as well as raw special keys.
 ///
 /// Author: Sebastian Keller
 pub fn read_fluid() -> Option<DecodedKey> {
-    let mut buffer: [u8; 2] = [0; 2];
+    if let Some(key) = PENDING_KEYS.lock().pop_front() {
+        return Some(key);
+    }
 
+    let mut buffer = [0; 4 * MAX_FLUID_KEY_LEN];
     let written_bytes = syscall(
         SystemCall::TerminalReadInput,
         &[buffer.as_mut_ptr() as usize, buffer.len(), TerminalMode::Fluid as usize],
     )
     .expect("Unable to read input");
 
-    if written_bytes != 2 {
-        return None;
-    }
-
-    let key_type = DecodedKeyType::from(*buffer.first().unwrap());
-    let key = *buffer.last().unwrap();
-
-    if key_type == DecodedKeyType::Unicode {
-        return Some(DecodedKey::Unicode(key as char));
-    }
-    if key_type == DecodedKeyType::RawKey {
-        return Some(DecodedKey::RawKey(unsafe { core::mem::transmute(key) }));
-    }
+    let mut keys = decode_fluid_keys(&buffer[..written_bytes]);
+    let key = keys.next();
+    PENDING_KEYS.lock().extend(keys);
+    key
+}
 
-    return None;
+/// Decode keys in the format of `encode_fluid_key()` (stops at the first invalid key).
+fn decode_fluid_keys(bytes: &[u8]) -> impl Iterator<Item = DecodedKey> + '_ {
+    let mut rest = bytes;
+    core::iter::from_fn(move || {
+        let (&key_type, data) = rest.split_first()?;
+        match DecodedKeyType::from(key_type) {
+            DecodedKeyType::RawKey => {
+                let (&code, tail) = data.split_first()?;
+                rest = tail;
+                Some(DecodedKey::RawKey(unsafe { core::mem::transmute::<u8, KeyCode>(code) }))
+            }
+            DecodedKeyType::Unicode => {
+                // UTF-8 is prefix free, so the shortest valid prefix is the character
+                let ch = (1..=4).find_map(|len| core::str::from_utf8(data.get(..len)?).ok())?;
+                rest = &data[ch.len()..];
+                ch.chars().next().map(DecodedKey::Unicode)
+            }
+        }
+    })
 }
 
 /// Read from terminal in raw mode.
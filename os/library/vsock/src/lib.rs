@@ -0,0 +1,75 @@
+//! This library provides stream sockets for communicating with the host via virtio-vsock,
+//! without depending on the network stack. The API mirrors `TcpListener` and `TcpStream`.
+
+#![no_std]
+
+use syscall::{return_vals::Errno, syscall, SystemCall};
+
+pub use syscall::vsock::{VsockAddr, VMADDR_CID_HOST};
+
+pub struct VsockListener {
+    handle: usize,
+    port: u32,
+}
+
+impl VsockListener {
+    /// Listen for connections on `port`. Returns `EEXIST`, if another socket is already listening on it,
+    /// and `ENOTSUP`, if there is no vsock device.
+    pub fn bind(port: u32) -> Result<Self, Errno> {
+        let handle = syscall(SystemCall::VsockListen, &[port as usize])?;
+        Ok(Self { handle, port })
+    }
+
+    /// Wait for a new connection on this socket.
+    pub fn accept(&self) -> Result<VsockStream, Errno> {
+        let mut peer = VsockAddr::default();
+        let handle = syscall(SystemCall::VsockAccept, &[self.handle, &raw mut peer as usize])?;
+        Ok(VsockStream { handle, peer })
+    }
+
+    pub fn port(&self) -> u32 {
+        self.port
+    }
+}
+
+impl Drop for VsockListener {
+    fn drop(&mut self) {
+        syscall(SystemCall::VsockClose, &[self.handle])
+            .expect("failed to close socket");
+    }
+}
+
+pub struct VsockStream {
+    handle: usize,
+    peer: VsockAddr,
+}
+
+impl VsockStream {
+    /// Connect to a port on another machine, usually the host (`VMADDR_CID_HOST`).
+    /// Returns `ECONNRESET`, if the connection has been refused.
+    pub fn connect(address: VsockAddr) -> Result<Self, Errno> {
+        let handle = syscall(SystemCall::VsockConnect, &[address.cid as usize, address.port as usize])?;
+        Ok(Self { handle, peer: address })
+    }
+
+    /// Send (a part of) `buf`. Returns the number of bytes that have been sent.
+    pub fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        syscall(SystemCall::VsockSend, &[self.handle, buf.as_ptr() as usize, buf.len()])
+    }
+
+    /// Wait for data. Returns `ECONNRESET`, if the peer has closed the connection and all data has been read.
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        syscall(SystemCall::VsockReceive, &[self.handle, buf.as_mut_ptr() as usize, buf.len()])
+    }
+
+    pub fn peer_addr(&self) -> VsockAddr {
+        self.peer
+    }
+}
+
+impl Drop for VsockStream {
+    fn drop(&mut self) {
+        syscall(SystemCall::VsockClose, &[self.handle])
+            .expect("failed to close socket");
+    }
+}